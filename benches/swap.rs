@@ -0,0 +1,121 @@
+//! Benchmarks of the swap hot paths, on the code of the Taker and the Maker: contract creation and signing, the
+//! Maker handling the request of its contract signatures, wallet sync, and its RPC calls sent batched or one by one.
+//!
+//! Run with `cargo bench --features=bench`. The benchmarks run a Bitcoin Core node, and require a `bitcoind` binary
+//! in `BITCOIND_EXE`, like the one the functional tests download in `bin/`. They fail without it.
+use bitcoin::Amount;
+use bitcoind::{
+    bitcoincore_rpc::{Auth, RpcApi},
+    tempfile::tempdir,
+    BitcoinD,
+};
+use coinswap::{
+    bench::{SwapBench, SyncBench},
+    wallet::RPCConfig,
+};
+use criterion::{criterion_group, criterion_main, Criterion};
+
+/// Number of utxos of the synced wallet.
+const SYNC_UTXO_COUNT: u64 = 100;
+
+/// Number of RPC calls, batched or not.
+const RPC_CALL_COUNT: u64 = 100;
+
+/// Funding txs of the benchmarked hop.
+const HOP_TX_COUNT: u32 = 3;
+
+/// Amount of the benchmarked hop.
+const HOP_AMOUNT: Amount = Amount::from_sat(500_000);
+
+/// Starts a node, and the RPC config of the wallet `wallet_name` on it.
+fn node(wallet_name: &str) -> (BitcoinD, RPCConfig) {
+    let exe = bitcoind::exe_path()
+        .expect("The benchmarks run a Bitcoin Core node: set BITCOIND_EXE to a bitcoind binary");
+    let mut conf = bitcoind::Conf::default();
+    conf.args.push("-txindex=1"); //txindex is must, or else wallet sync won't work.
+    let bitcoind = BitcoinD::with_conf(exe, &conf).unwrap();
+    let rpc_config = RPCConfig {
+        url: bitcoind.rpc_url().split_at(7).1.to_string(),
+        auth: Auth::CookieFile(bitcoind.params.cookie_file.clone()),
+        wallet_name: wallet_name.to_string(),
+    };
+    (bitcoind, rpc_config)
+}
+
+/// Matures the coinbases mined so far.
+fn mature_coinbases(bitcoind: &BitcoinD) {
+    let mining_address = bitcoind
+        .client
+        .get_new_address(None, None)
+        .unwrap()
+        .assume_checked();
+    bitcoind
+        .client
+        .generate_to_address(100, &mining_address)
+        .unwrap();
+}
+
+fn swap(c: &mut Criterion) {
+    let (bitcoind, rpc_config) = node("random");
+    let temp_dir = tempdir().unwrap();
+    let mut swap = SwapBench::new(temp_dir.path(), &rpc_config).unwrap();
+
+    // Fund both sides, the Maker to offer the hop amount.
+    let taker_address = swap.taker_address().unwrap();
+    bitcoind
+        .client
+        .generate_to_address(HOP_TX_COUNT as u64, &taker_address)
+        .unwrap();
+    let maker_address = swap.maker_address().unwrap();
+    bitcoind
+        .client
+        .generate_to_address(1, &maker_address)
+        .unwrap();
+    mature_coinbases(&bitcoind);
+    swap.sync().unwrap();
+    swap.prepare_first_hop(HOP_TX_COUNT, HOP_AMOUNT).unwrap();
+    let sigs = swap.handle_contract_sigs_request().unwrap();
+
+    let mut group = c.benchmark_group("swap");
+    group.bench_function("create_contract_txs", |b| {
+        b.iter(|| swap.create_contract_txs().unwrap())
+    });
+    group.bench_function("sign_contract_txs", |b| {
+        b.iter(|| swap.sign_contract_txs().unwrap())
+    });
+    group.bench_function("handle_contract_sigs_request", |b| {
+        b.iter(|| swap.handle_contract_sigs_request().unwrap())
+    });
+    group.bench_function("verify_contract_sigs", |b| {
+        b.iter(|| swap.verify_contract_sigs(&sigs).unwrap())
+    });
+    group.finish();
+}
+
+fn wallet_sync(c: &mut Criterion) {
+    let (bitcoind, rpc_config) = node("bench-wallet");
+    let temp_dir = tempdir().unwrap();
+    let mut wallet = SyncBench::new(&temp_dir.path().join("bench-wallet"), &rpc_config).unwrap();
+
+    // Mine the coinbases of the wallet, and mature them.
+    let wallet_address = wallet.next_address().unwrap();
+    bitcoind
+        .client
+        .generate_to_address(SYNC_UTXO_COUNT, &wallet_address)
+        .unwrap();
+    mature_coinbases(&bitcoind);
+
+    let mut group = c.benchmark_group("wallet");
+    group.sample_size(10);
+    group.bench_function("sync", |b| b.iter(|| wallet.sync().unwrap()));
+    group.bench_function("rpc_calls_batched", |b| {
+        b.iter(|| wallet.block_hashes(RPC_CALL_COUNT, true).unwrap())
+    });
+    group.bench_function("rpc_calls_one_by_one", |b| {
+        b.iter(|| wallet.block_hashes(RPC_CALL_COUNT, false).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, swap, wallet_sync);
+criterion_main!(benches);
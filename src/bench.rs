@@ -0,0 +1,203 @@
+//! Entry points into the swap code, for the benchmarks of `benches/`.
+//!
+//! Only built with the `bench` feature, and hidden from the docs: the benchmarks are a crate of their own, and reach
+//! the internals of the swap through this module only. Nothing here reimplements the swap. [SwapBench] runs a
+//! [Taker] and a [Maker] on a Bitcoin Core node, and calls the same functions they call for the first hop of a swap:
+//! the Taker creating and signing the contract txs, and the Maker handling the request of the contract signatures.
+//! [SyncBench] syncs a wallet with the node, and sends its RPC calls batched or one by one.
+
+use std::path::Path;
+
+use bitcoin::{
+    ecdsa::Signature,
+    hashes::{hash160::Hash as Hash160, Hash},
+    Address, Amount, BlockHash, Transaction,
+};
+use bitcoind::bitcoincore_rpc::RpcApi;
+use serde_json::json;
+
+use crate::{
+    maker::{Maker, MakerBehavior, MakerError},
+    protocol::{
+        contract::create_senders_contract_tx,
+        messages::{MakerToTakerMessage, ReqContractSigsForSender},
+    },
+    taker::{routines::contract_sigs_request_for_sender, Taker, TakerBehavior},
+    utill::{generate_maker_keys, ConnectionType},
+    wallet::{
+        batch_call, AmountDistribution, InputConstraints, OutgoingSwapCoin, RPCConfig, SwapCoin,
+        Wallet, WalletError, MINER_FEE,
+    },
+};
+
+/// A Taker and a Maker on the same node, and the first hop of a swap between them.
+pub struct SwapBench {
+    taker: Taker,
+    maker: Maker,
+    outgoing_swapcoins: Vec<OutgoingSwapCoin>,
+    request: Option<ReqContractSigsForSender>,
+}
+
+impl SwapBench {
+    /// Creates a Taker and a Maker with their data directories in `data_dir`, and their wallets on the node.
+    pub fn new(data_dir: &Path, rpc_config: &RPCConfig) -> Result<Self, MakerError> {
+        let taker = Taker::init(
+            Some(data_dir.join("taker")),
+            Some("bench-taker".to_string()),
+            Some(rpc_config.clone()),
+            TakerBehavior::Normal,
+            Some(ConnectionType::CLEARNET),
+        )?;
+        let maker = Maker::init(
+            Some(data_dir.join("maker")),
+            Some("bench-maker".to_string()),
+            Some(rpc_config.clone()),
+            None,
+            None,
+            None,
+            Some(ConnectionType::CLEARNET),
+            MakerBehavior::Normal,
+        )?;
+        Ok(Self {
+            taker,
+            maker,
+            outgoing_swapcoins: Vec::new(),
+            request: None,
+        })
+    }
+
+    /// A new receive address of the Taker, to fund it.
+    pub fn taker_address(&mut self) -> Result<Address, MakerError> {
+        Ok(self.taker.get_wallet_mut().get_next_external_address()?)
+    }
+
+    /// A new receive address of the Maker, to fund it.
+    pub fn maker_address(&self) -> Result<Address, MakerError> {
+        Ok(self
+            .maker
+            .get_wallet()
+            .write()?
+            .get_next_external_address()?)
+    }
+
+    /// Syncs both wallets with the node.
+    pub fn sync(&mut self) -> Result<(), MakerError> {
+        self.taker.get_wallet_mut().sync()?;
+        self.maker.get_wallet().write()?.sync()?;
+        Ok(())
+    }
+
+    /// Builds the funding and contract txs of a first hop of `amount` in `tx_count` txs, from the Taker to the
+    /// Maker, and the request of the Maker's contract signatures. Nothing is broadcast.
+    pub fn prepare_first_hop(&mut self, tx_count: u32, amount: Amount) -> Result<(), MakerError> {
+        let tweakable_point = self.maker.get_wallet().read()?.get_tweakable_keypair()?.1;
+        let (multisig_pubkeys, multisig_nonces, hashlock_pubkeys, hashlock_nonces) =
+            generate_maker_keys(&tweakable_point, tx_count)?;
+        let locktime = self.taker.config.refund_locktime;
+        let (_, outgoing_swapcoins, _) = self.taker.get_wallet_mut().initalize_coinswap(
+            amount,
+            &multisig_pubkeys,
+            &hashlock_pubkeys,
+            Hash160::hash(&[tx_count as u8; 32]),
+            locktime,
+            Amount::from_sat(MINER_FEE),
+            Amount::from_sat(MINER_FEE),
+            AmountDistribution::default(),
+            InputConstraints::default(),
+        )?;
+        self.request = Some(contract_sigs_request_for_sender(
+            &outgoing_swapcoins,
+            &multisig_nonces,
+            &hashlock_nonces,
+            locktime,
+            None,
+        )?);
+        self.outgoing_swapcoins = outgoing_swapcoins;
+        Ok(())
+    }
+
+    /// The Taker creates the contract txs of the hop again.
+    pub fn create_contract_txs(&self) -> Result<Vec<Transaction>, MakerError> {
+        self.outgoing_swapcoins
+            .iter()
+            .map(|swapcoin| {
+                let contract_tx = &swapcoin.contract_tx;
+                Ok(create_senders_contract_tx(
+                    contract_tx.input[0].previous_output,
+                    swapcoin.funding_amount,
+                    &swapcoin.contract_redeemscript,
+                    swapcoin.funding_amount - contract_tx.output[0].value,
+                )?)
+            })
+            .collect()
+    }
+
+    /// The Taker signs the contract txs of the hop.
+    pub fn sign_contract_txs(&self) -> Result<Vec<Signature>, MakerError> {
+        Ok(self
+            .outgoing_swapcoins
+            .iter()
+            .map(|swapcoin| swapcoin.sign_contract_tx_with_my_privkey(&swapcoin.contract_tx))
+            .collect::<Result<_, WalletError>>()?)
+    }
+
+    /// The Maker handles the request of its contract signatures, as sent by the Taker. Returns the signatures.
+    pub fn handle_contract_sigs_request(&self) -> Result<Vec<Signature>, MakerError> {
+        let request = self
+            .request
+            .as_ref()
+            .ok_or(MakerError::General("No hop prepared"))?;
+        match self
+            .maker
+            .handle_req_contract_sigs_for_sender(request.clone())?
+        {
+            MakerToTakerMessage::RespContractSigsForSender(message) => Ok(message.sigs),
+            _ => Err(MakerError::General("Unexpected reply of the maker")),
+        }
+    }
+
+    /// The Taker verifies the contract signatures of the Maker.
+    pub fn verify_contract_sigs(&self, sigs: &[Signature]) -> Result<(), MakerError> {
+        for (swapcoin, sig) in self.outgoing_swapcoins.iter().zip(sigs) {
+            swapcoin.verify_contract_tx_sender_sig(sig)?;
+        }
+        Ok(())
+    }
+}
+
+/// A wallet on a Bitcoin Core node, to benchmark its sync.
+pub struct SyncBench {
+    wallet: Wallet,
+}
+
+impl SyncBench {
+    /// Creates a wallet file at `path`, and its watch-only wallet on the node.
+    pub fn new(path: &Path, rpc_config: &RPCConfig) -> Result<Self, WalletError> {
+        Ok(Self {
+            wallet: Wallet::init(path, rpc_config)?,
+        })
+    }
+
+    /// A new receive address of the wallet, to fund it.
+    pub fn next_address(&mut self) -> Result<Address, WalletError> {
+        self.wallet.get_next_external_address()
+    }
+
+    /// Syncs the wallet with the node.
+    pub fn sync(&mut self) -> Result<(), WalletError> {
+        self.wallet.sync()
+    }
+
+    /// Hashes of the first `count` blocks, from `count` calls to the node: sent in a single batch, as the sync
+    /// sends its calls, or one round trip each.
+    pub fn block_hashes(&self, count: u64, batched: bool) -> Result<Vec<BlockHash>, WalletError> {
+        if batched {
+            let params = (0..count).map(|height| json!([height])).collect::<Vec<_>>();
+            batch_call(&self.wallet.rpc, "getblockhash", &params)
+        } else {
+            (0..count)
+                .map(|height| Ok(self.wallet.rpc.get_block_hash(height)?))
+                .collect()
+        }
+    }
+}
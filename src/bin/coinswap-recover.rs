@@ -0,0 +1,208 @@
+use std::{
+    io::{self, BufRead, Write},
+    path::PathBuf,
+    str::FromStr,
+    thread,
+    time::Duration,
+};
+
+use bitcoin::Network;
+use bitcoind::bitcoincore_rpc::Auth;
+use clap::Parser;
+use coinswap::{
+    utill::{default_rpc_address, parse_proxy_auth, setup_recovery_logger},
+    wallet::{
+        load_passphrase_file, FundsRecovery, RPCConfig, Recoverable, RecoverableKind, RecoveryStep,
+        WalletError,
+    },
+};
+use log::LevelFilter;
+
+/// Interval between scans with `--wait`.
+const RESCAN_INTERVAL: Duration = Duration::from_secs(60);
+
+/// A standalone app to recover the funds of a coinswap wallet.
+///
+/// It scans the chain for the contracts of unfinished swaps, contracts broadcast by counterparties and
+/// fidelity bonds of the wallet, and walks you through broadcasting the transactions claiming them back.
+/// It only needs the wallet file and a running Bitcoin Core node with RPC access: it works for both maker
+/// and taker wallets, even when `makerd` or `taker` won't start.
+///
+/// Swap contracts and fidelity bonds are only kept in the wallet file. A wallet restored from its seed
+/// phrase only recovers the regular wallet coins.
+#[derive(Parser, Debug)]
+#[clap(version = option_env ! ("CARGO_PKG_VERSION").unwrap_or("unknown"),
+author = option_env ! ("CARGO_PKG_AUTHORS").unwrap_or(""))]
+struct Cli {
+    /// Path of the wallet file. Ex: ~/.coinswap/taker/wallets/taker-wallet
+    /// With `--seed`, the restored wallet is written there, and the file must not exist.
+    #[clap(name = "WALLET_FILE")]
+    wallet_file: PathBuf,
+
+    /// Restore the wallet from its BIP39 seed phrase, instead of reading the wallet file.
+    #[clap(long, short = 's')]
+    seed: Option<String>,
+
+    /// With `--seed`, the block height the wallet was created at. The chain is scanned from there.
+    /// Default: from genesis.
+    #[clap(long, short = 'b', requires = "seed")]
+    birthday: Option<u64>,
+
+    /// Bitcoin network of the backend node: bitcoin, testnet, testnet4, signet or regtest.
+    /// If set, the node is checked to run on it. Default: testnet4
+    #[clap(long, short = 'n')]
+    network: Option<Network>,
+
+    /// Bitcoin Core RPC address:port value. Default: localhost, on the network's default RPC port.
+    #[clap(name = "ADDRESS:PORT", long, short = 'r')]
+    rpc: Option<String>,
+
+    /// Bitcoin Core RPC authentication string. Ex: username:password
+    #[clap(name="USER:PASSWORD",short='a',long, value_parser = parse_proxy_auth, default_value = "user:password")]
+    auth: (String, String),
+
+    /// File holding the wallet passphrase, encrypting the master key and swap coins of the wallet file.
+    /// Overrides the COINSWAP_WALLET_PASSPHRASE environment variable.
+    #[clap(long)]
+    passphrase_file: Option<PathBuf>,
+
+    /// Broadcast every recovery transaction without asking.
+    #[clap(long, short = 'y')]
+    yes: bool,
+
+    /// Keep running, scanning again every minute, until everything is recovered.
+    #[clap(long)]
+    wait: bool,
+
+    /// Sets the verbosity level of the logs.
+    #[clap(long, short = 'v', possible_values = &["off", "error", "warn", "info", "debug", "trace"], default_value = "warn")]
+    verbosity: String,
+}
+
+fn main() -> Result<(), WalletError> {
+    let args = Cli::parse();
+    setup_recovery_logger(LevelFilter::from_str(&args.verbosity).unwrap());
+    if let Some(path) = &args.passphrase_file {
+        load_passphrase_file(path)?;
+    }
+
+    let network = args.network.unwrap_or(Network::Testnet4);
+    let rpc_config = RPCConfig {
+        url: args.rpc.unwrap_or_else(|| default_rpc_address(network)),
+        auth: Auth::UserPass(args.auth.0, args.auth.1),
+        wallet_name: "random".to_string(), // Named after the wallet file on opening.
+    };
+    if let Some(network) = args.network {
+        rpc_config.check_network(network)?;
+    }
+
+    println!("Syncing the wallet, this may take a while...");
+    let mut recovery = match &args.seed {
+        Some(seed) => {
+            FundsRecovery::from_seed(&args.wallet_file, &rpc_config, seed.trim(), args.birthday)?
+        }
+        None => FundsRecovery::open(&args.wallet_file, &rpc_config)?,
+    };
+
+    loop {
+        let height = recovery.block_height()?;
+        let recoverables = recovery.scan()?;
+        println!("\nBlock height {}", height);
+        if recoverables.is_empty() {
+            println!("Nothing left to recover.");
+            break;
+        }
+
+        let mut pending = 0;
+        for (i, recoverable) in recoverables.iter().enumerate() {
+            println!("\n[{}] {}", i + 1, describe(recoverable));
+            match &recoverable.step {
+                RecoveryStep::AwaitConfirmation => {
+                    println!("    Waiting for the contract tx to confirm.");
+                    pending += 1;
+                }
+                RecoveryStep::AwaitLocktime(locktime) => {
+                    if locktime.is_block_height() {
+                        println!(
+                            "    Claimable at height {}, in {} blocks.",
+                            locktime,
+                            (locktime.to_consensus_u32() as u64).saturating_sub(height)
+                        );
+                    } else {
+                        println!("    Claimable after unix time {}.", locktime);
+                    }
+                    pending += 1;
+                }
+                RecoveryStep::BroadcastContract(_) | RecoveryStep::BroadcastClaim(_) => {
+                    if !args.yes && !confirm("    Broadcast it?")? {
+                        pending += 1;
+                        continue;
+                    }
+                    match recovery.broadcast(recoverable) {
+                        Ok(txid) => {
+                            println!("    Broadcasted {}", txid);
+                            // Contracts still have to be claimed.
+                            if matches!(recoverable.step, RecoveryStep::BroadcastContract(_)) {
+                                pending += 1;
+                            }
+                        }
+                        Err(e) => {
+                            println!("    Broadcast failed: {:?}", e);
+                            pending += 1;
+                        }
+                    }
+                }
+            }
+        }
+
+        if pending == 0 {
+            println!("\nAll recovery transactions broadcasted.");
+            break;
+        }
+        if !args.wait {
+            println!(
+                "\n{} coins still to recover. Run again later, or with `--wait` to keep going until done.",
+                pending
+            );
+            break;
+        }
+        thread::sleep(RESCAN_INTERVAL);
+    }
+
+    println!(
+        "Spendable balance: {} sats",
+        recovery.spendable_balance()?.to_sat()
+    );
+    Ok(())
+}
+
+/// What the next step of a recovery is about.
+fn describe(recoverable: &Recoverable) -> String {
+    let coin = match recoverable.kind {
+        RecoverableKind::TimelockContract => "Outgoing swap contract, refunded by timelock",
+        RecoverableKind::HashlockContract => "Incoming swap contract, claimed by hashlock",
+        RecoverableKind::FidelityBond => "Fidelity bond",
+    };
+    let action = match &recoverable.step {
+        RecoveryStep::BroadcastContract(tx) => format!("contract tx {}", tx.compute_txid()),
+        RecoveryStep::BroadcastClaim(tx) => format!("claim tx {}", tx.compute_txid()),
+        RecoveryStep::AwaitConfirmation | RecoveryStep::AwaitLocktime(_) => {
+            format!("coin {}", recoverable.outpoint)
+        }
+    };
+    format!(
+        "{} of {} sats: {}",
+        coin,
+        recoverable.amount.to_sat(),
+        action
+    )
+}
+
+/// Asks a yes/no question on the terminal. Defaults to no.
+fn confirm(question: &str) -> Result<bool, WalletError> {
+    print!("{} [y/N] ", question);
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
@@ -3,11 +3,11 @@ use std::{net::TcpStream, time::Duration};
 use clap::Parser;
 
 use coinswap::{
-    error::NetError,
     market::{
         directory::DirectoryServerError,
         rpc::{RpcMsgReq, RpcMsgResp},
     },
+    protocol::wire::decode,
     utill::{read_message, send_message, setup_directory_logger},
 };
 
@@ -27,6 +27,9 @@ struct App {
 enum Commands {
     /// Lists all the addresses from the directory server
     ListAddresses,
+    /// Shows the metrics of the directory server: makers registered and active, registrations, query rates and
+    /// rejections by reason
+    GetMetrics,
 }
 
 fn send_rpc_req(mut stream: TcpStream, req: RpcMsgReq) -> Result<(), DirectoryServerError> {
@@ -36,7 +39,7 @@ fn send_rpc_req(mut stream: TcpStream, req: RpcMsgReq) -> Result<(), DirectorySe
     send_message(&mut stream, &req)?;
 
     let resp_bytes = read_message(&mut stream)?;
-    let resp: RpcMsgResp = serde_cbor::from_slice(&resp_bytes).map_err(NetError::Cbor)?;
+    let resp: RpcMsgResp = decode(&resp_bytes)?;
 
     println!("{:#?}", resp);
     Ok(())
@@ -52,6 +55,9 @@ fn main() -> Result<(), DirectoryServerError> {
         Commands::ListAddresses => {
             send_rpc_req(stream, RpcMsgReq::ListAddresses)?;
         }
+        Commands::GetMetrics => {
+            send_rpc_req(stream, RpcMsgReq::GetMetrics)?;
+        }
     }
     Ok(())
 }
@@ -1,8 +1,9 @@
+use bitcoin::Network;
 use bitcoind::bitcoincore_rpc::Auth;
 use clap::Parser;
 use coinswap::{
     market::directory::{start_directory_server, DirectoryServer, DirectoryServerError},
-    utill::{parse_proxy_auth, setup_directory_logger, ConnectionType},
+    utill::{default_rpc_address, parse_proxy_auth, setup_directory_logger, ConnectionType},
     wallet::RPCConfig,
 };
 
@@ -15,14 +16,13 @@ struct Cli {
     /// Optional DNS data directory. Default value : "~/.coinswap/dns"
     #[clap(long, short = 'd')]
     data_directory: Option<PathBuf>,
-    /// Sets the full node address for rpc connection.
-    #[clap(
-        name = "ADDRESS:PORT",
-        long,
-        short = 'r',
-        default_value = "127.0.0.1:48332"
-    )]
-    pub(crate) rpc: String,
+    /// Bitcoin network of the backend node: bitcoin, testnet, testnet4, signet or regtest.
+    /// Custom signets are supported with `signet`. If set, the node is checked to run on it. Default: testnet4
+    #[clap(long, short = 'n')]
+    pub network: Option<Network>,
+    /// Sets the full node address for rpc connection. Default: localhost, on the network's default RPC port.
+    #[clap(name = "ADDRESS:PORT", long, short = 'r')]
+    pub(crate) rpc: Option<String>,
     /// Sets the rpc basic authentication.
     #[clap(
         name = "USER:PASSWORD",
@@ -38,11 +38,15 @@ fn main() -> Result<(), DirectoryServerError> {
     setup_directory_logger(log::LevelFilter::Info);
 
     let args = Cli::parse();
+    let network = args.network.unwrap_or(Network::Testnet4);
     let rpc_config = RPCConfig {
-        url: args.rpc,
+        url: args.rpc.unwrap_or_else(|| default_rpc_address(network)),
         auth: Auth::UserPass(args.auth.0, args.auth.1),
         wallet_name: "random".to_string(), // we can put anything here as it will get updated in the init.
     };
+    if let Some(network) = args.network {
+        rpc_config.check_network(network)?;
+    }
 
     #[cfg(feature = "tor")]
     let connection_type = if cfg!(feature = "integration-test") {
@@ -2,7 +2,8 @@ use std::{net::TcpStream, time::Duration};
 
 use clap::Parser;
 use coinswap::{
-    maker::{MakerError, RpcMsgReq, RpcMsgResp},
+    maker::{EarningsPeriod, MakerError, RpcMsgReq, RpcMsgResp},
+    protocol::wire::decode,
     utill::{read_message, send_message, setup_maker_logger},
 };
 
@@ -29,6 +30,13 @@ struct App {
 enum Commands {
     /// Sends a ping to makerd. Will return a pong.
     SendPing,
+    /// Shows the health of makerd: node connectivity, wallet sync height, Tor reachability, directory registration
+    /// and bond validity. Exits with an error if unhealthy, for container health checks.
+    Health {
+        /// Also exit with an error if not ready to take swaps: not set up, unregistered or without a valid bond.
+        #[clap(long)]
+        ready: bool,
+    },
     /// Lists all utxos in the wallet. Including fidelity bonds.
     ListUtxo,
     /// Lists utxos received from incoming swaps.
@@ -46,6 +54,19 @@ enum Commands {
     GetBalances,
     /// Gets a new bitcoin receiving address
     GetNewAddress,
+    /// Lists the wallet addresses which received coins more than once.
+    ListReused,
+    /// Lists the wallet transactions, newest first, tagged deposit, send, swap-funding, swap-incoming, contract, fidelity or sweep.
+    ListTransactions,
+    /// Attaches a memo to a transaction or a swap, shown in the history. An empty memo removes it.
+    SetMemo {
+        /// Txid of the transaction, or id of the swap.
+        target: String,
+        /// Text of the memo.
+        memo: String,
+    },
+    /// Gets a BIP21 payjoin URI for depositing into the wallet. Requires `payjoin_port` in the config.
+    GetPayjoinUri,
     /// Send Bitcoin to an external address and returns the txid.
     SendToAddress {
         /// Recipient's address.
@@ -62,8 +83,21 @@ enum Commands {
     ShowTorAddress,
     /// Show the data directory path
     ShowDataDir,
+    /// Rotate the server tor address. The new onion is advertised to the directory once the ongoing swaps finish.
+    RotateAddress,
+    /// Show the identity key of the server
+    ShowIdentity,
+    /// Move the server to a new identity key. The directory hands the reputation of the old identity over to the
+    /// new one at the next registration.
+    RotateIdentity,
+    /// Reload the fees, size limits and banned peers from the config file, without restarting the server.
+    ReloadConfig,
     /// Shutdown the makerd server
-    Stop,
+    Stop {
+        /// Stop taking new swaps, finish the ongoing ones and leave the directory before shutting down.
+        #[clap(long)]
+        drain: bool,
+    },
     /// Redeems the fidelity bond if timelock is matured. Returns the txid of the spending transaction.
     RedeemFidelity {
         #[clap(long, short = 'i', default_value = "0")]
@@ -73,10 +107,69 @@ enum Commands {
     ShowFidelity,
     /// Sync the maker wallet with current blockchain state.
     SyncWallet,
+    /// List the ongoing swaps: phase, locked amounts, counterparties, contract txids and timelocks.
+    ListSwaps,
+    /// Show the earnings of completed swaps: fees earned, miner fees spent, net profit and capital locked.
+    GetEarnings {
+        /// Aggregation period, `daily` or `weekly`.
+        #[clap(long, short = 'p', default_value = "daily")]
+        period: EarningsPeriod,
+    },
+    /// Show the bytes and messages exchanged with takers since makerd started, per peer IP and per swap.
+    GetTraffic,
+    /// Freezes a wallet UTXO. Frozen coins are never spent by swaps, consolidations or sweeps, nor counted as spendable.
+    Freeze {
+        /// The wallet outpoint, as `txid:vout`.
+        #[clap(long, short = 'o')]
+        outpoint: String,
+    },
+    /// Unfreezes a frozen UTXO.
+    Unfreeze {
+        /// The wallet outpoint, as `txid:vout`.
+        #[clap(long, short = 'o')]
+        outpoint: String,
+    },
+    /// Lists the frozen UTXOs.
+    ListFrozen,
+    /// Bump the fee of a stuck transaction, by spending one of its wallet outputs with a child transaction (CPFP).
+    /// Returns the txid of the child transaction.
+    Cpfp {
+        /// The wallet outpoint to spend, as `txid:vout`.
+        #[clap(long, short = 'o')]
+        outpoint: String,
+        /// Target feerate of the parent + child package, in sats/vbyte.
+        #[clap(long, short = 'f')]
+        feerate: u64,
+    },
+    /// Moves swap coins into the regular balance. Each coin is spent alone, to its own fresh internal address.
+    TransferSwapCoins {
+        /// The swap coins to transfer, as `txid:vout`. See `list-utxo-swap`.
+        #[clap(required = true)]
+        outpoints: Vec<String>,
+        /// Feerate of each transfer, in sats/vbyte.
+        #[clap(long, short = 'f')]
+        feerate: u64,
+        /// Broadcast each transfer after a random delay, up to this many seconds. The maker broadcasts them in the background.
+        #[clap(long, short = 'd', default_value = "0")]
+        max_delay_secs: u64,
+    },
+    /// Lists the unconfirmed deposits, and coins of completed swaps, whose parent tx pays below a feerate.
+    ListStuckDeposits {
+        /// Target feerate, in sats/vbyte.
+        #[clap(long, short = 'f')]
+        feerate: u64,
+    },
+    /// Bumps the stuck deposits to a feerate, each by spending it to the wallet with a child transaction (CPFP).
+    /// Returns the txids of the children.
+    BumpDeposits {
+        /// Target feerate of each parent + child package, in sats/vbyte.
+        #[clap(long, short = 'f')]
+        feerate: u64,
+    },
 }
 
 fn main() -> Result<(), MakerError> {
-    setup_maker_logger(log::LevelFilter::Info);
+    setup_maker_logger(log::LevelFilter::Info, true);
     let cli = App::parse();
 
     let stream = TcpStream::connect(cli.rpc_port)?;
@@ -85,6 +178,20 @@ fn main() -> Result<(), MakerError> {
         Commands::SendPing => {
             send_rpc_req(stream, RpcMsgReq::Ping)?;
         }
+        Commands::Health { ready } => {
+            let report = match send_rpc_req(stream, RpcMsgReq::Health)? {
+                RpcMsgResp::HealthResp(report) => report,
+                _ => return Err(MakerError::General("Unexpected health response")),
+            };
+            let passed = if ready {
+                report.is_ready()
+            } else {
+                report.is_healthy()
+            };
+            if !passed {
+                std::process::exit(1);
+            }
+        }
         Commands::ListUtxoContract => {
             send_rpc_req(stream, RpcMsgReq::ContractUtxo)?;
         }
@@ -103,6 +210,9 @@ fn main() -> Result<(), MakerError> {
         Commands::GetNewAddress => {
             send_rpc_req(stream, RpcMsgReq::NewAddress)?;
         }
+        Commands::GetPayjoinUri => {
+            send_rpc_req(stream, RpcMsgReq::NewPayjoinUri)?;
+        }
         Commands::SendToAddress {
             address,
             amount,
@@ -123,8 +233,25 @@ fn main() -> Result<(), MakerError> {
         Commands::ShowDataDir => {
             send_rpc_req(stream, RpcMsgReq::GetDataDir)?;
         }
-        Commands::Stop => {
-            send_rpc_req(stream, RpcMsgReq::Stop)?;
+        Commands::RotateAddress => {
+            send_rpc_req(stream, RpcMsgReq::RotateAddress)?;
+        }
+        Commands::ShowIdentity => {
+            send_rpc_req(stream, RpcMsgReq::GetIdentity)?;
+        }
+        Commands::RotateIdentity => {
+            send_rpc_req(stream, RpcMsgReq::RotateIdentity)?;
+        }
+        Commands::ReloadConfig => {
+            send_rpc_req(stream, RpcMsgReq::ReloadConfig)?;
+        }
+        Commands::Stop { drain } => {
+            let req = if drain {
+                RpcMsgReq::Drain
+            } else {
+                RpcMsgReq::Stop
+            };
+            send_rpc_req(stream, req)?;
         }
         Commands::RedeemFidelity { index } => {
             send_rpc_req(stream, RpcMsgReq::RedeemFidelity(index))?;
@@ -135,19 +262,69 @@ fn main() -> Result<(), MakerError> {
         Commands::SyncWallet => {
             send_rpc_req(stream, RpcMsgReq::SyncWallet)?;
         }
+        Commands::ListReused => {
+            send_rpc_req(stream, RpcMsgReq::ListReused)?;
+        }
+        Commands::ListTransactions => {
+            send_rpc_req(stream, RpcMsgReq::ListTransactions)?;
+        }
+        Commands::SetMemo { target, memo } => {
+            send_rpc_req(stream, RpcMsgReq::SetMemo { target, memo })?;
+        }
+        Commands::ListSwaps => {
+            send_rpc_req(stream, RpcMsgReq::ListSwaps)?;
+        }
+        Commands::GetEarnings { period } => {
+            send_rpc_req(stream, RpcMsgReq::GetEarnings(period))?;
+        }
+        Commands::GetTraffic => {
+            send_rpc_req(stream, RpcMsgReq::GetTraffic)?;
+        }
+        Commands::Freeze { outpoint } => {
+            send_rpc_req(stream, RpcMsgReq::FreezeUtxo { outpoint })?;
+        }
+        Commands::Unfreeze { outpoint } => {
+            send_rpc_req(stream, RpcMsgReq::UnfreezeUtxo { outpoint })?;
+        }
+        Commands::ListFrozen => {
+            send_rpc_req(stream, RpcMsgReq::ListFrozen)?;
+        }
+        Commands::Cpfp { outpoint, feerate } => {
+            send_rpc_req(stream, RpcMsgReq::Cpfp { outpoint, feerate })?;
+        }
+        Commands::TransferSwapCoins {
+            outpoints,
+            feerate,
+            max_delay_secs,
+        } => {
+            send_rpc_req(
+                stream,
+                RpcMsgReq::TransferSwapCoins {
+                    outpoints,
+                    feerate,
+                    max_delay_secs,
+                },
+            )?;
+        }
+        Commands::ListStuckDeposits { feerate } => {
+            send_rpc_req(stream, RpcMsgReq::ListStuckDeposits { feerate })?;
+        }
+        Commands::BumpDeposits { feerate } => {
+            send_rpc_req(stream, RpcMsgReq::BumpDeposits { feerate })?;
+        }
     }
 
     Ok(())
 }
 
-fn send_rpc_req(mut stream: TcpStream, req: RpcMsgReq) -> Result<(), MakerError> {
+fn send_rpc_req(mut stream: TcpStream, req: RpcMsgReq) -> Result<RpcMsgResp, MakerError> {
     // stream.set_read_timeout(Some(Duration::from_secs(20)))?;
     stream.set_write_timeout(Some(Duration::from_secs(20)))?;
 
     send_message(&mut stream, &req)?;
 
     let response_bytes = read_message(&mut stream)?;
-    let response: RpcMsgResp = serde_cbor::from_slice(&response_bytes)?;
+    let response: RpcMsgResp = decode(&response_bytes)?;
 
     if matches!(response, RpcMsgResp::Pong) {
         println!("success");
@@ -155,5 +332,5 @@ fn send_rpc_req(mut stream: TcpStream, req: RpcMsgReq) -> Result<(), MakerError>
         println!("{}", response);
     }
 
-    Ok(())
+    Ok(response)
 }
@@ -1,14 +1,19 @@
+use bitcoin::Network;
 use bitcoind::bitcoincore_rpc::Auth;
 use clap::Parser;
 use coinswap::{
     maker::{start_maker_server, Maker, MakerBehavior, MakerError},
-    utill::{parse_proxy_auth, setup_maker_logger, ConnectionType},
-    wallet::RPCConfig,
+    utill::{default_rpc_address, parse_proxy_auth, setup_maker_logger, ConnectionType},
+    wallet::{load_passphrase_file, RPCConfig},
 };
 use std::{path::PathBuf, sync::Arc};
+
+#[cfg(feature = "tui")]
+use coinswap::maker::run_dashboard;
+
 /// Coinswap Maker Server
 ///
-/// The server requires a Bitcoin Core RPC connection running in Testnet4. It requires some starting balance, around 50,000 sats for Fidelity + Swap Liquidity (suggested 50,000 sats).
+/// The server requires a Bitcoin Core RPC connection running in Testnet4 (default) or Signet, selected with `--network`. It requires some starting balance, around 50,000 sats for Fidelity + Swap Liquidity (suggested 50,000 sats).
 /// So topup with at least 0.001 BTC to start all the node processses. Suggested faucet: https://mempool.space/testnet4/faucet
 ///
 /// All server process will start after the fidelity bond transaction confirms. This may take some time. Approx: 10 mins.
@@ -26,14 +31,13 @@ struct Cli {
     /// Optional DNS data directory. Default value : "~/.coinswap/maker"
     #[clap(long, short = 'd')]
     data_directory: Option<PathBuf>,
-    /// Bitcoin Core  RPC network address.
-    #[clap(
-        name = "ADDRESS:PORT",
-        long,
-        short = 'r',
-        default_value = "127.0.0.1:48332"
-    )]
-    pub rpc: String,
+    /// Bitcoin network of the backend node: bitcoin, testnet, testnet4, signet or regtest.
+    /// Custom signets are supported with `signet`. If set, the node is checked to run on it. Default: testnet4
+    #[clap(long, short = 'n')]
+    pub network: Option<Network>,
+    /// Bitcoin Core  RPC network address. Default: localhost, on the network's default RPC port.
+    #[clap(name = "ADDRESS:PORT", long, short = 'r')]
+    pub rpc: Option<String>,
     /// Bitcoin Core RPC authentication string (username, password).
     #[clap(
         name = "USER:PASSWD",
@@ -46,18 +50,42 @@ struct Cli {
     /// Optional wallet name. If the wallet exists, load the wallet, else create a new wallet with given name. Default: maker-wallet
     #[clap(name = "WALLET", long, short = 'w')]
     pub(crate) wallet_name: Option<String>,
+    /// File holding the wallet passphrase, encrypting the master key and swap coins of the wallet file.
+    /// Overrides the COINSWAP_WALLET_PASSPHRASE environment variable.
+    #[clap(long)]
+    pub passphrase_file: Option<PathBuf>,
+    /// Encrypt the master key and swap coins of a wallet file stored in the clear with the wallet passphrase,
+    /// before starting the server.
+    #[clap(long)]
+    pub encrypt_wallet: bool,
+    /// Show a live dashboard in the terminal instead of the logs. Quitting the dashboard stops the server.
+    #[cfg(feature = "tui")]
+    #[clap(long)]
+    pub tui: bool,
 }
 
 fn main() -> Result<(), MakerError> {
-    setup_maker_logger(log::LevelFilter::Info);
-
     let args = Cli::parse();
+    if let Some(path) = &args.passphrase_file {
+        load_passphrase_file(path)?;
+    }
+
+    #[cfg(feature = "tui")]
+    let tui = args.tui;
+    #[cfg(not(feature = "tui"))]
+    let tui = false;
+
+    setup_maker_logger(log::LevelFilter::Info, !tui);
 
+    let network = args.network.unwrap_or(Network::Testnet4);
     let rpc_config = RPCConfig {
-        url: args.rpc,
+        url: args.rpc.unwrap_or_else(|| default_rpc_address(network)),
         auth: Auth::UserPass(args.auth.0, args.auth.1),
         wallet_name: "random".to_string(), // we can put anything here as it will get updated in the init.
     };
+    if let Some(network) = args.network {
+        rpc_config.check_network(network)?;
+    }
 
     #[cfg(feature = "tor")]
     let connection_type = if cfg!(feature = "integration-test") {
@@ -80,6 +108,24 @@ fn main() -> Result<(), MakerError> {
         MakerBehavior::Normal,
     )?);
 
+    if args.encrypt_wallet {
+        maker.get_wallet().write()?.encrypt_swap_state()?;
+        log::info!("Wallet encrypted");
+    }
+
+    #[cfg(feature = "tui")]
+    if tui {
+        let server_maker = maker.clone();
+        let server = std::thread::Builder::new()
+            .name("Maker Server Thread".to_string())
+            .spawn(move || start_maker_server(server_maker))?;
+        // Returns once the Maker is shut down.
+        run_dashboard(maker)?;
+        return server
+            .join()
+            .map_err(|_| MakerError::General("Maker server thread panicked"))?;
+    }
+
     start_maker_server(maker)?;
 
     Ok(())
@@ -1,19 +1,36 @@
-use bitcoin::{Address, Amount};
+use bitcoin::{Address, Amount, FeeRate, Network, OutPoint};
 use bitcoind::bitcoincore_rpc::Auth;
 use clap::Parser;
 use coinswap::{
-    taker::{error::TakerError, SwapParams, Taker, TakerBehavior},
-    utill::{parse_proxy_auth, setup_taker_logger, ConnectionType, REQUIRED_CONFIRMS, UTXO},
-    wallet::{Destination, RPCConfig, SendAmount},
+    taker::{
+        error::TakerError, AmountDistribution, ReportFormat, ScheduleTiming, SwapParams,
+        SwapSchedule, Taker, TakerBehavior,
+    },
+    utill::{default_rpc_address, parse_proxy_auth, setup_taker_logger, ConnectionType, UTXO},
+    wallet::{
+        load_passphrase_file, transfer_delays, Destination, MemoTarget, RPCConfig, ScriptType,
+        SendAmount,
+    },
 };
 use log::LevelFilter;
 use serde_json::{json, to_string_pretty};
-use std::{path::PathBuf, str::FromStr};
+use std::{
+    path::PathBuf,
+    str::FromStr,
+    sync::atomic::AtomicBool,
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+#[cfg(feature = "tui")]
+use coinswap::taker::run_swap_dashboard;
+#[cfg(feature = "tui")]
+use std::sync::{atomic::Ordering::Relaxed, Arc};
 
 /// A simple command line app to operate as coinswap client.
 ///
 /// The app works as regular Bitcoin wallet with added capability to perform coinswaps. The app
-/// requires a running Bitcoin Core node with RPC access. It currently only runs on Testnet4.
+/// requires a running Bitcoin Core node with RPC access. It runs on Testnet4 by default, and on Signet with `--network signet`.
 /// Suggested faucet for getting Testnet4 coins: https://mempool.space/testnet4/faucet
 ///
 /// For more detailed usage information, please refer: https://github.com/citadel-tech/coinswap/blob/master/docs/app%20demos/taker.md
@@ -27,14 +44,13 @@ struct Cli {
     #[clap(long, short = 'd')]
     data_directory: Option<PathBuf>,
 
-    /// Bitcoin Core RPC address:port value
-    #[clap(
-        name = "ADDRESS:PORT",
-        long,
-        short = 'r',
-        default_value = "127.0.0.1:48332"
-    )]
-    pub rpc: String,
+    /// Bitcoin network of the backend node: bitcoin, testnet, testnet4, signet or regtest.
+    /// Custom signets are supported with `signet`. If set, the node is checked to run on it. Default: testnet4
+    #[clap(long, short = 'n')]
+    pub network: Option<Network>,
+    /// Bitcoin Core RPC address:port value. Default: localhost, on the network's default RPC port.
+    #[clap(name = "ADDRESS:PORT", long, short = 'r')]
+    pub rpc: Option<String>,
 
     /// Bitcoin Core RPC authentication string. Ex: username:password
     #[clap(name="USER:PASSWORD",short='a',long, value_parser = parse_proxy_auth, default_value = "user:password")]
@@ -44,6 +60,11 @@ struct Cli {
     #[clap(name = "WALLET", long, short = 'w')]
     pub wallet_name: Option<String>,
 
+    /// File holding the wallet passphrase, encrypting the master key and swap coins of the wallet file.
+    /// Overrides the COINSWAP_WALLET_PASSPHRASE environment variable.
+    #[clap(long)]
+    pub passphrase_file: Option<PathBuf>,
+
     /// Sets the verbosity level of debug.log file
     #[clap(long, short = 'v', possible_values = &["off", "error", "warn", "info", "debug", "trace"], default_value = "info")]
     pub verbosity: String,
@@ -55,6 +76,9 @@ struct Cli {
 
 #[derive(Parser, Debug)]
 enum Commands {
+    /// Create or import the taker wallet from a BIP39 seed phrase.
+    #[clap(subcommand)]
+    Wallet(WalletCommands),
     // TODO: Design a better structure to display different utxos and balance groups.
     /// Lists all utxos we know about along with their spend info. This is useful for debugging
     ListUtxo,
@@ -72,6 +96,29 @@ enum Commands {
     GetBalances,
     /// Returns a new address
     GetNewAddress,
+    /// Lists the wallet addresses which received coins more than once. Spending such coins together links the payments.
+    ListReused,
+    /// Lists the wallet transactions, newest first, tagged deposit, send, swap-funding, swap-incoming, contract, fidelity or sweep.
+    ListTransactions,
+    /// Attaches a memo to a transaction or a swap, shown in the history and the swap reports. An empty memo removes it.
+    SetMemo {
+        /// Txid of the transaction, or id of the swap.
+        target: String,
+        /// Text of the memo.
+        memo: String,
+    },
+    /// Freezes a wallet UTXO. Frozen coins are never spent by swaps, consolidations or sweeps, nor counted as spendable.
+    Freeze {
+        /// The wallet outpoint, as `txid:vout`.
+        outpoint: String,
+    },
+    /// Unfreezes a frozen UTXO.
+    Unfreeze {
+        /// The wallet outpoint, as `txid:vout`.
+        outpoint: String,
+    },
+    /// Lists the frozen UTXOs.
+    ListFrozen,
     /// Send to an external wallet address.
     SendToAddress {
         /// Recipient's address.
@@ -84,6 +131,35 @@ enum Commands {
         #[clap(long, short = 'f')]
         fee: u64,
     },
+    /// Consolidate all regular wallet utxos, dust included, into one new internal address. Dust utxos are
+    /// otherwise never spent.
+    Consolidate {
+        /// Mining fee to be paid in sats
+        #[clap(long, short = 'f')]
+        fee: u64,
+    },
+    /// Bump the fee of a stuck transaction, by spending one of its wallet outputs with a child transaction (CPFP).
+    /// Returns the txid of the child transaction.
+    Cpfp {
+        /// The wallet outpoint to spend, as `txid:vout`.
+        #[clap(long, short = 'o')]
+        outpoint: String,
+        /// Target feerate of the parent + child package, in sats/vbyte.
+        #[clap(long, short = 'f')]
+        feerate: u32,
+    },
+    /// Moves swap coins into the regular balance. Each coin is spent alone, to its own fresh internal address.
+    TransferSwapCoins {
+        /// The swap coins to transfer, as `txid:vout`. See `list-utxo-swap`.
+        #[clap(required = true)]
+        outpoints: Vec<String>,
+        /// Feerate of each transfer, in sats/vbyte.
+        #[clap(long, short = 'f')]
+        feerate: u32,
+        /// Broadcast each transfer after a random delay, up to this many seconds. The command waits for all of them.
+        #[clap(long, short = 'd', default_value = "0")]
+        max_delay_secs: u64,
+    },
     /// Update the offerbook with current market offers and display them
     FetchOffers,
 
@@ -97,30 +173,204 @@ enum Commands {
         /// Sets the swap amount in sats.
         #[clap(long, short = 'a', default_value = "20000")]
         amount: u64,
+        /// Swap the whole spendable balance of the wallet. The amount is ignored.
+        #[clap(long)]
+        sweep: bool,
+        /// Abort the swap if its total fee (maker and miner fees) can exceed these many sats.
+        #[clap(long)]
+        max_fee: Option<u64>,
+        /// Abort the swap if its total fee can exceed these parts per million of the swap amount.
+        #[clap(long)]
+        max_fee_ppm: Option<u64>,
+        /// Script type of the wallet outputs the swapped coins are claimed to, `p2wpkh` or `p2tr`.
+        /// Default: the `script_type` of the config.
+        #[clap(long)]
+        script_type: Option<ScriptType>,
+        /// Only fund the swap with coins of at least these many confirmations.
+        #[clap(long, default_value = "0")]
+        min_confirmations: u32,
+        /// Only fund the swap with coins confirmed at least these many seconds ago.
+        #[clap(long, default_value = "0")]
+        min_input_age: u64,
+        /// Don't fund the swap with the unconfirmed change of the wallet's own txs.
+        #[clap(long)]
+        no_unconfirmed_change: bool,
+        /// Go through the swap up to its first broadcast, and print the txs it would have broadcast instead.
+        /// Nothing is broadcast nor saved in the wallet.
+        #[clap(long)]
+        dry_run: bool,
+        /// Show the progress of the swap in a terminal view instead of the logs.
+        #[cfg(feature = "tui")]
+        #[clap(long)]
+        tui: bool,
         // /// Sets how many new swap utxos to get. The swap amount will be randomly distrubted across the new utxos.
         // /// Increasing this number also increases total swap fee.
         // #[clap(long, short = 'u', default_value = "1")]
         // utxos: u32,
     },
+    /// Preview the fees and timelocks of a coinswap without starting it: the makers of the route and their fees,
+    /// the miner fees, and how long the coins may stay locked if the swap has to be recovered.
+    Quote {
+        /// Sets the maker count to swap with.
+        #[clap(long, short = 'm', default_value = "2")]
+        makers: usize,
+        /// Sets the swap amount in sats.
+        #[clap(long, short = 'a', default_value = "20000")]
+        amount: u64,
+        /// Quote swapping the whole spendable balance of the wallet. The amount is ignored.
+        #[clap(long)]
+        sweep: bool,
+    },
+    /// Run coinswaps on a recurring schedule. Each run swaps a random amount between min and max amount.
+    /// Without any timing option, runs every 24 hours.
+    Schedule {
+        /// Sets the maker count to swap with.
+        #[clap(long, short = 'm', default_value = "2")]
+        makers: usize,
+        /// Minimum swap amount in sats.
+        #[clap(long, default_value = "20000")]
+        min_amount: u64,
+        /// Maximum swap amount in sats.
+        #[clap(long, default_value = "20000")]
+        max_amount: u64,
+        /// Run every given seconds.
+        #[clap(long)]
+        every: Option<u64>,
+        /// Run after a random delay between `min-interval` and `max-interval` seconds.
+        #[clap(long, requires = "max_interval")]
+        min_interval: Option<u64>,
+        /// Maximum random delay in seconds.
+        #[clap(long, requires = "min_interval")]
+        max_interval: Option<u64>,
+        /// Run every day at the given UTC time, as `HH:MM`.
+        #[clap(long)]
+        daily_at: Option<String>,
+        /// Number of runs. Runs forever if not set.
+        #[clap(long)]
+        runs: Option<u32>,
+    },
     /// Recover from all failed swaps
     Recover,
+    /// Export the records of completed swaps: inputs, outputs, per-maker fees, miner fees, timestamps and
+    /// resulting utxos.
+    ExportReport {
+        /// Output format, `json` or `csv`.
+        #[clap(long, short = 'f', default_value = "json")]
+        format: ReportFormat,
+        /// Only swaps completed at or after this unix timestamp.
+        #[clap(long)]
+        from: Option<u64>,
+        /// Only swaps completed before this unix timestamp.
+        #[clap(long)]
+        to: Option<u64>,
+    },
+    /// Export the pre-signed contract and claim txs of an unfinished swap as JSON, with the heights to
+    /// broadcast them at, to hand its recovery over to an always-on machine.
+    ExportRecoveryBundle {
+        /// Id of the swap, as logged at its start.
+        swap_id: String,
+    },
+    /// Export the evidence saved when a swap aborted as JSON: hashes of the messages exchanged with the makers,
+    /// events of the swap txs seen on the node, and the swap txs. For a third party to attribute the blame.
+    ExportDisputeBundle {
+        /// Id of the swap, as logged at its start.
+        swap_id: String,
+    },
+    /// Analyze the transaction graph of a completed swap, and report the risks left of linking the coins sent to
+    /// the coins received: amount correlations, timing, script types and co-spends. Scored out of 100.
+    PrivacyReport {
+        /// Id of the swap, as logged at its start.
+        swap_id: String,
+    },
+    /// Dump the current offer book as a timestamped JSON snapshot: fees, bond values and size limits of
+    /// every maker, with the maker addresses hashed.
+    MarketSnapshot {
+        /// Get the latest snapshot taken by the directory server, instead of fetching the offers.
+        #[clap(long)]
+        from_directory: bool,
+    },
+}
+
+#[derive(Parser, Debug)]
+enum WalletCommands {
+    /// Create a new wallet, and show its 12 words seed phrase to back up.
+    Create,
+    /// Restore a wallet from its seed phrase, read from the standard input, and scan the chain for its coins.
+    Import {
+        /// Block height the wallet was created at, or any height before. The chain is scanned from there, 0 scans
+        /// it from genesis.
+        #[clap(long, short = 'b')]
+        birthday: u64,
+        /// Overwrite the wallet file, if there's one already.
+        #[clap(long)]
+        force: bool,
+    },
+    /// Encrypt the master key and swap coins of a wallet file stored in the clear with the wallet passphrase.
+    Encrypt,
 }
 
 fn main() -> Result<(), TakerError> {
     let args = Cli::parse();
+    if let Some(path) = &args.passphrase_file {
+        load_passphrase_file(path)?;
+    }
+
+    #[cfg(feature = "tui")]
+    let tui = matches!(args.command, Commands::Coinswap { tui: true, .. });
+    #[cfg(not(feature = "tui"))]
+    let tui = false;
+
     setup_taker_logger(
         LevelFilter::from_str(&args.verbosity).unwrap(),
-        matches!(
+        !tui && matches!(
             args.command,
-            Commands::Recover | Commands::FetchOffers | Commands::Coinswap { .. }
+            Commands::Recover
+                | Commands::FetchOffers
+                | Commands::Coinswap { .. }
+                | Commands::Schedule { .. }
         ),
     );
 
+    let network = args.network.unwrap_or(Network::Testnet4);
     let rpc_config = RPCConfig {
-        url: args.rpc,
+        url: args.rpc.unwrap_or_else(|| default_rpc_address(network)),
         auth: Auth::UserPass(args.auth.0, args.auth.1),
         wallet_name: "random".to_string(), // we can put anything here as it will get updated in the init.
     };
+    if let Some(network) = args.network {
+        rpc_config.check_network(network)?;
+    }
+
+    // The wallet commands run before the Taker is initialized, as that creates a wallet when there's none.
+    if let Commands::Wallet(command @ (WalletCommands::Create | WalletCommands::Import { .. })) =
+        &args.command
+    {
+        let (seed_phrase, birthday, force) = match command {
+            WalletCommands::Create => (None, None, false),
+            WalletCommands::Import { birthday, force } => {
+                println!("Enter the seed phrase:");
+                let mut seed_phrase = String::new();
+                std::io::stdin().read_line(&mut seed_phrase)?;
+                (Some(seed_phrase), Some(*birthday), *force)
+            }
+            WalletCommands::Encrypt => unreachable!("The wallet is encrypted after the Taker init"),
+        };
+        println!("Creating the wallet and syncing it, this may take a while...");
+        let mnemonic = Taker::create_wallet(
+            args.data_directory,
+            args.wallet_name,
+            Some(rpc_config),
+            seed_phrase.as_deref(),
+            birthday,
+            force,
+        )?;
+        if seed_phrase.is_none() {
+            println!("Write down the seed phrase of the new wallet, and keep it safe:");
+            println!("{}", mnemonic);
+        }
+        println!("Wallet ready.");
+        return Ok(());
+    }
 
     #[cfg(feature = "tor")]
     let connection_type = if cfg!(feature = "integration-test") {
@@ -144,14 +394,16 @@ fn main() -> Result<(), TakerError> {
         Commands::ListUtxo => {
             let utxos = taker.get_wallet().list_all_utxo_spend_info(None)?;
             for utxo in utxos {
-                let utxo = UTXO::from_utxo_data(utxo);
+                let frozen = taker.get_wallet().is_frozen(&utxo.0);
+                let utxo = UTXO::from_utxo_data(utxo, frozen);
                 println!("{}", serde_json::to_string_pretty(&utxo)?);
             }
         }
         Commands::ListUtxoRegular => {
             let utxos = taker.get_wallet().list_descriptor_utxo_spend_info(None)?;
             for utxo in utxos {
-                let utxo = UTXO::from_utxo_data(utxo);
+                let frozen = taker.get_wallet().is_frozen(&utxo.0);
+                let utxo = UTXO::from_utxo_data(utxo, frozen);
                 println!("{}", serde_json::to_string_pretty(&utxo)?);
             }
         }
@@ -160,7 +412,8 @@ fn main() -> Result<(), TakerError> {
                 .get_wallet()
                 .list_incoming_swap_coin_utxo_spend_info(None)?;
             for utxo in utxos {
-                let utxo = UTXO::from_utxo_data(utxo);
+                let frozen = taker.get_wallet().is_frozen(&utxo.0);
+                let utxo = UTXO::from_utxo_data(utxo, frozen);
                 println!("{}", serde_json::to_string_pretty(&utxo)?);
             }
         }
@@ -169,7 +422,8 @@ fn main() -> Result<(), TakerError> {
                 .get_wallet()
                 .list_live_timelock_contract_spend_info(None)?;
             for utxo in utxos {
-                let utxo = UTXO::from_utxo_data(utxo);
+                let frozen = taker.get_wallet().is_frozen(&utxo.0);
+                let utxo = UTXO::from_utxo_data(utxo, frozen);
                 println!("{}", serde_json::to_string_pretty(&utxo)?);
             }
         }
@@ -181,6 +435,7 @@ fn main() -> Result<(), TakerError> {
                     "regular": balances.regular.to_sat(),
                     "contract": balances.contract.to_sat(),
                     "swap": balances.swap.to_sat(),
+                    "frozen": balances.frozen.to_sat(),
                     "spendable": balances.spendable.to_sat(),
                 }))
                 .unwrap()
@@ -190,6 +445,29 @@ fn main() -> Result<(), TakerError> {
             let address = taker.get_wallet_mut().get_next_external_address()?;
             println!("{:?}", address);
         }
+        Commands::ListReused => {
+            let reused = taker.get_wallet().list_reused()?;
+            println!("{}", to_string_pretty(&reused)?);
+        }
+        Commands::ListTransactions => {
+            let txs = taker.get_wallet().list_transactions()?;
+            println!("{}", to_string_pretty(&txs)?);
+        }
+        Commands::SetMemo { target, memo } => {
+            let target = target.parse::<MemoTarget>()?;
+            taker.get_wallet_mut().set_memo(target, &memo)?;
+        }
+        Commands::Freeze { outpoint } => {
+            let outpoint = OutPoint::from_str(&outpoint).expect("Invalid outpoint");
+            taker.get_wallet_mut().freeze(outpoint)?;
+        }
+        Commands::Unfreeze { outpoint } => {
+            let outpoint = OutPoint::from_str(&outpoint).expect("Invalid outpoint");
+            taker.get_wallet_mut().unfreeze(outpoint)?;
+        }
+        Commands::ListFrozen => {
+            println!("{}", to_string_pretty(&taker.get_wallet().frozen_utxos())?);
+        }
         Commands::SendToAddress {
             address,
             amount,
@@ -227,23 +505,206 @@ fn main() -> Result<(), TakerError> {
             println!("{}", txid);
         }
 
+        Commands::Consolidate { fee } => {
+            let tx = taker.get_wallet_mut().consolidate(Amount::from_sat(fee))?;
+            let txid = taker.get_wallet().send_tx(&tx)?;
+            println!("{}", txid);
+        }
+
+        Commands::Cpfp { outpoint, feerate } => {
+            let outpoint = OutPoint::from_str(&outpoint).expect("Invalid outpoint");
+            let child = taker
+                .get_wallet_mut()
+                .cpfp(outpoint, FeeRate::from_sat_per_vb_u32(feerate))?;
+            let txid = taker.get_wallet().send_tx(&child)?;
+            println!("{}", txid);
+        }
+        Commands::TransferSwapCoins {
+            outpoints,
+            feerate,
+            max_delay_secs,
+        } => {
+            let outpoints = outpoints
+                .iter()
+                .map(|outpoint| OutPoint::from_str(outpoint).expect("Invalid outpoint"))
+                .collect::<Vec<_>>();
+            let txs = taker
+                .get_wallet_mut()
+                .transfer_swap_coins(&outpoints, FeeRate::from_sat_per_vb_u32(feerate))?;
+            let delays = transfer_delays(txs.len(), Duration::from_secs(max_delay_secs));
+            let start = Instant::now();
+            for (tx, delay) in txs.iter().zip(delays) {
+                sleep(delay.saturating_sub(start.elapsed()));
+                let txid = taker.get_wallet().send_tx(tx)?;
+                println!("{}", txid);
+            }
+        }
+
         Commands::FetchOffers => {
             let offerbook = taker.fetch_offers()?;
             println!("{:#?}", offerbook)
         }
-        Commands::Coinswap { makers, amount } => {
-            let swap_params = SwapParams {
-                send_amount: Amount::from_sat(amount),
-                maker_count: makers,
-                tx_count: 1,
-                required_confirms: REQUIRED_CONFIRMS,
+        Commands::Coinswap {
+            makers,
+            amount,
+            sweep,
+            max_fee,
+            max_fee_ppm,
+            script_type,
+            min_confirmations,
+            min_input_age,
+            no_unconfirmed_change,
+            dry_run,
+            ..
+        } => {
+            let mut swap_params = if sweep {
+                SwapParams::sweep(makers, 1)
+            } else {
+                SwapParams {
+                    send_amount: Amount::from_sat(amount),
+                    maker_count: makers,
+                    tx_count: 1,
+                    amount_distribution: AmountDistribution::default(),
+                    sweep: false,
+                    max_total_fee_sats: None,
+                    max_fee_ppm: None,
+                    script_type: None,
+                    min_input_confirmations: 0,
+                    min_input_age_secs: 0,
+                    forbid_unconfirmed_change: false,
+                }
             };
+            swap_params.max_total_fee_sats = max_fee;
+            swap_params.max_fee_ppm = max_fee_ppm;
+            swap_params.script_type = script_type;
+            swap_params.min_input_confirmations = min_confirmations;
+            swap_params.min_input_age_secs = min_input_age;
+            swap_params.forbid_unconfirmed_change = no_unconfirmed_change;
+
+            if dry_run {
+                let dry_run = taker.dry_run_coinswap(swap_params)?;
+                println!("{}", to_string_pretty(&dry_run)?);
+                return Ok(());
+            }
+
+            #[cfg(feature = "tui")]
+            if tui {
+                let events = taker.subscribe_events();
+                let finished = Arc::new(AtomicBool::new(false));
+                let dashboard = {
+                    let finished = finished.clone();
+                    std::thread::spawn(move || run_swap_dashboard(events, &finished))
+                };
+                let result = taker.do_coinswap(swap_params);
+                finished.store(true, Relaxed);
+                dashboard.join().expect("Dashboard thread panicked")?;
+                return result;
+            }
+
             taker.do_coinswap(swap_params)?;
         }
 
+        Commands::Quote {
+            makers,
+            amount,
+            sweep,
+        } => {
+            let swap_params = if sweep {
+                SwapParams::sweep(makers, 1)
+            } else {
+                SwapParams {
+                    send_amount: Amount::from_sat(amount),
+                    maker_count: makers,
+                    tx_count: 1,
+                    ..SwapParams::default()
+                }
+            };
+            let quote = taker.quote(swap_params)?;
+            println!("{}", to_string_pretty(&quote)?);
+        }
+
+        Commands::Schedule {
+            makers,
+            min_amount,
+            max_amount,
+            every,
+            min_interval,
+            max_interval,
+            daily_at,
+            runs,
+        } => {
+            let timing = if let Some(daily_at) = daily_at {
+                let (hour, minute) = daily_at
+                    .split_once(':')
+                    .and_then(|(h, m)| Some((h.parse().ok()?, m.parse().ok()?)))
+                    .filter(|(h, m): &(u8, u8)| *h < 24 && *m < 60)
+                    .expect("Invalid daily time, expected HH:MM");
+                ScheduleTiming::DailyAt { hour, minute }
+            } else if let (Some(min), Some(max)) = (min_interval, max_interval) {
+                ScheduleTiming::RandomInterval {
+                    min: Duration::from_secs(min),
+                    max: Duration::from_secs(max),
+                }
+            } else {
+                ScheduleTiming::Every(Duration::from_secs(every.unwrap_or(24 * 60 * 60)))
+            };
+            let schedule = SwapSchedule {
+                timing,
+                min_amount: Amount::from_sat(min_amount),
+                max_amount: Amount::from_sat(max_amount.max(min_amount)),
+                swap_params: SwapParams {
+                    send_amount: Amount::ZERO,
+                    maker_count: makers,
+                    tx_count: 1,
+                    amount_distribution: AmountDistribution::default(),
+                    sweep: false,
+                    max_total_fee_sats: None,
+                    max_fee_ppm: None,
+                    script_type: None,
+                    min_input_confirmations: 0,
+                    min_input_age_secs: 0,
+                    forbid_unconfirmed_change: false,
+                },
+                runs,
+            };
+            taker.run_schedule(&schedule, &AtomicBool::new(false))?;
+        }
+
+        Commands::Wallet(WalletCommands::Encrypt) => {
+            taker.get_wallet_mut().encrypt_swap_state()?;
+            println!("Wallet encrypted.");
+        }
+        Commands::Wallet(_) => unreachable!("Wallet commands run before the Taker init"),
         Commands::Recover => {
             taker.recover_from_swap()?;
         }
+        Commands::ExportReport { format, from, to } => {
+            let report = taker.export_report(from.unwrap_or(0)..to.unwrap_or(u64::MAX), format)?;
+            println!("{}", report);
+        }
+        Commands::ExportRecoveryBundle { swap_id } => {
+            let bundle = taker.export_recovery_bundle(&swap_id)?;
+            println!("{}", to_string_pretty(&bundle).unwrap());
+        }
+        Commands::ExportDisputeBundle { swap_id } => {
+            let bundle = taker.export_dispute_bundle(&swap_id)?;
+            println!("{}", to_string_pretty(&bundle)?);
+        }
+        Commands::PrivacyReport { swap_id } => {
+            let report = taker.privacy_report(&swap_id)?;
+            println!("{}", to_string_pretty(&report)?);
+        }
+        Commands::MarketSnapshot { from_directory } => {
+            let snapshot = if from_directory {
+                taker.fetch_market_snapshot()?
+            } else {
+                Some(taker.market_snapshot()?)
+            };
+            match snapshot {
+                Some(snapshot) => println!("{}", to_string_pretty(&snapshot).unwrap()),
+                None => println!("The directory has no market snapshot yet."),
+            }
+        }
     }
 
     Ok(())
@@ -0,0 +1,291 @@
+//! Multi-path transaction broadcasting.
+//!
+//! Recovery txs must get out before their timelocks expire, even when the local node can't relay them. The
+//! [Broadcaster] pushes a tx to the local node, or whichever [BlockchainBackend] the wallet uses, first. If the node can't be reached, rejects it by its mempool
+//! policy, or has no peers to relay it to, the tx is also posted over Tor to public broadcast endpoints: the onion
+//! services of mempool.space and blockstream.info, for the networks they serve. Txs invalid by consensus, or with
+//! their inputs missing or spent, are rejected by every node alike, and never leave the local node.
+//!
+//! Endpoints are only reached over Tor. Without it, txs go through the local node alone.
+
+use std::{
+    io::{self, Read, Write},
+    time::Duration,
+};
+
+use bitcoin::{consensus::encode::serialize_hex, Network, Transaction, Txid};
+use bitcoind::bitcoincore_rpc::{
+    jsonrpc::{self, error::RpcError},
+    Error as RpcClientError,
+};
+use socks::Socks5Stream;
+
+use crate::{
+    notifier::parse_http_url,
+    wallet::{BlockchainBackend, WalletError},
+};
+
+/// Onion service of mempool.space.
+pub(crate) const MEMPOOL_SPACE_ONION: &str =
+    "http://mempoolhqx4isw62xs7abwphsq7ldayuidyx2v2oethdhhj6mlo2r6ad.onion";
+/// Onion service of blockstream.info.
+const BLOCKSTREAM_ONION: &str =
+    "http://explorerzydxu5ecjrkwceayqybizmpjjznk5izmitf2modhcusuqlid.onion";
+/// Timeout of each read and write to an endpoint. Onion services are slow to reach.
+const ENDPOINT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// RPC error of the node on a tx with its inputs missing or spent.
+const RPC_VERIFY_ERROR: i32 = -25;
+/// RPC error of the node on a tx it rejects, by consensus or by its mempool policy.
+const RPC_VERIFY_REJECTED: i32 = -26;
+/// RPC error of the node on a tx already confirmed.
+const RPC_VERIFY_ALREADY_IN_CHAIN: i32 = -27;
+/// Reject reasons of the txs invalid by consensus. The other reasons are of the mempool policy of the node.
+const CONSENSUS_REJECT_REASONS: [&str; 4] = [
+    "mandatory-script-verify-flag-failed",
+    "non-final",
+    "non-BIP68-final",
+    "bad-txns-",
+];
+/// Reject reasons starting like the consensus ones, but of the mempool policy.
+const POLICY_BAD_TXNS_REASONS: [&str; 2] =
+    ["bad-txns-nonstandard-inputs", "bad-txns-too-many-sigops"];
+
+/// URLs of the public endpoints accepting raw txs for `network`, in order of preference.
+pub fn default_endpoints(network: Network) -> Vec<String> {
+    let paths: &[(&str, &str)] = match network {
+        Network::Bitcoin => &[(MEMPOOL_SPACE_ONION, ""), (BLOCKSTREAM_ONION, "")],
+        Network::Testnet => &[
+            (MEMPOOL_SPACE_ONION, "/testnet"),
+            (BLOCKSTREAM_ONION, "/testnet"),
+        ],
+        Network::Testnet4 => &[(MEMPOOL_SPACE_ONION, "/testnet4")],
+        Network::Signet => &[(MEMPOOL_SPACE_ONION, "/signet")],
+        _ => &[],
+    };
+    paths
+        .iter()
+        .map(|(host, network_path)| format!("{}{}/api/tx", host, network_path))
+        .collect()
+}
+
+/// Broadcasts txs through the local node, and public endpoints as fallback. See the [module docs](self).
+#[derive(Debug, Clone, Default)]
+pub struct Broadcaster {
+    endpoints: Vec<String>,
+    socks_port: Option<u16>,
+}
+
+impl Broadcaster {
+    /// A broadcaster through the local node only.
+    pub fn local() -> Self {
+        Self::default()
+    }
+
+    /// A broadcaster falling back to the [default_endpoints] of `network`, reached through the Tor socks port.
+    pub fn new(network: Network, socks_port: u16) -> Self {
+        Self::with_endpoints(default_endpoints(network), socks_port)
+    }
+
+    /// A broadcaster falling back to the given endpoints, reached through the Tor socks port.
+    ///
+    /// Endpoints must take the tx hex as the body of an HTTP POST, like the Esplora `/api/tx` API.
+    pub fn with_endpoints(endpoints: Vec<String>, socks_port: u16) -> Self {
+        Self {
+            endpoints,
+            socks_port: Some(socks_port),
+        }
+    }
+
+    /// Broadcasts the tx through the local node, and through the public endpoints if the node can't be reached,
+    /// rejects it by its mempool policy, or has no peers. Fails with the error of the node if no path took the tx.
+    pub fn broadcast(
+        &self,
+        chain: &dyn BlockchainBackend,
+        tx: &Transaction,
+    ) -> Result<Txid, WalletError> {
+        let txid = tx.compute_txid();
+        let node_result = chain.broadcast(tx);
+        match &node_result {
+            Ok(_) => match chain.relays() {
+                Ok(false) => log::warn!("Local node has no peers to relay tx {} to", txid),
+                _ => return Ok(txid),
+            },
+            Err(WalletError::Rpc(e)) if rejected_by_any_node(e) => {
+                log::error!(
+                    "Local node rejected tx {}, other nodes reject it too | {:?}",
+                    txid,
+                    e
+                );
+                return node_result;
+            }
+            Err(e) => log::warn!("Local node failed broadcasting tx {} | {:?}", txid, e),
+        }
+
+        let socks_port = match self.socks_port {
+            Some(socks_port) if !self.endpoints.is_empty() => socks_port,
+            _ => return node_result,
+        };
+        let tx_hex = serialize_hex(tx);
+        let mut pushed = false;
+        for endpoint in &self.endpoints {
+            match tor_http_request(endpoint, socks_port, "POST", &tx_hex) {
+                Ok(_) => {
+                    log::info!("Broadcasted tx {} through {}", txid, endpoint);
+                    pushed = true;
+                }
+                Err(e) => log::warn!(
+                    "Failed broadcasting tx {} through {} | {:?}",
+                    txid,
+                    endpoint,
+                    e
+                ),
+            }
+        }
+        if pushed {
+            Ok(txid)
+        } else {
+            node_result
+        }
+    }
+}
+
+/// Whether the node rejected the tx as invalid by consensus, or spending missing or spent inputs, or as already
+/// confirmed. No other node takes it either.
+fn rejected_by_any_node(error: &RpcClientError) -> bool {
+    let (code, message) = match error {
+        RpcClientError::JsonRpc(jsonrpc::Error::Rpc(RpcError { code, message, .. })) => {
+            (*code, message)
+        }
+        // The node couldn't be reached.
+        _ => return false,
+    };
+    match code {
+        RPC_VERIFY_ERROR | RPC_VERIFY_ALREADY_IN_CHAIN => true,
+        RPC_VERIFY_REJECTED => {
+            CONSENSUS_REJECT_REASONS
+                .iter()
+                .any(|reason| message.starts_with(reason))
+                && !POLICY_BAD_TXNS_REASONS
+                    .iter()
+                    .any(|reason| message.starts_with(reason))
+        }
+        _ => false,
+    }
+}
+
+/// Sends an HTTP request to an `http://` URL over Tor, and returns the body of the response. Fails on a non 2xx
+/// response.
+pub(crate) fn tor_http_request(
+    url: &str,
+    socks_port: u16,
+    method: &str,
+    body: &str,
+) -> io::Result<String> {
+    let (address, path) = parse_http_url(url)?;
+    let host = address.split(':').next().unwrap_or_default();
+    let mut stream = Socks5Stream::connect(
+        format!("127.0.0.1:{}", socks_port).as_str(),
+        address.as_str(),
+    )?
+    .into_inner();
+    stream.set_read_timeout(Some(ENDPOINT_TIMEOUT))?;
+    stream.set_write_timeout(Some(ENDPOINT_TIMEOUT))?;
+
+    // HTTP/1.0 responses are never chunked.
+    write!(
+        stream,
+        "{} {} HTTP/1.0\r\nHost: {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\n\r\n{}",
+        method,
+        path,
+        host,
+        body.len(),
+        body
+    )?;
+    stream.flush()?;
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    let status = response
+        .split_whitespace()
+        .nth(1)
+        .and_then(|status| status.parse::<u16>().ok());
+    let body = response
+        .split_once("\r\n\r\n")
+        .map(|(_, body)| body.to_string())
+        .unwrap_or_default();
+    match status {
+        Some(200..=299) => Ok(body),
+        _ => Err(io::Error::other(body)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_endpoints() {
+        assert_eq!(
+            default_endpoints(Network::Bitcoin),
+            vec![
+                format!("{}/api/tx", MEMPOOL_SPACE_ONION),
+                format!("{}/api/tx", BLOCKSTREAM_ONION),
+            ]
+        );
+        assert_eq!(
+            default_endpoints(Network::Testnet4),
+            vec![format!("{}/testnet4/api/tx", MEMPOOL_SPACE_ONION)]
+        );
+        assert!(default_endpoints(Network::Regtest).is_empty());
+
+        let (address, path) = parse_http_url(&default_endpoints(Network::Signet)[0]).unwrap();
+        assert_eq!(
+            address,
+            "mempoolhqx4isw62xs7abwphsq7ldayuidyx2v2oethdhhj6mlo2r6ad.onion:80"
+        );
+        assert_eq!(path, "/signet/api/tx");
+    }
+
+    #[test]
+    fn test_rejected_by_any_node() {
+        let rpc_error = |code, message: &str| {
+            RpcClientError::JsonRpc(jsonrpc::Error::Rpc(RpcError {
+                code,
+                message: message.to_string(),
+                data: None,
+            }))
+        };
+
+        // Rejected by any node.
+        for (code, message) in [
+            (
+                RPC_VERIFY_REJECTED,
+                "mandatory-script-verify-flag-failed (Signature must be zero for failed CHECK(MULTI)SIG operation)",
+            ),
+            (RPC_VERIFY_REJECTED, "non-BIP68-final"),
+            (RPC_VERIFY_REJECTED, "bad-txns-in-belowout"),
+            (RPC_VERIFY_ERROR, "bad-txns-inputs-missingorspent"),
+            (RPC_VERIFY_ALREADY_IN_CHAIN, "Transaction already in block chain"),
+        ] {
+            assert!(rejected_by_any_node(&rpc_error(code, message)), "{}", message);
+        }
+
+        // Other nodes may take it.
+        for (code, message) in [
+            (RPC_VERIFY_REJECTED, "min relay fee not met, 100 < 141"),
+            (RPC_VERIFY_REJECTED, "mempool min fee not met"),
+            (RPC_VERIFY_REJECTED, "txn-mempool-conflict"),
+            (RPC_VERIFY_REJECTED, "bad-txns-nonstandard-inputs"),
+            (-28, "Loading block index..."),
+        ] {
+            assert!(
+                !rejected_by_any_node(&rpc_error(code, message)),
+                "{}",
+                message
+            );
+        }
+        let unreachable = RpcClientError::Io(io::Error::from(io::ErrorKind::ConnectionRefused));
+        assert!(!rejected_by_any_node(&unreachable));
+    }
+}
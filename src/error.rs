@@ -1,7 +1,33 @@
-//! High-level network and protocol errors.
+//! High-level network and protocol errors, and the stable codes of all errors.
+//!
+//! Every error enum of the crate implements [ErrorCode]. Each one owns a range of codes:
+//!
+//! | Codes | Errors                                                  |
+//! |-------|---------------------------------------------------------|
+//! | 1xxx  | [NetError]                                              |
+//! | 2xxx  | [ProtocolError](crate::protocol::error::ProtocolError)  |
+//! | 3xxx  | [WalletError](crate::wallet::WalletError)               |
+//! | 4xxx  | `DirectoryServerError`                                  |
+//! | 5xxx  | `MakerError`                                            |
+//! | 6xxx  | `TakerError`                                            |
+//!
+//! A code keeps its meaning across releases: new variants get new codes, and the codes of removed variants
+//! aren't reused. Errors wrapping another error carry the code of the wrapped one, so a wallet error has the
+//! same code whether it's returned by the Maker or the Taker.
 
 use std::error::Error;
 
+/// Stable numeric code of an error, for RPC clients and retry logic to branch on the cause.
+pub trait ErrorCode {
+    /// The code of the error.
+    fn code(&self) -> u16;
+
+    /// Whether the error may go away by itself, like a lost connection, so the operation is worth retrying.
+    fn is_transient(&self) -> bool {
+        false
+    }
+}
+
 /// Represents all possible network-related errors.
 #[derive(Debug)]
 pub enum NetError {
@@ -26,6 +52,67 @@ pub enum NetError {
 
     /// Error indicating an invalid CLI application network.
     InvalidAppNetwork,
+
+    /// Error indicating a message frame larger than allowed.
+    MessageTooLarge {
+        /// Size of the message, in bytes.
+        size: u64,
+        /// Maximum allowed size, in bytes.
+        limit: u64,
+    },
+
+    /// Error indicating a peer using a wire format version older than supported.
+    UnsupportedWireVersion(u16),
+
+    /// Error indicating a message unknown to us, from a peer with a newer wire format version.
+    UnknownMessage {
+        /// Wire format version of the peer.
+        version: u16,
+        /// Name of the message.
+        message: String,
+    },
+
+    /// Error indicating a message from another session, or outside of any, replayed into the current one.
+    SessionMismatch,
+
+    /// Error indicating a message of the session with an unexpected sequence number: replayed, dropped or
+    /// reordered.
+    OutOfSequence {
+        /// Sequence number of the message expected next.
+        expected: u64,
+        /// Sequence number of the message received.
+        received: u64,
+    },
+
+    /// Error indicating a message of an authenticated session without a valid MAC: forged, or re-wrapped from
+    /// another envelope.
+    UnauthenticatedMessage,
+}
+
+impl ErrorCode for NetError {
+    fn code(&self) -> u16 {
+        match self {
+            Self::IO(_) => 1000,
+            Self::ReachedEOF => 1001,
+            Self::ConnectionTimedOut => 1002,
+            Self::InvalidNetworkAddress => 1003,
+            Self::Cbor(_) => 1004,
+            Self::InvalidAppNetwork => 1005,
+            Self::MessageTooLarge { .. } => 1006,
+            Self::UnsupportedWireVersion(_) => 1007,
+            Self::UnknownMessage { .. } => 1008,
+            Self::SessionMismatch => 1009,
+            Self::OutOfSequence { .. } => 1010,
+            Self::UnauthenticatedMessage => 1011,
+        }
+    }
+
+    fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            Self::IO(_) | Self::ReachedEOF | Self::ConnectionTimedOut
+        )
+    }
 }
 
 impl std::fmt::Display for NetError {
@@ -51,3 +138,30 @@ impl From<serde_cbor::Error> for NetError {
         Self::Cbor(value)
     }
 }
+
+#[cfg(all(test, feature = "taker"))]
+mod tests {
+    use super::*;
+    use crate::{protocol::error::ProtocolError, wallet::WalletError};
+
+    #[test]
+    fn test_error_codes() {
+        let timeout = NetError::ConnectionTimedOut;
+        assert_eq!(timeout.code(), 1002);
+        assert!(timeout.is_transient());
+
+        // Wrapped errors keep their code.
+        let wrong_message = WalletError::Protocol(ProtocolError::WrongMessage {
+            expected: "RespOffer".to_string(),
+            received: "MakerHello".to_string(),
+        });
+        assert_eq!(wrong_message.code(), 2005);
+        assert!(!wrong_message.is_transient());
+
+        let insufficient = WalletError::InsufficientFund {
+            available: 1000,
+            required: 2000,
+        };
+        assert_eq!(insufficient.code(), 3009);
+    }
+}
@@ -0,0 +1,272 @@
+//! Library level event bus.
+//!
+//! Both the [Maker](crate::maker::Maker) and the [Taker](crate::taker::Taker) publish typed [Event]s at
+//! key points of their lifecycle. Applications (GUIs, monitoring daemons, notifiers) can subscribe to these
+//! events without parsing log lines.
+//!
+//! Events are delivered over [std::sync::mpsc] channels. Every subscriber receives its own copy of each
+//! event. Subscribers that dropped their receiving end are removed at the next publish.
+
+use std::{
+    fmt,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Mutex,
+    },
+};
+
+use bitcoin::{Amount, FeeRate, OutPoint, Txid};
+
+/// Default number of blocks before the fidelity bond timelock expiry, from which [Event::BondExpiring] is published.
+pub const BOND_EXPIRY_WARNING_BLOCKS: u32 = 144 * 7; // ~1 week
+
+/// Events emitted by the Maker and Taker cores.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// A new swap round has started.
+    SwapStarted {
+        /// Unique id of the swap.
+        swap_id: String,
+        /// Amount being swapped.
+        amount: Amount,
+    },
+    /// A swap round completed successfully.
+    SwapCompleted {
+        /// Unique id of the swap.
+        swap_id: String,
+    },
+    /// Contract transactions of an ongoing swap were seen in the mempool or a block.
+    ContractBroadcastDetected {
+        /// Txids of the observed contract transactions.
+        txids: Vec<Txid>,
+    },
+    /// Recovery from an unfinished swap has been initiated.
+    RecoveryInitiated {
+        /// Unique id of the swap, if known.
+        swap_id: Option<String>,
+        /// Reason the recovery was triggered.
+        reason: String,
+    },
+    /// The fidelity bond is getting close to its timelock expiry.
+    BondExpiring {
+        /// Outpoint of the bond.
+        outpoint: OutPoint,
+        /// Blocks remaining until the timelock expires.
+        blocks_remaining: u32,
+    },
+    /// The wallet balance available for swaps fell below the configured threshold.
+    BalanceLow {
+        /// Balance available for swaps.
+        balance: Amount,
+        /// The configured threshold.
+        threshold: Amount,
+    },
+    /// A pre-signed transaction about to be broadcasted pays less than the current fee estimate,
+    /// and might not confirm before the timelock margin.
+    LowFeerate {
+        /// Txid of the transaction.
+        txid: Txid,
+        /// Feerate paid by the transaction.
+        feerate: FeeRate,
+        /// Estimated feerate required to confirm in time.
+        estimated: FeeRate,
+    },
+    /// The Taker's swap round moved to a new phase.
+    SwapPhaseChanged {
+        /// Unique id of the swap.
+        swap_id: String,
+        /// The new phase.
+        phase: SwapPhase,
+    },
+    /// A maker joined the Taker's swap route.
+    HopEstablished {
+        /// Unique id of the swap.
+        swap_id: String,
+        /// Position of the maker in the route, starting at 0.
+        hop: usize,
+        /// Address of the maker.
+        maker: String,
+    },
+    /// The confirmation count of a funding transaction changed.
+    FundingConfirmations {
+        /// Unique id of the swap.
+        swap_id: String,
+        /// Txid of the funding transaction.
+        txid: Txid,
+        /// Current confirmations. 0 while in the mempool.
+        confirmations: u32,
+        /// Confirmations required before the swap proceeds.
+        required: u32,
+    },
+    /// Running total of the fees of the Taker's swap round.
+    FeesAccrued {
+        /// Unique id of the swap.
+        swap_id: String,
+        /// Fees earned by the makers of the funded hops.
+        maker_fees: Amount,
+        /// Miner fees of all the funding transactions so far.
+        miner_fees: Amount,
+    },
+}
+
+/// Phases of the Taker's swap round, in order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwapPhase {
+    /// Funding the contract to the first maker.
+    FirstHopFunding,
+    /// Exchanging the contract signatures of a maker, and waiting for its funding to the next peer.
+    HopSetup {
+        /// Position of the maker in the route, starting at 0.
+        hop: usize,
+        /// Number of makers in the route.
+        maker_count: usize,
+    },
+    /// Exchanging the hash preimage and private keys with all makers.
+    Settlement,
+}
+
+impl fmt::Display for SwapPhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::FirstHopFunding => write!(f, "First hop funding"),
+            Self::HopSetup { hop, maker_count } => {
+                write!(f, "Hop setup {}/{}", hop + 1, maker_count)
+            }
+            Self::Settlement => write!(f, "Settlement"),
+        }
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::SwapStarted { swap_id, amount } => {
+                write!(f, "SwapStarted | id {} | amount {}", swap_id, amount)
+            }
+            Self::SwapCompleted { swap_id } => write!(f, "SwapCompleted | id {}", swap_id),
+            Self::ContractBroadcastDetected { txids } => {
+                write!(f, "ContractBroadcastDetected | txids {:?}", txids)
+            }
+            Self::RecoveryInitiated { swap_id, reason } => {
+                write!(f, "RecoveryInitiated | id {:?} | {}", swap_id, reason)
+            }
+            Self::BondExpiring {
+                outpoint,
+                blocks_remaining,
+            } => write!(
+                f,
+                "BondExpiring | outpoint {} | {} blocks remaining",
+                outpoint, blocks_remaining
+            ),
+            Self::BalanceLow { balance, threshold } => write!(
+                f,
+                "BalanceLow | balance {} | threshold {}",
+                balance, threshold
+            ),
+            Self::LowFeerate {
+                txid,
+                feerate,
+                estimated,
+            } => write!(
+                f,
+                "LowFeerate | txid {} | feerate {} sat/vb | estimated {} sat/vb",
+                txid,
+                feerate.to_sat_per_vb_ceil(),
+                estimated.to_sat_per_vb_ceil()
+            ),
+            Self::SwapPhaseChanged { swap_id, phase } => {
+                write!(f, "SwapPhaseChanged | id {} | {}", swap_id, phase)
+            }
+            Self::HopEstablished {
+                swap_id,
+                hop,
+                maker,
+            } => write!(
+                f,
+                "HopEstablished | id {} | hop {} | maker {}",
+                swap_id, hop, maker
+            ),
+            Self::FundingConfirmations {
+                swap_id,
+                txid,
+                confirmations,
+                required,
+            } => write!(
+                f,
+                "FundingConfirmations | id {} | txid {} | {}/{}",
+                swap_id, txid, confirmations, required
+            ),
+            Self::FeesAccrued {
+                swap_id,
+                maker_fees,
+                miner_fees,
+            } => write!(
+                f,
+                "FeesAccrued | id {} | maker fees {} | miner fees {}",
+                swap_id, maker_fees, miner_fees
+            ),
+        }
+    }
+}
+
+/// A simple fan-out publisher of [Event]s.
+#[derive(Debug, Default)]
+pub struct EventBus {
+    subscribers: Mutex<Vec<Sender<Event>>>,
+}
+
+impl EventBus {
+    /// Register a new subscriber. All events published after this call will be delivered to the returned [Receiver].
+    pub fn subscribe(&self) -> Receiver<Event> {
+        let (sender, receiver) = mpsc::channel();
+        if let Ok(mut subscribers) = self.subscribers.lock() {
+            subscribers.push(sender);
+        }
+        receiver
+    }
+
+    /// Publish an event to all live subscribers.
+    ///
+    /// Publishing never fails. A poisoned lock or a disconnected subscriber is just logged/dropped,
+    /// as events should never interrupt the swap routines.
+    pub(crate) fn publish(&self, event: Event) {
+        log::debug!("Publishing event: {}", event);
+        match self.subscribers.lock() {
+            Ok(mut subscribers) => {
+                subscribers.retain(|subscriber| subscriber.send(event.clone()).is_ok())
+            }
+            Err(e) => log::error!("Event bus lock poisoned: {:?}", e),
+        }
+    }
+
+    /// Number of live subscribers.
+    pub fn subscriber_count(&self) -> usize {
+        self.subscribers.lock().map(|s| s.len()).unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_fanout_and_cleanup() {
+        let bus = EventBus::default();
+        let rx1 = bus.subscribe();
+        let rx2 = bus.subscribe();
+        assert_eq!(bus.subscriber_count(), 2);
+
+        let event = Event::SwapCompleted {
+            swap_id: "abcd".to_string(),
+        };
+        bus.publish(event.clone());
+        assert_eq!(rx1.try_recv().unwrap(), event);
+        assert_eq!(rx2.try_recv().unwrap(), event);
+
+        // Dropped subscribers are removed on next publish.
+        drop(rx1);
+        bus.publish(event.clone());
+        assert_eq!(bus.subscriber_count(), 1);
+        assert_eq!(rx2.try_recv().unwrap(), event);
+    }
+}
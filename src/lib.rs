@@ -3,12 +3,32 @@
 extern crate bitcoin;
 extern crate bitcoind;
 
+// Reachable by the benchmarks of `benches/` only, not part of the public API.
+#[cfg(feature = "bench")]
+#[doc(hidden)]
+pub mod bench;
+#[cfg(feature = "taker")]
+pub mod broadcast;
 pub mod error;
+#[cfg(feature = "taker")]
+pub mod events;
+#[cfg(feature = "maker")]
 pub mod maker;
+#[cfg(feature = "taker")]
 pub mod market;
+#[cfg(feature = "nostr")]
+pub(crate) mod nostr;
+#[cfg(feature = "taker")]
+pub mod notifier;
+#[cfg(feature = "taker")]
 pub mod protocol;
+#[cfg(feature = "taker")]
 pub mod taker;
 #[cfg(feature = "tor")]
 pub mod tor;
+#[cfg(feature = "taker")]
 pub mod utill;
+#[cfg(feature = "taker")]
 pub mod wallet;
+#[cfg(feature = "taker")]
+pub mod watchtower;
@@ -7,28 +7,33 @@
 //! contract transactions and claiming funds after an unsuccessful swap event.
 
 use crate::{
+    broadcast::Broadcaster,
+    events::{Event, EventBus},
     protocol::{
         contract::check_hashvalues_are_equal,
-        messages::{FidelityProof, ReqContractSigsForSender},
+        machine::ExpectedMessage,
+        messages::{FidelityProof, MessageType, Offer, ReqContractSigsForSender, SignedReceipt},
+        session::Session,
         Hash160,
     },
     utill::{
-        get_maker_dir, redeemscript_to_scriptpubkey, ConnectionType, HEART_BEAT_INTERVAL,
-        REQUIRED_CONFIRMS,
+        directory_address_for_network, get_maker_dir, redeemscript_to_scriptpubkey, unix_time,
+        ConnectionType, HEART_BEAT_INTERVAL,
     },
     wallet::{RPCConfig, SwapCoin, WalletSwapCoin},
+    watchtower::{TimelockSweep, Watchtower},
 };
 use bitcoin::{
     ecdsa::Signature,
     secp256k1::{self, Secp256k1},
-    OutPoint, PublicKey, ScriptBuf, Transaction,
+    Address, Amount, OutPoint, PublicKey, ScriptBuf, Transaction,
 };
 use bitcoind::bitcoincore_rpc::RpcApi;
 use std::{
     collections::HashMap,
     path::PathBuf,
     sync::{
-        atomic::{AtomicBool, Ordering::Relaxed},
+        atomic::{AtomicBool, AtomicU64, Ordering::Relaxed},
         Arc, Mutex, RwLock,
     },
     thread::JoinHandle,
@@ -39,14 +44,23 @@ use crate::{
     protocol::{
         contract::{
             check_hashlock_has_pubkey, check_multisig_has_pubkey, check_reedemscript_is_multisig,
-            find_funding_output_index, read_contract_locktime,
+            contract_fee, contract_feerate_range, find_funding_output_index, is_valid_feerate,
+            read_contract_locktime, CONTRACT_FEERATE_CONF_TARGET,
         },
         messages::ProofOfFunding,
     },
-    wallet::{IncomingSwapCoin, OutgoingSwapCoin, Wallet, WalletError},
+    wallet::{
+        broadcasted_txids, ConfirmationTracker, ConfirmationUpdate, ContractConflict,
+        IncomingSwapCoin, OutgoingSwapCoin, Wallet,
+    },
 };
 
-use super::{config::MakerConfig, error::MakerError};
+use super::{
+    config::{MakerConfig, MakerPolicy},
+    error::MakerError,
+    schedule::{next_limit_change, swap_limit_at},
+    traffic::TrafficLedger,
+};
 
 /// Interval for health checks on a stable RPC connection with bitcoind.
 pub const RPC_PING_INTERVAL: Duration = Duration::from_secs(10);
@@ -56,9 +70,6 @@ pub const RPC_PING_INTERVAL: Duration = Duration::from_secs(10);
 // TODO: Make the maker repost their address to DNS once a day in spawned thread.
 // pub const DIRECTORY_SERVERS_REFRESH_INTERVAL_SECS: u64 = Duartion::from_days(1); // Once a day.
 
-/// Maker triggers the recovery mechanism, if Taker is idle for more than 15 mins during a swap.
-pub const IDLE_CONNECTION_TIMEOUT: Duration = Duration::from_secs(60 * 15);
-
 /// The minimum difference in locktime (in blocks) between the incoming and outgoing swaps.
 ///
 /// This value specifies the reaction time, in blocks, available to a Maker
@@ -66,7 +77,7 @@ pub const IDLE_CONNECTION_TIMEOUT: Duration = Duration::from_secs(60 * 15);
 ///
 /// According to [BOLT #2](https://github.com/lightning/bolts/blob/aa5207aeaa32d841353dd2df3ce725a4046d528d/02-peer-protocol.md?plain=1#L1798),
 /// the estimated minimum `cltv_expiry_delta` is 18 blocks.
-/// To enhance safety, the default value is set to 20 blocks.
+/// To enhance safety, the default value is set to 20 blocks. Makers can raise it with `min_contract_reaction_time`.
 pub const MIN_CONTRACT_REACTION_TIME: u16 = 20;
 
 /// # Fee Parameters for Coinswap
@@ -86,6 +97,9 @@ pub const MIN_CONTRACT_REACTION_TIME: u16 = 20;
 /// The total fee for a swap is calculated as:
 /// `total_fee = base_fee + (swap_amount * amount_relative_fee_pct) / 100 + (swap_amount * refund_locktime * time_relative_fee_pct) / 100`
 ///
+/// With `fee_tiers` set, the swaps of at least a tier's minimum amount pay the tier's `amount_relative_fee_pct`
+/// instead, e.g. cheaper for large swaps. The tiers are advertised in the offer.
+///
 /// ### Example (Default Values)
 /// For a swap amount of 100,000 sats and a refund locktime of 20 blocks:
 /// - `base_fee` = 1,000 sats
@@ -134,23 +148,60 @@ pub enum MakerBehavior {
     CloseAtHashPreimage,
     /// Simulates broadcasting the contract immediately after setup.
     BroadcastContractAfterSetup,
+    /// Sends signatures made with a wrong key in place of the contract signatures.
+    SendInvalidSignature,
+    /// Receives the hash preimage but never responds to it, keeping the connection open.
+    StallAtHashPreimage,
+    /// Simulates closure when receiving the given taker message.
+    CloseAtSpecificMessage(MessageType),
+    /// Waits for the given duration before sending each response.
+    DelayResponses(Duration),
+    /// Randomly corrupts, reorders, delays or drops responses, driven by an RNG seeded with the given value.
+    RandomByzantine(u64),
 }
 
-/// Expected messages for the taker in the context of [ConnectionState] structure.
-///
-/// If the received message doesn't match expected message,
-/// a protocol error will be returned.
-#[derive(Debug, Default, PartialEq, Clone)]
-pub(crate) enum ExpectedMessage {
-    #[default]
-    TakerHello,
-    NewlyConnectedTaker,
-    ReqContractSigsForSender,
-    ProofOfFunding,
-    ProofOfFundingORContractSigsForRecvrAndSender,
-    ReqContractSigsForRecvr,
-    HashPreimage,
-    PrivateKeyHandover,
+/// What a [MakerBehavior::RandomByzantine] maker does with a response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ByzantineAction {
+    /// Send the response as is.
+    Pass,
+    /// Send garbage bytes instead of the response.
+    Corrupt,
+    /// Send the previous response in place of this one.
+    Reorder,
+    /// Wait before sending the response.
+    Delay(Duration),
+    /// Close the connection without responding.
+    Drop,
+}
+
+/// Deterministic RNG (SplitMix64) for [MakerBehavior::RandomByzantine], so failing runs can be replayed from their seed.
+#[derive(Debug)]
+pub(crate) struct ByzantineRng(u64);
+
+impl ByzantineRng {
+    pub(crate) fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Half of the responses go through untouched. The rest are evenly spread over misbehaviors.
+    pub(crate) fn next_action(&mut self) -> ByzantineAction {
+        match self.next_u64() % 8 {
+            0..=3 => ByzantineAction::Pass,
+            4 => ByzantineAction::Corrupt,
+            5 => ByzantineAction::Reorder,
+            6 => ByzantineAction::Delay(Duration::from_millis(self.next_u64() % 5_000)),
+            _ => ByzantineAction::Drop,
+        }
+    }
 }
 
 /// Maintains the state of a connection, including the list of swapcoins and the next expected message.
@@ -160,6 +211,18 @@ pub(crate) struct ConnectionState {
     pub(crate) incoming_swapcoins: Vec<IncomingSwapCoin>,
     pub(crate) outgoing_swapcoins: Vec<OutgoingSwapCoin>,
     pub(crate) pending_funding_txes: Vec<Transaction>,
+    /// Amount received from the previous hop.
+    pub(crate) incoming_amount: Amount,
+    /// Amount sent to the next hop.
+    pub(crate) outgoing_amount: Amount,
+    /// Miner fees of the outgoing funding txs.
+    pub(crate) funding_fee: Amount,
+    /// Session of the connection, opened by the hellos. Messages after them are sealed in it.
+    pub(crate) session: Option<Session>,
+    /// Session key of the Taker, sent in its hello. It countersigns the receipt of the swap.
+    pub(crate) taker_session_key: Option<PublicKey>,
+    /// Receipt issued with the private key handover, waiting for the Taker's countersignature.
+    pub(crate) receipt: Option<SignedReceipt>,
 }
 
 pub(crate) struct ThreadPool {
@@ -224,6 +287,8 @@ pub struct Maker {
     pub wallet: RwLock<Wallet>,
     /// A flag to trigger shutdown event
     pub shutdown: AtomicBool,
+    /// A flag to stop taking new swaps, and shut down once the ongoing ones are finished or recovered.
+    pub draining: AtomicBool,
     /// Map of IP address to Connection State + last Connected instant
     pub(crate) ongoing_swap_state: Mutex<HashMap<String, (ConnectionState, Instant)>>,
     /// Highest Value Fidelity Proof
@@ -234,6 +299,24 @@ pub struct Maker {
     pub(crate) data_dir: PathBuf,
     /// Thread pool for managing all spawned threads
     pub(crate) thread_pool: Arc<ThreadPool>,
+    /// Publisher of Maker [Event]s.
+    pub(crate) events: EventBus,
+    /// Watchtower claiming the Maker's contracts, when they hit the chain.
+    pub(crate) watchtower: Arc<Watchtower>,
+    /// Random source of the [MakerBehavior::RandomByzantine] behavior.
+    pub(crate) byzantine_rng: Mutex<ByzantineRng>,
+    /// RPC config of the wallet, reused by the rebalancing Taker.
+    pub(crate) rpc_config: RPCConfig,
+    /// Address advertised to the directory. Empty until the network is set up.
+    pub(crate) advertised_address: RwLock<String>,
+    /// Set by the `rotate-address` RPC, cleared once the onion address is rotated.
+    pub(crate) rotate_address: AtomicBool,
+    /// Unix time our address was last posted to the directory. 0 if never.
+    pub(crate) last_directory_post: AtomicU64,
+    /// Fees, size limits and ban list, reloadable while the Maker runs.
+    pub(crate) policy: RwLock<MakerPolicy>,
+    /// Traffic of the taker connections, per peer and per swap.
+    pub(crate) traffic: Mutex<TrafficLedger>,
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -304,27 +387,87 @@ impl Maker {
             config.connection_type = connection_type;
         }
 
+        config.directory_server_address =
+            directory_address_for_network(&config.directory_server_address, wallet.store.network);
+
         let port = config.network_port;
 
         config.write_to_file(&data_dir.join("config.toml"))?;
+        wallet.apply_config(&config.wallet, config.tor_socks_port())?;
 
         log::info!("Initializing wallet sync");
         wallet.sync()?;
         log::info!("Completed wallet sync");
 
+        let watchtower = Arc::new(Watchtower::new(
+            &rpc_config,
+            &data_dir,
+            wallet.swap_account_key()?,
+        )?);
+        let policy = RwLock::new(MakerPolicy::from(&config));
+
         Ok(Self {
             behavior,
             config,
             wallet: RwLock::new(wallet),
             shutdown: AtomicBool::new(false),
+            draining: AtomicBool::new(false),
             ongoing_swap_state: Mutex::new(HashMap::new()),
             highest_fidelity_proof: RwLock::new(None),
             is_setup_complete: AtomicBool::new(false),
             data_dir,
             thread_pool: Arc::new(ThreadPool::new(port)),
+            events: EventBus::default(),
+            watchtower,
+            byzantine_rng: Mutex::new(ByzantineRng::new(match behavior {
+                MakerBehavior::RandomByzantine(seed) => seed,
+                _ => 0,
+            })),
+            rpc_config,
+            advertised_address: RwLock::new(String::new()),
+            rotate_address: AtomicBool::new(false),
+            last_directory_post: AtomicU64::new(0),
+            policy,
+            traffic: Mutex::new(TrafficLedger::default()),
         })
     }
 
+    /// Re-reads the fees, size limits, liquidity windows and ban list from the config file.
+    ///
+    /// The new policy applies to offers and connections from now on. Ongoing swaps and the onion service are
+    /// left alone. Changes to other fields are only logged, they need a restart.
+    pub(crate) fn reload_config(&self) -> Result<MakerPolicy, MakerError> {
+        let config = MakerConfig::new(Some(&self.data_dir.join("config.toml")))?;
+        let policy = MakerPolicy::from(&config);
+
+        let restart_needed = MakerConfig {
+            base_fee: self.config.base_fee,
+            amount_relative_fee_pct: self.config.amount_relative_fee_pct,
+            time_relative_fee_pct: self.config.time_relative_fee_pct,
+            fee_tiers: self.config.fee_tiers.clone(),
+            liquidity_windows: self.config.liquidity_windows.clone(),
+            min_swap_amount: self.config.min_swap_amount,
+            max_message_size: self.config.max_message_size,
+            max_connection_bytes: self.config.max_connection_bytes,
+            banned_peers: self.config.banned_peers.clone(),
+            ..config
+        } != self.config;
+        if restart_needed {
+            log::warn!(
+                "[{}] Config changes other than fees, size limits, liquidity windows and banned peers need a restart",
+                self.config.network_port
+            );
+        }
+
+        *self.policy.write()? = policy.clone();
+        log::info!(
+            "[{}] Reloaded maker policy: {:?}",
+            self.config.network_port,
+            policy
+        );
+        Ok(policy)
+    }
+
     pub(crate) fn get_data_dir(&self) -> &PathBuf {
         &self.data_dir
     }
@@ -334,6 +477,156 @@ impl Maker {
         &self.wallet
     }
 
+    /// Subscribe to the Maker's [Event] stream.
+    pub fn subscribe_events(&self) -> std::sync::mpsc::Receiver<Event> {
+        self.events.subscribe()
+    }
+
+    /// Range of contract feerates accepted, in sats/vB, advertised in the offer.
+    pub(crate) fn contract_feerate_range(&self) -> Result<(f64, f64), MakerError> {
+        let estimate = self
+            .wallet
+            .read()?
+            .estimate_feerate(CONTRACT_FEERATE_CONF_TARGET)?;
+        Ok(contract_feerate_range(
+            estimate,
+            self.config.max_contract_feerate,
+        ))
+    }
+
+    /// Largest swap taken now, in sats: the wallet's limit, capped by the current liquidity window. 0 outside
+    /// the liquidity windows.
+    pub(crate) fn swap_size_limit(&self) -> Result<u64, MakerError> {
+        let offer_maxsize = self.wallet.read()?.store.offer_maxsize;
+        let window_limit = swap_limit_at(&self.policy.read()?.liquidity_windows, unix_time());
+        Ok(offer_maxsize.min(window_limit))
+    }
+
+    /// Whether we're within our liquidity windows, always without any.
+    pub(crate) fn in_liquidity_window(&self) -> Result<bool, MakerError> {
+        Ok(swap_limit_at(&self.policy.read()?.liquidity_windows, unix_time()) > 0)
+    }
+
+    /// Expiry of the offers and directory registrations made now: after the offer TTL of the config, or at the
+    /// next change of the liquidity windows.
+    pub(crate) fn offer_expiry(&self) -> Result<u64, MakerError> {
+        let now = unix_time();
+        let expiry = now + self.config.offer_ttl_secs;
+        Ok(
+            match next_limit_change(&self.policy.read()?.liquidity_windows, now) {
+                Some(change) => expiry.min(change),
+                None => expiry,
+            },
+        )
+    }
+
+    /// Our current offer, valid until [Maker::offer_expiry].
+    pub(crate) fn offer(&self) -> Result<Offer, MakerError> {
+        let max_size = self.swap_size_limit()?;
+        let expires_at = self.offer_expiry()?;
+        let tweakable_point = self.wallet.read()?.get_tweakable_keypair()?.1;
+        let fidelity = self
+            .highest_fidelity_proof
+            .read()?
+            .clone()
+            .ok_or(MakerError::General("No fidelity proof for the offer"))?;
+        let identity = self.wallet.read()?.identity_binding(&fidelity.bond)?;
+        let policy = self.policy.read()?;
+        let (min_contract_feerate, max_contract_feerate) = self.contract_feerate_range()?;
+        Ok(Offer {
+            base_fee: policy.base_fee,
+            amount_relative_fee_pct: policy.amount_relative_fee_pct,
+            time_relative_fee_pct: policy.time_relative_fee_pct,
+            fee_tiers: policy.fee_tiers.clone(),
+            required_confirms: self.config.required_confirms,
+            minimum_locktime: self.config.min_contract_reaction_time,
+            max_size,
+            min_size: policy.min_swap_amount,
+            tweakable_point,
+            fidelity,
+            max_refund_locktime: self.config.max_refund_locktime,
+            min_contract_feerate,
+            max_contract_feerate,
+            expires_at,
+            identity: Some(identity),
+        })
+    }
+
+    /// Checks the contract feerate proposed by the taker is within the accepted range.
+    pub(crate) fn check_contract_feerate(&self, feerate: f64) -> Result<(), MakerError> {
+        let (min, max) = self.contract_feerate_range()?;
+        if !is_valid_feerate(feerate) || feerate < min || feerate > max {
+            log::warn!(
+                "[{}] Contract feerate {} sat/vb outside the accepted range {}..={} sat/vb",
+                self.config.network_port,
+                feerate,
+                min,
+                max
+            );
+            return Err(MakerError::General(
+                "Contract feerate outside the accepted range",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Checks the refund locktime of an incoming contract is within the advertised limit.
+    pub(crate) fn check_refund_locktime(&self, locktime: u16) -> Result<(), MakerError> {
+        let max = self.config.max_refund_locktime;
+        if max != 0 && locktime > max {
+            log::warn!(
+                "[{}] Refund locktime {} above the accepted maximum {}",
+                self.config.network_port,
+                locktime,
+                max
+            );
+            return Err(MakerError::General("Refund locktime too long"));
+        }
+        Ok(())
+    }
+
+    /// Broadcaster of the recovery txs. Over Tor, it falls back to public endpoints if the node can't relay them.
+    pub(crate) fn broadcaster(&self) -> Result<Broadcaster, MakerError> {
+        Ok(match self.config.connection_type {
+            #[cfg(feature = "tor")]
+            ConnectionType::TOR => {
+                Broadcaster::new(self.wallet.read()?.store.network, self.config.socks_port)
+            }
+            ConnectionType::CLEARNET => Broadcaster::local(),
+        })
+    }
+
+    /// Reserves a fresh wallet address to claim a contract to.
+    pub(crate) fn next_claim_address(&self) -> Result<Address, MakerError> {
+        let mut wallet = self.wallet.write()?;
+        let script_type = wallet.script_type();
+        Ok(wallet.reserve_internal_address(script_type)?)
+    }
+
+    /// Schedule the timelock refund of an outgoing contract in the watchtower, to a fresh wallet address.
+    pub(crate) fn schedule_timelock_sweep(
+        &self,
+        outgoing: &OutgoingSwapCoin,
+    ) -> Result<(), MakerError> {
+        let key_path = match self
+            .wallet
+            .read()?
+            .contract_key_subpath(&outgoing.get_timelock_pubkey()?)?
+        {
+            Some(key_path) => key_path,
+            None => {
+                log::warn!(
+                    "[{}] Timelock key of contract {} not derived from the seed. Not scheduling its sweep",
+                    self.config.network_port,
+                    outgoing.contract_tx.compute_txid()
+                );
+                return Ok(());
+            }
+        };
+        let sweep = TimelockSweep::new(outgoing, key_path, &self.next_claim_address()?)?;
+        Ok(self.watchtower.schedule_timelock_sweep(sweep)?)
+    }
+
     /// Checks consistency of the [ProofOfFunding] message and return the Hashvalue
     /// used in hashlock transaction.
     pub(crate) fn verify_proof_of_funding(
@@ -348,7 +641,11 @@ impl Maker {
             // check that the new locktime is sufficently short enough compared to the
             // locktime in the provided funding tx
             let locktime = read_contract_locktime(&funding_info.contract_redeemscript)?;
-            if locktime - message.refund_locktime < MIN_CONTRACT_REACTION_TIME {
+            self.check_refund_locktime(locktime)?;
+            if locktime
+                .checked_sub(message.refund_locktime)
+                .is_none_or(|window| window < self.config.min_contract_reaction_time)
+            {
                 return Err(MakerError::General(
                     "Next hop locktime too close to current hop locktime",
                 ));
@@ -357,24 +654,19 @@ impl Maker {
             let funding_output_index = find_funding_output_index(funding_info)?;
 
             //check the funding_tx is confirmed to required depth
-            if let Some(txout) = self
-                .wallet
-                .read()?
-                .rpc
-                .get_tx_out(
-                    &funding_info.funding_tx.compute_txid(),
-                    funding_output_index,
-                    None,
-                )
-                .map_err(WalletError::Rpc)?
-            {
-                if txout.confirmations < REQUIRED_CONFIRMS {
-                    return Err(MakerError::General(
-                        "funding tx not confirmed to required depth",
-                    ));
+            let funding_outpoint =
+                OutPoint::new(funding_info.funding_tx.compute_txid(), funding_output_index);
+            let confirmations = {
+                let wallet = self.wallet.read()?;
+                if !wallet.chain.is_unspent(&funding_outpoint, true)? {
+                    return Err(MakerError::General("funding tx output doesnt exist"));
                 }
-            } else {
-                return Err(MakerError::General("funding tx output doesnt exist"));
+                wallet.chain.tx_confirmations(&funding_outpoint.txid)?
+            };
+            if confirmations.unwrap_or(0) < self.config.required_confirms {
+                return Err(MakerError::General(
+                    "funding tx not confirmed to required depth",
+                ));
             }
 
             check_reedemscript_is_multisig(&funding_info.multisig_redeemscript)?;
@@ -418,6 +710,10 @@ impl Maker {
         &self,
         message: &ReqContractSigsForSender,
     ) -> Result<Vec<Signature>, MakerError> {
+        if let Some(feerate) = message.contract_feerate_sat_vb {
+            self.check_contract_feerate(feerate)?;
+        }
+        self.check_refund_locktime(message.locktime)?;
         let mut sigs = Vec::<Signature>::new();
         for txinfo in &message.txs_info {
             if txinfo.senders_contract_tx.input.len() != 1
@@ -455,13 +751,24 @@ impl Maker {
                 inner: secp256k1::PublicKey::from_secret_key(&secp, &hashlock_privkey),
             };
 
+            if let Some(feerate) = message.contract_feerate_sat_vb {
+                let fee = txinfo
+                    .funding_input_value
+                    .checked_sub(txinfo.senders_contract_tx.output[0].value);
+                if fee != Some(contract_fee(feerate)) {
+                    return Err(MakerError::General(
+                        "contract transaction doesn't pay the negotiated feerate",
+                    ));
+                }
+            }
+
             crate::protocol::contract::is_contract_out_valid(
                 &txinfo.senders_contract_tx.output[0],
                 &hashlock_pubkey,
                 &txinfo.timelock_pubkey,
                 &message.hashvalue,
                 &message.locktime,
-                &MIN_CONTRACT_REACTION_TIME,
+                &self.config.min_contract_reaction_time,
             )?;
 
             self.wallet.write()?.cache_prevout_to_contract(
@@ -488,7 +795,7 @@ impl Maker {
 ///
 /// If any one of the is ever observed, run the recovery routine.
 pub(crate) fn check_for_broadcasted_contracts(maker: Arc<Maker>) -> Result<(), MakerError> {
-    let mut failed_swap_ip = Vec::new();
+    let mut failed_swap_ids = Vec::new();
     loop {
         if maker.shutdown.load(Relaxed) {
             break;
@@ -496,7 +803,7 @@ pub(crate) fn check_for_broadcasted_contracts(maker: Arc<Maker>) -> Result<(), M
         // An extra scope to release all locks when done.
         {
             let mut lock_onstate = maker.ongoing_swap_state.lock()?;
-            for (ip, (connection_state, _)) in lock_onstate.iter_mut() {
+            for (swap_id, (connection_state, _)) in lock_onstate.iter_mut() {
                 let txids_to_watch = connection_state
                     .incoming_swapcoins
                     .iter()
@@ -509,89 +816,100 @@ pub(crate) fn check_for_broadcasted_contracts(maker: Arc<Maker>) -> Result<(), M
                     )
                     .collect::<Vec<_>>();
 
-                // No need to check for other contracts in the connection state, if any one of them
-                // is ever observed in the mempool/block, run recovery routine.
-                for txid in txids_to_watch {
-                    if maker
-                        .wallet
-                        .read()?
-                        .rpc
-                        .get_raw_transaction_info(&txid, None)
-                        .is_ok()
-                    {
-                        let mut outgoings = Vec::new();
-                        let mut incomings = Vec::new();
-                        // Something is broadcasted. Report, Recover and Abort.
-                        log::warn!(
-                            "[{}] Contract txs broadcasted!! txid: {} Recovering from ongoing swaps.",
-                            maker.config.network_port,
-                            txid
-                        );
-                        // Extract Incoming and Outgoing contracts, and timelock spends of the contract transactions.
-                        // fully signed.
-                        for (og_sc, ic_sc) in connection_state
-                            .outgoing_swapcoins
-                            .iter()
-                            .zip(connection_state.incoming_swapcoins.iter())
-                        {
-                            let contract_timelock = og_sc.get_timelock()?;
-                            let next_internal_address =
-                                &maker.wallet.read()?.get_next_internal_addresses(1)?[0];
-                            let time_lock_spend =
-                                og_sc.create_timelock_spend(next_internal_address)?;
-
-                            // Sometimes we might not have other's contact signatures.
-                            // This means the protocol have been stopped abruptly.
-                            // This needs more careful consideration as this should not happen
-                            // after funding transactions have been broadcasted for outgoing contracts.
-                            // For incomings, its less lethal as thats mostly the other party's burden.
-                            if let Ok(tx) = og_sc.get_fully_signed_contract_tx() {
-                                outgoings.push((
-                                    (og_sc.get_multisig_redeemscript(), tx),
-                                    (contract_timelock, time_lock_spend),
-                                ));
-                            } else {
-                                log::warn!(
-                                    "[{}] Outgoing contact signature not known. Not Broadcasting",
-                                    maker.config.network_port
-                                );
-                            }
-                            if let Ok(tx) = ic_sc.get_fully_signed_contract_tx() {
-                                incomings.push((ic_sc.get_multisig_redeemscript(), tx));
-                            } else {
-                                log::warn!(
-                                    "[{}] Incoming contact signature not known. Not Broadcasting",
-                                    maker.config.network_port
-                                );
-                            }
+                // If any one of the contracts is ever observed in the mempool or a block, run the recovery
+                // routine. The mempool is polled every heartbeat, so a premature broadcast is caught before its
+                // first confirmation, and our own contracts are broadcast right away.
+                let broadcasted =
+                    match broadcasted_txids(&*maker.wallet.read()?.chain, &txids_to_watch) {
+                        Ok(broadcasted) => broadcasted,
+                        Err(e) => {
+                            log::warn!(
+                                "[{}] Failed checking for broadcasted contracts: {:?}",
+                                maker.config.network_port,
+                                e
+                            );
+                            continue;
                         }
-                        failed_swap_ip.push(ip.clone());
+                    };
+                if !broadcasted.is_empty() {
+                    let mut outgoings = Vec::new();
+                    let mut incomings = Vec::new();
+                    // Something is broadcasted. Report, Recover and Abort.
+                    log::warn!(
+                        "[{}] Contract txs broadcasted!! txids: {:?} Recovering from ongoing swaps.",
+                        maker.config.network_port,
+                        broadcasted
+                    );
+                    maker
+                        .events
+                        .publish(Event::ContractBroadcastDetected { txids: broadcasted });
+                    // Extract Incoming and Outgoing contracts, and timelock spends of the contract transactions.
+                    // fully signed.
+                    for (og_sc, ic_sc) in connection_state
+                        .outgoing_swapcoins
+                        .iter()
+                        .zip(connection_state.incoming_swapcoins.iter())
+                    {
+                        let contract_timelock = og_sc.get_timelock()?;
+                        let next_internal_address = &maker.next_claim_address()?;
+                        let time_lock_spend = og_sc.create_timelock_spend(next_internal_address)?;
 
-                        // Spawn a separate thread to wait for contract maturity and broadcasting timelocked.
-                        let maker_clone = maker.clone();
-                        log::info!(
-                            "[{}] Spawning recovery thread after seeing contracts in mempool",
-                            maker.config.network_port
-                        );
-                        let handle = std::thread::Builder::new()
-                            .name("Swap recovery thread".to_string())
-                            .spawn(move || {
-                                if let Err(e) = recover_from_swap(maker_clone, outgoings, incomings)
-                                {
-                                    log::error!("Failed to recover from swap due to: {:?}", e);
-                                }
-                            })?;
-                        maker.thread_pool.add_thread(handle);
-                        // Clear the state value here
-                        *connection_state = ConnectionState::default();
-                        break;
+                        // Sometimes we might not have other's contact signatures.
+                        // This means the protocol have been stopped abruptly.
+                        // This needs more careful consideration as this should not happen
+                        // after funding transactions have been broadcasted for outgoing contracts.
+                        // For incomings, its less lethal as thats mostly the other party's burden.
+                        if let Ok(tx) = og_sc.get_fully_signed_contract_tx() {
+                            outgoings.push((
+                                (og_sc.get_multisig_redeemscript(), tx),
+                                (contract_timelock, time_lock_spend),
+                            ));
+                        } else {
+                            log::warn!(
+                                "[{}] Outgoing contact signature not known. Not Broadcasting",
+                                maker.config.network_port
+                            );
+                        }
+                        if let Ok(tx) = ic_sc.get_fully_signed_contract_tx() {
+                            incomings.push((ic_sc.get_multisig_redeemscript(), tx));
+                        } else {
+                            log::warn!(
+                                "[{}] Incoming contact signature not known. Not Broadcasting",
+                                maker.config.network_port
+                            );
+                        }
                     }
+                    failed_swap_ids.push(swap_id.clone());
+
+                    // Spawn a separate thread to wait for contract maturity and broadcasting timelocked.
+                    let maker_clone = maker.clone();
+                    log::info!(
+                        "[{}] Spawning recovery thread after seeing contracts in mempool",
+                        maker.config.network_port
+                    );
+                    maker.events.publish(Event::RecoveryInitiated {
+                        swap_id: Some(swap_id.clone()),
+                        reason: "Contract broadcast detected".to_string(),
+                    });
+                    // The recovery logs in the span of the swap.
+                    let span = tracing::info_span!("swap", id = %swap_id);
+                    let handle = std::thread::Builder::new()
+                        .name("Swap recovery thread".to_string())
+                        .spawn(move || {
+                            let _entered = span.enter();
+                            if let Err(e) = recover_from_swap(maker_clone, outgoings, incomings) {
+                                log::error!("Failed to recover from swap due to: {:?}", e);
+                            }
+                        })?;
+                    maker.thread_pool.add_thread(handle);
+                    // Clear the state value here
+                    *connection_state = ConnectionState::default();
                 }
             }
 
             // Clear the state entry here
-            for ip in failed_swap_ip.iter() {
-                lock_onstate.remove(ip);
+            for swap_id in failed_swap_ids.iter() {
+                lock_onstate.remove(swap_id);
             }
         } // All locks are cleared here.
 
@@ -612,7 +930,7 @@ pub(crate) fn restore_broadcasted_contracts_on_reboot(maker: Arc<Maker>) -> Resu
     // fully signed.
     for og_sc in out.iter() {
         let contract_timelock = og_sc.get_timelock()?;
-        let next_internal_address = &maker.wallet.read()?.get_next_internal_addresses(1)?[0];
+        let next_internal_address = &maker.next_claim_address()?;
         let time_lock_spend = og_sc.create_timelock_spend(next_internal_address)?;
 
         let tx = og_sc.get_fully_signed_contract_tx()?;
@@ -627,6 +945,11 @@ pub(crate) fn restore_broadcasted_contracts_on_reboot(maker: Arc<Maker>) -> Resu
         incomings.push((ic_sc.get_multisig_redeemscript(), tx));
     }
 
+    maker.events.publish(Event::RecoveryInitiated {
+        swap_id: None,
+        reason: "Unfinished swapcoins found on reboot".to_string(),
+    });
+
     // Spawn a separate thread to wait for contract maturity and broadcasting timelocked.
     let maker_clone = maker.clone();
     let handle = std::thread::Builder::new()
@@ -643,16 +966,10 @@ pub(crate) fn restore_broadcasted_contracts_on_reboot(maker: Arc<Maker>) -> Resu
 
 /// Check that if any Taker connection went idle.
 ///
-/// If a connection remains idle for more than idle timeout time, thats a potential DOS attack.
+/// If a connection remains idle for more than the timeout of its protocol phase, thats a potential DOS attack.
 /// Broadcast the contract transactions and claim funds via timelock.
 pub(crate) fn check_for_idle_states(maker: Arc<Maker>) -> Result<(), MakerError> {
-    let mut bad_ip = Vec::new();
-
-    let conn_timeout = if cfg!(feature = "integration-test") {
-        Duration::from_secs(60)
-    } else {
-        IDLE_CONNECTION_TIMEOUT
-    };
+    let mut bad_swap_ids = Vec::new();
 
     loop {
         if maker.shutdown.load(Relaxed) {
@@ -663,18 +980,20 @@ pub(crate) fn check_for_idle_states(maker: Arc<Maker>) -> Result<(), MakerError>
         // Extra scope to release all locks when done.
         {
             let mut lock_on_state = maker.ongoing_swap_state.lock()?;
-            for (ip, (state, last_connected_time)) in lock_on_state.iter_mut() {
+            for (swap_id, (state, last_connected_time)) in lock_on_state.iter_mut() {
                 let mut outgoings = Vec::new();
                 let mut incomings = Vec::new();
 
                 let no_response_since =
                     current_time.saturating_duration_since(*last_connected_time);
+                let phase = state.allowed_message.phase();
 
-                if no_response_since > conn_timeout {
+                if no_response_since > maker.config.phase_timeout(phase) {
                     log::error!(
-                        "[{}] Potential Dropped Connection from taker. No response since : {} secs. Recovering from swap",
+                        "[{}] Potential Dropped Connection from taker. No response since : {} secs in {}. Recovering from swap",
                         maker.config.network_port,
-                        no_response_since.as_secs()
+                        no_response_since.as_secs(),
+                        phase
                     );
 
                     // Extract Incoming and Outgoing contracts, and timelock spends of the contract transactions.
@@ -686,8 +1005,7 @@ pub(crate) fn check_for_idle_states(maker: Arc<Maker>) -> Result<(), MakerError>
                     {
                         let contract_timelock = og_sc.get_timelock()?;
                         let contract = og_sc.get_fully_signed_contract_tx()?;
-                        let next_internal_address =
-                            &maker.wallet.read()?.get_next_internal_addresses(1)?[0];
+                        let next_internal_address = &maker.next_claim_address()?;
                         let time_lock_spend = og_sc.create_timelock_spend(next_internal_address)?;
                         outgoings.push((
                             (og_sc.get_multisig_redeemscript(), contract),
@@ -696,16 +1014,23 @@ pub(crate) fn check_for_idle_states(maker: Arc<Maker>) -> Result<(), MakerError>
                         let incoming_contract = ic_sc.get_fully_signed_contract_tx()?;
                         incomings.push((ic_sc.get_multisig_redeemscript(), incoming_contract));
                     }
-                    bad_ip.push(ip.clone());
+                    bad_swap_ids.push(swap_id.clone());
                     // Spawn a separate thread to wait for contract maturity and broadcasting timelocked.
                     let maker_clone = maker.clone();
                     log::info!(
                         "[{}] Spawning recovery thread after Taker dropped",
                         maker.config.network_port
                     );
+                    maker.events.publish(Event::RecoveryInitiated {
+                        swap_id: Some(swap_id.clone()),
+                        reason: format!("Taker went idle in {}", phase),
+                    });
+                    // The recovery logs in the span of the swap.
+                    let span = tracing::info_span!("swap", id = %swap_id);
                     let handle = std::thread::Builder::new()
                         .name("Swap Recovery Thread".to_string())
                         .spawn(move || {
+                            let _entered = span.enter();
                             if let Err(e) = recover_from_swap(maker_clone, outgoings, incomings) {
                                 log::error!("Failed to recover from swap due to: {:?}", e);
                             }
@@ -718,8 +1043,8 @@ pub(crate) fn check_for_idle_states(maker: Arc<Maker>) -> Result<(), MakerError>
             }
 
             // Clear the state entry here
-            for ip in bad_ip.iter() {
-                lock_on_state.remove(ip);
+            for swap_id in bad_swap_ids.iter() {
+                lock_on_state.remove(swap_id);
             }
         } // All locks are cleared here
 
@@ -743,26 +1068,44 @@ pub(crate) fn recover_from_swap(
         if maker
             .wallet
             .read()?
-            .rpc
-            .get_raw_transaction_info(&tx.compute_txid(), None)
-            .is_ok()
+            .chain
+            .tx_confirmations(&tx.compute_txid())?
+            .is_some()
         {
             log::info!(
                 "[{}] Incoming Contract Already Broadcasted",
                 maker.config.network_port
             );
-        } else if let Err(e) = maker.wallet.read()?.send_tx(&tx) {
-            log::info!(
-                "Can't send incoming contract: {} | {:?}",
-                tx.compute_txid(),
-                e
-            );
         } else {
-            log::info!(
-                "[{}] Broadcasted Incoming Contract : {}",
-                maker.config.network_port,
-                tx.compute_txid()
+            maker.wallet.read()?.check_broadcast_feerate(
+                &tx,
+                maker.config.min_contract_reaction_time,
+                &maker.events,
             );
+            if let Err(e) = maker
+                .broadcaster()?
+                .broadcast(&*maker.wallet.read()?.chain, &tx)
+            {
+                // The swapcoin goes away either way, but a spent funding is no failure.
+                match maker.wallet.read()?.contract_conflict(&tx)? {
+                    Some(ContractConflict::FundingSpent) => log::info!(
+                        "[{}] Funding of incoming contract {} already spent",
+                        maker.config.network_port,
+                        tx.compute_txid()
+                    ),
+                    _ => log::info!(
+                        "Can't send incoming contract: {} | {:?}",
+                        tx.compute_txid(),
+                        e
+                    ),
+                }
+            } else {
+                log::info!(
+                    "[{}] Broadcasted Incoming Contract : {}",
+                    maker.config.network_port,
+                    tx.compute_txid()
+                );
+            }
         }
 
         let removed_incoming = maker
@@ -778,22 +1121,29 @@ pub(crate) fn recover_from_swap(
     }
 
     //broadcast all the outgoing contracts
-    for ((og_rs, tx), _) in outgoings.iter() {
+    for ((_, tx), _) in outgoings.iter() {
         let check_tx_result = maker
             .wallet
             .read()?
-            .rpc
-            .get_raw_transaction_info(&tx.compute_txid(), None);
+            .chain
+            .tx_confirmations(&tx.compute_txid())?;
 
         match check_tx_result {
-            Ok(_) => {
+            Some(_) => {
                 log::info!(
                     "[{}] Outgoing Contract already broadcasted",
                     maker.config.network_port
                 );
             }
-            Err(_) => {
-                let send_tx_result = maker.wallet.read()?.send_tx(tx);
+            None => {
+                maker.wallet.read()?.check_broadcast_feerate(
+                    tx,
+                    maker.config.min_contract_reaction_time,
+                    &maker.events,
+                );
+                let send_tx_result = maker
+                    .broadcaster()?
+                    .broadcast(&*maker.wallet.read()?.chain, tx);
                 match send_tx_result {
                     Ok(_) => {
                         log::info!(
@@ -803,18 +1153,18 @@ pub(crate) fn recover_from_swap(
                         );
                     }
                     Err(e) => {
-                        log::info!(
-                            "Can't send ougoing contract: {} | {:?}",
-                            tx.compute_txid(),
-                            e
-                        );
-                        if format!("{:?}", e).contains("bad-txns-inputs-missingorspent") {
-                            // This means the funding utxo doesn't exist anymore. Just remove this coin.
-                            maker
-                                .get_wallet()
-                                .write()?
-                                .remove_outgoing_swapcoin(og_rs)?;
-                            log::info!("Removed outgoing swapcoin: {}", tx.compute_txid());
+                        // Swapcoins with a spent funding are settled in the timelock loop below.
+                        match maker.wallet.read()?.contract_conflict(tx)? {
+                            Some(ContractConflict::FundingSpent) => log::info!(
+                                "[{}] Funding of outgoing contract {} already spent",
+                                maker.config.network_port,
+                                tx.compute_txid()
+                            ),
+                            _ => log::info!(
+                                "Can't send ougoing contract: {} | {:?}",
+                                tx.compute_txid(),
+                                e
+                            ),
                         }
                     }
                 }
@@ -829,6 +1179,7 @@ pub(crate) fn recover_from_swap(
 
     // Check for contract confirmations and broadcast timelocked transaction
     let mut timelock_boardcasted = Vec::new();
+    let mut tracker = ConfirmationTracker::default();
     let trigger_count = if cfg!(feature = "integration-test") {
         10 / HEART_BEAT_INTERVAL.as_secs() // triggers every 10 secs for tests
     } else {
@@ -846,12 +1197,25 @@ pub(crate) fn recover_from_swap(
                 }
                 // Check if the contract tx has reached required maturity
                 // Failure here means the transaction hasn't been broadcasted yet. So do nothing and try again.
-                let tx_from_chain = if let Ok(result) = maker
+                let result = maker
                     .wallet
                     .read()?
                     .rpc
                     .get_raw_transaction_info(&contract.compute_txid(), None)
-                {
+                    .ok();
+                // The maturity counts from the block the contract is in now.
+                if let ConfirmationUpdate::Reorged { blockhash } = tracker.update(
+                    contract.compute_txid(),
+                    result.as_ref().and_then(|result| result.blockhash),
+                ) {
+                    log::warn!(
+                        "[{}] Contract Txid : {} reorged out of block {}. Waiting for its timelock maturity again.",
+                        maker.config.network_port,
+                        contract.compute_txid(),
+                        blockhash
+                    );
+                }
+                let tx_from_chain = if let Some(result) = result {
                     log::info!(
                         "[{}] Contract Txid : {} reached confirmation : {:?}, Required Confirmation : {}",
                         maker.config.network_port,
@@ -861,12 +1225,28 @@ pub(crate) fn recover_from_swap(
                     );
                     result
                 } else {
+                    // The contract can't confirm anymore if its funding went elsewhere, e.g. to the cooperative
+                    // spend of the swap. Nothing is left to claim, so stop waiting for it.
+                    if maker.wallet.read()?.contract_conflict(contract)?
+                        == Some(ContractConflict::FundingSpent)
+                    {
+                        maker
+                            .wallet
+                            .write()?
+                            .remove_outgoing_swapcoin(outgoing_reedemscript)?;
+                        timelock_boardcasted.push(timelocked_tx);
+                        log::info!(
+                            "[{}] Funding of Contract Txid : {} already spent. Removed Outgoing Swapcoin from Wallet",
+                            maker.config.network_port,
+                            contract.compute_txid()
+                        );
+                    }
                     continue;
                 };
 
                 if let Some(confirmation) = tx_from_chain.confirmations {
                     // Now the transaction is confirmed in a block, check for required maturity
-                    if confirmation > (*timelock as u32) {
+                    if confirmation >= (*timelock as u32) {
                         log::info!(
                             "[{}] Timelock maturity of {} blocks reached for Contract Txid : {}",
                             maker.config.network_port,
@@ -878,12 +1258,41 @@ pub(crate) fn recover_from_swap(
                             maker.config.network_port,
                             timelocked_tx.compute_txid()
                         );
-                        maker
-                            .wallet
-                            .read()?
-                            .rpc
-                            .send_raw_transaction(timelocked_tx)
-                            .map_err(WalletError::Rpc)?;
+                        let low_feerate = maker.wallet.read()?.check_broadcast_feerate(
+                            timelocked_tx,
+                            maker.config.min_contract_reaction_time,
+                            &maker.events,
+                        );
+                        let send_result = maker
+                            .broadcaster()?
+                            .broadcast(&*maker.wallet.read()?.chain, timelocked_tx);
+                        if let Err(e) = send_result {
+                            // The watchtower might have already claimed this contract.
+                            let contract_spent = !maker
+                                .wallet
+                                .read()?
+                                .chain
+                                .is_unspent(&OutPoint::new(contract.compute_txid(), 0), true)?;
+                            if !contract_spent {
+                                return Err(e.into());
+                            }
+                            log::info!(
+                                "[{}] Contract {} already claimed",
+                                maker.config.network_port,
+                                contract.compute_txid()
+                            );
+                        } else if let Some(feerate) = low_feerate {
+                            let outpoint = OutPoint::new(timelocked_tx.compute_txid(), 0);
+                            if let Err(e) =
+                                maker.wallet.write()?.bump_fee_with_cpfp(outpoint, feerate)
+                            {
+                                log::warn!(
+                                    "[{}] CPFP of timelocked tx failed: {:?}",
+                                    maker.config.network_port,
+                                    e
+                                );
+                            }
+                        }
                         timelock_boardcasted.push(timelocked_tx);
 
                         let outgoing_removed = maker
@@ -1,13 +1,27 @@
 //! Maker Configuration. Controlling various behaviors.
 
-use crate::utill::parse_toml;
-use std::{io, path::Path};
+use std::{io, path::Path, time::Duration};
 
 use std::io::Write;
 
-use crate::utill::{get_maker_dir, parse_field, ConnectionType};
+use crate::utill::{
+    get_maker_dir, ConfigMap, ConnectionType, DEFAULT_MAX_MESSAGE_SIZE, REQUIRED_CONFIRMS,
+    TESTNET4_DIRECTORY_ADDRESS,
+};
 
-use super::api::MIN_SWAP_AMOUNT;
+use crate::{
+    events::BOND_EXPIRY_WARNING_BLOCKS,
+    protocol::{messages::FeeTier, ProtocolPhase},
+    wallet::WalletConfig,
+};
+
+use super::{
+    api::{
+        AMOUNT_RELATIVE_FEE_PCT, BASE_FEE, MIN_CONTRACT_REACTION_TIME, MIN_SWAP_AMOUNT,
+        TIME_RELATIVE_FEE_PCT,
+    },
+    schedule::LiquidityWindow,
+};
 
 /// Maker Configuration, controlling various maker behavior.
 #[derive(Debug, Clone, PartialEq)]
@@ -28,6 +42,67 @@ pub struct MakerConfig {
     pub fidelity_timelock: u32,
     /// Connection type
     pub connection_type: ConnectionType,
+    /// Minimum confirmations of incoming funding txs, advertised in the offer.
+    pub required_confirms: u32,
+    /// Payjoin (BIP78) listening port, for receiving deposits. Disabled if 0.
+    pub payjoin_port: u16,
+    /// Health and readiness HTTP port, for container orchestration and uptime monitors. Disabled if 0.
+    pub health_port: u16,
+    /// Port the maker is reached at over clearnet, when it differs from `network_port`, e.g. behind a port
+    /// forward or a proxy. The network port if 0.
+    pub advertised_port: u16,
+    /// Rebalance the swap coins once their balance reaches these many sats. Disabled if 0.
+    pub rebalance_swap_balance: u64,
+    /// Rebalance the swap coins once there are these many of them. Disabled if 0.
+    pub rebalance_max_swapcoins: u32,
+    /// Maximum total fee of a rebalancing swap, in sats.
+    pub rebalance_fee_budget: u64,
+    /// Minimum time between two rebalancing swaps, in seconds.
+    pub rebalance_interval: u64,
+    /// Maximum size of a message received from a taker, in bytes.
+    pub max_message_size: u32,
+    /// Maximum bytes received over a single taker connection.
+    pub max_connection_bytes: u64,
+    /// Base fee of a swap, in sats.
+    pub base_fee: u64,
+    /// Fee on the swap amount, in percent.
+    pub amount_relative_fee_pct: f64,
+    /// Fee on the swap amount per block of refund locktime, in percent.
+    pub time_relative_fee_pct: f64,
+    /// Fees on the amount of the larger swaps, replacing `amount_relative_fee_pct` from their minimum amount.
+    pub fee_tiers: Vec<FeeTier>,
+    /// UTC time windows swaps are taken in, see [crate::maker::LiquidityWindow]. Always open if empty.
+    pub liquidity_windows: Vec<LiquidityWindow>,
+    /// IP addresses of peers whose connections are refused.
+    pub banned_peers: Vec<String>,
+    /// Settings of the wallet.
+    pub wallet: WalletConfig,
+    /// Highest feerate of the contract txs accepted, in sats/vB.
+    pub max_contract_feerate: f64,
+    /// Fewest blocks between the refund locktimes of the incoming and outgoing contracts, advertised in the offer.
+    pub min_contract_reaction_time: u16,
+    /// Highest refund locktime of the incoming contracts, in blocks, advertised in the offer. 0 for no limit.
+    pub max_refund_locktime: u16,
+    /// URLs of the webhooks notified of operator events, see [crate::notifier].
+    pub notify_webhooks: Vec<String>,
+    /// Script run on operator events, see [crate::notifier]. Disabled if empty.
+    pub notify_script: String,
+    /// Notify of the fidelity bond expiry these many blocks before it.
+    pub bond_expiry_warning_blocks: u32,
+    /// Notify when the balance available for swaps falls below these many sats. Disabled if 0.
+    pub low_balance_threshold: u64,
+    /// `ws://` or `wss://` Nostr relays our offer is published to, with the `nostr` feature. Disabled if empty.
+    pub nostr_relays: Vec<String>,
+    /// Seconds our offers and directory registration stay valid. The registration is refreshed at half of it.
+    pub offer_ttl_secs: u64,
+    /// Seconds a taker connection outside of a swap may stay idle, e.g. before fetching our offer. Dropped after.
+    pub idle_connection_timeout_secs: u64,
+    /// Seconds a swapping taker may stay silent while exchanging contract signatures. Recovers the swap after.
+    pub contract_sigs_timeout_secs: u64,
+    /// Seconds a swapping taker may stay silent while waiting for funding confirmations. Recovers the swap after.
+    pub funding_confirmation_timeout_secs: u64,
+    /// Seconds a swapping taker may stay silent before revealing the preimage and private keys. Recovers the swap after.
+    pub preimage_reveal_timeout_secs: u64,
 }
 
 impl Default for MakerConfig {
@@ -37,8 +112,7 @@ impl Default for MakerConfig {
             rpc_port: 6103,
             min_swap_amount: MIN_SWAP_AMOUNT,
             socks_port: 19050,
-            directory_server_address:
-                "bhbzkndgad52ojm75w4goii7xsi6ou73fzyvorxas7swg2snlto4c4ad.onion:8080".to_string(),
+            directory_server_address: TESTNET4_DIRECTORY_ADDRESS.to_string(),
             #[cfg(feature = "integration-test")]
             fidelity_amount: 5_000_000, // 0.05 BTC for tests
             #[cfg(feature = "integration-test")]
@@ -57,11 +131,117 @@ impl Default for MakerConfig {
                     ConnectionType::CLEARNET
                 }
             },
+            required_confirms: REQUIRED_CONFIRMS,
+            payjoin_port: 0,
+            health_port: 0,
+            advertised_port: 0,
+            rebalance_swap_balance: 0,
+            rebalance_max_swapcoins: 0,
+            rebalance_fee_budget: 10_000,
+            rebalance_interval: 24 * 60 * 60,
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            max_connection_bytes: 4 * DEFAULT_MAX_MESSAGE_SIZE as u64,
+            base_fee: BASE_FEE,
+            amount_relative_fee_pct: AMOUNT_RELATIVE_FEE_PCT,
+            time_relative_fee_pct: TIME_RELATIVE_FEE_PCT,
+            fee_tiers: Vec::new(),
+            liquidity_windows: Vec::new(),
+            banned_peers: Vec::new(),
+            wallet: WalletConfig::default(),
+            max_contract_feerate: 100.0,
+            min_contract_reaction_time: MIN_CONTRACT_REACTION_TIME,
+            max_refund_locktime: 1008,
+            notify_webhooks: Vec::new(),
+            notify_script: String::new(),
+            bond_expiry_warning_blocks: BOND_EXPIRY_WARNING_BLOCKS,
+            low_balance_threshold: 0,
+            nostr_relays: Vec::new(),
+            offer_ttl_secs: 30 * 60,
+            idle_connection_timeout_secs: 60,
+            #[cfg(feature = "integration-test")]
+            contract_sigs_timeout_secs: 60,
+            #[cfg(feature = "integration-test")]
+            funding_confirmation_timeout_secs: 60,
+            #[cfg(feature = "integration-test")]
+            preimage_reveal_timeout_secs: 60,
+            #[cfg(not(feature = "integration-test"))]
+            contract_sigs_timeout_secs: 5 * 60,
+            #[cfg(not(feature = "integration-test"))]
+            funding_confirmation_timeout_secs: 15 * 60,
+            #[cfg(not(feature = "integration-test"))]
+            preimage_reveal_timeout_secs: 5 * 60,
+        }
+    }
+}
+
+/// The settings of the [MakerConfig] that can be reloaded on a running Maker, with `maker-cli reload-config`.
+///
+/// Changes apply to the offers and swaps that start after the reload. Ongoing swaps and the onion service are
+/// untouched.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct MakerPolicy {
+    pub(crate) base_fee: u64,
+    pub(crate) amount_relative_fee_pct: f64,
+    pub(crate) time_relative_fee_pct: f64,
+    /// By increasing minimum amount.
+    pub(crate) fee_tiers: Vec<FeeTier>,
+    pub(crate) liquidity_windows: Vec<LiquidityWindow>,
+    pub(crate) min_swap_amount: u64,
+    pub(crate) max_message_size: u32,
+    pub(crate) max_connection_bytes: u64,
+    pub(crate) banned_peers: Vec<String>,
+}
+
+impl From<&MakerConfig> for MakerPolicy {
+    fn from(config: &MakerConfig) -> Self {
+        let mut fee_tiers = config.fee_tiers.clone();
+        fee_tiers.sort_by_key(|tier| tier.min_amount);
+        Self {
+            base_fee: config.base_fee,
+            amount_relative_fee_pct: config.amount_relative_fee_pct,
+            time_relative_fee_pct: config.time_relative_fee_pct,
+            fee_tiers,
+            liquidity_windows: config.liquidity_windows.clone(),
+            min_swap_amount: config.min_swap_amount,
+            max_message_size: config.max_message_size,
+            max_connection_bytes: config.max_connection_bytes,
+            banned_peers: config.banned_peers.clone(),
         }
     }
 }
 
+impl MakerPolicy {
+    /// Whether connections from this IP address are refused.
+    pub(crate) fn is_banned(&self, ip: &str) -> bool {
+        self.banned_peers.iter().any(|peer| peer == ip)
+    }
+}
+
 impl MakerConfig {
+    /// The Tor socks port, if connecting over Tor.
+    pub(crate) fn tor_socks_port(&self) -> Option<u16> {
+        match self.connection_type {
+            #[cfg(feature = "tor")]
+            ConnectionType::TOR => Some(self.socks_port),
+            ConnectionType::CLEARNET => None,
+        }
+    }
+
+    /// Whether any of the rebalancing triggers is set.
+    pub(crate) fn rebalance_enabled(&self) -> bool {
+        self.rebalance_swap_balance != 0 || self.rebalance_max_swapcoins != 0
+    }
+
+    /// How long a taker may stay silent in a phase of the protocol. Outside of a swap, the idle connection timeout.
+    pub(crate) fn phase_timeout(&self, phase: ProtocolPhase) -> Duration {
+        Duration::from_secs(match phase {
+            ProtocolPhase::OfferFetch => self.idle_connection_timeout_secs,
+            ProtocolPhase::ContractSigs => self.contract_sigs_timeout_secs,
+            ProtocolPhase::FundingConfirmation => self.funding_confirmation_timeout_secs,
+            ProtocolPhase::PreimageReveal => self.preimage_reveal_timeout_secs,
+        })
+    }
+
     /// Constructs a [MakerConfig] from a specified data directory. Or create default configs and load them.
     ///
     /// The maker(/taker).toml file should exist at the provided data-dir location.
@@ -88,38 +268,100 @@ impl MakerConfig {
             default_config.write_to_file(config_path)?;
         }
 
-        let config_map = parse_toml(config_path)?;
+        let config_map = ConfigMap::load(config_path, "maker_config", "COINSWAP_MAKER_")?;
 
         log::info!(
             "Successfully loaded config file from : {}",
             config_path.display()
         );
 
-        Ok(MakerConfig {
-            network_port: parse_field(config_map.get("network_port"), default_config.network_port),
-            rpc_port: parse_field(config_map.get("rpc_port"), default_config.rpc_port),
-            min_swap_amount: parse_field(
-                config_map.get("min_swap_amount"),
-                default_config.min_swap_amount,
-            ),
-            socks_port: parse_field(config_map.get("socks_port"), default_config.socks_port),
-            directory_server_address: parse_field(
-                config_map.get("directory_server_address"),
+        let config = MakerConfig {
+            network_port: config_map.get("network_port", default_config.network_port)?,
+            rpc_port: config_map.get("rpc_port", default_config.rpc_port)?,
+            min_swap_amount: config_map.get("min_swap_amount", default_config.min_swap_amount)?,
+            socks_port: config_map.get("socks_port", default_config.socks_port)?,
+            directory_server_address: config_map.get(
+                "directory_server_address",
                 default_config.directory_server_address,
-            ),
-            fidelity_amount: parse_field(
-                config_map.get("fidelity_amount"),
-                default_config.fidelity_amount,
-            ),
-            fidelity_timelock: parse_field(
-                config_map.get("fidelity_timelock"),
-                default_config.fidelity_timelock,
-            ),
-            connection_type: parse_field(
-                config_map.get("connection_type"),
-                default_config.connection_type,
-            ),
-        })
+            )?,
+            fidelity_amount: config_map.get("fidelity_amount", default_config.fidelity_amount)?,
+            fidelity_timelock: config_map
+                .get("fidelity_timelock", default_config.fidelity_timelock)?,
+            connection_type: config_map.get("connection_type", default_config.connection_type)?,
+            required_confirms: config_map
+                .get("required_confirms", default_config.required_confirms)?,
+            payjoin_port: config_map.get("payjoin_port", default_config.payjoin_port)?,
+            health_port: config_map.get("health_port", default_config.health_port)?,
+            advertised_port: config_map.get("advertised_port", default_config.advertised_port)?,
+            rebalance_swap_balance: config_map.get(
+                "rebalance_swap_balance",
+                default_config.rebalance_swap_balance,
+            )?,
+            rebalance_max_swapcoins: config_map.get(
+                "rebalance_max_swapcoins",
+                default_config.rebalance_max_swapcoins,
+            )?,
+            rebalance_fee_budget: config_map
+                .get("rebalance_fee_budget", default_config.rebalance_fee_budget)?,
+            rebalance_interval: config_map
+                .get("rebalance_interval", default_config.rebalance_interval)?,
+            max_message_size: config_map
+                .get("max_message_size", default_config.max_message_size)?,
+            max_connection_bytes: config_map
+                .get("max_connection_bytes", default_config.max_connection_bytes)?,
+            base_fee: config_map.get("base_fee", default_config.base_fee)?,
+            amount_relative_fee_pct: config_map.get(
+                "amount_relative_fee_pct",
+                default_config.amount_relative_fee_pct,
+            )?,
+            time_relative_fee_pct: config_map.get(
+                "time_relative_fee_pct",
+                default_config.time_relative_fee_pct,
+            )?,
+            fee_tiers: config_map.get_parsed_list("fee_tiers", default_config.fee_tiers)?,
+            liquidity_windows: config_map
+                .get_parsed_list("liquidity_windows", default_config.liquidity_windows)?,
+            banned_peers: config_map.get_list("banned_peers", default_config.banned_peers),
+            wallet: WalletConfig::from_config_map(&config_map)?,
+            max_contract_feerate: config_map
+                .get("max_contract_feerate", default_config.max_contract_feerate)?,
+            min_contract_reaction_time: config_map.get(
+                "min_contract_reaction_time",
+                default_config.min_contract_reaction_time,
+            )?,
+            max_refund_locktime: config_map
+                .get("max_refund_locktime", default_config.max_refund_locktime)?,
+            notify_webhooks: config_map.get_list("notify_webhooks", default_config.notify_webhooks),
+            notify_script: config_map.get("notify_script", default_config.notify_script)?,
+            bond_expiry_warning_blocks: config_map.get(
+                "bond_expiry_warning_blocks",
+                default_config.bond_expiry_warning_blocks,
+            )?,
+            low_balance_threshold: config_map.get(
+                "low_balance_threshold",
+                default_config.low_balance_threshold,
+            )?,
+            nostr_relays: config_map.get_list("nostr_relays", default_config.nostr_relays),
+            offer_ttl_secs: config_map.get("offer_ttl_secs", default_config.offer_ttl_secs)?,
+            idle_connection_timeout_secs: config_map.get(
+                "idle_connection_timeout_secs",
+                default_config.idle_connection_timeout_secs,
+            )?,
+            contract_sigs_timeout_secs: config_map.get(
+                "contract_sigs_timeout_secs",
+                default_config.contract_sigs_timeout_secs,
+            )?,
+            funding_confirmation_timeout_secs: config_map.get(
+                "funding_confirmation_timeout_secs",
+                default_config.funding_confirmation_timeout_secs,
+            )?,
+            preimage_reveal_timeout_secs: config_map.get(
+                "preimage_reveal_timeout_secs",
+                default_config.preimage_reveal_timeout_secs,
+            )?,
+        };
+        config_map.reject_unknown_keys()?;
+        Ok(config)
     }
 
     // Method to serialize the MakerConfig into a TOML string and write it to a file
@@ -132,7 +374,37 @@ socks_port = {}
 directory_server_address = {}
 fidelity_amount = {}
 fidelity_timelock = {}
-connection_type = {:?}",
+connection_type = {:?}
+required_confirms = {}
+payjoin_port = {}
+health_port = {}
+advertised_port = {}
+rebalance_swap_balance = {}
+rebalance_max_swapcoins = {}
+rebalance_fee_budget = {}
+rebalance_interval = {}
+max_message_size = {}
+max_connection_bytes = {}
+base_fee = {}
+amount_relative_fee_pct = {}
+time_relative_fee_pct = {}
+fee_tiers = {}
+liquidity_windows = {}
+banned_peers = {}
+{}
+max_contract_feerate = {}
+min_contract_reaction_time = {}
+max_refund_locktime = {}
+notify_webhooks = {}
+notify_script = {}
+bond_expiry_warning_blocks = {}
+low_balance_threshold = {}
+nostr_relays = {}
+offer_ttl_secs = {}
+idle_connection_timeout_secs = {}
+contract_sigs_timeout_secs = {}
+funding_confirmation_timeout_secs = {}
+preimage_reveal_timeout_secs = {}",
             self.network_port,
             self.rpc_port,
             self.min_swap_amount,
@@ -141,6 +413,44 @@ connection_type = {:?}",
             self.fidelity_amount,
             self.fidelity_timelock,
             self.connection_type,
+            self.required_confirms,
+            self.payjoin_port,
+            self.health_port,
+            self.advertised_port,
+            self.rebalance_swap_balance,
+            self.rebalance_max_swapcoins,
+            self.rebalance_fee_budget,
+            self.rebalance_interval,
+            self.max_message_size,
+            self.max_connection_bytes,
+            self.base_fee,
+            self.amount_relative_fee_pct,
+            self.time_relative_fee_pct,
+            self.fee_tiers
+                .iter()
+                .map(FeeTier::to_string)
+                .collect::<Vec<_>>()
+                .join(","),
+            self.liquidity_windows
+                .iter()
+                .map(LiquidityWindow::to_string)
+                .collect::<Vec<_>>()
+                .join(","),
+            self.banned_peers.join(","),
+            self.wallet.to_toml(),
+            self.max_contract_feerate,
+            self.min_contract_reaction_time,
+            self.max_refund_locktime,
+            self.notify_webhooks.join(","),
+            self.notify_script,
+            self.bond_expiry_warning_blocks,
+            self.low_balance_threshold,
+            self.nostr_relays.join(","),
+            self.offer_ttl_secs,
+            self.idle_connection_timeout_secs,
+            self.contract_sigs_timeout_secs,
+            self.funding_confirmation_timeout_secs,
+            self.preimage_reveal_timeout_secs,
         );
 
         std::fs::create_dir_all(path.parent().expect("Path should NOT be root!"))?;
@@ -155,6 +465,7 @@ connection_type = {:?}",
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::wallet::{FeeSource, ScriptType};
     use std::{
         fs::{self, File},
         io::Write,
@@ -216,10 +527,135 @@ mod tests {
             network_port = "not_a_number"
         "#;
         let config_path = create_temp_config(contents, "incorrect_type_maker_config.toml");
+        let error = MakerConfig::new(Some(&config_path)).unwrap_err();
+        remove_temp_config(&config_path);
+
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().contains("`network_port`"));
+    }
+
+    #[test]
+    fn test_maker_policy() {
+        let contents = r#"
+            base_fee = 500
+            min_swap_amount = 20000
+            banned_peers = 10.0.0.1, 10.0.0.2
+        "#;
+        let config_path = create_temp_config(contents, "policy_maker_config.toml");
         let config = MakerConfig::new(Some(&config_path)).unwrap();
         remove_temp_config(&config_path);
 
-        assert_eq!(config, MakerConfig::default());
+        let policy = MakerPolicy::from(&config);
+        assert_eq!(policy.base_fee, 500);
+        assert_eq!(policy.min_swap_amount, 20000);
+        assert!(policy.is_banned("10.0.0.2"));
+        assert!(!policy.is_banned("10.0.0.3"));
+        assert_eq!(
+            policy.time_relative_fee_pct,
+            MakerConfig::default().time_relative_fee_pct
+        );
+    }
+
+    #[test]
+    fn test_fee_tiers() {
+        let contents = r#"
+            fee_tiers = 10000000:1.5, 1000000:2
+        "#;
+        let config_path = create_temp_config(contents, "fee_tiers_maker_config.toml");
+        let config = MakerConfig::new(Some(&config_path)).unwrap();
+        remove_temp_config(&config_path);
+
+        let policy = MakerPolicy::from(&config);
+        assert_eq!(
+            policy.fee_tiers,
+            vec![
+                FeeTier {
+                    min_amount: 1_000_000,
+                    amount_relative_fee_pct: 2.0
+                },
+                FeeTier {
+                    min_amount: 10_000_000,
+                    amount_relative_fee_pct: 1.5
+                },
+            ]
+        );
+
+        let contents = r#"
+            fee_tiers = 1000000:2, 10000000
+        "#;
+        let config_path = create_temp_config(contents, "invalid_fee_tiers_maker_config.toml");
+        let error = MakerConfig::new(Some(&config_path)).unwrap_err();
+        remove_temp_config(&config_path);
+        assert!(error.to_string().contains("`fee_tiers`"));
+    }
+
+    #[test]
+    fn test_notify_config() {
+        let contents = r#"
+            notify_webhooks = http://127.0.0.1:8080/hook, http://10.0.0.2/maker
+            notify_script = /usr/local/bin/notify.sh
+            low_balance_threshold = 100000
+        "#;
+        let config_path = create_temp_config(contents, "notify_maker_config.toml");
+        let config = MakerConfig::new(Some(&config_path)).unwrap();
+        remove_temp_config(&config_path);
+
+        assert_eq!(
+            config.notify_webhooks,
+            vec!["http://127.0.0.1:8080/hook", "http://10.0.0.2/maker"]
+        );
+        assert_eq!(config.notify_script, "/usr/local/bin/notify.sh");
+        assert_eq!(config.low_balance_threshold, 100_000);
+        assert_eq!(
+            config.bond_expiry_warning_blocks,
+            MakerConfig::default().bond_expiry_warning_blocks
+        );
+    }
+
+    #[test]
+    fn test_phase_timeouts() {
+        let contents = r#"
+            idle_connection_timeout_secs = 20
+            preimage_reveal_timeout_secs = 600
+        "#;
+        let config_path = create_temp_config(contents, "phase_timeouts_maker_config.toml");
+        let config = MakerConfig::new(Some(&config_path)).unwrap();
+        remove_temp_config(&config_path);
+
+        assert_eq!(
+            config.phase_timeout(ProtocolPhase::OfferFetch),
+            Duration::from_secs(20)
+        );
+        assert_eq!(
+            config.phase_timeout(ProtocolPhase::PreimageReveal),
+            Duration::from_secs(600)
+        );
+        assert_eq!(
+            config.phase_timeout(ProtocolPhase::FundingConfirmation),
+            MakerConfig::default().phase_timeout(ProtocolPhase::FundingConfirmation)
+        );
+    }
+
+    #[test]
+    fn test_wallet_config() {
+        let contents = r#"
+            gap_limit = 100
+            script_type = p2tr
+            fee_source = static:3
+        "#;
+        let config_path = create_temp_config(contents, "wallet_maker_config.toml");
+        let config = MakerConfig::new(Some(&config_path)).unwrap();
+        remove_temp_config(&config_path);
+
+        assert_eq!(
+            config.wallet,
+            WalletConfig {
+                gap_limit: 100,
+                script_type: ScriptType::P2tr,
+                fee_source: FeeSource::Static(3.0),
+                ..WalletConfig::default()
+            }
+        );
     }
 
     #[test]
@@ -4,7 +4,12 @@ use std::sync::{MutexGuard, PoisonError, RwLockReadGuard, RwLockWriteGuard};
 
 use bitcoin::secp256k1;
 
-use crate::{error::NetError, protocol::error::ProtocolError, wallet::WalletError};
+use crate::{
+    error::{ErrorCode, NetError},
+    protocol::error::ProtocolError,
+    taker::error::TakerError,
+    wallet::WalletError,
+};
 
 use super::MakerBehavior;
 
@@ -38,6 +43,35 @@ pub enum MakerError {
     SpecialBehaviour(MakerBehavior),
     /// Represents a protocol-related error.
     Protocol(ProtocolError),
+    /// Represents an error of the Taker run by the rebalancer.
+    Taker(TakerError),
+}
+
+impl ErrorCode for MakerError {
+    fn code(&self) -> u16 {
+        match self {
+            Self::IO(_) => 5000,
+            Self::UnexpectedMessage { .. } => 5001,
+            Self::General(_) => 5002,
+            Self::MutexPossion => 5003,
+            Self::Secp(_) => 5004,
+            Self::Wallet(e) => e.code(),
+            Self::Net(e) => e.code(),
+            Self::SpecialBehaviour(_) => 5005,
+            Self::Protocol(e) => e.code(),
+            Self::Taker(e) => e.code(),
+        }
+    }
+
+    fn is_transient(&self) -> bool {
+        match self {
+            Self::IO(_) => true,
+            Self::Wallet(e) => e.is_transient(),
+            Self::Net(e) => e.is_transient(),
+            Self::Taker(e) => e.is_transient(),
+            _ => false,
+        }
+    }
 }
 
 impl From<std::io::Error> for MakerError {
@@ -99,3 +133,9 @@ impl From<NetError> for MakerError {
         Self::Net(value)
     }
 }
+
+impl From<TakerError> for MakerError {
+    fn from(value: TakerError) -> Self {
+        Self::Taker(value)
+    }
+}
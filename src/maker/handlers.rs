@@ -7,209 +7,151 @@
 //! The file includes functions to validate and sign contract transactions, verify proof of funding, and handle unexpected recovery scenarios.
 //! Implements the core functionality for a Maker in a Bitcoin coinswap protocol.
 
-use std::{collections::HashMap, sync::Arc, time::Instant};
+use std::{
+    collections::HashMap,
+    sync::{atomic::Ordering::Relaxed, Arc},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
 
 use bitcoin::{
+    ecdsa::Signature,
     hashes::Hash,
-    secp256k1::{self, Secp256k1},
-    Amount, OutPoint, PublicKey, Transaction, Txid,
+    secp256k1::{self, Message, Secp256k1},
+    Amount, OutPoint, PublicKey, ScriptBuf, Transaction, Txid,
 };
 
 use super::{
-    api::{
-        recover_from_swap, ConnectionState, ExpectedMessage, Maker, MakerBehavior,
-        AMOUNT_RELATIVE_FEE_PCT, BASE_FEE, MIN_CONTRACT_REACTION_TIME, TIME_RELATIVE_FEE_PCT,
-    },
+    api::{recover_from_swap, ConnectionState, Maker, MakerBehavior},
     error::MakerError,
+    ledger::{Ledger, LedgerEntry},
 };
 
 use crate::{
+    events::Event,
     protocol::{
         contract::{
-            calculate_coinswap_fee, create_receivers_contract_tx, find_funding_output_index,
-            read_hashvalue_from_contract, read_pubkeys_from_multisig_redeemscript,
+            calculate_coinswap_fee, contract_fee, create_receivers_contract_tx,
+            find_funding_output_index, read_hashvalue_from_contract,
+            read_pubkeys_from_multisig_redeemscript, tiered_amount_relative_fee_pct,
+            CONTRACT_FEERATE_CONF_TARGET,
         },
-        error::ProtocolError,
+        machine::{maker_transition, MakerAction, PROTOCOL_VERSION},
         messages::{
             ContractSigsAsRecvrAndSender, ContractSigsForRecvr, ContractSigsForRecvrAndSender,
             ContractSigsForSender, HashPreimage, MakerHello, MakerToTakerMessage, MultisigPrivkey,
-            Offer, PrivKeyHandover, ProofOfFunding, ReqContractSigsForRecvr,
-            ReqContractSigsForSender, SenderContractTxInfo, TakerToMakerMessage,
+            PrivKeyHandover, ProofOfFunding, ReqContractSigsForRecvr, ReqContractSigsForSender,
+            SenderContractTxInfo, SignedReceipt, SwapReceipt, TakerToMakerMessage,
         },
+        session::{new_session_nonce, Session},
         Hash160,
     },
-    utill::REQUIRED_CONFIRMS,
-    wallet::{IncomingSwapCoin, SwapCoin, WalletError, WalletSwapCoin},
+    utill::{generate_keypair, unix_time},
+    wallet::{
+        verify_receipt, AmountDistribution, IncomingSwapCoin, InputConstraints, SwapCoin,
+        WalletError, WalletSwapCoin,
+    },
 };
 
 /// The Global Handle Message function. Takes in a [`Arc<Maker>`] and handle messages
 /// according to a [ConnectionState].
+///
+/// The protocol steps are decided by the pure [maker_transition]. This function performs the resulting
+/// [MakerAction] with the Maker's wallet, and the special behaviors of test Makers.
 pub(crate) fn handle_message(
     maker: &Arc<Maker>,
     connection_state: &mut ConnectionState,
     message: TakerToMakerMessage,
 ) -> Result<Option<MakerToTakerMessage>, MakerError> {
-    // If taker is waiting for funding confirmation, reset the timer.
-    if let TakerToMakerMessage::WaitingFundingConfirmation(id) = &message {
-        log::info!(
-            "[{}] Taker is waiting for funding confirmation. Reseting timer.",
-            maker.config.network_port
-        );
-        maker
-            .ongoing_swap_state
-            .lock()?
-            .entry(id.clone())
-            .and_modify(|(_, timer)| *timer = Instant::now());
-        return Ok(None);
+    // A draining maker finishes its ongoing swaps, but doesn't start new ones.
+    if maker.draining.load(Relaxed)
+        && matches!(
+            message,
+            TakerToMakerMessage::ReqGiveOffer(_) | TakerToMakerMessage::ReqContractSigsForSender(_)
+        )
+    {
+        return Err(MakerError::General(
+            "Draining for shutdown, not accepting new swaps",
+        ));
     }
 
-    let outgoing_message = match connection_state.allowed_message {
-        ExpectedMessage::TakerHello => {
-            if let TakerToMakerMessage::TakerHello(m) = message {
-                if m.protocol_version_min != 1 && m.protocol_version_max != 1 {
-                    return Err(ProtocolError::WrongMessage {
-                        expected: "Only protocol version 1 is allowed".to_string(),
-                        received: format!(
-                            "min/max version  = {}/{}",
-                            m.protocol_version_min, m.protocol_version_max
-                        ),
-                    }
-                    .into());
-                }
-                connection_state.allowed_message = ExpectedMessage::NewlyConnectedTaker;
-                let reply = MakerToTakerMessage::MakerHello(MakerHello {
-                    protocol_version_min: 1,
-                    protocol_version_max: 1,
-                });
-                Some(reply)
-            } else {
-                return Err(MakerError::UnexpectedMessage {
-                    expected: "TakerHello".to_string(),
-                    got: format!("{}", message),
-                });
-            }
+    match maker.behavior {
+        MakerBehavior::CloseAtSpecificMessage(message_type)
+            if message.message_type() == Some(message_type) =>
+        {
+            return Err(maker.behavior.into());
         }
-        ExpectedMessage::NewlyConnectedTaker => match message {
-            TakerToMakerMessage::ReqGiveOffer(_) => {
-                let (tweakable_point, max_size) = {
-                    let wallet_reader = maker.wallet.read()?;
-                    let max_size = wallet_reader.store.offer_maxsize;
-                    let tweakable_point = wallet_reader.get_tweakable_keypair()?.1;
-                    (tweakable_point, max_size)
-                };
-                connection_state.allowed_message = ExpectedMessage::ReqContractSigsForSender;
-                let fidelity = maker.highest_fidelity_proof.read()?;
-                let fidelity = fidelity.as_ref().expect("proof expected");
-                Some(MakerToTakerMessage::RespOffer(Box::new(Offer {
-                    base_fee: BASE_FEE,
-                    amount_relative_fee_pct: AMOUNT_RELATIVE_FEE_PCT,
-                    time_relative_fee_pct: TIME_RELATIVE_FEE_PCT,
-                    required_confirms: REQUIRED_CONFIRMS,
-                    minimum_locktime: MIN_CONTRACT_REACTION_TIME,
-                    max_size,
-                    min_size: maker.config.min_swap_amount,
-                    tweakable_point,
-                    fidelity: fidelity.clone(),
-                })))
-            }
-            TakerToMakerMessage::ReqContractSigsForSender(message) => {
-                connection_state.allowed_message = ExpectedMessage::ProofOfFunding;
-                Some(maker.handle_req_contract_sigs_for_sender(message)?)
-            }
-            TakerToMakerMessage::RespProofOfFunding(proof) => {
-                connection_state.allowed_message =
-                    ExpectedMessage::ProofOfFundingORContractSigsForRecvrAndSender;
-                Some(maker.handle_proof_of_funding(connection_state, proof)?)
-            }
-            TakerToMakerMessage::ReqContractSigsForRecvr(message) => {
-                connection_state.allowed_message = ExpectedMessage::HashPreimage;
-                Some(maker.handle_req_contract_sigs_for_recvr(message)?)
-            }
-            TakerToMakerMessage::RespHashPreimage(message) => {
-                connection_state.allowed_message = ExpectedMessage::PrivateKeyHandover;
-                Some(maker.handle_hash_preimage(message)?)
-            }
-            _ => {
-                log::info!("Newlyconnected taker stage message: {:?} ", message);
-                return Err(MakerError::General(
-                    "Unexpected Newly Connected Taker message",
-                ));
-            }
-        },
-        ExpectedMessage::ReqContractSigsForSender => {
-            if let TakerToMakerMessage::ReqContractSigsForSender(message) = message {
-                connection_state.allowed_message = ExpectedMessage::ProofOfFunding;
-                Some(maker.handle_req_contract_sigs_for_sender(message)?)
-            } else {
-                return Err(MakerError::UnexpectedMessage {
-                    expected: "ReqContractSigsForSender".to_string(),
-                    got: format!("{}", message),
-                });
-            }
+        MakerBehavior::StallAtHashPreimage
+            if matches!(message, TakerToMakerMessage::RespHashPreimage(_)) =>
+        {
+            log::warn!(
+                "[{}] Stalling at hash preimage, not responding.",
+                maker.config.network_port
+            );
+            return Ok(None);
         }
-        ExpectedMessage::ProofOfFunding => {
-            if let TakerToMakerMessage::RespProofOfFunding(proof) = message {
-                connection_state.allowed_message =
-                    ExpectedMessage::ProofOfFundingORContractSigsForRecvrAndSender;
-                Some(maker.handle_proof_of_funding(connection_state, proof)?)
-            } else {
-                return Err(MakerError::UnexpectedMessage {
-                    expected: "Proof OF Funding".to_string(),
-                    got: format!("{}", message),
-                });
-            }
+        _ => {}
+    }
+
+    let (action, next_message) = maker_transition(&connection_state.allowed_message, message)?;
+    connection_state.allowed_message = next_message;
+
+    let outgoing_message = match action {
+        // If taker is waiting for funding confirmation, reset the timer.
+        MakerAction::KeepAlive(id) => {
+            log::info!(
+                "[{}] Taker is waiting for funding confirmation. Reseting timer.",
+                maker.config.network_port
+            );
+            maker
+                .ongoing_swap_state
+                .lock()?
+                .entry(id)
+                .and_modify(|(_, timer)| *timer = Instant::now());
+            None
         }
-        ExpectedMessage::ProofOfFundingORContractSigsForRecvrAndSender => {
-            match message {
-                TakerToMakerMessage::RespProofOfFunding(proof) => {
-                    connection_state.allowed_message =
-                        ExpectedMessage::ProofOfFundingORContractSigsForRecvrAndSender;
-                    Some(maker.handle_proof_of_funding(connection_state, proof)?)
-                }
-                TakerToMakerMessage::RespContractSigsForRecvrAndSender(message) => {
-                    // Nothing to send. Maker now creates and broadcasts his funding Txs
-                    connection_state.allowed_message = ExpectedMessage::ReqContractSigsForRecvr;
-                    maker.handle_contract_sigs_for_recvr_and_sender(connection_state, message)?;
-                    if let MakerBehavior::BroadcastContractAfterSetup = maker.behavior {
-                        unexpected_recovery(maker.clone())?;
-                        return Err(maker.behavior.into());
-                    } else {
-                        None
-                    }
-                }
-                _ => {
-                    return Err(MakerError::General(
-                        "Expected proof of funding or sender's and reciever's contract signatures",
-                    ));
-                }
-            }
+        MakerAction::Hello(taker_nonce, session_key) => {
+            let maker_nonce = new_session_nonce();
+            let mut session = Session::new(&taker_nonce, &maker_nonce);
+            let identity = {
+                let wallet = maker.get_wallet().read()?;
+                session.bind(&wallet.session_secret(&session_key)?);
+                wallet.sign_session(session.id())?
+            };
+            connection_state.session = Some(session);
+            connection_state.taker_session_key = Some(session_key);
+            Some(MakerToTakerMessage::MakerHello(MakerHello {
+                protocol_version_min: PROTOCOL_VERSION,
+                protocol_version_max: PROTOCOL_VERSION,
+                session_nonce: Some(maker_nonce),
+                identity: Some(identity),
+            }))
         }
-        ExpectedMessage::ReqContractSigsForRecvr => {
-            if let TakerToMakerMessage::ReqContractSigsForRecvr(message) = message {
-                connection_state.allowed_message = ExpectedMessage::HashPreimage;
-                Some(maker.handle_req_contract_sigs_for_recvr(message)?)
-            } else {
-                return Err(MakerError::General(
-                    "Expected reciever's contract transaction",
-                ));
-            }
+        MakerAction::GiveOffer => Some(MakerToTakerMessage::RespOffer(Box::new(maker.offer()?))),
+        MakerAction::SignSenderContracts(message) => {
+            Some(maker.handle_req_contract_sigs_for_sender(message)?)
         }
-        ExpectedMessage::HashPreimage => {
-            if let TakerToMakerMessage::RespHashPreimage(message) = message {
-                connection_state.allowed_message = ExpectedMessage::PrivateKeyHandover;
-                Some(maker.handle_hash_preimage(message)?)
-            } else {
-                return Err(MakerError::General("Expected hash preimgae"));
-            }
+        MakerAction::VerifyFunding(proof) => {
+            Some(maker.handle_proof_of_funding(connection_state, proof)?)
         }
-        ExpectedMessage::PrivateKeyHandover => {
-            if let TakerToMakerMessage::RespPrivKeyHandover(message) = message {
-                // Nothing to send. Succesfully completed swap
-                maker.handle_private_key_handover(message)?;
-                None
-            } else {
-                return Err(MakerError::General("expected privatekey handover"));
+        MakerAction::FundNextHop(message) => {
+            // Nothing to send. Maker now creates and broadcasts his funding Txs
+            maker.handle_contract_sigs_for_recvr_and_sender(connection_state, message)?;
+            if let MakerBehavior::BroadcastContractAfterSetup = maker.behavior {
+                unexpected_recovery(maker.clone())?;
+                return Err(maker.behavior.into());
             }
+            None
+        }
+        MakerAction::SignReceiverContracts(message) => {
+            Some(maker.handle_req_contract_sigs_for_recvr(message)?)
+        }
+        MakerAction::HandOverPrivkeys(message) => {
+            Some(maker.handle_hash_preimage(connection_state, message)?)
+        }
+        MakerAction::Settle(message) => {
+            // Nothing to send. Succesfully completed swap
+            maker.handle_private_key_handover(connection_state, message)?;
+            None
         }
     };
 
@@ -230,7 +172,10 @@ impl Maker {
         }
 
         // Verify and sign the contract transaction, check function definition for all the checks.
-        let sigs = self.verify_and_sign_contract_tx(&message)?;
+        let mut sigs = self.verify_and_sign_contract_tx(&message)?;
+        if let MakerBehavior::SendInvalidSignature = self.behavior {
+            corrupt_signatures(&mut sigs);
+        }
 
         let funding_txids = message
             .txs_info
@@ -249,15 +194,17 @@ impl Maker {
             funding_txids
         );
 
-        let max_size = self.wallet.read()?.store.offer_maxsize;
-        if total_funding_amount >= self.config.min_swap_amount && total_funding_amount <= max_size {
+        // Capped by the liquidity window, and 0 outside of them.
+        let max_size = self.swap_size_limit()?;
+        let min_size = self.policy.read()?.min_swap_amount;
+        if total_funding_amount >= min_size && total_funding_amount <= max_size {
             Ok(MakerToTakerMessage::RespContractSigsForSender(
                 ContractSigsForSender { sigs },
             ))
         } else {
             log::error!(
                 "Funding amount not within min/max limit, min {}, max {}",
-                self.config.min_swap_amount,
+                min_size,
                 max_size
             );
             Err(MakerError::General("not enough funds"))
@@ -278,6 +225,15 @@ impl Maker {
         // Basic verification of ProofOfFunding Message.
         // Check function definition for all the checks performed.
         let hashvalue = self.verify_proof_of_funding(&message)?;
+
+        // Fee of the contract txs, of the incoming swap and of the next hop.
+        let contract_fee = match message.contract_feerate_sat_vb {
+            Some(feerate) => {
+                self.check_contract_feerate(feerate)?;
+                contract_fee(feerate)
+            }
+            None => Amount::from_sat(message.contract_feerate),
+        };
         log::info!(
             "[{}] Validated Proof of Funding of receiving swap. Adding Incoming Swaps.",
             self.config.network_port
@@ -305,7 +261,7 @@ impl Maker {
                 },
                 funding_output.value,
                 &funding_info.contract_redeemscript,
-                Amount::from_sat(message.contract_feerate),
+                contract_fee,
             )?;
 
             let (tweakable_privkey, _) = self.wallet.read()?.get_tweakable_keypair()?;
@@ -358,18 +314,25 @@ impl Maker {
                 Ok::<_, MakerError>(acc + txout.value.to_sat())
             })?;
 
-        let calc_coinswap_fees = calculate_coinswap_fee(
-            incoming_amount,
-            message.refund_locktime,
-            BASE_FEE,
-            AMOUNT_RELATIVE_FEE_PCT,
-            TIME_RELATIVE_FEE_PCT,
-        );
+        let calc_coinswap_fees = {
+            let policy = self.policy.read()?;
+            calculate_coinswap_fee(
+                incoming_amount,
+                message.refund_locktime,
+                policy.base_fee,
+                tiered_amount_relative_fee_pct(
+                    policy.amount_relative_fee_pct,
+                    &policy.fee_tiers,
+                    incoming_amount,
+                ),
+                policy.time_relative_fee_pct,
+            )
+        };
 
-        // NOTE: The `contract_feerate` currently represents the hardcoded `MINER_FEE` of a transaction, not the fee rate.
-        // This will remain unchanged to avoid modifying the structure of the [ProofOfFunding] message.
-        // Once issue https://github.com/citadel-tech/coinswap/issues/309 is resolved,
+        // NOTE: The `contract_feerate` currently represents the hardcoded `MINER_FEE` of a funding transaction,
+        // not the fee rate. Once issue https://github.com/citadel-tech/coinswap/issues/309 is resolved,
         //`contract_feerate` will represent the actual fee rate instead of the `MINER_FEE`.
+        // The contract txs pay the negotiated `contract_feerate_sat_vb` instead, when set.
         let calc_funding_tx_fees =
             message.contract_feerate * (message.next_coinswap_info.len() as u64);
 
@@ -403,6 +366,9 @@ impl Maker {
                 hashvalue,
                 message.refund_locktime,
                 Amount::from_sat(message.contract_feerate),
+                contract_fee,
+                AmountDistribution::default(),
+                InputConstraints::default(),
             )?
         };
 
@@ -431,6 +397,9 @@ impl Maker {
 
         connection_state.pending_funding_txes = my_funding_txes;
         connection_state.outgoing_swapcoins = outgoing_swapcoins;
+        connection_state.incoming_amount = Amount::from_sat(incoming_amount);
+        connection_state.outgoing_amount = Amount::from_sat(outgoing_amount);
+        connection_state.funding_fee = act_funding_txs_fees;
 
         // Save things to disk after Proof of Funding is confirmed.
         {
@@ -467,13 +436,24 @@ impl Maker {
             .collect::<Result<Vec<SenderContractTxInfo>, WalletError>>()?;
 
         // Update the connection state.
-        self.ongoing_swap_state.lock()?.insert(
-            message.id.clone(),
-            (connection_state.clone(), Instant::now()),
-        );
+        let is_new_swap = self
+            .ongoing_swap_state
+            .lock()?
+            .insert(
+                message.id.clone(),
+                (connection_state.clone(), Instant::now()),
+            )
+            .is_none();
 
         log::info!("Connection state initiatilzed for swap id: {}", message.id);
 
+        if is_new_swap {
+            self.events.publish(Event::SwapStarted {
+                swap_id: message.id.clone(),
+                amount: Amount::from_sat(incoming_amount),
+            });
+        }
+
         Ok(MakerToTakerMessage::ReqContractSigsAsRecvrAndSender(
             ContractSigsAsRecvrAndSender {
                 receivers_contract_txs,
@@ -533,6 +513,30 @@ impl Maker {
             wallet_writer.save_to_disk()?;
         }
 
+        // Contracts are fully signed now. Schedule the timelock refunds of the outgoing contracts,
+        // and let the incoming contracts be claimed via hashlock as soon as the preimage is revealed.
+        for outgoing_sc in &connection_state.outgoing_swapcoins {
+            self.schedule_timelock_sweep(outgoing_sc)?;
+        }
+        for incoming_sc in &connection_state.incoming_swapcoins {
+            let claim_address = &self.next_claim_address()?;
+            self.watchtower
+                .register_incoming(incoming_sc.clone(), claim_address)?;
+        }
+
+        // The funding txs carry the whole swap amount, flag broken fee estimates before they go out.
+        if let Err(e) = self
+            .wallet
+            .read()?
+            .cross_check_feerate(CONTRACT_FEERATE_CONF_TARGET)
+        {
+            log::warn!(
+                "[{}] Failed cross-checking fee estimates | {:?}",
+                self.config.network_port,
+                e
+            );
+        }
+
         let mut my_funding_txids = Vec::<Txid>::new();
         for my_funding_tx in &connection_state.pending_funding_txes {
             let txid = self.wallet.read()?.send_tx(my_funding_tx)?;
@@ -566,7 +570,7 @@ impl Maker {
             return Err(self.behavior.into());
         }
 
-        let sigs = message
+        let mut sigs = message
             .txs
             .iter()
             .map(|txinfo| {
@@ -578,6 +582,9 @@ impl Maker {
                     .sign_contract_tx_with_my_privkey(&txinfo.contract_tx)?)
             })
             .collect::<Result<Vec<_>, MakerError>>()?;
+        if let MakerBehavior::SendInvalidSignature = self.behavior {
+            corrupt_signatures(&mut sigs);
+        }
 
         Ok(MakerToTakerMessage::RespContractSigsForRecvr(
             ContractSigsForRecvr { sigs },
@@ -587,6 +594,7 @@ impl Maker {
     /// Handles a [HashPreimage] message and returns a [MakerToTakerMessage::RespPrivKeyHandover]
     pub(crate) fn handle_hash_preimage(
         &self,
+        connection_state: &mut ConnectionState,
         message: HashPreimage,
     ) -> Result<MakerToTakerMessage, MakerError> {
         if let MakerBehavior::CloseAtHashPreimage = self.behavior {
@@ -606,6 +614,9 @@ impl Maker {
             incoming_swapcoin.hash_preimage = Some(message.preimage);
         }
 
+        // With the preimage known, the incoming contracts can be claimed via hashlock.
+        self.watchtower.reveal_preimage(&message.preimage)?;
+
         log::info!(
             "[{}] received preimage for hashvalue={}",
             self.config.network_port,
@@ -633,17 +644,83 @@ impl Maker {
         }
 
         self.wallet.write()?.save_to_disk()?;
+
+        let receipt = match connection_state.taker_session_key {
+            Some(taker_key) => {
+                self.issue_receipt(&message.senders_multisig_redeemscripts, taker_key)?
+            }
+            None => None,
+        };
+        connection_state.receipt = receipt.clone();
         Ok(MakerToTakerMessage::RespPrivKeyHandover(PrivKeyHandover {
             multisig_privkeys: swapcoin_private_keys,
+            receipt,
         }))
     }
 
+    /// Issues the signed receipt of the ongoing swap receiving these multisigs, to be countersigned by
+    /// `taker_key`. None if no such swap is ongoing.
+    fn issue_receipt(
+        &self,
+        senders_multisig_redeemscripts: &[ScriptBuf],
+        taker_key: PublicKey,
+    ) -> Result<Option<SignedReceipt>, MakerError> {
+        let funding_txid = |contract_tx: &Transaction| contract_tx.input[0].previous_output.txid;
+        let ongoing_swaps = self.ongoing_swap_state.lock()?;
+        let receipt = swap_receiving(&ongoing_swaps, senders_multisig_redeemscripts)
+            .and_then(|swap_id| ongoing_swaps.get_key_value(&swap_id))
+            .map(|(swap_id, (state, _))| SwapReceipt {
+                swap_id: swap_id.clone(),
+                incoming_sats: state.incoming_amount.to_sat(),
+                outgoing_sats: state.outgoing_amount.to_sat(),
+                miner_fees_sats: state.funding_fee.to_sat(),
+                incoming_funding_txids: state
+                    .incoming_swapcoins
+                    .iter()
+                    .map(|sc| funding_txid(&sc.contract_tx))
+                    .collect(),
+                outgoing_funding_txids: state
+                    .outgoing_swapcoins
+                    .iter()
+                    .map(|sc| funding_txid(&sc.contract_tx))
+                    .collect(),
+                completed_at: unix_time(),
+                taker_key,
+            });
+        match receipt {
+            Some(receipt) => Ok(Some(self.wallet.read()?.sign_receipt(receipt)?)),
+            None => Ok(None),
+        }
+    }
+
     /// Handles [PrivKeyHandover] message and updates all the coinswap wallet states and stores it to disk.
     /// This is the last step of completing a coinswap round.
     pub(crate) fn handle_private_key_handover(
         &self,
+        connection_state: &mut ConnectionState,
         message: PrivKeyHandover,
     ) -> Result<(), MakerError> {
+        // The Taker countersigns with the session key of its hello, committed to in the receipt. Unlike the
+        // multisig keys it hands over, we never learn its secret, so we can't forge the countersignature.
+        let receipt = match (connection_state.receipt.take(), message.receipt) {
+            (Some(issued), Some(countersigned))
+                if countersigned.receipt == issued.receipt
+                    && countersigned.maker_sig == issued.maker_sig
+                    && countersigned.taker_sig.is_some()
+                    && verify_receipt(&countersigned).is_ok() =>
+            {
+                Some(countersigned)
+            }
+            (Some(_), Some(_)) => {
+                log::warn!(
+                    "[{}] Taker returned an invalid receipt, keeping none",
+                    self.config.network_port
+                );
+                None
+            }
+            _ => None,
+        };
+
         // Mark the incoming swapcoins as "done", by adding their's privkey
         for swapcoin_private_key in &message.multisig_privkeys {
             self.wallet
@@ -653,9 +730,35 @@ impl Maker {
                 .apply_privkey(swapcoin_private_key.key)?;
         }
 
-        // Reset the connection state so watchtowers are not triggered.
-        let mut conn_state = self.ongoing_swap_state.lock()?;
-        *conn_state = HashMap::default();
+        // Only this swap is complete, the ones of concurrent takers keep running. Its state is dropped so
+        // watchtowers are not triggered.
+        let handed_over_multisigs = message
+            .multisig_privkeys
+            .iter()
+            .map(|privkey| privkey.multisig_redeemscript.clone())
+            .collect::<Vec<_>>();
+        let completed = take_completed_swap(
+            &mut *self.ongoing_swap_state.lock()?,
+            &handed_over_multisigs,
+        );
+        if completed.is_none() {
+            log::warn!(
+                "[{}] No ongoing swap receiving the handed over multisigs",
+                self.config.network_port
+            );
+        }
+
+        // Swap is complete, no more contract claims needed.
+        if let Some((_, state)) = &completed {
+            for contract_tx in state
+                .incoming_swapcoins
+                .iter()
+                .map(|sc| &sc.contract_tx)
+                .chain(state.outgoing_swapcoins.iter().map(|sc| &sc.contract_tx))
+            {
+                self.watchtower.unwatch(&contract_tx.compute_txid())?;
+            }
+        }
 
         log::info!("initializing Wallet Sync.");
         {
@@ -664,14 +767,61 @@ impl Maker {
             wallet_write.save_to_disk()?;
         }
         log::info!("Completed Wallet Sync.");
+
+        if let Some((swap_id, state)) = completed {
+            let ledger_entry = LedgerEntry {
+                swap_id: swap_id.clone(),
+                completed_at: SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs(),
+                incoming_sats: state.incoming_amount.to_sat(),
+                capital_locked_sats: state.outgoing_amount.to_sat(),
+                miner_fees_sats: state.funding_fee.to_sat(),
+                receipt: receipt.filter(|r| r.receipt.swap_id == swap_id),
+            };
+            if let Err(e) = Ledger::append(&self.data_dir.join("ledger.dat"), vec![ledger_entry]) {
+                log::error!("Failed recording the swap in the ledger: {:?}", e);
+            }
+            self.events.publish(Event::SwapCompleted { swap_id });
+        }
+
         log::info!("Successfully Completed Coinswap");
         Ok(())
     }
 }
 
+/// Id of the ongoing swap receiving any of these multisigs.
+fn swap_receiving(
+    ongoing_swaps: &HashMap<String, (ConnectionState, Instant)>,
+    multisig_redeemscripts: &[ScriptBuf],
+) -> Option<String> {
+    ongoing_swaps
+        .iter()
+        .find(|(_, (state, _))| {
+            state
+                .incoming_swapcoins
+                .iter()
+                .any(|sc| multisig_redeemscripts.contains(&sc.get_multisig_redeemscript()))
+        })
+        .map(|(swap_id, _)| swap_id.clone())
+}
+
+/// Takes the ongoing swap receiving these multisigs out of `ongoing_swaps`, with its id. The swaps of concurrent
+/// takers are left in.
+fn take_completed_swap(
+    ongoing_swaps: &mut HashMap<String, (ConnectionState, Instant)>,
+    multisig_redeemscripts: &[ScriptBuf],
+) -> Option<(String, ConnectionState)> {
+    let swap_id = swap_receiving(ongoing_swaps, multisig_redeemscripts)?;
+    ongoing_swaps
+        .remove(&swap_id)
+        .map(|(state, _)| (swap_id, state))
+}
+
 fn unexpected_recovery(maker: Arc<Maker>) -> Result<(), MakerError> {
     let mut lock_on_state = maker.ongoing_swap_state.lock()?;
-    for (_, (state, _)) in lock_on_state.iter_mut() {
+    for (swap_id, (state, _)) in lock_on_state.iter_mut() {
         let mut outgoings = Vec::new();
         let mut incomings = Vec::new();
         // Extract Incoming and Outgoing contracts, and timelock spends of the contract transactions.
@@ -683,7 +833,7 @@ fn unexpected_recovery(maker: Arc<Maker>) -> Result<(), MakerError> {
         {
             let contract_timelock = og_sc.get_timelock()?;
             let contract = og_sc.get_fully_signed_contract_tx()?;
-            let next_internal_address = &maker.wallet.read()?.get_next_internal_addresses(1)?[0];
+            let next_internal_address = &maker.next_claim_address()?;
             let time_lock_spend = og_sc.create_timelock_spend(next_internal_address)?;
             outgoings.push((
                 (og_sc.get_multisig_redeemscript(), contract),
@@ -692,11 +842,18 @@ fn unexpected_recovery(maker: Arc<Maker>) -> Result<(), MakerError> {
             let incoming_contract = ic_sc.get_fully_signed_contract_tx()?;
             incomings.push((ic_sc.get_multisig_redeemscript(), incoming_contract));
         }
+        maker.events.publish(Event::RecoveryInitiated {
+            swap_id: Some(swap_id.clone()),
+            reason: "Contracts broadcasted after setup".to_string(),
+        });
         // Spawn a separate thread to wait for contract maturity and broadcasting timelocked.
         let maker_clone = maker.clone();
+        // The recovery logs in the span of the swap.
+        let span = tracing::info_span!("swap", id = %swap_id);
         let handle = std::thread::Builder::new()
             .name("Swap Recovery Thread".to_string())
             .spawn(move || {
+                let _entered = span.enter();
                 if let Err(e) = recover_from_swap(maker_clone, outgoings, incomings) {
                     log::error!("Failed to recover from swap due to: {:?}", e);
                 }
@@ -705,3 +862,77 @@ fn unexpected_recovery(maker: Arc<Maker>) -> Result<(), MakerError> {
     }
     Ok(())
 }
+
+/// Replace the signatures with ones made by a random key over an unrelated message.
+/// Used by [MakerBehavior::SendInvalidSignature].
+fn corrupt_signatures(sigs: &mut [Signature]) {
+    let secp = Secp256k1::new();
+    let (_, privkey) = generate_keypair();
+    let message = Message::from_digest([1; 32]);
+    for sig in sigs {
+        sig.signature = secp.sign_ecdsa(&message, &privkey);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::contract::create_contract_redeemscript;
+    use bitcoin::{absolute::LockTime, transaction::Version};
+
+    /// State of an ongoing swap, receiving a coin of a fresh multisig.
+    fn ongoing_swap(incoming_sats: u64) -> ConnectionState {
+        let (my_pubkey, my_privkey) = generate_keypair();
+        let (other_pubkey, _) = generate_keypair();
+        let (hashlock_pubkey, hashlock_privkey) = generate_keypair();
+        let contract_redeemscript = create_contract_redeemscript(
+            &hashlock_pubkey,
+            &my_pubkey,
+            &Hash160::hash(&[1; 32]),
+            &20,
+        );
+        let contract_tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![],
+            output: vec![],
+        };
+        ConnectionState {
+            incoming_swapcoins: vec![IncomingSwapCoin::new(
+                my_privkey,
+                other_pubkey,
+                contract_tx,
+                contract_redeemscript,
+                hashlock_privkey,
+                Amount::from_sat(incoming_sats),
+            )
+            .unwrap()],
+            incoming_amount: Amount::from_sat(incoming_sats),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_complete_one_of_concurrent_swaps() {
+        let first = ongoing_swap(100_000);
+        let second = ongoing_swap(200_000);
+        let second_multisig = second.incoming_swapcoins[0].get_multisig_redeemscript();
+        let mut ongoing_swaps = HashMap::new();
+        ongoing_swaps.insert("first".to_string(), (first, Instant::now()));
+        ongoing_swaps.insert("second".to_string(), (second, Instant::now()));
+
+        // An unknown multisig completes nothing.
+        let unknown = ongoing_swap(300_000).incoming_swapcoins[0].get_multisig_redeemscript();
+        assert!(take_completed_swap(&mut ongoing_swaps, &[unknown]).is_none());
+        assert_eq!(ongoing_swaps.len(), 2);
+
+        // The handover of the second swap completes it only.
+        let (swap_id, state) = take_completed_swap(&mut ongoing_swaps, &[second_multisig]).unwrap();
+        assert_eq!(swap_id, "second");
+        assert_eq!(state.incoming_amount, Amount::from_sat(200_000));
+        assert_eq!(
+            ongoing_swaps.keys().collect::<Vec<_>>(),
+            vec![&"first".to_string()]
+        );
+    }
+}
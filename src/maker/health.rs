@@ -0,0 +1,222 @@
+//! Health and readiness of the Maker, for container orchestration and uptime monitors.
+//!
+//! The report is served by the `health` RPC (`maker-cli health`), and over plain HTTP at `GET /health` and
+//! `GET /ready` on the `health_port` of the config. The HTTP endpoints answer `200 OK` or `503 Service
+//! Unavailable`, with the JSON report as body. They only listen on localhost.
+//!
+//! The Maker is healthy while its node answers and, over Tor, its Tor socks port is reachable: restarting it
+//! won't help otherwise. It's ready to take swaps once also set up, registered with the directory, and backed
+//! by an unexpired fidelity bond.
+
+use std::{
+    io::{self, ErrorKind, Read},
+    net::{TcpListener, TcpStream},
+    sync::{atomic::Ordering::Relaxed, Arc},
+    thread::sleep,
+    time::Duration,
+};
+
+#[cfg(feature = "tor")]
+use std::net::SocketAddr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::utill::{http_request_path, unix_time, ConnectionType, HEART_BEAT_INTERVAL};
+
+use super::{error::MakerError, payjoin::send_response, Maker};
+
+/// Time allowed to reach the Tor socks port.
+#[cfg(feature = "tor")]
+const TOR_CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Health and readiness of the Maker.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct HealthReport {
+    /// Whether the Bitcoin Core node answers.
+    pub node_connected: bool,
+    /// Block height of the node. None if it doesn't answer.
+    pub node_height: Option<u64>,
+    /// Block height the wallet is synced to.
+    pub wallet_synced_height: Option<u64>,
+    /// Whether the Tor socks port accepts connections. None on clearnet.
+    pub tor_reachable: Option<bool>,
+    /// Whether the directory holds our registration: it was posted within the offer TTL.
+    pub directory_registered: bool,
+    /// Seconds since our address was last posted to the directory. None if never.
+    pub directory_posted_secs_ago: Option<u64>,
+    /// Whether the fidelity bond of our offers is set up and unexpired.
+    pub bond_valid: bool,
+    /// Blocks left until the fidelity bond expires. None without a bond, or without the node.
+    pub bond_blocks_remaining: Option<u32>,
+    /// Whether the server threads are all started.
+    pub setup_complete: bool,
+}
+
+impl HealthReport {
+    /// Whether the Maker is alive: its node answers, and its Tor is reachable if it runs on Tor.
+    pub fn is_healthy(&self) -> bool {
+        self.node_connected && self.tor_reachable != Some(false)
+    }
+
+    /// Whether the Maker can take swaps: healthy, set up, listed by the directory, and backed by a bond.
+    pub fn is_ready(&self) -> bool {
+        self.is_healthy() && self.setup_complete && self.directory_registered && self.bond_valid
+    }
+}
+
+impl Maker {
+    /// Checks the health of the Maker. Never fails: what can't be checked is reported unhealthy.
+    pub(crate) fn health(&self) -> HealthReport {
+        let (node_height, wallet_synced_height) = match self.get_wallet().read() {
+            Ok(wallet) => (wallet.chain.block_count().ok(), wallet.last_synced_height()),
+            Err(_) => (None, None),
+        };
+
+        let tor_reachable = match self.config.connection_type {
+            ConnectionType::CLEARNET => None,
+            #[cfg(feature = "tor")]
+            ConnectionType::TOR => Some(
+                TcpStream::connect_timeout(
+                    &SocketAddr::from(([127, 0, 0, 1], self.config.socks_port)),
+                    TOR_CONNECT_TIMEOUT,
+                )
+                .is_ok(),
+            ),
+        };
+
+        let directory_posted_secs_ago = match self.last_directory_post.load(Relaxed) {
+            0 => None,
+            posted => Some(unix_time().saturating_sub(posted)),
+        };
+        let directory_registered =
+            directory_posted_secs_ago.is_some_and(|secs_ago| secs_ago < self.config.offer_ttl_secs);
+
+        let bond_lock_time = self.highest_fidelity_proof.read().ok().and_then(|proof| {
+            proof
+                .as_ref()
+                .map(|proof| proof.bond.lock_time.to_consensus_u32())
+        });
+        let bond_blocks_remaining = bond_lock_time
+            .zip(node_height)
+            .map(|(lock_time, height)| lock_time.saturating_sub(height as u32));
+
+        HealthReport {
+            node_connected: node_height.is_some(),
+            node_height,
+            wallet_synced_height,
+            tor_reachable,
+            directory_registered,
+            directory_posted_secs_ago,
+            bond_valid: bond_blocks_remaining.is_some_and(|blocks| blocks > 0),
+            bond_blocks_remaining,
+            setup_complete: self.is_setup_complete.load(Relaxed),
+        }
+    }
+}
+
+/// Runs the health HTTP server, until the Maker shuts down.
+pub(crate) fn start_health_server(maker: Arc<Maker>) -> Result<(), MakerError> {
+    let health_socket = format!("127.0.0.1:{}", maker.config.health_port);
+    let listener = TcpListener::bind(&health_socket)?;
+    log::info!(
+        "[{}] Health endpoint listening at {}",
+        maker.config.network_port,
+        health_socket
+    );
+
+    listener.set_nonblocking(true)?;
+
+    while !maker.shutdown.load(Relaxed) {
+        match listener.accept() {
+            Ok((mut stream, _)) => {
+                // The accepted socket may inherit the listener's non-blocking mode.
+                if let Err(e) = stream
+                    .set_nonblocking(false)
+                    .and_then(|_| stream.set_read_timeout(Some(Duration::from_secs(5))))
+                    .and_then(|_| stream.set_write_timeout(Some(Duration::from_secs(5))))
+                {
+                    log::error!("Error setting up health connection: {:?}", e);
+                    continue;
+                }
+                if let Err(e) = handle_health_request(&maker, &mut stream) {
+                    log::debug!("Error processing health request: {:?}", e);
+                }
+            }
+            Err(e) => {
+                if e.kind() != ErrorKind::WouldBlock {
+                    log::error!("Error accepting health connection: {:?}", e);
+                }
+            }
+        }
+
+        sleep(HEART_BEAT_INTERVAL);
+    }
+
+    Ok(())
+}
+
+fn handle_health_request(maker: &Maker, stream: &mut TcpStream) -> Result<(), MakerError> {
+    let mut buf = [0u8; 1024];
+    let read = stream.read(&mut buf)?;
+    let request = String::from_utf8_lossy(&buf[..read]);
+
+    let check: fn(&HealthReport) -> bool = match http_request_path(&request) {
+        Some("/health") => HealthReport::is_healthy,
+        Some("/ready") => HealthReport::is_ready,
+        _ => return send_response(stream, "404 Not Found", "text/plain", "Not found"),
+    };
+    let report = maker.health();
+    let status = if check(&report) {
+        "200 OK"
+    } else {
+        "503 Service Unavailable"
+    };
+    let body = serde_json::to_string(&report).map_err(io::Error::other)?;
+    send_response(stream, status, "application/json", &body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_health_report() {
+        let ready = HealthReport {
+            node_connected: true,
+            node_height: Some(850_000),
+            wallet_synced_height: Some(850_000),
+            tor_reachable: Some(true),
+            directory_registered: true,
+            directory_posted_secs_ago: Some(60),
+            bond_valid: true,
+            bond_blocks_remaining: Some(1000),
+            setup_complete: true,
+        };
+        assert!(ready.is_healthy() && ready.is_ready());
+
+        let clearnet = HealthReport {
+            tor_reachable: None,
+            ..ready.clone()
+        };
+        assert!(clearnet.is_ready());
+
+        let unregistered = HealthReport {
+            directory_registered: false,
+            ..ready.clone()
+        };
+        assert!(unregistered.is_healthy() && !unregistered.is_ready());
+
+        let tor_down = HealthReport {
+            tor_reachable: Some(false),
+            ..ready.clone()
+        };
+        assert!(!tor_down.is_healthy() && !tor_down.is_ready());
+
+        let node_down = HealthReport {
+            node_connected: false,
+            node_height: None,
+            ..ready
+        };
+        assert!(!node_down.is_healthy());
+    }
+}
@@ -0,0 +1,223 @@
+//! Profit and loss accounting of the Maker.
+//!
+//! Every completed swap is appended to `ledger.dat` in the Maker's data directory, with the fee earned, the
+//! miner fees spent on the outgoing funding txs and the capital locked in the swap. The `getearnings` RPC
+//! aggregates the ledger per day or per week. Entries keep the receipt of the swap countersigned by the Taker, as
+//! evidence of the service delivered.
+
+use std::{
+    fs::{read, File},
+    io::BufWriter,
+    path::Path,
+    str::FromStr,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::protocol::messages::SignedReceipt;
+
+use super::error::MakerError;
+
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// The unix epoch is a Thursday. Offset to start the weeks on Mondays.
+const WEEK_START_OFFSET: u64 = 3 * SECS_PER_DAY;
+
+/// Accounting of one completed swap.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LedgerEntry {
+    /// Unique id of the swap.
+    pub swap_id: String,
+    /// Unix timestamp of the completion of the swap.
+    pub completed_at: u64,
+    /// Amount received from the previous hop, in sats.
+    pub incoming_sats: u64,
+    /// Amount sent to the next hop, which stays locked in contracts until the swap completes, in sats.
+    pub capital_locked_sats: u64,
+    /// Miner fees of the outgoing funding txs, in sats.
+    pub miner_fees_sats: u64,
+    /// Receipt of the swap, signed by the Maker and countersigned by the Taker. None if the Taker didn't
+    /// countersign it.
+    #[serde(default)]
+    pub receipt: Option<SignedReceipt>,
+}
+
+impl LedgerEntry {
+    /// Gross fee earned: incoming minus outgoing amount.
+    pub fn fee_earned_sats(&self) -> u64 {
+        self.incoming_sats.saturating_sub(self.capital_locked_sats)
+    }
+
+    /// Net profit of the swap, after miner fees. Negative if the swap made a loss.
+    pub fn net_sats(&self) -> i64 {
+        self.fee_earned_sats() as i64 - self.miner_fees_sats as i64
+    }
+}
+
+/// Aggregation period of the earnings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum EarningsPeriod {
+    /// Per UTC day.
+    #[default]
+    Daily,
+    /// Per week, starting on Mondays.
+    Weekly,
+}
+
+impl EarningsPeriod {
+    /// Start of the period including `timestamp`.
+    fn period_start(&self, timestamp: u64) -> u64 {
+        match self {
+            Self::Daily => timestamp - timestamp % SECS_PER_DAY,
+            Self::Weekly => {
+                let shifted = timestamp + WEEK_START_OFFSET;
+                (shifted - shifted % (7 * SECS_PER_DAY)).saturating_sub(WEEK_START_OFFSET)
+            }
+        }
+    }
+}
+
+impl FromStr for EarningsPeriod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "daily" => Ok(Self::Daily),
+            "weekly" => Ok(Self::Weekly),
+            _ => Err(format!("Unknown earnings period: {}", s)),
+        }
+    }
+}
+
+/// Earnings of one period.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EarningsSummary {
+    /// Unix timestamp of the start of the period.
+    pub period_start: u64,
+    /// Number of completed swaps.
+    pub swaps: u32,
+    /// Total fees earned, in sats.
+    pub fees_earned_sats: u64,
+    /// Total miner fees spent, in sats.
+    pub miner_fees_sats: u64,
+    /// Net profit, in sats.
+    pub net_sats: i64,
+    /// Total capital locked in the swaps, in sats.
+    pub capital_locked_sats: u64,
+}
+
+/// The persistent ledger of completed swaps.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct Ledger {
+    entries: Vec<LedgerEntry>,
+}
+
+impl Ledger {
+    /// Reads the ledger from disk. Empty if the file doesn't exist yet.
+    pub(crate) fn read_from_disk(path: &Path) -> Result<Self, MakerError> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Ok(serde_cbor::from_slice(&read(path)?)?)
+    }
+
+    /// Writes the ledger to disk.
+    pub(crate) fn write_to_disk(&self, path: &Path) -> Result<(), MakerError> {
+        let writer = BufWriter::new(File::create(path)?);
+        Ok(serde_cbor::to_writer(writer, &self)?)
+    }
+
+    /// Appends entries and saves the ledger.
+    pub(crate) fn append(path: &Path, entries: Vec<LedgerEntry>) -> Result<(), MakerError> {
+        let mut ledger = Self::read_from_disk(path)?;
+        ledger.entries.extend(entries);
+        ledger.write_to_disk(path)
+    }
+
+    /// Earnings per period, oldest first. Periods without swaps are skipped.
+    pub(crate) fn earnings(&self, period: EarningsPeriod) -> Vec<EarningsSummary> {
+        let mut summaries: Vec<EarningsSummary> = Vec::new();
+        let mut entries = self.entries.iter().collect::<Vec<_>>();
+        entries.sort_by_key(|entry| entry.completed_at);
+
+        for entry in entries {
+            let period_start = period.period_start(entry.completed_at);
+            match summaries.last_mut() {
+                Some(summary) if summary.period_start == period_start => {
+                    summary.swaps += 1;
+                    summary.fees_earned_sats += entry.fee_earned_sats();
+                    summary.miner_fees_sats += entry.miner_fees_sats;
+                    summary.net_sats += entry.net_sats();
+                    summary.capital_locked_sats += entry.capital_locked_sats;
+                }
+                _ => summaries.push(EarningsSummary {
+                    period_start,
+                    swaps: 1,
+                    fees_earned_sats: entry.fee_earned_sats(),
+                    miner_fees_sats: entry.miner_fees_sats,
+                    net_sats: entry.net_sats(),
+                    capital_locked_sats: entry.capital_locked_sats,
+                }),
+            }
+        }
+        summaries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(completed_at: u64, incoming: u64, outgoing: u64, miner_fees: u64) -> LedgerEntry {
+        LedgerEntry {
+            swap_id: completed_at.to_string(),
+            completed_at,
+            incoming_sats: incoming,
+            capital_locked_sats: outgoing,
+            miner_fees_sats: miner_fees,
+            receipt: None,
+        }
+    }
+
+    #[test]
+    fn test_earnings_aggregation() {
+        // 2024-01-01 was a Monday.
+        let monday = 1_704_067_200;
+        let ledger = Ledger {
+            entries: vec![
+                entry(monday + 2 * SECS_PER_DAY, 100_000, 97_000, 1_000),
+                entry(monday + 100, 100_000, 98_000, 1_000),
+                entry(monday + 200, 50_000, 49_500, 1_000),
+                entry(monday + 8 * SECS_PER_DAY, 200_000, 195_000, 2_000),
+            ],
+        };
+        assert_eq!(ledger.entries[2].net_sats(), -500);
+
+        let daily = ledger.earnings(EarningsPeriod::Daily);
+        assert_eq!(
+            daily
+                .iter()
+                .map(|s| (s.period_start, s.swaps, s.net_sats))
+                .collect::<Vec<_>>(),
+            vec![
+                (monday, 2, 500),
+                (monday + 2 * SECS_PER_DAY, 1, 2_000),
+                (monday + 8 * SECS_PER_DAY, 1, 3_000),
+            ]
+        );
+
+        let weekly = ledger.earnings(EarningsPeriod::Weekly);
+        assert_eq!(weekly.len(), 2);
+        assert_eq!(weekly[0].period_start, monday);
+        assert_eq!(weekly[0].swaps, 3);
+        assert_eq!(weekly[0].fees_earned_sats, 5_500);
+        assert_eq!(weekly[0].miner_fees_sats, 3_000);
+        assert_eq!(weekly[0].capital_locked_sats, 244_500);
+        assert_eq!(weekly[1].period_start, monday + 7 * SECS_PER_DAY);
+
+        assert_eq!(
+            "Weekly".parse::<EarningsPeriod>().unwrap(),
+            EarningsPeriod::Weekly
+        );
+    }
+}
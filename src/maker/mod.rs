@@ -14,10 +14,26 @@ mod api;
 mod config;
 mod error;
 mod handlers;
+mod health;
+mod ledger;
+#[cfg(feature = "nostr")]
+mod nostr;
+mod payjoin;
+mod rebalance;
 mod rpc;
+mod schedule;
 mod server;
+mod traffic;
+#[cfg(feature = "tui")]
+mod tui;
 
 pub use api::{Maker, MakerBehavior};
 pub use error::MakerError;
-pub use rpc::{RpcMsgReq, RpcMsgResp};
+pub use health::HealthReport;
+pub use ledger::{EarningsPeriod, EarningsSummary, LedgerEntry};
+pub use rpc::{ContractSummary, RpcMsgReq, RpcMsgResp, SwapSummary};
+pub use schedule::LiquidityWindow;
 pub use server::start_maker_server;
+pub use traffic::{TrafficReport, TrafficStats};
+#[cfg(feature = "tui")]
+pub use tui::run_dashboard;
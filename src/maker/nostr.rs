@@ -0,0 +1,90 @@
+//! Publication of the Maker's offer to Nostr relays.
+//!
+//! With `nostr_relays` in the config, the offer is published to each relay as a replaceable event, see
+//! [crate::nostr], and republished at half the offer TTL, before it expires. A failing relay is retried at the
+//! next round. Nothing is published while draining, so the offer expires from the relays.
+
+use std::{
+    sync::{atomic::Ordering::Relaxed, Arc},
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use bitcoin::{
+    key::{Keypair, Secp256k1},
+    secp256k1::rand::thread_rng,
+};
+
+use crate::{
+    nostr::{offer_event, publish_event, NostrEvent},
+    taker::offers::{MakerAddress, OfferAndAddress},
+    utill::{unix_time, HEART_BEAT_INTERVAL},
+};
+
+use super::{error::MakerError, Maker};
+
+/// Publishes the offer to the relays of the config, until the Maker shuts down.
+pub(crate) fn start_nostr_publisher(maker: Arc<Maker>) -> Result<(), MakerError> {
+    let port = maker.config.network_port;
+    let keypair = Keypair::new(&Secp256k1::new(), &mut thread_rng());
+    let publish_interval = Duration::from_secs(maker.config.offer_ttl_secs / 2);
+
+    let mut last_publish: Option<Instant> = None;
+    let mut published_until = 0;
+    while !maker.shutdown.load(Relaxed) {
+        sleep(HEART_BEAT_INTERVAL);
+
+        // Outside the liquidity windows, the published offer expires. Published again once a window opens.
+        if maker.draining.load(Relaxed)
+            || !maker.is_setup_complete.load(Relaxed)
+            || matches!(maker.in_liquidity_window(), Ok(false))
+        {
+            last_publish = None;
+            continue;
+        }
+        if matches!(last_publish, Some(t) if t.elapsed() < publish_interval)
+            && unix_time() < published_until
+        {
+            continue;
+        }
+        last_publish = Some(Instant::now());
+
+        let event = match nostr_offer_event(&maker, &keypair) {
+            Ok((event, expires_at)) => {
+                published_until = expires_at;
+                event
+            }
+            Err(e) => {
+                log::error!("[{}] Failed creating the offer event: {:?}", port, e);
+                continue;
+            }
+        };
+        for relay in &maker.config.nostr_relays {
+            match publish_event(relay, maker.config.tor_socks_port(), &event) {
+                Ok(()) => log::info!("[{}] Offer published to relay {}", port, relay),
+                Err(e) => log::warn!(
+                    "[{}] Failed publishing the offer to relay {}: {}",
+                    port,
+                    relay,
+                    e
+                ),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The event of our current offer, and its expiry.
+fn nostr_offer_event(maker: &Maker, keypair: &Keypair) -> Result<(NostrEvent, u64), MakerError> {
+    let offer = OfferAndAddress {
+        offer: maker.offer()?,
+        address: MakerAddress::new(&maker.advertised_address.read()?)
+            .map_err(|_| MakerError::General("Invalid advertised address"))?,
+    };
+    let network = maker.get_wallet().read()?.store.network;
+    let expires_at = offer.offer.expires_at;
+    let event = offer_event(keypair, &offer, network, expires_at)
+        .map_err(|_| MakerError::General("Failed encoding the offer"))?;
+    Ok((event, expires_at))
+}
@@ -0,0 +1,242 @@
+//! BIP78 payjoin endpoint for funding the Maker wallet.
+//!
+//! The operator hands out a payjoin URI (see `maker-cli get-payjoin-uri`), and the sender's wallet posts
+//! its original PSBT here. The Maker answers with a proposal contributing one of its own utxos, so the
+//! deposit doesn't look like a plain payment into the maker wallet.
+//!
+//! The endpoint only listens on localhost. It should be exposed to senders via a reverse proxy or an
+//! onion service.
+
+use std::{
+    io::{ErrorKind, Read},
+    net::{TcpListener, TcpStream},
+    sync::{atomic::Ordering::Relaxed, Arc},
+    thread::{self, sleep},
+    time::{Duration, Instant},
+};
+
+use bitcoin::{OutPoint, Psbt, Transaction};
+
+use crate::utill::{send_http_response, DeadlineReader, HEART_BEAT_INTERVAL};
+
+use super::{error::MakerError, Maker};
+
+/// Maximum accepted size of a request.
+const MAX_REQUEST_SIZE: usize = 100_000;
+
+/// Time given to the sender to send its whole request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Time given to the sender to broadcast the payjoin, before the original transaction is broadcasted.
+const FALLBACK_BROADCAST_DELAY: Duration = Duration::from_secs(120);
+
+/// Path of the payjoin endpoint.
+pub(crate) const PAYJOIN_PATH: &str = "/payjoin";
+
+/// Runs the payjoin HTTP server, until the Maker shuts down.
+pub(crate) fn start_payjoin_server(maker: Arc<Maker>) -> Result<(), MakerError> {
+    let payjoin_socket = format!("127.0.0.1:{}", maker.config.payjoin_port);
+    let listener = TcpListener::bind(&payjoin_socket)?;
+    log::info!(
+        "[{}] Payjoin endpoint listening at {}",
+        maker.config.network_port,
+        payjoin_socket
+    );
+
+    listener.set_nonblocking(true)?;
+
+    while !maker.shutdown.load(Relaxed) {
+        match listener.accept() {
+            Ok((mut stream, addr)) => {
+                log::info!("Got payjoin request from: {}", addr);
+                // The accepted socket may inherit the listener's non-blocking mode.
+                if let Err(e) = stream
+                    .set_nonblocking(false)
+                    .and_then(|_| stream.set_write_timeout(Some(Duration::from_secs(20))))
+                {
+                    log::error!("Error setting up payjoin connection: {:?}", e);
+                    continue;
+                }
+                if let Err(e) = handle_payjoin_request(&maker, &mut stream) {
+                    log::error!("Error processing payjoin request: {:?}", e);
+                }
+            }
+            Err(e) => {
+                if e.kind() != ErrorKind::WouldBlock {
+                    log::error!("Error accepting payjoin connection: {:?}", e);
+                }
+            }
+        }
+
+        sleep(HEART_BEAT_INTERVAL);
+    }
+
+    Ok(())
+}
+
+fn handle_payjoin_request(maker: &Arc<Maker>, stream: &mut TcpStream) -> Result<(), MakerError> {
+    let body = match read_post_body(stream, Instant::now() + REQUEST_TIMEOUT)? {
+        Some(body) => body,
+        None => return send_error(stream, "original-psbt-rejected", "Invalid request"),
+    };
+
+    let original = match body.trim().parse::<Psbt>() {
+        Ok(psbt) => psbt,
+        Err(_) => return send_error(stream, "original-psbt-rejected", "Invalid PSBT"),
+    };
+
+    let proposal = match maker
+        .get_wallet()
+        .write()?
+        .create_payjoin_proposal(original)
+    {
+        Ok(proposal) => proposal,
+        Err(e) => {
+            log::warn!("Rejected payjoin request: {:?}", e);
+            return send_error(stream, "original-psbt-rejected", "Can't create a proposal");
+        }
+    };
+
+    schedule_fallback_broadcast(maker.clone(), proposal.original_tx, proposal.input)?;
+
+    send_response(stream, "200 OK", "text/plain", &proposal.psbt.to_string())
+}
+
+/// Broadcasts the original transaction if the sender doesn't broadcast the payjoin in time.
+/// If the payjoin got broadcasted, the original is a double spend and simply gets rejected.
+/// Either way, our payjoin input is released.
+fn schedule_fallback_broadcast(
+    maker: Arc<Maker>,
+    original_tx: Transaction,
+    payjoin_input: OutPoint,
+) -> Result<(), MakerError> {
+    let maker_clone = maker.clone();
+    let handle = thread::Builder::new()
+        .name("Payjoin Fallback Thread".to_string())
+        .spawn(move || {
+            let start = Instant::now();
+            while start.elapsed() < FALLBACK_BROADCAST_DELAY {
+                if maker_clone.shutdown.load(Relaxed) {
+                    return;
+                }
+                sleep(HEART_BEAT_INTERVAL);
+            }
+            let mut wallet = match maker_clone.get_wallet().write() {
+                Ok(wallet) => wallet,
+                Err(_) => return,
+            };
+            match wallet.send_tx(&original_tx) {
+                Ok(txid) => log::info!("Broadcasted payjoin fallback transaction: {}", txid),
+                Err(e) => log::debug!("Payjoin fallback not broadcasted: {:?}", e),
+            }
+            wallet.release_payjoin_input(payjoin_input);
+        })?;
+    maker.thread_pool.add_thread(handle);
+    Ok(())
+}
+
+/// Reads an HTTP POST request, returning its body.
+///
+/// The whole request must be received before the `deadline`, so a sender trickling bytes can't hold the endpoint.
+fn read_post_body(stream: &mut TcpStream, deadline: Instant) -> Result<Option<String>, MakerError> {
+    let mut reader = DeadlineReader {
+        reader: stream,
+        deadline,
+    };
+    let mut request = Vec::new();
+    let mut buf = [0u8; 4096];
+    let header_end = loop {
+        let read = reader.read(&mut buf)?;
+        if read == 0 {
+            return Ok(None);
+        }
+        request.extend_from_slice(&buf[..read]);
+        if let Some(pos) = request.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos + 4;
+        }
+        if request.len() > MAX_REQUEST_SIZE {
+            return Ok(None);
+        }
+    };
+
+    let headers = String::from_utf8_lossy(&request[..header_end]).to_string();
+    let mut lines = headers.lines();
+    let request_line = lines.next().unwrap_or_default();
+    let mut parts = request_line.split_whitespace();
+    if parts.next() != Some("POST")
+        || !parts
+            .next()
+            .is_some_and(|target| target.starts_with(PAYJOIN_PATH))
+    {
+        return Ok(None);
+    }
+
+    let content_length = lines
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.trim().parse::<usize>().ok());
+    let content_length = match content_length {
+        Some(len) if len <= MAX_REQUEST_SIZE => len,
+        _ => return Ok(None),
+    };
+
+    let mut body = request[header_end..].to_vec();
+    while body.len() < content_length {
+        let read = reader.read(&mut buf)?;
+        if read == 0 {
+            return Ok(None);
+        }
+        body.extend_from_slice(&buf[..read]);
+    }
+    body.truncate(content_length);
+
+    Ok(String::from_utf8(body).ok())
+}
+
+fn send_error(stream: &mut TcpStream, code: &str, message: &str) -> Result<(), MakerError> {
+    let body = serde_json::json!({
+        "errorCode": code,
+        "message": message,
+    })
+    .to_string();
+    send_response(stream, "400 Bad Request", "application/json", &body)
+}
+
+pub(super) fn send_response(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &str,
+) -> Result<(), MakerError> {
+    Ok(send_http_response(stream, status, content_type, body)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_read_post_body_deadline() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let writer = thread::spawn(move || {
+            let mut stream = TcpStream::connect(address).unwrap();
+            // Trickle the request, one byte at a time.
+            for byte in b"POST /payjoin HTTP/1.1\r\n" {
+                if stream.write_all(&[*byte]).is_err() {
+                    return;
+                }
+                sleep(Duration::from_millis(100));
+            }
+        });
+
+        let (mut socket, _) = listener.accept().unwrap();
+        let deadline = Instant::now() + Duration::from_millis(500);
+        assert!(read_post_body(&mut socket, deadline).is_err());
+        assert!(Instant::now() < deadline + Duration::from_millis(500));
+        drop(socket);
+        writer.join().unwrap();
+    }
+}
@@ -0,0 +1,243 @@
+//! Maker as Taker: auto-rebalancing of the swap liquidity.
+//!
+//! Incoming swap coins pile up in the Maker's wallet as it serves swaps. Spending many of them together
+//! links the swaps they came from. When enabled, the rebalancer moves the swap coins into a separate Taker
+//! wallet, swaps them through other makers, and moves the fresh coins back into the Maker's wallet. Every coin
+//! is moved in and out in its own transaction, so the moves don't link the coins either.
+//!
+//! A rebalance is triggered when the swap coin balance reaches `rebalance_swap_balance` sats, or when the
+//! number of swap coins reaches `rebalance_max_swapcoins`. Its swap fee is capped at `rebalance_fee_budget`
+//! sats, and it runs at most once every `rebalance_interval` seconds. Nothing is rebalanced while the
+//! Maker has ongoing swaps, or is draining for shutdown.
+
+use std::{
+    sync::{atomic::Ordering::Relaxed, Arc},
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use bitcoin::{Amount, FeeRate, Txid};
+
+use crate::{
+    taker::{SwapParams, Taker, TakerBehavior},
+    utill::HEART_BEAT_INTERVAL,
+    wallet::{UTXOSpendInfo, Wallet, WalletError},
+};
+
+use super::{api::Maker, config::MakerConfig, error::MakerError};
+
+/// Number of makers in the route of a rebalancing swap.
+const REBALANCE_MAKER_COUNT: usize = 2;
+
+/// Confirmation target of the moves in and out of the rebalancing wallet, in blocks.
+const TRANSFER_CONF_TARGET: u16 = 6;
+
+/// Feerate of the moves when there is no estimate, in sats/vB.
+const FALLBACK_TRANSFER_FEERATE: u32 = 2;
+
+/// Whether the Maker's swap coins call for a rebalance. Triggers set to 0 are disabled.
+fn needs_rebalance(config: &MakerConfig, swapcoin_count: usize, swap_balance: Amount) -> bool {
+    (config.rebalance_swap_balance != 0 && swap_balance.to_sat() >= config.rebalance_swap_balance)
+        || (config.rebalance_max_swapcoins != 0
+            && swapcoin_count as u32 >= config.rebalance_max_swapcoins)
+}
+
+/// Checks the swap coins periodically and rebalances them when needed, until the Maker shuts down.
+pub(crate) fn start_rebalancer(maker: Arc<Maker>) -> Result<(), MakerError> {
+    let check_interval = if cfg!(feature = "integration-test") {
+        Duration::from_secs(5)
+    } else {
+        Duration::from_secs(10 * 60)
+    };
+    let rebalance_interval = Duration::from_secs(maker.config.rebalance_interval);
+
+    let mut last_check: Option<Instant> = None;
+    let mut last_rebalance: Option<Instant> = None;
+
+    while !maker.shutdown.load(Relaxed) {
+        sleep(HEART_BEAT_INTERVAL);
+
+        if matches!(last_check, Some(t) if t.elapsed() < check_interval)
+            || matches!(last_rebalance, Some(t) if t.elapsed() < rebalance_interval)
+        {
+            continue;
+        }
+        last_check = Some(Instant::now());
+
+        if maker.draining.load(Relaxed) || !maker.ongoing_swap_state.lock()?.is_empty() {
+            continue;
+        }
+
+        let (swapcoin_count, swap_balance) = {
+            let wallet = maker.get_wallet().read()?;
+            let utxos = wallet.get_all_locked_utxo()?;
+            let swapcoins = wallet.list_incoming_swap_coin_utxo_spend_info(Some(&utxos))?;
+            (
+                swapcoins.len(),
+                swapcoins
+                    .iter()
+                    .map(|(utxo, _)| utxo.amount)
+                    .sum::<Amount>(),
+            )
+        };
+        if !needs_rebalance(&maker.config, swapcoin_count, swap_balance) {
+            continue;
+        }
+
+        log::info!(
+            "[{}] Rebalancing {} swap coins worth {}",
+            maker.config.network_port,
+            swapcoin_count,
+            swap_balance
+        );
+        last_rebalance = Some(Instant::now());
+        match rebalance(&maker) {
+            Ok(()) => log::info!("[{}] Rebalancing completed", maker.config.network_port),
+            Err(e) => log::error!(
+                "[{}] Rebalancing failed: {:?}",
+                maker.config.network_port,
+                e
+            ),
+        }
+    }
+
+    Ok(())
+}
+
+/// Swaps all the swap coins of the Maker through other makers, and moves the result back into its wallet.
+fn rebalance(maker: &Maker) -> Result<(), MakerError> {
+    let mut taker = Taker::init(
+        Some(maker.data_dir.join("rebalance")),
+        Some(format!("{}-rebalance", maker.rpc_config.wallet_name)),
+        Some(maker.rpc_config.clone()),
+        TakerBehavior::Normal,
+        Some(maker.config.connection_type),
+    )?;
+    taker.config.directory_server_address = maker.config.directory_server_address.clone();
+    taker.config.socks_port = maker.config.socks_port;
+    // Never route the swap through ourselves.
+    taker
+        .config
+        .excluded_address_prefixes
+        .push(own_address(maker)?);
+
+    let transfers = {
+        let mut wallet = maker.get_wallet().write()?;
+        let utxos = wallet.get_all_locked_utxo()?;
+        let swapcoins = wallet.list_incoming_swap_coin_utxo_spend_info(Some(&utxos))?;
+        let destinations = taker
+            .get_wallet()
+            .get_next_internal_addresses(swapcoins.len() as u32)?;
+        let feerate = transfer_feerate(&wallet)?;
+        let txs = wallet.transfer_coins(swapcoins, destinations, feerate)?;
+        txs.iter()
+            .map(|tx| wallet.send_tx(tx))
+            .collect::<Result<Vec<_>, _>>()?
+    };
+    log::info!(
+        "Moved the swap coins to the rebalancing wallet: {:?}",
+        transfers
+    );
+    for txid in &transfers {
+        wait_for_confirmation(maker, txid)?;
+    }
+    taker.get_wallet_mut().sync()?;
+
+    // Coins left over by an earlier failed rebalance are swapped along.
+    let mut swap_params = SwapParams::sweep(REBALANCE_MAKER_COUNT, 1);
+    swap_params.max_total_fee_sats = Some(maker.config.rebalance_fee_budget);
+    let swap_result = taker.do_coinswap(swap_params);
+
+    // Move back whatever the wallet holds, even if the swap failed.
+    let wallet = taker.get_wallet_mut();
+    wallet.sync()?;
+    let coins = wallet
+        .list_all_utxo_spend_info(None)?
+        .into_iter()
+        .filter(|(_, info)| {
+            matches!(
+                info,
+                UTXOSpendInfo::SeedCoin { .. } | UTXOSpendInfo::IncomingSwapCoin { .. }
+            )
+        })
+        .collect::<Vec<_>>();
+    let destinations = {
+        let mut maker_wallet = maker.get_wallet().write()?;
+        let script_type = maker_wallet.script_type();
+        (0..coins.len())
+            .map(|_| maker_wallet.reserve_internal_address(script_type))
+            .collect::<Result<Vec<_>, _>>()?
+    };
+    let feerate = transfer_feerate(wallet)?;
+    for tx in wallet.transfer_coins(coins, destinations, feerate)? {
+        let txid = wallet.send_tx(&tx)?;
+        log::info!("Moved a rebalanced coin back to the Maker wallet: {}", txid);
+    }
+
+    swap_result?;
+    Ok(())
+}
+
+/// Feerate of the moves in and out of the rebalancing wallet. They aren't urgent.
+fn transfer_feerate(wallet: &Wallet) -> Result<FeeRate, WalletError> {
+    Ok(match wallet.estimate_feerate(TRANSFER_CONF_TARGET)? {
+        // 1 vB is 4 weight units.
+        Some(feerate) => FeeRate::from_sat_per_kwu((feerate * 250.0) as u64),
+        None => FeeRate::from_sat_per_vb_u32(FALLBACK_TRANSFER_FEERATE),
+    })
+}
+
+/// Address the Maker advertises to the directory.
+fn own_address(maker: &Maker) -> Result<String, MakerError> {
+    Ok(maker.advertised_address.read()?.clone())
+}
+
+/// Blocks until the tx has a confirmation. Errors if the Maker shuts down in the meantime.
+fn wait_for_confirmation(maker: &Maker, txid: &Txid) -> Result<(), MakerError> {
+    let sleep_interval = if cfg!(feature = "integration-test") {
+        Duration::from_secs(1)
+    } else {
+        Duration::from_secs(30)
+    };
+    while !maker.shutdown.load(Relaxed) {
+        let confirmations = maker.get_wallet().read()?.chain.tx_confirmations(txid)?;
+        if confirmations.unwrap_or(0) > 0 {
+            return Ok(());
+        }
+        sleep(sleep_interval);
+    }
+    Err(MakerError::General(
+        "Shutdown while waiting for the rebalancing transfer",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_needs_rebalance() {
+        let disabled = MakerConfig::default();
+        assert!(!disabled.rebalance_enabled());
+        assert!(!needs_rebalance(&disabled, 100, Amount::ONE_BTC));
+
+        let by_balance = MakerConfig {
+            rebalance_swap_balance: 1_000_000,
+            ..MakerConfig::default()
+        };
+        assert!(by_balance.rebalance_enabled());
+        assert!(!needs_rebalance(
+            &by_balance,
+            100,
+            Amount::from_sat(999_999)
+        ));
+        assert!(needs_rebalance(&by_balance, 1, Amount::from_sat(1_000_000)));
+
+        let by_count = MakerConfig {
+            rebalance_max_swapcoins: 10,
+            ..MakerConfig::default()
+        };
+        assert!(!needs_rebalance(&by_count, 9, Amount::ONE_BTC));
+        assert!(needs_rebalance(&by_count, 10, Amount::from_sat(1)));
+    }
+}
@@ -1,12 +1,15 @@
 use std::{collections::HashMap, fmt::Display};
 
-use bitcoin::Txid;
+use bitcoin::{OutPoint, PublicKey, Txid};
 use bitcoind::bitcoincore_rpc::json::ListUnspentResultEntry;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, to_string_pretty};
 use std::path::PathBuf;
 
-use crate::wallet::{Balances, FidelityBond};
+use crate::{
+    maker::{EarningsPeriod, EarningsSummary, HealthReport, TrafficReport},
+    wallet::{Balances, FidelityBond, ReusedAddress, StuckDeposit, WalletTransaction},
+};
 
 /// Enum representing RPC message requests.
 ///
@@ -16,6 +19,8 @@ use crate::wallet::{Balances, FidelityBond};
 pub enum RpcMsgReq {
     /// Ping request to check connectivity.
     Ping,
+    /// Request for the health and readiness of the Maker.
+    Health,
     /// Request to fetch all utxos in the wallet.
     Utxo,
     /// Request to fetch only swap utxos in the wallet.
@@ -28,6 +33,8 @@ pub enum RpcMsgReq {
     Balances,
     /// Request for generating a new wallet address.
     NewAddress,
+    /// Request for a payjoin URI, paying to a new wallet address.
+    NewPayjoinUri,
     /// Request to send funds to a specific address.
     SendToAddress {
         /// The recipient's address.
@@ -41,14 +48,79 @@ pub enum RpcMsgReq {
     GetTorAddress,
     /// Request to retrieve the data directory path.
     GetDataDir,
+    /// Request to rotate the Tor address of the Maker, once the ongoing swaps are finished.
+    RotateAddress,
+    /// Request to retrieve the identity key of the Maker.
+    GetIdentity,
+    /// Request to move the Maker to a new identity key, keeping its reputation.
+    RotateIdentity,
+    /// Request to reload the fees, size limits and banned peers from the config file.
+    ReloadConfig,
     /// Request to stop the Maker server.
     Stop,
+    /// Request to stop taking new swaps, and stop the Maker server once the ongoing swaps are finished.
+    Drain,
     /// Request to reddem a fidelity bond for a given index.
     RedeemFidelity(u32),
     /// Request to list all active and past fidelity bonds.
     ListFidelity,
     /// Request to sync the internal wallet with blockchain.
     SyncWallet,
+    /// Request for the earnings of the completed swaps, aggregated per period.
+    GetEarnings(EarningsPeriod),
+    /// Request for the traffic exchanged with takers, per peer and per swap.
+    GetTraffic,
+    /// Request to list the ongoing swaps, with their phase, contracts and timelocks.
+    ListSwaps,
+    /// Request to list the wallet addresses which received coins more than once.
+    ListReused,
+    /// Request to list the wallet transactions, newest first.
+    ListTransactions,
+    /// Request to attach a memo to a transaction or a swap. An empty memo removes it.
+    SetMemo {
+        /// Txid of the transaction, or id of the swap.
+        target: String,
+        /// Text of the memo.
+        memo: String,
+    },
+    /// Request to freeze a wallet UTXO, excluding it from the automatic coin selections.
+    FreezeUtxo {
+        /// The wallet outpoint, as `txid:vout`.
+        outpoint: String,
+    },
+    /// Request to unfreeze a frozen UTXO.
+    UnfreezeUtxo {
+        /// The wallet outpoint, as `txid:vout`.
+        outpoint: String,
+    },
+    /// Request to list the frozen UTXOs.
+    ListFrozen,
+    /// Request to bump the fee of an unconfirmed transaction, by spending one of its outputs (CPFP).
+    Cpfp {
+        /// The wallet outpoint to spend, as `txid:vout`.
+        outpoint: String,
+        /// Target feerate of the package, in sats/vbyte.
+        feerate: u64,
+    },
+    /// Request to move swap coins into the regular balance, each to its own fresh internal address.
+    TransferSwapCoins {
+        /// The swap coins to transfer, as `txid:vout`.
+        outpoints: Vec<String>,
+        /// Feerate of each transfer, in sats/vbyte.
+        feerate: u64,
+        /// Most seconds to wait, at random, before broadcasting each transfer. 0 broadcasts them right away.
+        max_delay_secs: u64,
+    },
+    /// Request to list the unconfirmed deposits whose parent tx pays below a feerate.
+    ListStuckDeposits {
+        /// Target feerate, in sats/vbyte.
+        feerate: u64,
+    },
+    /// Request to bump the stuck deposits to a feerate, each by spending it to the wallet (CPFP).
+    BumpDeposits {
+        /// Target feerate of each parent + child package, in sats/vbyte.
+        feerate: u64,
+    },
 }
 
 /// Enum representing RPC message responses.
@@ -59,6 +131,8 @@ pub enum RpcMsgReq {
 pub enum RpcMsgResp {
     /// Response to a Ping request.
     Pong,
+    /// Response with the health and readiness of the Maker.
+    HealthResp(HealthReport),
     /// Response containing all spendable UTXOs
     UtxoResp {
         /// List of spndable UTXOs in the wallet.
@@ -83,27 +157,105 @@ pub enum RpcMsgResp {
     TotalBalanceResp(Balances),
     /// Response containing a newly generated wallet address.
     NewAddressResp(String),
+    /// Response containing a payjoin URI.
+    PayjoinUriResp(String),
     /// Response to a send-to-address request.
     SendToAddressResp(String),
     /// Response containing the Tor address of the Maker.
     GetTorAddressResp(String),
     /// Response containing the path to the data directory.
     GetDataDirResp(PathBuf),
+    /// Response indicating the address rotation is scheduled.
+    RotateAddressResp(String),
+    /// Response with the identity key of the Maker.
+    IdentityResp(PublicKey),
+    /// Response with the reloaded policy.
+    ReloadConfigResp(String),
     /// Response indicating the server has been shut down.
     Shutdown,
+    /// Response indicating the server is draining, with the number of ongoing swaps.
+    Draining(usize),
     /// Response with the fidelity spending txid.
     FidelitySpend(Txid),
-    /// Response with the internal server error.
-    ServerError(String),
+    /// Response with the internal server error, and its stable [ErrorCode](crate::error::ErrorCode).
+    ServerError {
+        /// Code of the error.
+        code: u16,
+        /// Description of the error.
+        message: String,
+    },
     /// Response listing all current and past fidelity bonds.
     ListBonds(HashMap<u32, (FidelityBond, bool)>),
+    /// Response with the CPFP child txid.
+    CpfpResp(Txid),
+    /// Response with the earnings per period, oldest first.
+    EarningsResp(Vec<EarningsSummary>),
+    /// Response with the traffic exchanged with takers.
+    TrafficResp(TrafficReport),
+    /// Response listing the ongoing swaps.
+    ListSwapsResp(Vec<SwapSummary>),
+    /// Response listing the reused addresses.
+    ListReusedResp(Vec<ReusedAddress>),
+    /// Response listing the wallet transactions.
+    ListTransactionsResp(Vec<WalletTransaction>),
+    /// Response with the memo set, and its target.
+    MemoResp(String),
+    /// Response with the UTXO frozen or unfrozen.
+    FreezeResp(String),
+    /// Response listing the frozen outpoints.
+    ListFrozenResp(Vec<OutPoint>),
+    /// Response with the txids of the transfers, broadcast or scheduled.
+    TransferResp(Vec<Txid>),
+    /// Response listing the stuck deposits.
+    StuckDepositsResp(Vec<StuckDeposit>),
+    /// Response with the txids of the CPFP children broadcast.
+    BumpDepositsResp(Vec<Txid>),
+}
+
+/// An ongoing swap of the Maker, as listed by [RpcMsgReq::ListSwaps].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SwapSummary {
+    /// Swap id.
+    pub id: String,
+    /// Next protocol message the Maker waits for.
+    pub phase: String,
+    /// Seconds since the swap last progressed.
+    pub idle_secs: u64,
+    /// Amount received from the previous hop, in sats.
+    pub incoming_amount_sats: u64,
+    /// Amount sent to the next hop, in sats.
+    pub outgoing_amount_sats: u64,
+    /// Contracts with the previous hop, where we hold the hashlock.
+    pub incoming: Vec<ContractSummary>,
+    /// Contracts with the next hop, where we hold the timelock.
+    pub outgoing: Vec<ContractSummary>,
+    /// Our funding txs of the next hop, not broadcast yet.
+    pub pending_funding_txids: Vec<Txid>,
+}
+
+/// A contract of an ongoing swap.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ContractSummary {
+    /// Multisig pubkey of the counterparty.
+    pub counterparty: PublicKey,
+    /// The 2of2 multisig funding output.
+    pub funding_outpoint: OutPoint,
+    /// Amount locked in the multisig, in sats.
+    pub amount_sats: u64,
+    /// Txid of the contract transaction.
+    pub contract_txid: Txid,
+    /// Refund timelock, in blocks after the contract transaction confirms.
+    pub timelock: u16,
 }
 
 impl Display for RpcMsgResp {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::Pong => write!(f, "Pong"),
+            Self::HealthResp(report) => write!(f, "{}", to_string_pretty(report).unwrap()),
+            Self::TrafficResp(report) => write!(f, "{}", to_string_pretty(report).unwrap()),
             Self::NewAddressResp(addr) => write!(f, "{}", addr),
+            Self::PayjoinUriResp(uri) => write!(f, "{}", uri),
             Self::TotalBalanceResp(balances) => {
                 write!(
                     f,
@@ -113,6 +265,7 @@ impl Display for RpcMsgResp {
                         "swap": balances.swap.to_sat(),
                         "contract": balances.contract.to_sat(),
                         "fidelity": balances.fidelity.to_sat(),
+                        "frozen": balances.frozen.to_sat(),
                         "spendable": balances.spendable.to_sat(),
                     }))
                     .unwrap()
@@ -125,10 +278,35 @@ impl Display for RpcMsgResp {
             Self::SendToAddressResp(tx_hex) => write!(f, "{}", tx_hex),
             Self::GetTorAddressResp(addr) => write!(f, "{}", addr),
             Self::GetDataDirResp(path) => write!(f, "{}", path.display()),
+            Self::RotateAddressResp(msg) => write!(f, "{}", msg),
+            Self::IdentityResp(identity) => write!(f, "{}", identity),
+            Self::ReloadConfigResp(msg) => write!(f, "{}", msg),
             Self::Shutdown => write!(f, "Shutdown Initiated"),
+            Self::Draining(swaps) => write!(
+                f,
+                "Draining initiated, shutting down after {} ongoing swaps",
+                swaps
+            ),
             Self::FidelitySpend(txid) => write!(f, "{}", txid),
-            Self::ServerError(e) => write!(f, "{}", e),
+            Self::ServerError { code, message } => write!(f, "Error {}: {}", code, message),
             Self::ListBonds(v) => write!(f, "{:#?}", v),
+            Self::CpfpResp(txid) => write!(f, "{}", txid),
+            Self::ListSwapsResp(swaps) => write!(f, "{}", to_string_pretty(swaps).unwrap()),
+            Self::ListReusedResp(reused) => write!(f, "{}", to_string_pretty(reused).unwrap()),
+            Self::ListTransactionsResp(txs) => write!(f, "{}", to_string_pretty(txs).unwrap()),
+            Self::MemoResp(memo) => write!(f, "{}", memo),
+            Self::FreezeResp(msg) => write!(f, "{}", msg),
+            Self::ListFrozenResp(outpoints) => {
+                write!(f, "{}", to_string_pretty(outpoints).unwrap())
+            }
+            Self::TransferResp(txids) => write!(f, "{}", to_string_pretty(txids).unwrap()),
+            Self::StuckDepositsResp(deposits) => {
+                write!(f, "{}", to_string_pretty(deposits).unwrap())
+            }
+            Self::BumpDepositsResp(txids) => write!(f, "{}", to_string_pretty(txids).unwrap()),
+            Self::EarningsResp(earnings) => {
+                write!(f, "{}", to_string_pretty(earnings).unwrap())
+            }
         }
     }
 }
@@ -1,5 +1,5 @@
 mod messages;
 mod server;
 
-pub use messages::{RpcMsgReq, RpcMsgResp};
+pub use messages::{ContractSummary, RpcMsgReq, RpcMsgResp, SwapSummary};
 pub(crate) use server::start_rpc_server;
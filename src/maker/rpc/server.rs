@@ -1,28 +1,89 @@
 use std::{
+    fmt::Debug,
     io::ErrorKind,
     net::{TcpListener, TcpStream},
     sync::{atomic::Ordering::Relaxed, Arc},
-    thread::sleep,
-    time::Duration,
+    thread::{self, sleep},
+    time::{Duration, Instant},
 };
 
-use bitcoin::{Address, Amount};
+use bitcoin::{Address, Amount, FeeRate, OutPoint, PublicKey, Transaction};
 
 use super::messages::RpcMsgReq;
 use crate::{
-    maker::{error::MakerError, rpc::messages::RpcMsgResp, Maker},
+    error::ErrorCode,
+    maker::{
+        error::MakerError,
+        ledger::Ledger,
+        payjoin::PAYJOIN_PATH,
+        rpc::messages::{ContractSummary, RpcMsgResp, SwapSummary},
+        Maker,
+    },
+    protocol::wire::decode,
     utill::{get_tor_hostname, read_message, send_message, ConnectionType, HEART_BEAT_INTERVAL},
-    wallet::{Destination, SendAmount},
+    wallet::{transfer_delays, Destination, MemoTarget, SendAmount, SwapCoin, WalletError},
 };
 use std::str::FromStr;
 
+/// The response to a failed request, with the code of its error.
+fn server_error(e: &(impl ErrorCode + Debug)) -> RpcMsgResp {
+    RpcMsgResp::ServerError {
+        code: e.code(),
+        message: format!("{:?}", e),
+    }
+}
+
+/// A feerate of the request, in sats/vbyte.
+fn parse_feerate(feerate: u64) -> Result<FeeRate, WalletError> {
+    FeeRate::from_sat_per_vb(feerate)
+        .ok_or_else(|| WalletError::General(format!("Invalid feerate: {} sat/vB", feerate)))
+}
+
+/// Broadcasts the transfers in the background, each after a random delay up to `max_delay`.
+fn schedule_transfers(
+    maker: &Arc<Maker>,
+    txs: Vec<Transaction>,
+    max_delay: Duration,
+) -> Result<(), MakerError> {
+    let maker_clone = maker.clone();
+    let handle = thread::Builder::new()
+        .name("Swap Coin Transfer Thread".to_string())
+        .spawn(move || {
+            let start = Instant::now();
+            let delays = transfer_delays(txs.len(), max_delay);
+            for (tx, delay) in txs.iter().zip(delays) {
+                while start.elapsed() < delay {
+                    if maker_clone.shutdown.load(Relaxed) {
+                        log::warn!(
+                            "Shutting down with swap coin transfers not broadcast, from {}",
+                            tx.compute_txid()
+                        );
+                        return;
+                    }
+                    sleep(HEART_BEAT_INTERVAL.min(delay.saturating_sub(start.elapsed())));
+                }
+                let wallet = match maker_clone.get_wallet().read() {
+                    Ok(wallet) => wallet,
+                    Err(_) => return,
+                };
+                match wallet.send_tx(tx) {
+                    Ok(txid) => log::info!("Broadcasted swap coin transfer: {}", txid),
+                    Err(e) => log::error!("Failed to broadcast swap coin transfer: {:?}", e),
+                }
+            }
+        })?;
+    maker.thread_pool.add_thread(handle);
+    Ok(())
+}
+
 fn handle_request(maker: &Arc<Maker>, socket: &mut TcpStream) -> Result<(), MakerError> {
     let msg_bytes = read_message(socket)?;
-    let rpc_request: RpcMsgReq = serde_cbor::from_slice(&msg_bytes)?;
+    let rpc_request: RpcMsgReq = decode(&msg_bytes)?;
     log::info!("RPC request received: {:?}", rpc_request);
 
     let resp = match rpc_request {
         RpcMsgReq::Ping => RpcMsgResp::Pong,
+        RpcMsgReq::Health => RpcMsgResp::HealthResp(maker.health()),
         RpcMsgReq::ContractUtxo => {
             let utxos = maker
                 .get_wallet()
@@ -71,6 +132,22 @@ fn handle_request(maker: &Arc<Maker>, socket: &mut TcpStream) -> Result<(), Make
             let new_address = maker.get_wallet().write()?.get_next_external_address()?;
             RpcMsgResp::NewAddressResp(new_address.to_string())
         }
+        RpcMsgReq::NewPayjoinUri => {
+            if maker.config.payjoin_port == 0 {
+                server_error(&MakerError::General("Payjoin endpoint is not enabled"))
+            } else {
+                let new_address = maker.get_wallet().write()?.get_next_external_address()?;
+                let host = if maker.config.connection_type == ConnectionType::CLEARNET {
+                    "127.0.0.1".to_string()
+                } else {
+                    get_tor_hostname(&maker.data_dir.join("tor"))?
+                };
+                RpcMsgResp::PayjoinUriResp(format!(
+                    "bitcoin:{}?pj=http://{}:{}{}",
+                    new_address, host, maker.config.payjoin_port, PAYJOIN_PATH
+                ))
+            }
+        }
         RpcMsgReq::SendToAddress {
             address,
             amount,
@@ -112,10 +189,36 @@ fn handle_request(maker: &Arc<Maker>, socket: &mut TcpStream) -> Result<(), Make
                 RpcMsgResp::GetTorAddressResp(address)
             }
         }
+        RpcMsgReq::RotateAddress => {
+            if maker.config.connection_type == ConnectionType::CLEARNET {
+                server_error(&MakerError::General("Maker is not running on TOR"))
+            } else {
+                maker.rotate_address.store(true, Relaxed);
+                let ongoing_swaps = maker.ongoing_swap_state.lock()?.len();
+                RpcMsgResp::RotateAddressResp(format!(
+                    "Address rotation scheduled, after {} ongoing swaps finish. Check the new address with show-tor-address.",
+                    ongoing_swaps
+                ))
+            }
+        }
+        RpcMsgReq::GetIdentity => RpcMsgResp::IdentityResp(maker.get_wallet().read()?.identity()?),
+        RpcMsgReq::RotateIdentity => {
+            let identity = maker.get_wallet().write()?.rotate_identity()?;
+            log::info!("Rotated to the identity {}", identity);
+            RpcMsgResp::IdentityResp(identity)
+        }
+        RpcMsgReq::ReloadConfig => match maker.reload_config() {
+            Ok(policy) => RpcMsgResp::ReloadConfigResp(format!("Config reloaded: {:#?}", policy)),
+            Err(e) => server_error(&e),
+        },
         RpcMsgReq::Stop => {
             maker.shutdown.store(true, Relaxed);
             RpcMsgResp::Shutdown
         }
+        RpcMsgReq::Drain => {
+            maker.draining.store(true, Relaxed);
+            RpcMsgResp::Draining(maker.ongoing_swap_state.lock()?.len())
+        }
 
         RpcMsgReq::RedeemFidelity(index) => {
             let txid = maker.get_wallet().write()?.redeem_fidelity(index)?;
@@ -132,10 +235,91 @@ fn handle_request(maker: &Arc<Maker>, socket: &mut TcpStream) -> Result<(), Make
 
             RpcMsgResp::ListBonds(list)
         }
+        RpcMsgReq::FreezeUtxo { outpoint } => {
+            let outpoint = OutPoint::from_str(&outpoint)
+                .map_err(|e| WalletError::General(format!("Invalid outpoint: {}", e)))?;
+            maker.get_wallet().write()?.freeze(outpoint)?;
+            RpcMsgResp::FreezeResp(format!("Froze {}", outpoint))
+        }
+        RpcMsgReq::UnfreezeUtxo { outpoint } => {
+            let outpoint = OutPoint::from_str(&outpoint)
+                .map_err(|e| WalletError::General(format!("Invalid outpoint: {}", e)))?;
+            maker.get_wallet().write()?.unfreeze(outpoint)?;
+            RpcMsgResp::FreezeResp(format!("Unfroze {}", outpoint))
+        }
+        RpcMsgReq::ListFrozen => {
+            RpcMsgResp::ListFrozenResp(maker.get_wallet().read()?.frozen_utxos())
+        }
+        RpcMsgReq::Cpfp { outpoint, feerate } => {
+            let outpoint = OutPoint::from_str(&outpoint)
+                .map_err(|e| WalletError::General(format!("Invalid outpoint: {}", e)))?;
+            let child = maker
+                .get_wallet()
+                .write()?
+                .cpfp(outpoint, parse_feerate(feerate)?)?;
+            let txid = maker.get_wallet().read()?.send_tx(&child)?;
+            RpcMsgResp::CpfpResp(txid)
+        }
+        RpcMsgReq::TransferSwapCoins {
+            outpoints,
+            feerate,
+            max_delay_secs,
+        } => {
+            let outpoints = outpoints
+                .iter()
+                .map(|outpoint| OutPoint::from_str(outpoint))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| WalletError::General(format!("Invalid outpoint: {}", e)))?;
+            let txs = maker
+                .get_wallet()
+                .write()?
+                .transfer_swap_coins(&outpoints, parse_feerate(feerate)?)?;
+            let txids = txs.iter().map(|tx| tx.compute_txid()).collect();
+            if max_delay_secs == 0 {
+                for tx in &txs {
+                    maker.get_wallet().read()?.send_tx(tx)?;
+                }
+            } else {
+                schedule_transfers(maker, txs, Duration::from_secs(max_delay_secs))?;
+            }
+            RpcMsgResp::TransferResp(txids)
+        }
+        RpcMsgReq::ListStuckDeposits { feerate } => RpcMsgResp::StuckDepositsResp(
+            maker
+                .get_wallet()
+                .read()?
+                .list_stuck_deposits(parse_feerate(feerate)?)?,
+        ),
+        RpcMsgReq::BumpDeposits { feerate } => RpcMsgResp::BumpDepositsResp(
+            maker
+                .get_wallet()
+                .write()?
+                .bump_stuck_deposits(parse_feerate(feerate)?)?,
+        ),
+        RpcMsgReq::ListSwaps => RpcMsgResp::ListSwapsResp(list_ongoing_swaps(maker)?),
+        RpcMsgReq::ListReused => {
+            RpcMsgResp::ListReusedResp(maker.get_wallet().read()?.list_reused()?)
+        }
+        RpcMsgReq::ListTransactions => {
+            RpcMsgResp::ListTransactionsResp(maker.get_wallet().read()?.list_transactions()?)
+        }
+        RpcMsgReq::SetMemo { target, memo } => {
+            let target = target.parse::<MemoTarget>()?;
+            maker
+                .get_wallet()
+                .write()?
+                .set_memo(target.clone(), &memo)?;
+            RpcMsgResp::MemoResp(format!("Memo set on {}", target))
+        }
+        RpcMsgReq::GetEarnings(period) => {
+            let ledger = Ledger::read_from_disk(&maker.data_dir.join("ledger.dat"))?;
+            RpcMsgResp::EarningsResp(ledger.earnings(period))
+        }
+        RpcMsgReq::GetTraffic => RpcMsgResp::TrafficResp(maker.traffic.lock()?.report()),
         RpcMsgReq::SyncWallet => {
             log::info!("Initializing wallet sync");
             if let Err(e) = maker.get_wallet().write()?.sync() {
-                RpcMsgResp::ServerError(format!("{:?}", e))
+                server_error(&e)
             } else {
                 log::info!("Completed wallet sync");
                 RpcMsgResp::Pong
@@ -150,6 +334,55 @@ fn handle_request(maker: &Arc<Maker>, socket: &mut TcpStream) -> Result<(), Make
     Ok(())
 }
 
+/// Summaries of the Maker's ongoing swaps, most recently active first.
+fn list_ongoing_swaps(maker: &Maker) -> Result<Vec<SwapSummary>, MakerError> {
+    let mut swaps = maker
+        .ongoing_swap_state
+        .lock()?
+        .iter()
+        .map(|(id, (state, last_seen))| {
+            Ok(SwapSummary {
+                id: id.clone(),
+                phase: format!("{:?}", state.allowed_message),
+                idle_secs: last_seen.elapsed().as_secs(),
+                incoming_amount_sats: state.incoming_amount.to_sat(),
+                outgoing_amount_sats: state.outgoing_amount.to_sat(),
+                incoming: state
+                    .incoming_swapcoins
+                    .iter()
+                    .map(|coin| contract_summary(coin, coin.other_pubkey))
+                    .collect::<Result<_, _>>()?,
+                outgoing: state
+                    .outgoing_swapcoins
+                    .iter()
+                    .map(|coin| contract_summary(coin, coin.other_pubkey))
+                    .collect::<Result<_, _>>()?,
+                pending_funding_txids: state
+                    .pending_funding_txes
+                    .iter()
+                    .map(|tx| tx.compute_txid())
+                    .collect(),
+            })
+        })
+        .collect::<Result<Vec<_>, MakerError>>()?;
+    swaps.sort_by_key(|swap| swap.idle_secs);
+    Ok(swaps)
+}
+
+fn contract_summary(
+    coin: &impl SwapCoin,
+    counterparty: PublicKey,
+) -> Result<ContractSummary, WalletError> {
+    let contract_tx = coin.get_contract_tx();
+    Ok(ContractSummary {
+        counterparty,
+        funding_outpoint: contract_tx.input[0].previous_output,
+        amount_sats: coin.get_funding_amount().to_sat(),
+        contract_txid: contract_tx.compute_txid(),
+        timelock: coin.get_timelock()?,
+    })
+}
+
 pub(crate) fn start_rpc_server(maker: Arc<Maker>) -> Result<(), MakerError> {
     let rpc_port = maker.config.rpc_port;
     let rpc_socket = format!("127.0.0.1:{}", rpc_port);
@@ -166,15 +399,21 @@ pub(crate) fn start_rpc_server(maker: Arc<Maker>) -> Result<(), MakerError> {
         match listener.accept() {
             Ok((mut stream, addr)) => {
                 log::info!("Got RPC request from: {}", addr);
-                stream.set_read_timeout(Some(Duration::from_secs(20)))?;
-                stream.set_write_timeout(Some(Duration::from_secs(20)))?;
+                // The accepted socket may inherit the listener's non-blocking mode, which would
+                // fail the read right away.
+                if let Err(e) = stream
+                    .set_nonblocking(false)
+                    .and_then(|_| stream.set_read_timeout(Some(Duration::from_secs(20))))
+                    .and_then(|_| stream.set_write_timeout(Some(Duration::from_secs(20))))
+                {
+                    log::error!("Error setting up RPC connection: {:?}", e);
+                    continue;
+                }
                 // Do not cause hard error if a rpc request fails
                 if let Err(e) = handle_request(&maker, &mut stream) {
                     log::error!("Error processing RPC Request: {:?}", e);
                     // Send the error back to client.
-                    if let Err(e) =
-                        send_message(&mut stream, &RpcMsgResp::ServerError(format!("{:?}", e)))
-                    {
+                    if let Err(e) = send_message(&mut stream, &server_error(&e)) {
                         log::error!("Error sending RPC response {:?}", e);
                     };
                 }
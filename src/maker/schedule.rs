@@ -0,0 +1,216 @@
+//! Liquidity schedules of part-time makers.
+//!
+//! With `liquidity_windows` in the config, the Maker only takes swaps within the windows, in UTC. A window may
+//! also cap the swap size, e.g. smaller swaps at night. Outside all the windows, the offer advertises no
+//! liquidity, and new swaps are refused. The offers and the directory registration expire when the liquidity
+//! changes, so that takers don't start swaps with a Maker about to go offline, and the Maker registers again
+//! when its next window opens.
+//!
+//! A window is written `days hours [max_swap_amount]`: `mon-fri 08-22`, `sat 10-14 1000000` or `daily 22-06`.
+//! Hours run from the start hour to the end hour, excluded, and past midnight when the end is before the start.
+
+use std::{fmt, str::FromStr};
+
+const DAY_NAMES: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+
+const SECS_PER_HOUR: u64 = 60 * 60;
+
+/// Hours looked ahead for the next liquidity change: a week.
+const LOOKAHEAD_HOURS: u64 = 7 * 24;
+
+/// A time window the Maker takes swaps in, in UTC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LiquidityWindow {
+    /// Days the window opens on, a bit per day from Monday, the lowest.
+    pub days: u8,
+    /// Hour the window opens at, 0-23.
+    pub start_hour: u8,
+    /// Hour the window closes at, 1-24. Before the start hour for windows past midnight.
+    pub end_hour: u8,
+    /// Largest swap accepted in the window, in sats. None for the wallet's limit.
+    pub max_swap_amount: Option<u64>,
+}
+
+/// Weekday, from Monday as 0, and hour of unix time `now`.
+fn weekday_and_hour(now: u64) -> (u8, u8) {
+    let days = now / (24 * SECS_PER_HOUR);
+    // 1970-01-01 was a Thursday.
+    (((days + 3) % 7) as u8, ((now / SECS_PER_HOUR) % 24) as u8)
+}
+
+impl LiquidityWindow {
+    fn opens_on(&self, weekday: u8) -> bool {
+        self.days & (1 << weekday) != 0
+    }
+
+    /// Whether the window is open at unix time `now`.
+    pub(crate) fn is_open(&self, now: u64) -> bool {
+        let (weekday, hour) = weekday_and_hour(now);
+        if self.start_hour < self.end_hour {
+            self.opens_on(weekday) && hour >= self.start_hour && hour < self.end_hour
+        } else {
+            // Past midnight, opened the day before.
+            (self.opens_on(weekday) && hour >= self.start_hour)
+                || (self.opens_on((weekday + 6) % 7) && hour < self.end_hour)
+        }
+    }
+}
+
+impl FromStr for LiquidityWindow {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts = s.split_whitespace().collect::<Vec<_>>();
+        if parts.len() < 2 || parts.len() > 3 {
+            return Err(format!(
+                "Liquidity window {:?} isn't of the form `days hours [max_swap_amount]`",
+                s
+            ));
+        }
+        let day = |name: &str| {
+            DAY_NAMES
+                .iter()
+                .position(|day| *day == name.to_lowercase())
+                .ok_or_else(|| format!("Invalid day {:?} in liquidity window {:?}", name, s))
+        };
+        let days = match parts[0].split_once('-') {
+            _ if parts[0].eq_ignore_ascii_case("daily") => 0x7f,
+            Some((first, last)) => {
+                let (first, last) = (day(first)?, day(last)?);
+                // Through the end of the week, as in `sat-mon`.
+                (0..7)
+                    .map(|offset| (first + offset) % 7)
+                    .take((last + 7 - first) % 7 + 1)
+                    .fold(0u8, |days, day| days | (1 << day))
+            }
+            None => 1 << day(parts[0])?,
+        };
+        let hour = |hour: &str| {
+            hour.parse::<u8>()
+                .ok()
+                .filter(|hour| *hour <= 24)
+                .ok_or_else(|| format!("Invalid hour {:?} in liquidity window {:?}", hour, s))
+        };
+        let (start_hour, end_hour) = match parts[1].split_once('-') {
+            Some((start, end)) => (hour(start)?, hour(end)?),
+            None => return Err(format!("Invalid hours in liquidity window {:?}", s)),
+        };
+        if start_hour == end_hour || start_hour == 24 || (start_hour > end_hour && end_hour == 24) {
+            return Err(format!("Invalid hours in liquidity window {:?}", s));
+        }
+        let max_swap_amount = match parts.get(2) {
+            Some(amount) => Some(
+                amount
+                    .parse()
+                    .map_err(|_| format!("Invalid amount in liquidity window {:?}", s))?,
+            ),
+            None => None,
+        };
+        Ok(Self {
+            days,
+            start_hour,
+            end_hour,
+            max_swap_amount,
+        })
+    }
+}
+
+impl fmt::Display for LiquidityWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.days & 0x7f == 0x7f {
+            write!(f, "daily")?;
+        } else {
+            // Days parsed from a range are a single run, possibly through the end of the week.
+            let first = (0..7u8)
+                .find(|day| self.opens_on(*day) && !self.opens_on((day + 6) % 7))
+                .unwrap_or(0);
+            let last = (0..7u8)
+                .map(|offset| (first + offset) % 7)
+                .take_while(|day| self.opens_on(*day))
+                .last()
+                .unwrap_or(first);
+            if first == last {
+                write!(f, "{}", DAY_NAMES[first as usize])?;
+            } else {
+                write!(
+                    f,
+                    "{}-{}",
+                    DAY_NAMES[first as usize], DAY_NAMES[last as usize]
+                )?;
+            }
+        }
+        write!(f, " {:02}-{:02}", self.start_hour, self.end_hour)?;
+        if let Some(max_swap_amount) = self.max_swap_amount {
+            write!(f, " {}", max_swap_amount)?;
+        }
+        Ok(())
+    }
+}
+
+/// Largest swap taken at unix time `now`, in sats: 0 outside the windows, `u64::MAX` for the wallet's limit.
+/// Without windows, the Maker is always open.
+pub(crate) fn swap_limit_at(windows: &[LiquidityWindow], now: u64) -> u64 {
+    if windows.is_empty() {
+        return u64::MAX;
+    }
+    windows
+        .iter()
+        .filter(|window| window.is_open(now))
+        .map(|window| window.max_swap_amount.unwrap_or(u64::MAX))
+        .max()
+        .unwrap_or(0)
+}
+
+/// Unix time of the next change of the swap limit after `now`, if any within a week.
+pub(crate) fn next_limit_change(windows: &[LiquidityWindow], now: u64) -> Option<u64> {
+    let limit = swap_limit_at(windows, now);
+    let next_hour = (now / SECS_PER_HOUR + 1) * SECS_PER_HOUR;
+    (0..LOOKAHEAD_HOURS)
+        .map(|hours| next_hour + hours * SECS_PER_HOUR)
+        .find(|time| swap_limit_at(windows, *time) != limit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_liquidity_windows() {
+        let windows = ["mon-fri 08-22", "sat-sun 10-14 1000000", "fri 22-02 500000"]
+            .iter()
+            .map(|window| window.parse::<LiquidityWindow>().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(windows[0].to_string(), "mon-fri 08-22");
+        assert_eq!(windows[1].to_string(), "sat-sun 10-14 1000000");
+        assert_eq!("daily 00-24".parse::<LiquidityWindow>().unwrap().days, 0x7f);
+        let weekend = "sat-mon 00-06".parse::<LiquidityWindow>().unwrap();
+        assert_eq!(weekend.days, 0b110_0001);
+        assert_eq!(weekend.to_string(), "sat-mon 00-06");
+        for invalid in [
+            "mon-fri",
+            "funday 08-22",
+            "mon 22-22",
+            "mon 08-25",
+            "mon 08-22 lots",
+        ] {
+            assert!(invalid.parse::<LiquidityWindow>().is_err(), "{}", invalid);
+        }
+
+        // Monday 2024-01-01 00:00 UTC.
+        let monday = 1_704_067_200;
+        let at = |day: u64, hour: u64| monday + day * 24 * SECS_PER_HOUR + hour * SECS_PER_HOUR;
+
+        assert_eq!(swap_limit_at(&[], at(0, 3)), u64::MAX);
+        assert_eq!(swap_limit_at(&windows, at(0, 3)), 0);
+        assert_eq!(swap_limit_at(&windows, at(0, 8)), u64::MAX);
+        assert_eq!(swap_limit_at(&windows, at(5, 12)), 1_000_000);
+        // Friday night, past midnight.
+        assert_eq!(swap_limit_at(&windows, at(4, 23)), 500_000);
+        assert_eq!(swap_limit_at(&windows, at(5, 1)), 500_000);
+        assert_eq!(swap_limit_at(&windows, at(5, 2)), 0);
+
+        assert_eq!(next_limit_change(&windows, at(0, 3) + 600), Some(at(0, 8)));
+        assert_eq!(next_limit_change(&windows, at(4, 21)), Some(at(4, 22)));
+        assert_eq!(next_limit_change(&[], at(0, 3)), None);
+    }
+}
@@ -7,18 +7,19 @@
 use std::{
     io::ErrorKind,
     net::{Ipv4Addr, TcpListener, TcpStream},
-    path::Path,
+    path::PathBuf,
     process::Child,
     sync::{
         atomic::{AtomicBool, Ordering::Relaxed},
         Arc,
     },
     thread::{self, sleep},
-    time::Duration,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
-use bitcoin::{absolute::LockTime, Amount};
+use bitcoin::{absolute::LockTime, Amount, OutPoint};
 use bitcoind::bitcoincore_rpc::RpcApi;
+use serde::Serialize;
 
 #[cfg(feature = "tor")]
 use socks::Socks5Stream;
@@ -27,21 +28,39 @@ pub(crate) use super::{api::RPC_PING_INTERVAL, Maker};
 
 use crate::{
     error::NetError,
+    events::Event,
     maker::{
         api::{
             check_for_broadcasted_contracts, check_for_idle_states,
-            restore_broadcasted_contracts_on_reboot, ConnectionState,
+            restore_broadcasted_contracts_on_reboot, ByzantineAction, ConnectionState,
+            MakerBehavior,
         },
         handlers::handle_message,
+        health::start_health_server,
+        payjoin::start_payjoin_server,
+        rebalance::start_rebalancer,
         rpc::start_rpc_server,
+        traffic::{ConnectionTraffic, TrafficStats},
+    },
+    notifier::Notifier,
+    protocol::{
+        messages::{DnsMetadata, DnsRequest, TakerToMakerMessage},
+        wire::decode,
+    },
+    utill::{
+        read_message_before, send_message, send_message_counted, unix_time, ConnectionType,
+        HEART_BEAT_INTERVAL,
     },
-    protocol::messages::{DnsMetadata, DnsRequest, TakerToMakerMessage},
-    utill::{get_tor_hostname, read_message, send_message, ConnectionType, HEART_BEAT_INTERVAL},
     wallet::WalletError,
 };
 
 #[cfg(feature = "tor")]
-use crate::utill::monitor_log_for_completion;
+use crate::utill::{get_tor_hostname, monitor_log_for_completion};
+#[cfg(feature = "tor")]
+use std::path::Path;
+
+#[cfg(feature = "nostr")]
+use crate::maker::nostr::start_nostr_publisher;
 
 use crate::maker::error::MakerError;
 
@@ -56,78 +75,21 @@ pub(crate) const DIRECTORY_SERVERS_REFRESH_INTERVAL_SECS: u64 = 60 * 15; // 15 m
 /// Errors if ConncetionType=TOR but, the tor feature is not enabled.
 fn network_bootstrap(maker: Arc<Maker>) -> Result<Option<Child>, MakerError> {
     let maker_port = maker.config.network_port;
-    let (maker_address, dns_address, tor_handle) = match maker.config.connection_type {
+    let (maker_address, tor_handle) = match maker.config.connection_type {
         ConnectionType::CLEARNET => {
-            let maker_address = format!("127.0.0.1:{}", maker_port);
-            let dns_address = if cfg!(feature = "integration-test") {
-                format!("127.0.0.1:{}", 8080)
-            } else {
-                maker.config.directory_server_address.clone()
+            let advertised_port = match maker.config.advertised_port {
+                0 => maker_port,
+                port => port,
             };
-
-            (maker_address, dns_address, None)
+            (format!("127.0.0.1:{}", advertised_port), None)
         }
         #[cfg(feature = "tor")]
         ConnectionType::TOR => {
-            let maker_socks_port = maker.config.socks_port;
-
-            let tor_dir = maker.data_dir.join("tor");
-            let tor_log_file = tor_dir.join("log");
-
-            // Hard error if previous log file can't be removed, as monitor_log_for_completion doesn't work with existing file.
-            // Tell the user to manually delete the file and restart.
-            if tor_log_file.exists() {
-                if let Err(e) = std::fs::remove_file(&tor_log_file) {
-                    log::error!(
-                        "Error removing previous tor log. Please delete the file and restart. | {:?}",
-                        tor_log_file
-                    );
-                    return Err(e.into());
-                } else {
-                    log::info!("Previous tor log file deleted succesfully");
-                }
-            }
-
-            let tor_handle = Some(crate::tor::spawn_tor(
-                maker_socks_port,
-                maker_port,
-                tor_dir.to_str().unwrap().to_owned(),
-            )?);
-
-            log::info!(
-                "[{}] waiting for tor setup to compelte.",
-                maker.config.network_port
-            );
-
-            // TODO: move this function inside `spawn_tor` routine. `
-            if let Err(e) =
-                monitor_log_for_completion(&tor_log_file, "Bootstrapped 100% (done): Done")
-            {
-                log::error!(
-                    "[{}] Error monitoring log file {:?}. Remove the file and restart again. | {}",
-                    maker_port,
-                    tor_log_file,
-                    e
-                );
-                return Err(e.into());
-            }
-
-            log::info!("[{}] tor setup complete!", maker_port);
-
-            let maker_hostname = get_tor_hostname(&tor_dir)?;
-            let maker_address = format!("{}:{}", maker_hostname, maker.config.network_port);
-
-            let dns_address = if cfg!(feature = "integration-test") {
-                let dns_tor_dir = Path::new("/tmp/coinswap/dns/tor");
-                let dns_hostname = get_tor_hostname(dns_tor_dir)?;
-                format!("{}:{}", dns_hostname, 8080)
-            } else {
-                maker.config.directory_server_address.clone()
-            };
-
-            (maker_address, dns_address, tor_handle)
+            let (tor_handle, maker_address) = spawn_maker_tor(&maker)?;
+            (maker_address, Some(tor_handle))
         }
     };
+    let dns_address = directory_address(&maker)?;
 
     log::info!(
         "[{}] Server is listening at {}",
@@ -140,38 +102,51 @@ fn network_bootstrap(maker: Arc<Maker>) -> Result<Option<Child>, MakerError> {
         "Max offer size : {} sats",
         maker.get_wallet().read()?.store.offer_maxsize
     );
-
-    let proof = maker
-        .highest_fidelity_proof
-        .read()?
-        .as_ref()
-        .unwrap()
-        .clone();
-
-    let dns_metadata = DnsMetadata {
-        url: maker_address.clone(),
-        proof,
-    };
-
-    let request = DnsRequest::Post {
-        metadata: dns_metadata,
-    };
+    *maker.advertised_address.write()? = maker_address;
 
     thread::spawn(move || {
-        let trigger_count = DIRECTORY_SERVERS_REFRESH_INTERVAL_SECS / HEART_BEAT_INTERVAL.as_secs();
+        // Refreshed before the registration expires.
+        let refresh_secs = (maker.config.offer_ttl_secs / 2).clamp(
+            HEART_BEAT_INTERVAL.as_secs(),
+            DIRECTORY_SERVERS_REFRESH_INTERVAL_SECS,
+        );
+        let trigger_count = refresh_secs / HEART_BEAT_INTERVAL.as_secs();
         let mut i = 0;
+        let mut posted_address = String::new();
+        let mut registered_until = 0;
 
-        while !maker.shutdown.load(Relaxed) {
-            if i >= trigger_count || i == 0 {
-                let stream = match maker.config.connection_type {
-                    ConnectionType::CLEARNET => TcpStream::connect(&dns_address),
-                    #[cfg(feature = "tor")]
-                    ConnectionType::TOR => Socks5Stream::connect(
-                        format!("127.0.0.1:{}", maker.config.socks_port),
-                        dns_address.as_str(),
-                    )
-                    .map(|stream| stream.into_inner()),
-                };
+        // A draining Maker deregisters instead, see [deregister_from_directory].
+        while !maker.shutdown.load(Relaxed) && !maker.draining.load(Relaxed) {
+            // Outside the liquidity windows, our registration expires. Posted again once a window opens.
+            if matches!(maker.in_liquidity_window(), Ok(false)) {
+                i = 0;
+                thread::sleep(HEART_BEAT_INTERVAL);
+                continue;
+            }
+
+            let metadata = match dns_metadata(&maker) {
+                Ok(metadata) => metadata,
+                Err(e) => {
+                    log::error!(
+                        "[{}] Failed reading our directory registration: {:?}",
+                        maker_port,
+                        e
+                    );
+                    thread::sleep(HEART_BEAT_INTERVAL);
+                    continue;
+                }
+            };
+
+            // Post right away when our address was rotated, or the registration expired at a liquidity change.
+            if i >= trigger_count
+                || i == 0
+                || metadata.url != posted_address
+                || unix_time() >= registered_until
+            {
+                let url = metadata.url.clone();
+                let expires_at = metadata.expires_at;
+                let request = DnsRequest::Post { metadata };
+                let stream = connect_directory(&maker, &dns_address);
 
                 log::info!(
                     "[{}] Connecting to DNS: {}",
@@ -207,6 +182,9 @@ fn network_bootstrap(maker: Arc<Maker>) -> Result<Option<Child>, MakerError> {
                     maker_port,
                     dns_address
                 );
+                posted_address = url;
+                registered_until = expires_at;
+                maker.last_directory_post.store(unix_time(), Relaxed);
                 // Reset counter when success
                 i = 0;
             }
@@ -218,6 +196,173 @@ fn network_bootstrap(maker: Arc<Maker>) -> Result<Option<Child>, MakerError> {
     Ok(tor_handle)
 }
 
+/// Spawns the Tor instance of the Maker, and waits for it to bootstrap. Returns the Tor handle and the onion address.
+///
+/// The onion service keys are kept in `<data_dir>/tor/hs-dir`, and reused across restarts, so the advertised address
+/// stays the same until it is rotated.
+#[cfg(feature = "tor")]
+fn spawn_maker_tor(maker: &Maker) -> Result<(Child, String), MakerError> {
+    let maker_port = maker.config.network_port;
+    let tor_dir = maker.data_dir.join("tor");
+    let tor_log_file = tor_dir.join("log");
+
+    // Hard error if previous log file can't be removed, as monitor_log_for_completion doesn't work with existing file.
+    // Tell the user to manually delete the file and restart.
+    if tor_log_file.exists() {
+        if let Err(e) = std::fs::remove_file(&tor_log_file) {
+            log::error!(
+                "Error removing previous tor log. Please delete the file and restart. | {:?}",
+                tor_log_file
+            );
+            return Err(e.into());
+        } else {
+            log::info!("Previous tor log file deleted succesfully");
+        }
+    }
+
+    if tor_dir.join("hs-dir").join("hostname").exists() {
+        log::info!("[{}] Reusing the existing onion service keys", maker_port);
+    } else {
+        log::info!("[{}] Generating new onion service keys", maker_port);
+    }
+
+    let tor_handle = crate::tor::spawn_tor(
+        maker.config.socks_port,
+        maker_port,
+        tor_dir.to_str().unwrap().to_owned(),
+    )?;
+
+    log::info!("[{}] waiting for tor setup to compelte.", maker_port);
+
+    // TODO: move this function inside `spawn_tor` routine. `
+    if let Err(e) = monitor_log_for_completion(&tor_log_file, "Bootstrapped 100% (done): Done") {
+        log::error!(
+            "[{}] Error monitoring log file {:?}. Remove the file and restart again. | {}",
+            maker_port,
+            tor_log_file,
+            e
+        );
+        return Err(e.into());
+    }
+
+    log::info!("[{}] tor setup complete!", maker_port);
+
+    let maker_hostname = get_tor_hostname(&tor_dir)?;
+    Ok((tor_handle, format!("{}:{}", maker_hostname, maker_port)))
+}
+
+/// Rotates the onion address: restarts Tor with new onion service keys, and binds the fidelity proof to the
+/// new address, which the directory posting thread then advertises. The old keys are kept aside in `<data_dir>/tor`.
+#[cfg(feature = "tor")]
+fn rotate_onion_address(
+    maker: &Arc<Maker>,
+    tor_handle: &mut Option<Child>,
+) -> Result<(), MakerError> {
+    if let Some(mut handle) = tor_handle.take() {
+        crate::tor::kill_tor_handles(&mut handle);
+    }
+
+    let tor_dir = maker.data_dir.join("tor");
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    std::fs::rename(
+        tor_dir.join("hs-dir"),
+        tor_dir.join(format!("hs-dir.retired-{}", timestamp)),
+    )?;
+
+    let (handle, maker_address) = spawn_maker_tor(maker)?;
+    *tor_handle = Some(handle);
+
+    // Hold the address while the proof is updated, so the posting thread never pairs the new address with the old proof.
+    let mut advertised_address = maker.advertised_address.write()?;
+    setup_fidelity_bond(maker, &maker_address)?;
+    log::info!(
+        "[{}] Onion address rotated | old {} | new {}",
+        maker.config.network_port,
+        *advertised_address,
+        maker_address
+    );
+    *advertised_address = maker_address;
+    Ok(())
+}
+
+/// The directory registration of the Maker: its advertised address, with the fidelity proof bound to it.
+/// Address of the directory server. The local test directory under integration tests.
+fn directory_address(maker: &Maker) -> Result<String, MakerError> {
+    if !cfg!(feature = "integration-test") {
+        return Ok(maker.config.directory_server_address.clone());
+    }
+    match maker.config.connection_type {
+        ConnectionType::CLEARNET => Ok(format!("127.0.0.1:{}", 8080)),
+        #[cfg(feature = "tor")]
+        ConnectionType::TOR => {
+            let dns_tor_dir = Path::new("/tmp/coinswap/dns/tor");
+            let dns_hostname = get_tor_hostname(dns_tor_dir)?;
+            Ok(format!("{}:{}", dns_hostname, 8080))
+        }
+    }
+}
+
+/// Connects to the directory server, through Tor if the Maker runs on Tor.
+fn connect_directory(maker: &Maker, dns_address: &str) -> std::io::Result<TcpStream> {
+    match maker.config.connection_type {
+        ConnectionType::CLEARNET => TcpStream::connect(dns_address),
+        #[cfg(feature = "tor")]
+        ConnectionType::TOR => Socks5Stream::connect(
+            format!("127.0.0.1:{}", maker.config.socks_port),
+            dns_address,
+        )
+        .map(|stream| stream.into_inner()),
+    }
+}
+
+/// Removes our address from the directory, so that takers stop reaching out to us.
+fn deregister_from_directory(maker: &Maker) -> Result<(), MakerError> {
+    let metadata = dns_metadata(maker)?;
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let sig = maker.get_wallet().read()?.sign_directory_removal(
+        &metadata.proof,
+        &metadata.url,
+        timestamp,
+    )?;
+    let mut stream = connect_directory(maker, &directory_address(maker)?)?;
+    send_message(
+        &mut stream,
+        &DnsRequest::Remove {
+            metadata,
+            timestamp,
+            sig,
+        },
+    )?;
+    Ok(())
+}
+
+fn dns_metadata(maker: &Maker) -> Result<DnsMetadata, MakerError> {
+    // Address first, see [rotate_onion_address].
+    let url = maker.advertised_address.read()?.clone();
+    let proof = maker
+        .highest_fidelity_proof
+        .read()?
+        .clone()
+        .ok_or(MakerError::General("No fidelity proof"))?;
+    let expires_at = maker.offer_expiry()?;
+    let identity = maker
+        .get_wallet()
+        .read()?
+        .identity_proof(&proof, &url, expires_at)?;
+    Ok(DnsMetadata {
+        url,
+        proof,
+        expires_at,
+        identity: Some(identity),
+    })
+}
+
 /// Checks if the wallet already has fidelity bonds. if not, create the first fidelity bond.
 fn setup_fidelity_bond(maker: &Arc<Maker>, maker_address: &str) -> Result<(), MakerError> {
     let highest_index = maker.get_wallet().read()?.get_highest_fidelity_index()?;
@@ -225,10 +370,7 @@ fn setup_fidelity_bond(maker: &Arc<Maker>, maker_address: &str) -> Result<(), Ma
         let wallet_read = maker.get_wallet().read()?;
         let (bond, _, _) = wallet_read.get_fidelity_bonds().get(&i).unwrap();
 
-        let current_height = wallet_read
-            .rpc
-            .get_block_count()
-            .map_err(WalletError::Rpc)? as u32;
+        let current_height = wallet_read.chain.block_count()? as u32;
 
         let highest_proof = maker
             .get_wallet()
@@ -253,12 +395,7 @@ fn setup_fidelity_bond(maker: &Arc<Maker>, maker_address: &str) -> Result<(), Ma
         // xxxxx
         // No bond in the wallet. Lets attempt to create one.
         let amount = Amount::from_sat(maker.config.fidelity_amount);
-        let current_height = maker
-            .get_wallet()
-            .read()?
-            .rpc
-            .get_block_count()
-            .map_err(WalletError::Rpc)? as u32;
+        let current_height = maker.get_wallet().read()?.chain.block_count()? as u32;
 
         // Set 950 blocks locktime for test
         let locktime = if cfg!(feature = "integration-test") {
@@ -338,6 +475,69 @@ fn setup_fidelity_bond(maker: &Arc<Maker>, maker_address: &str) -> Result<(), Ma
     Ok(())
 }
 
+/// Publishes [Event::BondExpiring] once per bond, when the highest fidelity bond comes within
+/// the configured `bond_expiry_warning_blocks` of its timelock expiry.
+fn check_bond_expiry(maker: &Maker, notified: &mut Option<OutPoint>) -> Result<(), MakerError> {
+    let proof = maker.highest_fidelity_proof.read()?;
+    let bond = match proof.as_ref() {
+        Some(proof) => &proof.bond,
+        None => return Ok(()),
+    };
+
+    if *notified == Some(bond.outpoint) {
+        return Ok(());
+    }
+
+    let current_height = maker.get_wallet().read()?.chain.block_count()? as u32;
+    let blocks_remaining = bond
+        .lock_time
+        .to_consensus_u32()
+        .saturating_sub(current_height);
+
+    if blocks_remaining <= maker.config.bond_expiry_warning_blocks {
+        log::warn!(
+            "[{}] Fidelity bond {} expires in {} blocks. Consider creating a new bond.",
+            maker.config.network_port,
+            bond.outpoint,
+            blocks_remaining
+        );
+        maker.events.publish(Event::BondExpiring {
+            outpoint: bond.outpoint,
+            blocks_remaining,
+        });
+        *notified = Some(bond.outpoint);
+    }
+
+    Ok(())
+}
+
+/// Publishes [Event::BalanceLow] when the spendable balance falls below the configured
+/// `low_balance_threshold`. Once more, only after the balance went back above it.
+fn check_low_balance(maker: &Maker, notified: &mut bool) -> Result<(), MakerError> {
+    let threshold = Amount::from_sat(maker.config.low_balance_threshold);
+    if threshold == Amount::ZERO {
+        return Ok(());
+    }
+
+    let balance = maker.get_wallet().read()?.get_balances(None)?.spendable;
+    if balance >= threshold {
+        *notified = false;
+    } else if !*notified {
+        log::warn!(
+            "[{}] Spendable balance {} fell below {}",
+            maker.config.network_port,
+            balance,
+            threshold
+        );
+        maker
+            .events
+            .publish(Event::BalanceLow { balance, threshold });
+        *notified = true;
+    }
+
+    Ok(())
+}
+
 /// Keep checking if the Bitcoin Core RPC connection is live. Sets the global `accepting_client` flag as per RPC connection status.
 ///
 /// This will not block. Once Core RPC connection is live, accepting_client will set as `true` again.
@@ -382,21 +582,75 @@ fn check_connection_with_core(
     Ok(())
 }
 
+/// Sends a reply, in the session of the connection if the message replied to was. The reply to the hello goes
+/// out of the session it opens.
+fn send_reply(
+    stream: &mut TcpStream,
+    connection_state: &mut ConnectionState,
+    in_session: bool,
+    message: &impl Serialize,
+) -> Result<usize, NetError> {
+    match connection_state.session.as_mut() {
+        Some(session) if in_session => session.send(stream, message),
+        _ => send_message_counted(stream, message),
+    }
+}
+
 /// Handle a single client connection.
-fn handle_client(maker: Arc<Maker>, stream: &mut TcpStream) -> Result<(), MakerError> {
+fn handle_client(
+    maker: Arc<Maker>,
+    stream: &mut TcpStream,
+    peer: String,
+) -> Result<(), MakerError> {
     stream.set_nonblocking(false)?; // Block this thread until message is read.
 
+    // The lines of this connection show the taker, and the swap once known.
+    let span = tracing::info_span!("connection", peer = %peer, swap_id = tracing::field::Empty);
+    let _entered = span.enter();
+
     let mut connection_state = ConnectionState::default();
+    let mut traffic = ConnectionTraffic::new(&maker.traffic, peer);
+    // Last response sent, replayed by byzantine makers to simulate reordering.
+    let mut last_reply: Option<serde_cbor::Value> = None;
+    // Bytes received over this connection, capped at `max_connection_bytes`.
+    let mut received_bytes = 0u64;
+    // Time the last complete message was received.
+    let mut last_activity = Instant::now();
 
     while !maker.shutdown.load(Relaxed) {
+        let (max_message_size, max_connection_bytes) = {
+            let policy = maker.policy.read()?;
+            (policy.max_message_size, policy.max_connection_bytes)
+        };
+        // A taker silent for longer than the timeout of the current phase is dropped. Outside of a swap, that's
+        // the idle connection timeout. An ongoing swap is recovered by the idle state checker.
+        let phase = connection_state.allowed_message.phase();
+        let deadline = last_activity + maker.config.phase_timeout(phase);
         let mut taker_msg_bytes = Vec::new();
-        match read_message(stream) {
+        match read_message_before(stream, max_message_size, deadline) {
             Ok(b) => taker_msg_bytes = b,
+            Err(NetError::MessageTooLarge { size, limit }) => {
+                log::warn!(
+                    "[{}] Closing connection, message of {} bytes exceeds the {} bytes limit",
+                    maker.config.network_port,
+                    size,
+                    limit
+                );
+                break;
+            }
             Err(e) => {
                 if let NetError::IO(e) = e {
                     if e.kind() == ErrorKind::UnexpectedEof {
                         log::info!("[{}] Connection ended.", maker.config.network_port);
                         break;
+                    } else if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) {
+                        log::warn!(
+                            "[{}] Closing connection, taker idle for {} secs in {}",
+                            maker.config.network_port,
+                            last_activity.elapsed().as_secs(),
+                            phase
+                        );
+                        break;
                     } else {
                         // For any other errors, report them
                         log::error!("[{}] Net Error: {}", maker.config.network_port, e);
@@ -406,19 +660,81 @@ fn handle_client(maker: Arc<Maker>, stream: &mut TcpStream) -> Result<(), MakerE
             }
         }
 
-        let taker_msg: TakerToMakerMessage = serde_cbor::from_slice(&taker_msg_bytes)?;
+        last_activity = Instant::now();
+        received_bytes += taker_msg_bytes.len() as u64;
+        if received_bytes > max_connection_bytes {
+            log::warn!(
+                "[{}] Closing connection, received more than {} bytes",
+                maker.config.network_port,
+                max_connection_bytes
+            );
+            break;
+        }
+
+        // Once the hellos opened the session, messages outside of it, or out of sequence, are replays.
+        let in_session = connection_state.session.is_some();
+        let taker_msg: TakerToMakerMessage = match connection_state.session.as_mut() {
+            Some(session) => session.decode(&taker_msg_bytes)?,
+            None => decode(&taker_msg_bytes)?,
+        };
         log::info!("[{}] <=== {}", maker.config.network_port, taker_msg);
+        if let Some(swap_id) = taker_msg.swap_id() {
+            span.record("swap_id", swap_id);
+            traffic.set_swap_id(swap_id);
+        }
+        // The length prefix counts too.
+        traffic.record(TrafficStats::received(taker_msg_bytes.len() + 4));
 
         let reply = handle_message(&maker, &mut connection_state, taker_msg);
 
         match reply {
             Ok(reply) => {
                 if let Some(message) = reply {
+                    if let MakerBehavior::DelayResponses(delay) = maker.behavior {
+                        thread::sleep(delay);
+                    }
                     log::info!("[{}] ===> {} ", maker.config.network_port, message);
-                    if let Err(e) = send_message(stream, &message) {
-                        log::error!("Closing due to IO error in sending message: {:?}", e);
+                    if let MakerBehavior::RandomByzantine(_) = maker.behavior {
+                        let reply = serde_cbor::value::to_value(&message)?;
+                        let action = maker.byzantine_rng.lock()?.next_action();
+                        log::warn!(
+                            "[{}] Byzantine action on response: {:?}",
+                            maker.config.network_port,
+                            action
+                        );
+                        let to_send = match action {
+                            ByzantineAction::Pass => reply.clone(),
+                            ByzantineAction::Corrupt => serde_cbor::Value::Bytes(
+                                serde_cbor::to_vec(&reply)?
+                                    .iter()
+                                    .map(|b| b ^ 0xa5)
+                                    .collect(),
+                            ),
+                            ByzantineAction::Reorder => {
+                                last_reply.clone().unwrap_or_else(|| reply.clone())
+                            }
+                            ByzantineAction::Delay(delay) => {
+                                thread::sleep(delay);
+                                reply.clone()
+                            }
+                            ByzantineAction::Drop => break,
+                        };
+                        last_reply = Some(reply);
+                        match send_reply(stream, &mut connection_state, in_session, &to_send) {
+                            Ok(sent) => traffic.record(TrafficStats::sent(sent)),
+                            Err(e) => {
+                                log::error!("Closing due to IO error in sending message: {:?}", e)
+                            }
+                        }
                         continue;
                     }
+                    match send_reply(stream, &mut connection_state, in_session, &message) {
+                        Ok(sent) => traffic.record(TrafficStats::sent(sent)),
+                        Err(e) => {
+                            log::error!("Closing due to IO error in sending message: {:?}", e);
+                            continue;
+                        }
+                    }
                 } else {
                     continue;
                 }
@@ -469,7 +785,8 @@ pub fn start_maker_server(maker: Arc<Maker>) -> Result<(), MakerError> {
     // Initialize network connections.
 
     // Setup the wallet with fidelity bond.
-    let _tor_thread = network_bootstrap(maker.clone())?;
+    #[cfg_attr(not(feature = "tor"), allow(unused_mut))]
+    let mut _tor_thread = network_bootstrap(maker.clone())?;
 
     let port = maker.config.network_port;
     let network = maker.get_wallet().read()?.store.network;
@@ -566,6 +883,99 @@ pub fn start_maker_server(maker: Arc<Maker>) -> Result<(), MakerError> {
 
         maker.thread_pool.add_thread(rpc_thread);
 
+        // The Payjoin endpoint, for receiving deposits into the wallet. Only when configured.
+        if maker.config.payjoin_port != 0 {
+            let maker_clone = maker.clone();
+            let payjoin_thread = thread::Builder::new()
+                .name("Payjoin Thread".to_string())
+                .spawn(move || {
+                    log::info!("[{}] Spawning payjoin server thread", port);
+                    if let Err(e) = start_payjoin_server(maker_clone) {
+                        log::error!("Failed starting payjoin server {:?}", e);
+                    }
+                })?;
+            maker.thread_pool.add_thread(payjoin_thread);
+        }
+
+        // The health endpoint, for container orchestration and uptime monitors. Only when configured.
+        if maker.config.health_port != 0 {
+            let maker_clone = maker.clone();
+            let health_thread = thread::Builder::new()
+                .name("Health Thread".to_string())
+                .spawn(move || {
+                    log::info!("[{}] Spawning health server thread", port);
+                    if let Err(e) = start_health_server(maker_clone) {
+                        log::error!("Failed starting health server {:?}", e);
+                    }
+                })?;
+            maker.thread_pool.add_thread(health_thread);
+        }
+
+        // The offer publication to Nostr relays. Only when configured.
+        if !maker.config.nostr_relays.is_empty() {
+            #[cfg(feature = "nostr")]
+            {
+                let maker_clone = maker.clone();
+                let nostr_thread = thread::Builder::new()
+                    .name("Nostr Publisher Thread".to_string())
+                    .spawn(move || {
+                        log::info!("[{}] Spawning nostr publisher thread", port);
+                        if let Err(e) = start_nostr_publisher(maker_clone) {
+                            log::error!("Nostr publisher failed {:?}", e);
+                        }
+                    })?;
+                maker.thread_pool.add_thread(nostr_thread);
+            }
+            #[cfg(not(feature = "nostr"))]
+            log::warn!(
+                "[{}] nostr_relays configured, but built without the nostr feature. Offers aren't published to relays",
+                port
+            );
+        }
+
+        // The rebalancer, swapping the Maker's own swap coins through other makers. Only when configured.
+        if maker.config.rebalance_enabled() {
+            let maker_clone = maker.clone();
+            let rebalance_thread = thread::Builder::new()
+                .name("Rebalance Thread".to_string())
+                .spawn(move || {
+                    log::info!("[{}] Spawning rebalance thread", port);
+                    if let Err(e) = start_rebalancer(maker_clone) {
+                        log::error!("Rebalancer failed {:?}", e);
+                    }
+                })?;
+            maker.thread_pool.add_thread(rebalance_thread);
+        }
+
+        // The operator notifications. Only when configured.
+        let notifier = Notifier::new(
+            maker.config.notify_webhooks.clone(),
+            Some(PathBuf::from(&maker.config.notify_script))
+                .filter(|_| !maker.config.notify_script.is_empty()),
+        );
+        if notifier.is_enabled() {
+            let events = maker.subscribe_events();
+            let maker_clone = maker.clone();
+            let notifier_thread = thread::Builder::new()
+                .name("Notifier Thread".to_string())
+                .spawn(move || {
+                    log::info!("[{}] Spawning notifier thread", port);
+                    notifier.run(events, &maker_clone.shutdown);
+                })?;
+            maker.thread_pool.add_thread(notifier_thread);
+        }
+
+        // 5: The Watchtower thread.
+        // Claims our contracts via hashlock or timelock, as soon as they are claimable on chain.
+        let maker_clone = maker.clone();
+        let watchtower_thread = thread::Builder::new()
+            .name("Watchtower Thread".to_string())
+            .spawn(move || {
+                log::info!("[{}] Spawning watchtower thread", port);
+                maker_clone.watchtower.run();
+            })?;
+        maker.thread_pool.add_thread(watchtower_thread);
+
         sleep(HEART_BEAT_INTERVAL); // wait for 1 beat, to complete spawns of all the threads.
         maker.is_setup_complete.store(true, Relaxed);
         log::info!("[{}] Server Setup completed!! Use maker-cli to operate the server and the internal wallet.", maker.config.network_port);
@@ -580,6 +990,8 @@ pub fn start_maker_server(maker: Arc<Maker>) -> Result<(), MakerError> {
     }
 
     let mut sync_counter = 0;
+    let mut bond_expiry_notified = None;
+    let mut low_balance_notified = false;
     // The P2P Client connection loop.
     // Each client connection will spawn a new handler thread, which is added back in the global thread_pool.
     // This loop beats at `maker.config.heart_beat_interval_secs`
@@ -589,18 +1001,52 @@ pub fn start_maker_server(maker: Arc<Maker>) -> Result<(), MakerError> {
         if sync_counter >= 10 || sync_counter == 0 {
             maker.get_wallet().write()?.sync_no_fail();
             let offer_max_size = maker.get_wallet().read()?.store.offer_maxsize;
-            if offer_max_size <= maker.config.min_swap_amount {
-                log::warn!("[WARN!] Swaps are disabled due to low balance, Please put more funds in the wallet | Min required {} sats | Available {} sats", maker.config.min_swap_amount, offer_max_size);
+            let min_swap_amount = maker.policy.read()?.min_swap_amount;
+            if offer_max_size <= min_swap_amount {
+                log::warn!("[WARN!] Swaps are disabled due to low balance, Please put more funds in the wallet | Min required {} sats | Available {} sats", min_swap_amount, offer_max_size);
             } else {
                 log::info!(
                     "Total available balance for swaps: {} sats | Listening for incoming swap requests",
                     offer_max_size
                 );
             }
+            if let Err(e) = check_bond_expiry(&maker, &mut bond_expiry_notified) {
+                log::error!("[{}] Failed checking bond expiry: {:?}", port, e);
+            }
+            if let Err(e) = check_low_balance(&maker, &mut low_balance_notified) {
+                log::error!("[{}] Failed checking balance: {:?}", port, e);
+            }
             sync_counter = 0;
         }
         sync_counter += 1;
 
+        // Rotate the onion address once the ongoing swaps, which reach us at the old address, are finished.
+        #[cfg(feature = "tor")]
+        if maker.rotate_address.load(Relaxed) && maker.ongoing_swap_state.lock()?.is_empty() {
+            log::info!("[{}] Rotating the onion address", port);
+            if let Err(e) = rotate_onion_address(&maker, &mut _tor_thread) {
+                log::error!("[{}] Address rotation failed: {:?}", port, e);
+            }
+            maker.rotate_address.store(false, Relaxed);
+        }
+
+        // Drained: once the ongoing swaps are finished or recovered, leave the directory and shut down.
+        if maker.draining.load(Relaxed) && maker.ongoing_swap_state.lock()?.is_empty() {
+            log::info!(
+                "[{}] No ongoing swaps left, deregistering from the directory",
+                port
+            );
+            if let Err(e) = deregister_from_directory(&maker) {
+                log::warn!(
+                    "[{}] Failed to deregister from the directory: {:?}",
+                    port,
+                    e
+                );
+            }
+            maker.shutdown.store(true, Relaxed);
+            break;
+        }
+
         let maker = maker.clone(); // This clone is needed to avoid moving the Arc<Maker> in each iterations.
 
         // Block client connections if accepting_client=false
@@ -614,13 +1060,22 @@ pub fn start_maker_server(maker: Arc<Maker>) -> Result<(), MakerError> {
         }
 
         match listener.accept() {
-            Ok((mut stream, _)) => {
+            Ok((mut stream, peer)) => {
                 log::info!(
                     "[{}] Received incoming connection",
                     maker.config.network_port
                 );
 
-                if let Err(e) = handle_client(maker, &mut stream) {
+                if maker.policy.read()?.is_banned(&peer.ip().to_string()) {
+                    log::warn!(
+                        "[{}] Refusing connection from banned peer {}",
+                        maker.config.network_port,
+                        peer.ip()
+                    );
+                    continue;
+                }
+
+                if let Err(e) = handle_client(maker, &mut stream, peer.ip().to_string()) {
                     log::error!("[{}] Error Handling client request {:?}", port, e);
                 }
             }
@@ -642,6 +1097,7 @@ pub fn start_maker_server(maker: Arc<Maker>) -> Result<(), MakerError> {
     }
 
     log::info!("[{}] Maker is shutting down.", port);
+    maker.watchtower.stop();
     maker.thread_pool.join_all_threads()?;
 
     #[cfg(feature = "tor")]
@@ -0,0 +1,238 @@
+//! Traffic accounting of the Maker's taker connections.
+//!
+//! The bytes and messages exchanged are counted per peer and per swap, since the Maker started, and listed by
+//! `maker-cli get-traffic`. Operators can spot a peer abusing the bandwidth, and relate their Tor costs with
+//! their swaps.
+//!
+//! Peers are told apart by IP address. Over Tor, all connections come from the local Tor process: the swap
+//! totals are then the meaningful ones. The messages of a connection before its swap id is known, like the
+//! offer request, count towards the swap once it is.
+
+use std::{collections::HashMap, sync::Mutex};
+
+use serde::{Deserialize, Serialize};
+
+/// Most peers and swaps tracked each. The traffic of the others is counted under [OTHERS].
+pub(crate) const MAX_TRACKED: usize = 1000;
+
+/// Key of the traffic of the peers and swaps past [MAX_TRACKED].
+pub(crate) const OTHERS: &str = "others";
+
+/// Bytes and messages exchanged with takers.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TrafficStats {
+    /// Connections opened.
+    pub connections: u64,
+    /// Messages received.
+    pub messages_received: u64,
+    /// Messages sent.
+    pub messages_sent: u64,
+    /// Bytes received, with the message length prefixes.
+    pub bytes_received: u64,
+    /// Bytes sent, with the message length prefixes.
+    pub bytes_sent: u64,
+}
+
+impl TrafficStats {
+    /// Stats of one received message of `bytes`.
+    pub(crate) fn received(bytes: usize) -> Self {
+        Self {
+            messages_received: 1,
+            bytes_received: bytes as u64,
+            ..Self::default()
+        }
+    }
+
+    /// Stats of one sent message of `bytes`.
+    pub(crate) fn sent(bytes: usize) -> Self {
+        Self {
+            messages_sent: 1,
+            bytes_sent: bytes as u64,
+            ..Self::default()
+        }
+    }
+
+    fn add(&mut self, other: &TrafficStats) {
+        self.connections += other.connections;
+        self.messages_received += other.messages_received;
+        self.messages_sent += other.messages_sent;
+        self.bytes_received += other.bytes_received;
+        self.bytes_sent += other.bytes_sent;
+    }
+
+    /// Total bytes, both ways.
+    pub fn total_bytes(&self) -> u64 {
+        self.bytes_received + self.bytes_sent
+    }
+}
+
+/// Traffic of the Maker since it started, as listed by the `get-traffic` RPC.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct TrafficReport {
+    /// Traffic of all the connections.
+    pub total: TrafficStats,
+    /// Traffic per peer IP address, the heaviest first.
+    pub peers: Vec<(String, TrafficStats)>,
+    /// Traffic per swap id, the heaviest first.
+    pub swaps: Vec<(String, TrafficStats)>,
+}
+
+/// Counts the traffic of the Maker.
+#[derive(Debug, Default)]
+pub(crate) struct TrafficLedger {
+    total: TrafficStats,
+    peers: HashMap<String, TrafficStats>,
+    swaps: HashMap<String, TrafficStats>,
+}
+
+/// Adds `stats` to the entry of `key`, or of [OTHERS] once [MAX_TRACKED] entries are tracked.
+fn add_to(map: &mut HashMap<String, TrafficStats>, key: &str, stats: &TrafficStats) {
+    let key = if map.contains_key(key) || map.len() < MAX_TRACKED {
+        key
+    } else {
+        OTHERS
+    };
+    map.entry(key.to_string()).or_default().add(stats);
+}
+
+fn heaviest_first(map: &HashMap<String, TrafficStats>) -> Vec<(String, TrafficStats)> {
+    let mut entries = map
+        .iter()
+        .map(|(key, stats)| (key.clone(), *stats))
+        .collect::<Vec<_>>();
+    entries.sort_by(|(a_key, a), (b_key, b)| {
+        b.total_bytes()
+            .cmp(&a.total_bytes())
+            .then_with(|| a_key.cmp(b_key))
+    });
+    entries
+}
+
+impl TrafficLedger {
+    /// Counts traffic with `peer`, and with the swap, if known.
+    pub(crate) fn record(&mut self, peer: &str, swap_id: Option<&str>, stats: &TrafficStats) {
+        self.total.add(stats);
+        add_to(&mut self.peers, peer, stats);
+        if let Some(swap_id) = swap_id {
+            add_to(&mut self.swaps, swap_id, stats);
+        }
+    }
+
+    /// Counts the earlier traffic of a connection towards its swap, once its id is known.
+    pub(crate) fn record_swap(&mut self, swap_id: &str, stats: &TrafficStats) {
+        add_to(&mut self.swaps, swap_id, stats);
+    }
+
+    /// The traffic so far.
+    pub(crate) fn report(&self) -> TrafficReport {
+        TrafficReport {
+            total: self.total,
+            peers: heaviest_first(&self.peers),
+            swaps: heaviest_first(&self.swaps),
+        }
+    }
+}
+
+/// Counts the traffic of one connection, towards its swap once its id is known.
+pub(crate) struct ConnectionTraffic<'a> {
+    ledger: &'a Mutex<TrafficLedger>,
+    peer: String,
+    swap_id: Option<String>,
+    stats: TrafficStats,
+}
+
+impl<'a> ConnectionTraffic<'a> {
+    /// Counts a new connection from `peer`.
+    pub(crate) fn new(ledger: &'a Mutex<TrafficLedger>, peer: String) -> Self {
+        let mut traffic = Self {
+            ledger,
+            peer,
+            swap_id: None,
+            stats: TrafficStats::default(),
+        };
+        traffic.record(TrafficStats {
+            connections: 1,
+            ..TrafficStats::default()
+        });
+        traffic
+    }
+
+    /// Counts traffic over the connection. Accounting never fails the connection: a poisoned ledger is skipped.
+    pub(crate) fn record(&mut self, stats: TrafficStats) {
+        self.stats.add(&stats);
+        if let Ok(mut ledger) = self.ledger.lock() {
+            ledger.record(&self.peer, self.swap_id.as_deref(), &stats);
+        }
+    }
+
+    /// Sets the swap of the connection, the first time its id is seen.
+    pub(crate) fn set_swap_id(&mut self, swap_id: &str) {
+        if self.swap_id.is_some() {
+            return;
+        }
+        self.swap_id = Some(swap_id.to_string());
+        if let Ok(mut ledger) = self.ledger.lock() {
+            ledger.record_swap(swap_id, &self.stats);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_traffic_ledger() {
+        let mut ledger = TrafficLedger::default();
+        let connection = TrafficStats {
+            connections: 1,
+            ..TrafficStats::default()
+        };
+
+        // An offer request, before the swap id is known.
+        let mut offer_connection = connection;
+        offer_connection.add(&TrafficStats::received(100));
+        offer_connection.add(&TrafficStats::sent(400));
+        ledger.record("10.0.0.1", None, &offer_connection);
+        ledger.record_swap("swap", &offer_connection);
+
+        ledger.record("10.0.0.1", Some("swap"), &connection);
+        ledger.record("10.0.0.1", Some("swap"), &TrafficStats::received(5000));
+        ledger.record("10.0.0.2", None, &TrafficStats::received(10));
+
+        let report = ledger.report();
+        assert_eq!(report.total.connections, 2);
+        assert_eq!(report.total.messages_received, 3);
+        assert_eq!(report.total.bytes_received, 5110);
+        assert_eq!(report.total.bytes_sent, 400);
+        assert_eq!(report.peers[0].0, "10.0.0.1");
+        assert_eq!(report.peers[0].1.total_bytes(), 5500);
+        assert_eq!(report.peers[1].0, "10.0.0.2");
+        assert_eq!(
+            report.swaps,
+            vec![(
+                "swap".to_string(),
+                TrafficStats {
+                    connections: 2,
+                    messages_received: 2,
+                    messages_sent: 1,
+                    bytes_received: 5100,
+                    bytes_sent: 400,
+                }
+            )]
+        );
+
+        // Past the limit, new peers are counted together.
+        for i in 0..MAX_TRACKED + 10 {
+            ledger.record(&format!("peer-{}", i), None, &TrafficStats::received(1));
+        }
+        let report = ledger.report();
+        assert_eq!(report.peers.len(), MAX_TRACKED + 1);
+        let others = report
+            .peers
+            .iter()
+            .find(|(peer, _)| peer == OTHERS)
+            .unwrap();
+        assert_eq!(others.1.messages_received, 12);
+    }
+}
@@ -0,0 +1,253 @@
+//! Terminal dashboard of the Maker server.
+//!
+//! Shows the ongoing swaps with their current phase, the wallet balances, the fidelity bond expiry
+//! countdown and the recent log lines, refreshed every second. Started with `makerd --tui`.
+//! Pressing `q` or `Esc` shuts the server down.
+//!
+//! Only available with the `tui` feature.
+
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    sync::{atomic::Ordering::Relaxed, Arc},
+    time::Duration,
+};
+
+use bitcoin::{Amount, OutPoint};
+use bitcoind::bitcoincore_rpc::RpcApi;
+use ratatui::{
+    crossterm::event::{self, Event as TermEvent, KeyCode, KeyEventKind},
+    layout::{Constraint, Direction, Layout},
+    style::{Color, Modifier, Style},
+    text::Line,
+    widgets::{Block, Borders, Paragraph, Row, Table},
+    DefaultTerminal, Frame,
+};
+
+use crate::{utill::get_maker_dir, wallet::Balances};
+
+use super::{api::Maker, error::MakerError};
+
+/// Refresh interval of the dashboard.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Only the end of the log file is read.
+const LOG_TAIL_BYTES: u64 = 32 * 1024;
+
+/// An ongoing swap, as shown on the dashboard.
+struct SwapRow {
+    id: String,
+    phase: String,
+    amount: Amount,
+    idle_secs: u64,
+}
+
+/// The highest fidelity bond, as shown on the dashboard.
+struct BondInfo {
+    outpoint: OutPoint,
+    amount: Amount,
+    blocks_remaining: u32,
+}
+
+/// State of the Maker at one refresh.
+#[derive(Default)]
+struct Snapshot {
+    swaps: Vec<SwapRow>,
+    balances: Option<Balances>,
+    bond: Option<BondInfo>,
+    logs: Vec<String>,
+}
+
+/// Runs the dashboard on the current terminal, until the user quits or the Maker shuts down.
+/// Quitting the dashboard shuts the Maker down.
+pub fn run_dashboard(maker: Arc<Maker>) -> Result<(), MakerError> {
+    let mut terminal = ratatui::init();
+    let result = dashboard_loop(&mut terminal, &maker);
+    ratatui::restore();
+    result
+}
+
+fn dashboard_loop(terminal: &mut DefaultTerminal, maker: &Maker) -> Result<(), MakerError> {
+    while !maker.shutdown.load(Relaxed) {
+        let snapshot = take_snapshot(maker);
+        terminal.draw(|frame| draw(frame, maker, &snapshot))?;
+
+        if event::poll(REFRESH_INTERVAL)? {
+            if let TermEvent::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press
+                    && matches!(key.code, KeyCode::Char('q') | KeyCode::Esc)
+                {
+                    log::info!("Dashboard closed, shutting down.");
+                    maker.shutdown.store(true, Relaxed);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn take_snapshot(maker: &Maker) -> Snapshot {
+    let swaps = match maker.ongoing_swap_state.lock() {
+        Ok(state) => state
+            .iter()
+            .map(|(id, (connection_state, last_seen))| SwapRow {
+                id: id.clone(),
+                phase: format!("{:?}", connection_state.allowed_message),
+                amount: connection_state.incoming_amount,
+                idle_secs: last_seen.elapsed().as_secs(),
+            })
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+
+    let (balances, height) = match maker.get_wallet().read() {
+        // Don't unlock the utxos of ongoing swaps.
+        Ok(wallet) => (
+            wallet
+                .get_all_locked_utxo()
+                .and_then(|utxos| wallet.get_balances(Some(&utxos)))
+                .ok(),
+            wallet.chain.block_count().ok(),
+        ),
+        Err(_) => (None, None),
+    };
+
+    let bond = match (maker.highest_fidelity_proof.read(), height) {
+        (Ok(proof), Some(height)) => proof.as_ref().map(|proof| BondInfo {
+            outpoint: proof.bond.outpoint,
+            amount: proof.bond.amount,
+            blocks_remaining: proof
+                .bond
+                .lock_time
+                .to_consensus_u32()
+                .saturating_sub(height as u32),
+        }),
+        _ => None,
+    };
+
+    Snapshot {
+        swaps,
+        balances,
+        bond,
+        logs: read_log_tail().unwrap_or_default(),
+    }
+}
+
+/// Last lines of the Maker's log file.
+fn read_log_tail() -> std::io::Result<Vec<String>> {
+    let mut file = File::open(get_maker_dir().join("debug.log"))?;
+    let len = file.metadata()?.len();
+    file.seek(SeekFrom::Start(len.saturating_sub(LOG_TAIL_BYTES)))?;
+    let mut buf = Vec::new();
+    file.read_to_end(&mut buf)?;
+    let text = String::from_utf8_lossy(&buf);
+    let mut lines = text.lines().map(str::to_string).collect::<Vec<_>>();
+    // The first line is likely cut in the middle.
+    if len > LOG_TAIL_BYTES && !lines.is_empty() {
+        lines.remove(0);
+    }
+    Ok(lines)
+}
+
+fn draw(frame: &mut Frame, maker: &Maker, snapshot: &Snapshot) {
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(8),
+            Constraint::Length(snapshot.swaps.len().max(1) as u16 + 3),
+            Constraint::Min(5),
+        ])
+        .split(frame.area());
+    let top = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(40), Constraint::Percentage(60)])
+        .split(rows[0]);
+
+    let title = format!(" Maker {} | q to quit ", maker.config.network_port);
+
+    let balances = match &snapshot.balances {
+        Some(b) => vec![
+            Line::from(format!("Regular  : {}", b.regular)),
+            Line::from(format!("Swap     : {}", b.swap)),
+            Line::from(format!("Contract : {}", b.contract)),
+            Line::from(format!("Fidelity : {}", b.fidelity)),
+            Line::from(format!("Spendable: {}", b.spendable)),
+        ],
+        None => vec![Line::from("Balances unavailable")],
+    };
+    frame.render_widget(
+        Paragraph::new(balances).block(Block::default().borders(Borders::ALL).title(title)),
+        top[0],
+    );
+
+    let bond = match &snapshot.bond {
+        Some(bond) => {
+            let style = if bond.blocks_remaining <= maker.config.bond_expiry_warning_blocks {
+                Style::default().fg(Color::Red)
+            } else {
+                Style::default()
+            };
+            vec![
+                Line::from(format!("Outpoint : {}", bond.outpoint)),
+                Line::from(format!("Amount   : {}", bond.amount)),
+                Line::styled(
+                    format!(
+                        "Expires in {} blocks (~{} days)",
+                        bond.blocks_remaining,
+                        bond.blocks_remaining / 144
+                    ),
+                    style,
+                ),
+            ]
+        }
+        None => vec![Line::from("No fidelity bond yet")],
+    };
+    frame.render_widget(
+        Paragraph::new(bond).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(" Fidelity Bond "),
+        ),
+        top[1],
+    );
+
+    let swaps = Table::new(
+        snapshot.swaps.iter().map(|swap| {
+            Row::new(vec![
+                swap.id.clone(),
+                swap.phase.clone(),
+                swap.amount.to_sat().to_string(),
+                format!("{}s", swap.idle_secs),
+            ])
+        }),
+        [
+            Constraint::Length(18),
+            Constraint::Min(30),
+            Constraint::Length(14),
+            Constraint::Length(8),
+        ],
+    )
+    .header(
+        Row::new(vec!["Swap id", "Waiting for", "Amount (sats)", "Idle"])
+            .style(Style::default().add_modifier(Modifier::BOLD)),
+    )
+    .block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(format!(" Ongoing swaps ({}) ", snapshot.swaps.len())),
+    );
+    frame.render_widget(swaps, rows[1]);
+
+    // Show as many of the latest log lines as fit.
+    let log_height = rows[2].height.saturating_sub(2) as usize;
+    let logs = snapshot
+        .logs
+        .iter()
+        .skip(snapshot.logs.len().saturating_sub(log_height))
+        .map(|line| Line::from(line.as_str()))
+        .collect::<Vec<_>>();
+    frame.render_widget(
+        Paragraph::new(logs).block(Block::default().borders(Borders::ALL).title(" Logs ")),
+        rows[2],
+    );
+}
@@ -0,0 +1,152 @@
+//! Directory Server Configuration.
+
+use std::{
+    io::{self, Write},
+    path::Path,
+};
+
+use crate::utill::{ConfigMap, ConnectionType};
+
+/// Directory Server Configuration, read from its `config.toml`.
+///
+/// For reference of the config file checkout `./directory.toml` in repo folder. Each key can be overridden by a
+/// `COINSWAP_DIRECTORY_<KEY>` environment variable, e.g. `COINSWAP_DIRECTORY_PORT`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DirectoryConfig {
+    /// RPC listening port.
+    pub rpc_port: u16,
+    /// Network listening port, the `port` key.
+    pub network_port: u16,
+    /// Socks port.
+    pub socks_port: u16,
+    /// Connection type.
+    pub connection_type: ConnectionType,
+    /// Seconds a client has to send its request once connected.
+    pub handshake_timeout_secs: u64,
+    /// Maximum number of connections open at once. 0 for no limit.
+    pub max_connections: usize,
+    /// Maximum number of connections open at once from a single IP. 0 for no limit.
+    pub max_connections_per_ip: usize,
+    /// Maximum number of connections open at once from localhost, where all the Tor clients come from.
+    /// 0 for no limit.
+    pub max_tor_connections: usize,
+    /// New connections allowed per minute from a single IP. 0 for no limit.
+    pub connections_per_minute: u32,
+    /// Posts and removals allowed per hour for a single maker address. 0 for no limit.
+    pub registrations_per_hour: u32,
+    /// Port of the HTTP metrics endpoint, on localhost. 0 disables it.
+    pub metrics_port: u16,
+}
+
+impl Default for DirectoryConfig {
+    fn default() -> Self {
+        Self {
+            rpc_port: 4321,
+            network_port: 8080,
+            socks_port: 19060,
+            connection_type: {
+                #[cfg(feature = "tor")]
+                {
+                    ConnectionType::TOR
+                }
+                #[cfg(not(feature = "tor"))]
+                {
+                    ConnectionType::CLEARNET
+                }
+            },
+            handshake_timeout_secs: 10,
+            max_connections: 64,
+            max_connections_per_ip: 8,
+            max_tor_connections: 48,
+            connections_per_minute: 30,
+            registrations_per_hour: 12,
+            metrics_port: 0,
+        }
+    }
+}
+
+impl DirectoryConfig {
+    /// Loads the config file, creating a default one if it doesn't exist or is empty.
+    pub fn new(config_path: &Path) -> io::Result<Self> {
+        let default_config = Self::default();
+
+        if !config_path.exists() || std::fs::metadata(config_path)?.len() == 0 {
+            log::warn!(
+                "Directory config file not found, creating default config file at path: {}",
+                config_path.display()
+            );
+            default_config.write_to_file(config_path)?;
+        }
+
+        let config_map = ConfigMap::load(config_path, "directory_config", "COINSWAP_DIRECTORY_")?;
+
+        log::info!(
+            "Successfully loaded config file from : {}",
+            config_path.display()
+        );
+
+        let config = Self {
+            rpc_port: config_map.get("rpc_port", default_config.rpc_port)?,
+            network_port: config_map.get("port", default_config.network_port)?,
+            socks_port: config_map.get("socks_port", default_config.socks_port)?,
+            connection_type: config_map.get("connection_type", default_config.connection_type)?,
+            handshake_timeout_secs: config_map.get(
+                "handshake_timeout_secs",
+                default_config.handshake_timeout_secs,
+            )?,
+            max_connections: config_map.get("max_connections", default_config.max_connections)?,
+            max_connections_per_ip: config_map.get(
+                "max_connections_per_ip",
+                default_config.max_connections_per_ip,
+            )?,
+            max_tor_connections: config_map
+                .get("max_tor_connections", default_config.max_tor_connections)?,
+            connections_per_minute: config_map.get(
+                "connections_per_minute",
+                default_config.connections_per_minute,
+            )?,
+            registrations_per_hour: config_map.get(
+                "registrations_per_hour",
+                default_config.registrations_per_hour,
+            )?,
+            metrics_port: config_map.get("metrics_port", default_config.metrics_port)?,
+        };
+        config_map.reject_unknown_keys()?;
+        Ok(config)
+    }
+
+    // Method to serialize the DirectoryConfig into a TOML string and write it to a file
+    pub(crate) fn write_to_file(&self, path: &Path) -> io::Result<()> {
+        let toml_data = format!(
+            "port = {}
+socks_port = {}
+connection_type = {}
+rpc_port = {}
+handshake_timeout_secs = {}
+max_connections = {}
+max_connections_per_ip = {}
+max_tor_connections = {}
+connections_per_minute = {}
+registrations_per_hour = {}
+metrics_port = {}
+",
+            self.network_port,
+            self.socks_port,
+            self.connection_type,
+            self.rpc_port,
+            self.handshake_timeout_secs,
+            self.max_connections,
+            self.max_connections_per_ip,
+            self.max_tor_connections,
+            self.connections_per_minute,
+            self.registrations_per_hour,
+            self.metrics_port,
+        );
+
+        std::fs::create_dir_all(path.parent().expect("Path should NOT be root!"))?;
+        let mut file = std::fs::File::create(path)?;
+        file.write_all(toml_data.as_bytes())?;
+        file.flush()?;
+        Ok(())
+    }
+}
@@ -3,38 +3,57 @@
 //! Handles market-related logic where Makers post their offers. Also provides functions to synchronize
 //! maker addresses from directory servers, post maker addresses to directory servers,
 
-use bitcoin::{transaction::ParseOutPointError, OutPoint};
+use bitcoin::{transaction::ParseOutPointError, Amount, OutPoint, PublicKey};
 use bitcoind::bitcoincore_rpc::{self, Client, RpcApi};
 
 use crate::{
-    market::rpc::start_rpc_server_thread,
-    protocol::messages::DnsRequest,
+    market::{
+        config::DirectoryConfig,
+        listing::{rank_listings, BondMetadata, MakerListing},
+        metrics::{start_metrics_server_thread, DirectoryMetrics, MetricsReport, RejectionReason},
+        ratelimit::{limited_ip, ConnectionLimiter, RateLimiter},
+        rpc::start_rpc_server_thread,
+        snapshot::MarketSnapshot,
+        subscription::{serve_subscription, OfferBookFeed, OfferBookUpdate},
+        uptime::{MakerUptime, UptimeReport},
+    },
+    protocol::{
+        messages::{DnsRequest, IdentityProof},
+        wire::decode,
+    },
+    taker::{
+        offers::{fetch_offer_from_makers, MakerAddress},
+        routines::connect_and_handshake_maker,
+        TakerConfig,
+    },
     utill::{
-        get_dns_dir, parse_field, parse_toml, read_message, send_message, verify_fidelity_checks,
-        ConnectionType, HEART_BEAT_INTERVAL,
+        get_dns_dir, read_message_before, send_message, unix_time, verify_directory_removal,
+        verify_fidelity_checks, ConnectionType, DEFAULT_MAX_MESSAGE_SIZE, HEART_BEAT_INTERVAL,
+    },
+    wallet::{
+        calculate_fidelity_value, estimate_locktime_timestamp, verify_identity_proof, FidelityBond,
+        RPCConfig, WalletError,
     },
-    wallet::{RPCConfig, WalletError},
 };
 
 #[cfg(feature = "tor")]
 use crate::utill::{get_tor_hostname, monitor_log_for_completion};
 
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     convert::TryFrom,
-    fs::{self, File},
-    io::Write,
+    io::ErrorKind,
     net::{Ipv4Addr, TcpListener, TcpStream},
-    path::{Path, PathBuf},
+    path::PathBuf,
     sync::{
         atomic::{AtomicBool, Ordering::Relaxed},
         Arc, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard,
     },
     thread::{self, sleep},
-    time::{Duration, Instant},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
-use crate::error::NetError;
+use crate::error::{ErrorCode, NetError};
 
 /// Represents errors that may occur during directory server operations.
 #[derive(Debug)]
@@ -64,6 +83,27 @@ pub enum DirectoryServerError {
     AddressFileCorrupted(String),
 }
 
+impl ErrorCode for DirectoryServerError {
+    fn code(&self) -> u16 {
+        match self {
+            Self::IO(_) => 4000,
+            Self::Net(e) => e.code(),
+            Self::MutexPossion => 4001,
+            Self::Wallet(e) => e.code(),
+            Self::AddressFileCorrupted(_) => 4002,
+        }
+    }
+
+    fn is_transient(&self) -> bool {
+        match self {
+            Self::IO(_) => true,
+            Self::Net(e) => e.is_transient(),
+            Self::Wallet(e) => e.is_transient(),
+            _ => false,
+        }
+    }
+}
+
 impl From<WalletError> for DirectoryServerError {
     fn from(value: WalletError) -> Self {
         Self::Wallet(value)
@@ -127,30 +167,46 @@ pub struct DirectoryServer {
     pub data_dir: PathBuf,
     /// Shutdown flag to stop the directory server
     pub shutdown: AtomicBool,
-    /// A store of all the received maker addresses indexed by fidelity bond outpoints.
+    /// A store of all the received maker addresses indexed by fidelity bond outpoints, with when their
+    /// registration expires.
     pub addresses: Arc<RwLock<HashMap<OutPoint, (String, Instant)>>>,
+    /// The verified fidelity bonds of the makers, indexed by outpoint.
+    pub bonds: RwLock<HashMap<OutPoint, BondMetadata>>,
+    /// Addresses each bond was registered with before moving to its current address.
+    pub retired_addresses: RwLock<HashMap<OutPoint, HashSet<String>>>,
+    /// Bonds caught backing several maker addresses, with when they were caught.
+    pub reused_bonds: RwLock<HashMap<OutPoint, Instant>>,
+    /// Latest snapshot of the offers of the live makers.
+    pub market_snapshot: RwLock<Option<MarketSnapshot>>,
+    /// Uptime records of the live makers, indexed by address.
+    pub uptime: RwLock<UptimeReport>,
+    /// Addresses of the makers, indexed by identity key. The uptime record of a maker follows its identity.
+    pub identities: RwLock<HashMap<PublicKey, String>>,
+    /// Seconds a client has to send its request once connected.
+    pub handshake_timeout_secs: u64,
+    /// Maximum number of connections open at once. 0 for no limit.
+    pub max_connections: usize,
+    /// Maximum number of connections open at once from a single IP. 0 for no limit.
+    /// Localhost, where connections over Tor come from, has the `max_tor_connections` cap instead.
+    pub max_connections_per_ip: usize,
+    /// Maximum number of connections open at once from localhost. 0 for no limit.
+    pub max_tor_connections: usize,
+    /// New connections allowed per minute from a single IP. 0 for no limit.
+    /// Doesn't apply to localhost, where connections over Tor come from.
+    pub connections_per_minute: u32,
+    /// Posts and removals allowed per hour for a single maker address. 0 for no limit.
+    pub registrations_per_hour: u32,
+    /// Port of the HTTP metrics endpoint. 0 disables it.
+    pub metrics_port: u16,
+    /// Registrations, queries and rejections counted since the directory started.
+    pub metrics: DirectoryMetrics,
+    /// Subscriptions of the takers to the changes of the makers listed.
+    pub offerbook_feed: OfferBookFeed,
 }
 
 impl Default for DirectoryServer {
     fn default() -> Self {
-        Self {
-            rpc_port: 4321,
-            network_port: 8080,
-            socks_port: 19060,
-            connection_type: {
-                #[cfg(feature = "tor")]
-                {
-                    ConnectionType::TOR
-                }
-                #[cfg(not(feature = "tor"))]
-                {
-                    ConnectionType::CLEARNET
-                }
-            },
-            data_dir: get_dns_dir(),
-            shutdown: AtomicBool::new(false),
-            addresses: Arc::new(RwLock::new(HashMap::new())),
-        }
+        Self::from_config(DirectoryConfig::default(), get_dns_dir())
     }
 }
 
@@ -171,67 +227,202 @@ impl DirectoryServer {
     ) -> Result<Self, DirectoryServerError> {
         let data_dir = data_dir.unwrap_or(get_dns_dir());
         let config_path = data_dir.join("config.toml");
+        let mut config = DirectoryConfig::new(&config_path)?;
 
-        // This will create parent directories if they don't exist
-        if !config_path.exists() || fs::metadata(&config_path)?.len() == 0 {
-            log::warn!(
-                "Directory config file not found, creating default config file at path: {}",
-                config_path.display()
-            );
-            write_default_directory_config(&config_path)?;
+        // Update the connection type in config if given.
+        if let Some(connection_type) = connection_type {
+            config.connection_type = connection_type;
+            config.write_to_file(&config_path)?;
         }
 
-        let mut config_map = parse_toml(&config_path)?;
+        Ok(Self::from_config(config, data_dir))
+    }
 
-        log::info!(
-            "Successfully loaded config file from : {}",
-            config_path.display()
-        );
+    fn from_config(config: DirectoryConfig, data_dir: PathBuf) -> Self {
+        Self {
+            rpc_port: config.rpc_port,
+            network_port: config.network_port,
+            socks_port: config.socks_port,
+            connection_type: config.connection_type,
+            data_dir,
+            shutdown: AtomicBool::new(false),
+            addresses: Arc::new(RwLock::new(HashMap::new())),
+            bonds: RwLock::new(HashMap::new()),
+            retired_addresses: RwLock::new(HashMap::new()),
+            reused_bonds: RwLock::new(HashMap::new()),
+            market_snapshot: RwLock::new(None),
+            uptime: RwLock::new(HashMap::new()),
+            identities: RwLock::new(HashMap::new()),
+            handshake_timeout_secs: config.handshake_timeout_secs,
+            max_connections: config.max_connections,
+            max_connections_per_ip: config.max_connections_per_ip,
+            max_tor_connections: config.max_tor_connections,
+            connections_per_minute: config.connections_per_minute,
+            registrations_per_hour: config.registrations_per_hour,
+            metrics_port: config.metrics_port,
+            metrics: DirectoryMetrics::default(),
+            offerbook_feed: OfferBookFeed::default(),
+        }
+    }
 
-        // Update the connection type in config if given.
-        if let Some(conn_type) = connection_type {
-            // update the config map
-            let value = config_map.get_mut("connection_type").expect("must exist");
-            let conn_type_string = format!("{:?}", conn_type);
-            *value = conn_type_string;
-
-            // Update the file on disk
-            let mut config_file = File::create(config_path)?;
-            let mut content = String::new();
-
-            for (i, (key, value)) in config_map.iter().enumerate() {
-                // Format each line, adding a newline for all except the last one
-                content.push_str(&format!("{} = {}", key, value));
-                if i < config_map.len() - 1 {
-                    content.push('\n');
-                }
+    /// Addresses of the makers whose registration hasn't expired.
+    pub(crate) fn live_addresses(&self) -> Result<Vec<String>, DirectoryServerError> {
+        let now = Instant::now();
+        Ok(self
+            .addresses
+            .read()?
+            .values()
+            .filter(|(_, expires_at)| *expires_at > now)
+            .map(|(address, _)| address.clone())
+            .collect())
+    }
+
+    /// The metrics of the directory, with the makers it holds.
+    pub(crate) fn metrics_report(&self) -> Result<MetricsReport, DirectoryServerError> {
+        let registered_makers = self.addresses.read()?.len();
+        let active_makers = self.live_addresses()?.len();
+        Ok(self.metrics.report(registered_makers, active_makers))
+    }
+
+    /// The makers whose registration hasn't expired, with their bonds, ranked by bond value.
+    /// See [rank_listings].
+    pub(crate) fn ranked_listings(&self) -> Result<Vec<MakerListing>, DirectoryServerError> {
+        let now = Instant::now();
+        let addresses = self.addresses.read()?;
+        let bonds = self.bonds.read()?;
+        let identities = self.identities.read()?;
+        let mut listings = addresses
+            .iter()
+            .filter(|(_, (_, expires_at))| *expires_at > now)
+            .map(|(outpoint, (address, _))| MakerListing {
+                address: address.clone(),
+                bond_outpoint: *outpoint,
+                bond: bonds.get(outpoint).copied(),
+                identity: identities
+                    .iter()
+                    .find(|(_, registered)| *registered == address)
+                    .map(|(identity, _)| *identity),
+            })
+            .collect::<Vec<_>>();
+        rank_listings(&mut listings);
+        Ok(listings)
+    }
+
+    /// Checks a verified registration of `address` with a bond for bond reuse. Returns whether the bond is reused.
+    ///
+    /// A maker moving to a new address leaves its old address behind for good. A bond registered again with an
+    /// address it already left backs several live makers: the bond is dropped from the directory, and its
+    /// registrations refused for [BOND_REUSE_BAN].
+    pub(crate) fn detect_bond_reuse(
+        &self,
+        outpoint: &OutPoint,
+        address: &str,
+    ) -> Result<bool, DirectoryServerError> {
+        {
+            let mut reused_bonds = self.reused_bonds.write()?;
+            reused_bonds.retain(|_, caught| caught.elapsed() < BOND_REUSE_BAN);
+            if reused_bonds.contains_key(outpoint) {
+                return Ok(true);
             }
+        }
 
-            config_file.write_all(content.as_bytes())?;
+        let current = self
+            .addresses
+            .read()?
+            .get(outpoint)
+            .map(|(current, _)| current.clone());
+        let reused = {
+            let mut retired_addresses = self.retired_addresses.write()?;
+            let retired = retired_addresses.entry(*outpoint).or_default();
+            if retired.contains(address) {
+                retired_addresses.remove(outpoint);
+                true
+            } else {
+                if let Some(current) = current {
+                    if current != address {
+                        retired.insert(current);
+                    }
+                }
+                false
+            }
+        };
+        if reused {
+            log::warn!(
+                "Bond {} reused by several makers, last from {} | Dropping it",
+                outpoint,
+                address
+            );
+            self.reused_bonds.write()?.insert(*outpoint, Instant::now());
+            if let Some((address, _)) = self.addresses.write()?.remove(outpoint) {
+                self.uptime.write()?.remove(&address);
+                self.identities.write()?.retain(|_, a| *a != address);
+            }
+            self.bonds.write()?.remove(outpoint);
         }
+        Ok(reused)
+    }
 
-        let addresses = Arc::new(RwLock::new(HashMap::new()));
-        let default_dns = Self::default();
+    /// Removes a maker, registered with this fidelity bond at this address, along with its uptime record.
+    pub(crate) fn remove_address(
+        &self,
+        outpoint: &OutPoint,
+        address: &str,
+    ) -> Result<(), DirectoryServerError> {
+        let mut write_lock = self.addresses.write()?;
+        if matches!(write_lock.get(outpoint), Some((addr, _)) if addr == address) {
+            write_lock.remove(outpoint);
+            self.bonds.write()?.remove(outpoint);
+            self.uptime.write()?.remove(address);
+            self.identities.write()?.retain(|_, a| a != address);
+            self.offerbook_feed
+                .publish(OfferBookUpdate::Left(address.to_string()));
+            log::info!(
+                "Removed maker info: Fidelity {} | Address {}",
+                outpoint,
+                address
+            );
+        }
+        Ok(())
+    }
 
-        Ok(DirectoryServer {
-            rpc_port: parse_field(config_map.get("rpc_port"), default_dns.rpc_port),
-            network_port: parse_field(config_map.get("port"), default_dns.network_port),
-            socks_port: parse_field(config_map.get("socks_port"), default_dns.socks_port),
-            data_dir,
-            shutdown: AtomicBool::new(false),
-            connection_type: parse_field(
-                config_map.get("connection_type"),
-                default_dns.connection_type,
-            ),
-            addresses,
-        })
+    /// Records the identity of the maker registered at `address`. The uptime record of the identity, or of the
+    /// identity it rotated from, moves along to the address.
+    pub(crate) fn record_identity(
+        &self,
+        proof: &IdentityProof,
+        address: &str,
+    ) -> Result<(), DirectoryServerError> {
+        let identity = proof.binding.identity;
+        let mut identities = self.identities.write()?;
+        let rotated_from = proof
+            .rotation
+            .as_ref()
+            .and_then(|rotation| identities.remove(&rotation.identity));
+        let previous_address = rotated_from.or_else(|| identities.get(&identity).cloned());
+        if let Some(previous_address) = previous_address.filter(|previous| previous != address) {
+            let mut uptime = self.uptime.write()?;
+            if let Some(record) = uptime.remove(&previous_address) {
+                log::info!(
+                    "Moving the uptime record of identity {} from {} to {}",
+                    identity,
+                    previous_address,
+                    address
+                );
+                uptime.insert(address.to_string(), record);
+            }
+        }
+        identities.insert(identity, address.to_string());
+        Ok(())
     }
 
     /// Updates the in-memory address map. If entry already exists, updates the value. If new entry, inserts the value.
+    /// The registration expires after `ttl`, unless updated again.
     pub fn updated_address_map(
         &self,
         metadata: (String, OutPoint),
+        ttl: Duration,
     ) -> Result<(), DirectoryServerError> {
+        let expires_at = Instant::now() + ttl;
         let mut write_lock = self.addresses.write()?;
         // Check if the value exists with a different key
         if let Some(existing_key) =
@@ -248,7 +439,9 @@ impl DirectoryServer {
                     metadata.1
                 );
                 write_lock.remove(&existing_key);
-                write_lock.insert(metadata.1, (metadata.0, Instant::now()));
+                self.offerbook_feed
+                    .publish(OfferBookUpdate::Updated(metadata.0.clone()));
+                write_lock.insert(metadata.1, (metadata.0, expires_at));
             } else {
                 log::info!(
                     "Maker data already exist for {} | restarted counter",
@@ -256,13 +449,13 @@ impl DirectoryServer {
                 );
                 write_lock
                     .entry(metadata.1)
-                    .and_modify(|(_, instant)| *instant = Instant::now());
+                    .and_modify(|(_, expiry)| *expiry = expires_at);
             }
         } else if write_lock.contains_key(&metadata.1) {
             // Update the address for the existing fidelity
             if write_lock[&metadata.1].0 != metadata.0 {
                 let old_addr = write_lock
-                    .insert(metadata.1, (metadata.0.clone(), Instant::now()))
+                    .insert(metadata.1, (metadata.0.clone(), expires_at))
                     .expect("value expected");
                 log::info!(
                     "Address updated for fidelity: {} | old address {:?} | new address {}",
@@ -270,6 +463,10 @@ impl DirectoryServer {
                     old_addr,
                     metadata.0
                 );
+                self.offerbook_feed
+                    .publish(OfferBookUpdate::Left(old_addr.0));
+                self.offerbook_feed
+                    .publish(OfferBookUpdate::Joined(metadata.0));
             } else {
                 log::info!(
                     "Maker data already exist for {} | restarted counter",
@@ -277,36 +474,31 @@ impl DirectoryServer {
                 );
                 write_lock
                     .entry(metadata.1)
-                    .and_modify(|(_, instant)| *instant = Instant::now());
+                    .and_modify(|(_, expiry)| *expiry = expires_at);
             }
         } else {
             // Add a new entry if both fidelity and address are new
-            write_lock.insert(metadata.1, (metadata.0.clone(), Instant::now()));
+            write_lock.insert(metadata.1, (metadata.0.clone(), expires_at));
             log::info!(
                 "Added new maker info: Fidelity {} | Address {}",
                 metadata.1,
                 metadata.0
             );
+            self.offerbook_feed
+                .publish(OfferBookUpdate::Joined(metadata.0));
         }
         Ok(())
     }
 }
 
-fn write_default_directory_config(config_path: &Path) -> Result<(), DirectoryServerError> {
-    let config_string = String::from(
-        "\
-            port = 8080\n\
-            socks_port = 19060\n\
-            connection_type = tor\n\
-            rpc_port = 4321\n\
-            ",
-    );
-    std::fs::create_dir_all(config_path.parent().expect("Path should NOT be root!"))?;
-    let mut file = File::create(config_path)?;
-    file.write_all(config_string.as_bytes())?;
-    file.flush()?;
-    Ok(())
-}
+/// How long a registration lasts when the maker doesn't set its expiry.
+pub(crate) const DEFAULT_REGISTRATION_TTL: Duration = Duration::from_secs(30 * 60);
+
+/// Longest a registration lasts, whatever expiry the maker sets, so that dead makers are dropped.
+pub(crate) const MAX_REGISTRATION_TTL: Duration = Duration::from_secs(2 * 60 * 60);
+
+/// How long the registrations of a bond caught backing several makers are refused.
+pub(crate) const BOND_REUSE_BAN: Duration = Duration::from_secs(24 * 60 * 60);
 
 pub(crate) fn start_address_writer_thread(
     directory: Arc<DirectoryServer>,
@@ -315,22 +507,128 @@ pub(crate) fn start_address_writer_thread(
     loop {
         sleep(Duration::from_secs(interval));
         let mut directory_address_book = directory.addresses.write()?;
-        let ttl = Duration::from_secs(60 * 30);
+        let now = Instant::now();
 
         let expired_outpoints: Vec<_> = directory_address_book
             .iter()
-            .filter(|(_, (_, timestamp))| timestamp.elapsed() > ttl)
+            .filter(|(_, (_, expires_at))| *expires_at <= now)
             .map(|(outpoint, _)| *outpoint)
             .collect();
         for outpoint in &expired_outpoints {
             log::info!(
-                "No update for 30 mins from maker with fidelity : {}",
+                "Registration expired for maker with fidelity : {}",
                 outpoint
             );
-            directory_address_book.remove(outpoint);
+            if let Some((address, _)) = directory_address_book.remove(outpoint) {
+                directory
+                    .offerbook_feed
+                    .publish(OfferBookUpdate::Left(address));
+            }
             log::info!("Maker entry removed");
         }
+        directory
+            .bonds
+            .write()?
+            .retain(|outpoint, _| directory_address_book.contains_key(outpoint));
+        directory
+            .retired_addresses
+            .write()?
+            .retain(|outpoint, _| directory_address_book.contains_key(outpoint));
+    }
+}
+
+/// Refreshes the market snapshot with the offers of all live makers, every 15 minutes.
+pub(crate) fn start_market_snapshot_thread(
+    directory: Arc<DirectoryServer>,
+) -> Result<(), DirectoryServerError> {
+    let interval = Duration::from_secs(60 * 15);
+    let config = TakerConfig {
+        socks_port: directory.socks_port,
+        connection_type: directory.connection_type,
+        ..TakerConfig::default()
+    };
+
+    let mut last_refresh: Option<Instant> = None;
+    while !directory.shutdown.load(Relaxed) {
+        sleep(HEART_BEAT_INTERVAL);
+        if matches!(last_refresh, Some(t) if t.elapsed() < interval) {
+            continue;
+        }
+
+        let addresses = directory
+            .live_addresses()?
+            .iter()
+            .filter_map(|address| MakerAddress::new(address).ok())
+            .collect::<Vec<_>>();
+        // Wait for the first makers to post.
+        if addresses.is_empty() {
+            continue;
+        }
+        last_refresh = Some(Instant::now());
+
+        match fetch_offer_from_makers(addresses, &config) {
+            Ok(offers) => {
+                let snapshot = MarketSnapshot::new(&offers);
+                log::info!(
+                    "Market snapshot taken | {} makers | liquidity {} sats",
+                    snapshot.makers.len(),
+                    snapshot.total_liquidity_sats()
+                );
+                *directory.market_snapshot.write()? = Some(snapshot);
+            }
+            Err(e) => log::error!("Failed fetching offers for the market snapshot: {:?}", e),
+        }
+    }
+    Ok(())
+}
+
+/// Probes every live maker with a handshake, every 5 minutes, and records the results in the uptime records.
+pub(crate) fn start_uptime_probe_thread(
+    directory: Arc<DirectoryServer>,
+) -> Result<(), DirectoryServerError> {
+    let interval = if cfg!(feature = "integration-test") {
+        Duration::from_secs(10)
+    } else {
+        Duration::from_secs(60 * 5)
+    };
+    let config = TakerConfig {
+        socks_port: directory.socks_port,
+        connection_type: directory.connection_type,
+        ..TakerConfig::default()
+    };
+
+    let mut last_probe: Option<Instant> = None;
+    while !directory.shutdown.load(Relaxed) {
+        sleep(HEART_BEAT_INTERVAL);
+        if matches!(last_probe, Some(t) if t.elapsed() < interval) {
+            continue;
+        }
+        last_probe = Some(Instant::now());
+
+        let addresses = directory.live_addresses()?;
+        let mut results = Vec::new();
+        for address in &addresses {
+            let reachable = match MakerAddress::new(address) {
+                Ok(maker_addr) => connect_and_handshake_maker(&maker_addr, &config).is_ok(),
+                Err(_) => false,
+            };
+            if !reachable {
+                log::info!("Uptime probe failed for maker {}", address);
+            }
+            results.push((address, reachable));
+        }
+
+        let mut uptime = directory.uptime.write()?;
+        // Forget the makers which are gone from the address book.
+        uptime.retain(|address, _| addresses.contains(address));
+        for (address, reachable) in results {
+            uptime
+                .entry(address.clone())
+                .or_insert_with(MakerUptime::default)
+                .record_probe(reachable);
+        }
     }
+    Ok(())
 }
 
 /// Initializes and starts the Directory Server with the provided configuration.
@@ -339,8 +637,9 @@ pub(crate) fn start_address_writer_thread(
 /// It handles both Clearnet and Tor connections (if the `tor` feature is enabled) and performs the following tasks:
 ///
 /// - Sets up the Directory Server for the appropriate connection type.
-/// - Spawns threads for handling RPC requests and writing address data to disk.
-/// - Monitors and manages incoming TCP connections.
+/// - Spawns threads for handling RPC requests, writing address data to disk, and probing the makers' uptime.
+/// - Monitors and manages incoming TCP connections, each on its own thread, within the connection caps and
+///   rate limits of the directory config.
 /// - Handles shutdown signals gracefully, ensuring all threads are terminated and resources are cleaned up.
 ///
 pub fn start_directory_server(
@@ -352,7 +651,7 @@ pub fn start_directory_server(
 
     let rpc_config = rpc_config.unwrap_or_default();
 
-    let rpc_client = bitcoincore_rpc::Client::try_from(&rpc_config)?;
+    let rpc_client = Arc::new(bitcoincore_rpc::Client::try_from(&rpc_config)?);
 
     // Stop early if bitcoin core connection is wrong
     if let Err(e) = rpc_client.get_blockchain_info() {
@@ -371,7 +670,7 @@ pub fn start_directory_server(
                 let tor_dir = directory.data_dir.join("tor");
                 let log_file = tor_dir.join("log");
                 if log_file.exists() {
-                    match fs::remove_file(&log_file) {
+                    match std::fs::remove_file(&log_file) {
                         Ok(_) => log::info!("Previous tor log file deleted successfully"),
                         Err(_) => log::error!("Error deleting tor log file"),
                     }
@@ -415,25 +714,102 @@ pub fn start_directory_server(
         start_address_writer_thread(directory_clone)
     });
 
+    let directory_clone = directory.clone();
+    let market_snapshot_thread = thread::spawn(move || {
+        log::info!("Spawning Market Snapshot Thread");
+        start_market_snapshot_thread(directory_clone)
+    });
+
+    let directory_clone = directory.clone();
+    let uptime_probe_thread = thread::spawn(move || {
+        log::info!("Spawning Uptime Probe Thread");
+        start_uptime_probe_thread(directory_clone)
+    });
+
+    let metrics_thread = if directory.metrics_port != 0 {
+        let directory_clone = directory.clone();
+        Some(thread::spawn(move || {
+            log::info!("Spawning Metrics Server Thread");
+            start_metrics_server_thread(directory_clone)
+        }))
+    } else {
+        None
+    };
+
     let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, directory.network_port))?;
+    listener.set_nonblocking(true)?;
+
+    let connections = Arc::new(ConnectionLimiter::new(
+        directory.max_connections,
+        directory.max_connections_per_ip,
+        directory.max_tor_connections,
+    ));
+    let connection_rate =
+        RateLimiter::new(directory.connections_per_minute, Duration::from_secs(60));
+    let registrations = Arc::new(RateLimiter::new(
+        directory.registrations_per_hour,
+        Duration::from_secs(60 * 60),
+    ));
+    let handshake_timeout = Duration::from_secs(directory.handshake_timeout_secs);
 
     while !directory.shutdown.load(Relaxed) {
         match listener.accept() {
-            Ok((mut stream, _)) => {
-                stream.set_read_timeout(Some(Duration::from_secs(60)))?;
-                stream.set_write_timeout(Some(Duration::from_secs(60)))?;
-                if let Err(e) = handle_client(&mut stream, &directory, &rpc_client) {
-                    log::error!("Error accepting incoming connection: {:?}", e);
+            Ok((mut stream, peer)) => {
+                if let Some(ip) = limited_ip(peer.ip()) {
+                    if !connection_rate.allow(&ip.to_string()) {
+                        log::warn!("Connection rate limit reached for {}, dropping", ip);
+                        directory.metrics.rejected(RejectionReason::ConnectionRate);
+                        continue;
+                    }
+                }
+                let slot = match connections.try_acquire(peer.ip()) {
+                    Some(slot) => slot,
+                    None => {
+                        log::warn!(
+                            "Connection limit reached, dropping connection from {}",
+                            peer
+                        );
+                        directory.metrics.rejected(RejectionReason::ConnectionLimit);
+                        continue;
+                    }
+                };
+
+                // The accepted socket may inherit the listener's non-blocking mode.
+                if let Err(e) = stream
+                    .set_nonblocking(false)
+                    .and_then(|_| stream.set_write_timeout(Some(Duration::from_secs(60))))
+                {
+                    log::error!("Error setting up connection from {}: {:?}", peer, e);
+                    continue;
                 }
+                // The whole request must come within the handshake timeout.
+                let handshake_deadline = Instant::now() + handshake_timeout;
+
+                let directory = directory.clone();
+                let rpc_client = rpc_client.clone();
+                let registrations = registrations.clone();
+                thread::spawn(move || {
+                    // Holds the connection slot until the client is served.
+                    let _slot = slot;
+                    if let Err(e) = handle_client(
+                        &mut stream,
+                        handshake_deadline,
+                        &directory,
+                        &rpc_client,
+                        &registrations,
+                    ) {
+                        log::error!("Error handling client {}: {:?}", peer, e);
+                    }
+                });
             }
 
-            // If no connection received, check for shutdown or save addresses to disk
+            // If no connection received, check for shutdown
+            Err(e) if e.kind() == ErrorKind::WouldBlock => sleep(HEART_BEAT_INTERVAL),
             Err(e) => {
                 log::error!("Error accepting incoming connection: {:?}", e);
+                sleep(HEART_BEAT_INTERVAL);
             }
         }
-
-        sleep(HEART_BEAT_INTERVAL);
     }
 
     log::info!("Shutdown signal received. Stopping directory server.");
@@ -445,6 +821,15 @@ pub fn start_directory_server(
     if let Err(e) = address_writer_thread.join() {
         log::error!("Error closing Address Writer Thread : {:?}", e);
     }
+    if let Err(e) = market_snapshot_thread.join() {
+        log::error!("Error closing Market Snapshot Thread : {:?}", e);
+    }
+    if let Err(e) = uptime_probe_thread.join() {
+        log::error!("Error closing Uptime Probe Thread : {:?}", e);
+    }
+    if let Some(Err(e)) = metrics_thread.map(|thread| thread.join()) {
+        log::error!("Error closing Metrics Server Thread : {:?}", e);
+    }
 
     #[cfg(feature = "tor")]
     {
@@ -457,21 +842,46 @@ pub fn start_directory_server(
     Ok(())
 }
 
-// The stream should have read and write timeout set.
+// The stream should have a write timeout set. The request must be received before the `handshake_deadline`.
+// Posts and removals of a maker address are limited by `registrations`, once their fidelity bond verified.
 fn handle_client(
     stream: &mut TcpStream,
+    handshake_deadline: Instant,
     directory: &Arc<DirectoryServer>,
     rpc: &Client,
+    registrations: &RateLimiter,
 ) -> Result<(), DirectoryServerError> {
-    let buf = read_message(&mut stream.try_clone()?)?;
-    let dns_request: DnsRequest = serde_cbor::de::from_reader(&buf[..])?;
+    let dns_request =
+        match read_message_before(stream, DEFAULT_MAX_MESSAGE_SIZE, handshake_deadline)
+            .and_then(|buf| decode::<DnsRequest>(&buf))
+        {
+            Ok(dns_request) => dns_request,
+            Err(e) => {
+                directory
+                    .metrics
+                    .rejected(RejectionReason::MalformedRequest);
+                return Err(e.into());
+            }
+        };
     match dns_request {
         DnsRequest::Post { metadata } => {
             log::info!("Received POST | From {}", &metadata.url);
+            if metadata.expires_at != 0 && metadata.expires_at <= unix_time() {
+                log::warn!("Dropping expired registration of {}", metadata.url);
+                directory
+                    .metrics
+                    .rejected(RejectionReason::ExpiredRegistration);
+                return Ok(());
+            }
 
             let txid = metadata.proof.bond.outpoint.txid;
             let transaction = rpc.get_raw_transaction(&txid, None)?;
             let current_height = rpc.get_block_count()?;
+            // The amount on chain, not the one claimed in the bond.
+            let bond_amount = transaction
+                .output
+                .get(metadata.proof.bond.outpoint.vout as usize)
+                .map(|txout| txout.value);
 
             match verify_fidelity_checks(
                 &metadata.proof,
@@ -480,11 +890,58 @@ fn handle_client(
                 current_height,
             ) {
                 Ok(_) => {
+                    if !registrations.allow(&metadata.url) {
+                        log::warn!("Registration rate limit reached for {}", metadata.url);
+                        directory
+                            .metrics
+                            .rejected(RejectionReason::RegistrationRate);
+                        return Ok(());
+                    }
+                    if directory.detect_bond_reuse(&metadata.proof.bond.outpoint, &metadata.url)? {
+                        log::warn!(
+                            "Refusing {}, its bond {} backs several makers",
+                            metadata.url,
+                            metadata.proof.bond.outpoint
+                        );
+                        directory.metrics.rejected(RejectionReason::BondReuse);
+                        return Ok(());
+                    }
+                    if let Some(identity) = &metadata.identity {
+                        if let Err(e) = verify_identity_proof(
+                            identity,
+                            &metadata.proof.bond,
+                            &metadata.url,
+                            metadata.expires_at,
+                        ) {
+                            log::warn!("Invalid identity from {}: {:?}", metadata.url, e);
+                            directory.metrics.rejected(RejectionReason::InvalidIdentity);
+                            return Ok(());
+                        }
+                        directory.record_identity(identity, &metadata.url)?;
+                    }
                     log::info!(
                         "Fidelity verification success from {}. Adding/updating to address data.",
                         metadata.url
                     );
-                    directory.updated_address_map((metadata.url, metadata.proof.bond.outpoint))?;
+                    let bond = verified_bond_metadata(
+                        rpc,
+                        &metadata.proof.bond,
+                        bond_amount.unwrap_or(Amount::ZERO),
+                    )?;
+                    log::info!(
+                        "Bond {} of {} valued {} sats",
+                        metadata.proof.bond.outpoint,
+                        metadata.url,
+                        bond.value.to_sat()
+                    );
+                    directory
+                        .bonds
+                        .write()?
+                        .insert(metadata.proof.bond.outpoint, bond);
+                    let ttl = registration_ttl(metadata.expires_at, unix_time());
+                    directory
+                        .updated_address_map((metadata.url, metadata.proof.bond.outpoint), ttl)?;
+                    directory.metrics.registered();
                 }
                 Err(e) => {
                     log::error!(
@@ -492,22 +949,107 @@ fn handle_client(
                         metadata.url,
                         e
                     );
+                    directory.metrics.rejected(RejectionReason::InvalidBond);
+                }
+            }
+        }
+        DnsRequest::Remove {
+            metadata,
+            timestamp,
+            sig,
+        } => {
+            log::info!("Received REMOVE | From {}", &metadata.url);
+
+            let txid = metadata.proof.bond.outpoint.txid;
+            let transaction = rpc.get_raw_transaction(&txid, None)?;
+            let current_height = rpc.get_block_count()?;
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default();
+
+            match verify_fidelity_checks(
+                &metadata.proof,
+                &metadata.url,
+                transaction,
+                current_height,
+            )
+            .and_then(|_| {
+                verify_directory_removal(&metadata.proof, &metadata.url, timestamp, &sig, now)
+            }) {
+                Ok(_) => {
+                    if !registrations.allow(&metadata.url) {
+                        log::warn!("Registration rate limit reached for {}", metadata.url);
+                        directory
+                            .metrics
+                            .rejected(RejectionReason::RegistrationRate);
+                        return Ok(());
+                    }
+                    directory.remove_address(&metadata.proof.bond.outpoint, &metadata.url)?;
+                    directory.metrics.removed();
+                }
+                Err(e) => {
+                    log::error!("Invalid removal request for {:?} | {:?}", metadata.url, e);
+                    directory.metrics.rejected(RejectionReason::InvalidRemoval);
                 }
             }
         }
         DnsRequest::Get => {
             log::info!("Received GET");
+            directory.metrics.queried();
 
-            let addresses = directory.addresses.read()?;
-
-            let response = addresses
+            // Highest bond values first.
+            let response = directory
+                .ranked_listings()?
                 .iter()
-                .filter(|(_, (_, timestamp))| timestamp.elapsed() <= Duration::from_secs(30 * 60))
-                .fold(String::new(), |acc, (_, addr)| acc + &addr.0 + "\n");
+                .fold(String::new(), |acc, listing| acc + &listing.address + "\n");
 
             log::debug!("Sending Addresses: {}", response);
             send_message(stream, &response)?;
         }
+        DnsRequest::Listings => {
+            log::info!("Received LISTINGS");
+            directory.metrics.queried();
+
+            let listings = directory.ranked_listings()?;
+            send_message(stream, &listings)?;
+        }
+        DnsRequest::Snapshot => {
+            log::info!("Received SNAPSHOT");
+            directory.metrics.queried();
+
+            let snapshot = directory.market_snapshot.read()?.clone();
+            send_message(stream, &snapshot)?;
+        }
+        DnsRequest::Uptime => {
+            log::info!("Received UPTIME");
+            directory.metrics.queried();
+
+            let uptime = directory.uptime.read()?.clone();
+            send_message(stream, &uptime)?;
+        }
+        DnsRequest::Subscribe => {
+            log::info!("Received SUBSCRIBE");
+            let updates = match directory.offerbook_feed.subscribe() {
+                Some(updates) => updates,
+                None => {
+                    log::warn!("Subscriber limit reached, refusing the subscription");
+                    directory.metrics.rejected(RejectionReason::SubscriberLimit);
+                    return Ok(());
+                }
+            };
+            directory.metrics.queried();
+
+            // Subscribed first, so that no change is missed between the two.
+            let addresses = directory
+                .ranked_listings()?
+                .into_iter()
+                .map(|listing| listing.address)
+                .collect::<Vec<_>>();
+            send_message(stream, &addresses)?;
+            serve_subscription(stream, directory, updates)?;
+            log::info!("Subscription closed");
+        }
         #[cfg(feature = "integration-test")]
         // Used for IT, only checks the updated_address_map() function.
         DnsRequest::Dummy { url, vout } => {
@@ -522,16 +1064,66 @@ fn handle_client(
             .unwrap();
             let fidelity_op = OutPoint::new(txid, vout);
 
-            directory.updated_address_map((url, fidelity_op))?;
+            directory.updated_address_map((url, fidelity_op), DEFAULT_REGISTRATION_TTL)?;
         }
     }
     Ok(())
 }
 
+/// How long a registration expiring at unix time `expires_at` lasts, from unix time `now`.
+/// 0 for the [DEFAULT_REGISTRATION_TTL], and capped at [MAX_REGISTRATION_TTL].
+fn registration_ttl(expires_at: u64, now: u64) -> Duration {
+    if expires_at == 0 {
+        return DEFAULT_REGISTRATION_TTL;
+    }
+    Duration::from_secs(expires_at.saturating_sub(now)).min(MAX_REGISTRATION_TTL)
+}
+
+/// The bond metadata of a verified bond, holding `amount` on chain. Valued like makers value their bonds,
+/// from the confirmation time of the bond tx, or now if unconfirmed.
+fn verified_bond_metadata(
+    rpc: &Client,
+    bond: &FidelityBond,
+    amount: Amount,
+) -> Result<BondMetadata, DirectoryServerError> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let tip = rpc.get_block_header_info(&rpc.get_best_block_hash()?)?;
+    let (confirmation_height, confirmation_time) = match rpc
+        .get_raw_transaction_info(&bond.outpoint.txid, None)?
+        .blockhash
+    {
+        Some(blockhash) => {
+            let header = rpc.get_block_header_info(&blockhash)?;
+            (Some(header.height as u32), header.time as u64)
+        }
+        None => (None, now),
+    };
+    let value =
+        match estimate_locktime_timestamp(bond.lock_time, tip.height as u64, tip.time as u64) {
+            Some(locktime) => calculate_fidelity_value(
+                amount,
+                locktime.max(confirmation_time),
+                confirmation_time,
+                now,
+            ),
+            None => Amount::ZERO,
+        };
+    Ok(BondMetadata {
+        amount,
+        lock_time: bond.lock_time,
+        confirmation_height,
+        value,
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use bitcoind::tempfile::TempDir;
+    use std::str::FromStr;
 
     fn create_temp_config(contents: &str, temp_dir: &TempDir) -> PathBuf {
         let config_path = temp_dir.path().join("config.toml");
@@ -569,6 +1161,14 @@ mod tests {
 
         assert_eq!(dns.network_port, 8080);
         assert_eq!(dns.socks_port, DirectoryServer::default().socks_port);
+        assert_eq!(
+            dns.max_connections_per_ip,
+            DirectoryServer::default().max_connections_per_ip
+        );
+        assert_eq!(
+            dns.registrations_per_hour,
+            DirectoryServer::default().registrations_per_hour
+        );
 
         temp_dir.close().unwrap();
     }
@@ -581,15 +1181,72 @@ mod tests {
             port = "not_a_number"
         "#;
         create_temp_config(contents, &temp_dir);
-        let dns = DirectoryServer::new(Some(temp_dir.path().to_path_buf()), None).unwrap();
-        let default_dns = DirectoryServer::default();
-
-        assert_eq!(dns.network_port, default_dns.network_port);
-        assert_eq!(dns.socks_port, default_dns.socks_port);
+        match DirectoryServer::new(Some(temp_dir.path().to_path_buf()), None) {
+            Err(DirectoryServerError::IO(e)) => assert!(e.to_string().contains("`port`")),
+            other => panic!("Expected an invalid config error, got {:?}", other.err()),
+        }
 
         temp_dir.close().unwrap();
     }
 
+    #[test]
+    fn test_bond_reuse() {
+        let directory = DirectoryServer::default();
+        let bond = OutPoint::new(
+            bitcoin::Txid::from_str(
+                "c3a04e4bdf3c8684c5cf5c8b2f3c43009670bc194ac6c856b3ec9d3a7a6e2602",
+            )
+            .unwrap(),
+            0,
+        );
+
+        assert!(!directory.detect_bond_reuse(&bond, "a.onion:6102").unwrap());
+        directory
+            .updated_address_map(("a.onion:6102".to_string(), bond), DEFAULT_REGISTRATION_TTL)
+            .unwrap();
+        // Moving to a new address is fine.
+        assert!(!directory.detect_bond_reuse(&bond, "b.onion:6102").unwrap());
+        directory
+            .updated_address_map(("b.onion:6102".to_string(), bond), DEFAULT_REGISTRATION_TTL)
+            .unwrap();
+        assert!(!directory.detect_bond_reuse(&bond, "b.onion:6102").unwrap());
+
+        // Coming back to the old one means both are live.
+        assert!(directory.detect_bond_reuse(&bond, "a.onion:6102").unwrap());
+        assert!(directory.addresses.read().unwrap().is_empty());
+        // The bond stays refused, from any address.
+        assert!(directory.detect_bond_reuse(&bond, "c.onion:6102").unwrap());
+    }
+
+    #[test]
+    fn test_registration_expiry() {
+        let directory = DirectoryServer::default();
+        let bond = |vout| {
+            OutPoint::new(
+                bitcoin::Txid::from_str(
+                    "c3a04e4bdf3c8684c5cf5c8b2f3c43009670bc194ac6c856b3ec9d3a7a6e2602",
+                )
+                .unwrap(),
+                vout,
+            )
+        };
+        directory
+            .updated_address_map(
+                ("live.onion:6102".to_string(), bond(0)),
+                DEFAULT_REGISTRATION_TTL,
+            )
+            .unwrap();
+        directory
+            .updated_address_map(("dead.onion:6102".to_string(), bond(1)), Duration::ZERO)
+            .unwrap();
+        assert_eq!(directory.live_addresses().unwrap(), vec!["live.onion:6102"]);
+
+        assert_eq!(registration_ttl(0, 1_000), DEFAULT_REGISTRATION_TTL);
+        assert_eq!(registration_ttl(1_600, 1_000), Duration::from_secs(600));
+        assert_eq!(registration_ttl(500, 1_000), Duration::ZERO);
+        assert_eq!(registration_ttl(u64::MAX, 1_000), MAX_REGISTRATION_TTL);
+    }
+
     #[test]
     fn test_missing_file() {
         let temp_dir = TempDir::new().unwrap();
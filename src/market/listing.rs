@@ -0,0 +1,105 @@
+//! Bond-weighted maker listings.
+//!
+//! The directory serves its makers ordered by the value of their verified fidelity bond, highest first, so
+//! takers reading only the first entries favor the makers with the most coins at stake. Makers with bonds of
+//! the same value are shuffled, so no maker is favored by its registration order.
+//!
+//! The bond value is computed by the directory when it verifies a registration, from the bond tx found on
+//! chain, not from the values claimed by the maker.
+
+#[cfg(feature = "directory")]
+use bitcoin::secp256k1::rand::{seq::SliceRandom, thread_rng};
+use bitcoin::{absolute::LockTime, Amount, OutPoint, PublicKey};
+use serde::{Deserialize, Serialize};
+
+/// A fidelity bond, as verified by the directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BondMetadata {
+    /// Amount locked in the bond.
+    pub amount: Amount,
+    /// Timelock of the bond.
+    pub lock_time: LockTime,
+    /// Height the bond tx confirmed at. None if it was unconfirmed at verification.
+    pub confirmation_height: Option<u32>,
+    /// Value of the bond, by the JoinMarket bond value formula the makers use too.
+    pub value: Amount,
+}
+
+/// A maker registered at the directory, with its fidelity bond.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MakerListing {
+    /// Address of the maker.
+    pub address: String,
+    /// Outpoint of the maker's fidelity bond.
+    pub bond_outpoint: OutPoint,
+    /// The verified bond. None for makers registered without verification, in tests.
+    pub bond: Option<BondMetadata>,
+    /// Identity key of the maker. None for makers registered without one.
+    #[serde(default)]
+    pub identity: Option<PublicKey>,
+}
+
+impl MakerListing {
+    /// Value of the maker's bond. Zero if unverified.
+    pub fn bond_value(&self) -> Amount {
+        self.bond.map_or(Amount::ZERO, |bond| bond.value)
+    }
+}
+
+/// Orders listings by bond value, highest first, in random order among equal values.
+#[cfg(feature = "directory")]
+pub(crate) fn rank_listings(listings: &mut [MakerListing]) {
+    listings.shuffle(&mut thread_rng());
+    // Stable sort, the shuffled order is kept among equal values.
+    listings.sort_by_key(|listing| std::cmp::Reverse(listing.bond_value()));
+}
+
+#[cfg(all(test, feature = "directory"))]
+mod tests {
+    use std::str::FromStr;
+
+    use bitcoin::Txid;
+
+    use super::*;
+
+    fn listing(address: &str, value: Option<u64>) -> MakerListing {
+        MakerListing {
+            address: address.to_string(),
+            bond_outpoint: OutPoint::new(
+                Txid::from_str("c3a04e4bdf3c8684c5cf5c8b2f3c43009670bc194ac6c856b3ec9d3a7a6e2602")
+                    .unwrap(),
+                0,
+            ),
+            bond: value.map(|value| BondMetadata {
+                amount: Amount::from_sat(50_000),
+                lock_time: LockTime::from_height(1_000).unwrap(),
+                confirmation_height: Some(100),
+                value: Amount::from_sat(value),
+            }),
+            identity: None,
+        }
+    }
+
+    #[test]
+    fn test_rank_listings() {
+        let mut listings = vec![
+            listing("unverified", None),
+            listing("small", Some(10)),
+            listing("tie-a", Some(500)),
+            listing("large", Some(1_000)),
+            listing("tie-b", Some(500)),
+        ];
+        rank_listings(&mut listings);
+
+        let order = listings
+            .iter()
+            .map(|listing| listing.address.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(order[0], "large");
+        assert!(matches!(
+            (order[1], order[2]),
+            ("tie-a", "tie-b") | ("tie-b", "tie-a")
+        ));
+        assert_eq!(&order[3..], ["small", "unverified"]);
+    }
+}
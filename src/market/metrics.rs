@@ -0,0 +1,367 @@
+//! Metrics of the directory server.
+//!
+//! The directory counts the registrations, removals and queries it serves, and the requests it rejects, by
+//! reason. With the number of registered and live makers, they're listed by `directory-cli get-metrics`, and
+//! served at `GET /metrics` on the `metrics_port` of the config, in the Prometheus text format. The metrics
+//! endpoint only listens on localhost.
+//!
+//! Whoever hosts the directory can follow the size of the market, and spot abuse: a surge of rate limited
+//! connections, or of bonds failing verification.
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt::Write as _,
+    io::{ErrorKind, Read},
+    net::{Ipv4Addr, TcpListener, TcpStream},
+    sync::{atomic::Ordering::Relaxed, Arc, Mutex},
+    thread::sleep,
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::utill::{http_request_path, send_http_response, unix_time, HEART_BEAT_INTERVAL};
+
+use super::directory::{DirectoryServer, DirectoryServerError};
+
+/// Minutes of queries counted in the query rate.
+const QUERY_WINDOW_MINUTES: u64 = 60;
+
+/// Why the directory rejected a connection or a request.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum RejectionReason {
+    /// The peer IP opened connections too fast.
+    ConnectionRate,
+    /// Too many connections were open, in total or from the peer IP.
+    ConnectionLimit,
+    /// The request didn't come within the handshake timeout, or couldn't be decoded.
+    MalformedRequest,
+    /// The registration had already expired.
+    ExpiredRegistration,
+    /// The maker address posted or removed too often.
+    RegistrationRate,
+    /// The fidelity bond of the registration failed verification.
+    InvalidBond,
+    /// The fidelity bond already backs another maker.
+    BondReuse,
+    /// The identity of the registration wasn't bound to its bond, or had invalid signatures.
+    InvalidIdentity,
+    /// The removal had an invalid bond or signature.
+    InvalidRemoval,
+    /// The most offer book subscriptions were already held.
+    SubscriberLimit,
+}
+
+impl RejectionReason {
+    /// Label of the reason in the Prometheus metrics.
+    fn label(&self) -> &'static str {
+        match self {
+            Self::ConnectionRate => "connection_rate",
+            Self::ConnectionLimit => "connection_limit",
+            Self::MalformedRequest => "malformed_request",
+            Self::ExpiredRegistration => "expired_registration",
+            Self::RegistrationRate => "registration_rate",
+            Self::InvalidBond => "invalid_bond",
+            Self::BondReuse => "bond_reuse",
+            Self::InvalidIdentity => "invalid_identity",
+            Self::InvalidRemoval => "invalid_removal",
+            Self::SubscriberLimit => "subscriber_limit",
+        }
+    }
+}
+
+/// Metrics of the directory since it started, as listed by the `get-metrics` RPC.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct MetricsReport {
+    /// Seconds since the directory started.
+    pub uptime_secs: u64,
+    /// Maker addresses held, live or expired.
+    pub registered_makers: usize,
+    /// Makers whose registration hasn't expired.
+    pub active_makers: usize,
+    /// Registrations accepted, renewals included.
+    pub registrations: u64,
+    /// Removals accepted.
+    pub removals: u64,
+    /// Queries served: address lists, listings, snapshots and uptime reports.
+    pub queries: u64,
+    /// Queries served over the last hour.
+    pub queries_last_hour: u64,
+    /// Connections and requests rejected, per reason.
+    pub rejections: BTreeMap<RejectionReason, u64>,
+}
+
+impl MetricsReport {
+    /// The metrics in the Prometheus text format.
+    pub fn to_prometheus(&self) -> String {
+        let mut text = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, value: u64| {
+            let _ = write!(
+                text,
+                "# HELP {name} {help}\n# TYPE {name} {kind}\n{name} {value}\n",
+                name = name,
+                kind = kind,
+                help = help,
+                value = value
+            );
+        };
+        metric(
+            "directory_uptime_seconds",
+            "gauge",
+            "Seconds since the directory started.",
+            self.uptime_secs,
+        );
+        metric(
+            "directory_registered_makers",
+            "gauge",
+            "Maker addresses held, live or expired.",
+            self.registered_makers as u64,
+        );
+        metric(
+            "directory_active_makers",
+            "gauge",
+            "Makers whose registration hasn't expired.",
+            self.active_makers as u64,
+        );
+        metric(
+            "directory_registrations_total",
+            "counter",
+            "Registrations accepted.",
+            self.registrations,
+        );
+        metric(
+            "directory_removals_total",
+            "counter",
+            "Removals accepted.",
+            self.removals,
+        );
+        metric(
+            "directory_queries_total",
+            "counter",
+            "Queries served.",
+            self.queries,
+        );
+        metric(
+            "directory_queries_last_hour",
+            "gauge",
+            "Queries served over the last hour.",
+            self.queries_last_hour,
+        );
+
+        text.push_str("# HELP directory_rejections_total Connections and requests rejected.\n");
+        text.push_str("# TYPE directory_rejections_total counter\n");
+        for (reason, count) in &self.rejections {
+            let _ = writeln!(
+                text,
+                "directory_rejections_total{{reason=\"{}\"}} {}",
+                reason.label(),
+                count
+            );
+        }
+        text
+    }
+}
+
+/// Counters of the directory.
+#[derive(Debug, Default)]
+struct Counters {
+    registrations: u64,
+    removals: u64,
+    queries: u64,
+    /// Queries per unix minute, over the last [QUERY_WINDOW_MINUTES].
+    recent_queries: VecDeque<(u64, u64)>,
+    rejections: BTreeMap<RejectionReason, u64>,
+}
+
+impl Counters {
+    fn query_at(&mut self, now: u64) {
+        self.queries += 1;
+        let minute = now / 60;
+        match self.recent_queries.back_mut() {
+            Some((last, count)) if *last == minute => *count += 1,
+            _ => self.recent_queries.push_back((minute, 1)),
+        }
+        self.prune(minute);
+    }
+
+    fn prune(&mut self, minute: u64) {
+        while let Some((first, _)) = self.recent_queries.front() {
+            if first + QUERY_WINDOW_MINUTES > minute {
+                break;
+            }
+            self.recent_queries.pop_front();
+        }
+    }
+
+    fn queries_last_hour(&self, now: u64) -> u64 {
+        let minute = now / 60;
+        self.recent_queries
+            .iter()
+            .filter(|(first, _)| first + QUERY_WINDOW_MINUTES > minute)
+            .map(|(_, count)| count)
+            .sum()
+    }
+}
+
+/// Counts the activity of the directory. Counting never fails: a poisoned lock is recovered.
+#[derive(Debug)]
+pub struct DirectoryMetrics {
+    started: u64,
+    counters: Mutex<Counters>,
+}
+
+impl Default for DirectoryMetrics {
+    fn default() -> Self {
+        Self {
+            started: unix_time(),
+            counters: Mutex::new(Counters::default()),
+        }
+    }
+}
+
+impl DirectoryMetrics {
+    fn counters(&self) -> std::sync::MutexGuard<'_, Counters> {
+        self.counters.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Counts an accepted registration.
+    pub(crate) fn registered(&self) {
+        self.counters().registrations += 1;
+    }
+
+    /// Counts an accepted removal.
+    pub(crate) fn removed(&self) {
+        self.counters().removals += 1;
+    }
+
+    /// Counts a query served.
+    pub(crate) fn queried(&self) {
+        self.counters().query_at(unix_time());
+    }
+
+    /// Counts a rejection.
+    pub(crate) fn rejected(&self, reason: RejectionReason) {
+        *self.counters().rejections.entry(reason).or_default() += 1;
+    }
+
+    /// The metrics so far, with the number of makers held and live.
+    pub(crate) fn report(&self, registered_makers: usize, active_makers: usize) -> MetricsReport {
+        self.report_at(registered_makers, active_makers, unix_time())
+    }
+
+    fn report_at(&self, registered_makers: usize, active_makers: usize, now: u64) -> MetricsReport {
+        let counters = self.counters();
+        MetricsReport {
+            uptime_secs: now.saturating_sub(self.started),
+            registered_makers,
+            active_makers,
+            registrations: counters.registrations,
+            removals: counters.removals,
+            queries: counters.queries,
+            queries_last_hour: counters.queries_last_hour(now),
+            rejections: counters.rejections.clone(),
+        }
+    }
+}
+
+/// Runs the metrics HTTP server, until the directory shuts down.
+pub(crate) fn start_metrics_server_thread(
+    directory: Arc<DirectoryServer>,
+) -> Result<(), DirectoryServerError> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, directory.metrics_port))?;
+    log::info!(
+        "Metrics endpoint listening at 127.0.0.1:{}",
+        directory.metrics_port
+    );
+
+    listener.set_nonblocking(true)?;
+
+    while !directory.shutdown.load(Relaxed) {
+        match listener.accept() {
+            Ok((mut stream, _)) => {
+                // The accepted socket may inherit the listener's non-blocking mode.
+                if let Err(e) = stream
+                    .set_nonblocking(false)
+                    .and_then(|_| stream.set_read_timeout(Some(Duration::from_secs(5))))
+                    .and_then(|_| stream.set_write_timeout(Some(Duration::from_secs(5))))
+                {
+                    log::error!("Error setting up metrics connection: {:?}", e);
+                    continue;
+                }
+                if let Err(e) = handle_metrics_request(&directory, &mut stream) {
+                    log::debug!("Error processing metrics request: {:?}", e);
+                }
+            }
+            Err(e) => {
+                if e.kind() != ErrorKind::WouldBlock {
+                    log::error!("Error accepting metrics connection: {:?}", e);
+                }
+            }
+        }
+
+        sleep(HEART_BEAT_INTERVAL);
+    }
+
+    Ok(())
+}
+
+fn handle_metrics_request(
+    directory: &DirectoryServer,
+    stream: &mut TcpStream,
+) -> Result<(), DirectoryServerError> {
+    let mut buf = [0u8; 1024];
+    let read = stream.read(&mut buf)?;
+    let request = String::from_utf8_lossy(&buf[..read]);
+
+    if http_request_path(&request) != Some("/metrics") {
+        send_http_response(stream, "404 Not Found", "text/plain", "Not found")?;
+        return Ok(());
+    }
+    let body = directory.metrics_report()?.to_prometheus();
+    send_http_response(stream, "200 OK", "text/plain; version=0.0.4", &body)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_directory_metrics() {
+        let metrics = DirectoryMetrics {
+            started: 1_000,
+            counters: Mutex::new(Counters::default()),
+        };
+        metrics.registered();
+        metrics.registered();
+        metrics.removed();
+        metrics.rejected(RejectionReason::BondReuse);
+        metrics.rejected(RejectionReason::ConnectionRate);
+        metrics.rejected(RejectionReason::ConnectionRate);
+        {
+            let mut counters = metrics.counters();
+            counters.query_at(1_000);
+            counters.query_at(1_010);
+            counters.query_at(1_000 + 30 * 60);
+        }
+
+        let report = metrics.report_at(3, 2, 1_000 + 45 * 60);
+        assert_eq!(report.uptime_secs, 45 * 60);
+        assert_eq!(report.registrations, 2);
+        assert_eq!(report.removals, 1);
+        assert_eq!(report.queries, 3);
+        assert_eq!(report.queries_last_hour, 3);
+        assert_eq!(report.rejections[&RejectionReason::ConnectionRate], 2);
+
+        // The first queries fall out of the last hour.
+        let report = metrics.report_at(3, 2, 1_000 + 80 * 60);
+        assert_eq!(report.queries, 3);
+        assert_eq!(report.queries_last_hour, 1);
+
+        let text = report.to_prometheus();
+        assert!(text.contains("directory_active_makers 2\n"));
+        assert!(text.contains("directory_registrations_total 2\n"));
+        assert!(text.contains("directory_rejections_total{reason=\"connection_rate\"} 2\n"));
+        assert!(text.contains("directory_rejections_total{reason=\"bond_reuse\"} 1\n"));
+    }
+}
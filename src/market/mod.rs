@@ -1,4 +1,22 @@
 //! (dummy) Current toy implementation of a directory-server.
+//!
+//! The listing, snapshot, uptime and subscription types are shared with the Taker. The server itself is only
+//! built with the `directory` feature.
 
+#[cfg(feature = "directory")]
+mod config;
+#[cfg(feature = "directory")]
 pub mod directory;
+pub mod listing;
+#[cfg(feature = "directory")]
+pub mod metrics;
+#[cfg(feature = "directory")]
+mod ratelimit;
+#[cfg(feature = "directory")]
 pub mod rpc;
+pub mod snapshot;
+pub mod subscription;
+pub mod uptime;
+
+#[cfg(feature = "directory")]
+pub use config::DirectoryConfig;
@@ -0,0 +1,221 @@
+//! Abuse protection of the directory server.
+//!
+//! A single client must not be able to tie up the directory:
+//! - Open connections are capped, in total and per peer IP. Connections over the caps are closed right away.
+//! - New connections of a peer IP, and registrations (posts and removals) of a maker address, are rate
+//!   limited by token buckets. Registrations are only counted once their fidelity bond verified, so that
+//!   junk posted with the address of an honest maker doesn't use up its quota.
+//! - A connection must send its request before the handshake deadline, however slowly it sends it.
+//!
+//! The directory listens on localhost, behind its onion service, so connections over Tor all come from the
+//! loopback address. Loopback connections have their own cap, below the total, so that Tor clients can't
+//! take every slot. They are not rate limited per IP, which would throttle all Tor clients together.
+
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// A token bucket: holds up to a capacity of tokens, refilled at a constant rate. Each event takes one.
+#[derive(Debug, Clone, Copy)]
+struct TokenBucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    /// Refills the bucket up to `now`, and takes a token if there's one.
+    fn try_take(&mut self, capacity: f64, refill_per_sec: f64, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * refill_per_sec).min(capacity);
+        self.updated = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Whether the bucket would be full by `now`. Full buckets are the same as new ones.
+    fn is_full(&self, capacity: f64, refill_per_sec: f64, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens + elapsed * refill_per_sec >= capacity
+    }
+}
+
+/// Number of keys tracked by a [RateLimiter] before the full buckets are dropped.
+const MAX_IDLE_BUCKETS: usize = 1024;
+
+/// Rate limits events per key, allowing bursts of up to `capacity` events.
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    capacity: f64,
+    refill_per_sec: f64,
+    buckets: Mutex<HashMap<String, TokenBucket>>,
+}
+
+impl RateLimiter {
+    /// Allows `capacity` events per `period` to each key. 0 disables the limit.
+    pub(crate) fn new(capacity: u32, period: Duration) -> Self {
+        Self {
+            capacity: capacity as f64,
+            refill_per_sec: capacity as f64 / period.as_secs_f64(),
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Whether an event of `key` is allowed now. Counts it if so.
+    pub(crate) fn allow(&self, key: &str) -> bool {
+        self.allow_at(key, Instant::now())
+    }
+
+    fn allow_at(&self, key: &str, now: Instant) -> bool {
+        if self.capacity == 0.0 {
+            return true;
+        }
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        if buckets.len() >= MAX_IDLE_BUCKETS {
+            buckets.retain(|_, bucket| !bucket.is_full(self.capacity, self.refill_per_sec, now));
+        }
+        buckets
+            .entry(key.to_string())
+            .or_insert(TokenBucket {
+                tokens: self.capacity,
+                updated: now,
+            })
+            .try_take(self.capacity, self.refill_per_sec, now)
+    }
+}
+
+#[derive(Debug, Default)]
+struct OpenConnections {
+    total: usize,
+    per_ip: HashMap<IpAddr, usize>,
+}
+
+/// Caps the connections open at once, in total and per peer IP. Loopback peers, behind which are all Tor
+/// clients, have a cap of their own. 0 disables a cap.
+#[derive(Debug)]
+pub(crate) struct ConnectionLimiter {
+    max_total: usize,
+    max_per_ip: usize,
+    max_loopback: usize,
+    open: Mutex<OpenConnections>,
+}
+
+impl ConnectionLimiter {
+    pub(crate) fn new(max_total: usize, max_per_ip: usize, max_loopback: usize) -> Self {
+        Self {
+            max_total,
+            max_per_ip,
+            max_loopback,
+            open: Mutex::new(OpenConnections::default()),
+        }
+    }
+
+    /// Takes a slot for a new connection from `ip`, released when the returned [ConnectionSlot] is dropped.
+    /// None if over a cap.
+    pub(crate) fn try_acquire(self: &Arc<Self>, ip: IpAddr) -> Option<ConnectionSlot> {
+        let mut open = self.open.lock().unwrap_or_else(|e| e.into_inner());
+        if self.max_total != 0 && open.total >= self.max_total {
+            return None;
+        }
+        let max_per_ip = if ip.is_loopback() {
+            self.max_loopback
+        } else {
+            self.max_per_ip
+        };
+        let per_ip = open.per_ip.entry(ip).or_insert(0);
+        if max_per_ip != 0 && *per_ip >= max_per_ip {
+            return None;
+        }
+        *per_ip += 1;
+        open.total += 1;
+        Some(ConnectionSlot {
+            limiter: self.clone(),
+            ip,
+        })
+    }
+
+    fn release(&self, ip: IpAddr) {
+        let mut open = self.open.lock().unwrap_or_else(|e| e.into_inner());
+        open.total = open.total.saturating_sub(1);
+        if let Some(per_ip) = open.per_ip.get_mut(&ip) {
+            *per_ip -= 1;
+            if *per_ip == 0 {
+                open.per_ip.remove(&ip);
+            }
+        }
+    }
+}
+
+/// An open connection, counted by its [ConnectionLimiter] until dropped.
+#[derive(Debug)]
+pub(crate) struct ConnectionSlot {
+    limiter: Arc<ConnectionLimiter>,
+    ip: IpAddr,
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        self.limiter.release(self.ip);
+    }
+}
+
+/// The IP the connection rate limit applies to. None for loopback peers, behind which are all Tor clients.
+pub(crate) fn limited_ip(ip: IpAddr) -> Option<IpAddr> {
+    Some(ip).filter(|ip| !ip.is_loopback())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    #[test]
+    fn test_rate_limiter() {
+        let limiter = RateLimiter::new(2, Duration::from_secs(60));
+        let start = Instant::now();
+        assert!(limiter.allow_at("a.onion", start));
+        assert!(limiter.allow_at("a.onion", start));
+        assert!(!limiter.allow_at("a.onion", start));
+        // Other keys have their own bucket.
+        assert!(limiter.allow_at("b.onion", start));
+        // One token back every 30 seconds.
+        assert!(!limiter.allow_at("a.onion", start + Duration::from_secs(20)));
+        assert!(limiter.allow_at("a.onion", start + Duration::from_secs(31)));
+
+        let unlimited = RateLimiter::new(0, Duration::from_secs(60));
+        assert!((0..100).all(|_| unlimited.allow_at("a.onion", start)));
+    }
+
+    #[test]
+    fn test_connection_limiter() {
+        let limiter = Arc::new(ConnectionLimiter::new(4, 2, 1));
+        let peer = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+        let other = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2));
+        let loopback = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+        let first = limiter.try_acquire(peer).unwrap();
+        let _second = limiter.try_acquire(peer).unwrap();
+        assert!(limiter.try_acquire(peer).is_none());
+        // Tor clients, over loopback, have their own cap.
+        let _tor = limiter.try_acquire(loopback).unwrap();
+        assert!(limiter.try_acquire(loopback).is_none());
+        let _third = limiter.try_acquire(other).unwrap();
+        // The total cap applies to every peer.
+        assert!(limiter
+            .try_acquire(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 3)))
+            .is_none());
+
+        drop(first);
+        assert!(limiter.try_acquire(peer).is_some());
+
+        assert_eq!(limited_ip(loopback), None);
+        assert_eq!(limited_ip(peer), Some(peer));
+    }
+}
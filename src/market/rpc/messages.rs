@@ -2,11 +2,15 @@ use bitcoin::OutPoint;
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeSet;
 
+use crate::market::metrics::MetricsReport;
+
 /// Directory server RPC message request
 #[derive(Serialize, Deserialize, Debug)]
 pub enum RpcMsgReq {
     /// ListAddresses RPC message request variant
     ListAddresses,
+    /// GetMetrics RPC message request variant
+    GetMetrics,
 }
 
 /// Directory message RPC message Response
@@ -14,4 +18,6 @@ pub enum RpcMsgReq {
 pub enum RpcMsgResp {
     /// ListAddressesResp RPC message response variant
     ListAddressesResp(BTreeSet<(OutPoint, String)>),
+    /// MetricsResp RPC message response variant
+    MetricsResp(MetricsReport),
 }
@@ -1,31 +1,30 @@
-use bitcoin::OutPoint;
-
 use super::{RpcMsgReq, RpcMsgResp};
 use crate::{
-    error::NetError,
     market::directory::{DirectoryServer, DirectoryServerError},
+    protocol::wire::decode,
     utill::{read_message, send_message, HEART_BEAT_INTERVAL},
 };
 use std::{
-    collections::{BTreeSet, HashMap},
+    collections::BTreeSet,
     io::ErrorKind,
     net::{TcpListener, TcpStream},
-    sync::{atomic::Ordering::Relaxed, Arc, RwLock},
+    sync::{atomic::Ordering::Relaxed, Arc},
     thread::sleep,
-    time::{Duration, Instant},
+    time::Duration,
 };
 fn handle_request(
     socket: &mut TcpStream,
-    address: Arc<RwLock<HashMap<OutPoint, (String, Instant)>>>,
+    directory: &DirectoryServer,
 ) -> Result<(), DirectoryServerError> {
     let req_bytes = read_message(socket)?;
-    let rpc_request: RpcMsgReq = serde_cbor::from_slice(&req_bytes).map_err(NetError::Cbor)?;
+    let rpc_request: RpcMsgReq = decode(&req_bytes)?;
 
     match rpc_request {
         RpcMsgReq::ListAddresses => {
             log::info!("RPC request received: {:?}", rpc_request);
             let resp = RpcMsgResp::ListAddressesResp(
-                address
+                directory
+                    .addresses
                     .read()?
                     .iter()
                     .map(|(op, address)| (*op, address.0.clone()))
@@ -33,6 +32,11 @@ fn handle_request(
             );
             send_message(socket, &resp)?;
         }
+        RpcMsgReq::GetMetrics => {
+            log::info!("RPC request received: {:?}", rpc_request);
+            let resp = RpcMsgResp::MetricsResp(directory.metrics_report()?);
+            send_message(socket, &resp)?;
+        }
     }
 
     Ok(())
@@ -52,9 +56,17 @@ pub fn start_rpc_server_thread(
         match listener.accept() {
             Ok((mut stream, addr)) => {
                 log::info!("Got RPC request from: {}", addr);
-                stream.set_read_timeout(Some(Duration::from_secs(20)))?;
-                stream.set_write_timeout(Some(Duration::from_secs(20)))?;
-                if let Err(e) = handle_request(&mut stream, directory.addresses.clone()) {
+                // The accepted socket may inherit the listener's non-blocking mode, which would
+                // fail the read right away.
+                if let Err(e) = stream
+                    .set_nonblocking(false)
+                    .and_then(|_| stream.set_read_timeout(Some(Duration::from_secs(20))))
+                    .and_then(|_| stream.set_write_timeout(Some(Duration::from_secs(20))))
+                {
+                    log::error!("Error setting up RPC connection: {:?}", e);
+                    continue;
+                }
+                if let Err(e) = handle_request(&mut stream, &directory) {
                     log::error!("Error handling RPC request: {:?}", e);
                 }
             }
@@ -0,0 +1,100 @@
+//! Market snapshots.
+//!
+//! A timestamped dump of an offer book: fees, size limits and fidelity bond of every maker. Snapshots are
+//! meant to be collected over time, for studies of the market's liquidity and pricing.
+//!
+//! Maker addresses are replaced by their SHA256 hash, so that a maker can be followed across snapshots
+//! without the snapshot listing the addresses directly.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bitcoin::hashes::{sha256, Hash};
+use serde::{Deserialize, Serialize};
+
+use crate::{protocol::messages::FeeTier, taker::offers::OfferAndAddress};
+
+/// The offer of one maker, at the time of the snapshot.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MakerSnapshot {
+    /// SHA256 of the maker's address, hex encoded.
+    pub address_hash: String,
+    /// Base fee, in sats.
+    pub base_fee_sats: u64,
+    /// Fee on the swap amount, in percent.
+    pub amount_relative_fee_pct: f64,
+    /// Fee on the swap amount per block of refund locktime, in percent.
+    pub time_relative_fee_pct: f64,
+    /// Fees on the amount of the larger swaps, replacing `amount_relative_fee_pct`.
+    #[serde(default)]
+    pub fee_tiers: Vec<FeeTier>,
+    /// Minimum swap size, in sats.
+    pub min_size_sats: u64,
+    /// Maximum swap size, in sats.
+    pub max_size_sats: u64,
+    /// Confirmations required on the incoming funding txs.
+    pub required_confirms: u32,
+    /// Minimum refund locktime, in blocks.
+    pub minimum_locktime: u16,
+    /// Maximum refund locktime, in blocks. 0 if unlimited.
+    #[serde(default)]
+    pub max_refund_locktime: u16,
+    /// Amount of the fidelity bond, in sats.
+    pub bond_amount_sats: u64,
+    /// Timelock of the fidelity bond.
+    pub bond_locktime: u32,
+    /// Confirmation height of the fidelity bond.
+    pub bond_conf_height: u32,
+}
+
+/// All offers of a market, at a point in time.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MarketSnapshot {
+    /// Unix timestamp of the snapshot.
+    pub timestamp: u64,
+    /// Offers of all makers, ordered by address hash.
+    pub makers: Vec<MakerSnapshot>,
+}
+
+impl MarketSnapshot {
+    /// Takes a snapshot of the given offers, timestamped now.
+    pub(crate) fn new<'a>(offers: impl IntoIterator<Item = &'a OfferAndAddress>) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let mut makers = offers
+            .into_iter()
+            .map(|oa| {
+                let offer = &oa.offer;
+                let bond = &offer.fidelity.bond;
+                MakerSnapshot {
+                    address_hash: sha256::Hash::hash(oa.address.to_string().as_bytes()).to_string(),
+                    base_fee_sats: offer.base_fee,
+                    amount_relative_fee_pct: offer.amount_relative_fee_pct,
+                    time_relative_fee_pct: offer.time_relative_fee_pct,
+                    fee_tiers: offer.fee_tiers.clone(),
+                    min_size_sats: offer.min_size,
+                    max_size_sats: offer.max_size,
+                    required_confirms: offer.required_confirms,
+                    minimum_locktime: offer.minimum_locktime,
+                    max_refund_locktime: offer.max_refund_locktime,
+                    bond_amount_sats: bond.amount.to_sat(),
+                    bond_locktime: bond.lock_time.to_consensus_u32(),
+                    bond_conf_height: bond.conf_height,
+                }
+            })
+            .collect::<Vec<_>>();
+        makers.sort_by(|a, b| a.address_hash.cmp(&b.address_hash));
+        Self { timestamp, makers }
+    }
+
+    /// Sum of the maximum swap sizes of all makers, in sats.
+    pub fn total_liquidity_sats(&self) -> u64 {
+        self.makers.iter().map(|m| m.max_size_sats).sum()
+    }
+
+    /// Sum of the fidelity bonds of all makers, in sats.
+    pub fn total_bond_sats(&self) -> u64 {
+        self.makers.iter().map(|m| m.bond_amount_sats).sum()
+    }
+}
@@ -0,0 +1,175 @@
+//! Offer book subscriptions.
+//!
+//! Instead of polling the full maker list, a long running taker can send [DnsRequest::Subscribe]
+//! and keep the connection open. The directory answers with the live maker addresses, highest bond values
+//! first, like [DnsRequest::Get] but as a `Vec<String>`, then streams an [OfferBookUpdate] each time a maker
+//! joins, leaves or updates its registration. A [OfferBookUpdate::Heartbeat] is sent when nothing happened
+//! for [HEARTBEAT_INTERVAL], so both ends notice a dead connection.
+//!
+//! Subscribers which don't keep up are dropped, and resubscribe to resync. A directory holds at most
+//! 100 subscriptions.
+//!
+//! [DnsRequest::Subscribe]: crate::protocol::messages::DnsRequest::Subscribe
+//! [DnsRequest::Get]: crate::protocol::messages::DnsRequest::Get
+
+use std::time::Duration;
+
+#[cfg(feature = "directory")]
+use std::{
+    net::TcpStream,
+    sync::{
+        atomic::Ordering::Relaxed,
+        mpsc::{self, Receiver, RecvTimeoutError, SyncSender, TrySendError},
+        Mutex,
+    },
+};
+
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "directory")]
+use crate::utill::{send_message, HEART_BEAT_INTERVAL};
+
+#[cfg(feature = "directory")]
+use super::directory::{DirectoryServer, DirectoryServerError};
+
+/// Most subscriptions held at once.
+#[cfg(feature = "directory")]
+pub(crate) const MAX_SUBSCRIBERS: usize = 100;
+
+/// Updates queued for a subscriber before it's dropped as too slow.
+#[cfg(feature = "directory")]
+const SUBSCRIBER_QUEUE: usize = 256;
+
+/// Longest silence on a subscription before a heartbeat is sent.
+pub(crate) const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// A change of the makers listed by the directory, streamed to the subscribers.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum OfferBookUpdate {
+    /// A maker registered at a new address.
+    Joined(String),
+    /// A maker was removed, or its registration expired.
+    Left(String),
+    /// A maker registered again with another fidelity bond: its offer changed.
+    Updated(String),
+    /// Nothing changed since the last message.
+    Heartbeat,
+}
+
+/// Fans out the updates of the directory to its subscribers.
+#[cfg(feature = "directory")]
+#[derive(Debug, Default)]
+pub struct OfferBookFeed {
+    subscribers: Mutex<Vec<SyncSender<OfferBookUpdate>>>,
+}
+
+#[cfg(feature = "directory")]
+impl OfferBookFeed {
+    /// A new subscription to the updates. `None` if [MAX_SUBSCRIBERS] are already subscribed.
+    pub(crate) fn subscribe(&self) -> Option<Receiver<OfferBookUpdate>> {
+        let mut subscribers = self.subscribers.lock().unwrap_or_else(|e| e.into_inner());
+        if subscribers.len() >= MAX_SUBSCRIBERS {
+            // Make room of the subscribers gone since the last update.
+            subscribers.retain(|subscriber| {
+                !matches!(
+                    subscriber.try_send(OfferBookUpdate::Heartbeat),
+                    Err(TrySendError::Disconnected(_))
+                )
+            });
+        }
+        if subscribers.len() >= MAX_SUBSCRIBERS {
+            return None;
+        }
+        let (sender, receiver) = mpsc::sync_channel(SUBSCRIBER_QUEUE);
+        subscribers.push(sender);
+        Some(receiver)
+    }
+
+    /// Sends an update to the subscribers. Those gone or too slow are dropped.
+    pub(crate) fn publish(&self, update: OfferBookUpdate) {
+        let mut subscribers = self.subscribers.lock().unwrap_or_else(|e| e.into_inner());
+        subscribers.retain(|subscriber| subscriber.try_send(update.clone()).is_ok());
+    }
+
+    /// Number of subscriptions held.
+    #[cfg(test)]
+    pub(crate) fn subscriber_count(&self) -> usize {
+        self.subscribers
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .len()
+    }
+}
+
+/// Streams the updates of a subscription to the taker, until it disconnects, falls behind, or the directory
+/// shuts down. The live makers were sent before.
+#[cfg(feature = "directory")]
+pub(crate) fn serve_subscription(
+    stream: &mut TcpStream,
+    directory: &DirectoryServer,
+    updates: Receiver<OfferBookUpdate>,
+) -> Result<(), DirectoryServerError> {
+    let mut silent_for = Duration::ZERO;
+    while !directory.shutdown.load(Relaxed) {
+        match updates.recv_timeout(HEART_BEAT_INTERVAL) {
+            Ok(OfferBookUpdate::Heartbeat) => continue,
+            Ok(update) => {
+                send_message(stream, &update)?;
+                silent_for = Duration::ZERO;
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                silent_for += HEART_BEAT_INTERVAL;
+                if silent_for >= HEARTBEAT_INTERVAL {
+                    send_message(stream, &OfferBookUpdate::Heartbeat)?;
+                    silent_for = Duration::ZERO;
+                }
+            }
+            // Dropped as too slow, the taker resubscribes.
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(all(test, feature = "directory"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_offerbook_feed() {
+        let feed = OfferBookFeed::default();
+        let first = feed.subscribe().unwrap();
+        let second = feed.subscribe().unwrap();
+
+        feed.publish(OfferBookUpdate::Joined("maker1:6102".to_string()));
+        assert_eq!(
+            first.try_recv().unwrap(),
+            OfferBookUpdate::Joined("maker1:6102".to_string())
+        );
+
+        // A subscriber gone is dropped.
+        drop(first);
+        feed.publish(OfferBookUpdate::Left("maker1:6102".to_string()));
+        assert_eq!(feed.subscriber_count(), 1);
+        assert_eq!(
+            second.try_iter().collect::<Vec<_>>(),
+            vec![
+                OfferBookUpdate::Joined("maker1:6102".to_string()),
+                OfferBookUpdate::Left("maker1:6102".to_string()),
+            ]
+        );
+
+        // So is one falling behind.
+        for _ in 0..=SUBSCRIBER_QUEUE {
+            feed.publish(OfferBookUpdate::Updated("maker2:6102".to_string()));
+        }
+        assert_eq!(feed.subscriber_count(), 0);
+
+        let subscriptions = (0..MAX_SUBSCRIBERS)
+            .map(|_| feed.subscribe().unwrap())
+            .collect::<Vec<_>>();
+        assert!(feed.subscribe().is_none());
+        drop(subscriptions);
+        assert!(feed.subscribe().is_some());
+    }
+}
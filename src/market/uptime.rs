@@ -0,0 +1,90 @@
+//! Maker uptime tracking.
+//!
+//! The directory periodically probes every registered maker with a handshake, and keeps the number of
+//! probes, the successful ones and when the maker was last reachable. Takers fetch these records to
+//! deprioritize flaky makers when choosing a route.
+
+use std::collections::HashMap;
+#[cfg(feature = "directory")]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+/// Uptime records of makers, indexed by maker address.
+pub type UptimeReport = HashMap<String, MakerUptime>;
+
+/// Makers are only judged once they have been probed this many times.
+const MIN_PROBES: u32 = 6;
+
+/// Makers reachable in less than this percentage of probes are considered flaky.
+const FLAKY_UPTIME_PCT: f64 = 80.0;
+
+/// Probe results of one maker.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MakerUptime {
+    /// Number of probes made.
+    pub probes: u32,
+    /// Number of probes the maker answered.
+    pub successes: u32,
+    /// Unix timestamp of the last successful probe.
+    pub last_seen: Option<u64>,
+}
+
+impl MakerUptime {
+    /// Records the result of a probe.
+    #[cfg(feature = "directory")]
+    pub(crate) fn record_probe(&mut self, success: bool) {
+        self.probes += 1;
+        if success {
+            self.successes += 1;
+            self.last_seen = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .ok();
+        }
+    }
+
+    /// Percentage of the probes the maker answered. 100 if it wasn't probed yet.
+    pub fn uptime_pct(&self) -> f64 {
+        if self.probes == 0 {
+            100.0
+        } else {
+            self.successes as f64 * 100.0 / self.probes as f64
+        }
+    }
+
+    /// Whether the maker was probed enough and missed too many of the probes.
+    pub fn is_flaky(&self) -> bool {
+        self.probes >= MIN_PROBES && self.uptime_pct() < FLAKY_UPTIME_PCT
+    }
+}
+
+#[cfg(all(test, feature = "directory"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_maker_uptime() {
+        let mut uptime = MakerUptime::default();
+        assert_eq!(uptime.uptime_pct(), 100.0);
+        assert!(uptime.last_seen.is_none());
+
+        // Too few probes to judge.
+        for _ in 0..MIN_PROBES - 1 {
+            uptime.record_probe(false);
+        }
+        assert!(!uptime.is_flaky());
+        assert!(uptime.last_seen.is_none());
+
+        uptime.record_probe(true);
+        assert!(uptime.is_flaky());
+        assert!(uptime.last_seen.is_some());
+
+        let mut reliable = MakerUptime::default();
+        for i in 0..10 {
+            reliable.record_probe(i != 0);
+        }
+        assert_eq!(reliable.uptime_pct(), 90.0);
+        assert!(!reliable.is_flaky());
+    }
+}
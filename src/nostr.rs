@@ -0,0 +1,450 @@
+//! Offers over Nostr.
+//!
+//! Makers with `nostr_relays` in their config publish their offer as a signed Nostr event to each relay, as a
+//! censorship resistant complement to the directory server. Takers with `nostr_relays` read the offers of the
+//! relays along with the maker addresses of the directories. An offer read from a relay is only a hint: the taker
+//! still downloads the current offer from the maker, and verifies its fidelity proof.
+//!
+//! Offers are parameterized replaceable events (NIP-33) of kind [OFFER_EVENT_KIND], tagged `t` = `coinswap` and
+//! with the Bitcoin network, expiring (NIP-40) with the offer. Each maker run signs with a fresh key: the offer is
+//! authenticated by the fidelity proof inside, the Nostr key only links the events of a run.
+//!
+//! Relays are reached at their `ws://` or `wss://` URL, over the Tor socks port when connecting over Tor.
+
+use std::{collections::HashMap, fmt, io, net::TcpStream, str::FromStr, time::Duration};
+
+#[cfg(feature = "maker")]
+use bitcoin::key::Keypair;
+use bitcoin::{
+    hashes::{sha256, Hash},
+    hex::{DisplayHex, FromHex},
+    key::{Secp256k1, XOnlyPublicKey},
+    secp256k1::{
+        rand::{thread_rng, RngCore},
+        schnorr::Signature,
+        Message,
+    },
+    Network,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use socks::Socks5Stream;
+use tungstenite::{stream::MaybeTlsStream, Message as WsMessage, WebSocket};
+
+use crate::{taker::offers::OfferAndAddress, utill::unix_time};
+
+/// Kind of the offer events. A parameterized replaceable kind, unassigned in the NIPs.
+pub(crate) const OFFER_EVENT_KIND: u32 = 37_196;
+
+/// `d` tag of the offer events: a maker key has one offer at a time.
+#[cfg(feature = "maker")]
+const OFFER_EVENT_ID_TAG: &str = "coinswap-offer";
+
+/// Topic tag of the offer events.
+const OFFER_TOPIC: &str = "coinswap";
+
+/// Timeout of the reads and writes to a relay.
+const RELAY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Most events read from a relay for a query.
+const MAX_EVENTS: usize = 1000;
+
+/// Errors of the Nostr relays and events.
+#[derive(Debug)]
+pub(crate) enum NostrError {
+    /// Error reaching the relay.
+    IO(io::Error),
+    /// Error of the WebSocket connection to the relay.
+    WebSocket(String),
+    /// Message or event not in the expected JSON format.
+    Json(String),
+    /// Relay URL not of the form `ws://host[:port][/path]` or `wss://...`.
+    InvalidRelayUrl(String),
+    /// Event whose id or signature doesn't match its content.
+    InvalidEvent(&'static str),
+    /// The relay refused the event or the query.
+    Rejected(String),
+}
+
+impl fmt::Display for NostrError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::IO(e) => write!(f, "Relay I/O error: {}", e),
+            Self::WebSocket(e) => write!(f, "WebSocket error: {}", e),
+            Self::Json(e) => write!(f, "Invalid JSON: {}", e),
+            Self::InvalidRelayUrl(url) => write!(f, "Invalid relay URL: {}", url),
+            Self::InvalidEvent(reason) => write!(f, "Invalid event: {}", reason),
+            Self::Rejected(reason) => write!(f, "Rejected by the relay: {}", reason),
+        }
+    }
+}
+
+impl From<io::Error> for NostrError {
+    fn from(value: io::Error) -> Self {
+        Self::IO(value)
+    }
+}
+
+impl From<serde_json::Error> for NostrError {
+    fn from(value: serde_json::Error) -> Self {
+        Self::Json(value.to_string())
+    }
+}
+
+impl From<tungstenite::Error> for NostrError {
+    fn from(value: tungstenite::Error) -> Self {
+        Self::WebSocket(value.to_string())
+    }
+}
+
+/// A Nostr event, as defined by NIP-01.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct NostrEvent {
+    /// Hex SHA256 of the serialized event.
+    pub(crate) id: String,
+    /// Hex x-only public key of the author.
+    pub(crate) pubkey: String,
+    /// Unix time of the event.
+    pub(crate) created_at: u64,
+    /// Kind of the event.
+    pub(crate) kind: u32,
+    /// Tags of the event.
+    pub(crate) tags: Vec<Vec<String>>,
+    /// Content of the event.
+    pub(crate) content: String,
+    /// Hex Schnorr signature of the id.
+    pub(crate) sig: String,
+}
+
+fn event_id(
+    pubkey: &str,
+    created_at: u64,
+    kind: u32,
+    tags: &[Vec<String>],
+    content: &str,
+) -> [u8; 32] {
+    let serialized = json!([0, pubkey, created_at, kind, tags, content]).to_string();
+    sha256::Hash::hash(serialized.as_bytes()).to_byte_array()
+}
+
+impl NostrEvent {
+    /// Signs a new event with `keypair`.
+    #[cfg(feature = "maker")]
+    pub(crate) fn sign(
+        keypair: &Keypair,
+        created_at: u64,
+        kind: u32,
+        tags: Vec<Vec<String>>,
+        content: String,
+    ) -> Self {
+        let pubkey = keypair.x_only_public_key().0.to_string();
+        let id = event_id(&pubkey, created_at, kind, &tags, &content);
+        let sig = Secp256k1::new().sign_schnorr_no_aux_rand(&Message::from_digest(id), keypair);
+        Self {
+            id: id.to_lower_hex_string(),
+            pubkey,
+            created_at,
+            kind,
+            tags,
+            content,
+            sig: sig.to_string(),
+        }
+    }
+
+    /// Checks the id and the signature of the event.
+    pub(crate) fn verify(&self) -> Result<(), NostrError> {
+        let id = event_id(
+            &self.pubkey,
+            self.created_at,
+            self.kind,
+            &self.tags,
+            &self.content,
+        );
+        if <[u8; 32]>::from_hex(&self.id).ok() != Some(id) {
+            return Err(NostrError::InvalidEvent("Id doesn't match the event"));
+        }
+        let pubkey = XOnlyPublicKey::from_str(&self.pubkey)
+            .map_err(|_| NostrError::InvalidEvent("Invalid public key"))?;
+        let sig = Signature::from_str(&self.sig)
+            .map_err(|_| NostrError::InvalidEvent("Invalid signature encoding"))?;
+        Secp256k1::verification_only()
+            .verify_schnorr(&sig, &Message::from_digest(id), &pubkey)
+            .map_err(|_| NostrError::InvalidEvent("Invalid signature"))
+    }
+
+    /// Value of the first tag named `name`.
+    pub(crate) fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|tag| tag.first().map(String::as_str) == Some(name))
+            .and_then(|tag| tag.get(1))
+            .map(String::as_str)
+    }
+}
+
+/// The offer event of a maker, expiring at unix time `expires_at`.
+#[cfg(feature = "maker")]
+pub(crate) fn offer_event(
+    keypair: &Keypair,
+    offer: &OfferAndAddress,
+    network: Network,
+    expires_at: u64,
+) -> Result<NostrEvent, NostrError> {
+    let tags = vec![
+        vec!["d".to_string(), OFFER_EVENT_ID_TAG.to_string()],
+        vec!["t".to_string(), OFFER_TOPIC.to_string()],
+        vec!["network".to_string(), network.to_string()],
+        vec!["expiration".to_string(), expires_at.to_string()],
+    ];
+    Ok(NostrEvent::sign(
+        keypair,
+        unix_time(),
+        OFFER_EVENT_KIND,
+        tags,
+        serde_json::to_string(offer)?,
+    ))
+}
+
+/// The offer of an event, if it's a valid, unexpired offer event of the network.
+fn event_offer(
+    event: &NostrEvent,
+    network: Network,
+    now: u64,
+) -> Result<OfferAndAddress, NostrError> {
+    event.verify()?;
+    if event.kind != OFFER_EVENT_KIND || event.tag("t") != Some(OFFER_TOPIC) {
+        return Err(NostrError::InvalidEvent("Not an offer event"));
+    }
+    if event.tag("network") != Some(network.to_string().as_str()) {
+        return Err(NostrError::InvalidEvent("Offer of another network"));
+    }
+    let expires_at = event
+        .tag("expiration")
+        .and_then(|expiration| expiration.parse::<u64>().ok())
+        .ok_or(NostrError::InvalidEvent("Offer without expiration"))?;
+    if expires_at <= now {
+        return Err(NostrError::InvalidEvent("Expired offer"));
+    }
+    Ok(serde_json::from_str(&event.content)?)
+}
+
+/// The latest valid offer of each maker address, among `events`.
+pub(crate) fn offers_from_events(
+    events: &[NostrEvent],
+    network: Network,
+    now: u64,
+) -> Vec<OfferAndAddress> {
+    let mut offers = HashMap::<String, (u64, OfferAndAddress)>::new();
+    for event in events {
+        match event_offer(event, network, now) {
+            Ok(offer) => {
+                let address = offer.address.to_string();
+                if offers
+                    .get(&address)
+                    .is_none_or(|(created_at, _)| *created_at < event.created_at)
+                {
+                    offers.insert(address, (event.created_at, offer));
+                }
+            }
+            Err(e) => log::debug!("Skipping Nostr event {}: {}", event.id, e),
+        }
+    }
+    offers.into_values().map(|(_, offer)| offer).collect()
+}
+
+type RelaySocket = WebSocket<MaybeTlsStream<TcpStream>>;
+
+/// Host and port of a relay URL.
+fn relay_host(relay: &str) -> Result<(&str, u16), NostrError> {
+    let invalid = || NostrError::InvalidRelayUrl(relay.to_string());
+    let (rest, default_port) = if let Some(rest) = relay.strip_prefix("wss://") {
+        (rest, 443)
+    } else if let Some(rest) = relay.strip_prefix("ws://") {
+        (rest, 80)
+    } else {
+        return Err(invalid());
+    };
+    let authority = rest.split('/').next().unwrap_or_default();
+    match authority.rsplit_once(':') {
+        Some((host, port)) => Ok((host, port.parse().map_err(|_| invalid())?)),
+        None if !authority.is_empty() => Ok((authority, default_port)),
+        None => Err(invalid()),
+    }
+}
+
+fn connect_relay(relay: &str, socks_port: Option<u16>) -> Result<RelaySocket, NostrError> {
+    let (host, port) = relay_host(relay)?;
+    let stream = match socks_port {
+        Some(socks_port) => {
+            Socks5Stream::connect(("127.0.0.1", socks_port), (host, port))?.into_inner()
+        }
+        None => TcpStream::connect((host, port))?,
+    };
+    stream.set_read_timeout(Some(RELAY_TIMEOUT))?;
+    stream.set_write_timeout(Some(RELAY_TIMEOUT))?;
+    let (socket, _) =
+        tungstenite::client_tls(relay, stream).map_err(|e| NostrError::WebSocket(e.to_string()))?;
+    Ok(socket)
+}
+
+/// Reads the next relay message, a JSON array.
+fn read_relay_message(socket: &mut RelaySocket) -> Result<Vec<Value>, NostrError> {
+    loop {
+        match socket.read()? {
+            WsMessage::Text(text) => return Ok(serde_json::from_str(&text)?),
+            WsMessage::Close(_) => {
+                return Err(NostrError::WebSocket("Closed by the relay".to_string()))
+            }
+            _ => continue,
+        }
+    }
+}
+
+fn send_relay_message(socket: &mut RelaySocket, message: Value) -> Result<(), NostrError> {
+    Ok(socket.send(WsMessage::Text(message.to_string()))?)
+}
+
+/// Publishes an event to a relay, waiting for the relay to accept it.
+#[cfg(feature = "maker")]
+pub(crate) fn publish_event(
+    relay: &str,
+    socks_port: Option<u16>,
+    event: &NostrEvent,
+) -> Result<(), NostrError> {
+    let mut socket = connect_relay(relay, socks_port)?;
+    send_relay_message(&mut socket, json!(["EVENT", event]))?;
+    let result = loop {
+        let message = read_relay_message(&mut socket)?;
+        match message.first().and_then(Value::as_str) {
+            Some("OK") if message.get(1).and_then(Value::as_str) == Some(event.id.as_str()) => {
+                break match message.get(2).and_then(Value::as_bool) {
+                    Some(true) => Ok(()),
+                    _ => Err(NostrError::Rejected(
+                        message
+                            .get(3)
+                            .and_then(Value::as_str)
+                            .unwrap_or_default()
+                            .to_string(),
+                    )),
+                };
+            }
+            Some("NOTICE") => log::info!("Notice from relay {}: {:?}", relay, message.get(1)),
+            _ => continue,
+        }
+    };
+    let _ = socket.close(None);
+    result
+}
+
+/// Queries the events of a relay matching `filter`, until the relay has sent its stored events.
+pub(crate) fn query_events(
+    relay: &str,
+    socks_port: Option<u16>,
+    filter: Value,
+) -> Result<Vec<NostrEvent>, NostrError> {
+    let mut subscription = [0u8; 8];
+    thread_rng().fill_bytes(&mut subscription);
+    let subscription = subscription.to_lower_hex_string();
+
+    let mut socket = connect_relay(relay, socks_port)?;
+    send_relay_message(&mut socket, json!(["REQ", subscription, filter]))?;
+    let mut events = Vec::new();
+    while events.len() < MAX_EVENTS {
+        let message = read_relay_message(&mut socket)?;
+        if message.get(1).and_then(Value::as_str) != Some(subscription.as_str()) {
+            continue;
+        }
+        match message.first().and_then(Value::as_str) {
+            Some("EVENT") => match message.get(2).cloned().map(serde_json::from_value) {
+                Some(Ok(event)) => events.push(event),
+                _ => log::debug!("Skipping malformed event from relay {}", relay),
+            },
+            Some("EOSE") => break,
+            Some("CLOSED") => {
+                return Err(NostrError::Rejected(
+                    message
+                        .get(2)
+                        .and_then(Value::as_str)
+                        .unwrap_or_default()
+                        .to_string(),
+                ))
+            }
+            _ => continue,
+        }
+    }
+    let _ = send_relay_message(&mut socket, json!(["CLOSE", subscription]));
+    let _ = socket.close(None);
+    Ok(events)
+}
+
+/// Reads the unexpired offers of the network from the relays, the latest one of each maker address. Relays
+/// failing are skipped.
+pub(crate) fn fetch_offers_from_relays(
+    relays: &[String],
+    network: Network,
+    socks_port: Option<u16>,
+) -> Vec<OfferAndAddress> {
+    let filter = json!({
+        "kinds": [OFFER_EVENT_KIND],
+        "#t": [OFFER_TOPIC],
+    });
+    let mut events = Vec::new();
+    for relay in relays {
+        match query_events(relay, socks_port, filter.clone()) {
+            Ok(relay_events) => {
+                log::info!("Relay {} sent {} offer events", relay, relay_events.len());
+                events.extend(relay_events);
+            }
+            Err(e) => log::warn!("Failed reading offers from relay {}: {}", relay, e),
+        }
+    }
+    offers_from_events(&events, network, unix_time())
+}
+
+#[cfg(all(test, feature = "maker"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nostr_events() {
+        let keypair = Keypair::new(&Secp256k1::new(), &mut thread_rng());
+        let event = NostrEvent::sign(
+            &keypair,
+            1_700_000_000,
+            1,
+            vec![vec!["t".to_string(), "coinswap".to_string()]],
+            "hello \"relay\"\n".to_string(),
+        );
+        assert!(event.verify().is_ok());
+        assert_eq!(event.tag("t"), Some("coinswap"));
+        assert_eq!(event.tag("d"), None);
+
+        // Events survive their JSON encoding.
+        let decoded: NostrEvent =
+            serde_json::from_str(&serde_json::to_string(&event).unwrap()).unwrap();
+        assert!(decoded.verify().is_ok());
+
+        let tampered = NostrEvent {
+            content: "bye".to_string(),
+            ..event.clone()
+        };
+        assert!(tampered.verify().is_err());
+        let forged = NostrEvent {
+            id: event_id(&event.pubkey, 0, 1, &event.tags, &event.content).to_lower_hex_string(),
+            created_at: 0,
+            ..event
+        };
+        assert!(forged.verify().is_err());
+
+        assert_eq!(
+            relay_host("wss://relay.example.com").unwrap(),
+            ("relay.example.com", 443)
+        );
+        assert_eq!(
+            relay_host("ws://abcdef.onion:8080/nostr").unwrap(),
+            ("abcdef.onion", 8080)
+        );
+        assert!(relay_host("https://relay.example.com").is_err());
+        assert!(relay_host("wss://").is_err());
+    }
+}
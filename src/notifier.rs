@@ -0,0 +1,236 @@
+//! Operator notifications.
+//!
+//! The [Notifier] subscribes to the [Event]s of the Maker and forwards the ones an operator should act on
+//! to configured webhooks and a script:
+//! - `swap_completed`: a swap completed.
+//! - `contract_broadcast`: contract txs of an ongoing swap were broadcast, and a recovery started.
+//! - `bond_expiring`: the fidelity bond is close to its timelock expiry.
+//! - `balance_low`: the wallet balance available for swaps fell below the configured threshold.
+//!
+//! Every notification is a JSON object:
+//!
+//! ```json
+//! {
+//!   "event": "bond_expiring",
+//!   "timestamp": 1760000000,
+//!   "message": "BondExpiring | outpoint 5c3e…:0 | 100 blocks remaining",
+//!   "details": { "outpoint": "5c3e…:0", "blocks_remaining": 100 }
+//! }
+//! ```
+//!
+//! Webhooks get it as the body of an HTTP POST. Only plain `http://` URLs are supported, TLS endpoints can
+//! be reached through a local relay, or from the script. The script is run with the event name as its only
+//! argument, and the notification on its standard input.
+//!
+//! Deliveries are attempted once. Failures are logged, and never interrupt the Maker.
+
+use std::{
+    io::{self, ErrorKind, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering::Relaxed},
+        mpsc::{Receiver, RecvTimeoutError},
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde_json::{json, Value};
+
+use crate::{events::Event, utill::HEART_BEAT_INTERVAL, wallet::WALLET_PASSPHRASE_ENV};
+
+/// Timeout of the connection, and of each read and write, to a webhook.
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Delivers operator notifications to webhooks and a script. See the [module docs](self).
+#[derive(Debug, Clone, Default)]
+pub struct Notifier {
+    webhooks: Vec<String>,
+    script: Option<PathBuf>,
+}
+
+impl Notifier {
+    /// A notifier posting to the `webhooks` URLs, and running the `script`, if any.
+    pub fn new(webhooks: Vec<String>, script: Option<PathBuf>) -> Self {
+        Self { webhooks, script }
+    }
+
+    /// Whether there's anywhere to deliver notifications.
+    pub fn is_enabled(&self) -> bool {
+        !self.webhooks.is_empty() || self.script.is_some()
+    }
+
+    /// Delivers the notifications of the `events`, until `shutdown` is set or the events stop.
+    pub fn run(&self, events: Receiver<Event>, shutdown: &AtomicBool) {
+        while !shutdown.load(Relaxed) {
+            match events.recv_timeout(HEART_BEAT_INTERVAL) {
+                Ok(event) => self.notify(&event),
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+    }
+
+    /// Delivers the notification of an event, if it's one operators are notified of.
+    pub fn notify(&self, event: &Event) {
+        let (name, notification) = match notification(event) {
+            Some(notification) => notification,
+            None => return,
+        };
+        let body = notification.to_string();
+        for url in &self.webhooks {
+            if let Err(e) = post_webhook(url, &body) {
+                log::warn!("Webhook {} failed for {}: {:?}", url, name, e);
+            }
+        }
+        if let Some(script) = &self.script {
+            if let Err(e) = run_script(script, name, &body) {
+                log::warn!("Notify script {:?} failed for {}: {:?}", script, name, e);
+            }
+        }
+    }
+}
+
+/// The name and JSON notification of an event, if operators are notified of it.
+pub fn notification(event: &Event) -> Option<(&'static str, Value)> {
+    let (name, details) = match event {
+        Event::SwapCompleted { swap_id } => ("swap_completed", json!({ "swap_id": swap_id })),
+        Event::ContractBroadcastDetected { txids } => (
+            "contract_broadcast",
+            json!({ "txids": txids.iter().map(|txid| txid.to_string()).collect::<Vec<_>>() }),
+        ),
+        Event::BondExpiring {
+            outpoint,
+            blocks_remaining,
+        } => (
+            "bond_expiring",
+            json!({ "outpoint": outpoint.to_string(), "blocks_remaining": blocks_remaining }),
+        ),
+        Event::BalanceLow { balance, threshold } => (
+            "balance_low",
+            json!({ "balance_sats": balance.to_sat(), "threshold_sats": threshold.to_sat() }),
+        ),
+        _ => return None,
+    };
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    Some((
+        name,
+        json!({
+            "event": name,
+            "timestamp": timestamp,
+            "message": event.to_string(),
+            "details": details,
+        }),
+    ))
+}
+
+/// Splits an `http://host[:port][/path]` URL into its address and path.
+pub(crate) fn parse_http_url(url: &str) -> io::Result<(String, String)> {
+    let invalid = || io::Error::new(ErrorKind::InvalidInput, "Not an http:// URL");
+    let rest = url.strip_prefix("http://").ok_or_else(invalid)?;
+    let (host, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    };
+    if host.is_empty() {
+        return Err(invalid());
+    }
+    let address = if host.contains(':') {
+        host.to_string()
+    } else {
+        format!("{}:80", host)
+    };
+    Ok((address, path.to_string()))
+}
+
+/// POSTs the JSON `body` to a webhook. Fails on a non 2xx response.
+fn post_webhook(url: &str, body: &str) -> io::Result<()> {
+    let (address, path) = parse_http_url(url)?;
+    let host = address.split(':').next().unwrap_or_default();
+    let socket_addr = address
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(ErrorKind::NotFound, "Webhook host not found"))?;
+    let mut stream = TcpStream::connect_timeout(&socket_addr, WEBHOOK_TIMEOUT)?;
+    stream.set_read_timeout(Some(WEBHOOK_TIMEOUT))?;
+    stream.set_write_timeout(Some(WEBHOOK_TIMEOUT))?;
+
+    write!(
+        stream,
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        path,
+        host,
+        body.len(),
+        body
+    )?;
+    stream.flush()?;
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    let status = response
+        .split_whitespace()
+        .nth(1)
+        .and_then(|status| status.parse::<u16>().ok());
+    match status {
+        Some(200..=299) => Ok(()),
+        _ => Err(io::Error::other("Webhook refused the notification")),
+    }
+}
+
+/// Runs the notify script with the event name as argument, and the JSON `body` on its standard input.
+fn run_script(script: &Path, name: &str, body: &str) -> io::Result<()> {
+    let mut child = Command::new(script)
+        .arg(name)
+        .env_remove(WALLET_PASSPHRASE_ENV)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(body.as_bytes())?;
+    }
+    if !child.wait()?.success() {
+        return Err(io::Error::other("Notify script exited with an error"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::Amount;
+
+    use super::*;
+
+    #[test]
+    fn test_notifications() {
+        let (name, notification) = notification(&Event::BalanceLow {
+            balance: Amount::from_sat(1_000),
+            threshold: Amount::from_sat(50_000),
+        })
+        .unwrap();
+        assert_eq!(name, "balance_low");
+        assert_eq!(notification["event"], "balance_low");
+        assert_eq!(notification["details"]["balance_sats"], 1_000);
+        assert_eq!(notification["details"]["threshold_sats"], 50_000);
+
+        // Not for operators.
+        assert!(super::notification(&Event::SwapStarted {
+            swap_id: "abcd".to_string(),
+            amount: Amount::from_sat(1_000),
+        })
+        .is_none());
+
+        assert_eq!(
+            parse_http_url("http://127.0.0.1:8080/hooks/maker").unwrap(),
+            ("127.0.0.1:8080".to_string(), "/hooks/maker".to_string())
+        );
+        assert_eq!(
+            parse_http_url("http://localhost").unwrap(),
+            ("localhost:80".to_string(), "/".to_string())
+        );
+        assert!(parse_http_url("https://example.com/hook").is_err());
+    }
+}
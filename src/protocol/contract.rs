@@ -25,9 +25,11 @@ pub(crate) use bitcoin::hashes::hash160::Hash as Hash160;
 
 use crate::utill::redeemscript_to_scriptpubkey;
 
+#[cfg(feature = "maker")]
+use super::messages::ProofOfFunding;
 use super::{
     error::ProtocolError,
-    messages::{FundingTxInfo, ProofOfFunding},
+    messages::{FeeTier, FundingTxInfo},
 };
 
 // relatively simple handling of miner fees for now, each funding transaction is considered
@@ -51,6 +53,46 @@ const PUBKEY_LENGTH: usize = 33;
 const PUBKEY1_OFFSET: usize = 2;
 const PUBKEY2_OFFSET: usize = PUBKEY1_OFFSET + PUBKEY_LENGTH + 1;
 
+/// Virtual size of a contract tx: one 2-of-2 multisig input, one contract output.
+pub(crate) const CONTRACT_TX_VSIZE: u64 = 150;
+
+/// Confirmation target, in blocks, of the feerate estimate the contract feerate is negotiated around.
+pub(crate) const CONTRACT_FEERATE_CONF_TARGET: u16 = 6;
+
+/// Lowest contract feerate, in sats/vB. The minimum relay feerate.
+pub(crate) const MIN_CONTRACT_FEERATE: f64 = 1.0;
+
+/// Contract feerates are accepted between the node's estimate divided and multiplied by this factor.
+const CONTRACT_FEERATE_TOLERANCE: f64 = 2.0;
+
+/// Range of contract feerates accepted for a swap, in sats/vB, around the node's feerate estimate.
+/// Without an estimate, ex: on regtest, any feerate up to `max_feerate` is accepted.
+pub(crate) fn contract_feerate_range(estimate: Option<f64>, max_feerate: f64) -> (f64, f64) {
+    let max_feerate = max_feerate.max(MIN_CONTRACT_FEERATE);
+    match estimate {
+        Some(estimate) => (
+            (estimate / CONTRACT_FEERATE_TOLERANCE)
+                .max(MIN_CONTRACT_FEERATE)
+                .min(max_feerate),
+            (estimate * CONTRACT_FEERATE_TOLERANCE)
+                .max(MIN_CONTRACT_FEERATE)
+                .min(max_feerate),
+        ),
+        None => (MIN_CONTRACT_FEERATE, max_feerate),
+    }
+}
+
+/// Whether a contract feerate, in sats/vB, is a positive finite number. NaN passes every range check, as
+/// comparisons with it are false, and pays no fee at all.
+pub(crate) fn is_valid_feerate(feerate: f64) -> bool {
+    feerate.is_finite() && feerate > 0.0
+}
+
+/// Absolute fee of a contract tx at the feerate, in sats/vB.
+pub(crate) fn contract_fee(feerate: f64) -> Amount {
+    Amount::from_sat((feerate * CONTRACT_TX_VSIZE as f64).ceil() as u64)
+}
+
 /// Calculate the coin swap fee based on various parameters.
 /// swap_amount in sats, refund_locktime in blocks.
 pub(crate) fn calculate_coinswap_fee(
@@ -69,6 +111,20 @@ pub(crate) fn calculate_coinswap_fee(
     total_fee.ceil() as u64
 }
 
+/// The amount relative fee of a swap of `amount` sats: the one of the tier with the highest minimum amount
+/// not above it, or `default_pct` below all the tiers.
+pub(crate) fn tiered_amount_relative_fee_pct(
+    default_pct: f64,
+    tiers: &[FeeTier],
+    amount: u64,
+) -> f64 {
+    tiers
+        .iter()
+        .filter(|tier| tier.min_amount <= amount)
+        .max_by_key(|tier| tier.min_amount)
+        .map_or(default_pct, |tier| tier.amount_relative_fee_pct)
+}
+
 /// Apply two signatures to a 2-of-2 multisig spend.
 pub(crate) fn apply_two_signatures_to_2of2_multisig_spend(
     key1: &PublicKey,
@@ -167,6 +223,7 @@ pub(crate) fn check_reedemscript_is_multisig(redeemscript: &Script) -> Result<()
 }
 
 /// Check if the given multisig redeem script contains the provided public key.
+#[cfg(feature = "maker")]
 pub(crate) fn check_multisig_has_pubkey(
     redeemscript: &Script,
     tweakable_point: &PublicKey,
@@ -184,6 +241,7 @@ pub(crate) fn check_multisig_has_pubkey(
 }
 
 /// Check if the given hashlock redeem script contains the provided public key and nonce.
+#[cfg(feature = "maker")]
 pub(crate) fn check_hashlock_has_pubkey(
     contract_redeemscript: &Script,
     tweakable_point: &PublicKey,
@@ -298,6 +356,7 @@ pub(crate) fn read_hashvalue_from_contract(
 }
 
 /// Check that all the contract redeemscripts involve the same hashvalue.
+#[cfg(feature = "maker")]
 pub(crate) fn check_hashvalues_are_equal(
     message: &ProofOfFunding,
 ) -> Result<Hash160, ProtocolError> {
@@ -421,6 +480,7 @@ pub(crate) fn create_receivers_contract_tx(
 }
 
 /// Check if a contract output is valid.
+#[cfg(feature = "maker")]
 pub(crate) fn is_contract_out_valid(
     contract_output: &TxOut,
     hashlock_pubkey: &PublicKey,
@@ -511,12 +571,13 @@ pub(crate) fn verify_contract_tx_sig(
             EcdsaSighashType::All,
         )?[..],
     )?;
-    let secp = Secp256k1::new();
+    let secp = Secp256k1::verification_only();
     Ok(secp.verify_ecdsa(&sighash, sig, &pubkey.inner)?)
 }
 
 #[cfg(test)]
 mod test {
+    #[cfg(feature = "maker")]
     use crate::protocol::messages::NextHopInfo;
 
     use super::*;
@@ -534,6 +595,7 @@ mod test {
 
     const TEST_CURRENT_HEIGHT: u32 = 100;
 
+    #[cfg(feature = "maker")]
     fn read_pubkeys_from_contract_reedimscript(
         contract_script: &Script,
     ) -> Result<(PublicKey, PublicKey), &'static str> {
@@ -741,21 +803,24 @@ mod test {
             deserialize(&Vec::from_hex(&expected_tx_hex).unwrap()).unwrap();
         assert_eq!(expected_tx, contract_tx);
 
-        // Extract contract script data
-        let hashvalue = read_hashvalue_from_contract(&contract_script).unwrap();
-        let locktime = read_contract_locktime(&contract_script).unwrap();
-        let (pub1, pub2) = read_pubkeys_from_contract_reedimscript(&contract_script).unwrap();
-
         // Validates if contract outpoint is correct
-        assert!(is_contract_out_valid(
-            &contract_tx.output[0],
-            &pub1,
-            &pub2,
-            &hashvalue,
-            &locktime,
-            &2
-        )
-        .is_ok());
+        #[cfg(feature = "maker")]
+        {
+            // Extract contract script data
+            let hashvalue = read_hashvalue_from_contract(&contract_script).unwrap();
+            let locktime = read_contract_locktime(&contract_script).unwrap();
+            let (pub1, pub2) = read_pubkeys_from_contract_reedimscript(&contract_script).unwrap();
+
+            assert!(is_contract_out_valid(
+                &contract_tx.output[0],
+                &pub1,
+                &pub2,
+                &hashvalue,
+                &locktime,
+                &2
+            )
+            .is_ok());
+        }
 
         // Validate if the contract transaction is spending correctl utxo
         assert!(validate_contract_tx(&contract_tx, Some(&spending_utxo), &contract_script).is_ok());
@@ -912,6 +977,7 @@ mod test {
         .is_ok());
     }
 
+    #[cfg(feature = "maker")]
     #[test]
     fn test_check_multisig_has_pubkey() {
         let secp = Secp256k1::new();
@@ -942,6 +1008,7 @@ mod test {
         assert_eq!(val, "wrong pubkeys in multisig_redeemscript");
     }
 
+    #[cfg(feature = "maker")]
     #[test]
     fn test_check_hashlock_has_pubkey() {
         let hash_value = Hash160::from_slice(&thread_rng().gen::<[u8; 20]>()).unwrap();
@@ -1210,6 +1277,7 @@ mod test {
         assert_eq!(tx_input_1, tx_input_2);
     }
 
+    #[cfg(feature = "maker")]
     #[test]
     fn test_check_hashvalues_are_equal() {
         let secp = Secp256k1::new();
@@ -1295,6 +1363,7 @@ mod test {
             }],
             refund_locktime: u16::default(),
             contract_feerate: u64::default(),
+            contract_feerate_sat_vb: None,
             id: "random".to_string(),
         };
 
@@ -1325,6 +1394,7 @@ mod test {
             }],
             refund_locktime: u16::default(),
             contract_feerate: u64::default(),
+            contract_feerate_sat_vb: None,
             id: "random".to_string(),
         };
 
@@ -1339,4 +1409,48 @@ mod test {
             "contract reedemscript doesn't have equal hashvalues"
         );
     }
+
+    #[test]
+    fn test_contract_feerate_range() {
+        assert_eq!(contract_feerate_range(None, 50.0), (1.0, 50.0));
+        assert_eq!(contract_feerate_range(Some(10.0), 50.0), (5.0, 20.0));
+        // Bounded by the relay minimum and the configured maximum.
+        assert_eq!(contract_feerate_range(Some(1.0), 50.0), (1.0, 2.0));
+        assert_eq!(contract_feerate_range(Some(200.0), 50.0), (50.0, 50.0));
+
+        assert_eq!(contract_fee(2.0), Amount::from_sat(300));
+        assert_eq!(contract_fee(1.01), Amount::from_sat(152));
+    }
+
+    #[test]
+    fn test_is_valid_feerate() {
+        assert!(is_valid_feerate(1.0));
+        assert!(is_valid_feerate(0.5));
+        assert!(!is_valid_feerate(0.0));
+        assert!(!is_valid_feerate(-1.0));
+        assert!(!is_valid_feerate(f64::NAN));
+        assert!(!is_valid_feerate(f64::INFINITY));
+        assert!(!is_valid_feerate(f64::NEG_INFINITY));
+
+        // NaN would pay no fee.
+        assert_eq!(contract_fee(f64::NAN), Amount::ZERO);
+    }
+
+    #[test]
+    fn test_tiered_amount_relative_fee_pct() {
+        // Unordered, as the maker may list them.
+        let tiers = ["10000000:1.5", "1000000:2"]
+            .iter()
+            .map(|tier| tier.parse::<FeeTier>().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(tiered_amount_relative_fee_pct(2.5, &tiers, 999_999), 2.5);
+        assert_eq!(tiered_amount_relative_fee_pct(2.5, &tiers, 1_000_000), 2.0);
+        assert_eq!(tiered_amount_relative_fee_pct(2.5, &tiers, 50_000_000), 1.5);
+        assert_eq!(tiered_amount_relative_fee_pct(2.5, &[], 50_000_000), 2.5);
+
+        assert_eq!(tiers[1].to_string(), "1000000:2");
+        assert!("1000000".parse::<FeeTier>().is_err());
+        assert!("1000000:-1".parse::<FeeTier>().is_err());
+        assert!("abc:1".parse::<FeeTier>().is_err());
+    }
 }
@@ -2,6 +2,8 @@
 
 use bitcoin::{secp256k1, Amount};
 
+use crate::error::ErrorCode;
+
 /// Represents errors encountered during protocol operations.
 ///
 /// This enum encapsulates various errors that can occur during protocol execution,
@@ -61,6 +63,25 @@ pub enum ProtocolError {
     General(&'static str),
 }
 
+impl ErrorCode for ProtocolError {
+    fn code(&self) -> u16 {
+        match self {
+            Self::Secp(_) => 2000,
+            Self::Script(_) => 2001,
+            Self::Hash(_) => 2002,
+            Self::Key(_) => 2003,
+            Self::Sighash(_) => 2004,
+            Self::WrongMessage { .. } => 2005,
+            Self::WrongNumOfSigs { .. } => 2006,
+            Self::WrongNumOfContractTxs { .. } => 2007,
+            Self::WrongNumOfPrivkeys { .. } => 2008,
+            Self::IncorrectFundingAmount { .. } => 2009,
+            Self::ScriptPubkey(_) => 2010,
+            Self::General(_) => 2011,
+        }
+    }
+}
+
 impl From<bitcoin::script::witness_program::Error> for ProtocolError {
     fn from(value: bitcoin::script::witness_program::Error) -> Self {
         Self::ScriptPubkey(value)
@@ -0,0 +1,745 @@
+//! The protocol, as a pure state machine for each side.
+//!
+//! [maker_transition] takes the message awaited next, and the message received from the Taker. It returns the
+//! [MakerAction] to perform, and the message awaited after it. It does no IO: reading and writing the messages,
+//! signing, funding and settling the swaps, all are left to the caller. The Maker server interprets the actions
+//! with its wallet, and replies over its socket, in the `handle_message` of the Maker handlers. Other
+//! environments, like tests or non-tokio runtimes, can drive the same machine with their own effects.
+//!
+//! The Taker sends one request at a time, and awaits its reply. [AwaitedReply::after] gives the reply awaited
+//! after a request, and [taker_transition] checks the received reply against it, returning the [TakerAction] to
+//! perform. The Taker routines send the requests, and interpret the actions with the swap state and the wallet.
+//!
+//! Since protocol version 2, the sessions are authenticated both ways: the Taker sends a session key in its
+//! hello, and the Maker signs the session with its identity key in its own. Hellos without them are refused, or a
+//! man in the middle could strip them and downgrade the connection to an unauthenticated session.
+
+#[cfg(feature = "maker")]
+use bitcoin::PublicKey;
+
+use super::{
+    error::ProtocolError,
+    messages::{
+        ContractSigsAsRecvrAndSender, ContractSigsForRecvr, ContractSigsForSender,
+        IdentitySignature, MakerHello, MakerToTakerMessage, Offer, PrivKeyHandover,
+        TakerToMakerMessage,
+    },
+    session::SessionNonce,
+};
+#[cfg(feature = "maker")]
+use super::{
+    messages::{
+        ContractSigsForRecvrAndSender, HashPreimage, ProofOfFunding, ReqContractSigsForRecvr,
+        ReqContractSigsForSender,
+    },
+    ProtocolPhase,
+};
+
+/// Protocol version spoken by this implementation.
+pub(crate) const PROTOCOL_VERSION: u32 = 2;
+
+/// Expected messages for the taker in the context of a connection.
+///
+/// If the received message doesn't match expected message,
+/// a protocol error will be returned.
+#[cfg(feature = "maker")]
+#[derive(Debug, Default, PartialEq, Clone)]
+pub(crate) enum ExpectedMessage {
+    #[default]
+    TakerHello,
+    NewlyConnectedTaker,
+    ReqContractSigsForSender,
+    ProofOfFunding,
+    ProofOfFundingORContractSigsForRecvrAndSender,
+    ReqContractSigsForRecvr,
+    HashPreimage,
+    PrivateKeyHandover,
+}
+
+#[cfg(feature = "maker")]
+impl ExpectedMessage {
+    /// The protocol phase this message is awaited in, setting how long the taker may stay silent.
+    pub(crate) fn phase(&self) -> ProtocolPhase {
+        match self {
+            // Nothing is signed until the taker asks for our contract signatures.
+            Self::TakerHello | Self::NewlyConnectedTaker | Self::ReqContractSigsForSender => {
+                ProtocolPhase::OfferFetch
+            }
+            Self::ProofOfFundingORContractSigsForRecvrAndSender | Self::ReqContractSigsForRecvr => {
+                ProtocolPhase::ContractSigs
+            }
+            Self::ProofOfFunding => ProtocolPhase::FundingConfirmation,
+            Self::HashPreimage | Self::PrivateKeyHandover => ProtocolPhase::PreimageReveal,
+        }
+    }
+
+    /// The messages accepted in this state, for the errors.
+    fn expected(&self) -> &'static str {
+        match self {
+            Self::TakerHello => "TakerHello",
+            Self::NewlyConnectedTaker => {
+                "any message but TakerHello or RespContractSigsForRecvrAndSender"
+            }
+            Self::ReqContractSigsForSender => "ReqContractSigsForSender",
+            Self::ProofOfFunding => "RespProofOfFunding",
+            Self::ProofOfFundingORContractSigsForRecvrAndSender => {
+                "RespProofOfFunding or RespContractSigsForRecvrAndSender"
+            }
+            Self::ReqContractSigsForRecvr => "ReqContractSigsForRecvr",
+            Self::HashPreimage => "RespHashPreimage",
+            Self::PrivateKeyHandover => "RespPrivKeyHandover",
+        }
+    }
+}
+
+/// What the Maker does upon a Taker message. Each action carries the message it acts on.
+#[cfg(feature = "maker")]
+#[derive(Debug)]
+#[allow(clippy::large_enum_variant)]
+pub(crate) enum MakerAction {
+    /// Restart the timer of the swap, the Taker is waiting for its funding to confirm. Nothing to reply.
+    KeepAlive(String),
+    /// Reply the handshake, opening a session with the nonce of the Taker, authenticated with its session key.
+    Hello(SessionNonce, PublicKey),
+    /// Reply our offer.
+    GiveOffer,
+    /// Sign the contract txs of the hop we receive, as its sender asked.
+    SignSenderContracts(ReqContractSigsForSender),
+    /// Check the confirmed funding of the hop we receive, and request the signatures of the hop we send.
+    VerifyFunding(ProofOfFunding),
+    /// Broadcast the funding of the hop we send, now that all its contracts are signed. Nothing to reply.
+    FundNextHop(ContractSigsForRecvrAndSender),
+    /// Sign the contract txs of the hop we send, as its receiver asked.
+    SignReceiverContracts(ReqContractSigsForRecvr),
+    /// Check the preimage, and hand over the multisig keys of the hop we send.
+    HandOverPrivkeys(HashPreimage),
+    /// Take over the multisig keys of the hop we receive, completing the swap. Nothing to reply.
+    Settle(PrivKeyHandover),
+}
+
+/// The action upon `message` while awaiting `state`, and the message awaited after it.
+#[cfg(feature = "maker")]
+pub(crate) fn maker_transition(
+    state: &ExpectedMessage,
+    message: TakerToMakerMessage,
+) -> Result<(MakerAction, ExpectedMessage), ProtocolError> {
+    use ExpectedMessage as S;
+    use TakerToMakerMessage as M;
+
+    let transition = match (state, message) {
+        // Keep-alives aren't a protocol step, they are welcome at any time after the hello.
+        (state, M::WaitingFundingConfirmation(id)) if *state != S::TakerHello => {
+            (MakerAction::KeepAlive(id), state.clone())
+        }
+        (S::TakerHello, M::TakerHello(m)) => {
+            if m.protocol_version_min != PROTOCOL_VERSION
+                && m.protocol_version_max != PROTOCOL_VERSION
+            {
+                return Err(ProtocolError::WrongMessage {
+                    expected: format!("Only protocol version {} is allowed", PROTOCOL_VERSION),
+                    received: format!(
+                        "min/max version  = {}/{}",
+                        m.protocol_version_min, m.protocol_version_max
+                    ),
+                });
+            }
+            // Without a session, earlier messages could be replayed into this connection.
+            let nonce = m.session_nonce.ok_or_else(|| ProtocolError::WrongMessage {
+                expected: "TakerHello with a session nonce".to_string(),
+                received: "TakerHello without a session nonce".to_string(),
+            })?;
+            let session_key = m.session_key.ok_or_else(|| ProtocolError::WrongMessage {
+                expected: "TakerHello with a session key".to_string(),
+                received: "TakerHello without a session key".to_string(),
+            })?;
+            (
+                MakerAction::Hello(nonce, session_key),
+                S::NewlyConnectedTaker,
+            )
+        }
+        // A fresh connection picks up the swap at any step, as the Taker reconnects for each message.
+        (S::NewlyConnectedTaker, M::ReqGiveOffer(_)) => {
+            (MakerAction::GiveOffer, S::ReqContractSigsForSender)
+        }
+        (S::NewlyConnectedTaker | S::ReqContractSigsForSender, M::ReqContractSigsForSender(m)) => {
+            (MakerAction::SignSenderContracts(m), S::ProofOfFunding)
+        }
+        (
+            S::NewlyConnectedTaker
+            | S::ProofOfFunding
+            | S::ProofOfFundingORContractSigsForRecvrAndSender,
+            M::RespProofOfFunding(m),
+        ) => (
+            MakerAction::VerifyFunding(m),
+            S::ProofOfFundingORContractSigsForRecvrAndSender,
+        ),
+        (
+            S::ProofOfFundingORContractSigsForRecvrAndSender,
+            M::RespContractSigsForRecvrAndSender(m),
+        ) => (MakerAction::FundNextHop(m), S::ReqContractSigsForRecvr),
+        (S::NewlyConnectedTaker | S::ReqContractSigsForRecvr, M::ReqContractSigsForRecvr(m)) => {
+            (MakerAction::SignReceiverContracts(m), S::HashPreimage)
+        }
+        (S::NewlyConnectedTaker | S::HashPreimage, M::RespHashPreimage(m)) => {
+            (MakerAction::HandOverPrivkeys(m), S::PrivateKeyHandover)
+        }
+        (S::PrivateKeyHandover, M::RespPrivKeyHandover(m)) => {
+            (MakerAction::Settle(m), S::PrivateKeyHandover)
+        }
+        (state, message) => {
+            return Err(ProtocolError::WrongMessage {
+                expected: state.expected().to_string(),
+                received: format!("{}", message),
+            })
+        }
+    };
+
+    Ok(transition)
+}
+
+/// Replies awaited by the Taker, after each of its requests. The counts are the number of swapcoins of the
+/// request, which the reply must match.
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) enum AwaitedReply {
+    MakerHello,
+    Offer,
+    ContractSigsForSender(usize),
+    ContractSigsForRecvr(usize),
+    ContractSigsAsRecvrAndSender { receivers: usize, senders: usize },
+    PrivKeyHandover(usize),
+}
+
+impl AwaitedReply {
+    /// The reply awaited after sending `request`. None if the Maker doesn't reply it.
+    pub(crate) fn after(request: &TakerToMakerMessage) -> Option<Self> {
+        use TakerToMakerMessage as M;
+        match request {
+            M::TakerHello(_) => Some(Self::MakerHello),
+            M::ReqGiveOffer(_) => Some(Self::Offer),
+            M::ReqContractSigsForSender(m) => Some(Self::ContractSigsForSender(m.txs_info.len())),
+            M::ReqContractSigsForRecvr(m) => Some(Self::ContractSigsForRecvr(m.txs.len())),
+            M::RespProofOfFunding(m) => Some(Self::ContractSigsAsRecvrAndSender {
+                receivers: m.confirmed_funding_txes.len(),
+                senders: m.next_coinswap_info.len(),
+            }),
+            M::RespHashPreimage(m) => Some(Self::PrivKeyHandover(
+                m.receivers_multisig_redeemscripts.len(),
+            )),
+            M::RespContractSigsForRecvrAndSender(_)
+            | M::RespPrivKeyHandover(_)
+            | M::WaitingFundingConfirmation(_) => None,
+        }
+    }
+
+    /// The message accepted in this state, for the errors.
+    fn expected(&self) -> &'static str {
+        match self {
+            Self::MakerHello => "MakerHello",
+            Self::Offer => "RespOffer",
+            Self::ContractSigsForSender(_) => "RespContractSigsForSender",
+            Self::ContractSigsForRecvr(_) => "RespContractSigsForRecvr",
+            Self::ContractSigsAsRecvrAndSender { .. } => "ReqContractSigsAsRecvrAndSender",
+            Self::PrivKeyHandover(_) => "RespPrivKeyHandover",
+        }
+    }
+}
+
+/// What the Taker does upon a Maker reply. Each action carries the reply it acts on.
+#[derive(Debug)]
+#[allow(clippy::large_enum_variant)]
+pub(crate) enum TakerAction {
+    /// Open the session with the nonce of the Maker, and check its identity signature of it.
+    OpenSession(SessionNonce, IdentitySignature),
+    /// Verify the offer, and add it to the offerbook.
+    TakeOffer(Box<Offer>),
+    /// Verify the signatures of the contracts of the hop we send, or watch.
+    VerifySenderSigs(ContractSigsForSender),
+    /// Verify the signatures of the contracts of the hop we receive, or watch.
+    VerifyReceiverSigs(ContractSigsForRecvr),
+    /// Check the amounts and contracts of the next hop, and get its contracts signed.
+    SignNextHop(ContractSigsAsRecvrAndSender),
+    /// Take over the multisig keys of the hop, and hand over ours.
+    TakeOverPrivkeys(PrivKeyHandover),
+}
+
+/// The action upon the `reply` of the Maker while awaiting `awaited`.
+pub(crate) fn taker_transition(
+    awaited: &AwaitedReply,
+    reply: MakerToTakerMessage,
+) -> Result<TakerAction, ProtocolError> {
+    use AwaitedReply as S;
+    use MakerToTakerMessage as M;
+
+    let action = match (awaited, reply) {
+        (S::MakerHello, M::MakerHello(m)) => {
+            let (nonce, identity) = check_maker_hello(m)?;
+            TakerAction::OpenSession(nonce, identity)
+        }
+        (S::Offer, M::RespOffer(m)) => TakerAction::TakeOffer(m),
+        (S::ContractSigsForSender(count), M::RespContractSigsForSender(m)) => {
+            if m.sigs.len() != *count {
+                return Err(ProtocolError::WrongNumOfSigs {
+                    expected: *count,
+                    received: m.sigs.len(),
+                });
+            }
+            TakerAction::VerifySenderSigs(m)
+        }
+        (S::ContractSigsForRecvr(count), M::RespContractSigsForRecvr(m)) => {
+            if m.sigs.len() != *count {
+                return Err(ProtocolError::WrongNumOfSigs {
+                    expected: *count,
+                    received: m.sigs.len(),
+                });
+            }
+            TakerAction::VerifyReceiverSigs(m)
+        }
+        (
+            S::ContractSigsAsRecvrAndSender { receivers, senders },
+            M::ReqContractSigsAsRecvrAndSender(m),
+        ) => {
+            if m.receivers_contract_txs.len() != *receivers {
+                return Err(ProtocolError::WrongNumOfContractTxs {
+                    expected: *receivers,
+                    received: m.receivers_contract_txs.len(),
+                });
+            }
+            if m.senders_contract_txs_info.len() != *senders {
+                return Err(ProtocolError::WrongNumOfContractTxs {
+                    expected: *senders,
+                    received: m.senders_contract_txs_info.len(),
+                });
+            }
+            TakerAction::SignNextHop(m)
+        }
+        (S::PrivKeyHandover(count), M::RespPrivKeyHandover(m)) => {
+            if m.multisig_privkeys.len() != *count {
+                return Err(ProtocolError::WrongNumOfPrivkeys {
+                    expected: *count,
+                    received: m.multisig_privkeys.len(),
+                });
+            }
+            TakerAction::TakeOverPrivkeys(m)
+        }
+        (awaited, reply) => {
+            return Err(ProtocolError::WrongMessage {
+                expected: awaited.expected().to_string(),
+                received: format!("{}", reply),
+            })
+        }
+    };
+
+    Ok(action)
+}
+
+/// The session nonce and the identity signature of the session of a [MakerHello], checked to be of our protocol
+/// version. The signature itself is checked once the session is opened with the nonce.
+fn check_maker_hello(
+    hello: MakerHello,
+) -> Result<(SessionNonce, IdentitySignature), ProtocolError> {
+    if hello.protocol_version_max != PROTOCOL_VERSION
+        || hello.protocol_version_min != PROTOCOL_VERSION
+    {
+        return Err(ProtocolError::WrongMessage {
+            expected: format!("Only protocol version {} is allowed", PROTOCOL_VERSION),
+            received: format!(
+                "min/max version  = {}/{}",
+                hello.protocol_version_min, hello.protocol_version_max
+            ),
+        });
+    }
+    // Without a session, the maker's replies could be replays.
+    let nonce = hello
+        .session_nonce
+        .ok_or_else(|| ProtocolError::WrongMessage {
+            expected: "MakerHello with a session nonce".to_string(),
+            received: "MakerHello without a session nonce".to_string(),
+        })?;
+    let identity = hello.identity.ok_or_else(|| ProtocolError::WrongMessage {
+        expected: "MakerHello with an identity signature".to_string(),
+        received: "MakerHello without an identity signature".to_string(),
+    })?;
+    Ok((nonce, identity))
+}
+
+#[cfg(all(test, feature = "maker"))]
+mod tests {
+    use bitcoin::hashes::Hash;
+
+    use super::*;
+    use crate::{
+        protocol::{
+            messages::{FidelityProof, GiveOffer, TakerHello},
+            session::new_session_key,
+            Hash160,
+        },
+        wallet::FidelityBond,
+    };
+    use bitcoin::{
+        absolute::LockTime,
+        secp256k1::{ecdsa::Signature, Message, Secp256k1},
+        Amount, OutPoint,
+    };
+
+    const STATES: [ExpectedMessage; 8] = [
+        ExpectedMessage::TakerHello,
+        ExpectedMessage::NewlyConnectedTaker,
+        ExpectedMessage::ReqContractSigsForSender,
+        ExpectedMessage::ProofOfFunding,
+        ExpectedMessage::ProofOfFundingORContractSigsForRecvrAndSender,
+        ExpectedMessage::ReqContractSigsForRecvr,
+        ExpectedMessage::HashPreimage,
+        ExpectedMessage::PrivateKeyHandover,
+    ];
+
+    fn messages() -> Vec<TakerToMakerMessage> {
+        vec![
+            TakerToMakerMessage::TakerHello(TakerHello {
+                protocol_version_min: PROTOCOL_VERSION,
+                protocol_version_max: PROTOCOL_VERSION,
+                session_nonce: Some([1; 32]),
+                session_key: Some(new_session_key().1),
+            }),
+            TakerToMakerMessage::ReqGiveOffer(GiveOffer),
+            TakerToMakerMessage::ReqContractSigsForSender(ReqContractSigsForSender {
+                txs_info: Vec::new(),
+                hashvalue: Hash160::all_zeros(),
+                locktime: 20,
+                contract_feerate_sat_vb: None,
+            }),
+            TakerToMakerMessage::RespProofOfFunding(ProofOfFunding {
+                confirmed_funding_txes: Vec::new(),
+                next_coinswap_info: Vec::new(),
+                refund_locktime: 20,
+                contract_feerate: 1000,
+                contract_feerate_sat_vb: None,
+                id: "swap".to_string(),
+            }),
+            TakerToMakerMessage::RespContractSigsForRecvrAndSender(ContractSigsForRecvrAndSender {
+                receivers_sigs: Vec::new(),
+                senders_sigs: Vec::new(),
+                id: "swap".to_string(),
+            }),
+            TakerToMakerMessage::ReqContractSigsForRecvr(ReqContractSigsForRecvr {
+                txs: Vec::new(),
+            }),
+            TakerToMakerMessage::RespHashPreimage(HashPreimage {
+                senders_multisig_redeemscripts: Vec::new(),
+                receivers_multisig_redeemscripts: Vec::new(),
+                preimage: [0; 32],
+            }),
+            TakerToMakerMessage::RespPrivKeyHandover(PrivKeyHandover {
+                multisig_privkeys: Vec::new(),
+                receipt: None,
+            }),
+            TakerToMakerMessage::WaitingFundingConfirmation("swap".to_string()),
+        ]
+    }
+
+    /// The message a transition acts on, to check each action keeps its message.
+    fn acted_on(action: &MakerAction) -> &'static str {
+        match action {
+            MakerAction::KeepAlive(_) => "WaitingFundingConfirmation",
+            MakerAction::Hello(..) => "TakerHello",
+            MakerAction::GiveOffer => "ReqGiveOffer",
+            MakerAction::SignSenderContracts(_) => "ReqContractSigsForSender",
+            MakerAction::VerifyFunding(_) => "RespProofOfFunding",
+            MakerAction::FundNextHop(_) => "RespContractSigsForRecvrAndSender",
+            MakerAction::SignReceiverContracts(_) => "ReqContractSigsForRecvr",
+            MakerAction::HandOverPrivkeys(_) => "RespHashPreimage",
+            MakerAction::Settle(_) => "RespPrivKeyHandover",
+        }
+    }
+
+    /// The state after `message` in `state`, None if it's refused.
+    fn expected_next(state: &ExpectedMessage, message: &str) -> Option<ExpectedMessage> {
+        use ExpectedMessage as S;
+        match (state, message) {
+            (S::TakerHello, "WaitingFundingConfirmation") => None,
+            (state, "WaitingFundingConfirmation") => Some(state.clone()),
+            (S::TakerHello, "TakerHello") => Some(S::NewlyConnectedTaker),
+            (S::NewlyConnectedTaker, "ReqGiveOffer") => Some(S::ReqContractSigsForSender),
+            (S::NewlyConnectedTaker | S::ReqContractSigsForSender, "ReqContractSigsForSender") => {
+                Some(S::ProofOfFunding)
+            }
+            (
+                S::NewlyConnectedTaker
+                | S::ProofOfFunding
+                | S::ProofOfFundingORContractSigsForRecvrAndSender,
+                "RespProofOfFunding",
+            ) => Some(S::ProofOfFundingORContractSigsForRecvrAndSender),
+            (
+                S::ProofOfFundingORContractSigsForRecvrAndSender,
+                "RespContractSigsForRecvrAndSender",
+            ) => Some(S::ReqContractSigsForRecvr),
+            (S::NewlyConnectedTaker | S::ReqContractSigsForRecvr, "ReqContractSigsForRecvr") => {
+                Some(S::HashPreimage)
+            }
+            (S::NewlyConnectedTaker | S::HashPreimage, "RespHashPreimage") => {
+                Some(S::PrivateKeyHandover)
+            }
+            (S::PrivateKeyHandover, "RespPrivKeyHandover") => Some(S::PrivateKeyHandover),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_maker_transitions() {
+        for state in STATES.iter() {
+            for message in messages() {
+                let name = message.to_string();
+                let expected = expected_next(state, &name);
+                match maker_transition(state, message) {
+                    Ok((action, next)) => {
+                        assert_eq!(acted_on(&action), name);
+                        assert_eq!(Some(next), expected, "{:?} upon {}", state, name);
+                    }
+                    Err(ProtocolError::WrongMessage { .. }) => {
+                        assert_eq!(expected, None, "{:?} refused {}", state, name)
+                    }
+                    Err(e) => panic!("unexpected error {:?}", e),
+                }
+            }
+        }
+
+        // Unsupported protocol versions are refused.
+        let hello = TakerToMakerMessage::TakerHello(TakerHello {
+            protocol_version_min: 3,
+            protocol_version_max: 4,
+            session_nonce: Some([1; 32]),
+            session_key: Some(new_session_key().1),
+        });
+        assert!(maker_transition(&ExpectedMessage::TakerHello, hello).is_err());
+
+        // So are takers not opening a session.
+        let hello = TakerToMakerMessage::TakerHello(TakerHello {
+            protocol_version_min: PROTOCOL_VERSION,
+            protocol_version_max: PROTOCOL_VERSION,
+            session_nonce: None,
+            session_key: Some(new_session_key().1),
+        });
+        assert!(maker_transition(&ExpectedMessage::TakerHello, hello).is_err());
+    }
+
+    #[test]
+    fn test_authentication_downgrade() {
+        // A taker hello stripped of its session key, leaving the session unauthenticated.
+        for version in [1, PROTOCOL_VERSION].iter() {
+            let hello = TakerToMakerMessage::TakerHello(TakerHello {
+                protocol_version_min: *version,
+                protocol_version_max: *version,
+                session_nonce: Some([1; 32]),
+                session_key: None,
+            });
+            assert!(matches!(
+                maker_transition(&ExpectedMessage::TakerHello, hello),
+                Err(ProtocolError::WrongMessage { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_maker_hello_downgrade() {
+        let (identity_secret, identity) = new_session_key();
+        let signature = IdentitySignature {
+            identity,
+            sig: Secp256k1::new().sign_ecdsa(&Message::from_digest([3; 32]), &identity_secret),
+        };
+        let hello = |identity: Option<IdentitySignature>, version: u32| MakerHello {
+            protocol_version_min: version,
+            protocol_version_max: version,
+            session_nonce: Some([2; 32]),
+            identity,
+        };
+
+        let (nonce, checked) =
+            check_maker_hello(hello(Some(signature.clone()), PROTOCOL_VERSION)).unwrap();
+        assert_eq!(nonce, [2; 32]);
+        assert_eq!(checked.identity, identity);
+
+        // A maker hello stripped of its identity signature, or of an older version not sending it.
+        assert!(check_maker_hello(hello(None, PROTOCOL_VERSION)).is_err());
+        assert!(check_maker_hello(hello(Some(signature), 1)).is_err());
+        assert!(check_maker_hello(hello(None, 1)).is_err());
+    }
+
+    const AWAITED: [AwaitedReply; 6] = [
+        AwaitedReply::MakerHello,
+        AwaitedReply::Offer,
+        AwaitedReply::ContractSigsForSender(0),
+        AwaitedReply::ContractSigsForRecvr(0),
+        AwaitedReply::ContractSigsAsRecvrAndSender {
+            receivers: 0,
+            senders: 0,
+        },
+        AwaitedReply::PrivKeyHandover(0),
+    ];
+
+    fn signature() -> Signature {
+        let (secret, _) = new_session_key();
+        Secp256k1::new().sign_ecdsa(&Message::from_digest([3; 32]), &secret)
+    }
+
+    fn offer() -> Offer {
+        let (_, pubkey) = new_session_key();
+        Offer {
+            base_fee: 1000,
+            amount_relative_fee_pct: 0.025,
+            time_relative_fee_pct: 0.001,
+            required_confirms: 1,
+            minimum_locktime: 20,
+            max_size: 100_000,
+            min_size: 10_000,
+            tweakable_point: pubkey,
+            fidelity: FidelityProof {
+                bond: FidelityBond {
+                    outpoint: OutPoint::null(),
+                    amount: Amount::from_sat(50_000),
+                    lock_time: LockTime::from_height(500).unwrap(),
+                    pubkey,
+                    conf_height: 100,
+                    cert_expiry: 1,
+                },
+                cert_hash: bitcoin::hashes::sha256d::Hash::all_zeros(),
+                cert_sig: signature(),
+            },
+            max_refund_locktime: 0,
+            min_contract_feerate: 0.0,
+            max_contract_feerate: 0.0,
+            expires_at: 0,
+            fee_tiers: Vec::new(),
+            identity: None,
+        }
+    }
+
+    /// A reply of each kind, with no swapcoins.
+    fn replies() -> Vec<MakerToTakerMessage> {
+        let (_, identity) = new_session_key();
+        vec![
+            MakerToTakerMessage::MakerHello(MakerHello {
+                protocol_version_min: PROTOCOL_VERSION,
+                protocol_version_max: PROTOCOL_VERSION,
+                session_nonce: Some([2; 32]),
+                identity: Some(IdentitySignature {
+                    identity,
+                    sig: signature(),
+                }),
+            }),
+            MakerToTakerMessage::RespOffer(Box::new(offer())),
+            MakerToTakerMessage::RespContractSigsForSender(ContractSigsForSender {
+                sigs: Vec::new(),
+            }),
+            MakerToTakerMessage::RespContractSigsForRecvr(ContractSigsForRecvr {
+                sigs: Vec::new(),
+            }),
+            MakerToTakerMessage::ReqContractSigsAsRecvrAndSender(ContractSigsAsRecvrAndSender {
+                receivers_contract_txs: Vec::new(),
+                senders_contract_txs_info: Vec::new(),
+            }),
+            MakerToTakerMessage::RespPrivKeyHandover(PrivKeyHandover {
+                multisig_privkeys: Vec::new(),
+                receipt: None,
+            }),
+        ]
+    }
+
+    /// The reply a Taker action acts on.
+    fn taker_acted_on(action: &TakerAction) -> &'static str {
+        match action {
+            TakerAction::OpenSession(..) => "MakerHello",
+            TakerAction::TakeOffer(_) => "RespOffer",
+            TakerAction::VerifySenderSigs(_) => "RespContractSigsForSender",
+            TakerAction::VerifyReceiverSigs(_) => "RespContractSigsForRecvr",
+            TakerAction::SignNextHop(_) => "ReqContractSigsAsRecvrAndSender",
+            TakerAction::TakeOverPrivkeys(_) => "RespPrivKeyHandover",
+        }
+    }
+
+    #[test]
+    fn test_awaited_replies() {
+        let awaited = messages()
+            .iter()
+            .map(AwaitedReply::after)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            awaited,
+            vec![
+                Some(AwaitedReply::MakerHello),
+                Some(AwaitedReply::Offer),
+                Some(AwaitedReply::ContractSigsForSender(0)),
+                Some(AwaitedReply::ContractSigsAsRecvrAndSender {
+                    receivers: 0,
+                    senders: 0
+                }),
+                // The Maker replies nothing to the signatures of its hops, the handover, and the keep-alives.
+                None,
+                Some(AwaitedReply::ContractSigsForRecvr(0)),
+                Some(AwaitedReply::PrivKeyHandover(0)),
+                None,
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_taker_transitions() {
+        for awaited in AWAITED.iter() {
+            for reply in replies() {
+                let name = reply.to_string();
+                match taker_transition(awaited, reply) {
+                    Ok(action) => {
+                        assert_eq!(awaited.expected(), name, "{:?} accepted {}", awaited, name);
+                        assert_eq!(taker_acted_on(&action), name);
+                    }
+                    Err(ProtocolError::WrongMessage { .. }) => {
+                        assert_ne!(awaited.expected(), name, "{:?} refused {}", awaited, name)
+                    }
+                    Err(e) => panic!("unexpected error {:?}", e),
+                }
+            }
+        }
+
+        // Replies not matching the swapcoins of the request are refused.
+        let mut replies = replies().into_iter().skip(2);
+        assert!(matches!(
+            taker_transition(
+                &AwaitedReply::ContractSigsForSender(1),
+                replies.next().unwrap()
+            ),
+            Err(ProtocolError::WrongNumOfSigs {
+                expected: 1,
+                received: 0
+            })
+        ));
+        assert!(matches!(
+            taker_transition(
+                &AwaitedReply::ContractSigsForRecvr(2),
+                replies.next().unwrap()
+            ),
+            Err(ProtocolError::WrongNumOfSigs {
+                expected: 2,
+                received: 0
+            })
+        ));
+        assert!(matches!(
+            taker_transition(
+                &AwaitedReply::ContractSigsAsRecvrAndSender {
+                    receivers: 0,
+                    senders: 1
+                },
+                replies.next().unwrap()
+            ),
+            Err(ProtocolError::WrongNumOfContractTxs {
+                expected: 1,
+                received: 0
+            })
+        ));
+        assert!(matches!(
+            taker_transition(&AwaitedReply::PrivKeyHandover(1), replies.next().unwrap()),
+            Err(ProtocolError::WrongNumOfPrivkeys {
+                expected: 1,
+                received: 0
+            })
+        ));
+    }
+}
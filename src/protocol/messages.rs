@@ -54,12 +54,15 @@
 //! Taker -> Maker2: [`TakerToMakerMessage::RespHashPreimage`] (for Maker2-Taker HTLC).
 //! Maker2 -> Taker: [`MakerToTakerMessage::RespPrivKeyHandover`] (For Maker2-Taker funding multisig).
 //! ```
+//!
+//! Each Maker's [PrivKeyHandover] carries a [SwapReceipt] of its hop, signed by its identity key. The Taker
+//! countersigns it and returns it in its own [PrivKeyHandover], so both keep a receipt signed by both.
 
-use std::fmt::Display;
+use std::{fmt::Display, str::FromStr};
 
 use bitcoin::{
     ecdsa::Signature, hashes::sha256d::Hash, secp256k1::SecretKey, Amount, PublicKey, ScriptBuf,
-    Transaction,
+    Transaction, Txid,
 };
 
 use serde::{Deserialize, Serialize};
@@ -68,6 +71,8 @@ use bitcoin::hashes::hash160::Hash as Hash160;
 
 use crate::wallet::FidelityBond;
 
+use super::contract::{calculate_coinswap_fee, is_valid_feerate, tiered_amount_relative_fee_pct};
+
 /// Defines the length of the Preimage.
 pub(crate) const PREIMAGE_LEN: usize = 32;
 
@@ -79,6 +84,13 @@ pub(crate) type Preimage = [u8; PREIMAGE_LEN];
 pub(crate) struct TakerHello {
     pub(crate) protocol_version_min: u32,
     pub(crate) protocol_version_max: u32,
+    /// Nonce of the Taker for the session of the connection. Not sent by older takers, which are refused.
+    #[serde(default)]
+    pub(crate) session_nonce: Option<[u8; 32]>,
+    /// Fresh key of the Taker authenticating the envelopes of the session, see
+    /// [session](crate::protocol::session). Not sent by older takers, which are refused.
+    #[serde(default)]
+    pub(crate) session_key: Option<PublicKey>,
 }
 
 /// Represents a request to give an offer.
@@ -86,7 +98,7 @@ pub(crate) struct TakerHello {
 pub(crate) struct GiveOffer;
 
 /// Contract Sigs requesting information for the Sender side of the hop.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub(crate) struct ContractTxInfoForSender {
     pub(crate) multisig_nonce: SecretKey,
     pub(crate) hashlock_nonce: SecretKey,
@@ -97,11 +109,14 @@ pub(crate) struct ContractTxInfoForSender {
 }
 
 /// Request for Contract Sigs **for** the Sender side of the hop.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub(crate) struct ReqContractSigsForSender {
     pub(crate) txs_info: Vec<ContractTxInfoForSender>,
     pub(crate) hashvalue: Hash160,
     pub(crate) locktime: u16,
+    /// Negotiated feerate of the contract txs, in sats/vB. Not sent by older takers.
+    #[serde(default)]
+    pub(crate) contract_feerate_sat_vb: Option<f64>,
 }
 
 /// Contract Sigs requesting information for the Receiver side of the hop.
@@ -143,7 +158,12 @@ pub(crate) struct ProofOfFunding {
     // TODO: Directly use Vec of Pubkeys.
     pub(crate) next_coinswap_info: Vec<NextHopInfo>,
     pub(crate) refund_locktime: u16,
+    /// Miner fee of each funding tx of the next hop, in sats. Also the fee of the contract txs, when
+    /// `contract_feerate_sat_vb` isn't set.
     pub(crate) contract_feerate: u64,
+    /// Negotiated feerate of the contract txs, in sats/vB. Not sent by older takers.
+    #[serde(default)]
+    pub(crate) contract_feerate_sat_vb: Option<f64>,
     pub(crate) id: String,
 }
 
@@ -183,10 +203,15 @@ pub(crate) struct MultisigPrivkey {
 #[derive(Debug, Serialize, Deserialize)]
 pub(crate) struct PrivKeyHandover {
     pub(crate) multisig_privkeys: Vec<MultisigPrivkey>,
+    /// Receipt of the swap, signed by the Maker, and countersigned in the Taker's handover. Not sent by older
+    /// peers.
+    #[serde(default)]
+    pub(crate) receipt: Option<SignedReceipt>,
 }
 
 /// All messages sent from Taker to Maker.
 #[derive(Debug, Serialize, Deserialize)]
+#[allow(clippy::large_enum_variant)]
 pub(crate) enum TakerToMakerMessage {
     /// Protocol Handshake.
     TakerHello(TakerHello),
@@ -207,6 +232,67 @@ pub(crate) enum TakerToMakerMessage {
     WaitingFundingConfirmation(String),
 }
 
+/// Kinds of [TakerToMakerMessage]s, used to target a specific protocol step.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageType {
+    /// [TakerToMakerMessage::TakerHello]
+    TakerHello,
+    /// [TakerToMakerMessage::ReqGiveOffer]
+    ReqGiveOffer,
+    /// [TakerToMakerMessage::ReqContractSigsForSender]
+    ReqContractSigsForSender,
+    /// [TakerToMakerMessage::RespProofOfFunding]
+    RespProofOfFunding,
+    /// [TakerToMakerMessage::RespContractSigsForRecvrAndSender]
+    RespContractSigsForRecvrAndSender,
+    /// [TakerToMakerMessage::ReqContractSigsForRecvr]
+    ReqContractSigsForRecvr,
+    /// [TakerToMakerMessage::RespHashPreimage]
+    RespHashPreimage,
+    /// [TakerToMakerMessage::RespPrivKeyHandover]
+    RespPrivKeyHandover,
+}
+
+impl MessageType {
+    /// The type of a Taker message as sent on the wire, in its envelope without the length prefix. `None` if the
+    /// bytes aren't a Taker message, or for keep-alive messages.
+    pub fn of_taker_message(bytes: &[u8]) -> Option<Self> {
+        super::wire::decode::<TakerToMakerMessage>(bytes)
+            .ok()?
+            .message_type()
+    }
+}
+
+impl TakerToMakerMessage {
+    /// The [MessageType] of this message. `None` for keep-alive messages, which aren't a protocol step.
+    pub(crate) fn message_type(&self) -> Option<MessageType> {
+        match self {
+            Self::TakerHello(_) => Some(MessageType::TakerHello),
+            Self::ReqGiveOffer(_) => Some(MessageType::ReqGiveOffer),
+            Self::ReqContractSigsForSender(_) => Some(MessageType::ReqContractSigsForSender),
+            Self::RespProofOfFunding(_) => Some(MessageType::RespProofOfFunding),
+            Self::RespContractSigsForRecvrAndSender(_) => {
+                Some(MessageType::RespContractSigsForRecvrAndSender)
+            }
+            Self::ReqContractSigsForRecvr(_) => Some(MessageType::ReqContractSigsForRecvr),
+            Self::RespHashPreimage(_) => Some(MessageType::RespHashPreimage),
+            Self::RespPrivKeyHandover(_) => Some(MessageType::RespPrivKeyHandover),
+            Self::WaitingFundingConfirmation(_) => None,
+        }
+    }
+
+    /// Id of the swap, for the messages carrying it.
+    #[cfg(feature = "maker")]
+    pub(crate) fn swap_id(&self) -> Option<&str> {
+        match self {
+            Self::RespProofOfFunding(proof) => Some(&proof.id),
+            Self::RespContractSigsForRecvrAndSender(sigs) => Some(&sigs.id),
+            Self::WaitingFundingConfirmation(id) => Some(id),
+            _ => None,
+        }
+    }
+}
+
 impl Display for TakerToMakerMessage {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -230,6 +316,12 @@ impl Display for TakerToMakerMessage {
 pub(crate) struct MakerHello {
     pub(crate) protocol_version_min: u32,
     pub(crate) protocol_version_max: u32,
+    /// Nonce of the Maker for the session of the connection. Not sent by older makers, which are refused.
+    #[serde(default)]
+    pub(crate) session_nonce: Option<[u8; 32]>,
+    /// Signature of the session by the identity key of the Maker. Not sent by older makers, which are refused.
+    #[serde(default)]
+    pub(crate) identity: Option<IdentitySignature>,
 }
 
 /// Contains proof data related to fidelity bond.
@@ -240,6 +332,75 @@ pub struct FidelityProof {
     pub(crate) cert_sig: bitcoin::secp256k1::ecdsa::Signature,
 }
 
+/// The long-term identity key of a maker, bound to its fidelity bond by a signature of the bond key.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct IdentityBinding {
+    pub(crate) identity: PublicKey,
+    pub(crate) sig: bitcoin::secp256k1::ecdsa::Signature,
+}
+
+/// A signature by the identity key of a maker.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct IdentitySignature {
+    pub(crate) identity: PublicKey,
+    pub(crate) sig: bitcoin::secp256k1::ecdsa::Signature,
+}
+
+/// The identity of a maker in its directory registration.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct IdentityProof {
+    pub(crate) binding: IdentityBinding,
+    /// Signature of the registered address and expiry, by the identity key.
+    pub(crate) advertisement_sig: bitcoin::secp256k1::ecdsa::Signature,
+    /// After a rotation, the previous identity, signing over its reputation to this one.
+    #[serde(default)]
+    pub(crate) rotation: Option<IdentitySignature>,
+}
+
+/// What one maker served in a completed swap, as issued by the maker at settlement.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SwapReceipt {
+    /// Unique id of the swap.
+    pub swap_id: String,
+    /// Amount received by the maker from the previous hop, in sats.
+    pub incoming_sats: u64,
+    /// Amount sent by the maker to the next hop, in sats.
+    pub outgoing_sats: u64,
+    /// Miner fees of the maker's funding txs, in sats.
+    pub miner_fees_sats: u64,
+    /// Funding txs of the previous hop.
+    pub incoming_funding_txids: Vec<Txid>,
+    /// Funding txs of the maker, to the next hop.
+    pub outgoing_funding_txids: Vec<Txid>,
+    /// Unix timestamp the maker received the preimage at.
+    pub completed_at: u64,
+    /// Session key of the taker's hello, the only key the taker countersigns with.
+    pub taker_key: PublicKey,
+}
+
+impl SwapReceipt {
+    /// Fee charged by the maker: incoming minus outgoing amount, in sats.
+    pub fn fee_sats(&self) -> u64 {
+        self.incoming_sats.saturating_sub(self.outgoing_sats)
+    }
+}
+
+/// A [SwapReceipt] signed by the identity key of the maker and countersigned by the taker.
+///
+/// The taker countersigns with the session key of its hello, which the maker signed into the receipt. The maker
+/// never learns its secret, unlike the multisig keys handed over in the same messages, so it can't forge the
+/// countersignature. A session key is fresh for every connection, so the receipts kept by the makers don't link
+/// the swaps of a taker together.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SignedReceipt {
+    /// The receipt.
+    pub receipt: SwapReceipt,
+    /// Signature of the maker.
+    pub maker_sig: IdentitySignature,
+    /// Countersignature of the taker. None until the taker signs.
+    pub taker_sig: Option<IdentitySignature>,
+}
+
 /// Represents an offer in the context of the Coinswap protocol.
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub(crate) struct Offer {
@@ -252,6 +413,111 @@ pub(crate) struct Offer {
     pub(crate) min_size: u64,
     pub(crate) tweakable_point: PublicKey,
     pub(crate) fidelity: FidelityProof,
+    /// Highest refund locktime accepted on the incoming contracts, in blocks. 0 for no limit.
+    #[serde(default)]
+    pub(crate) max_refund_locktime: u16,
+    /// Lowest contract feerate accepted, in sats/vB. 0 for makers not negotiating the feerate.
+    #[serde(default)]
+    pub(crate) min_contract_feerate: f64,
+    /// Highest contract feerate accepted, in sats/vB. 0 for makers not negotiating the feerate.
+    #[serde(default)]
+    pub(crate) max_contract_feerate: f64,
+    /// Unix time the offer expires at. 0 for makers not expiring their offers.
+    #[serde(default)]
+    pub(crate) expires_at: u64,
+    /// Amount relative fees of the larger swaps, by increasing minimum amount. Empty for makers without tiers.
+    #[serde(default)]
+    pub(crate) fee_tiers: Vec<FeeTier>,
+    /// Identity of the maker, signing its hellos. Not sent by older makers.
+    #[serde(default)]
+    pub(crate) identity: Option<IdentityBinding>,
+}
+
+/// An amount relative fee applying to the swaps of at least `min_amount` sats, written `min_amount:fee_pct`,
+/// e.g. `10000000:1.5`.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct FeeTier {
+    /// Smallest swap amount of the tier, in sats.
+    pub min_amount: u64,
+    /// Fee on the swap amount, in percent.
+    pub amount_relative_fee_pct: f64,
+}
+
+impl FromStr for FeeTier {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (min_amount, fee_pct) = s
+            .split_once(':')
+            .ok_or_else(|| format!("Fee tier {:?} isn't of the form min_amount:fee_pct", s))?;
+        let tier = FeeTier {
+            min_amount: min_amount
+                .trim()
+                .parse()
+                .map_err(|_| format!("Invalid minimum amount in fee tier {:?}", s))?,
+            amount_relative_fee_pct: fee_pct
+                .trim()
+                .parse()
+                .map_err(|_| format!("Invalid fee in fee tier {:?}", s))?,
+        };
+        if tier.amount_relative_fee_pct.is_nan() || tier.amount_relative_fee_pct < 0.0 {
+            return Err(format!("Negative fee in fee tier {:?}", s));
+        }
+        Ok(tier)
+    }
+}
+
+impl Display for FeeTier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.min_amount, self.amount_relative_fee_pct)
+    }
+}
+
+impl Offer {
+    /// Fee on a swap of `amount` sats, with its incoming contract locked for `refund_locktime` blocks. The amount
+    /// relative fee is the one of the tier of the amount, see [tiered_amount_relative_fee_pct].
+    pub(crate) fn coinswap_fee(&self, amount: u64, refund_locktime: u16) -> u64 {
+        calculate_coinswap_fee(
+            amount,
+            refund_locktime,
+            self.base_fee,
+            tiered_amount_relative_fee_pct(self.amount_relative_fee_pct, &self.fee_tiers, amount),
+            self.time_relative_fee_pct,
+        )
+    }
+
+    /// Whether the offer expired by unix time `now`. Offers without expiry never do.
+    pub(crate) fn is_expired(&self, now: u64) -> bool {
+        self.expires_at != 0 && self.expires_at <= now
+    }
+
+    /// Whether the maker accepts an incoming contract locked for `locktime` blocks, with `window` blocks
+    /// between its incoming and outgoing refund locktimes.
+    pub(crate) fn accepts_refund_locktime(&self, locktime: u16, window: u16) -> bool {
+        window >= self.minimum_locktime
+            && (self.max_refund_locktime == 0 || locktime <= self.max_refund_locktime)
+    }
+
+    /// Whether the maker negotiates the contract feerate.
+    pub(crate) fn negotiates_contract_feerate(&self) -> bool {
+        self.max_contract_feerate > 0.0
+    }
+
+    /// Whether the maker accepts contract txs at the feerate, in sats/vB.
+    pub(crate) fn accepts_contract_feerate(&self, feerate: f64) -> bool {
+        is_valid_feerate(feerate)
+            && feerate >= self.min_contract_feerate
+            && feerate <= self.max_contract_feerate
+    }
+
+    /// Whether the advertised contract feerate range is well formed: finite and not negative bounds, both zero
+    /// if the maker doesn't negotiate the feerate.
+    pub(crate) fn has_valid_contract_feerates(&self) -> bool {
+        [self.min_contract_feerate, self.max_contract_feerate]
+            .iter()
+            .all(|feerate| feerate.is_finite() && *feerate >= 0.0)
+            && self.min_contract_feerate <= self.max_contract_feerate
+    }
 }
 
 /// Contract Tx signatures provided by a Sender of a Coinswap.
@@ -289,6 +555,7 @@ pub(crate) struct ContractSigsForRecvr {
 
 /// All messages sent from Maker to Taker.
 #[derive(Debug, Serialize, Deserialize)]
+#[allow(clippy::large_enum_variant)]
 pub(crate) enum MakerToTakerMessage {
     /// Protocol Handshake.
     MakerHello(MakerHello),
@@ -329,6 +596,12 @@ pub struct DnsMetadata {
     pub url: String,
     /// Proof of the maker's fidelity bond funding.
     pub proof: FidelityProof,
+    /// Unix time the registration expires at, unless posted again. 0 for the directory's default expiry.
+    #[serde(default)]
+    pub expires_at: u64,
+    /// Identity of the maker, carrying its reputation across addresses. Not sent by older makers.
+    #[serde(default)]
+    pub identity: Option<IdentityProof>,
 }
 
 /// Enum representing DNS request message types.
@@ -342,8 +615,32 @@ pub enum DnsRequest {
         /// Metadata containing the maker's URL and fidelity proof.
         metadata: DnsMetadata,
     },
+    /// A request sent by a shutting down maker to remove its address from the DNS server.
+    /// Signed with the key of the fidelity bond, at unix time `timestamp`.
+    Remove {
+        /// Metadata containing the maker's URL and fidelity proof.
+        metadata: DnsMetadata,
+        /// Unix timestamp of the request.
+        timestamp: u64,
+        /// Signature of the removal by the fidelity bond key.
+        sig: bitcoin::secp256k1::ecdsa::Signature,
+    },
     /// A request sent by the taker to fetch all valid maker addresses from the DNS server.
+    /// Answered with the addresses, one per line, highest fidelity bond values first.
     Get,
+    /// A request for the makers, with their verified fidelity bonds, highest bond values first.
+    /// Answered with a `Vec<MakerListing>` of [MakerListing](crate::market::listing::MakerListing)s.
+    Listings,
+    /// A request for the latest [MarketSnapshot](crate::market::snapshot::MarketSnapshot) of the live makers.
+    /// Answered with an `Option<MarketSnapshot>`, `None` until the first snapshot is taken.
+    Snapshot,
+    /// A request for the uptime records of the registered makers.
+    /// Answered with an [UptimeReport](crate::market::uptime::UptimeReport).
+    Uptime,
+    /// A subscription to the offer book updates, see [crate::market::subscription].
+    /// Answered with the live maker addresses as a `Vec<String>`, highest fidelity bond values first, then
+    /// with an [OfferBookUpdate](crate::market::subscription::OfferBookUpdate) per change, on the same connection.
+    Subscribe,
     /// Dummy data used for integration tests.
     #[cfg(feature = "integration-test")]
     /// Send a dummy, request, only used in integration tests
@@ -2,8 +2,13 @@
 
 pub(crate) mod contract;
 pub mod error;
+pub(crate) mod machine;
 pub mod messages;
+pub mod phase;
+pub(crate) mod session;
+pub mod wire;
 
 pub(crate) use contract::Hash160;
 
 pub use messages::{DnsMetadata, DnsRequest};
+pub use phase::ProtocolPhase;
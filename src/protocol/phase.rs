@@ -0,0 +1,31 @@
+//! Phases of the swap protocol, each bounded by its own timeout.
+//!
+//! Makers and Takers configure a timeout for every phase. A peer silent for longer than the timeout of the
+//! current phase is dropped, and once coins are locked in contracts the swap goes straight to recovery
+//! instead of waiting for the peer indefinitely.
+
+use std::fmt;
+
+/// A phase of the swap protocol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProtocolPhase {
+    /// Fetching the Maker's offer, before anything is signed.
+    OfferFetch,
+    /// Exchanging the signatures of the contract txs.
+    ContractSigs,
+    /// Waiting for the funding txs to confirm.
+    FundingConfirmation,
+    /// Revealing the hash preimage and handing over the multisig private keys.
+    PreimageReveal,
+}
+
+impl fmt::Display for ProtocolPhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OfferFetch => write!(f, "offer fetch"),
+            Self::ContractSigs => write!(f, "contract signature exchange"),
+            Self::FundingConfirmation => write!(f, "funding confirmation"),
+            Self::PreimageReveal => write!(f, "preimage reveal"),
+        }
+    }
+}
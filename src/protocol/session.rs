@@ -0,0 +1,336 @@
+//! Replay protection of the messages exchanged over a connection.
+//!
+//! The Taker and the Maker each send a fresh random [SessionNonce] in their hello. The id of the session is the
+//! hash of both nonces, so neither side alone picks it. Every later message of the connection carries the session
+//! id and its sequence number in the wire envelope, counted separately in each direction from 0.
+//!
+//! A message recorded from another connection, another swap or another peer carries another session id, and an
+//! earlier message of the same connection carries an old sequence number. Both are rejected, so a relay or a
+//! malicious peer can't replay earlier messages, signed ones included, in a different context.
+//!
+//! The envelope itself isn't signed, so a relay could still move an old message body into a fresh envelope. To
+//! prevent it, the Taker also sends a fresh [session key](new_session_key) in its hello, and the Maker signs the
+//! session with its identity key. Both sides then derive an [EnvelopeKey] from the ECDH secret of the session key
+//! and the identity key, which only they know, and every later envelope carries a MAC of its body, session id and
+//! sequence number under it. A hello without the session key or the session signature is refused, see the
+//! [machine](super::machine), so a man in the middle can't downgrade the connection to an unauthenticated session.
+//!
+//! A session can also record the hashes of its messages in a [Transcript], as evidence of what was exchanged.
+
+use bitcoin::{
+    hashes::{sha256, Hash, HashEngine},
+    hex::DisplayHex,
+    secp256k1::{
+        ecdh::SharedSecret,
+        rand::{rngs::OsRng, RngCore},
+        Secp256k1, SecretKey,
+    },
+    PublicKey,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    net::TcpStream,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    error::NetError,
+    utill::{send_frame, unix_time},
+    wallet::{verify_session_signature, WalletError},
+};
+
+use super::{
+    messages::IdentitySignature,
+    wire::{decode_envelope, encode_in_session, EnvelopeKey, SessionId},
+};
+
+/// Length of the session nonces.
+pub(crate) const SESSION_NONCE_LEN: usize = 32;
+
+/// Random nonce contributed by one side to the session, in its hello.
+pub(crate) type SessionNonce = [u8; SESSION_NONCE_LEN];
+
+/// A fresh random nonce for a new session.
+pub(crate) fn new_session_nonce() -> SessionNonce {
+    let mut nonce = [0u8; SESSION_NONCE_LEN];
+    OsRng.fill_bytes(&mut nonce);
+    nonce
+}
+
+/// A fresh session key of the Taker, sent in its hello to authenticate the envelopes of the session.
+pub(crate) fn new_session_key() -> (SecretKey, PublicKey) {
+    let secret = SecretKey::new(&mut OsRng);
+    let public = PublicKey::new(secret.public_key(&Secp256k1::new()));
+    (secret, public)
+}
+
+/// Direction of a message of a [Transcript], for the Taker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MessageDirection {
+    /// Sent by the Taker to the Maker.
+    Sent,
+    /// Received by the Taker from the Maker.
+    Received,
+}
+
+/// Hash of one message of a session.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TranscriptEntry {
+    /// Unix timestamp the message was sent or received at.
+    pub at: u64,
+    /// Id of the session, hex encoded.
+    pub session: String,
+    /// Identity key of the Maker, if it signed the session.
+    pub maker_identity: Option<PublicKey>,
+    /// Direction of the message.
+    pub direction: MessageDirection,
+    /// Sequence number of the message in its direction.
+    pub sequence: u64,
+    /// Hash of the message as sent on the wire, in its sealed envelope.
+    pub message_hash: sha256::Hash,
+}
+
+/// Message hashes of the sessions of a swap, shared by its connections.
+pub(crate) type Transcript = Arc<Mutex<Vec<TranscriptEntry>>>;
+
+/// The messages exchanged in one session, established by the hellos.
+#[derive(Debug, Clone)]
+pub(crate) struct Session {
+    id: SessionId,
+    /// Sequence number of the next message sent.
+    sent: u64,
+    /// Sequence number of the next message expected.
+    received: u64,
+    /// Identity key of the Maker, once it signed the session.
+    peer_identity: Option<PublicKey>,
+    /// Key of the envelope MACs, once the session is authenticated.
+    envelope_key: Option<EnvelopeKey>,
+    /// Transcript the messages are recorded in, if any.
+    transcript: Option<Transcript>,
+}
+
+impl Session {
+    /// The session of the hellos with these nonces. Both sides derive the same one.
+    pub(crate) fn new(taker_nonce: &SessionNonce, maker_nonce: &SessionNonce) -> Self {
+        let mut engine = sha256::Hash::engine();
+        engine.input(taker_nonce);
+        engine.input(maker_nonce);
+        Self {
+            id: sha256::Hash::from_engine(engine).to_byte_array(),
+            sent: 0,
+            received: 0,
+            peer_identity: None,
+            envelope_key: None,
+            transcript: None,
+        }
+    }
+
+    /// Records the hashes of the next messages of this session in the transcript.
+    pub(crate) fn record_into(&mut self, transcript: &Transcript) {
+        self.transcript = Some(transcript.clone());
+    }
+
+    fn record(&self, direction: MessageDirection, sequence: u64, bytes: &[u8]) {
+        if let Some(Ok(mut transcript)) = self.transcript.as_ref().map(|t| t.lock()) {
+            transcript.push(TranscriptEntry {
+                at: unix_time(),
+                session: self.id.to_lower_hex_string(),
+                maker_identity: self.peer_identity,
+                direction,
+                sequence,
+                message_hash: sha256::Hash::hash(bytes),
+            });
+        }
+    }
+
+    /// Id of the session.
+    #[cfg(feature = "maker")]
+    pub(crate) fn id(&self) -> &SessionId {
+        &self.id
+    }
+
+    /// Checks the signature of this session by the identity key of the Maker, and records the identity. The next
+    /// envelopes are authenticated with the secret of our `session_key` and the identity key.
+    pub(crate) fn authenticate(
+        &mut self,
+        signature: &IdentitySignature,
+        session_key: &SecretKey,
+    ) -> Result<(), WalletError> {
+        verify_session_signature(signature, &self.id)?;
+        self.peer_identity = Some(signature.identity);
+        self.bind(&SharedSecret::new(&signature.identity.inner, session_key));
+        Ok(())
+    }
+
+    /// Authenticates the next envelopes of this session with the secret shared by the session key of the Taker
+    /// and the identity key of the Maker.
+    pub(crate) fn bind(&mut self, shared_secret: &SharedSecret) {
+        self.envelope_key = Some(EnvelopeKey::new(shared_secret, &self.id));
+    }
+
+    /// Identity key of the Maker, if it signed the session.
+    pub(crate) fn peer_identity(&self) -> Option<&PublicKey> {
+        self.peer_identity.as_ref()
+    }
+
+    /// Encodes the next message sent in this session.
+    pub(crate) fn encode<T: Serialize>(&mut self, message: &T) -> Result<Vec<u8>, NetError> {
+        let bytes = encode_in_session(message, self.id, self.sent, self.envelope_key.as_ref())?;
+        self.record(MessageDirection::Sent, self.sent, &bytes);
+        self.sent += 1;
+        Ok(bytes)
+    }
+
+    /// Sends the next message of this session, returning the bytes sent, with the length prefix.
+    pub(crate) fn send<T: Serialize>(
+        &mut self,
+        socket: &mut TcpStream,
+        message: &T,
+    ) -> Result<usize, NetError> {
+        let bytes = self.encode(message)?;
+        send_frame(socket, &bytes)
+    }
+
+    /// Decodes the next message received in this session.
+    ///
+    /// Errors with [NetError::SessionMismatch] for a message outside of this session, with
+    /// [NetError::OutOfSequence] for a message replayed, dropped or reordered within it, and with
+    /// [NetError::UnauthenticatedMessage] for an envelope without a valid MAC, once authenticated.
+    pub(crate) fn decode<T: DeserializeOwned>(&mut self, bytes: &[u8]) -> Result<T, NetError> {
+        let (message, session, sequence) = decode_envelope(bytes, self.envelope_key.as_ref())?;
+        if session != Some(self.id) {
+            return Err(NetError::SessionMismatch);
+        }
+        match sequence {
+            Some(sequence) if sequence == self.received => {
+                self.record(MessageDirection::Received, sequence, bytes);
+                self.received += 1;
+                Ok(message)
+            }
+            Some(sequence) => Err(NetError::OutOfSequence {
+                expected: self.received,
+                received: sequence,
+            }),
+            None => Err(NetError::SessionMismatch),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::wire::encode;
+
+    #[test]
+    fn test_session_replay() {
+        let (taker_nonce, maker_nonce) = (new_session_nonce(), new_session_nonce());
+        let mut taker = Session::new(&taker_nonce, &maker_nonce);
+        let mut maker = Session::new(&taker_nonce, &maker_nonce);
+
+        let first = taker.encode(&"first".to_string()).unwrap();
+        let second = taker.encode(&"second".to_string()).unwrap();
+        assert_eq!(maker.decode::<String>(&first).unwrap(), "first");
+
+        // Replaying an earlier message of the session.
+        assert!(matches!(
+            maker.decode::<String>(&first),
+            Err(NetError::OutOfSequence {
+                expected: 1,
+                received: 0
+            })
+        ));
+        assert_eq!(maker.decode::<String>(&second).unwrap(), "second");
+
+        // Skipping a message.
+        taker.encode(&"dropped".to_string()).unwrap();
+        let fourth = taker.encode(&"fourth".to_string()).unwrap();
+        assert!(matches!(
+            maker.decode::<String>(&fourth),
+            Err(NetError::OutOfSequence {
+                expected: 2,
+                received: 3
+            })
+        ));
+
+        // Replaying into another session, and messages outside of any session.
+        let mut other = Session::new(&taker_nonce, &new_session_nonce());
+        assert!(matches!(
+            other.decode::<String>(&first),
+            Err(NetError::SessionMismatch)
+        ));
+        assert!(matches!(
+            other.decode::<String>(&encode(&"plain".to_string()).unwrap()),
+            Err(NetError::SessionMismatch)
+        ));
+
+        // Each direction is numbered on its own.
+        let reply = maker.encode(&"reply".to_string()).unwrap();
+        assert_eq!(taker.decode::<String>(&reply).unwrap(), "reply");
+
+        // Recorded messages, sent and received.
+        let transcript = Transcript::default();
+        let (mut taker, mut maker) = (
+            Session::new(&taker_nonce, &maker_nonce),
+            Session::new(&taker_nonce, &maker_nonce),
+        );
+        taker.record_into(&transcript);
+        let first = taker.encode(&"first".to_string()).unwrap();
+        maker.decode::<String>(&first).unwrap();
+        taker
+            .decode::<String>(&maker.encode(&"reply".to_string()).unwrap())
+            .unwrap();
+        let entries = transcript.lock().unwrap();
+        assert_eq!(
+            entries
+                .iter()
+                .map(|e| (e.direction, e.sequence))
+                .collect::<Vec<_>>(),
+            vec![(MessageDirection::Sent, 0), (MessageDirection::Received, 0)]
+        );
+        assert_eq!(entries[0].message_hash, sha256::Hash::hash(&first));
+    }
+
+    #[test]
+    fn test_session_authentication() {
+        let (taker_nonce, maker_nonce) = (new_session_nonce(), new_session_nonce());
+        let (identity_secret, identity) = new_session_key();
+        let (session_secret, session_key) = new_session_key();
+        let mut taker = Session::new(&taker_nonce, &maker_nonce);
+        let mut maker = Session::new(&taker_nonce, &maker_nonce);
+        taker.bind(&SharedSecret::new(&identity.inner, &session_secret));
+        maker.bind(&SharedSecret::new(&session_key.inner, &identity_secret));
+
+        let first = taker.encode(&"first".to_string()).unwrap();
+        assert_eq!(maker.decode::<String>(&first).unwrap(), "first");
+        let reply = maker.encode(&"reply".to_string()).unwrap();
+        assert_eq!(taker.decode::<String>(&reply).unwrap(), "reply");
+
+        // A relay knows the session id and the sequence, but can't wrap a body in a valid envelope.
+        let mut relay = Session::new(&taker_nonce, &maker_nonce);
+        relay.sent = 1;
+        let rewrapped = relay.encode(&"first".to_string()).unwrap();
+        assert!(matches!(
+            maker.decode::<String>(&rewrapped),
+            Err(NetError::UnauthenticatedMessage)
+        ));
+        let (relay_secret, _) = new_session_key();
+        relay.bind(&SharedSecret::new(&identity.inner, &relay_secret));
+        relay.sent = 1;
+        let forged = relay.encode(&"first".to_string()).unwrap();
+        assert!(matches!(
+            maker.decode::<String>(&forged),
+            Err(NetError::UnauthenticatedMessage)
+        ));
+
+        // The session goes on with the genuine message. The MAC holds for any encoding of the body.
+        let second = (0.1f64, 2.5f64, u64::MAX, vec![0u8; 3], None::<String>);
+        let bytes = taker.encode(&second).unwrap();
+        assert_eq!(
+            maker
+                .decode::<(f64, f64, u64, Vec<u8>, Option<String>)>(&bytes)
+                .unwrap(),
+            second
+        );
+    }
+}
@@ -0,0 +1,258 @@
+//! Versioned wire format of the network messages.
+//!
+//! Every message goes on the wire as a CBOR envelope map `{"v": <wire version>, "b": <message>}`, in a
+//! length-prefixed frame. The envelope is the stable part of the format: it doesn't change between versions.
+//!
+//! [WIRE_VERSION] is bumped with every change of the messages. Additive changes, new optional fields or new
+//! message variants, keep older peers working:
+//! - Unknown fields of a message are ignored. New fields must be `#[serde(default)]`, as older peers don't send them.
+//! - A message variant unknown to us, from a peer with a newer version, is reported as [NetError::UnknownMessage],
+//!   so the caller can tell a peer that's ahead of us from a broken one.
+//!
+//! Peers older than [MIN_WIRE_VERSION] are rejected.
+//!
+//! Messages of a swap session also carry the session id and their sequence number in the envelope, and once the
+//! session is authenticated, a MAC of the whole envelope. See [Session](super::session::Session).
+
+use std::fmt;
+
+use bitcoin::secp256k1::ecdh::SharedSecret;
+use hmac::{Hmac, Mac};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_cbor::Value;
+use sha2::Sha256;
+use zeroize::Zeroizing;
+
+use crate::error::NetError;
+
+/// Id of a session, see [Session](super::session::Session).
+pub(crate) type SessionId = [u8; 32];
+
+/// Wire format version of this build.
+pub const WIRE_VERSION: u16 = 4;
+
+/// Oldest wire format version still understood.
+pub const MIN_WIRE_VERSION: u16 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct Envelope<T> {
+    #[serde(rename = "v")]
+    version: u16,
+    #[serde(rename = "b")]
+    body: T,
+    #[serde(rename = "n", default, skip_serializing_if = "Option::is_none")]
+    session: Option<SessionId>,
+    #[serde(rename = "s", default, skip_serializing_if = "Option::is_none")]
+    sequence: Option<u64>,
+    #[serde(rename = "m", default, skip_serializing_if = "Option::is_none")]
+    mac: Option<[u8; 32]>,
+}
+
+/// Key authenticating the envelopes of a session, derived from a secret shared by both sides.
+#[derive(Clone)]
+pub(crate) struct EnvelopeKey(Zeroizing<[u8; 32]>);
+
+impl fmt::Debug for EnvelopeKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("EnvelopeKey(..)")
+    }
+}
+
+impl EnvelopeKey {
+    /// The key of the envelopes of `session`, from the secret shared by its sides.
+    pub(crate) fn new(shared_secret: &SharedSecret, session: &SessionId) -> Self {
+        let mut mac = Self::hmac(&shared_secret.secret_bytes());
+        mac.update(b"coinswap envelope");
+        mac.update(session);
+        Self(Zeroizing::new(mac.finalize().into_bytes().into()))
+    }
+
+    fn hmac(key: &[u8]) -> Hmac<Sha256> {
+        Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any length")
+    }
+
+    fn mac(&self, bytes: &[u8]) -> [u8; 32] {
+        let mut mac = Self::hmac(self.0.as_ref());
+        mac.update(bytes);
+        mac.finalize().into_bytes().into()
+    }
+
+    fn verify(&self, bytes: &[u8], tag: &[u8; 32]) -> bool {
+        let mut mac = Self::hmac(self.0.as_ref());
+        mac.update(bytes);
+        mac.verify_slice(tag).is_ok()
+    }
+}
+
+/// Encodes a message in the versioned envelope.
+pub fn encode<T: Serialize>(message: &T) -> Result<Vec<u8>, NetError> {
+    encode_envelope(message, None, None)
+}
+
+/// Decodes a message from the versioned envelope.
+pub fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, NetError> {
+    let (body, _, _) = decode_envelope(bytes, None)?;
+    Ok(body)
+}
+
+/// Encodes a message of the `session`, numbered `sequence`, authenticated with `key` if given.
+///
+/// The MAC covers the encoding of the envelope without it, with the message as a CBOR value, so the receiver
+/// encodes the same bytes again from the value it decoded.
+pub(super) fn encode_in_session<T: Serialize>(
+    message: &T,
+    session: SessionId,
+    sequence: u64,
+    key: Option<&EnvelopeKey>,
+) -> Result<Vec<u8>, NetError> {
+    let key = match key {
+        Some(key) => key,
+        None => return encode_envelope(message, Some(session), Some(sequence)),
+    };
+    let mut envelope = Envelope {
+        version: WIRE_VERSION,
+        body: serde_cbor::value::to_value(message)?,
+        session: Some(session),
+        sequence: Some(sequence),
+        mac: None,
+    };
+    envelope.mac = Some(key.mac(&serde_cbor::to_vec(&envelope)?));
+    Ok(serde_cbor::to_vec(&envelope)?)
+}
+
+/// Decodes a message, with the session id and sequence number it carries. With a `key`, the envelope must carry
+/// a valid MAC, or it's refused as [NetError::UnauthenticatedMessage].
+pub(super) fn decode_envelope<T: DeserializeOwned>(
+    bytes: &[u8],
+    key: Option<&EnvelopeKey>,
+) -> Result<(T, Option<SessionId>, Option<u64>), NetError> {
+    let mut envelope: Envelope<Value> = serde_cbor::from_slice(bytes)?;
+    if envelope.version < MIN_WIRE_VERSION {
+        return Err(NetError::UnsupportedWireVersion(envelope.version));
+    }
+    if let Some(key) = key {
+        let mac = envelope
+            .mac
+            .take()
+            .ok_or(NetError::UnauthenticatedMessage)?;
+        if !key.verify(&serde_cbor::to_vec(&envelope)?, &mac) {
+            return Err(NetError::UnauthenticatedMessage);
+        }
+    }
+
+    let version = envelope.version;
+    let name = message_name(&envelope.body);
+    let body = serde_cbor::value::from_value(envelope.body).map_err(|e| match name {
+        // A newer peer may send messages we don't know yet.
+        Some(message) if version > WIRE_VERSION => NetError::UnknownMessage { version, message },
+        _ => NetError::Cbor(e),
+    })?;
+    Ok((body, envelope.session, envelope.sequence))
+}
+
+fn encode_envelope<T: Serialize>(
+    message: &T,
+    session: Option<SessionId>,
+    sequence: Option<u64>,
+) -> Result<Vec<u8>, NetError> {
+    Ok(serde_cbor::to_vec(&Envelope {
+        version: WIRE_VERSION,
+        body: message,
+        session,
+        sequence,
+        mac: None,
+    })?)
+}
+
+/// Name of an enum message: the tag of a variant with data, or the name of a unit variant.
+fn message_name(body: &Value) -> Option<String> {
+    match body {
+        Value::Map(map) if map.len() == 1 => match map.keys().next() {
+            Some(Value::Text(name)) => Some(name.clone()),
+            _ => None,
+        },
+        Value::Text(name) => Some(name.clone()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct HelloV1 {
+        min: u32,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct HelloV2 {
+        min: u32,
+        #[serde(default)]
+        max: u32,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum MessageV1 {
+        Hello(HelloV1),
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum MessageV2 {
+        Hello(HelloV2),
+        Goodbye,
+    }
+
+    #[test]
+    fn test_wire_compatibility() {
+        // A new field is ignored by older peers, and defaulted by newer ones.
+        let bytes = encode(&MessageV2::Hello(HelloV2 { min: 1, max: 2 })).unwrap();
+        assert_eq!(
+            decode::<MessageV1>(&bytes).unwrap(),
+            MessageV1::Hello(HelloV1 { min: 1 })
+        );
+        let bytes = encode(&MessageV1::Hello(HelloV1 { min: 1 })).unwrap();
+        assert_eq!(
+            decode::<MessageV2>(&bytes).unwrap(),
+            MessageV2::Hello(HelloV2 { min: 1, max: 0 })
+        );
+
+        // A new variant from a newer peer is reported as unknown.
+        let bytes = serde_cbor::to_vec(&Envelope {
+            version: WIRE_VERSION + 1,
+            body: MessageV2::Goodbye,
+            session: None,
+            sequence: None,
+            mac: None,
+        })
+        .unwrap();
+        match decode::<MessageV1>(&bytes) {
+            Err(NetError::UnknownMessage { version, message }) => {
+                assert_eq!(version, WIRE_VERSION + 1);
+                assert_eq!(message, "Goodbye");
+            }
+            other => panic!("Expected UnknownMessage, got {:?}", other),
+        }
+
+        // Same version, a broken message.
+        let bytes = encode(&MessageV2::Goodbye).unwrap();
+        assert!(matches!(
+            decode::<MessageV1>(&bytes),
+            Err(NetError::Cbor(_))
+        ));
+
+        // Too old peers.
+        let bytes = serde_cbor::to_vec(&Envelope {
+            version: MIN_WIRE_VERSION - 1,
+            body: MessageV1::Hello(HelloV1 { min: 1 }),
+            session: None,
+            sequence: None,
+            mac: None,
+        })
+        .unwrap();
+        assert!(matches!(
+            decode::<MessageV1>(&bytes),
+            Err(NetError::UnsupportedWireVersion(0))
+        ));
+    }
+}
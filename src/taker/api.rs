@@ -6,83 +6,108 @@
 //! [SwapParams]: Set of parameters defining a specific Swap round.
 //! [OngoingSwapState]: Represents the State of an ongoing swap round. All swap related data are stored in this state.
 //!
-//! [Taker::do_coinswap]: The routine running all other protocol subroutines.
+//! [Taker::do_coinswap]: The routine running all other protocol subroutines. [Taker::do_coinswap_async] is its async
+//! version, talking to the makers over tokio sockets.
 
 use std::{
+    cmp::Reverse,
     collections::{HashMap, HashSet},
-    io::BufWriter,
-    net::TcpStream,
+    future::Future,
+    io::{self, BufWriter},
+    ops::RangeBounds,
     path::{Path, PathBuf},
     process::Child,
-    thread::sleep,
+    sync::{Arc, Mutex},
+    thread::{self, JoinHandle},
     time::{Duration, Instant},
 };
 
-use bitcoind::bitcoincore_rpc::RpcApi;
+use tokio::{
+    runtime::{Handle, RuntimeFlavor},
+    task::{block_in_place, spawn_blocking},
+    time::sleep,
+};
+use tracing::Instrument;
 
-#[cfg(feature = "tor")]
-use socks::Socks5Stream;
+use bip39::Mnemonic;
+use bitcoind::bitcoincore_rpc::RpcApi;
 
 use bitcoin::{
-    consensus::encode::deserialize,
+    consensus::encode::{deserialize, serialize_hex},
     hashes::{hash160::Hash as Hash160, Hash},
     hex::{Case, DisplayHex},
     secp256k1::{
         rand::{rngs::OsRng, RngCore},
-        SecretKey,
+        Secp256k1, SecretKey,
     },
-    Amount, BlockHash, OutPoint, PublicKey, ScriptBuf, Transaction, Txid,
+    Address, Amount, OutPoint, PublicKey, ScriptBuf, Transaction, Txid,
 };
 
 use super::{
+    blame::{attribute_broadcast, mempool_first_seen, observer_client},
+    connection::MakerConnection,
+    cover::CoverTraffic,
+    directories::{ask_directories, fetch_addresses_from_directories},
+    dispute::{
+        ChainObservation, DisputeBundle, DisputedMaker, EvidenceTx, EvidenceTxKind,
+        ObservationKind, DISPUTE_BUNDLE_VERSION,
+    },
+    dry_run::{DryRun, DryRunTx},
     error::TakerError,
-    offers::{fetch_addresses_from_dns, fetch_offer_from_makers, MakerAddress, OfferAndAddress},
+    offers::{
+        fetch_market_snapshot_from_dns, fetch_offer_from_makers, fetch_uptime_from_dns,
+        reused_bonds, MakerAddress, OfferAndAddress,
+    },
+    quote::SwapQuote,
+    report::{render_report, MakerFeeRecord, ReportFormat, SwapHistory, SwapRecord},
     routines::*,
+    subscription::{offerbook_changes, subscribe_to_directory, OfferBookSubscription},
 };
 use crate::{
+    broadcast::Broadcaster,
+    events::{Event, EventBus, SwapPhase},
+    market::{snapshot::MarketSnapshot, subscription::OfferBookUpdate},
     protocol::{
+        contract::{
+            contract_fee, contract_feerate_range, CONTRACT_FEERATE_CONF_TARGET, CONTRACT_TX_VSIZE,
+            MIN_CONTRACT_FEERATE,
+        },
         error::ProtocolError,
         messages::{
             ContractSigsAsRecvrAndSender, ContractSigsForRecvr, ContractSigsForRecvrAndSender,
-            ContractSigsForSender, FundingTxInfo, MultisigPrivkey, Preimage, PrivKeyHandover,
-            TakerToMakerMessage,
+            ContractSigsForSender, FundingTxInfo, MultisigPrivkey, Offer, Preimage,
+            PrivKeyHandover, SignedReceipt, TakerToMakerMessage,
         },
+        session::{Session, Transcript},
+        ProtocolPhase,
+    },
+    taker::{
+        config::TakerConfig,
+        locktime::{check_timelock_ladder, pick_locktime_step, refund_locktime_ladder},
+        offers::OfferBook,
     },
-    taker::{config::TakerConfig, offers::OfferBook},
     utill::*,
     wallet::{
-        IncomingSwapCoin, OutgoingSwapCoin, RPCConfig, SwapCoin, Wallet, WalletError,
+        broadcasted_txids, verify_identity_binding, verify_receipt, ConfirmationTracker,
+        ConfirmationUpdate, ContractConflict, IncomingSwapCoin, InputConstraints, KeyClass,
+        MemoTarget, OutgoingSwapCoin, RPCConfig, ScriptType, SwapCoin, Wallet, WalletError,
         WalletSwapCoin, WatchOnlySwapCoin,
     },
+    watchtower::{TimelockSweep, Watchtower},
 };
 
 #[cfg(feature = "tor")]
 use crate::tor::kill_tor_handles;
 
+#[cfg(feature = "nostr")]
+use crate::nostr::fetch_offers_from_relays;
+
+pub use crate::wallet::AmountDistribution;
+pub(crate) use crate::wallet::MINER_FEE;
+
 // Default values for Taker configurations
 pub(crate) const REFUND_LOCKTIME: u16 = 20;
 pub(crate) const REFUND_LOCKTIME_STEP: u16 = 20;
-pub(crate) const FIRST_CONNECT_ATTEMPTS: u32 = 5;
-pub(crate) const FIRST_CONNECT_SLEEP_DELAY_SEC: u64 = 1;
-pub(crate) const FIRST_CONNECT_ATTEMPT_TIMEOUT_SEC: u64 = 30;
-
-// Tries reconnection by variable delay.
-// First 10 attempts at 1 sec interval.
-// Next 5 attempts by 30 sec interval.
-// This is useful to cater for random network failure.
-pub(crate) const RECONNECT_ATTEMPTS: u32 = 10;
-pub(crate) const RECONNECT_SHORT_SLEEP_DELAY: u64 = 1;
-pub(crate) const RECONNECT_LONG_SLEEP_DELAY: u64 = 5;
-pub(crate) const SHORT_LONG_SLEEP_DELAY_TRANSITION: u32 = 30;
-pub(crate) const TCP_TIMEOUT_SECONDS: u64 = 300;
-// TODO: Maker should decide this miner fee
-// This fee is used for both funding and contract txs.
-#[cfg(feature = "integration-test")]
-pub(crate) const MINER_FEE: u64 = 1000;
-
-/// This fee is used for both funding and contract txs.
-#[cfg(not(feature = "integration-test"))]
-pub(crate) const MINER_FEE: u64 = 300; // around 2 sats/vb for funding tx
 
 /// Swap specific parameters. These are user's policy and can differ among swaps.
 /// SwapParams govern the criteria to find suitable set of makers from the offerbook.
@@ -96,9 +121,96 @@ pub struct SwapParams {
     pub maker_count: usize,
     /// How many splits
     pub tx_count: u32,
-    // TODO: Following two should be moved to TakerConfig as global configuration.
-    /// Confirmation count required for funding txs.
-    pub required_confirms: u32,
+    /// Distribution of the amount across the funding transactions.
+    pub amount_distribution: AmountDistribution,
+    /// Swap the whole balance the funding txs can spend: the dust and frozen coins, and the ones outside the
+    /// input constraints, stay. `send_amount` is ignored and computed at the start of the swap as the balance of
+    /// these coins minus the funding transaction fees.
+    pub sweep: bool,
+    /// Maximum total fee of the swap in sats, maker fees and miner fees included.
+    pub max_total_fee_sats: Option<u64>,
+    /// Maximum total fee of the swap, in parts per million of the send amount.
+    pub max_fee_ppm: Option<u64>,
+    /// Script type of the wallet outputs the swapped coins are claimed to. The wallet's type if not set.
+    pub script_type: Option<ScriptType>,
+    /// Fewest confirmations of the wallet coins funding the swap.
+    pub min_input_confirmations: u32,
+    /// Fewest seconds since the wallet coins funding the swap confirmed. 0 for no minimum.
+    pub min_input_age_secs: u64,
+    /// Don't fund the swap with the unconfirmed change of our own txs, which is lost if the tx is replaced.
+    pub forbid_unconfirmed_change: bool,
+}
+
+impl SwapParams {
+    /// The fee budget of the swap, if any. The smallest of the absolute and relative limits.
+    pub fn fee_budget(&self) -> Option<Amount> {
+        let relative_budget = self.max_fee_ppm.map(|ppm| {
+            Amount::from_sat((self.send_amount.to_sat() as u128 * ppm as u128 / 1_000_000) as u64)
+        });
+        match (
+            self.max_total_fee_sats.map(Amount::from_sat),
+            relative_budget,
+        ) {
+            (Some(absolute), Some(relative)) => Some(absolute.min(relative)),
+            (absolute, relative) => absolute.or(relative),
+        }
+    }
+
+    /// Errors if a swap expected to cost `expected` exceeds the fee budget.
+    pub(crate) fn check_fee_budget(&self, expected: Amount) -> Result<(), TakerError> {
+        if let Some(budget) = self.fee_budget() {
+            if expected > budget {
+                log::error!(
+                    "Expected swap fee {} exceeds the fee budget {}",
+                    expected,
+                    budget
+                );
+                return Err(TakerError::FeeBudgetExceeded { expected, budget });
+            }
+            log::info!("Expected swap fee {} | Fee budget {}", expected, budget);
+        }
+        Ok(())
+    }
+
+    /// Swap params for sweeping the whole spendable balance of the wallet through the makers.
+    pub fn sweep(maker_count: usize, tx_count: u32) -> Self {
+        Self {
+            send_amount: Amount::ZERO,
+            maker_count,
+            tx_count,
+            amount_distribution: AmountDistribution::default(),
+            sweep: true,
+            max_total_fee_sats: None,
+            max_fee_ppm: None,
+            script_type: None,
+            min_input_confirmations: 0,
+            min_input_age_secs: 0,
+            forbid_unconfirmed_change: false,
+        }
+    }
+
+    /// Constraints on the wallet coins funding the swap.
+    pub(crate) fn input_constraints(&self) -> InputConstraints {
+        InputConstraints {
+            min_confirmations: self.min_input_confirmations,
+            min_age_secs: self.min_input_age_secs,
+            forbid_unconfirmed_change: self.forbid_unconfirmed_change,
+        }
+    }
+}
+
+/// Upper limit of the confirmations required as per the amount at risk.
+const MAX_RISK_CONFIRMS: u32 = 6;
+
+/// Confirmations required for funding txs of `amount_at_risk` from a maker whose fidelity bond locks
+/// `bond_amount`. One confirmation while the amount is covered by the bond, one more for every
+/// additional bond amount at risk, up to [MAX_RISK_CONFIRMS].
+pub(crate) fn risk_required_confirms(amount_at_risk: Amount, bond_amount: Amount) -> u32 {
+    if bond_amount == Amount::ZERO {
+        return MAX_RISK_CONFIRMS;
+    }
+    let ratio = amount_at_risk.to_sat().div_ceil(bond_amount.to_sat());
+    ratio.clamp(1, MAX_RISK_CONFIRMS as u64) as u32
 }
 
 // Defines the Taker's position in the current ongoing swap.
@@ -139,6 +251,21 @@ struct OngoingSwapState {
     pub(crate) taker_position: TakerPosition,
     /// Unique ID for a swap
     pub(crate) id: String,
+    /// Unix timestamp of the start of the swap.
+    pub(crate) started_at: u64,
+    /// Miner fees of the Taker's funding txs.
+    pub(crate) funding_fee: Amount,
+    /// Negotiated feerate of the contract txs, in sats/vB. None if a maker doesn't negotiate it, the contract
+    /// txs then pay `MINER_FEE`.
+    pub(crate) contract_feerate: Option<f64>,
+    /// Refund locktimes of the hops, from the Taker's funding txs to the last hop, in blocks.
+    pub(crate) refund_locktimes: Vec<u16>,
+    /// Receipts of the settled makers, countersigned.
+    pub(crate) receipts: Vec<SignedReceipt>,
+    /// Hashes of the messages exchanged with the makers.
+    pub(crate) transcript: Transcript,
+    /// Events of the swap txs seen on the node.
+    pub(crate) observations: Mutex<Vec<ChainObservation>>,
 }
 
 /// Information for the next maker in the hop.
@@ -160,6 +287,12 @@ pub enum TakerBehavior {
     DropConnectionAfterFullSetup,
     /// Behavior to broadcast the contract after the full coinswap setup.
     BroadcastContractAfterFullSetup,
+    /// Drops the swap after the first maker sent its contract signatures and funded the next hop.
+    DropAfterContractSigs,
+    /// Broadcasts the contracts as soon as the first hop's funding is confirmed.
+    BroadcastContractPrematurely,
+    /// Sends a random preimage instead of the real one at settlement.
+    SendWrongPreimage,
 }
 
 /// The Taker structure that performs bulk of the coinswap protocol. Taker connects
@@ -175,6 +308,12 @@ pub struct Taker {
     behavior: TakerBehavior,
     tor_handle: Option<Child>,
     data_dir: PathBuf,
+    events: EventBus,
+    watchtower: Arc<Watchtower>,
+    watchtower_handle: Option<JoinHandle<()>>,
+    cover_traffic: Arc<CoverTraffic>,
+    cover_traffic_handle: Option<JoinHandle<()>>,
+    offerbook_subscription: Option<OfferBookSubscription>,
 }
 
 impl Drop for Taker {
@@ -187,6 +326,20 @@ impl Drop for Taker {
         self.wallet.save_to_disk().unwrap();
         log::info!("Wallet data saved to disk.");
 
+        self.cover_traffic.stop();
+        if let Some(handle) = self.cover_traffic_handle.take() {
+            if handle.join().is_err() {
+                log::error!("Cover traffic thread panicked.");
+            }
+        }
+
+        self.watchtower.stop();
+        if let Some(handle) = self.watchtower_handle.take() {
+            if handle.join().is_err() {
+                log::error!("Watchtower thread panicked");
+            }
+        }
+
         if !cfg!(feature = "tor") {
             assert!(self.tor_handle.is_none(), "Tor handle should not exist")
         }
@@ -251,6 +404,9 @@ impl Taker {
             config.connection_type = connection_type;
         }
 
+        config.directory_server_address =
+            directory_address_for_network(&config.directory_server_address, wallet.store.network);
+
         config.write_to_file(&data_dir.join("config.toml"))?;
 
         // Load offerbook. If doesn't exists, creates fresh file.
@@ -278,10 +434,21 @@ impl Taker {
             empty_book
         };
 
+        wallet.apply_config(&config.wallet, config.tor_socks_port())?;
         log::info!("Initializing wallet sync");
         wallet.sync()?;
         log::info!("Completed wallet sync");
 
+        let watchtower = Arc::new(Watchtower::new(
+            &rpc_config,
+            &data_dir,
+            wallet.swap_account_key()?,
+        )?);
+        let cover_traffic = Arc::new(CoverTraffic::new(
+            config.clone(),
+            Duration::from_secs(config.cover_traffic_interval_secs),
+        ));
+
         Ok(Self {
             wallet,
             config,
@@ -290,9 +457,58 @@ impl Taker {
             behavior,
             tor_handle: None,
             data_dir,
+            events: EventBus::default(),
+            watchtower,
+            watchtower_handle: None,
+            cover_traffic,
+            cover_traffic_handle: None,
+            offerbook_subscription: None,
         })
     }
 
+    /// Creates a new taker wallet file from a BIP39 seed phrase, and syncs it. Returns the mnemonic, to be
+    /// backed up.
+    ///
+    /// Without a `seed_phrase`, a new 12 words mnemonic is generated, and the wallet starts at the current
+    /// block height. An imported wallet is scanned from the `birthday` height, or from genesis if unknown.
+    /// The data directory and wallet name default as in [Taker::init]. An existing wallet file is only
+    /// overwritten by an imported wallet with `force`.
+    pub fn create_wallet(
+        data_dir: Option<PathBuf>,
+        wallet_file_name: Option<String>,
+        rpc_config: Option<RPCConfig>,
+        seed_phrase: Option<&str>,
+        birthday: Option<u64>,
+        force: bool,
+    ) -> Result<Mnemonic, TakerError> {
+        let data_dir = data_dir.unwrap_or(get_taker_dir());
+        let wallet_file_name = wallet_file_name.unwrap_or_else(|| "taker-wallet".to_string());
+        let wallet_path = data_dir.join("wallets").join(&wallet_file_name);
+        let mut rpc_config = rpc_config.unwrap_or_default();
+        rpc_config.wallet_name = wallet_file_name;
+
+        let (mut wallet, mnemonic) = match seed_phrase {
+            Some(seed_phrase) => {
+                let mnemonic = Mnemonic::parse(seed_phrase.trim()).map_err(WalletError::from)?;
+                let wallet =
+                    Wallet::restore(&wallet_path, &rpc_config, &mnemonic, birthday, force)?;
+                (wallet, mnemonic)
+            }
+            None => Wallet::create(&wallet_path, &rpc_config)?,
+        };
+        log::info!("New Wallet created at : {:?}", wallet_path);
+
+        // Scan with the configured keychains, if any.
+        let config = TakerConfig::new(Some(&data_dir.join("config.toml")))?;
+        wallet.set_gap_limit(config.wallet.gap_limit)?;
+        wallet.set_script_type(config.wallet.script_type)?;
+        log::info!("Initializing wallet sync");
+        wallet.sync()?;
+        log::info!("Completed wallet sync");
+
+        Ok(mnemonic)
+    }
+
     /// Get wallet
     pub fn get_wallet(&self) -> &Wallet {
         &self.wallet
@@ -303,52 +519,123 @@ impl Taker {
         &mut self.wallet
     }
 
-    ///  Does the coinswap process
-    pub fn do_coinswap(&mut self, swap_params: SwapParams) -> Result<(), TakerError> {
-        self.tor_handle = self.setup_tor()?;
-        self.send_coinswap(swap_params)
+    /// Data directory of the Taker.
+    pub(crate) fn data_dir(&self) -> &Path {
+        &self.data_dir
     }
 
-    fn setup_tor(&self) -> Result<Option<Child>, TakerError> {
-        match self.config.connection_type {
-            ConnectionType::CLEARNET => Ok(None),
-            #[cfg(feature = "tor")]
-            ConnectionType::TOR => {
-                let tor_dir = self.data_dir.join("tor");
-                let tor_log_file = tor_dir.join("log");
+    /// Subscribe to the Taker's [Event] stream.
+    pub fn subscribe_events(&self) -> std::sync::mpsc::Receiver<Event> {
+        self.events.subscribe()
+    }
 
-                // Hard error if previous log file can't be removed, as monitor_log_for_completion doesn't work with existing file.
-                // Tell the user to manually delete the file and restart.
-                if tor_log_file.exists() {
-                    if let Err(e) = std::fs::remove_file(&tor_log_file) {
-                        log::error!(
-                        "Error removing previous tor log. Please delete the file and restart. | {:?}",
-                        tor_log_file
-                    );
-                        return Err(e.into());
-                    } else {
-                        log::info!("Previous tor log file deleted succesfully");
-                    }
-                }
+    ///  Does the coinswap process, blocking the thread until it's done.
+    ///
+    /// It drives [Taker::do_coinswap_async] on a runtime of its own, on a thread of its own when called from
+    /// within a tokio runtime. Async applications await [Taker::do_coinswap_async] instead.
+    pub fn do_coinswap(&mut self, swap_params: SwapParams) -> Result<(), TakerError> {
+        block_on(self.do_coinswap_async(swap_params))
+    }
 
-                let handle = Some(crate::tor::spawn_tor(
-                    self.config.socks_port,
-                    self.config.network_port,
-                    tor_dir.to_str().unwrap().to_owned(),
-                )?);
+    /// Does the coinswap process, talking to the makers over tokio sockets.
+    ///
+    /// The swap awaits the makers, and the confirmations of the funding txs. Dropping the future at one of
+    /// these points abandons the swap with its state kept in the Taker, and its swapcoins saved in the wallet
+    /// once funded: call [Taker::recover_from_swap] to get the funds back. Starting Tor runs on the blocking
+    /// threads of the runtime. The offerbook sync, the wallet RPC calls and the recovery on a failed swap borrow
+    /// the Taker, and block in place on a multi-threaded runtime.
+    pub async fn do_coinswap_async(&mut self, swap_params: SwapParams) -> Result<(), TakerError> {
+        let (config, data_dir) = (self.config.clone(), self.data_dir.clone());
+        self.tor_handle = spawn_blocking(move || setup_tor(&config, &data_dir))
+            .await
+            .map_err(io::Error::from)??;
+        if self.watchtower_handle.is_none() {
+            let watchtower = self.watchtower.clone();
+            self.watchtower_handle = Some(
+                thread::Builder::new()
+                    .name("Watchtower Thread".to_string())
+                    .spawn(move || watchtower.run())?,
+            );
+        }
+        if self.config.cover_traffic_interval_secs > 0 {
+            self.start_cover_traffic()?;
+        }
+        self.send_coinswap(swap_params).await
+    }
 
-                if let Err(e) =
-                    monitor_log_for_completion(&tor_log_file, "Bootstrapped 100% (done): Done")
-                {
-                    log::error!("Error monitoring taker log file. Try removing the tor log file {:?} and try again. | {}", tor_log_file, e);
-                    return Err(e.into());
-                }
+    /// Sets up a swap as [Taker::send_coinswap] does, and prepares its first hop without broadcasting it.
+    /// The swap state is cleared after, see [dry_run](super::dry_run). Like [Taker::do_coinswap], it blocks
+    /// the thread on a runtime of its own.
+    pub(crate) fn dry_run_first_hop(
+        &mut self,
+        mut swap_params: SwapParams,
+        quote: SwapQuote,
+    ) -> Result<DryRun, TakerError> {
+        swap_params.send_amount = Amount::from_sat(quote.send_amount_sats);
 
-                log::info!("tor is ready!");
+        let mut preimage = [0u8; 32];
+        OsRng.fill_bytes(&mut preimage);
+        self.ongoing_swap_state.active_preimage = preimage;
+        self.ongoing_swap_state.swap_params = swap_params;
+        self.ongoing_swap_state.id = preimage[0..8].to_hex_string(Case::Lower);
+        self.ongoing_swap_state.contract_feerate = quote.contract_feerate;
+        self.ongoing_swap_state.refund_locktimes = quote.refund_locktimes.clone();
+        log::info!("Dry run of a coinswap, nothing will be broadcast");
 
-                Ok(handle)
-            }
+        let result = block_on(self.prepare_first_hop()).and_then(|(maker, funding_txs)| {
+            let contract_txs = self
+                .ongoing_swap_state
+                .outgoing_swapcoins
+                .iter()
+                .map(|sc| Ok(DryRunTx::from(&sc.get_fully_signed_contract_tx()?)))
+                .collect::<Result<_, TakerError>>()?;
+            Ok(DryRun {
+                quote,
+                first_maker: maker.address.to_string(),
+                funding_txs: funding_txs.iter().map(DryRunTx::from).collect(),
+                funding_fee_sats: self.ongoing_swap_state.funding_fee.to_sat(),
+                contract_txs,
+            })
+        });
+        self.clear_ongoing_swaps();
+        result
+    }
+
+    /// Starts the cover traffic thread, if not already running. It keeps making decoy offer fetches and
+    /// idle connections to random makers of the offerbook, every `cover_traffic_interval_secs` on average,
+    /// until the Taker is dropped.
+    pub fn start_cover_traffic(&mut self) -> Result<(), TakerError> {
+        if self.cover_traffic_handle.is_some() {
+            return Ok(());
+        }
+        if self.config.cover_traffic_interval_secs == 0 {
+            log::warn!("Cover traffic interval is not configured, not starting cover traffic.");
+            return Ok(());
         }
+        self.update_cover_traffic_makers();
+        let cover_traffic = self.cover_traffic.clone();
+        self.cover_traffic_handle = Some(
+            thread::Builder::new()
+                .name("Cover Traffic Thread".to_string())
+                .spawn(move || cover_traffic.run())?,
+        );
+        Ok(())
+    }
+
+    fn update_cover_traffic_makers(&self) {
+        self.cover_traffic.update_makers(
+            self.offerbook
+                .all_good_makers()
+                .iter()
+                .map(|oa| oa.address.clone())
+                .collect(),
+        );
+    }
+
+    /// Starts Tor, when connecting over Tor.
+    pub(crate) fn start_tor(&mut self) -> Result<(), TakerError> {
+        self.tor_handle = setup_tor(&self.config, &self.data_dir)?;
+        Ok(())
     }
 
     /// Perform a coinswap round with given [SwapParams]. The Taker will try to perform swap with makers
@@ -360,40 +647,24 @@ impl Taker {
     /// by executing the contract txs. If that fails too for any reason, user should manually call the [Taker::recover_from_swap].
     ///
     /// If that fails too. Open an issue at [our github](https://github.com/citadel-tech/coinswap/issues)
-    pub(crate) fn send_coinswap(&mut self, swap_params: SwapParams) -> Result<(), TakerError> {
-        // Check if we have enough balance.
-        let available = self.wallet.get_balances(None)?.spendable;
-
-        // TODO: Make more exact estimate of swap cost and ensure balance.
-        // For now ensure at least swap_amount + 1000 sats is available.
-        let required = swap_params.send_amount + Amount::from_sat(1000);
-        if available < required {
-            let err = WalletError::InsufficientFund {
-                available: available.to_sat(),
-                required: required.to_sat(),
-            };
-            log::error!("Not enough balance to do swap : {:?}", err);
-            return Err(err.into());
-        }
+    pub(crate) async fn send_coinswap(
+        &mut self,
+        mut swap_params: SwapParams,
+    ) -> Result<(), TakerError> {
+        blocking(|| self.check_swap_funds(&mut swap_params))?;
 
         log::info!("Syncing Offerbook");
-        self.sync_offerbook()?;
+        blocking(|| self.sync_offerbook())?;
+        self.check_maker_count(&swap_params)?;
 
-        // Error early if hop_count > available good makers.
-        if swap_params.maker_count > self.offerbook.all_good_makers().len() {
-            log::error!(
-                "Not enough makers in the offerbook. Required {}, avaialable {}",
-                swap_params.maker_count,
-                self.offerbook.all_good_makers().len()
-            );
-            return Err(TakerError::NotEnoughMakersInOfferBook);
-        }
+        self.ongoing_swap_state.contract_feerate = blocking(|| self.negotiate_contract_feerate())?;
+        self.ongoing_swap_state.refund_locktimes =
+            self.plan_refund_locktimes(&swap_params, self.ongoing_swap_state.contract_feerate)?;
 
-        // Error early if less than 2 makers.
-        if swap_params.maker_count < 2 {
-            log::error!("Cannot swap with less than 2 makers");
-            return Err(ProtocolError::General("Swap maker count < 2").into());
-        }
+        // Abort before any funding is broadcasted if the swap can exceed the fee budget.
+        let expected =
+            self.estimate_max_swap_fee(&swap_params, &self.ongoing_swap_state.refund_locktimes);
+        swap_params.check_fee_budget(expected)?;
 
         // Generate new random preimage and initiate the first hop.
         let mut preimage = [0u8; 32];
@@ -401,21 +672,56 @@ impl Taker {
 
         let unique_id = preimage[0..8].to_hex_string(Case::Lower);
 
+        // Everything logged until the swap ends, its recovery included, shows the swap id.
+        let swap_span = tracing::info_span!("swap", id = %unique_id);
+        self.run_coinswap(swap_params, preimage, unique_id)
+            .instrument(swap_span)
+            .await
+    }
+
+    /// [Internal] Runs the swap round of [Taker::send_coinswap], with the `preimage` and the id drawn for it.
+    async fn run_coinswap(
+        &mut self,
+        swap_params: SwapParams,
+        preimage: Preimage,
+        unique_id: String,
+    ) -> Result<(), TakerError> {
         log::info!("Initiating coinswap with id : {}", unique_id);
 
         self.ongoing_swap_state.active_preimage = preimage;
         self.ongoing_swap_state.swap_params = swap_params;
-        self.ongoing_swap_state.id = unique_id;
+        self.ongoing_swap_state.id = unique_id.clone();
+        let preimage_hash = self.get_preimage_hash();
+        blocking(|| self.wallet.record_swap_id(&unique_id, preimage_hash))?;
+        self.ongoing_swap_state.started_at = unix_time();
+
+        self.events.publish(Event::SwapStarted {
+            swap_id: unique_id,
+            amount: swap_params.send_amount,
+        });
 
         // Try first hop. Abort if error happens.
-        if let Err(e) = self.init_first_hop() {
+        self.publish_phase(SwapPhase::FirstHopFunding);
+        if let Err(e) = self.init_first_hop().await {
             log::error!("Could not initiate first hop: {:?}", e);
-            self.recover_from_swap()?;
+            blocking(|| self.recover_from_swap())?;
             return Err(e);
         }
+        self.publish_fees_accrued();
+        blocking(|| self.watch_outgoing_contracts())?;
+
+        if self.behavior == TakerBehavior::BroadcastContractPrematurely {
+            log::error!("Special Behavior BroadcastContractPrematurely");
+            blocking(|| self.recover_from_swap())?;
+            return Ok(());
+        }
 
         // Iterate until `maker_count` numbers of Makers are found and initiate swap between them sequentially.
         for maker_index in 0..self.ongoing_swap_state.swap_params.maker_count {
+            self.publish_phase(SwapPhase::HopSetup {
+                hop: maker_index,
+                maker_count: self.ongoing_swap_state.swap_params.maker_count,
+            });
             if maker_index == 0 {
                 self.ongoing_swap_state.taker_position = TakerPosition::FirstPeer;
             } else if maker_index == self.ongoing_swap_state.swap_params.maker_count - 1 {
@@ -424,81 +730,103 @@ impl Taker {
                 self.ongoing_swap_state.taker_position = TakerPosition::WatchOnly;
             }
 
-            // Refund lock time decreases by `refund_locktime_step` for each hop.
-            let maker_refund_locktime = REFUND_LOCKTIME
-                + REFUND_LOCKTIME_STEP
-                    * (self.ongoing_swap_state.swap_params.maker_count - maker_index - 1) as u16;
+            // Refund lock time decreases with each hop.
+            let maker_refund_locktime = self.ongoing_swap_state.refund_locktimes[maker_index + 1];
 
             let funding_tx_infos = self.funding_info_for_next_maker();
 
             // Attempt to initiate the next hop of the swap. If anything goes wrong, abort immediately.
             // If succeeded, collect the funding_outpoints and multisig_reedemscripts of the next hop.
             // If error then aborts from current swap. Ban the Peer.
-            let (funding_outpoints, multisig_reedemscripts) =
-                match self.send_sigs_init_next_hop(maker_refund_locktime, &funding_tx_infos) {
-                    Ok((next_peer_info, contract_sigs)) => {
-                        self.ongoing_swap_state.peer_infos.push(next_peer_info);
-                        let multisig_reedemscripts = contract_sigs
-                            .senders_contract_txs_info
-                            .iter()
-                            .map(|senders_contract_tx_info| {
-                                senders_contract_tx_info.multisig_redeemscript.clone()
-                            })
-                            .collect::<Vec<_>>();
-                        let funding_outpoints = contract_sigs
-                            .senders_contract_txs_info
-                            .iter()
-                            .map(|senders_contract_tx_info| {
-                                senders_contract_tx_info.contract_tx.input[0].previous_output
-                            })
-                            .collect::<Vec<OutPoint>>();
-
-                        (funding_outpoints, multisig_reedemscripts)
-                    }
-                    Err(e) => {
-                        log::error!("Could not initiate next hop. Error : {:?}", e);
-                        log::warn!("Starting recovery from existing swap");
-                        self.recover_from_swap()?;
-                        return Ok(());
+            let (funding_outpoints, multisig_reedemscripts) = match self
+                .send_sigs_init_next_hop(maker_refund_locktime, &funding_tx_infos)
+                .await
+            {
+                Ok((next_peer_info, contract_sigs)) => {
+                    // The last next peer is the Taker itself.
+                    if self.ongoing_swap_state.taker_position != TakerPosition::LastPeer {
+                        self.events.publish(Event::HopEstablished {
+                            swap_id: self.ongoing_swap_state.id.clone(),
+                            hop: maker_index + 1,
+                            maker: next_peer_info.peer.address.to_string(),
+                        });
                     }
-                };
+                    self.ongoing_swap_state.peer_infos.push(next_peer_info);
+                    let multisig_reedemscripts = contract_sigs
+                        .senders_contract_txs_info
+                        .iter()
+                        .map(|senders_contract_tx_info| {
+                            senders_contract_tx_info.multisig_redeemscript.clone()
+                        })
+                        .collect::<Vec<_>>();
+                    let funding_outpoints = contract_sigs
+                        .senders_contract_txs_info
+                        .iter()
+                        .map(|senders_contract_tx_info| {
+                            senders_contract_tx_info.contract_tx.input[0].previous_output
+                        })
+                        .collect::<Vec<OutPoint>>();
+
+                    (funding_outpoints, multisig_reedemscripts)
+                }
+                Err(e) => {
+                    log::error!("Could not initiate next hop. Error : {:?}", e);
+                    log::warn!("Starting recovery from existing swap");
+                    blocking(|| self.recover_from_swap())?;
+                    return Ok(());
+                }
+            };
 
             // Watch for both expected and unexpected transactions.
-            // This errors in two cases.
-            // TakerError::ContractsBroadcasted and TakerError::FundingTxWaitTimeOut.
+            // This errors in three cases.
+            // TakerError::ContractsBroadcasted, TakerError::FundingTxWaitTimeOut and TakerError::FundingTxDoubleSpent.
             // For all cases, abort from swap immediately.
-            // For the timeout case also ban the Peer.
+            // For the timeout and double spend cases also ban the Peer.
+            // For the contract-broadcasted case, the likely culprit was blamed already.
             let txids_to_watch = funding_outpoints.iter().map(|op| op.txid).collect();
-            match self.watch_for_txs(&txids_to_watch) {
+            match self.watch_for_txs(&txids_to_watch).await {
                 Ok(r) => self.ongoing_swap_state.funding_txs.push(r),
                 Err(e) => {
                     log::error!("Error: {:?}", e);
                     log::warn!("Starting recovery from existing swap");
-                    if let TakerError::FundingTxWaitTimeOut = e {
+                    if matches!(
+                        e,
+                        TakerError::FundingTxWaitTimeOut | TakerError::FundingTxDoubleSpent(_)
+                    ) {
                         let bad_maker = &self.ongoing_swap_state.peer_infos[maker_index].peer;
                         self.offerbook.add_bad_maker(bad_maker);
                     }
-                    self.recover_from_swap()?;
+                    blocking(|| self.recover_from_swap())?;
                     return Ok(());
                 }
             }
 
+            if self.behavior == TakerBehavior::DropAfterContractSigs {
+                log::error!("Dropping Swap Process after contract signatures exchange");
+                return Ok(());
+            }
+
             // For the last hop, initiate the incoming swapcoins, and request the sigs for it.
             if self.ongoing_swap_state.taker_position == TakerPosition::LastPeer {
                 let incoming_swapcoins =
                     self.create_incoming_swapcoins(multisig_reedemscripts, funding_outpoints)?;
                 log::debug!("Incoming Swapcoins: {:?}", incoming_swapcoins);
                 self.ongoing_swap_state.incoming_swapcoins = incoming_swapcoins;
-                match self.request_sigs_for_incoming_swap() {
+                match self
+                    .request_sigs_for_incoming_swap()
+                    .await
+                    .and_then(|_| self.watch_incoming_contracts())
+                {
                     Ok(_) => (),
                     Err(e) => {
                         log::error!("Incoming SwapCoin Generation failed : {:?}", e);
                         log::warn!("Starting recovery from existing swap");
-                        self.recover_from_swap()?;
+                        blocking(|| self.recover_from_swap())?;
                         return Ok(());
                     }
                 }
             }
+            self.publish_fees_accrued();
         } // Contract establishment completed.
 
         if self.behavior == TakerBehavior::DropConnectionAfterFullSetup {
@@ -508,24 +836,53 @@ impl Taker {
 
         if self.behavior == TakerBehavior::BroadcastContractAfterFullSetup {
             log::error!("Special Behavior BroadcastContractAfterFullSetup");
-            self.recover_from_swap()?;
+            blocking(|| self.recover_from_swap())?;
             return Ok(());
         }
 
-        match self.settle_all_swaps() {
+        self.publish_phase(SwapPhase::Settlement);
+        match self.settle_all_swaps().await {
             Ok(_) => (),
             Err(e) => {
                 log::error!("Swap Settlement Failed : {:?}", e);
                 log::warn!("Starting recovery from existing swap");
-                self.recover_from_swap()?;
+                blocking(|| self.recover_from_swap())?;
                 return Ok(());
             }
         }
 
+        self.unwatch_swap_contracts()?;
+
+        let swap_makers = self
+            .ongoing_swap_state
+            .peer_infos
+            .iter()
+            .map(|pi| pi.peer.address.clone())
+            .collect();
+        self.offerbook.record_swap_makers(swap_makers);
+        self.offerbook
+            .write_to_disk(&self.data_dir.join("offerbook.dat"))?;
+
+        let history_path = self.data_dir.join("swap_history.dat");
+        if let Err(e) = SwapHistory::append(&history_path, self.swap_record()) {
+            log::error!("Failed recording the swap in the history: {:?}", e);
+        }
+
+        let swap_id = self.ongoing_swap_state.id.clone();
+        match self.privacy_report(&swap_id) {
+            Ok(report) => log::info!(
+                "Privacy score of the swap: {}/100, with {} linkability risks. See `taker privacy-report {}`.",
+                report.score,
+                report.risks.len(),
+                swap_id
+            ),
+            Err(e) => log::warn!("Failed analyzing the privacy of the swap: {:?}", e),
+        }
         log::info!("Initializing Sync and Save.");
         self.save_and_reset_swap_round()?;
         log::info!("Completed Sync and Save.");
         log::info!("Successfully Completed Coinswap.");
+        self.events.publish(Event::SwapCompleted { swap_id });
         Ok(())
     }
 
@@ -534,17 +891,77 @@ impl Taker {
     /// Initiate the first coinswap hop. Makers are selected from the [OfferBook], and round will
     /// fail if no suitable makers are found.
     /// Creates and stores the [OutgoingSwapCoin] into [OngoingSwapState], and also saves it into the [Wallet] file.
-    fn init_first_hop(&mut self) -> Result<(), TakerError> {
+    async fn init_first_hop(&mut self) -> Result<(), TakerError> {
+        let (maker, funding_txs) = self.prepare_first_hop().await?;
+
+        for outgoing_swapcoin in &self.ongoing_swap_state.outgoing_swapcoins {
+            self.wallet.add_outgoing_swapcoin(outgoing_swapcoin);
+        }
+        self.wallet.save_to_disk()?;
+
+        log::debug!(
+            "Outgoing SwapCoins: {:?}",
+            self.ongoing_swap_state.outgoing_swapcoins
+        );
+
+        // The funding txs carry the whole swap amount, flag broken fee estimates before they go out.
+        if let Err(e) = self
+            .wallet
+            .cross_check_feerate(CONTRACT_FEERATE_CONF_TARGET)
+        {
+            log::warn!("Failed cross-checking fee estimates | {:?}", e);
+        }
+
+        // Broadcast and wait for funding txs to confirm
+        let funding_txids = blocking(|| {
+            funding_txs
+                .iter()
+                .map(|tx| {
+                    let txid = self.wallet.send_tx(tx)?;
+                    log::info!("Broadcasted Funding tx. txid: {}", txid);
+                    assert_eq!(txid, tx.compute_txid());
+                    Ok(txid)
+                })
+                .collect::<Result<_, TakerError>>()
+        })?;
+
+        // Watch for the funding transactions to be confirmed.
+        // This errors in three cases.
+        // TakerError::ContractsBroadcasted, TakerError::FundingTxWaitTimeOut and TakerError::FundingTxDoubleSpent.
+        // For all cases, abort from swap immediately.
+        // For the contract-broadcasted case also ban the Peer.
+        match self.watch_for_txs(&funding_txids).await {
+            Ok(stuffs) => {
+                self.ongoing_swap_state.funding_txs.push(stuffs);
+            }
+            Err(e) => {
+                log::error!("Error: {:?}", e);
+                if let TakerError::ContractsBroadcasted(_) = e {
+                    self.offerbook.add_bad_maker(&maker);
+                }
+                return Err(e);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Builds and signs the funding txs of the first hop, and gets the contract signatures of the first maker.
+    /// Nothing is broadcast nor saved in the [Wallet].
+    ///
+    /// Returns the first maker, and the funding txs.
+    pub(crate) async fn prepare_first_hop(
+        &mut self,
+    ) -> Result<(OfferAndAddress, Vec<Transaction>), TakerError> {
         log::info!("Initializing First Hop.");
         // Set the Taker Position state
         self.ongoing_swap_state.taker_position = TakerPosition::FirstPeer;
 
         // Locktime to be used for this swap.
-        let swap_locktime = REFUND_LOCKTIME
-            + REFUND_LOCKTIME_STEP * self.ongoing_swap_state.swap_params.maker_count as u16;
+        let swap_locktime = self.ongoing_swap_state.refund_locktimes[0];
 
         // Loop until we find a live maker who responded to our signature request.
-        let (maker, funding_txs) = loop {
+        loop {
             let maker = self.choose_next_maker()?.clone();
             log::info!("Choosing next maker: {}", maker.address);
             let (multisig_pubkeys, multisig_nonces, hashlock_pubkeys, hashlock_nonces) =
@@ -552,15 +969,21 @@ impl Taker {
                     &maker.offer.tweakable_point,
                     self.ongoing_swap_state.swap_params.tx_count,
                 )?;
-            let (funding_txs, mut outgoing_swapcoins, funding_fee) =
+            let (preimage_hash, contract_fee) = (self.get_preimage_hash(), self.contract_fee());
+            let swap_params = self.ongoing_swap_state.swap_params;
+            let (funding_txs, mut outgoing_swapcoins, funding_fee) = blocking(|| {
                 self.wallet.initalize_coinswap(
-                    self.ongoing_swap_state.swap_params.send_amount,
+                    swap_params.send_amount,
                     &multisig_pubkeys,
                     &hashlock_pubkeys,
-                    self.get_preimage_hash(),
+                    preimage_hash,
                     swap_locktime,
                     Amount::from_sat(MINER_FEE),
-                )?;
+                    contract_fee,
+                    swap_params.amount_distribution,
+                    swap_params.input_constraints(),
+                )
+            })?;
 
             let contract_reedemscripts = outgoing_swapcoins
                 .iter()
@@ -568,13 +991,16 @@ impl Taker {
                 .collect();
 
             // Request for Sender's Signatures
-            let contract_sigs = match self.req_sigs_for_sender(
-                &maker.address,
-                &outgoing_swapcoins,
-                &multisig_nonces,
-                &hashlock_nonces,
-                swap_locktime,
-            ) {
+            let contract_sigs = match self
+                .req_sigs_for_sender(
+                    &maker.address,
+                    &outgoing_swapcoins,
+                    &multisig_nonces,
+                    &hashlock_nonces,
+                    swap_locktime,
+                )
+                .await
+            {
                 Ok(contract_sigs) => contract_sigs,
                 Err(e) => {
                     // Bad maker, mark it, and try next one.
@@ -588,8 +1014,7 @@ impl Taker {
                 }
             };
 
-            // // Maker has returned a valid signature, save all the data in memory,
-            // // and persist in disk.
+            // // Maker has returned a valid signature, save all the data in memory.
             self.ongoing_swap_state.peer_infos.push(NextPeerInfo {
                 peer: maker.clone(),
                 multisig_pubkeys,
@@ -597,6 +1022,11 @@ impl Taker {
                 hashlock_nonces,
                 contract_reedemscripts,
             });
+            self.events.publish(Event::HopEstablished {
+                swap_id: self.ongoing_swap_state.id.clone(),
+                hop: 0,
+                maker: maker.address.to_string(),
+            });
 
             contract_sigs
                 .sigs
@@ -606,76 +1036,65 @@ impl Taker {
                     outgoing_swapcoin.others_contract_sig = Some(*sig);
                 });
 
-            for outgoing_swapcoin in &outgoing_swapcoins {
-                self.wallet.add_outgoing_swapcoin(outgoing_swapcoin);
-            }
-            self.wallet.save_to_disk()?;
-
             self.ongoing_swap_state.outgoing_swapcoins = outgoing_swapcoins;
 
             log::info!("Total Funding Txs Fees: {}", funding_fee);
+            self.ongoing_swap_state.funding_fee = funding_fee;
 
-            break (maker, funding_txs);
-        };
-
-        log::debug!(
-            "Outgoing SwapCoins: {:?}",
-            self.ongoing_swap_state.outgoing_swapcoins
-        );
+            return Ok((maker, funding_txs));
+        }
+    }
 
-        // Broadcast and wait for funding txs to confirm
-        let funding_txids = funding_txs
-            .iter()
-            .map(|tx| {
-                let txid = self.wallet.send_tx(tx)?;
-                log::info!("Broadcasted Funding tx. txid: {}", txid);
-                assert_eq!(txid, tx.compute_txid());
-                Ok(txid)
-            })
-            .collect::<Result<_, TakerError>>()?;
+    /// Confirmations to wait for on the funding txs of the current hop.
+    ///
+    /// It's the receiving maker's advertised minimum (ours, if we are the receiver), raised as per the
+    /// amount at risk against the funding maker's bond. Funding txs from the Taker itself carry no risk for us.
+    fn hop_required_confirms(&self) -> u32 {
+        let peer_infos = &self.ongoing_swap_state.peer_infos;
+        let send_amount = self.ongoing_swap_state.swap_params.send_amount;
+        let last_peer = peer_infos
+            .last()
+            .map(|npi| &npi.peer)
+            .expect("Maker information expected in swap state");
 
-        // Watch for the funding transactions to be confirmed.
-        // This errors in two cases.
-        // TakerError::ContractsBroadcasted and TakerError::FundingTxWaitTimeOut.
-        // For all cases, abort from swap immediately.
-        // For the contract-broadcasted case also ban the Peer.
-        match self.watch_for_txs(&funding_txids) {
-            Ok(stuffs) => {
-                self.ongoing_swap_state.funding_txs.push(stuffs);
-            }
-            Err(e) => {
-                log::error!("Error: {:?}", e);
-                if let TakerError::ContractsBroadcasted(_) = e {
-                    self.offerbook.add_bad_maker(&maker);
-                }
-                return Err(e);
-            }
+        // The first hop is funded by us.
+        if self.ongoing_swap_state.funding_txs.is_empty() {
+            return last_peer.offer.required_confirms;
         }
 
-        Ok(())
+        // In the last hop, the last peer entry is the funding maker and we are receiving.
+        let (receiver_minimum, funding_maker) =
+            if self.ongoing_swap_state.taker_position == TakerPosition::LastPeer {
+                (REQUIRED_CONFIRMS, last_peer)
+            } else {
+                let funding_maker = peer_infos
+                    .iter()
+                    .rev()
+                    .nth(1)
+                    .map(|npi| &npi.peer)
+                    .expect("Funding maker expected in swap state");
+                (last_peer.offer.required_confirms, funding_maker)
+            };
+
+        receiver_minimum.max(risk_required_confirms(
+            send_amount,
+            funding_maker.offer.fidelity.bond.amount,
+        ))
     }
 
     /// Return a list of confirmed funding txs with their corresponding merkle proofs.
     /// Errors if any watching contract txs have been broadcasted during the time too.
     /// The error contanis the list of broadcasted contract [Txid]s.
-    fn watch_for_txs(
-        &self,
+    async fn watch_for_txs(
+        &mut self,
         funding_txids: &Vec<Txid>,
     ) -> Result<(Vec<Transaction>, Vec<String>), TakerError> {
         let mut txid_tx_map = HashMap::<Txid, Transaction>::new();
-        let mut txid_blockhash_map = HashMap::<Txid, BlockHash>::new();
+        let mut tracker = ConfirmationTracker::default();
+        let mut seen_txids = HashSet::<Txid>::new();
+        let mut txid_confirmations_map = HashMap::<Txid, u32>::new();
 
-        // Find next maker's details
-        let required_confirmations =
-            if self.ongoing_swap_state.taker_position == TakerPosition::LastPeer {
-                self.ongoing_swap_state.swap_params.required_confirms
-            } else {
-                self.ongoing_swap_state
-                    .peer_infos
-                    .last()
-                    .map(|npi| npi.peer.offer.required_confirms)
-                    .expect("Maker information expected in swap state")
-            };
+        let required_confirmations = self.hop_required_confirms();
 
         let maker_addrs = self
             .ongoing_swap_state
@@ -703,29 +1122,59 @@ impl Taker {
             30 // 30 secs for production
         };
 
+        // Give up on the hop, and recover, if its funding doesn't confirm in time.
+        let confirmation_timeout = self
+            .config
+            .phase_timeout(ProtocolPhase::FundingConfirmation);
+
         let start_time = Instant::now();
 
         loop {
+            if start_time.elapsed() > confirmation_timeout {
+                log::error!(
+                    "Timed out waiting for funding tx confirmation. | Not confirmed in {} secs",
+                    confirmation_timeout.as_secs()
+                );
+                return Err(TakerError::FundingTxWaitTimeOut);
+            }
+
             // Abort if any of the contract transaction is broadcasted
-            // TODO: Find the culprit Maker, and ban it's fidelity bond.
-            let contracts_broadcasted = self.check_for_broadcasted_contract_txes();
+            let contracts_broadcasted = blocking(|| self.check_for_broadcasted_contract_txes());
             if !contracts_broadcasted.is_empty() {
                 log::error!(
                     "Fatal! Contract txs broadcasted by makers. Txids : {:?}",
                     contracts_broadcasted
                 );
+                for txid in &contracts_broadcasted {
+                    self.observe(*txid, ObservationKind::ContractBroadcast);
+                }
+                self.events.publish(Event::ContractBroadcastDetected {
+                    txids: contracts_broadcasted.clone(),
+                });
                 return Err(TakerError::ContractsBroadcasted(contracts_broadcasted));
             }
 
-            // Check for each funding transactions if they are confirmed
+            // Check for each funding transactions if they are confirmed, or reorged since.
             for txid in funding_txids {
-                if txid_tx_map.contains_key(txid) {
-                    continue;
-                }
-                let gettx = match self.wallet.rpc.get_raw_transaction_info(txid, None) {
+                let gettx = match blocking(|| self.wallet.rpc.get_raw_transaction_info(txid, None))
+                {
                     Ok(r) => r,
                     // Transaction haven't arrived in our mempool, keep looping.
                     Err(_e) => {
+                        // A tx seen before, and now unknown to the node, got replaced or double spent.
+                        // Evicted from the mempool at worst, it won't confirm either.
+                        let reorged = matches!(
+                            tracker.update(*txid, None),
+                            ConfirmationUpdate::Reorged { .. }
+                        );
+                        if reorged || seen_txids.contains(txid) {
+                            log::error!(
+                                "Funding tx {} dropped by the node. It was replaced or double spent.",
+                                txid
+                            );
+                            self.observe(*txid, ObservationKind::FundingDropped);
+                            return Err(TakerError::FundingTxDoubleSpent(*txid));
+                        }
                         let elapsed = start_time.elapsed().as_secs();
                         log::info!(
                             "Waiting for funding tx to appear in mempool | {} secs",
@@ -739,8 +1188,42 @@ impl Taker {
                     }
                 };
 
+                // Revert to unconfirmed, and wait for the confirmations again.
+                if let ConfirmationUpdate::Reorged { blockhash } =
+                    tracker.update(*txid, gettx.blockhash)
+                {
+                    log::warn!(
+                        "Funding tx {} reorged out of block {}. Waiting for its confirmation again.",
+                        txid,
+                        blockhash
+                    );
+                    txid_tx_map.remove(txid);
+                }
+
+                let confirmations = gettx.confirmations.unwrap_or(0);
+                if txid_confirmations_map.insert(*txid, confirmations) != Some(confirmations) {
+                    self.observe(*txid, ObservationKind::Funding { confirmations });
+                    self.events.publish(Event::FundingConfirmations {
+                        swap_id: self.ongoing_swap_state.id.clone(),
+                        txid: *txid,
+                        confirmations,
+                        required: required_confirmations,
+                    });
+                }
+
                 // log that its waiting for confirmation.
                 if gettx.confirmations.is_none() {
+                    seen_txids.insert(*txid);
+                    let tx = deserialize::<Transaction>(&gettx.hex).map_err(WalletError::from)?;
+                    if self.is_double_spent(&tx)? {
+                        log::error!(
+                            "Funding tx {} double spent. An input was spent in a block by another tx.",
+                            txid
+                        );
+                        self.observe(*txid, ObservationKind::FundingDropped);
+                        return Err(TakerError::FundingTxDoubleSpent(*txid));
+                    }
+
                     let elapsed = start_time.elapsed().as_secs();
                     log::info!(
                         "Funding tx Seen in Mempool. Waiting for confirmation for {} secs",
@@ -751,25 +1234,28 @@ impl Taker {
                     for addr in &maker_addrs {
                         // Ignore transient network error and retry in next loop.
                         // It's safe to ignore the error here, because if the maker is actually offline, the swap will fail in the later stages.
-                        if let Err(e) = self.send_to_maker(
-                            addr,
-                            TakerToMakerMessage::WaitingFundingConfirmation(
-                                self.ongoing_swap_state.id.clone(),
-                            ),
-                        ) {
+                        if let Err(e) = self
+                            .send_to_maker(
+                                addr,
+                                TakerToMakerMessage::WaitingFundingConfirmation(
+                                    self.ongoing_swap_state.id.clone(),
+                                ),
+                            )
+                            .await
+                        {
                             log::error!("error sending wait-notif to maker {} | {:?}", addr, e);
                         }
                     }
                 }
 
                 // handle confirmations
-                //TODO handle confirm<0
-                if gettx.confirmations >= Some(required_confirmations) {
+                if gettx.confirmations >= Some(required_confirmations)
+                    && !txid_tx_map.contains_key(txid)
+                {
                     txid_tx_map.insert(
                         *txid,
                         deserialize::<Transaction>(&gettx.hex).map_err(WalletError::from)?,
                     );
-                    txid_blockhash_map.insert(*txid, gettx.blockhash.expect("Blockhash expected"));
                     log::info!("Tx {} | Confirmed at {}", txid, required_confirmations);
                 }
             }
@@ -783,27 +1269,45 @@ impl Taker {
                             .clone()
                     })
                     .collect::<Vec<Transaction>>();
-                let merkleproofs = funding_txids
-                    .iter()
-                    .map(|&txid| {
-                        self.wallet
-                            .rpc
-                            .get_tx_out_proof(
-                                &[txid],
-                                Some(
-                                    txid_blockhash_map
-                                        .get(&txid)
-                                        .expect("txid expected in the map"),
-                                ),
-                            )
-                            .map(|gettxoutproof_result| gettxoutproof_result.to_lower_hex_string())
-                    })
-                    .collect::<Result<Vec<String>, _>>()
-                    .map_err(WalletError::from)?;
+                let merkleproofs = blocking(|| {
+                    funding_txids
+                        .iter()
+                        .map(|&txid| {
+                            self.wallet
+                                .rpc
+                                .get_tx_out_proof(
+                                    &[txid],
+                                    Some(
+                                        &tracker
+                                            .blockhash(&txid)
+                                            .expect("blockhash expected for confirmed tx"),
+                                    ),
+                                )
+                                .map(|gettxoutproof_result| {
+                                    gettxoutproof_result.to_lower_hex_string()
+                                })
+                        })
+                        .collect::<Result<Vec<String>, _>>()
+                })
+                .map_err(WalletError::from)?;
                 return Ok((txes, merkleproofs));
             }
-            sleep(Duration::from_secs(sleep_interval));
+            sleep(Duration::from_secs(sleep_interval)).await;
+        }
+    }
+
+    /// Returns true if an input of the unconfirmed tx is already spent in a block, by a conflicting tx.
+    fn is_double_spent(&self, tx: &Transaction) -> Result<bool, TakerError> {
+        for input in &tx.input {
+            if !self
+                .wallet
+                .chain
+                .is_unspent(&input.previous_output, false)?
+            {
+                return Ok(true);
+            }
         }
+        Ok(false)
     }
 
     /// Create [FundingTxInfo] for the "next_maker". Next maker is the last stored [NextPeerInfo] in the swp state.
@@ -904,24 +1408,12 @@ impl Taker {
 
     /// Send signatures to a maker, and initiate the next hop of the swap by finding a new maker.
     /// If no suitable makers are found in [OfferBook], next swap will not initiate and the swap round will fail.
-    fn send_sigs_init_next_hop(
+    async fn send_sigs_init_next_hop(
         &mut self,
         maker_refund_locktime: u16,
         funding_tx_infos: &[FundingTxInfo],
     ) -> Result<(NextPeerInfo, ContractSigsAsRecvrAndSender), TakerError> {
-        // Configurable reconnection attempts for testing
-        let reconnect_attempts = if cfg!(feature = "integration-test") {
-            10
-        } else {
-            RECONNECT_ATTEMPTS
-        };
-
-        // Custom sleep delay for testing.
-        let sleep_delay = if cfg!(feature = "integration-test") {
-            1
-        } else {
-            RECONNECT_SHORT_SLEEP_DELAY
-        };
+        let reconnect_attempts = self.config.reconnect_attempts;
 
         let mut ii = 0;
 
@@ -932,40 +1424,42 @@ impl Taker {
             .expect("at least one active maker expected")
             .peer
             .clone();
+        let peer = tracing::info_span!("peer", address = %maker_oa.address);
 
-        loop {
-            ii += 1;
-            match self.send_sigs_init_next_hop_once(maker_refund_locktime, funding_tx_infos) {
-                Ok(ret) => return Ok(ret),
-                Err(e) => {
-                    log::warn!(
-                        "Failed to connect to maker {} to send signatures and init next hop, \
-                            reattempting {} of {} | error={:?}",
-                        &maker_oa.address,
-                        ii,
-                        reconnect_attempts,
-                        e
-                    );
-                    if ii <= reconnect_attempts {
-                        sleep(Duration::from_secs(
-                            if ii <= SHORT_LONG_SLEEP_DELAY_TRANSITION {
-                                sleep_delay
-                            } else {
-                                RECONNECT_LONG_SLEEP_DELAY
-                            },
-                        ));
-                        continue;
-                    } else {
-                        self.offerbook.add_bad_maker(&maker_oa);
-                        return Err(e);
+        async {
+            loop {
+                ii += 1;
+                match self
+                    .send_sigs_init_next_hop_once(maker_refund_locktime, funding_tx_infos)
+                    .await
+                {
+                    Ok(ret) => return Ok(ret),
+                    Err(e) => {
+                        log::warn!(
+                            "Failed to connect to maker {} to send signatures and init next hop, \
+                                reattempting {} of {} | error={:?}",
+                            &maker_oa.address,
+                            ii,
+                            reconnect_attempts,
+                            e
+                        );
+                        if ii <= reconnect_attempts {
+                            sleep(self.config.retry_delay(ii)).await;
+                            continue;
+                        } else {
+                            self.offerbook.add_bad_maker(&maker_oa);
+                            return Err(e);
+                        }
                     }
                 }
             }
         }
+        .instrument(peer)
+        .await
     }
 
     /// [Internal] Single attempt to send signatures and initiate next hop.
-    fn send_sigs_init_next_hop_once(
+    async fn send_sigs_init_next_hop_once(
         &mut self,
         maker_refund_locktime: u16,
         funding_tx_infos: &[FundingTxInfo],
@@ -983,23 +1477,13 @@ impl Taker {
             "Connecting to {} | Send Sigs Init Next Hop",
             this_maker.address
         );
-        let address = this_maker.address.to_string();
-        let mut socket = match self.config.connection_type {
-            ConnectionType::CLEARNET => TcpStream::connect(address)?,
-            #[cfg(feature = "tor")]
-            ConnectionType::TOR => Socks5Stream::connect(
-                format!("127.0.0.1:{}", self.config.socks_port).as_str(),
-                address.as_str(),
-            )?
-            .into_inner(),
-        };
-
-        let reconnect_timeout = Duration::from_secs(TCP_TIMEOUT_SECONDS);
-
-        socket.set_read_timeout(Some(reconnect_timeout))?;
-        socket.set_write_timeout(Some(reconnect_timeout))?;
-
-        handshake_maker(&mut socket)?;
+        let mut connection = MakerConnection::open(
+            &this_maker.address,
+            &self.config,
+            self.config.phase_timeout(ProtocolPhase::ContractSigs),
+            Some(&self.ongoing_swap_state.transcript),
+        )
+        .await?;
         let mut next_maker = this_maker.clone();
         let (
             next_peer_multisig_pubkeys,
@@ -1017,13 +1501,16 @@ impl Taker {
                 next_peer_hashlock_pubkeys,
                 next_peer_hashlock_keys_or_nonces,
             ) = if self.ongoing_swap_state.taker_position == TakerPosition::LastPeer {
-                let (my_recv_ms_pubkeys, my_recv_ms_nonce): (Vec<_>, Vec<_>) =
-                    (0..self.ongoing_swap_state.swap_params.tx_count)
-                        .map(|_| generate_keypair())
-                        .unzip();
-                let (my_recv_hashlock_pubkeys, my_recv_hashlock_nonce): (Vec<_>, Vec<_>) = (0
-                    ..self.ongoing_swap_state.swap_params.tx_count)
-                    .map(|_| generate_keypair())
+                let tx_count = self.ongoing_swap_state.swap_params.tx_count;
+                let (my_recv_ms_pubkeys, my_recv_ms_nonce): (Vec<_>, Vec<_>) = self
+                    .wallet
+                    .next_swap_keypairs(KeyClass::Swap, tx_count)?
+                    .into_iter()
+                    .unzip();
+                let (my_recv_hashlock_pubkeys, my_recv_hashlock_nonce): (Vec<_>, Vec<_>) = self
+                    .wallet
+                    .next_swap_keypairs(KeyClass::Contract, tx_count)?
+                    .into_iter()
                     .unzip();
                 (
                     my_recv_ms_pubkeys,
@@ -1082,12 +1569,14 @@ impl Taker {
 
             let (contract_sigs_as_recvr_sender, next_swap_contract_redeemscripts) =
                 send_proof_of_funding_and_init_next_hop(
-                    &mut socket,
+                    &mut connection,
                     this_maker_info,
                     next_maker_info,
                     self.get_preimage_hash(),
                     self.ongoing_swap_state.id.clone(),
-                )?;
+                    self.ongoing_swap_state.contract_feerate,
+                )
+                .await?;
             log::info!(
                 "<=== ReqContractSigsAsRecvrAndSender | {}",
                 this_maker.address
@@ -1123,13 +1612,16 @@ impl Taker {
                     &next_peer_multisig_pubkeys,
                     &next_swap_contract_redeemscripts,
                 )?;
-                let sigs = match self.req_sigs_for_sender(
-                    &next_maker.address,
-                    &watchonly_swapcoins,
-                    &next_peer_multisig_keys_or_nonces,
-                    &next_peer_hashlock_keys_or_nonces,
-                    maker_refund_locktime,
-                ) {
+                let sigs = match self
+                    .req_sigs_for_sender(
+                        &next_maker.address,
+                        &watchonly_swapcoins,
+                        &next_peer_multisig_keys_or_nonces,
+                        &next_peer_hashlock_keys_or_nonces,
+                        maker_refund_locktime,
+                    )
+                    .await
+                {
                     Ok(r) => r,
                     Err(e) => {
                         self.offerbook.add_bad_maker(&next_maker);
@@ -1184,11 +1676,14 @@ impl Taker {
                         [self.ongoing_swap_state.watchonly_swapcoins.len() - 2]
                 };
 
-            match self.req_sigs_for_recvr(
-                previous_maker_addr,
-                previous_maker_watchonly_swapcoins,
-                &contract_sigs_as_recvr_sender.receivers_contract_txs,
-            ) {
+            match self
+                .req_sigs_for_recvr(
+                    previous_maker_addr,
+                    previous_maker_watchonly_swapcoins,
+                    &contract_sigs_as_recvr_sender.receivers_contract_txs,
+                )
+                .await
+            {
                 Ok(s) => s.sigs,
                 Err(e) => {
                     log::error!("Could not get Receiver's signatures : {:?}", e);
@@ -1203,16 +1698,15 @@ impl Taker {
             this_maker.address
         );
         let id = self.ongoing_swap_state.id.clone();
-        send_message(
-            &mut socket,
-            &TakerToMakerMessage::RespContractSigsForRecvrAndSender(
+        connection
+            .send(&TakerToMakerMessage::RespContractSigsForRecvrAndSender(
                 ContractSigsForRecvrAndSender {
                     receivers_sigs,
                     senders_sigs,
                     id,
                 },
-            ),
-        )?;
+            ))
+            .await?;
 
         let next_swap_info = NextPeerInfo {
             peer: next_maker.clone(),
@@ -1310,7 +1804,7 @@ impl Taker {
                         previous_funding_output,
                         maker_funding_tx_value,
                         next_contract_redeemscript,
-                        Amount::from_sat(MINER_FEE),
+                        self.contract_fee(),
                     )
                 },
             )
@@ -1386,7 +1880,7 @@ impl Taker {
     }
 
     /// Request signatures for the [IncomingSwapCoin] from the last maker of the swap round.
-    fn request_sigs_for_incoming_swap(&mut self) -> Result<(), TakerError> {
+    async fn request_sigs_for_incoming_swap(&mut self) -> Result<(), TakerError> {
         // Intermediate hops completed. Perform the last receiving hop.
         let last_maker = self
             .ongoing_swap_state
@@ -1397,16 +1891,19 @@ impl Taker {
             .expect("previous maker expected")
             .peer
             .clone();
-        let receiver_contract_sig = match self.req_sigs_for_recvr(
-            &last_maker.address,
-            &self.ongoing_swap_state.incoming_swapcoins,
-            &self
-                .ongoing_swap_state
-                .incoming_swapcoins
-                .iter()
-                .map(|swapcoin| swapcoin.contract_tx.clone())
-                .collect::<Vec<Transaction>>(),
-        ) {
+        let receiver_contract_sig = match self
+            .req_sigs_for_recvr(
+                &last_maker.address,
+                &self.ongoing_swap_state.incoming_swapcoins,
+                &self
+                    .ongoing_swap_state
+                    .incoming_swapcoins
+                    .iter()
+                    .map(|swapcoin| swapcoin.contract_tx.clone())
+                    .collect::<Vec<Transaction>>(),
+            )
+            .await
+        {
             Ok(s) => s,
             Err(e) => {
                 log::warn!("Banning Maker : {}", last_maker.address);
@@ -1432,8 +1929,9 @@ impl Taker {
     }
 
     /// Request signatures for sender side of the swap.
-    /// Keep trying until `first_connect_attempts` limit, with time delay of `first_connect_sleep_delay_sec`.
-    fn req_sigs_for_sender<S: SwapCoin>(
+    /// Keep trying until the `first_connect_attempts` limit, with the retry delay of the config.
+    /// Each attempt makes a new connection to the maker.
+    async fn req_sigs_for_sender<S: SwapCoin + Sync>(
         &self,
         maker_address: &MakerAddress,
         outgoing_swapcoins: &[S],
@@ -1441,171 +1939,146 @@ impl Taker {
         maker_hashlock_nonces: &[SecretKey],
         locktime: u16,
     ) -> Result<ContractSigsForSender, TakerError> {
-        let reconnect_time_out = Duration::from_secs(FIRST_CONNECT_ATTEMPT_TIMEOUT_SEC);
-        // Configurable reconnection attempts for testing
-        let first_connect_attempts = if cfg!(feature = "integration-test") {
-            10
-        } else {
-            FIRST_CONNECT_ATTEMPTS
-        };
-
-        // Custom sleep delay for testing.
-        let sleep_delay = if cfg!(feature = "integration-test") {
-            1
-        } else {
-            FIRST_CONNECT_SLEEP_DELAY_SEC
-        };
+        let reconnect_time_out = self.config.phase_timeout(ProtocolPhase::ContractSigs);
+        let first_connect_attempts = self.config.first_connect_attempts;
 
         let mut ii = 0;
 
         let maker_addr_str = maker_address.to_string();
+        let peer = tracing::info_span!("peer", address = %maker_addr_str);
 
-        let mut socket = match self.config.connection_type {
-            ConnectionType::CLEARNET => TcpStream::connect(maker_addr_str.clone())?,
-            #[cfg(feature = "tor")]
-            ConnectionType::TOR => Socks5Stream::connect(
-                format!("127.0.0.1:{}", self.config.socks_port).as_str(),
-                &*maker_addr_str,
-            )?
-            .into_inner(),
-        };
-
-        socket.set_read_timeout(Some(reconnect_time_out))?;
-        socket.set_write_timeout(Some(reconnect_time_out))?;
-
-        loop {
-            ii += 1;
-            log::info!("===> ReqContractSigsForSender | {}", maker_addr_str);
-            match req_sigs_for_sender_once(
-                &mut socket,
-                outgoing_swapcoins,
-                maker_multisig_nonces,
-                maker_hashlock_nonces,
-                locktime,
-            ) {
-                Ok(ret) => {
-                    return {
-                        log::info!("<=== RespContractSigsForSender | {}", maker_addr_str);
-                        Ok(ret)
+        async {
+            loop {
+                ii += 1;
+                log::info!("===> ReqContractSigsForSender | {}", maker_addr_str);
+                let attempt = async {
+                    let mut connection = MakerConnection::open(
+                        maker_address,
+                        &self.config,
+                        reconnect_time_out,
+                        Some(&self.ongoing_swap_state.transcript),
+                    )
+                    .await?;
+                    req_sigs_for_sender_once(
+                        &mut connection,
+                        outgoing_swapcoins,
+                        maker_multisig_nonces,
+                        maker_hashlock_nonces,
+                        locktime,
+                        self.ongoing_swap_state.contract_feerate,
+                    )
+                    .await
+                };
+                match attempt.await {
+                    Ok(ret) => {
+                        return {
+                            log::info!("<=== RespContractSigsForSender | {}", maker_addr_str);
+                            Ok(ret)
+                        }
                     }
-                }
-                Err(e) => {
-                    log::warn!(
-                        "Failed to connect to maker {} to request signatures for receiver, \
-                                reattempting {} of {} | error={:?}",
-                        &maker_addr_str,
-                        ii,
-                        first_connect_attempts,
-                        e
-                    );
-                    if ii <= first_connect_attempts {
-                        sleep(Duration::from_secs(
-                            if ii <= SHORT_LONG_SLEEP_DELAY_TRANSITION {
-                                sleep_delay
-                            } else {
-                                RECONNECT_LONG_SLEEP_DELAY
-                            },
-                        ));
-                        continue;
-                    } else {
+                    Err(e) => {
                         log::warn!(
                             "Failed to connect to maker {} to request signatures for receiver, \
-                                    reattempt limit exceeded",
+                                    reattempting {} of {} | error={:?}",
                             &maker_addr_str,
+                            ii,
+                            first_connect_attempts,
+                            e
                         );
-                        return Err(e);
+                        if ii <= first_connect_attempts {
+                            sleep(self.config.retry_delay(ii)).await;
+                            continue;
+                        } else {
+                            log::warn!(
+                                "Failed to connect to maker {} to request signatures for receiver, \
+                                        reattempt limit exceeded",
+                                &maker_addr_str,
+                            );
+                            return Err(e);
+                        }
                     }
                 }
             }
         }
+        .instrument(peer)
+        .await
     }
 
     /// Request signatures for receiver side of the swap.
     /// Keep trying until `reconnect_attempts` limit, with a time delay.
-    /// The time delay transitions from `reconnect_short_slepp_delay` to `reconnect_locg_sleep_delay`,
-    /// after `short_long_sleep_delay_transition` time.
-    fn req_sigs_for_recvr<S: SwapCoin>(
+    /// The delay between attempts grows by the retry backoff factor of the config.
+    /// Each attempt makes a new connection to the maker.
+    async fn req_sigs_for_recvr<S: SwapCoin + Sync>(
         &self,
         maker_address: &MakerAddress,
         incoming_swapcoins: &[S],
         receivers_contract_txes: &[Transaction],
     ) -> Result<ContractSigsForRecvr, TakerError> {
-        let reconnect_time_out = Duration::from_secs(TCP_TIMEOUT_SECONDS);
-
-        // Configurable reconnection attempts for testing
-        let reconnect_attempts = if cfg!(feature = "integration-test") {
-            10
-        } else {
-            RECONNECT_ATTEMPTS
-        };
+        let reconnect_time_out = self.config.phase_timeout(ProtocolPhase::ContractSigs);
 
-        // Custom sleep delay for testing.
-        let sleep_delay = if cfg!(feature = "integration-test") {
-            1
-        } else {
-            RECONNECT_SHORT_SLEEP_DELAY
-        };
+        let reconnect_attempts = self.config.reconnect_attempts;
 
         let mut ii = 0;
 
         let maker_addr_str = maker_address.to_string();
-        let mut socket = match self.config.connection_type {
-            ConnectionType::CLEARNET => TcpStream::connect(maker_addr_str.clone())?,
-            #[cfg(feature = "tor")]
-            ConnectionType::TOR => Socks5Stream::connect(
-                format!("127.0.0.1:{}", self.config.socks_port).as_str(),
-                &*maker_addr_str,
-            )?
-            .into_inner(),
-        };
+        let peer = tracing::info_span!("peer", address = %maker_addr_str);
 
-        socket.set_read_timeout(Some(reconnect_time_out))?;
-        socket.set_write_timeout(Some(reconnect_time_out))?;
-
-        loop {
-            ii += 1;
-            log::info!("===> ReqContractSigsForRecvr | {}", maker_addr_str);
-            match req_sigs_for_recvr_once(&mut socket, incoming_swapcoins, receivers_contract_txes)
-            {
-                Ok(ret) => {
-                    log::info!("<=== RespContractSigsForRecvr | {}", maker_addr_str);
-                    return Ok(ret);
-                }
-                Err(e) => {
-                    log::warn!(
-                        "Failed to connect to maker {} to request signatures for receiver, \
-                                reattempting, {} of {} |  error={:?}",
-                        &maker_addr_str,
-                        ii,
-                        reconnect_attempts,
-                        e
-                    );
-                    if ii <= reconnect_attempts {
-                        sleep(Duration::from_secs(
-                            if ii <= SHORT_LONG_SLEEP_DELAY_TRANSITION {
-                                sleep_delay
-                            } else {
-                                RECONNECT_LONG_SLEEP_DELAY
-                            },
-                        ));
-                        continue;
-                    } else {
+        async {
+            loop {
+                ii += 1;
+                log::info!("===> ReqContractSigsForRecvr | {}", maker_addr_str);
+                let attempt = async {
+                    let mut connection = MakerConnection::open(
+                        maker_address,
+                        &self.config,
+                        reconnect_time_out,
+                        Some(&self.ongoing_swap_state.transcript),
+                    )
+                    .await?;
+                    req_sigs_for_recvr_once(
+                        &mut connection,
+                        incoming_swapcoins,
+                        receivers_contract_txes,
+                    )
+                    .await
+                };
+                match attempt.await {
+                    Ok(ret) => {
+                        log::info!("<=== RespContractSigsForRecvr | {}", maker_addr_str);
+                        return Ok(ret);
+                    }
+                    Err(e) => {
                         log::warn!(
                             "Failed to connect to maker {} to request signatures for receiver, \
-                                    reattempt limit exceeded",
+                                    reattempting, {} of {} |  error={:?}",
                             &maker_addr_str,
+                            ii,
+                            reconnect_attempts,
+                            e
                         );
-                        return Err(e);
+                        if ii <= reconnect_attempts {
+                            sleep(self.config.retry_delay(ii)).await;
+                            continue;
+                        } else {
+                            log::warn!(
+                                "Failed to connect to maker {} to request signatures for receiver, \
+                                        reattempt limit exceeded",
+                                &maker_addr_str,
+                            );
+                            return Err(e);
+                        }
                     }
                 }
             }
         }
+        .instrument(peer)
+        .await
     }
 
     /// Settle all the ongoing swaps. This routine sends the hash preimage to all the makers.
     /// Pass around the Maker's multisig privatekeys. Saves all the data in wallet file. This marks
     /// the ends of swap round.
-    fn settle_all_swaps(&mut self) -> Result<(), TakerError> {
+    async fn settle_all_swaps(&mut self) -> Result<(), TakerError> {
         let mut outgoing_privkeys: Option<Vec<MultisigPrivkey>> = None;
 
         // Because the last peer info is the Taker, we take upto (0..n-1), where n = peer_info.len()
@@ -1659,29 +2132,20 @@ impl Taker {
 
             let mut ii = 0;
 
-            // Configurable reconnection attempts for testing
-            let reconnect_attempts = if cfg!(feature = "integration-test") {
-                10
-            } else {
-                RECONNECT_ATTEMPTS
-            };
-
-            // Custom sleep delay for testing.
-            let sleep_delay = if cfg!(feature = "integration-test") {
-                1
-            } else {
-                RECONNECT_SHORT_SLEEP_DELAY
-            };
+            let reconnect_attempts = self.config.reconnect_attempts;
 
             loop {
                 ii += 1;
-                match self.settle_one_coinswap(
-                    &maker_address.address,
-                    index,
-                    &mut outgoing_privkeys,
-                    &senders_multisig_redeemscripts,
-                    &receivers_multisig_redeemscripts,
-                ) {
+                match self
+                    .settle_one_coinswap(
+                        &maker_address.address,
+                        index,
+                        &mut outgoing_privkeys,
+                        &senders_multisig_redeemscripts,
+                        &receivers_multisig_redeemscripts,
+                    )
+                    .await
+                {
                     Ok(()) => break,
                     Err(e) => {
                         log::warn!(
@@ -1693,13 +2157,7 @@ impl Taker {
                             e
                         );
                         if ii <= reconnect_attempts {
-                            sleep(Duration::from_secs(
-                                if ii <= SHORT_LONG_SLEEP_DELAY_TRANSITION {
-                                    sleep_delay
-                                } else {
-                                    RECONNECT_LONG_SLEEP_DELAY
-                                },
-                            ));
+                            sleep(self.config.retry_delay(ii)).await;
                             continue;
                         } else {
                             log::warn!(
@@ -1718,7 +2176,7 @@ impl Taker {
     }
 
     /// [Internal] Setlle one swap. This is recursively called for all the makers.
-    fn settle_one_coinswap(
+    async fn settle_one_coinswap(
         &mut self,
         maker_address: &MakerAddress,
         index: usize,
@@ -1726,76 +2184,203 @@ impl Taker {
         senders_multisig_redeemscripts: &[ScriptBuf],
         receivers_multisig_redeemscripts: &[ScriptBuf],
     ) -> Result<(), TakerError> {
-        let maker_addr_str = maker_address.to_string();
-        let mut socket = match self.config.connection_type {
-            ConnectionType::CLEARNET => TcpStream::connect(maker_addr_str.clone())?,
-            #[cfg(feature = "tor")]
-            ConnectionType::TOR => Socks5Stream::connect(
-                format!("127.0.0.1:{}", self.config.socks_port).as_str(),
-                &*maker_addr_str,
-            )?
-            .into_inner(),
-        };
-
-        socket.set_read_timeout(Some(Duration::from_secs(TCP_TIMEOUT_SECONDS)))?;
-        socket.set_write_timeout(Some(Duration::from_secs(TCP_TIMEOUT_SECONDS)))?;
-        handshake_maker(&mut socket)?;
+        let peer = tracing::info_span!("peer", address = %maker_address);
+
+        async {
+            let mut connection = MakerConnection::open(
+                maker_address,
+                &self.config,
+                self.config.phase_timeout(ProtocolPhase::PreimageReveal),
+                Some(&self.ongoing_swap_state.transcript),
+            )
+            .await?;
 
-        log::info!("===> HashPreimage | {}", maker_address);
-        let maker_private_key_handover = send_hash_preimage_and_get_private_keys(
-            &mut socket,
-            senders_multisig_redeemscripts,
-            receivers_multisig_redeemscripts,
-            &self.ongoing_swap_state.active_preimage,
-        )?;
-        log::info!("<=== PrivateKeyHandover | {}", maker_address);
+            let preimage = if self.behavior == TakerBehavior::SendWrongPreimage {
+                log::error!("Special Behavior SendWrongPreimage");
+                let mut wrong_preimage = [0u8; 32];
+                OsRng.fill_bytes(&mut wrong_preimage);
+                wrong_preimage
+            } else {
+                self.ongoing_swap_state.active_preimage
+            };
 
-        let privkeys_reply = if self.ongoing_swap_state.taker_position == TakerPosition::FirstPeer {
-            self.ongoing_swap_state
-                .outgoing_swapcoins
-                .iter()
-                .map(|outgoing_swapcoin| MultisigPrivkey {
-                    multisig_redeemscript: outgoing_swapcoin.get_multisig_redeemscript(),
-                    key: outgoing_swapcoin.my_privkey,
-                })
-                .collect::<Vec<MultisigPrivkey>>()
-        } else {
-            assert!(outgoing_privkeys.is_some());
-            let reply = outgoing_privkeys
-                .as_ref()
-                .expect("outgoing privkey expected")
-                .to_vec();
-            *outgoing_privkeys = None;
-            reply
-        };
-        (if self.ongoing_swap_state.taker_position == TakerPosition::LastPeer {
-            check_and_apply_maker_private_keys(
-                &mut self.ongoing_swap_state.incoming_swapcoins,
-                &maker_private_key_handover.multisig_privkeys,
+            log::info!("===> HashPreimage | {}", maker_address);
+            let mut maker_private_key_handover = send_hash_preimage_and_get_private_keys(
+                &mut connection,
+                senders_multisig_redeemscripts,
+                receivers_multisig_redeemscripts,
+                &preimage,
             )
-        } else {
-            let ret = check_and_apply_maker_private_keys(
-                self.ongoing_swap_state
-                    .watchonly_swapcoins
-                    .get_mut(index)
-                    .expect("watchonly coins expected"),
-                &maker_private_key_handover.multisig_privkeys,
+            .await?;
+            log::info!("<=== PrivateKeyHandover | {}", maker_address);
+
+            let privkeys_reply =
+                if self.ongoing_swap_state.taker_position == TakerPosition::FirstPeer {
+                    self.ongoing_swap_state
+                        .outgoing_swapcoins
+                        .iter()
+                        .map(|outgoing_swapcoin| MultisigPrivkey {
+                            multisig_redeemscript: outgoing_swapcoin.get_multisig_redeemscript(),
+                            key: outgoing_swapcoin.my_privkey,
+                        })
+                        .collect::<Vec<MultisigPrivkey>>()
+                } else {
+                    assert!(outgoing_privkeys.is_some());
+                    let reply = outgoing_privkeys
+                        .as_ref()
+                        .expect("outgoing privkey expected")
+                        .to_vec();
+                    *outgoing_privkeys = None;
+                    reply
+                };
+            let receipt = self.countersign_receipt(
+                connection.session(),
+                maker_private_key_handover.receipt.take(),
+                connection.session_secret(),
             );
-            *outgoing_privkeys = Some(maker_private_key_handover.multisig_privkeys);
-            ret
-        })?;
-        log::info!("===> PrivateKeyHandover | {}", maker_address);
-        send_message(
-            &mut socket,
-            &TakerToMakerMessage::RespPrivKeyHandover(PrivKeyHandover {
-                multisig_privkeys: privkeys_reply,
-            }),
-        )?;
-        Ok(())
+            (if self.ongoing_swap_state.taker_position == TakerPosition::LastPeer {
+                check_and_apply_maker_private_keys(
+                    &mut self.ongoing_swap_state.incoming_swapcoins,
+                    &maker_private_key_handover.multisig_privkeys,
+                )
+            } else {
+                let ret = check_and_apply_maker_private_keys(
+                    self.ongoing_swap_state
+                        .watchonly_swapcoins
+                        .get_mut(index)
+                        .expect("watchonly coins expected"),
+                    &maker_private_key_handover.multisig_privkeys,
+                );
+                *outgoing_privkeys = Some(maker_private_key_handover.multisig_privkeys);
+                ret
+            })?;
+            log::info!("===> PrivateKeyHandover | {}", maker_address);
+            connection
+                .send(&TakerToMakerMessage::RespPrivKeyHandover(PrivKeyHandover {
+                    multisig_privkeys: privkeys_reply,
+                    receipt: receipt.clone(),
+                }))
+                .await?;
+            self.ongoing_swap_state.receipts.extend(receipt);
+            Ok(())
+        }
+        .instrument(peer)
+        .await
+    }
+
+    /// Countersigns the receipt of a maker with `session_secret`, the secret of the session key of our hello. None
+    /// if the maker sent no receipt, or one not for this swap, not to be countersigned by our session key or not
+    /// signed by the identity of the session.
+    fn countersign_receipt(
+        &self,
+        session: &Session,
+        receipt: Option<SignedReceipt>,
+        session_secret: &SecretKey,
+    ) -> Option<SignedReceipt> {
+        let mut receipt = receipt?;
+        let session_key = PublicKey::new(session_secret.public_key(&Secp256k1::new()));
+        let signed_by_peer = matches!(
+            session.peer_identity(),
+            Some(identity) if *identity == receipt.maker_sig.identity
+        );
+        if receipt.receipt.swap_id != self.ongoing_swap_state.id
+            || receipt.receipt.taker_key != session_key
+            || !signed_by_peer
+            || verify_receipt(&receipt).is_err()
+        {
+            log::warn!("Invalid receipt from the maker, not countersigning it");
+            return None;
+        }
+        match receipt.countersign(session_secret) {
+            Ok(()) => Some(receipt),
+            Err(e) => {
+                log::warn!("Failed countersigning the receipt: {:?}", e);
+                None
+            }
+        }
     }
 
     // ######## UTILITY AND HELPERS ############
 
+    /// Checks the wallet can fund the swap, and sets the send amount of a sweep.
+    pub(crate) fn check_swap_funds(&self, swap_params: &mut SwapParams) -> Result<(), TakerError> {
+        // Check if we have enough balance, in the coins the funding txs can spend.
+        let available = self
+            .wallet
+            .selectable_balance(&swap_params.input_constraints())?;
+
+        if swap_params.sweep {
+            // Every funding tx pays `MINER_FEE`, the rest of the selectable coins is swapped.
+            let funding_fees = Amount::from_sat(MINER_FEE * swap_params.tx_count as u64);
+            swap_params.send_amount = available.checked_sub(funding_fees).ok_or_else(|| {
+                WalletError::InsufficientFund {
+                    available: available.to_sat(),
+                    required: funding_fees.to_sat(),
+                }
+            })?;
+            log::info!(
+                "Sweeping the wallet, send amount : {}",
+                swap_params.send_amount
+            );
+        } else {
+            // TODO: Make more exact estimate of swap cost and ensure balance.
+            // For now ensure at least swap_amount + 1000 sats is available.
+            let required = swap_params.send_amount + Amount::from_sat(1000);
+            if available < required {
+                let err = WalletError::InsufficientFund {
+                    available: available.to_sat(),
+                    required: required.to_sat(),
+                };
+                log::error!("Not enough balance to do swap : {:?}", err);
+                return Err(err.into());
+            }
+
+            // The change of the selected coins would be left to the miners. Better swap it along, or leave more.
+            let funding_fees = Amount::from_sat(MINER_FEE * swap_params.tx_count as u64);
+            let spent = swap_params.send_amount + funding_fees;
+            let selected = self
+                .wallet
+                .coin_select_constrained(spent, &swap_params.input_constraints())?
+                .iter()
+                .map(|(utxo, _)| utxo.amount)
+                .sum::<Amount>();
+            let remainder = selected.checked_sub(spent).unwrap_or(Amount::ZERO);
+            let threshold = self.wallet.dust_threshold();
+            if remainder > Amount::ZERO && remainder < threshold {
+                log::error!(
+                    "The swap leaves {} in the wallet, below the dust threshold {}",
+                    remainder,
+                    threshold
+                );
+                return Err(TakerError::DustRemainder {
+                    remainder,
+                    threshold,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the offer book has enough good makers for the swap, and the swap enough makers for privacy.
+    pub(crate) fn check_maker_count(&self, swap_params: &SwapParams) -> Result<(), TakerError> {
+        // Error early if hop_count > available good makers.
+        if swap_params.maker_count > self.offerbook.all_good_makers().len() {
+            log::error!(
+                "Not enough makers in the offerbook. Required {}, avaialable {}",
+                swap_params.maker_count,
+                self.offerbook.all_good_makers().len()
+            );
+            return Err(TakerError::NotEnoughMakersInOfferBook);
+        }
+
+        // Error early if less than 2 makers.
+        if swap_params.maker_count < 2 {
+            log::error!("Cannot swap with less than 2 makers");
+            return Err(ProtocolError::General("Swap maker count < 2").into());
+        }
+        Ok(())
+    }
+
     /// Choose a suitable **untried** maker address from the offerbook that fits the swap params.
     fn choose_next_maker(&self) -> Result<&OfferAndAddress, TakerError> {
         let send_amount = self.ongoing_swap_state.swap_params.send_amount;
@@ -1803,27 +2388,455 @@ impl Taker {
             return Err(TakerError::SendAmountNotSet);
         }
 
-        // Ensure that we don't select a maker we are already swaping with.
-        Ok(self
-            .offerbook
-            .all_good_makers()
+        let chosen_peers = self
+            .ongoing_swap_state
+            .peer_infos
             .iter()
+            .map(|pi| &pi.peer)
+            .collect::<Vec<_>>();
+        self.pick_maker(
+            send_amount,
+            &chosen_peers,
+            self.ongoing_swap_state.contract_feerate,
+            &self.ongoing_swap_state.refund_locktimes,
+        )
+        .ok_or(TakerError::NotEnoughMakersInOfferBook)
+    }
+
+    /// The maker of the offerbook to add to a route of `chosen_peers`, for a swap of `send_amount` with
+    /// these contract feerate and refund locktimes.
+    pub(crate) fn pick_maker(
+        &self,
+        send_amount: Amount,
+        chosen_peers: &[&OfferAndAddress],
+        contract_feerate: Option<f64>,
+        refund_locktimes: &[u16],
+    ) -> Option<&OfferAndAddress> {
+        let config = &self.config;
+
+        // Ensure that we don't select a maker we are already swaping with, and that the route stays diverse:
+        // - No two makers with fidelity bonds from the same funding transaction.
+        // - No maker used in the last `recent_maker_exclusion` swaps.
+        // - No maker with an excluded address prefix.
+        // Makers the directory found flaky are only chosen when no reliable maker fits.
+        self.offerbook
+            .good_makers_by_reliability()
+            .into_iter()
             .find(|oa| {
+                let bond_txid = oa.offer.fidelity.bond.outpoint.txid;
+                let address = oa.address.to_string();
                 send_amount >= Amount::from_sat(oa.offer.min_size)
                     && send_amount <= Amount::from_sat(oa.offer.max_size)
+                    && !chosen_peers.iter().any(|peer| {
+                        **peer == **oa || peer.offer.fidelity.bond.outpoint.txid == bond_txid
+                    })
                     && !self
-                        .ongoing_swap_state
-                        .peer_infos
+                        .offerbook
+                        .used_in_recent_swaps(&oa.address, config.recent_maker_exclusion)
+                    && !config
+                        .excluded_address_prefixes
                         .iter()
-                        .map(|pi| &pi.peer)
-                        .any(|noa| noa == **oa)
+                        .any(|prefix| address.starts_with(prefix.as_str()))
+                    && contract_feerate
+                        .is_none_or(|feerate| oa.offer.accepts_contract_feerate(feerate))
+                    && match refund_locktimes.get(chosen_peers.len()..chosen_peers.len() + 2) {
+                        Some(&[incoming, outgoing]) => oa
+                            .offer
+                            .accepts_refund_locktime(incoming, incoming - outgoing),
+                        _ => true,
+                    }
             })
-            .ok_or(TakerError::NotEnoughMakersInOfferBook)?)
     }
 
-    /// Get the [Preimage] of the ongoing swap. If no swap is in progress will return a `[0u8; 32]`.
-    fn get_preimage(&self) -> &Preimage {
-        &self.ongoing_swap_state.active_preimage
+    /// Picks the feerate of the contract txs for the swap, in sats/vB: the node's estimate, bounded by
+    /// `max_contract_feerate` and, where they overlap, the ranges advertised by the makers.
+    ///
+    /// Returns None if any maker doesn't negotiate the feerate, the swap then falls back to `MINER_FEE`.
+    pub(crate) fn negotiate_contract_feerate(&self) -> Result<Option<f64>, TakerError> {
+        let makers = self.offerbook.all_good_makers();
+        if makers
+            .iter()
+            .any(|oa| !oa.offer.negotiates_contract_feerate())
+        {
+            log::info!(
+                "Not all makers negotiate the contract feerate, using the default contract fee"
+            );
+            return Ok(None);
+        }
+
+        let estimate = self.wallet.estimate_feerate(CONTRACT_FEERATE_CONF_TARGET)?;
+        let (own_min, own_max) = contract_feerate_range(estimate, self.config.max_contract_feerate);
+        let makers_min = makers
+            .iter()
+            .map(|oa| oa.offer.min_contract_feerate)
+            .fold(own_min, f64::max);
+        let makers_max = makers
+            .iter()
+            .map(|oa| oa.offer.max_contract_feerate)
+            .fold(own_max, f64::min);
+        // Makers outside the common range are left out of the route.
+        let (min, max) = if makers_min <= makers_max {
+            (makers_min, makers_max)
+        } else {
+            (own_min, own_max)
+        };
+
+        let feerate = estimate.unwrap_or(MIN_CONTRACT_FEERATE).clamp(min, max);
+        log::info!(
+            "Contract feerate : {} sat/vb | Estimate : {:?} sat/vb",
+            feerate,
+            estimate
+        );
+        Ok(Some(feerate))
+    }
+
+    /// Refund locktimes of the hops of the swap, from the Taker's funding txs to the last hop.
+    ///
+    /// The hops are spaced by `refund_locktime_step`, or by the smallest claim window enough of the
+    /// suitable makers accept, if larger. Refuses ladders leaving any party too small a claim window, see
+    /// [check_timelock_ladder].
+    pub(crate) fn plan_refund_locktimes(
+        &self,
+        swap_params: &SwapParams,
+        contract_feerate: Option<f64>,
+    ) -> Result<Vec<u16>, TakerError> {
+        let send_amount = swap_params.send_amount.to_sat();
+        let accepted_windows = self
+            .offerbook
+            .all_good_makers()
+            .into_iter()
+            .map(|oa| &oa.offer)
+            .filter(|offer| send_amount >= offer.min_size && send_amount <= offer.max_size)
+            .map(|offer| offer.minimum_locktime)
+            .collect::<Vec<_>>();
+        let step = pick_locktime_step(
+            self.config.refund_locktime_step,
+            accepted_windows,
+            swap_params.maker_count,
+        );
+        let locktimes = refund_locktime_ladder(
+            self.config.refund_locktime,
+            &vec![step; swap_params.maker_count],
+        )?;
+
+        // The contract txs pay the negotiated feerate, or `MINER_FEE`.
+        let contract_feerate =
+            contract_feerate.unwrap_or(MINER_FEE as f64 / CONTRACT_TX_VSIZE as f64);
+        let confirm_blocks = self.wallet.blocks_to_confirm(contract_feerate)?;
+        check_timelock_ladder(&locktimes, confirm_blocks)?;
+        log::info!(
+            "Refund locktimes of the hops : {:?} | Contract txs confirm within {:?} blocks",
+            locktimes,
+            confirm_blocks
+        );
+        Ok(locktimes)
+    }
+
+    /// Fee of each contract tx of the ongoing swap.
+    fn contract_fee(&self) -> Amount {
+        self.ongoing_swap_state
+            .contract_feerate
+            .map_or(Amount::from_sat(MINER_FEE), contract_fee)
+    }
+
+    /// Upper bound of the total fee of a swap through the makers of the offerbook, see [max_swap_fee].
+    fn estimate_max_swap_fee(&self, swap_params: &SwapParams, refund_locktimes: &[u16]) -> Amount {
+        let offers = self
+            .offerbook
+            .all_good_makers()
+            .into_iter()
+            .map(|oa| &oa.offer)
+            .collect::<Vec<_>>();
+        max_swap_fee(&offers, swap_params, refund_locktimes)
+    }
+
+    /// Fees of the makers whose outgoing hop is already funded, in route order.
+    fn maker_fee_records(&self) -> Vec<MakerFeeRecord> {
+        let state = &self.ongoing_swap_state;
+
+        // Amount received by each maker, followed by the amount the Taker received.
+        let hop_amounts = std::iter::once(
+            state
+                .outgoing_swapcoins
+                .iter()
+                .map(|sc| sc.funding_amount)
+                .sum::<Amount>(),
+        )
+        .chain(
+            state
+                .watchonly_swapcoins
+                .iter()
+                .map(|coins| coins.iter().map(|sc| sc.funding_amount).sum::<Amount>()),
+        )
+        .chain(
+            // Not known before the last hop is funded.
+            Some(
+                state
+                    .incoming_swapcoins
+                    .iter()
+                    .map(|sc| sc.funding_amount)
+                    .sum::<Amount>(),
+            )
+            .filter(|_| !state.incoming_swapcoins.is_empty()),
+        )
+        .collect::<Vec<_>>();
+
+        let maker_miner_fees = MINER_FEE * state.swap_params.tx_count as u64;
+        state
+            .peer_infos
+            .iter()
+            .take(state.swap_params.maker_count)
+            .zip(hop_amounts.windows(2))
+            .map(|(peer_info, amounts)| MakerFeeRecord {
+                address: peer_info.peer.address.to_string(),
+                fee_sats: amounts[0]
+                    .to_sat()
+                    .saturating_sub(amounts[1].to_sat() + maker_miner_fees),
+                miner_fees_sats: maker_miner_fees,
+            })
+            .collect()
+    }
+
+    /// Record of the ongoing swap, once completed.
+    fn swap_record(&self) -> SwapRecord {
+        let state = &self.ongoing_swap_state;
+        let funding_txs = state
+            .funding_txs
+            .first()
+            .map(|(txs, _)| txs.as_slice())
+            .unwrap_or_default();
+        let makers = self.maker_fee_records();
+
+        SwapRecord {
+            id: state.id.clone(),
+            started_at: state.started_at,
+            completed_at: unix_time(),
+            send_amount_sats: state.swap_params.send_amount.to_sat(),
+            inputs: funding_txs
+                .iter()
+                .flat_map(|tx| tx.input.iter().map(|txin| txin.previous_output))
+                .collect(),
+            outputs: funding_txs
+                .iter()
+                .flat_map(|tx| {
+                    let txid = tx.compute_txid();
+                    (0..tx.output.len() as u32).map(move |vout| OutPoint { txid, vout })
+                })
+                .collect(),
+            makers,
+            miner_fees_sats: state.funding_fee.to_sat(),
+            resulting_utxos: state
+                .incoming_swapcoins
+                .iter()
+                .map(|sc| sc.contract_tx.input[0].previous_output)
+                .collect(),
+            memo: self
+                .wallet
+                .memo(&MemoTarget::Swap(state.id.clone()))
+                .map(str::to_string),
+            receipts: state.receipts.clone(),
+        }
+    }
+
+    /// Records an event of a swap tx seen on the node, for the dispute bundle.
+    fn observe(&self, txid: Txid, kind: ObservationKind) {
+        if let Ok(mut observations) = self.ongoing_swap_state.observations.lock() {
+            observations.push(ChainObservation {
+                at: unix_time(),
+                txid,
+                kind,
+            });
+        }
+    }
+
+    /// Adds to the ban score of the maker most likely to have broadcast the contracts, see [blame](super::blame).
+    fn blame_premature_broadcast(&mut self, broadcast: &[Txid]) {
+        let state = &self.ongoing_swap_state;
+        // Hops in route order: our funding, the hops between makers, then the hop paying us.
+        let mut hops = vec![state
+            .outgoing_swapcoins
+            .iter()
+            .map(|sc| sc.contract_tx.compute_txid())
+            .collect::<Vec<_>>()];
+        hops.extend(state.watchonly_swapcoins.iter().map(|hop| {
+            hop.iter()
+                .map(|sc| sc.contract_tx.compute_txid())
+                .collect::<Vec<_>>()
+        }));
+        hops.push(
+            state
+                .incoming_swapcoins
+                .iter()
+                .map(|sc| sc.contract_tx.compute_txid())
+                .collect(),
+        );
+
+        let observers = self
+            .config
+            .blame_observers
+            .iter()
+            .filter_map(|observer| match observer_client(observer) {
+                Ok(client) => Some(client),
+                Err(e) => {
+                    log::warn!("Could not connect to blame observer {}: {:?}", observer, e);
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+        let noticed = state
+            .observations
+            .lock()
+            .map(|observations| observations.clone())
+            .unwrap_or_default();
+
+        // The earliest time any node saw the tx. Confirmed txs left the mempools, fall back to when we noticed them.
+        let first_seen = broadcast
+            .iter()
+            .map(|txid| {
+                let seen = std::iter::once(&self.wallet.rpc)
+                    .chain(&observers)
+                    .filter_map(|rpc| mempool_first_seen(rpc, txid))
+                    .min()
+                    .or_else(|| {
+                        noticed
+                            .iter()
+                            .filter(|o| {
+                                o.txid == *txid && o.kind == ObservationKind::ContractBroadcast
+                            })
+                            .map(|o| o.at)
+                            .min()
+                    })
+                    .unwrap_or_else(unix_time);
+                (*txid, seen)
+            })
+            .collect::<HashMap<_, _>>();
+
+        let blame = match attribute_broadcast(&hops, &first_seen, state.peer_infos.len()) {
+            Some(blame) => blame,
+            None => return,
+        };
+        let maker = state.peer_infos[blame.maker_index].peer.clone();
+        log::warn!(
+            "Contracts likely broadcast by maker {}, with confidence {:.2}",
+            maker.address,
+            blame.confidence
+        );
+        if self.offerbook.add_ban_score(&maker, blame.confidence) {
+            log::warn!("Banned maker {} for the premature broadcast", maker.address);
+        }
+    }
+
+    /// Evidence of the ongoing swap, when it aborts.
+    pub(crate) fn dispute_bundle(&self) -> Result<DisputeBundle, TakerError> {
+        let state = &self.ongoing_swap_state;
+        let makers = state
+            .peer_infos
+            .iter()
+            .map(|pi| DisputedMaker {
+                address: pi.peer.address.to_string(),
+                identity: pi.peer.offer.identity.as_ref().map(|b| b.identity),
+                bond_outpoint: pi.peer.offer.fidelity.bond.outpoint,
+            })
+            .collect();
+
+        let funding_txs = state.funding_txs.iter().flat_map(|(txs, _)| txs.iter());
+        let contract_txs = state
+            .outgoing_swapcoins
+            .iter()
+            .map(|sc| &sc.contract_tx)
+            .chain(
+                state
+                    .watchonly_swapcoins
+                    .iter()
+                    .flatten()
+                    .map(|sc| &sc.contract_tx),
+            )
+            .chain(state.incoming_swapcoins.iter().map(|sc| &sc.contract_tx));
+        let mut transactions = Vec::new();
+        for (kind, tx) in funding_txs
+            .map(|tx| (EvidenceTxKind::Funding, tx))
+            .chain(contract_txs.map(|tx| (EvidenceTxKind::Contract, tx)))
+        {
+            let txid = tx.compute_txid();
+            transactions.push(EvidenceTx {
+                kind,
+                txid,
+                tx: serialize_hex(tx),
+                height: self.confirmation_height(&txid)?,
+            });
+        }
+
+        Ok(DisputeBundle {
+            version: DISPUTE_BUNDLE_VERSION,
+            swap_id: state.id.clone(),
+            network: self.wallet.store.network.to_string(),
+            aborted_at: unix_time(),
+            makers,
+            transcript: state
+                .transcript
+                .lock()
+                .map(|t| t.clone())
+                .unwrap_or_default(),
+            observations: state
+                .observations
+                .lock()
+                .map(|o| o.clone())
+                .unwrap_or_default(),
+            transactions,
+            receipts: state.receipts.clone(),
+        })
+    }
+
+    /// Publishes [Event::SwapPhaseChanged] for the ongoing swap.
+    fn publish_phase(&self, phase: SwapPhase) {
+        self.events.publish(Event::SwapPhaseChanged {
+            swap_id: self.ongoing_swap_state.id.clone(),
+            phase,
+        });
+    }
+
+    /// Publishes [Event::FeesAccrued] with the fees of the hops funded so far.
+    fn publish_fees_accrued(&self) {
+        let makers = self.maker_fee_records();
+        let maker_miner_fees = makers.iter().map(|m| m.miner_fees_sats).sum::<u64>();
+        self.events.publish(Event::FeesAccrued {
+            swap_id: self.ongoing_swap_state.id.clone(),
+            maker_fees: Amount::from_sat(makers.iter().map(|m| m.fee_sats).sum()),
+            miner_fees: self.ongoing_swap_state.funding_fee + Amount::from_sat(maker_miner_fees),
+        });
+    }
+
+    /// Exports the records of the swaps completed within `range`, given as unix timestamps.
+    ///
+    /// Records include the funding inputs and outputs, per-maker fees, miner fees and the resulting swap coins.
+    pub fn export_report(
+        &self,
+        range: impl RangeBounds<u64>,
+        format: ReportFormat,
+    ) -> Result<String, TakerError> {
+        let records = self
+            .read_swap_history()?
+            .records_in(range)
+            .into_iter()
+            .map(|record| SwapRecord {
+                memo: self
+                    .wallet
+                    .memo(&MemoTarget::Swap(record.id.clone()))
+                    .map(str::to_string),
+                ..record.clone()
+            })
+            .collect::<Vec<_>>();
+        render_report(&records, format)
+    }
+
+    /// Reads the records of the completed swaps.
+    pub(crate) fn read_swap_history(&self) -> Result<SwapHistory, TakerError> {
+        SwapHistory::read_from_disk(&self.data_dir.join("swap_history.dat"))
+    }
+
+    /// Get the [Preimage] of the ongoing swap. If no swap is in progress will return a `[0u8; 32]`.
+    fn get_preimage(&self) -> &Preimage {
+        &self.ongoing_swap_state.active_preimage
     }
 
     /// Get the [Preimage] hash for the ongoing swap. If no swap is in progress will return `hash160([0u8; 32])`.
@@ -1832,7 +2845,7 @@ impl Taker {
     }
 
     /// Clear the [OngoingSwapState].
-    fn clear_ongoing_swaps(&mut self) {
+    pub(crate) fn clear_ongoing_swaps(&mut self) {
         self.ongoing_swap_state = OngoingSwapState::default();
     }
 
@@ -1841,6 +2854,87 @@ impl Taker {
         self.offerbook.get_bad_makers()
     }
 
+    /// Reserves a fresh wallet address to claim the coins of the swap to, of the script type of the swap.
+    pub(super) fn next_claim_address(&mut self) -> Result<Address, WalletError> {
+        let script_type = self
+            .ongoing_swap_state
+            .swap_params
+            .script_type
+            .unwrap_or(self.wallet.script_type());
+        self.wallet.reserve_internal_address(script_type)
+    }
+
+    /// Reserves a fresh claim address for each of `count` contracts.
+    fn next_claim_addresses(&mut self, count: usize) -> Result<Vec<Address>, WalletError> {
+        (0..count).map(|_| self.next_claim_address()).collect()
+    }
+
+    /// Schedule the timelock refunds of our outgoing contracts in the watchtower.
+    fn watch_outgoing_contracts(&mut self) -> Result<(), TakerError> {
+        let claim_addresses =
+            self.next_claim_addresses(self.ongoing_swap_state.outgoing_swapcoins.len())?;
+        for (outgoing, claim_address) in self
+            .ongoing_swap_state
+            .outgoing_swapcoins
+            .iter()
+            .zip(claim_addresses.iter())
+        {
+            match self
+                .wallet
+                .contract_key_subpath(&outgoing.get_timelock_pubkey()?)?
+            {
+                Some(key_path) => self.watchtower.schedule_timelock_sweep(TimelockSweep::new(
+                    outgoing,
+                    key_path,
+                    claim_address,
+                )?)?,
+                None => log::warn!(
+                    "Timelock key of contract {} not derived from the seed. Not scheduling its sweep",
+                    outgoing.contract_tx.compute_txid()
+                ),
+            }
+        }
+        Ok(())
+    }
+
+    /// Register our incoming contracts to the watchtower, and reveal the preimage so they can be
+    /// claimed via hashlock if ever broadcasted.
+    fn watch_incoming_contracts(&mut self) -> Result<(), TakerError> {
+        let claim_addresses =
+            self.next_claim_addresses(self.ongoing_swap_state.incoming_swapcoins.len())?;
+        for (incoming, claim_address) in self
+            .ongoing_swap_state
+            .incoming_swapcoins
+            .iter()
+            .zip(claim_addresses.iter())
+        {
+            self.watchtower
+                .register_incoming(incoming.clone(), claim_address)?;
+        }
+        self.watchtower
+            .reveal_preimage(&self.ongoing_swap_state.active_preimage)?;
+        Ok(())
+    }
+
+    /// Remove all the contracts of the current swap round from the watchtower.
+    fn unwatch_swap_contracts(&self) -> Result<(), TakerError> {
+        let contract_txids = self
+            .ongoing_swap_state
+            .incoming_swapcoins
+            .iter()
+            .map(|sc| sc.contract_tx.compute_txid())
+            .chain(
+                self.ongoing_swap_state
+                    .outgoing_swapcoins
+                    .iter()
+                    .map(|sc| sc.contract_tx.compute_txid()),
+            );
+        for txid in contract_txids {
+            self.watchtower.unwatch(&txid)?;
+        }
+        Ok(())
+    }
+
     /// Save all the finalized swap data and reset the [OngoingSwapState].
     fn save_and_reset_swap_round(&mut self) -> Result<(), TakerError> {
         // Mark incoiming swapcoins as done
@@ -1868,10 +2962,11 @@ impl Taker {
         Ok(())
     }
 
-    /// Checks if any contreact transactions have been broadcasted.
-    /// Returns the txid list of all the broadcasted contract transaction.
+    /// Checks if any contreact transactions have been broadcasted, and blames the likely culprit, see
+    /// [blame](super::blame).
+    /// Returns the txid list of all the broadcasted contract transaction, in the order our node first saw them.
     /// Empty vector if nothing is nothing is broadcasted. (usual case).
-    pub(crate) fn check_for_broadcasted_contract_txes(&self) -> Vec<Txid> {
+    pub(crate) fn check_for_broadcasted_contract_txes(&mut self) -> Vec<Txid> {
         let contract_txids = self
             .ongoing_swap_state
             .incoming_swapcoins
@@ -1892,19 +2987,46 @@ impl Taker {
             )
             .collect::<Vec<_>>();
 
-        // TODO: Find out which txid was boradcasted first
-        // This requires -txindex to be enabled in the node.
-        let seen_txids = contract_txids
-            .iter()
-            .filter(|txid| self.wallet.rpc.get_raw_transaction_info(txid, None).is_ok())
-            .cloned()
-            .collect::<Vec<Txid>>();
+        // The mempool is polled at every check, so a premature broadcast is caught before its first confirmation.
+        // Finding the confirmed ones requires -txindex to be enabled in the node.
+        let mut seen_txids = broadcasted_txids(&*self.wallet.chain, &contract_txids)
+            .unwrap_or_else(|e| {
+                log::warn!("Failed checking for broadcasted contracts: {:?}", e);
+                Vec::new()
+            });
+        // Confirmed txs left the mempool, they were seen before.
+        seen_txids.sort_by_key(|txid| mempool_first_seen(&self.wallet.rpc, txid).unwrap_or(0));
 
+        if !seen_txids.is_empty() {
+            self.blame_premature_broadcast(&seen_txids);
+        }
         seen_txids
     }
 
+    /// Broadcaster of the recovery txs. Over Tor, it falls back to public endpoints if the node can't relay them.
+    fn broadcaster(&self) -> Broadcaster {
+        match self.config.connection_type {
+            #[cfg(feature = "tor")]
+            ConnectionType::TOR => {
+                Broadcaster::new(self.wallet.store.network, self.config.socks_port)
+            }
+            ConnectionType::CLEARNET => Broadcaster::local(),
+        }
+    }
+
     /// Recover from a bad swap
     pub fn recover_from_swap(&mut self) -> Result<(), TakerError> {
+        let swap_id = Some(self.ongoing_swap_state.id.clone()).filter(|id| !id.is_empty());
+        if swap_id.is_some() {
+            if let Err(e) = self.save_dispute_bundle() {
+                log::warn!("Failed saving the dispute bundle of the swap: {:?}", e);
+            }
+        }
+        self.events.publish(Event::RecoveryInitiated {
+            swap_id,
+            reason: "Recovering from unfinished swap".to_string(),
+        });
+
         let (incomings, outgoings) = self.wallet.find_unfinished_swapcoins();
 
         let incoming_contracts = incomings
@@ -1921,20 +3043,40 @@ impl Taker {
         for (contract_tx, redeemscript) in &incoming_contracts {
             if self
                 .wallet
-                .rpc
-                .get_raw_transaction_info(&contract_tx.compute_txid(), None)
-                .is_ok()
+                .chain
+                .tx_confirmations(&contract_tx.compute_txid())?
+                .is_some()
             {
                 log::info!(
                     "Incoming Contract already broadacsted. Txid : {}",
                     contract_tx.compute_txid()
                 );
             } else {
-                self.wallet.send_tx(contract_tx)?;
-                log::info!(
-                    "Broadcasting Incoming Contract. Removing from wallet. Txid : {}",
-                    contract_tx.compute_txid()
+                self.wallet.check_broadcast_feerate(
+                    contract_tx,
+                    self.config.refund_locktime_step,
+                    &self.events,
                 );
+                if let Err(e) = self
+                    .broadcaster()
+                    .broadcast(&*self.wallet.chain, contract_tx)
+                {
+                    // A spent funding, e.g. by the cooperative spend, leaves nothing to recover.
+                    if self.wallet.contract_conflict(contract_tx)?
+                        != Some(ContractConflict::FundingSpent)
+                    {
+                        return Err(e.into());
+                    }
+                    log::info!(
+                        "Funding of Incoming Contract already spent. Txid : {}",
+                        contract_tx.compute_txid()
+                    );
+                } else {
+                    log::info!(
+                        "Broadcasting Incoming Contract. Removing from wallet. Txid : {}",
+                        contract_tx.compute_txid()
+                    );
+                }
             }
             log::info!(
                 "Incoming Swapcoin removed from wallet, Txid: {}",
@@ -1950,30 +3092,51 @@ impl Taker {
             let contract_tx = outgoing.get_fully_signed_contract_tx()?;
             if self
                 .wallet
-                .rpc
-                .get_raw_transaction_info(&contract_tx.compute_txid(), None)
-                .is_ok()
+                .chain
+                .tx_confirmations(&contract_tx.compute_txid())?
+                .is_some()
             {
                 log::info!(
                     "Outgoing Contract already broadcasted | Txid: {}",
                     contract_tx.compute_txid()
                 );
             } else {
-                self.wallet.send_tx(&contract_tx)?;
-                log::info!(
-                    "Broadcasted Outgoing Contract | txid : {}",
-                    contract_tx.compute_txid()
+                self.wallet.check_broadcast_feerate(
+                    &contract_tx,
+                    self.config.refund_locktime_step,
+                    &self.events,
                 );
+                if let Err(e) = self
+                    .broadcaster()
+                    .broadcast(&*self.wallet.chain, &contract_tx)
+                {
+                    // Swapcoins with a spent funding are settled in the timelock loop below.
+                    if self.wallet.contract_conflict(&contract_tx)?
+                        != Some(ContractConflict::FundingSpent)
+                    {
+                        return Err(e.into());
+                    }
+                    log::info!(
+                        "Funding of Outgoing Contract already spent | Txid: {}",
+                        contract_tx.compute_txid()
+                    );
+                } else {
+                    log::info!(
+                        "Broadcasted Outgoing Contract | txid : {}",
+                        contract_tx.compute_txid()
+                    );
+                }
             }
             let reedemscript = outgoing.get_multisig_redeemscript();
             let timelock = outgoing.get_timelock()?;
-            let next_internal = &self.wallet.get_next_internal_addresses(1)?[0];
+            let next_internal = &self.next_claim_address()?;
             let timelock_spend = outgoing.create_timelock_spend(next_internal)?;
             outgoing_infos.push(((reedemscript, contract_tx), (timelock, timelock_spend)));
         }
 
         // Check for contract confirmations and broadcast timelocked transaction
         let mut timelock_boardcasted = Vec::new();
+        let mut tracker = ConfirmationTracker::default();
 
         // Save the wallet file here before going into the expensive loop.
         self.wallet.sync()?;
@@ -1994,11 +3157,23 @@ impl Taker {
                 }
                 // Check if the contract tx has reached required maturity
                 // Failure here means the transaction hasn't been broadcasted yet. So do nothing and try again.
-                if let Ok(result) = self
+                let result = self
                     .wallet
                     .rpc
                     .get_raw_transaction_info(&contract.compute_txid(), None)
-                {
+                    .ok();
+                // The maturity counts from the block the contract is in now.
+                if let ConfirmationUpdate::Reorged { blockhash } = tracker.update(
+                    contract.compute_txid(),
+                    result.as_ref().and_then(|result| result.blockhash),
+                ) {
+                    log::warn!(
+                        "Contract Tx : {} reorged out of block {}. Waiting for its timelock maturity again.",
+                        contract.compute_txid(),
+                        blockhash
+                    );
+                }
+                if let Some(result) = result {
                     log::info!(
                         "Contract Tx : {}, reached confirmation : {:?}, required : {}",
                         contract.compute_txid(),
@@ -2007,7 +3182,7 @@ impl Taker {
                     );
                     if let Some(confirmation) = result.confirmations {
                         // Now the transaction is confirmed in a block, check for required maturity
-                        if confirmation > (*timelock as u32) {
+                        if confirmation >= (*timelock as u32) {
                             log::info!(
                                 "Timelock maturity of {} blocks for Contract Tx is reached : {}",
                                 timelock,
@@ -2017,7 +3192,30 @@ impl Taker {
                                 "Broadcasting timelocked tx: {}",
                                 timelocked_tx.compute_txid()
                             );
-                            self.wallet.send_tx(timelocked_tx)?;
+                            let low_feerate = self.wallet.check_broadcast_feerate(
+                                timelocked_tx,
+                                self.config.refund_locktime_step,
+                                &self.events,
+                            );
+                            if let Err(e) = self
+                                .broadcaster()
+                                .broadcast(&*self.wallet.chain, timelocked_tx)
+                            {
+                                // The watchtower might have already claimed this contract.
+                                if self
+                                    .wallet
+                                    .chain
+                                    .is_unspent(&OutPoint::new(contract.compute_txid(), 0), true)?
+                                {
+                                    return Err(e.into());
+                                }
+                                log::info!("Contract {} already claimed", contract.compute_txid());
+                            } else if let Some(feerate) = low_feerate {
+                                let outpoint = OutPoint::new(timelocked_tx.compute_txid(), 0);
+                                if let Err(e) = self.wallet.bump_fee_with_cpfp(outpoint, feerate) {
+                                    log::warn!("CPFP of timelocked tx failed: {:?}", e);
+                                }
+                            }
                             timelock_boardcasted.push(timelocked_tx);
 
                             let outgoing_removed = self
@@ -2034,6 +3232,17 @@ impl Taker {
                             log::info!("Completed wallet sync and save");
                         }
                     }
+                } else if self.wallet.contract_conflict(contract)?
+                    == Some(ContractConflict::FundingSpent)
+                {
+                    // The contract can't confirm anymore if its funding went elsewhere, e.g. to the cooperative
+                    // spend of the swap. Nothing is left to claim, so stop waiting for it.
+                    self.wallet.remove_outgoing_swapcoin(reedemscript)?;
+                    timelock_boardcasted.push(timelocked_tx);
+                    log::info!(
+                        "Funding of Contract Tx : {} already spent. Removed Outgoing Swapcoin from Wallet",
+                        contract.compute_txid()
+                    );
                 }
             }
 
@@ -2063,8 +3272,8 @@ impl Taker {
         Ok(())
     }
 
-    /// Synchronizes the offer book with addresses obtained from directory servers and local configurations.
-    pub fn sync_offerbook(&mut self) -> Result<(), TakerError> {
+    /// Address of the directory server, and the socks port to reach it with, if any.
+    fn dns_address(&self) -> Result<(String, Option<u16>), TakerError> {
         let dns_addr = match self.config.connection_type {
             ConnectionType::CLEARNET => {
                 if cfg!(feature = "integration-test") {
@@ -2097,17 +3306,170 @@ impl Taker {
             None
         };
 
-        log::info!("Fetching addresses from DNS: {}", dns_addr);
+        Ok((dns_addr, socks_port))
+    }
+
+    /// Addresses of the directory server, of the extra ones and of the bundled ones if enabled, and the socks
+    /// port to reach them with, if any.
+    fn directory_addresses(&self) -> Result<(Vec<String>, Option<u16>), TakerError> {
+        let (dns_addr, socks_port) = self.dns_address()?;
+        let bootstrap_directories: &[&str] = if self.config.bootstrap_directories {
+            bootstrap_directory_addresses(self.wallet.store.network)
+        } else {
+            &[]
+        };
+        let mut directories = vec![dns_addr];
+        for directory in self
+            .config
+            .extra_directory_servers
+            .iter()
+            .map(String::as_str)
+            .chain(bootstrap_directories.iter().copied())
+        {
+            if !directories.iter().any(|known| known == directory) {
+                directories.push(directory.to_string());
+            }
+        }
+        Ok((directories, socks_port))
+    }
+
+    /// The maker addresses of the offers published to the Nostr relays of the config.
+    #[cfg(feature = "nostr")]
+    fn relay_addresses(&self, socks_port: Option<u16>) -> Vec<MakerAddress> {
+        if self.config.nostr_relays.is_empty() {
+            return Vec::new();
+        }
+        log::info!(
+            "Fetching offers from Nostr relays: {}",
+            self.config.nostr_relays.join(", ")
+        );
+        fetch_offers_from_relays(
+            &self.config.nostr_relays,
+            self.wallet.store.network,
+            socks_port,
+        )
+        .into_iter()
+        .map(|offer| offer.address)
+        .collect()
+    }
+
+    #[cfg(not(feature = "nostr"))]
+    fn relay_addresses(&self, _socks_port: Option<u16>) -> Vec<MakerAddress> {
+        if !self.config.nostr_relays.is_empty() {
+            log::warn!(
+                "nostr_relays configured, but built without the nostr feature. Relays aren't read"
+            );
+        }
+        Vec::new()
+    }
+
+    /// Synchronizes the offer book with addresses obtained from directory servers and local configurations.
+    ///
+    /// With an offer book subscription, see [Taker::subscribe_offerbook], only the offers of the makers which
+    /// changed or expired are downloaded again.
+    pub fn sync_offerbook(&mut self) -> Result<(), TakerError> {
+        if let Some(subscription) = &self.offerbook_subscription {
+            match subscription.pending_updates() {
+                Some(updates) => return self.apply_offerbook_updates(&updates),
+                None => {
+                    log::warn!(
+                        "Offer book subscription to {} closed, resubscribing",
+                        subscription.directory
+                    );
+                    self.offerbook_subscription = None;
+                    match self.subscribe_offerbook() {
+                        Ok(()) => return Ok(()),
+                        Err(e) => log::warn!("Could not resubscribe to the offer book: {:?}", e),
+                    }
+                }
+            }
+        }
+        self.poll_offerbook()
+    }
+
+    /// Subscribes to the offer book updates of the first directory answering, and syncs the offer book in full.
+    /// Meant for long running takers: the following syncs only download the offers of the makers which changed
+    /// or expired, without asking the directories again. The makers only published to Nostr relays are found at
+    /// the full syncs, once the subscription drops.
+    pub fn subscribe_offerbook(&mut self) -> Result<(), TakerError> {
+        let (directories, socks_port) = self.directory_addresses()?;
+        // Subscribed before syncing, so that no change is missed in between.
+        let (addresses, subscription) = ask_directories(
+            &directories,
+            socks_port,
+            self.config.connection_type,
+            subscribe_to_directory,
+        )?;
+        log::info!(
+            "Subscribed to the offer book of {}, listing {} makers",
+            subscription.directory,
+            addresses.len()
+        );
+        self.poll_offerbook()?;
+        self.offerbook_subscription = Some(subscription);
+        Ok(())
+    }
 
-        let addresses_from_dns =
-            match fetch_addresses_from_dns(socks_port, dns_addr, self.config.connection_type) {
-                Ok(dns_addrs) => dns_addrs,
+    /// Downloads the offers of the makers changed since the last sync, or whose offer expired, and drops the
+    /// makers gone.
+    fn apply_offerbook_updates(&mut self, updates: &[OfferBookUpdate]) -> Result<(), TakerError> {
+        let (mut changed, left) = offerbook_changes(updates);
+        let now = unix_time();
+        for offer in &self.offerbook.all_makers {
+            if offer.offer.is_expired(now)
+                && !changed.contains(&offer.address)
+                && !left.contains(&offer.address)
+            {
+                changed.push(offer.address.clone());
+            }
+        }
+        log::info!(
+            "Offer book updates: {} makers changed or expired, {} left",
+            changed.len(),
+            left.len()
+        );
+
+        self.offerbook
+            .all_makers
+            .retain(|offer| !changed.contains(&offer.address) && !left.contains(&offer.address));
+        let offers = fetch_offer_from_makers(changed, &self.config)?;
+        self.add_verified_offers(offers);
+        self.update_cover_traffic_makers();
+        Ok(())
+    }
+
+    /// Synchronizes the offer book in full, asking the directories and the Nostr relays for the makers.
+    fn poll_offerbook(&mut self) -> Result<(), TakerError> {
+        let (directories, socks_port) = self.directory_addresses()?;
+
+        log::info!("Fetching addresses from DNS: {}", directories.join(", "));
+
+        let (mut addresses_from_dns, listed_bonds, directory_error) =
+            match fetch_addresses_from_directories(&directories, socks_port, &self.config) {
+                Ok((dns_addrs, listed_bonds)) => (dns_addrs, listed_bonds, None),
+                Err(e) if !self.config.nostr_relays.is_empty() => {
+                    log::warn!(
+                        "Could not connect to DNS Server, using Nostr relays: {:?}",
+                        e
+                    );
+                    (Vec::new(), HashMap::new(), Some(e))
+                }
                 Err(e) => {
                     log::error!("Could not connect to DNS Server: {:?}", e);
                     return Err(e);
                 }
             };
 
+        // The makers found on Nostr relays, some may be missing from the directories.
+        for address in self.relay_addresses(socks_port) {
+            if !addresses_from_dns.contains(&address) {
+                addresses_from_dns.push(address);
+            }
+        }
+        if let (true, Some(e)) = (addresses_from_dns.is_empty(), directory_error) {
+            return Err(e);
+        }
+
         // For now, ask offers from everyone,
         // Because we don not have any smart update mechanism, not asking again could cause problem.
         // if a maker changes their offer without changing tor address, the taker will not ask them again for updated offer.
@@ -2116,23 +3478,87 @@ impl Taker {
         // Further TODO: The Offer book needs to be restructured to store a unqiue value per fidelity bond. Similar to DNS.
         let offers = fetch_offer_from_makers(addresses_from_dns, &self.config)?;
 
+        // The offers are verified anyway, a bond other than the registered one only means a stale directory, or a
+        // maker moving its bond around.
+        for offer in &offers {
+            let offered = offer.offer.fidelity.bond.outpoint;
+            if let Some(listed) = listed_bonds
+                .get(&offer.address)
+                .filter(|listed| **listed != offered)
+            {
+                log::warn!(
+                    "Maker {} offers bond {}, but registered bond {} at the directories",
+                    offer.address,
+                    offered,
+                    listed
+                );
+            }
+        }
+
+        // Uptime records only help ranking the makers, the sync goes on without them.
+        let uptime = match ask_directories(
+            &directories,
+            socks_port,
+            self.config.connection_type,
+            fetch_uptime_from_dns,
+        ) {
+            Ok(uptime) => uptime,
+            Err(e) => {
+                log::warn!("Could not fetch maker uptime from DNS: {:?}", e);
+                Default::default()
+            }
+        };
+
         // TODO: Use better logic to update offerbook than to just rewrite everything.
-        self.offerbook = OfferBook::default();
+        self.offerbook = OfferBook {
+            uptime,
+            ..OfferBook::default()
+        };
+        self.add_verified_offers(offers);
+        self.update_cover_traffic_makers();
+        Ok(())
+    }
+
+    /// Adds the offers whose fidelity proof verifies to the offer book, the others to the bad makers.
+    fn add_verified_offers(&mut self, offers: Vec<OfferAndAddress>) {
+        // Makers sharing a bond can't be told apart from sybils, none of them is trusted.
+        let known_offers = self
+            .offerbook
+            .all_makers
+            .iter()
+            .chain(&offers)
+            .cloned()
+            .collect::<Vec<_>>();
+        let reused_bonds = reused_bonds(&known_offers);
 
         for offer in offers {
+            if reused_bonds.contains(&offer.offer.fidelity.bond.outpoint) {
+                log::warn!(
+                    "Fidelity bond {} is advertised by several makers. Adding this to bad maker list : {}",
+                    offer.offer.fidelity.bond.outpoint,
+                    offer.address
+                );
+                self.offerbook.add_bad_maker(&offer);
+                continue;
+            }
             log::info!(
                 "Found offer from {}. Verifying Fidelity Proof",
-                offer.address.to_string()
+                offer.address
             );
             log::debug!("{:?}", offer);
-            if let Err(e) = self
+            let verification = self
                 .wallet
                 .verify_fidelity_proof(&offer.offer.fidelity, &offer.address.to_string())
-            {
+                .and_then(|_| match &offer.offer.identity {
+                    Some(binding) => verify_identity_binding(binding, &offer.offer.fidelity.bond),
+                    None => Err(WalletError::General(
+                        "Offer without an identity bound to its fidelity bond".to_string(),
+                    )),
+                });
+            if let Err(e) = verification {
                 log::warn!(
                     "Fidelity Proof Verification failed with error: {:?}. Adding this to bad maker list : {}",
-                    e,
-                    offer.address.to_string()
+                    e, offer.address
                 );
                 self.offerbook.add_bad_maker(&offer);
             } else {
@@ -2140,42 +3566,289 @@ impl Taker {
                 self.offerbook.add_new_offer(&offer);
             }
         }
-        Ok(())
     }
 
     /// fetches only the offer data from DNS and returns the updated Offerbook.
     /// Used for taker cli app, in `fetch-offers` command.
     pub fn fetch_offers(&mut self) -> Result<&OfferBook, TakerError> {
-        self.tor_handle = self.setup_tor()?;
+        self.start_tor()?;
         self.sync_offerbook()?;
         Ok(&self.offerbook)
     }
 
+    /// Syncs the offer book, and takes a [MarketSnapshot] of it.
+    pub fn market_snapshot(&mut self) -> Result<MarketSnapshot, TakerError> {
+        self.fetch_offers()?;
+        Ok(MarketSnapshot::new(self.offerbook.all_good_makers()))
+    }
+
+    /// Fetches the latest [MarketSnapshot] taken by the first directory server answering. `None` if it hasn't
+    /// taken one yet.
+    pub fn fetch_market_snapshot(&mut self) -> Result<Option<MarketSnapshot>, TakerError> {
+        self.start_tor()?;
+        let (directories, socks_port) = self.directory_addresses()?;
+        ask_directories(
+            &directories,
+            socks_port,
+            self.config.connection_type,
+            fetch_market_snapshot_from_dns,
+        )
+    }
+
     /// Send any message to a maker
-    fn send_to_maker(
+    async fn send_to_maker(
         &self,
         maker_addr: &MakerAddress,
         msg: TakerToMakerMessage,
     ) -> Result<(), TakerError> {
         // Notify the maker that we are waiting for funding confirmation
-        let address = maker_addr.to_string();
-        let mut socket = match self.config.connection_type {
-            ConnectionType::CLEARNET => TcpStream::connect(address)?,
-            #[cfg(feature = "tor")]
-            ConnectionType::TOR => Socks5Stream::connect(
-                format!("127.0.0.1:{}", self.config.socks_port).as_str(),
-                address.as_str(),
-            )?
-            .into_inner(),
-        };
+        let peer = tracing::info_span!("peer", address = %maker_addr);
+        async {
+            let mut connection = MakerConnection::open(
+                maker_addr,
+                &self.config,
+                self.config.message_timeout(),
+                Some(&self.ongoing_swap_state.transcript),
+            )
+            .await?;
+            connection.send(&msg).await?;
+            log::info!("===> {} | {}", msg, maker_addr);
+
+            Ok(())
+        }
+        .instrument(peer)
+        .await
+    }
+}
 
-        let reconnect_timeout = Duration::from_secs(TCP_TIMEOUT_SECONDS);
+/// Starts Tor with its data in `data_dir`, when the Taker connects over Tor. Blocks until Tor is bootstrapped.
+#[cfg_attr(not(feature = "tor"), allow(unused_variables))]
+fn setup_tor(config: &TakerConfig, data_dir: &Path) -> Result<Option<Child>, TakerError> {
+    match config.connection_type {
+        ConnectionType::CLEARNET => Ok(None),
+        #[cfg(feature = "tor")]
+        ConnectionType::TOR => {
+            let tor_dir = data_dir.join("tor");
+            let tor_log_file = tor_dir.join("log");
+
+            // Hard error if previous log file can't be removed, as monitor_log_for_completion doesn't work with existing file.
+            // Tell the user to manually delete the file and restart.
+            if tor_log_file.exists() {
+                if let Err(e) = std::fs::remove_file(&tor_log_file) {
+                    log::error!(
+                    "Error removing previous tor log. Please delete the file and restart. | {:?}",
+                    tor_log_file
+                );
+                    return Err(e.into());
+                } else {
+                    log::info!("Previous tor log file deleted succesfully");
+                }
+            }
 
-        socket.set_write_timeout(Some(reconnect_timeout))?;
+            let handle = Some(crate::tor::spawn_tor(
+                config.socks_port,
+                config.network_port,
+                tor_dir.to_str().unwrap().to_owned(),
+            )?);
 
-        send_message(&mut socket, &msg)?;
-        log::info!("===> {} | {}", msg, maker_addr);
+            if let Err(e) =
+                monitor_log_for_completion(&tor_log_file, "Bootstrapped 100% (done): Done")
+            {
+                log::error!("Error monitoring taker log file. Try removing the tor log file {:?} and try again. | {}", tor_log_file, e);
+                return Err(e.into());
+            }
 
-        Ok(())
+            log::info!("tor is ready!");
+
+            Ok(handle)
+        }
+    }
+}
+
+/// Runs `future` to completion on a runtime of its own, blocking the thread.
+///
+/// A runtime can't be blocked on from within another one, so when called from a task of the caller's runtime,
+/// the future runs on a thread of its own.
+fn block_on<T: Send>(
+    future: impl Future<Output = Result<T, TakerError>> + Send,
+) -> Result<T, TakerError> {
+    let run = || {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?
+            .block_on(future)
+    };
+    if Handle::try_current().is_err() {
+        return run();
+    }
+    thread::scope(|scope| {
+        scope
+            .spawn(run)
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    })
+}
+
+/// Runs the blocking `f`, like the RPC calls to the wallet's node, without stalling the other tasks of the
+/// runtime. Work owning its inputs goes to [tokio::task::spawn_blocking] instead.
+///
+/// `f` borrows the Taker, so it can't move to the blocking threads. On a multi-threaded runtime the other tasks
+/// move off this worker thread while `f` runs. A current-thread runtime has no other worker to hand them to, so
+/// `f` runs in place: it's the runtime [Taker::do_coinswap] runs the swap alone on.
+fn blocking<T>(f: impl FnOnce() -> T) -> T {
+    match Handle::try_current() {
+        Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => block_in_place(f),
+        _ => f(),
+    }
+}
+
+/// Upper bound of the total fee of a swap: maker fees, plus the miner fees of the funding txs of all hops, which
+/// are all paid by the Taker.
+///
+/// The route is picked hop by hop while swapping, so the most expensive suitable makers of `offers` are assumed.
+fn max_swap_fee(offers: &[&Offer], swap_params: &SwapParams, refund_locktimes: &[u16]) -> Amount {
+    let send_amount = swap_params.send_amount.to_sat();
+    let max_locktime = refund_locktimes.first().copied().unwrap_or_default();
+    let mut offers = offers
+        .iter()
+        .copied()
+        .filter(|offer| send_amount >= offer.min_size && send_amount <= offer.max_size)
+        .collect::<Vec<_>>();
+    offers.sort_by_key(|offer| Reverse(offer.coinswap_fee(send_amount, max_locktime)));
+
+    let funding_fees = MINER_FEE * swap_params.tx_count as u64;
+    // The Taker's own funding txs.
+    let mut total_fee = funding_fees;
+    let mut amount = send_amount;
+    for (index, offer) in offers.iter().take(swap_params.maker_count).enumerate() {
+        let locktime = refund_locktimes.get(index).copied().unwrap_or_default();
+        let maker_fee = offer.coinswap_fee(amount, locktime);
+        // Each maker deducts its fee and the miner fees of its funding txs for the next hop.
+        total_fee += maker_fee + funding_fees;
+        amount = amount.saturating_sub(maker_fee + funding_fees);
+    }
+    Amount::from_sat(total_fee)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        protocol::{messages::FidelityProof, session::new_session_key},
+        wallet::FidelityBond,
+    };
+    use bitcoin::{
+        absolute::LockTime,
+        hashes::Hash,
+        secp256k1::{Message, Secp256k1},
+        OutPoint,
+    };
+
+    #[test]
+    fn test_block_on_within_runtime() {
+        assert_eq!(block_on(async { Ok(1) }).unwrap(), 1);
+
+        // From the tasks of either runtime flavor, without panicking on a nested runtime.
+        let current_thread = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        assert_eq!(
+            current_thread
+                .block_on(async { block_on(async { Ok(2) }) })
+                .unwrap(),
+            2
+        );
+        let multi_thread = tokio::runtime::Runtime::new().unwrap();
+        assert_eq!(
+            multi_thread
+                .block_on(async { blocking(|| block_on(async { Ok(3) })) })
+                .unwrap(),
+            3
+        );
+    }
+
+    fn offer(base_fee: u64, max_size: u64) -> Offer {
+        let (secret, pubkey) = new_session_key();
+        Offer {
+            base_fee,
+            amount_relative_fee_pct: 0.0,
+            time_relative_fee_pct: 0.0,
+            required_confirms: 1,
+            minimum_locktime: 20,
+            max_size,
+            min_size: 10_000,
+            tweakable_point: pubkey,
+            fidelity: FidelityProof {
+                bond: FidelityBond {
+                    outpoint: OutPoint::null(),
+                    amount: Amount::from_sat(50_000),
+                    lock_time: LockTime::from_height(500).unwrap(),
+                    pubkey,
+                    conf_height: 100,
+                    cert_expiry: 1,
+                },
+                cert_hash: bitcoin::hashes::sha256d::Hash::all_zeros(),
+                cert_sig: Secp256k1::new().sign_ecdsa(&Message::from_digest([3; 32]), &secret),
+            },
+            max_refund_locktime: 0,
+            min_contract_feerate: 0.0,
+            max_contract_feerate: 0.0,
+            expires_at: 0,
+            fee_tiers: Vec::new(),
+            identity: None,
+        }
+    }
+
+    #[test]
+    fn test_fee_budget() {
+        // The maker too small for the swap is left out of the route, the 2 most expensive others are in it.
+        let offers = [
+            offer(1000, 1_000_000),
+            offer(3000, 1_000_000),
+            offer(10_000, 100_000),
+            offer(2000, 1_000_000),
+        ];
+        let offers = offers.iter().collect::<Vec<_>>();
+        let mut swap_params = SwapParams {
+            send_amount: Amount::from_sat(500_000),
+            maker_count: 2,
+            tx_count: 3,
+            ..Default::default()
+        };
+        let expected = max_swap_fee(&offers, &swap_params, &[60, 40]);
+        // The funding txs of the Taker and both makers.
+        assert_eq!(expected, Amount::from_sat(3000 + 2000 + 9 * MINER_FEE));
+
+        // No budget.
+        swap_params.check_fee_budget(expected).unwrap();
+
+        swap_params.max_total_fee_sats = Some(expected.to_sat());
+        swap_params.check_fee_budget(expected).unwrap();
+        swap_params.max_total_fee_sats = Some(expected.to_sat() - 1);
+        match swap_params.check_fee_budget(expected) {
+            Err(TakerError::FeeBudgetExceeded {
+                expected: fee,
+                budget,
+            }) => {
+                assert_eq!(fee, expected);
+                assert_eq!(budget, expected - Amount::ONE_SAT);
+            }
+            other => panic!("Expected the fee budget to be exceeded, got {:?}", other),
+        }
+
+        // The smallest of the limits applies: 1% of the send amount is 5000 sats.
+        swap_params.max_total_fee_sats = None;
+        swap_params.max_fee_ppm = Some(10_000);
+        assert!(matches!(
+            swap_params.check_fee_budget(expected),
+            Err(TakerError::FeeBudgetExceeded { .. })
+        ));
+        swap_params.max_fee_ppm = Some(100_000);
+        swap_params.check_fee_budget(expected).unwrap();
+        swap_params.max_total_fee_sats = Some(expected.to_sat() - 1);
+        assert!(matches!(
+            swap_params.check_fee_budget(expected),
+            Err(TakerError::FeeBudgetExceeded { .. })
+        ));
     }
 }
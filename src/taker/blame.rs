@@ -0,0 +1,145 @@
+//! Attribution of premature contract broadcasts.
+//!
+//! Both parties of a hop hold its fully signed contract txs, so a contract tx on chain doesn't tell which of them
+//! broadcast it. But a maker broadcasting early broadcasts the contracts of both its hops at once, and the other
+//! makers only react to it later, once their watchtowers notice. The hops whose contracts were seen first are
+//! the ones of the culprit.
+//!
+//! The times a contract tx was first seen are read from the mempool entries of the Taker's node, and of the
+//! extra nodes of [TakerConfig::blame_observers](super::TakerConfig::blame_observers), taking the earliest.
+//! A node records the time it first received a tx in its mempool entry. Txs already confirmed fall back to the
+//! time the Taker noticed them.
+//!
+//! Each maker of the route is scored by the hops it is a party of, weighted by how early their contracts were
+//! seen. The Taker didn't broadcast, so a maker sharing a hop with the Taker gets the whole weight of the hop,
+//! and makers sharing a hop share its weight. The confidence of the attribution is the lead of the top scored
+//! maker over the runner-up, relative to its score. It feeds the ban score of the maker in the offer book, see
+//! [BAN_SCORE_THRESHOLD](super::offers::BAN_SCORE_THRESHOLD).
+
+use std::collections::HashMap;
+
+use bitcoin::Txid;
+use bitcoind::bitcoincore_rpc::{Auth, Client, RpcApi};
+
+use super::error::TakerError;
+use crate::wallet::WalletError;
+
+/// Seconds after the first broadcast a hop weighs half as much.
+const FIRST_SEEN_HALF_WEIGHT_SECS: f64 = 5.0;
+
+/// The blame of a premature broadcast, for one maker.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Blame {
+    /// Position of the maker in the route.
+    pub(crate) maker_index: usize,
+    /// Confidence the maker is the culprit, from 0 to 1.
+    pub(crate) confidence: f64,
+}
+
+/// Attributes a premature broadcast to the most likely maker of a route of `maker_count` makers.
+///
+/// `hops` are the contract txids of each hop, from the Taker's funding to the last hop paying the Taker. Hop `i`
+/// is sent by maker `i - 1` and received by maker `i`. `first_seen` are the times the broadcast contracts were
+/// first seen. None if no contract was seen.
+pub(crate) fn attribute_broadcast(
+    hops: &[Vec<Txid>],
+    first_seen: &HashMap<Txid, u64>,
+    maker_count: usize,
+) -> Option<Blame> {
+    let hop_times = hops
+        .iter()
+        .map(|txids| {
+            txids
+                .iter()
+                .filter_map(|txid| first_seen.get(txid).copied())
+                .min()
+        })
+        .collect::<Vec<_>>();
+    let first = hop_times.iter().flatten().copied().min()?;
+
+    let mut scores = vec![0f64; maker_count];
+    for (hop, time) in hop_times.iter().enumerate() {
+        let time = match time {
+            Some(time) => *time,
+            None => continue,
+        };
+        let weight = 1.0 / (1.0 + (time - first) as f64 / FIRST_SEEN_HALF_WEIGHT_SECS);
+        let parties = [hop.checked_sub(1), Some(hop)]
+            .iter()
+            .flatten()
+            .copied()
+            .filter(|maker| *maker < maker_count)
+            .collect::<Vec<_>>();
+        for maker in &parties {
+            scores[*maker] += weight / parties.len() as f64;
+        }
+    }
+
+    let mut ranked = scores.iter().copied().enumerate().collect::<Vec<_>>();
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+    let (maker_index, top) = ranked[0];
+    let runner_up = ranked.get(1).map(|(_, score)| *score).unwrap_or(0.0);
+    if top <= 0.0 {
+        return None;
+    }
+    Some(Blame {
+        maker_index,
+        confidence: (top - runner_up) / top,
+    })
+}
+
+/// Time a node first saw a tx, from its mempool entry. None if the tx isn't in its mempool.
+pub(crate) fn mempool_first_seen(rpc: &Client, txid: &Txid) -> Option<u64> {
+    rpc.get_mempool_entry(txid).ok().map(|entry| entry.time)
+}
+
+/// Connects to an observer node, given as `user:password@host:port`.
+pub(crate) fn observer_client(observer: &str) -> Result<Client, TakerError> {
+    let (auth, address) = match observer.rsplit_once('@') {
+        Some((credentials, address)) => {
+            let (user, password) = credentials.split_once(':').unwrap_or((credentials, ""));
+            (
+                Auth::UserPass(user.to_string(), password.to_string()),
+                address,
+            )
+        }
+        None => (Auth::None, observer),
+    };
+    Ok(Client::new(&format!("http://{}", address), auth).map_err(WalletError::Rpc)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::hashes::Hash;
+
+    use super::*;
+
+    fn txid(byte: u8) -> Txid {
+        Txid::from_byte_array([byte; 32])
+    }
+
+    #[test]
+    fn test_attribute_broadcast() {
+        // Taker -> Maker0 -> Maker1 -> Maker2 -> Taker.
+        let hops = (0..4).map(|hop| vec![txid(hop)]).collect::<Vec<_>>();
+
+        // Maker1 broadcasts its contracts, its neighbours react later.
+        let first_seen = HashMap::from([(txid(1), 100), (txid(2), 100), (txid(0), 130)]);
+        let blame = attribute_broadcast(&hops, &first_seen, 3).unwrap();
+        assert_eq!(blame.maker_index, 1);
+        assert!(blame.confidence > 0.3, "{:?}", blame);
+
+        // Maker0 shares its first hop with the Taker, who didn't broadcast.
+        let first_seen = HashMap::from([(txid(0), 100), (txid(1), 101)]);
+        let blame = attribute_broadcast(&hops, &first_seen, 3).unwrap();
+        assert_eq!(blame.maker_index, 0);
+        assert!(blame.confidence > 0.6, "{:?}", blame);
+
+        // A single hop between two makers can't be attributed.
+        let first_seen = HashMap::from([(txid(2), 100)]);
+        let blame = attribute_broadcast(&hops, &first_seen, 3).unwrap();
+        assert_eq!(blame.confidence, 0.0);
+
+        assert_eq!(attribute_broadcast(&hops, &HashMap::new(), 3), None);
+    }
+}
@@ -0,0 +1,232 @@
+//! Recovery bundles, for handing the recovery of a swap over to an always-on machine.
+//!
+//! [Taker::export_recovery_bundle] collects the fully signed contract txs of the unfinished swap coins
+//! of a swap, along with pre-signed claim txs paying back to the wallet, as a JSON [RecoveryBundle]:
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "swap_id": "3f2a0c9d1e5b7a46",
+//!   "network": "testnet4",
+//!   "exported_at_height": 81234,
+//!   "contracts": [
+//!     {
+//!       "role": "outgoing",
+//!       "contract_txid": "…",
+//!       "contract_tx": "<hex>",
+//!       "locktime": 60,
+//!       "contract_height": null,
+//!       "claim_tx": "<hex>",
+//!       "claim_delay": 60,
+//!       "claim_height": null,
+//!       "claim_deadline": null
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! Txs are hex encoded. Heights are absolute block heights, `null` while the contract tx isn't confirmed.
+//! A watcher recovers the swap by following these rules, the same the Taker follows in
+//! [Taker::recover_from_swap]:
+//! 1. Broadcast every `contract_tx` not yet on chain.
+//! 2. Once a contract tx confirms at height `h`, broadcast its `claim_tx` at `h + claim_delay`, that is
+//!    `claim_height`. Outgoing contracts are claimed by timelock after `locktime` blocks, incoming ones by
+//!    hashlock right away.
+//! 3. Incoming claims must confirm before `h + locktime`, the `claim_deadline`, when the sender can refund.
+//!
+//! The bundle is only meant for swaps which didn't complete: it holds no claim for finished swap coins.
+
+use bitcoin::{consensus::encode::serialize_hex, Transaction, Txid};
+use serde::{Deserialize, Serialize};
+
+use super::{api::Taker, error::TakerError};
+use crate::wallet::{SwapCoin, WalletSwapCoin};
+
+/// Version of the [RecoveryBundle] format.
+pub const RECOVERY_BUNDLE_VERSION: u32 = 1;
+
+/// Side of the swap a contract is on, for the Taker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContractRole {
+    /// Coins sent by the Taker, refunded by timelock.
+    Outgoing,
+    /// Coins received by the Taker, claimed by hashlock.
+    Incoming,
+}
+
+/// A contract of the swap, and the pre-signed tx claiming it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecoveryContract {
+    /// Side of the swap of the contract.
+    pub role: ContractRole,
+    /// Txid of the contract tx.
+    pub contract_txid: Txid,
+    /// Fully signed contract tx, hex encoded.
+    pub contract_tx: String,
+    /// Relative timelock of the contract, in blocks.
+    pub locktime: u16,
+    /// Height the contract tx confirmed at, if it did.
+    pub contract_height: Option<u64>,
+    /// Fully signed tx claiming the contract output to the wallet, hex encoded.
+    pub claim_tx: String,
+    /// Blocks after the confirmation of the contract tx the claim tx becomes valid.
+    pub claim_delay: u16,
+    /// Height the claim tx becomes valid at, once the contract tx is confirmed.
+    pub claim_height: Option<u64>,
+    /// Height the claim tx must confirm before, for incoming contracts, once the contract tx is confirmed.
+    pub claim_deadline: Option<u64>,
+}
+
+/// Everything needed to recover the coins of an unfinished swap, see the [module docs](self).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecoveryBundle {
+    /// Version of the format, [RECOVERY_BUNDLE_VERSION].
+    pub version: u32,
+    /// Id of the swap.
+    pub swap_id: String,
+    /// Network of the transactions.
+    pub network: String,
+    /// Block height at the time of the export.
+    pub exported_at_height: u64,
+    /// Contracts of the swap still to be recovered.
+    pub contracts: Vec<RecoveryContract>,
+}
+
+impl RecoveryContract {
+    fn new(
+        role: ContractRole,
+        contract_tx: &Transaction,
+        locktime: u16,
+        contract_height: Option<u64>,
+        claim_tx: &Transaction,
+    ) -> Self {
+        let claim_delay = match role {
+            ContractRole::Outgoing => locktime,
+            ContractRole::Incoming => 0,
+        };
+        Self {
+            role,
+            contract_txid: contract_tx.compute_txid(),
+            contract_tx: serialize_hex(contract_tx),
+            locktime,
+            contract_height,
+            claim_tx: serialize_hex(claim_tx),
+            claim_delay,
+            claim_height: contract_height.map(|h| h + claim_delay as u64),
+            claim_deadline: contract_height
+                .filter(|_| role == ContractRole::Incoming)
+                .map(|h| h + locktime as u64),
+        }
+    }
+}
+
+impl Taker {
+    /// Exports the pre-signed contract and claim txs of the unfinished swap coins of a swap, with the
+    /// heights to broadcast them at. See the [bundle format](crate::taker::bundle).
+    pub fn export_recovery_bundle(&mut self, swap_id: &str) -> Result<RecoveryBundle, TakerError> {
+        let hashvalue = self
+            .get_wallet()
+            .swap_hashvalue(swap_id)
+            .ok_or_else(|| TakerError::UnknownSwap(swap_id.to_string()))?;
+        let (incomings, outgoings) = self.get_wallet().find_unfinished_swapcoins();
+
+        let mut contracts = Vec::new();
+        for outgoing in outgoings {
+            if outgoing.get_hashvalue()? != hashvalue {
+                continue;
+            }
+            let contract_tx = outgoing.get_fully_signed_contract_tx()?;
+            let claim_tx = outgoing.create_timelock_spend(&self.next_claim_address()?)?;
+            contracts.push(RecoveryContract::new(
+                ContractRole::Outgoing,
+                &contract_tx,
+                outgoing.get_timelock()?,
+                self.confirmation_height(&contract_tx.compute_txid())?,
+                &claim_tx,
+            ));
+        }
+        for incoming in incomings {
+            if incoming.get_hashvalue()? != hashvalue {
+                continue;
+            }
+            let preimage = match incoming.hash_preimage {
+                Some(preimage) => preimage,
+                None => {
+                    log::warn!(
+                        "Preimage of incoming contract {} unknown, leaving it out of the bundle",
+                        incoming.contract_tx.compute_txid()
+                    );
+                    continue;
+                }
+            };
+            let contract_tx = incoming.get_fully_signed_contract_tx()?;
+            let claim_tx =
+                incoming.create_hashlock_spend(&self.next_claim_address()?, &preimage)?;
+            contracts.push(RecoveryContract::new(
+                ContractRole::Incoming,
+                &contract_tx,
+                incoming.get_timelock()?,
+                self.confirmation_height(&contract_tx.compute_txid())?,
+                &claim_tx,
+            ));
+        }
+        if contracts.is_empty() {
+            log::warn!("Swap {} has nothing left to recover", swap_id);
+        }
+
+        let wallet = self.get_wallet();
+        Ok(RecoveryBundle {
+            version: RECOVERY_BUNDLE_VERSION,
+            swap_id: swap_id.to_string(),
+            network: wallet.store.network.to_string(),
+            exported_at_height: wallet.chain.block_count()?,
+            contracts,
+        })
+    }
+
+    /// Height a tx confirmed at. None if it isn't confirmed, or unknown to the node.
+    pub(crate) fn confirmation_height(&self, txid: &Txid) -> Result<Option<u64>, TakerError> {
+        let chain = &self.get_wallet().chain;
+        match chain.tx_confirmations(txid)? {
+            Some(confirmations) if confirmations > 0 => {
+                Ok(Some(chain.block_count()? + 1 - confirmations as u64))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{absolute::LockTime, transaction::Version};
+
+    use super::*;
+
+    #[test]
+    fn test_recovery_contract_heights() {
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![],
+            output: vec![],
+        };
+
+        let outgoing = RecoveryContract::new(ContractRole::Outgoing, &tx, 60, Some(100), &tx);
+        assert_eq!(outgoing.claim_delay, 60);
+        assert_eq!(outgoing.claim_height, Some(160));
+        assert_eq!(outgoing.claim_deadline, None);
+
+        let incoming = RecoveryContract::new(ContractRole::Incoming, &tx, 20, Some(100), &tx);
+        assert_eq!(incoming.claim_height, Some(100));
+        assert_eq!(incoming.claim_deadline, Some(120));
+
+        let unconfirmed = RecoveryContract::new(ContractRole::Incoming, &tx, 20, None, &tx);
+        assert_eq!(unconfirmed.claim_height, None);
+        assert_eq!(unconfirmed.claim_deadline, None);
+
+        let json = serde_json::to_value(&outgoing).unwrap();
+        assert_eq!(json["role"], "outgoing");
+        assert_eq!(json["contract_tx"], serialize_hex(&tx));
+    }
+}
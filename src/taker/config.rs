@@ -3,8 +3,13 @@
 //!  Represents the configuration options for the Taker module, controlling behaviors
 //! such as refund locktime, connection attempts, sleep delays, and timeouts.
 
-use crate::utill::{get_taker_dir, parse_field, parse_toml, ConnectionType};
-use std::{io, io::Write, path::Path};
+use crate::protocol::ProtocolPhase;
+use crate::taker::api::{REFUND_LOCKTIME, REFUND_LOCKTIME_STEP};
+use crate::utill::{
+    get_taker_dir, ConfigMap, ConnectionType, DEFAULT_MAX_MESSAGE_SIZE, TESTNET4_DIRECTORY_ADDRESS,
+};
+use crate::wallet::WalletConfig;
+use std::{io, io::Write, path::Path, time::Duration};
 
 /// Taker configuration with refund, connection, and sleep settings.
 #[derive(Debug, Clone, PartialEq)]
@@ -15,8 +20,57 @@ pub struct TakerConfig {
     pub socks_port: u16,
     /// Directory server address (can be clearnet or onion)
     pub directory_server_address: String,
+    /// More directory servers, queried along with the one above.
+    pub extra_directory_servers: Vec<String>,
+    /// Directory servers which must answer for the offer book to sync. Makers listed by any of them are used.
+    pub directory_quorum: usize,
+    /// Whether to also query the directory servers bundled for the network, see [bootstrap_directory_addresses](crate::utill::bootstrap_directory_addresses).
+    pub bootstrap_directories: bool,
+    /// `ws://` or `wss://` Nostr relays whose maker offers are used along with the directories, with the `nostr` feature.
+    pub nostr_relays: Vec<String>,
     /// Connection type
     pub connection_type: ConnectionType,
+    /// Don't reuse makers from this many last completed swaps. 0 disables the rule.
+    pub recent_maker_exclusion: usize,
+    /// Makers whose address starts with any of these prefixes are never selected.
+    pub excluded_address_prefixes: Vec<String>,
+    /// Average seconds between cover traffic connections to random makers. 0 disables cover traffic.
+    pub cover_traffic_interval_secs: u64,
+    /// Maximum size of a message received from a maker, in bytes.
+    pub max_message_size: u32,
+    /// Settings of the wallet.
+    pub wallet: WalletConfig,
+    /// Highest feerate of the contract txs of a swap, in sats/vB.
+    pub max_contract_feerate: f64,
+    /// Refund locktime of the last hop, paying the Taker, in blocks.
+    pub refund_locktime: u16,
+    /// Blocks between the refund locktimes of two consecutive hops. Raised for a swap when too few makers accept it.
+    pub refund_locktime_step: u16,
+    /// Retries of a first connection to a maker, to fetch its offer or start a swap with it.
+    pub first_connect_attempts: u32,
+    /// Retries of a connection to a maker already in the swap.
+    pub reconnect_attempts: u32,
+    /// Seconds before the first retry.
+    pub retry_delay_secs: u64,
+    /// Factor the delay is multiplied by after each retry. 1 for a constant delay.
+    pub retry_backoff_factor: f64,
+    /// Longest delay between two retries, in seconds.
+    pub max_retry_delay_secs: u64,
+    /// Seconds of inactivity before a first connection to a maker times out.
+    pub connect_timeout_secs: u64,
+    /// Seconds of inactivity before a connection to a maker in the swap times out, outside of the phases below.
+    pub message_timeout_secs: u64,
+    /// Seconds waited on a maker sending its offer.
+    pub offer_fetch_timeout_secs: u64,
+    /// Seconds waited on a maker sending contract signatures.
+    pub contract_sigs_timeout_secs: u64,
+    /// Seconds waited for the funding txs of a hop to confirm, before recovering the swap.
+    pub funding_confirmation_timeout_secs: u64,
+    /// Seconds waited on a maker handing over its private keys after the preimage reveal.
+    pub preimage_reveal_timeout_secs: u64,
+    /// More nodes, as `user:password@host:port`, whose mempools tell when the contract txs broadcast before a
+    /// swap completed were first seen, to blame the maker who broadcast them.
+    pub blame_observers: Vec<String>,
 }
 
 impl Default for TakerConfig {
@@ -24,8 +78,11 @@ impl Default for TakerConfig {
         Self {
             network_port: 8000,
             socks_port: 19070,
-            directory_server_address:
-                "bhbzkndgad52ojm75w4goii7xsi6ou73fzyvorxas7swg2snlto4c4ad.onion:8080".to_string(),
+            directory_server_address: TESTNET4_DIRECTORY_ADDRESS.to_string(),
+            extra_directory_servers: Vec::new(),
+            directory_quorum: 1,
+            bootstrap_directories: true,
+            nostr_relays: Vec::new(),
             connection_type: {
                 #[cfg(feature = "tor")]
                 {
@@ -36,11 +93,48 @@ impl Default for TakerConfig {
                     ConnectionType::CLEARNET
                 }
             },
+            recent_maker_exclusion: 0,
+            excluded_address_prefixes: Vec::new(),
+            cover_traffic_interval_secs: 0,
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            wallet: WalletConfig::default(),
+            max_contract_feerate: 100.0,
+            refund_locktime: REFUND_LOCKTIME,
+            refund_locktime_step: REFUND_LOCKTIME_STEP,
+            first_connect_attempts: if cfg!(feature = "integration-test") {
+                10
+            } else {
+                5
+            },
+            reconnect_attempts: 10,
+            retry_delay_secs: 1,
+            retry_backoff_factor: 1.0,
+            max_retry_delay_secs: 5,
+            connect_timeout_secs: 30,
+            message_timeout_secs: 300,
+            offer_fetch_timeout_secs: 30,
+            contract_sigs_timeout_secs: 300,
+            funding_confirmation_timeout_secs: if cfg!(feature = "integration-test") {
+                10 * 60
+            } else {
+                3 * 60 * 60
+            },
+            preimage_reveal_timeout_secs: 300,
+            blame_observers: Vec::new(),
         }
     }
 }
 
 impl TakerConfig {
+    /// The Tor socks port, if connecting over Tor.
+    pub(crate) fn tor_socks_port(&self) -> Option<u16> {
+        match self.connection_type {
+            #[cfg(feature = "tor")]
+            ConnectionType::TOR => Some(self.socks_port),
+            ConnectionType::CLEARNET => None,
+        }
+    }
+
     /// Constructs a [TakerConfig] from a specified data directory. Or create default configs and load them.
     ///
     /// The maker(/taker).toml file should exist at the provided data-dir location.
@@ -66,24 +160,114 @@ impl TakerConfig {
             default_config.write_to_file(config_path)?;
         }
 
-        let config_map = parse_toml(config_path)?;
+        let config_map = ConfigMap::load(config_path, "taker_config", "COINSWAP_TAKER_")?;
 
         log::info!(
             "Successfully loaded config file from : {}",
             config_path.display()
         );
 
-        Ok(TakerConfig {
-            network_port: parse_field(config_map.get("network_port"), default_config.network_port),
-            socks_port: parse_field(config_map.get("socks_port"), default_config.socks_port),
-            directory_server_address: parse_field(
-                config_map.get("directory_server_address"),
+        let config = TakerConfig {
+            network_port: config_map.get("network_port", default_config.network_port)?,
+            socks_port: config_map.get("socks_port", default_config.socks_port)?,
+            directory_server_address: config_map.get(
+                "directory_server_address",
                 default_config.directory_server_address,
+            )?,
+            extra_directory_servers: config_map.get_list(
+                "extra_directory_servers",
+                default_config.extra_directory_servers,
             ),
-            connection_type: parse_field(
-                config_map.get("connection_type"),
-                default_config.connection_type,
+            directory_quorum: config_map
+                .get("directory_quorum", default_config.directory_quorum)?,
+            bootstrap_directories: config_map.get(
+                "bootstrap_directories",
+                default_config.bootstrap_directories,
+            )?,
+            nostr_relays: config_map.get_list("nostr_relays", default_config.nostr_relays),
+            connection_type: config_map.get("connection_type", default_config.connection_type)?,
+            recent_maker_exclusion: config_map.get(
+                "recent_maker_exclusion",
+                default_config.recent_maker_exclusion,
+            )?,
+            excluded_address_prefixes: config_map.get_list(
+                "excluded_address_prefixes",
+                default_config.excluded_address_prefixes,
             ),
+            cover_traffic_interval_secs: config_map.get(
+                "cover_traffic_interval_secs",
+                default_config.cover_traffic_interval_secs,
+            )?,
+            max_message_size: config_map
+                .get("max_message_size", default_config.max_message_size)?,
+            wallet: WalletConfig::from_config_map(&config_map)?,
+            max_contract_feerate: config_map
+                .get("max_contract_feerate", default_config.max_contract_feerate)?,
+            refund_locktime: config_map.get("refund_locktime", default_config.refund_locktime)?,
+            refund_locktime_step: config_map
+                .get("refund_locktime_step", default_config.refund_locktime_step)?,
+            first_connect_attempts: config_map.get(
+                "first_connect_attempts",
+                default_config.first_connect_attempts,
+            )?,
+            reconnect_attempts: config_map
+                .get("reconnect_attempts", default_config.reconnect_attempts)?,
+            retry_delay_secs: config_map
+                .get("retry_delay_secs", default_config.retry_delay_secs)?,
+            retry_backoff_factor: config_map
+                .get("retry_backoff_factor", default_config.retry_backoff_factor)?,
+            max_retry_delay_secs: config_map
+                .get("max_retry_delay_secs", default_config.max_retry_delay_secs)?,
+            connect_timeout_secs: config_map
+                .get("connect_timeout_secs", default_config.connect_timeout_secs)?,
+            message_timeout_secs: config_map
+                .get("message_timeout_secs", default_config.message_timeout_secs)?,
+            offer_fetch_timeout_secs: config_map.get(
+                "offer_fetch_timeout_secs",
+                default_config.offer_fetch_timeout_secs,
+            )?,
+            contract_sigs_timeout_secs: config_map.get(
+                "contract_sigs_timeout_secs",
+                default_config.contract_sigs_timeout_secs,
+            )?,
+            funding_confirmation_timeout_secs: config_map.get(
+                "funding_confirmation_timeout_secs",
+                default_config.funding_confirmation_timeout_secs,
+            )?,
+            preimage_reveal_timeout_secs: config_map.get(
+                "preimage_reveal_timeout_secs",
+                default_config.preimage_reveal_timeout_secs,
+            )?,
+            blame_observers: config_map.get_list("blame_observers", default_config.blame_observers),
+        };
+        config_map.reject_unknown_keys()?;
+        Ok(config)
+    }
+
+    /// Delay before the `attempt`th retry, counted from 1: growing by the backoff factor, up to the max delay.
+    pub(crate) fn retry_delay(&self, attempt: u32) -> Duration {
+        let factor = self.retry_backoff_factor.max(1.0);
+        let delay = self.retry_delay_secs as f64 * factor.powi(attempt.saturating_sub(1) as i32);
+        Duration::from_secs_f64(delay.min(self.max_retry_delay_secs as f64))
+    }
+
+    /// Timeout of the reads and writes of a first connection to a maker.
+    pub(crate) fn connect_timeout(&self) -> Duration {
+        Duration::from_secs(self.connect_timeout_secs)
+    }
+
+    /// Timeout of the reads and writes of a connection to a maker in the swap.
+    pub(crate) fn message_timeout(&self) -> Duration {
+        Duration::from_secs(self.message_timeout_secs)
+    }
+
+    /// How long a maker may stay silent in a phase of the protocol, or the funding txs may take to confirm.
+    pub(crate) fn phase_timeout(&self, phase: ProtocolPhase) -> Duration {
+        Duration::from_secs(match phase {
+            ProtocolPhase::OfferFetch => self.offer_fetch_timeout_secs,
+            ProtocolPhase::ContractSigs => self.contract_sigs_timeout_secs,
+            ProtocolPhase::FundingConfirmation => self.funding_confirmation_timeout_secs,
+            ProtocolPhase::PreimageReveal => self.preimage_reveal_timeout_secs,
         })
     }
 
@@ -93,8 +277,59 @@ impl TakerConfig {
             "network_port = {}
 socks_port = {}
 directory_server_address = {}
-connection_type = {:?}",
-            self.network_port, self.socks_port, self.directory_server_address, self.connection_type
+extra_directory_servers = {}
+directory_quorum = {}
+bootstrap_directories = {}
+nostr_relays = {}
+connection_type = {:?}
+recent_maker_exclusion = {}
+excluded_address_prefixes = {}
+cover_traffic_interval_secs = {}
+max_message_size = {}
+{}
+max_contract_feerate = {}
+refund_locktime = {}
+refund_locktime_step = {}
+first_connect_attempts = {}
+reconnect_attempts = {}
+retry_delay_secs = {}
+retry_backoff_factor = {}
+max_retry_delay_secs = {}
+connect_timeout_secs = {}
+message_timeout_secs = {}
+offer_fetch_timeout_secs = {}
+contract_sigs_timeout_secs = {}
+funding_confirmation_timeout_secs = {}
+preimage_reveal_timeout_secs = {}
+blame_observers = {}",
+            self.network_port,
+            self.socks_port,
+            self.directory_server_address,
+            self.extra_directory_servers.join(","),
+            self.directory_quorum,
+            self.bootstrap_directories,
+            self.nostr_relays.join(","),
+            self.connection_type,
+            self.recent_maker_exclusion,
+            self.excluded_address_prefixes.join(","),
+            self.cover_traffic_interval_secs,
+            self.max_message_size,
+            self.wallet.to_toml(),
+            self.max_contract_feerate,
+            self.refund_locktime,
+            self.refund_locktime_step,
+            self.first_connect_attempts,
+            self.reconnect_attempts,
+            self.retry_delay_secs,
+            self.retry_backoff_factor,
+            self.max_retry_delay_secs,
+            self.connect_timeout_secs,
+            self.message_timeout_secs,
+            self.offer_fetch_timeout_secs,
+            self.contract_sigs_timeout_secs,
+            self.funding_confirmation_timeout_secs,
+            self.preimage_reveal_timeout_secs,
+            self.blame_observers.join(","),
         );
         std::fs::create_dir_all(path.parent().expect("Path should NOT be root!"))?;
         let mut file = std::fs::File::create(path)?;
@@ -129,18 +364,35 @@ mod tests {
 
     #[test]
     fn test_valid_config() {
+        // Clearnet, to pass with or without the tor feature.
         let contents = r#"
         network_port = 8000
         socks_port = 19070
-        connection_type = "TOR"
-        rpc_port = 8081
+        connection_type = "CLEARNET"
         "#;
         let config_path = create_temp_config(contents, "valid_taker_config.toml");
-        let config = TakerConfig::new(Some(&config_path)).unwrap();
+        let config = TakerConfig::new(Some(&config_path));
         remove_temp_config(&config_path);
 
-        let default_config = TakerConfig::default();
-        assert_eq!(config, default_config);
+        let default_config = TakerConfig {
+            connection_type: ConnectionType::CLEARNET,
+            ..TakerConfig::default()
+        };
+        assert_eq!(config.unwrap(), default_config);
+    }
+
+    #[test]
+    fn test_unknown_key() {
+        let contents = r#"
+            [taker_config]
+            refund_lcoktime = 48
+        "#;
+        let config_path = create_temp_config(contents, "unknown_key_taker_config.toml");
+        let error = TakerConfig::new(Some(&config_path)).unwrap_err();
+        remove_temp_config(&config_path);
+
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().contains("`refund_lcoktime`"));
     }
 
     #[test]
@@ -154,7 +406,14 @@ mod tests {
         remove_temp_config(&config_path);
 
         assert_eq!(REFUND_LOCKTIME, 20);
-        assert_eq!(config, TakerConfig::default());
+        assert_eq!(config.refund_locktime, 48);
+        assert_eq!(
+            TakerConfig {
+                refund_locktime: REFUND_LOCKTIME,
+                ..config
+            },
+            TakerConfig::default()
+        );
     }
 
     #[test]
@@ -164,10 +423,11 @@ mod tests {
             refund_locktime = "not_a_number"
         "#;
         let config_path = create_temp_config(contents, "incorrect_type_taker_config.toml");
-        let config = TakerConfig::new(Some(&config_path)).unwrap();
+        let error = TakerConfig::new(Some(&config_path)).unwrap_err();
         remove_temp_config(&config_path);
 
-        assert_eq!(config, TakerConfig::default());
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().contains("`refund_locktime`"));
     }
 
     #[test]
@@ -189,6 +449,73 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_route_diversity_fields() {
+        let contents = r#"
+            recent_maker_exclusion = 3
+            excluded_address_prefixes = abc, "def",
+        "#;
+        let config_path = create_temp_config(contents, "route_diversity_taker_config.toml");
+        let config = TakerConfig::new(Some(&config_path)).unwrap();
+        remove_temp_config(&config_path);
+        assert_eq!(
+            TakerConfig {
+                recent_maker_exclusion: 3,
+                excluded_address_prefixes: vec!["abc".to_string(), "def".to_string()],
+                ..TakerConfig::default()
+            },
+            config
+        );
+    }
+
+    #[test]
+    fn test_retry_policy() {
+        let contents = r#"
+            reconnect_attempts = 20
+            retry_delay_secs = 2
+            retry_backoff_factor = 2
+            max_retry_delay_secs = 60
+        "#;
+        let config_path = create_temp_config(contents, "retry_policy_taker_config.toml");
+        let config = TakerConfig::new(Some(&config_path)).unwrap();
+        remove_temp_config(&config_path);
+
+        assert_eq!(config.reconnect_attempts, 20);
+        let delays = (1..=7)
+            .map(|attempt| config.retry_delay(attempt).as_secs())
+            .collect::<Vec<_>>();
+        assert_eq!(delays, vec![2, 4, 8, 16, 32, 60, 60]);
+
+        // The default delay is constant.
+        let default_config = TakerConfig::default();
+        assert_eq!(default_config.retry_delay(1), Duration::from_secs(1));
+        assert_eq!(default_config.retry_delay(10), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_phase_timeouts() {
+        let contents = r#"
+            contract_sigs_timeout_secs = 120
+            funding_confirmation_timeout_secs = 7200
+        "#;
+        let config_path = create_temp_config(contents, "phase_timeouts_taker_config.toml");
+        let config = TakerConfig::new(Some(&config_path)).unwrap();
+        remove_temp_config(&config_path);
+
+        assert_eq!(
+            config.phase_timeout(ProtocolPhase::ContractSigs),
+            Duration::from_secs(120)
+        );
+        assert_eq!(
+            config.phase_timeout(ProtocolPhase::FundingConfirmation),
+            Duration::from_secs(7200)
+        );
+        assert_eq!(
+            config.phase_timeout(ProtocolPhase::OfferFetch),
+            Duration::from_secs(30)
+        );
+    }
+
     #[test]
     fn test_missing_file() {
         let config_path = get_taker_dir().join("taker.toml");
@@ -0,0 +1,229 @@
+//! Async connections to the makers.
+//!
+//! The swap routines of the Taker talk to the makers over tokio sockets, so that a swap awaits the makers without
+//! blocking a thread. A [MakerConnection] makes the handshake, and sends and reads the later messages in the
+//! session it opened. Like the read and write timeouts of the blocking sockets, each read and write of a
+//! connection times out on its own.
+
+use std::{convert::TryFrom, future::Future, io, time::Duration};
+
+use bitcoin::secp256k1::SecretKey;
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    time::timeout,
+};
+
+use crate::{
+    error::NetError,
+    protocol::{
+        machine::{taker_transition, TakerAction},
+        messages::{MakerToTakerMessage, TakerToMakerMessage},
+        session::{Session, Transcript},
+        wire,
+    },
+    utill::ConnectionType,
+};
+
+use super::{
+    config::TakerConfig,
+    error::TakerError,
+    offers::MakerAddress,
+    routines::{awaited_reply, Handshake},
+};
+
+/// Size of the chunks a message is read in. The buffer only grows with the bytes actually received.
+const READ_CHUNK_SIZE: usize = 64 * 1024;
+
+/// A connection to a maker, with its session open.
+pub(crate) struct MakerConnection {
+    socket: TcpStream,
+    session: Session,
+    /// Secret of the session key sent in our hello.
+    session_secret: SecretKey,
+    timeout: Duration,
+    max_message_size: u32,
+}
+
+impl MakerConnection {
+    /// Connects to the maker at `address`, and makes the handshake. The messages of the session are recorded in
+    /// the `transcript` if given. Each read and write times out after `timeout`.
+    pub(crate) async fn open(
+        address: &MakerAddress,
+        config: &TakerConfig,
+        timeout: Duration,
+        transcript: Option<&Transcript>,
+    ) -> Result<Self, TakerError> {
+        let mut socket = connect(address, config, timeout).await?;
+        let handshake = Handshake::new();
+        let session_secret = handshake.session_secret();
+        let hello = wire::encode(handshake.hello())?;
+        timed(timeout, write_frame(&mut socket, &hello)).await?;
+        let reply = timed(timeout, read_frame(&mut socket, config.max_message_size)).await?;
+        let session = handshake.open_session(wire::decode(&reply)?, transcript)?;
+        Ok(Self {
+            socket,
+            session,
+            session_secret,
+            timeout,
+            max_message_size: config.max_message_size,
+        })
+    }
+
+    /// The session of the connection.
+    pub(crate) fn session(&self) -> &Session {
+        &self.session
+    }
+
+    /// Secret of the session key sent in our hello, countersigning the receipt of the maker.
+    pub(crate) fn session_secret(&self) -> &SecretKey {
+        &self.session_secret
+    }
+
+    /// Sends the next message of the session.
+    pub(crate) async fn send(&mut self, message: &TakerToMakerMessage) -> Result<(), TakerError> {
+        let bytes = self.session.encode(message)?;
+        timed(self.timeout, write_frame(&mut self.socket, &bytes)).await?;
+        Ok(())
+    }
+
+    /// Sends `request`, and reads the action upon the reply of the maker.
+    pub(crate) async fn request(
+        &mut self,
+        request: &TakerToMakerMessage,
+    ) -> Result<TakerAction, TakerError> {
+        self.send(request).await?;
+        let bytes = timed(
+            self.timeout,
+            read_frame(&mut self.socket, self.max_message_size),
+        )
+        .await?;
+        let reply: MakerToTakerMessage = self.session.decode(&bytes)?;
+        Ok(taker_transition(&awaited_reply(request), reply)?)
+    }
+}
+
+/// Connects to `address`, directly or through the Tor socks port of the config.
+async fn connect(
+    address: &MakerAddress,
+    config: &TakerConfig,
+    timeout: Duration,
+) -> Result<TcpStream, NetError> {
+    let address = address.to_string();
+    let socket = match config.connection_type {
+        ConnectionType::CLEARNET => timed(timeout, TcpStream::connect(address)).await?,
+        #[cfg(feature = "tor")]
+        ConnectionType::TOR => {
+            timed(timeout, async {
+                tokio_socks::tcp::Socks5Stream::connect(
+                    ("127.0.0.1", config.socks_port),
+                    address.as_str(),
+                )
+                .await
+                .map(|stream| stream.into_inner())
+                .map_err(|e| io::Error::new(io::ErrorKind::ConnectionRefused, e))
+            })
+            .await?
+        }
+    };
+    Ok(socket)
+}
+
+/// Runs the socket operation `io`, failing with [io::ErrorKind::TimedOut] if it takes longer than `duration`.
+async fn timed<T, E: From<io::Error>>(
+    duration: Duration,
+    io: impl Future<Output = Result<T, E>>,
+) -> Result<T, E> {
+    match timeout(duration, io).await {
+        Ok(result) => result,
+        Err(_) => Err(io::Error::new(io::ErrorKind::TimedOut, "maker connection timed out").into()),
+    }
+}
+
+/// Sends an encoded message, with its length prefix.
+async fn write_frame(socket: &mut TcpStream, bytes: &[u8]) -> Result<(), NetError> {
+    let length = u32::try_from(bytes.len()).map_err(|_| NetError::MessageTooLarge {
+        size: bytes.len() as u64,
+        limit: u32::MAX as u64,
+    })?;
+    let mut frame = Vec::with_capacity(bytes.len() + 4);
+    frame.extend(length.to_be_bytes());
+    frame.extend(bytes);
+    socket.write_all(&frame).await?;
+    socket.flush().await?;
+    Ok(())
+}
+
+/// Reads a length-prefixed message of at most `max_size` bytes. Larger frames are rejected from their length
+/// prefix, before reading the body.
+async fn read_frame(socket: &mut TcpStream, max_size: u32) -> Result<Vec<u8>, NetError> {
+    let mut length = [0u8; 4];
+    socket.read_exact(&mut length).await?;
+    let length = u32::from_be_bytes(length) as usize;
+    if length > max_size as usize {
+        return Err(NetError::MessageTooLarge {
+            size: length as u64,
+            limit: max_size as u64,
+        });
+    }
+
+    let mut buffer = Vec::with_capacity(length.min(READ_CHUNK_SIZE));
+    let mut chunk = vec![0u8; length.min(READ_CHUNK_SIZE)];
+    while buffer.len() < length {
+        let to_read = (length - buffer.len()).min(READ_CHUNK_SIZE);
+        match socket.read(&mut chunk[..to_read]).await? {
+            0 => return Err(NetError::ReachedEOF),
+            n => buffer.extend_from_slice(&chunk[..n]),
+        }
+    }
+    Ok(buffer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::{net::TcpListener, runtime::Builder};
+
+    /// Both ends of a local connection.
+    async fn socket_pair() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+        (client, server)
+    }
+
+    #[test]
+    fn test_frames() {
+        let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            let (mut client, mut server) = socket_pair().await;
+            let message = vec![7u8; READ_CHUNK_SIZE + 10];
+            write_frame(&mut client, &message).await.unwrap();
+            assert_eq!(read_frame(&mut server, u32::MAX).await.unwrap(), message);
+
+            // Too large frames are refused before their body is read.
+            write_frame(&mut client, &message).await.unwrap();
+            assert!(matches!(
+                read_frame(&mut server, 100).await,
+                Err(NetError::MessageTooLarge { size, limit: 100 }) if size == message.len() as u64
+            ));
+
+            // A silent maker times out.
+            let (mut client, mut server) = socket_pair().await;
+            let read = timed(Duration::from_millis(10), read_frame(&mut client, u32::MAX)).await;
+            assert!(matches!(read, Err(NetError::IO(e)) if e.kind() == io::ErrorKind::TimedOut));
+
+            // A frame cut short by the maker closing the connection.
+            server.write_all(&100u32.to_be_bytes()).await.unwrap();
+            server.write_all(&[1; 10]).await.unwrap();
+            drop(server);
+            assert!(matches!(
+                read_frame(&mut client, u32::MAX).await,
+                Err(NetError::ReachedEOF)
+            ));
+        });
+    }
+}
@@ -0,0 +1,141 @@
+//! Cover traffic for the Taker.
+//!
+//! Without it, the Taker only talks to makers right before a swap, so a network observer seeing a burst
+//! of connections can infer that a swap is about to happen. [CoverTraffic] runs in the background and,
+//! at randomized intervals, either fetches the offer of a random maker or just opens a connection to one,
+//! handshakes, and leaves it idle for a while before closing it.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering::Relaxed},
+        Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use bitcoin::secp256k1::rand::{rngs::OsRng, RngCore};
+
+use super::{
+    config::TakerConfig,
+    offers::MakerAddress,
+    routines::{connect_and_handshake_maker, download_maker_offer_attempt_once},
+};
+
+/// How often the shutdown flag is checked while waiting.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Background task generating decoy maker connections.
+pub(crate) struct CoverTraffic {
+    config: TakerConfig,
+    interval: Duration,
+    makers: Mutex<Vec<MakerAddress>>,
+    shutdown: AtomicBool,
+}
+
+impl CoverTraffic {
+    /// Creates the task. `interval` is the average time between two decoy connections.
+    pub(crate) fn new(config: TakerConfig, interval: Duration) -> Self {
+        Self {
+            config,
+            interval,
+            makers: Mutex::new(Vec::new()),
+            shutdown: AtomicBool::new(false),
+        }
+    }
+
+    /// Replaces the set of makers decoy connections are made to.
+    pub(crate) fn update_makers(&self, makers: Vec<MakerAddress>) {
+        *self
+            .makers
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = makers;
+    }
+
+    /// Runs until [CoverTraffic::stop] is called.
+    pub(crate) fn run(&self) {
+        log::info!("Starting cover traffic.");
+        while self.wait(jitter(self.interval)) {
+            let maker = match self.random_maker() {
+                Some(maker) => maker,
+                None => continue,
+            };
+            if OsRng.next_u32().is_multiple_of(2) {
+                log::debug!("Cover traffic: fetching offer from {}", maker);
+                if let Err(e) = download_maker_offer_attempt_once(&maker, &self.config) {
+                    log::debug!("Cover traffic offer fetch failed: {:?}", e);
+                }
+            } else {
+                log::debug!("Cover traffic: idle connection to {}", maker);
+                match connect_and_handshake_maker(&maker, &self.config) {
+                    // Keep the connection open for a while, as a real swap would.
+                    Ok(_connection) => {
+                        self.wait(jitter(self.interval / 4));
+                    }
+                    Err(e) => log::debug!("Cover traffic connection failed: {:?}", e),
+                }
+            }
+        }
+        log::info!("Cover traffic stopped.");
+    }
+
+    /// Stops the task.
+    pub(crate) fn stop(&self) {
+        self.shutdown.store(true, Relaxed);
+    }
+
+    fn random_maker(&self) -> Option<MakerAddress> {
+        let makers = self
+            .makers
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if makers.is_empty() {
+            return None;
+        }
+        Some(makers[OsRng.next_u32() as usize % makers.len()].clone())
+    }
+
+    // Sleeps for `duration`, returns false if the task was stopped meanwhile.
+    fn wait(&self, duration: Duration) -> bool {
+        let start = Instant::now();
+        while start.elapsed() < duration {
+            if self.shutdown.load(Relaxed) {
+                return false;
+            }
+            thread::sleep(POLL_INTERVAL.min(duration));
+        }
+        !self.shutdown.load(Relaxed)
+    }
+}
+
+/// Random duration between half and one and a half of `interval`.
+fn jitter(interval: Duration) -> Duration {
+    let millis = interval.as_millis() as u64;
+    if millis == 0 {
+        return interval;
+    }
+    Duration::from_millis(millis / 2 + OsRng.next_u64() % (millis + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cover_traffic_stop() {
+        let interval = Duration::from_secs(10);
+        for _ in 0..100 {
+            let delay = jitter(interval);
+            assert!(delay >= interval / 2 && delay <= interval * 3 / 2);
+        }
+
+        let cover = CoverTraffic::new(TakerConfig::default(), interval);
+        assert!(cover.random_maker().is_none());
+        cover.update_makers(vec![MakerAddress::new("127.0.0.1:6102").unwrap()]);
+        assert!(cover.random_maker().is_some());
+
+        cover.stop();
+        // Returns right away once stopped.
+        cover.run();
+    }
+}
@@ -0,0 +1,258 @@
+//! Querying several directory servers.
+//!
+//! The Taker asks its `directory_server_address` and each of its `extra_directory_servers` in parallel, and goes
+//! on once `directory_quorum` of them answer: a single directory being down or censored doesn't block swaps.
+//! The maker addresses of all the answering directories are merged. A directory can't make up makers, whose
+//! fidelity bonds are verified anyway, but it can hide some: makers missing from some directories are logged,
+//! to spot a censoring one.
+//!
+//! The directories list the makers by fidelity bond value, highest first, with the bonds they verified. An offer
+//! backed by another bond than the one its maker registered is flagged.
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::mpsc,
+    thread::{self, Builder},
+};
+
+use bitcoin::OutPoint;
+
+use crate::{market::listing::MakerListing, utill::ConnectionType};
+
+use super::{
+    config::TakerConfig,
+    error::TakerError,
+    offers::{fetch_maker_listings_from_dns, MakerAddress},
+};
+
+/// Fetches the maker listings of the directory servers in parallel, each retried up to the first connection
+/// attempts of the config. Returns the maker addresses, merged with [merge_directory_addresses], and the bonds the
+/// directories verified for them.
+pub(crate) fn fetch_addresses_from_directories(
+    directories: &[String],
+    socks_port: Option<u16>,
+    config: &TakerConfig,
+) -> Result<(Vec<MakerAddress>, HashMap<MakerAddress, OutPoint>), TakerError> {
+    let (results_writer, results_reader) = mpsc::channel::<(String, Option<Vec<MakerListing>>)>();
+    let mut thread_pool = Vec::new();
+    for directory in directories {
+        let results_writer = results_writer.clone();
+        let directory = directory.clone();
+        let config = config.clone();
+        let thread = Builder::new()
+            .name(format!("directory_fetch_thread_{}", directory))
+            .spawn(move || -> Result<(), TakerError> {
+                let listings = request_with_retries(&directory, socks_port, &config);
+                Ok(results_writer.send((directory, listings))?)
+            })?;
+        thread_pool.push(thread);
+    }
+
+    let mut results = Vec::new();
+    let mut listed_bonds = HashMap::new();
+    for _ in 0..directories.len() {
+        if let (directory, Some(listings)) = results_reader.recv()? {
+            let mut addresses = Vec::new();
+            for listing in listings {
+                let address = match MakerAddress::new(&listing.address) {
+                    Ok(address) => address,
+                    Err(e) => {
+                        log::warn!(
+                            "Directory {} lists an invalid maker address {}: {:?}",
+                            directory,
+                            listing.address,
+                            e
+                        );
+                        continue;
+                    }
+                };
+                if listing.bond.is_some() {
+                    listed_bonds
+                        .entry(address.clone())
+                        .or_insert(listing.bond_outpoint);
+                }
+                addresses.push(address);
+            }
+            results.push((directory, addresses));
+        }
+    }
+
+    for thread in thread_pool {
+        if let Err(e) = thread.join() {
+            log::error!("Error while joining thread: {:?}", e);
+        }
+    }
+
+    Ok((
+        merge_directory_addresses(results, config.directory_quorum)?,
+        listed_bonds,
+    ))
+}
+
+fn request_with_retries(
+    directory: &str,
+    socks_port: Option<u16>,
+    config: &TakerConfig,
+) -> Option<Vec<MakerListing>> {
+    let attempts = config.first_connect_attempts.max(1);
+    for attempt in 1..=attempts {
+        match fetch_maker_listings_from_dns(
+            socks_port,
+            directory.to_string(),
+            config.connection_type,
+        ) {
+            Ok(listings) => {
+                log::info!(
+                    "Directory {} lists {} makers, {} with a verified bond",
+                    directory,
+                    listings.len(),
+                    listings
+                        .iter()
+                        .filter(|listing| listing.bond.is_some())
+                        .count()
+                );
+                return Some(listings);
+            }
+            Err(e) => {
+                log::warn!(
+                    "Failed fetching listings from directory {}, attempt {}/{}: {:?}",
+                    directory,
+                    attempt,
+                    attempts,
+                    e
+                );
+                if attempt < attempts {
+                    thread::sleep(config.retry_delay(attempt));
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Merges the maker addresses of the directories which answered, in the order they list them, without duplicates.
+/// Fails if fewer than `quorum` directories answered.
+pub(crate) fn merge_directory_addresses(
+    results: Vec<(String, Vec<MakerAddress>)>,
+    quorum: usize,
+) -> Result<Vec<MakerAddress>, TakerError> {
+    let quorum = quorum.max(1);
+    if results.len() < quorum {
+        return Err(TakerError::DirectoryQuorum {
+            responded: results.len(),
+            quorum,
+        });
+    }
+
+    let mut listings = HashMap::<&MakerAddress, usize>::new();
+    let mut merged = Vec::new();
+    for (_, addresses) in &results {
+        for address in addresses.iter().collect::<HashSet<_>>() {
+            *listings.entry(address).or_default() += 1;
+        }
+        for address in addresses {
+            if !merged.contains(address) {
+                merged.push(address.clone());
+            }
+        }
+    }
+
+    // Cross check the directories: all of them should list the same makers.
+    for (directory, addresses) in &results {
+        let missing = merged
+            .iter()
+            .filter(|address| !addresses.contains(address))
+            .count();
+        if missing > 0 {
+            log::warn!(
+                "Directory {} doesn't list {} of the {} makers listed by the other directories",
+                directory,
+                missing,
+                merged.len()
+            );
+        }
+    }
+    for (address, count) in &listings {
+        if *count < results.len() {
+            log::info!(
+                "Maker {} is only listed by {} of {} directories",
+                address,
+                count,
+                results.len()
+            );
+        }
+    }
+
+    Ok(merged)
+}
+
+/// Asks the directories in turn, until one answers.
+pub(crate) fn ask_directories<T>(
+    directories: &[String],
+    socks_port: Option<u16>,
+    connection_type: ConnectionType,
+    ask: impl Fn(Option<u16>, String, ConnectionType) -> Result<T, TakerError>,
+) -> Result<T, TakerError> {
+    let mut last_error = TakerError::DirectoryQuorum {
+        responded: 0,
+        quorum: 1,
+    };
+    for directory in directories {
+        match ask(socks_port, directory.clone(), connection_type) {
+            Ok(answer) => return Ok(answer),
+            Err(e) => {
+                log::warn!("Directory {} didn't answer: {:?}", directory, e);
+                last_error = e;
+            }
+        }
+    }
+    Err(last_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addresses(addresses: &[&str]) -> Vec<MakerAddress> {
+        addresses
+            .iter()
+            .map(|address| MakerAddress::new(address).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_merge_directory_addresses() {
+        let results = vec![
+            (
+                "dir-a:8080".to_string(),
+                addresses(&["maker1:6102", "maker2:6102"]),
+            ),
+            (
+                "dir-b:8080".to_string(),
+                addresses(&["maker2:6102", "maker3:6102", "maker2:6102"]),
+            ),
+        ];
+
+        let merged = merge_directory_addresses(results.clone(), 2).unwrap();
+        assert_eq!(
+            merged,
+            addresses(&["maker1:6102", "maker2:6102", "maker3:6102"])
+        );
+
+        // A quorum of 0 still needs an answer.
+        assert!(matches!(
+            merge_directory_addresses(Vec::new(), 0),
+            Err(TakerError::DirectoryQuorum {
+                responded: 0,
+                quorum: 1
+            })
+        ));
+        assert!(matches!(
+            merge_directory_addresses(results, 3),
+            Err(TakerError::DirectoryQuorum {
+                responded: 2,
+                quorum: 3
+            })
+        ));
+    }
+}
@@ -0,0 +1,208 @@
+//! Dispute bundles, evidence of what happened in a swap which didn't complete.
+//!
+//! When a swap aborts, the Taker saves a [DisputeBundle] in the `disputes` directory of its data directory, encrypted
+//! like the swap state of the wallet file if the wallet has a passphrase, see
+//! [WALLET_PASSPHRASE_ENV](crate::wallet::WALLET_PASSPHRASE_ENV). [Taker::export_dispute_bundle] exports it as JSON,
+//! for a third party to inspect:
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "swap_id": "3f2a0c9d1e5b7a46",
+//!   "network": "regtest",
+//!   "aborted_at": 1718000000,
+//!   "makers": [
+//!     { "address": "…:6102", "identity": "02…", "bond_outpoint": "…:0" }
+//!   ],
+//!   "transcript": [
+//!     {
+//!       "at": 1717999990,
+//!       "session": "…",
+//!       "maker_identity": "02…",
+//!       "direction": "sent",
+//!       "sequence": 0,
+//!       "message_hash": "…"
+//!     }
+//!   ],
+//!   "observations": [
+//!     { "at": 1717999995, "txid": "…", "kind": { "funding": { "confirmations": 0 } } }
+//!   ],
+//!   "transactions": [
+//!     { "kind": "contract", "txid": "…", "tx": "<hex>", "height": 120 }
+//!   ],
+//!   "receipts": []
+//! }
+//! ```
+//!
+//! The transcript holds the hashes of the messages exchanged with each maker, in their sealed envelopes. The
+//! messages themselves aren't kept: they hold the private keys and nonces of the swap. Either party can reveal a
+//! message later, and anyone can check it against its hash, its session and its sequence number. The
+//! observations are the events of the swap txs seen on the node while waiting for the funding, and the
+//! transactions are the funding and contract txs of the swap, with their height at the time of the abort.
+
+use std::{
+    fs::{create_dir_all, read, write},
+    path::PathBuf,
+};
+
+use bitcoin::{OutPoint, PublicKey, Txid};
+use serde::{Deserialize, Serialize};
+
+use super::{api::Taker, error::TakerError};
+use crate::protocol::{messages::SignedReceipt, session::TranscriptEntry};
+
+/// Extension of the dispute bundles encrypted under the wallet passphrase.
+const SEALED_EXTENSION: &str = "sealed";
+
+/// Version of the [DisputeBundle] format.
+pub const DISPUTE_BUNDLE_VERSION: u32 = 1;
+
+/// What was seen of a swap tx on the node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ObservationKind {
+    /// A funding tx, with its confirmations. 0 while in the mempool.
+    Funding {
+        /// Confirmations of the tx.
+        confirmations: u32,
+    },
+    /// A funding tx seen before, replaced, double spent or evicted since.
+    FundingDropped,
+    /// A contract tx broadcast before the swap completed.
+    ContractBroadcast,
+}
+
+/// A swap tx seen on the node, at some time.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChainObservation {
+    /// Unix timestamp of the observation.
+    pub at: u64,
+    /// Txid of the tx.
+    pub txid: Txid,
+    /// What was seen.
+    pub kind: ObservationKind,
+}
+
+/// A maker of the route of the swap.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DisputedMaker {
+    /// Address of the maker.
+    pub address: String,
+    /// Identity key of the maker, bound to its fidelity bond in its offer.
+    pub identity: Option<PublicKey>,
+    /// Outpoint of the fidelity bond of the maker.
+    pub bond_outpoint: OutPoint,
+}
+
+/// Kind of a tx of the swap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EvidenceTxKind {
+    /// A funding tx of a hop.
+    Funding,
+    /// A contract tx of a hop.
+    Contract,
+}
+
+/// A tx of the swap.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EvidenceTx {
+    /// Kind of the tx.
+    pub kind: EvidenceTxKind,
+    /// Txid of the tx.
+    pub txid: Txid,
+    /// The tx, hex encoded. Contract txs may miss the signatures of the peers.
+    pub tx: String,
+    /// Height the tx confirmed at, at the time of the abort. None if it wasn't confirmed.
+    pub height: Option<u64>,
+}
+
+/// Evidence of an aborted swap, see the [module docs](self).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DisputeBundle {
+    /// Version of the format, [DISPUTE_BUNDLE_VERSION].
+    pub version: u32,
+    /// Id of the swap.
+    pub swap_id: String,
+    /// Network of the transactions.
+    pub network: String,
+    /// Unix timestamp of the abort.
+    pub aborted_at: u64,
+    /// Makers of the route, in route order.
+    pub makers: Vec<DisputedMaker>,
+    /// Hashes of the messages exchanged with the makers.
+    pub transcript: Vec<TranscriptEntry>,
+    /// Events of the swap txs seen on the node.
+    pub observations: Vec<ChainObservation>,
+    /// Funding and contract txs of the swap.
+    pub transactions: Vec<EvidenceTx>,
+    /// Receipts of the makers settled before the abort.
+    pub receipts: Vec<SignedReceipt>,
+}
+
+impl Taker {
+    /// Exports the dispute bundle saved when a swap aborted. See the [bundle format](crate::taker::dispute).
+    pub fn export_dispute_bundle(&self, swap_id: &str) -> Result<DisputeBundle, TakerError> {
+        let sealed_path = self.dispute_bundle_path(swap_id, SEALED_EXTENSION);
+        if sealed_path.exists() {
+            let json = self.get_wallet().unseal(&read(sealed_path)?)?;
+            return Ok(serde_json::from_slice(&json)?);
+        }
+        let path = self.dispute_bundle_path(swap_id, "json");
+        if !path.exists() {
+            return Err(TakerError::UnknownSwap(swap_id.to_string()));
+        }
+        Ok(serde_json::from_slice(&read(path)?)?)
+    }
+
+    /// Saves the dispute bundle of the ongoing swap.
+    pub(crate) fn save_dispute_bundle(&self) -> Result<(), TakerError> {
+        let bundle = self.dispute_bundle()?;
+        let json = serde_json::to_vec_pretty(&bundle)?;
+        let (path, content) = match self.get_wallet().seal(&json)? {
+            Some(sealed) => (
+                self.dispute_bundle_path(&bundle.swap_id, SEALED_EXTENSION),
+                sealed,
+            ),
+            None => (self.dispute_bundle_path(&bundle.swap_id, "json"), json),
+        };
+        if let Some(dir) = path.parent() {
+            create_dir_all(dir)?;
+        }
+        write(&path, content)?;
+        log::info!("Saved the dispute bundle of the swap at {}", path.display());
+        Ok(())
+    }
+
+    fn dispute_bundle_path(&self, swap_id: &str, extension: &str) -> PathBuf {
+        self.data_dir()
+            .join("disputes")
+            .join(format!("{}.{}", swap_id, extension))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::hashes::Hash;
+
+    use super::*;
+
+    #[test]
+    fn test_observation_json() {
+        let observation = ChainObservation {
+            at: 100,
+            txid: Txid::from_byte_array([1; 32]),
+            kind: ObservationKind::Funding { confirmations: 2 },
+        };
+        let json = serde_json::to_value(&observation).unwrap();
+        assert_eq!(json["kind"]["funding"]["confirmations"], 2);
+        assert_eq!(
+            serde_json::to_value(ObservationKind::ContractBroadcast).unwrap(),
+            "contract_broadcast"
+        );
+        assert_eq!(
+            serde_json::from_value::<ChainObservation>(json).unwrap(),
+            observation
+        );
+    }
+}
@@ -0,0 +1,85 @@
+//! Dry runs of swaps, stopping before anything is broadcast.
+//!
+//! [Taker::dry_run_coinswap] goes through the start of [Taker::do_coinswap]: it fetches the offers, picks the
+//! route and prices it like [Taker::quote], then builds and signs the funding txs of the first hop and gets the
+//! first maker's signatures on their contract txs. It stops there, before the funding txs are broadcast, and
+//! returns the txs the swap would have broadcast. Nothing is saved in the wallet.
+//!
+//! The later hops are funded by the makers, only once the funding txs of the previous hop confirm, so their txs
+//! can't be built without broadcasting. Their fees and locktimes are in the quote.
+
+use bitcoin::{consensus::encode::serialize_hex, Transaction, Txid};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    api::{SwapParams, Taker},
+    error::TakerError,
+    quote::SwapQuote,
+};
+
+/// A tx the swap would have broadcast.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DryRunTx {
+    /// Txid of the tx.
+    pub txid: Txid,
+    /// The tx, hex encoded.
+    pub tx: String,
+}
+
+impl From<&Transaction> for DryRunTx {
+    fn from(tx: &Transaction) -> Self {
+        Self {
+            txid: tx.compute_txid(),
+            tx: serialize_hex(tx),
+        }
+    }
+}
+
+/// Outcome of a dry run, see the [module docs](self).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DryRun {
+    /// Route and fees of the swap.
+    pub quote: SwapQuote,
+    /// Address of the maker which signed the contracts of the first hop.
+    pub first_maker: String,
+    /// Signed funding txs of the first hop.
+    pub funding_txs: Vec<DryRunTx>,
+    /// Miner fees of the funding txs, in sats.
+    pub funding_fee_sats: u64,
+    /// Contract txs of the first hop, signed by the Taker and the first maker. Only broadcast on recovery.
+    pub contract_txs: Vec<DryRunTx>,
+}
+
+impl Taker {
+    /// Runs a swap up to its first broadcast, and returns the txs it would have broadcast. See the
+    /// [module docs](crate::taker::dry_run).
+    pub fn dry_run_coinswap(&mut self, swap_params: SwapParams) -> Result<DryRun, TakerError> {
+        self.start_tor()?;
+        let quote = self.quote(swap_params)?;
+        self.dry_run_first_hop(swap_params, quote)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{absolute::LockTime, transaction::Version, TxIn};
+
+    use super::*;
+
+    #[test]
+    fn test_dry_run_tx() {
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            // Without inputs, the tx would be serialized with the segwit marker.
+            input: vec![TxIn::default()],
+            output: vec![],
+        };
+        let dry_run_tx = DryRunTx::from(&tx);
+        assert_eq!(dry_run_tx.txid, tx.compute_txid());
+        assert_eq!(
+            dry_run_tx.tx,
+            format!("0200000001{}ffffffff00ffffffff0000000000", "00".repeat(32))
+        );
+    }
+}
@@ -1,9 +1,13 @@
 //! All Taker-related errors.
 use crate::{
-    error::NetError, market::directory::DirectoryServerError, protocol::error::ProtocolError,
+    error::{ErrorCode, NetError},
+    protocol::error::ProtocolError,
     wallet::WalletError,
 };
 
+#[cfg(feature = "directory")]
+use crate::market::directory::DirectoryServerError;
+
 /// Represents errors that can occur during Taker operations.
 ///
 /// This enum covers a range of errors related to I/O, wallet operations, network communication,
@@ -20,6 +24,7 @@ pub enum TakerError {
     /// Error related to wallet operations.
     Wallet(WalletError),
     /// Error encountered during interaction with the directory server.
+    #[cfg(feature = "directory")]
     Directory(DirectoryServerError),
     /// Error related to network operations.
     Net(NetError),
@@ -27,14 +32,83 @@ pub enum TakerError {
     SendAmountNotSet,
     /// Error indicating a timeout while waiting for the funding transaction.
     FundingTxWaitTimeOut,
+    /// Error indicating a funding transaction was replaced or double spent before confirming.
+    FundingTxDoubleSpent(bitcoin::Txid),
     /// Error deserializing data, typically related to CBOR-encoded data.
     Deserialize(String),
+    /// Error indicating the expected swap fee is above the fee budget of the [SwapParams](super::SwapParams).
+    FeeBudgetExceeded {
+        /// Expected total fee of the swap.
+        expected: bitcoin::Amount,
+        /// Fee budget of the swap.
+        budget: bitcoin::Amount,
+    },
+    /// Error indicating the swap would leave a dust remainder in the wallet.
+    DustRemainder {
+        /// Wallet balance left after the swap.
+        remainder: bitcoin::Amount,
+        /// Dust threshold of the wallet.
+        threshold: bitcoin::Amount,
+    },
+    /// Error indicating no swap with this id is known to the wallet.
+    UnknownSwap(String),
+    /// Error indicating a hop of the swap leaves too few blocks to claim its coins before they can be refunded.
+    UnsafeLocktime {
+        /// Index of the hop, the last one is the Taker's.
+        hop: usize,
+        /// Claim window of the hop, in blocks.
+        window: u16,
+        /// Smallest safe claim window, in blocks.
+        minimum: u16,
+    },
+    /// Error indicating fewer directory servers answered than the `directory_quorum` of the config.
+    DirectoryQuorum {
+        /// Directory servers which answered.
+        responded: usize,
+        /// Directory servers required to answer.
+        quorum: usize,
+    },
     /// Error indicating an MPSC channel failure.
     ///
     /// This error occurs during internal thread communication.
     MPSC(String),
 }
 
+impl ErrorCode for TakerError {
+    fn code(&self) -> u16 {
+        match self {
+            Self::IO(_) => 6000,
+            Self::ContractsBroadcasted(_) => 6001,
+            Self::NotEnoughMakersInOfferBook => 6002,
+            Self::Wallet(e) => e.code(),
+            #[cfg(feature = "directory")]
+            Self::Directory(e) => e.code(),
+            Self::Net(e) => e.code(),
+            Self::SendAmountNotSet => 6003,
+            Self::FundingTxWaitTimeOut => 6004,
+            Self::FundingTxDoubleSpent(_) => 6005,
+            Self::Deserialize(_) => 6006,
+            Self::FeeBudgetExceeded { .. } => 6007,
+            Self::DustRemainder { .. } => 6008,
+            Self::UnknownSwap(_) => 6009,
+            Self::UnsafeLocktime { .. } => 6010,
+            Self::DirectoryQuorum { .. } => 6011,
+            Self::MPSC(_) => 6012,
+        }
+    }
+
+    fn is_transient(&self) -> bool {
+        match self {
+            Self::IO(_) | Self::NotEnoughMakersInOfferBook | Self::DirectoryQuorum { .. } => true,
+            Self::Wallet(e) => e.is_transient(),
+            #[cfg(feature = "directory")]
+            Self::Directory(e) => e.is_transient(),
+            Self::Net(e) => e.is_transient(),
+            _ => false,
+        }
+    }
+}
+
 impl From<serde_cbor::Error> for TakerError {
     fn from(value: serde_cbor::Error) -> Self {
         Self::Deserialize(value.to_string())
@@ -53,6 +127,7 @@ impl From<WalletError> for TakerError {
     }
 }
 
+#[cfg(feature = "directory")]
 impl From<DirectoryServerError> for TakerError {
     fn from(value: DirectoryServerError) -> Self {
         Self::Directory(value)
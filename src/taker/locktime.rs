@@ -0,0 +1,142 @@
+//! Refund locktimes of the hops of a swap.
+//!
+//! The contracts of a swap are locked with decreasing refund locktimes: the Taker's funding txs have the
+//! highest, the last hop, paying the Taker, the lowest. The difference between the incoming and outgoing
+//! locktime of a maker is its claim window: once its outgoing coins are claimed by hashlock, it has that
+//! many blocks to claim its incoming coins the same way, before their sender can take them back by refund.
+//!
+//! Makers advertise the smallest window they accept, and the highest locktime they accept on their
+//! incoming coins. The Taker spaces the hops by its configured `refund_locktime_step`, raised when needed
+//! to a window enough makers of the offerbook accept, and only routes through makers accepting their hop.
+//!
+//! Before funding, the Taker validates the whole ladder: the locktimes must decrease by at least
+//! [MIN_SAFE_CLAIM_WINDOW] at each hop, and its own claim window on the last hop must leave room for the
+//! contract and claim txs to confirm at the contract feerate, by conservative estimates, with the same margin.
+
+use super::error::TakerError;
+use crate::protocol::error::ProtocolError;
+
+/// Fewest blocks any party of a swap is left to claim its incoming coins. Windows below this are refused.
+pub(crate) const MIN_SAFE_CLAIM_WINDOW: u16 = 10;
+
+/// The window between hops: the configured one, raised to the smallest window accepted by `maker_count`
+/// of the makers, given the smallest windows they each accept.
+pub(crate) fn pick_locktime_step(
+    configured: u16,
+    mut accepted_windows: Vec<u16>,
+    maker_count: usize,
+) -> u16 {
+    accepted_windows.sort_unstable();
+    accepted_windows
+        .get(maker_count.saturating_sub(1))
+        .map_or(configured, |&window| window.max(configured))
+}
+
+/// Refund locktimes of every hop, from the Taker's funding txs to the last hop, which is locked for
+/// `last_locktime` blocks. `steps[i]` is the claim window of the i-th maker of the route.
+pub(crate) fn refund_locktime_ladder(
+    last_locktime: u16,
+    steps: &[u16],
+) -> Result<Vec<u16>, TakerError> {
+    let mut locktimes = vec![last_locktime];
+    for step in steps.iter().rev() {
+        let next = locktimes
+            .last()
+            .and_then(|locktime| locktime.checked_add(*step))
+            .ok_or(ProtocolError::General("Refund locktime overflow"))?;
+        locktimes.push(next);
+    }
+    locktimes.reverse();
+    Ok(locktimes)
+}
+
+/// Validates the refund locktimes of a swap, from the Taker's funding txs to the last hop.
+///
+/// Every party must be left a safe claim window: each maker between its incoming and outgoing refund
+/// locktimes, which keeps the ladder decreasing, and the Taker on the last hop. `confirm_blocks` is the
+/// number of blocks the contract txs are expected to take to confirm, when known. The Taker's window must
+/// cover it on top of the minimum.
+pub(crate) fn check_timelock_ladder(
+    locktimes: &[u16],
+    confirm_blocks: Option<u16>,
+) -> Result<(), TakerError> {
+    let windows = locktimes
+        .windows(2)
+        .map(|pair| pair[0].saturating_sub(pair[1]))
+        .chain(locktimes.last().copied());
+    let last_hop = locktimes.len().saturating_sub(1);
+    for (hop, window) in windows.enumerate() {
+        let minimum = if hop == last_hop {
+            confirm_blocks
+                .unwrap_or(0)
+                .saturating_add(MIN_SAFE_CLAIM_WINDOW)
+        } else {
+            MIN_SAFE_CLAIM_WINDOW
+        };
+        if window < minimum {
+            log::error!(
+                "Hop {} leaves a claim window of {} blocks, below the minimum of {}",
+                hop,
+                window,
+                minimum
+            );
+            return Err(TakerError::UnsafeLocktime {
+                hop,
+                window,
+                minimum,
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_refund_locktimes() {
+        assert_eq!(pick_locktime_step(20, vec![], 2), 20);
+        assert_eq!(pick_locktime_step(20, vec![10, 30, 15], 2), 20);
+        // Only one maker accepts 20 blocks.
+        assert_eq!(pick_locktime_step(20, vec![10, 30, 25], 2), 25);
+
+        let locktimes = refund_locktime_ladder(20, &[20, 20]).unwrap();
+        assert_eq!(locktimes, vec![60, 40, 20]);
+        assert!(check_timelock_ladder(&locktimes, None).is_ok());
+        assert!(check_timelock_ladder(&locktimes, Some(6)).is_ok());
+        assert!(refund_locktime_ladder(20, &[u16::MAX]).is_err());
+
+        let locktimes = refund_locktime_ladder(20, &[20, 5]).unwrap();
+        assert!(matches!(
+            check_timelock_ladder(&locktimes, None),
+            Err(TakerError::UnsafeLocktime {
+                hop: 1,
+                window: 5,
+                ..
+            })
+        ));
+        assert!(matches!(
+            check_timelock_ladder(&[40, 20, 5], None),
+            Err(TakerError::UnsafeLocktime { hop: 2, .. })
+        ));
+        // Not decreasing.
+        assert!(matches!(
+            check_timelock_ladder(&[20, 40, 20], None),
+            Err(TakerError::UnsafeLocktime {
+                hop: 0,
+                window: 0,
+                ..
+            })
+        ));
+        // The contract txs would take too long to confirm for the Taker to claim in time.
+        assert!(matches!(
+            check_timelock_ladder(&[60, 40, 20], Some(12)),
+            Err(TakerError::UnsafeLocktime {
+                hop: 2,
+                window: 20,
+                minimum: 22,
+            })
+        ));
+    }
+}
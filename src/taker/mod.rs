@@ -5,11 +5,38 @@
 //! protocol workflow is described in the [protocol between takers and makers](https://github.com/citadel-tech/Coinswap-Protocol-Specification/blob/main/v1/3_protocol-flow.md)
 
 pub mod api;
+mod blame;
+pub mod bundle;
 mod config;
+mod connection;
+mod cover;
+mod directories;
+pub mod dispute;
+pub mod dry_run;
 pub mod error;
+mod locktime;
 pub(crate) mod offers;
-mod routines;
+pub mod privacy;
+pub mod quote;
+mod report;
+pub(crate) mod routines;
+mod scheduler;
+mod subscription;
+#[cfg(feature = "tui")]
+mod tui;
 
 pub use self::api::TakerBehavior;
-pub use api::{SwapParams, Taker};
+pub use crate::protocol::session::{MessageDirection, TranscriptEntry};
+pub use api::{AmountDistribution, SwapParams, Taker};
+pub use bundle::{ContractRole, RecoveryBundle, RecoveryContract};
 pub use config::TakerConfig;
+pub use dispute::{
+    ChainObservation, DisputeBundle, DisputedMaker, EvidenceTx, EvidenceTxKind, ObservationKind,
+};
+pub use dry_run::{DryRun, DryRunTx};
+pub use privacy::{PrivacyReport, PrivacyRisk, RiskKind, RiskSeverity};
+pub use quote::{QuotedMaker, SwapQuote};
+pub use report::{MakerFeeRecord, ReportFormat, SwapRecord};
+pub use scheduler::{ScheduleTiming, SwapSchedule};
+#[cfg(feature = "tui")]
+pub use tui::run_swap_dashboard;
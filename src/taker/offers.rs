@@ -6,6 +6,7 @@
 //! It uses asynchronous channels for concurrent processing of maker offers.
 
 use std::{
+    collections::{HashMap, HashSet},
     convert::TryFrom,
     fmt,
     fs::read,
@@ -13,9 +14,10 @@ use std::{
     net::TcpStream,
     path::Path,
     sync::mpsc,
-    thread::{self, Builder},
+    thread::Builder,
 };
 
+use bitcoin::OutPoint;
 use serde::{Deserialize, Serialize};
 
 #[cfg(feature = "tor")]
@@ -23,8 +25,12 @@ use socks::Socks5Stream;
 
 use crate::{
     error::NetError,
-    protocol::messages::{DnsRequest, Offer},
-    utill::{read_message, send_message, ConnectionType, GLOBAL_PAUSE, NET_TIMEOUT},
+    market::{listing::MakerListing, snapshot::MarketSnapshot, uptime::UptimeReport},
+    protocol::{
+        messages::{DnsRequest, Offer},
+        wire::decode,
+    },
+    utill::{read_message, send_message, unix_time, ConnectionType, NET_TIMEOUT},
 };
 
 use super::{config::TakerConfig, error::TakerError, routines::download_maker_offer};
@@ -86,8 +92,23 @@ impl TryFrom<&mut TcpStream> for MakerAddress {
 pub struct OfferBook {
     pub(super) all_makers: Vec<OfferAndAddress>,
     pub(super) bad_makers: Vec<OfferAndAddress>,
+    /// Makers of the most recent completed swaps, oldest first.
+    #[serde(default)]
+    pub(super) recent_swaps: Vec<Vec<MakerAddress>>,
+    /// Uptime records of the makers, as reported by the directory.
+    #[serde(default)]
+    pub(super) uptime: UptimeReport,
+    /// Ban scores of the makers blamed for premature broadcasts, by address.
+    #[serde(default)]
+    pub(super) ban_scores: HashMap<String, f64>,
 }
 
+/// Number of completed swaps remembered in the [OfferBook] for route diversity.
+const MAX_RECENT_SWAPS: usize = 100;
+
+/// Ban score at which a maker is marked bad. A maker blamed with full confidence is banned at once.
+pub(crate) const BAN_SCORE_THRESHOLD: f64 = 1.0;
+
 impl OfferBook {
     // TODO: design a better offerbook:
     // - unique key.
@@ -95,14 +116,31 @@ impl OfferBook {
     // - ranking system.
     // - various categories of livelynesss, to smartly distribute try counts.
 
-    /// Gets all "not-bad" offers.
+    /// Gets all "not-bad" offers. Expired offers are left out, their makers may be gone.
     pub fn all_good_makers(&self) -> Vec<&OfferAndAddress> {
+        let now = unix_time();
         self.all_makers
             .iter()
-            .filter(|offer| !self.bad_makers.contains(offer))
+            .filter(|offer| !self.bad_makers.contains(offer) && !offer.offer.is_expired(now))
             .collect()
     }
 
+    /// Gets all "not-bad" offers, with the makers the directory found flaky moved to the end.
+    pub(crate) fn good_makers_by_reliability(&self) -> Vec<&OfferAndAddress> {
+        let mut makers = self.all_good_makers();
+        // Stable sort, the order among reliable makers is kept.
+        makers.sort_by_key(|oa| self.is_flaky(&oa.address));
+        makers
+    }
+
+    /// Whether the directory found the maker flaky. Makers without uptime records are not.
+    pub(crate) fn is_flaky(&self, address: &MakerAddress) -> bool {
+        self.uptime
+            .get(&address.to_string())
+            .map(|uptime| uptime.is_flaky())
+            .unwrap_or(false)
+    }
+
     /// Adds a new offer to the offer book.
     pub(crate) fn add_new_offer(&mut self, offer: &OfferAndAddress) -> bool {
         if !self.all_makers.contains(offer) {
@@ -123,11 +161,39 @@ impl OfferBook {
         }
     }
 
+    /// Adds to the ban score of a maker, and marks it bad once the score reaches [BAN_SCORE_THRESHOLD]. Returns
+    /// true if the maker was newly marked bad.
+    pub(crate) fn add_ban_score(&mut self, maker: &OfferAndAddress, score: f64) -> bool {
+        let total = self
+            .ban_scores
+            .entry(maker.address.to_string())
+            .or_insert(0.0);
+        *total += score;
+        *total >= BAN_SCORE_THRESHOLD && self.add_bad_maker(maker)
+    }
+
     /// Gets the list of bad makers.
     pub(crate) fn get_bad_makers(&self) -> Vec<&OfferAndAddress> {
         self.bad_makers.iter().collect()
     }
 
+    /// Records the makers used in a completed swap.
+    pub(crate) fn record_swap_makers(&mut self, makers: Vec<MakerAddress>) {
+        self.recent_swaps.push(makers);
+        if self.recent_swaps.len() > MAX_RECENT_SWAPS {
+            self.recent_swaps.remove(0);
+        }
+    }
+
+    /// Returns true if the maker was used in any of the last `swap_count` completed swaps.
+    pub(crate) fn used_in_recent_swaps(&self, address: &MakerAddress, swap_count: usize) -> bool {
+        self.recent_swaps
+            .iter()
+            .rev()
+            .take(swap_count)
+            .any(|makers| makers.contains(address))
+    }
+
     /// Load existing file, updates it, writes it back (errors if path doesn't exist).
     pub fn write_to_disk(&self, path: &Path) -> Result<(), TakerError> {
         let wallet_file = std::fs::OpenOptions::new().write(true).open(path)?;
@@ -162,6 +228,22 @@ impl OfferBook {
     }
 }
 
+/// Fidelity bonds advertised by more than one maker address. A bond backs a single maker, the others are sybils.
+pub(crate) fn reused_bonds(offers: &[OfferAndAddress]) -> HashSet<OutPoint> {
+    let mut makers_by_bond: HashMap<OutPoint, HashSet<&MakerAddress>> = HashMap::new();
+    for offer in offers {
+        makers_by_bond
+            .entry(offer.offer.fidelity.bond.outpoint)
+            .or_default()
+            .insert(&offer.address);
+    }
+    makers_by_bond
+        .into_iter()
+        .filter(|(_, makers)| makers.len() > 1)
+        .map(|(outpoint, _)| outpoint)
+        .collect()
+}
+
 /// Synchronizes the offer book with specific maker addresses.
 pub(crate) fn fetch_offer_from_makers(
     maker_addresses: Vec<MakerAddress>,
@@ -200,12 +282,44 @@ pub(crate) fn fetch_offer_from_makers(
     Ok(result)
 }
 
-/// Retrieves advertised maker addresses from directory servers based on the specified network.
-pub fn fetch_addresses_from_dns(
+/// Fetches the makers registered at a directory server, with their verified fidelity bonds, highest bond values first.
+pub fn fetch_maker_listings_from_dns(
+    socks_port: Option<u16>,
+    dns_addr: String,
+    connection_type: ConnectionType,
+) -> Result<Vec<MakerListing>, TakerError> {
+    let mut stream = connect_to_dns(socks_port, &dns_addr, connection_type)?;
+    send_message(&mut stream, &DnsRequest::Listings)?;
+    Ok(decode(&read_message(&mut stream)?)?)
+}
+
+/// Fetches the latest market snapshot of a directory server. `None` if it hasn't taken one yet.
+pub fn fetch_market_snapshot_from_dns(
+    socks_port: Option<u16>,
+    dns_addr: String,
+    connection_type: ConnectionType,
+) -> Result<Option<MarketSnapshot>, TakerError> {
+    let mut stream = connect_to_dns(socks_port, &dns_addr, connection_type)?;
+    send_message(&mut stream, &DnsRequest::Snapshot)?;
+    Ok(decode(&read_message(&mut stream)?)?)
+}
+
+/// Fetches the uptime records of the makers registered at a directory server.
+pub fn fetch_uptime_from_dns(
     socks_port: Option<u16>,
     dns_addr: String,
     connection_type: ConnectionType,
-) -> Result<Vec<MakerAddress>, TakerError> {
+) -> Result<UptimeReport, TakerError> {
+    let mut stream = connect_to_dns(socks_port, &dns_addr, connection_type)?;
+    send_message(&mut stream, &DnsRequest::Uptime)?;
+    Ok(decode(&read_message(&mut stream)?)?)
+}
+
+pub(crate) fn connect_to_dns(
+    socks_port: Option<u16>,
+    dns_addr: &str,
+    connection_type: ConnectionType,
+) -> Result<TcpStream, TakerError> {
     if !cfg!(feature = "tor") {
         assert!(
             socks_port.is_none(),
@@ -213,64 +327,16 @@ pub fn fetch_addresses_from_dns(
         );
     }
 
-    loop {
-        let mut stream = match connection_type {
-            ConnectionType::CLEARNET => match TcpStream::connect(dns_addr.as_str()) {
-                Err(e) => {
-                    log::error!("Error connecting to DNS: {:?}", e);
-                    thread::sleep(GLOBAL_PAUSE);
-                    continue;
-                }
-                Ok(s) => s,
-            },
-            #[cfg(feature = "tor")]
-            ConnectionType::TOR => {
-                let socket_addrs = format!("127.0.0.1:{}", socks_port.expect("Tor port expected"));
-                match Socks5Stream::connect(socket_addrs, dns_addr.as_str()) {
-                    Err(e) => {
-                        log::error!("Error connecting to DNS: {:?}", e);
-                        thread::sleep(GLOBAL_PAUSE);
-                        continue;
-                    }
-                    Ok(s) => s.into_inner(),
-                }
-            }
-        };
-
-        stream.set_read_timeout(Some(NET_TIMEOUT))?;
-        stream.set_write_timeout(Some(NET_TIMEOUT))?;
-        stream.set_nonblocking(false)?;
-
-        if let Err(e) = send_message(&mut stream, &DnsRequest::Get) {
-            log::error!("Failed to send request. Retrying...{}", e);
-            thread::sleep(GLOBAL_PAUSE);
-            continue;
-        }
-
-        // Read the response
-        let response: String = match read_message(&mut stream) {
-            Ok(resp) => serde_cbor::de::from_slice(&resp[..])?,
-            Err(e) => {
-                log::error!("Error reading DNS response: {}. Retrying...", e);
-                thread::sleep(GLOBAL_PAUSE);
-                continue;
-            }
-        };
-
-        // Parse and validate the response
-        match response
-            .lines()
-            .map(MakerAddress::new)
-            .collect::<Result<Vec<MakerAddress>, _>>()
-        {
-            Ok(addresses) => {
-                return Ok(addresses);
-            }
-            Err(e) => {
-                log::error!("Error decoding DNS response: {:?}. Retrying...", e);
-                thread::sleep(GLOBAL_PAUSE);
-                continue;
-            }
+    let stream = match connection_type {
+        ConnectionType::CLEARNET => TcpStream::connect(dns_addr)?,
+        #[cfg(feature = "tor")]
+        ConnectionType::TOR => {
+            let socket_addrs = format!("127.0.0.1:{}", socks_port.expect("Tor port expected"));
+            Socks5Stream::connect(socket_addrs, dns_addr)?.into_inner()
         }
-    }
+    };
+    stream.set_read_timeout(Some(NET_TIMEOUT))?;
+    stream.set_write_timeout(Some(NET_TIMEOUT))?;
+    stream.set_nonblocking(false)?;
+    Ok(stream)
 }
@@ -0,0 +1,565 @@
+//! Post-swap privacy reports.
+//!
+//! A swap breaks the on-chain link between the coins sent and the coins received, but the transaction graph
+//! around it can still link them back. [Taker::privacy_report] looks at the funding txs of a completed swap,
+//! the txs paying the swapped coins to the wallet, and the wallet txs spending any of them since, and lists
+//! the remaining linkability risks:
+//! - amounts: coins received close to the amount sent, or a round amount sent.
+//! - timing: coins received within a few blocks of the funding.
+//! - script types: change outputs of another script type than the swap outputs, or mixed input types.
+//! - co-spends: coins of different origins spent together, above all swapped coins with pre-swap ones.
+//!
+//! Each risk costs points off a score of 100.
+
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+};
+
+use bitcoin::{Amount, OutPoint, Script, Transaction, TxOut, Txid};
+use bitcoind::bitcoincore_rpc::RpcApi;
+use serde::{Deserialize, Serialize};
+
+use super::{api::Taker, error::TakerError, SwapRecord};
+use crate::wallet::WalletError;
+
+/// Amounts received within this percentage of the amount sent can be matched.
+const AMOUNT_MATCH_HIGH_PCT: f64 = 0.5;
+const AMOUNT_MATCH_MEDIUM_PCT: f64 = 3.0;
+/// Amounts sent in multiples of this are round.
+const ROUND_AMOUNT: Amount = Amount::from_sat(100_000);
+/// Coins received within these many blocks of the funding can be matched by timing.
+const TIMING_HIGH_BLOCKS: u32 = 1;
+const TIMING_MEDIUM_BLOCKS: u32 = 6;
+
+/// Kind of a linkability risk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RiskKind {
+    /// Amount received close to the amount sent.
+    AmountCorrelation,
+    /// Round amount sent.
+    RoundAmount,
+    /// Coins received shortly after the funding.
+    Timing,
+    /// Outputs or inputs telling themselves apart by their script type.
+    ScriptType,
+    /// Coins of different origins spent together.
+    CoSpend,
+}
+
+/// Severity of a linkability risk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RiskSeverity {
+    /// A hint, which only adds up with other risks.
+    Low,
+    /// Narrows down the candidates for the link.
+    Medium,
+    /// Likely links the coins sent to the coins received.
+    High,
+}
+
+impl RiskSeverity {
+    /// Points taken off the score.
+    fn penalty(&self) -> u8 {
+        match self {
+            Self::Low => 5,
+            Self::Medium => 15,
+            Self::High => 35,
+        }
+    }
+}
+
+/// A linkability risk left by a swap.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PrivacyRisk {
+    /// Kind of the risk.
+    pub kind: RiskKind,
+    /// Severity of the risk.
+    pub severity: RiskSeverity,
+    /// What was found, with the txs involved.
+    pub detail: String,
+}
+
+/// Linkability risks of a completed swap, see the [module docs](self).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PrivacyReport {
+    /// Id of the swap.
+    pub swap_id: String,
+    /// Score out of 100, higher is better.
+    pub score: u8,
+    /// Risks found, most severe first.
+    pub risks: Vec<PrivacyRisk>,
+}
+
+impl PrivacyReport {
+    fn new(swap_id: String, mut risks: Vec<PrivacyRisk>) -> Self {
+        risks.sort_by_key(|risk| Reverse(risk.severity));
+        let score = risks.iter().fold(100u8, |score, risk| {
+            score.saturating_sub(risk.severity.penalty())
+        });
+        Self {
+            swap_id,
+            score,
+            risks,
+        }
+    }
+}
+
+/// A tx, with the height it confirmed at.
+#[derive(Debug, Clone)]
+struct ChainTx {
+    tx: Transaction,
+    height: Option<u32>,
+}
+
+/// The txs around a swap.
+#[derive(Debug, Default)]
+struct SwapGraph {
+    /// Funding txs of the Taker.
+    funding: Vec<ChainTx>,
+    /// Outputs spent by the funding txs.
+    prevouts: HashMap<OutPoint, TxOut>,
+    /// Txs paying the swapped coins.
+    incoming: Vec<ChainTx>,
+    /// Wallet txs since the swap, spending any coin.
+    spends: Vec<Transaction>,
+}
+
+/// Name of the script type of an output.
+fn script_type(script: &Script) -> &'static str {
+    if script.is_p2wpkh() {
+        "P2WPKH"
+    } else if script.is_p2wsh() {
+        "P2WSH"
+    } else if script.is_p2tr() {
+        "P2TR"
+    } else if script.is_p2pkh() {
+        "P2PKH"
+    } else if script.is_p2sh() {
+        "P2SH"
+    } else {
+        "non-standard"
+    }
+}
+
+/// Outputs of the txs spending only coins of `seed`, or of their descendants, along with `seed`.
+fn descendants(seed: HashSet<OutPoint>, txs: &[Transaction]) -> HashSet<OutPoint> {
+    let mut coins = seed;
+    loop {
+        let before = coins.len();
+        for tx in txs {
+            if tx
+                .input
+                .iter()
+                .all(|input| coins.contains(&input.previous_output))
+            {
+                let txid = tx.compute_txid();
+                coins.extend((0..tx.output.len() as u32).map(|vout| OutPoint { txid, vout }));
+            }
+        }
+        if coins.len() == before {
+            return coins;
+        }
+    }
+}
+
+fn analyze(record: &SwapRecord, graph: &SwapGraph) -> Vec<PrivacyRisk> {
+    let mut risks = Vec::new();
+    let mut risk = |kind, severity, detail| {
+        risks.push(PrivacyRisk {
+            kind,
+            severity,
+            detail,
+        })
+    };
+
+    // Amounts
+    let sent = Amount::from_sat(record.send_amount_sats);
+    let incoming = graph
+        .incoming
+        .iter()
+        .map(|chain_tx| (chain_tx.tx.compute_txid(), &chain_tx.tx))
+        .collect::<HashMap<_, _>>();
+    let received = record
+        .resulting_utxos
+        .iter()
+        .filter_map(|outpoint| {
+            incoming
+                .get(&outpoint.txid)
+                .and_then(|tx| tx.output.get(outpoint.vout as usize))
+        })
+        .map(|output| output.value)
+        .sum::<Amount>();
+    if sent > Amount::ZERO && received > Amount::ZERO {
+        let gap_pct =
+            sent.to_sat().abs_diff(received.to_sat()) as f64 * 100.0 / sent.to_sat() as f64;
+        let severity = if gap_pct < AMOUNT_MATCH_HIGH_PCT {
+            Some(RiskSeverity::High)
+        } else if gap_pct < AMOUNT_MATCH_MEDIUM_PCT {
+            Some(RiskSeverity::Medium)
+        } else {
+            None
+        };
+        if let Some(severity) = severity {
+            risk(
+                RiskKind::AmountCorrelation,
+                severity,
+                format!(
+                    "Received {} for {} sent, {:.2}% apart: the amounts can be matched across the swap",
+                    received, sent, gap_pct
+                ),
+            );
+        }
+    }
+    if sent > Amount::ZERO && sent.to_sat().is_multiple_of(ROUND_AMOUNT.to_sat()) {
+        risk(
+            RiskKind::RoundAmount,
+            RiskSeverity::Low,
+            format!("Sent a round amount, {}", sent),
+        );
+    }
+
+    // Timing, only known once all the txs are confirmed
+    let funded_at = graph
+        .funding
+        .iter()
+        .map(|chain_tx| chain_tx.height)
+        .collect::<Option<Vec<_>>>()
+        .and_then(|heights| heights.into_iter().max());
+    let received_at = graph
+        .incoming
+        .iter()
+        .map(|chain_tx| chain_tx.height)
+        .collect::<Option<Vec<_>>>()
+        .and_then(|heights| heights.into_iter().min());
+    match (funded_at, received_at) {
+        (Some(funded_at), Some(received_at)) => {
+            let gap = received_at.saturating_sub(funded_at);
+            let severity = if gap <= TIMING_HIGH_BLOCKS {
+                Some(RiskSeverity::High)
+            } else if gap <= TIMING_MEDIUM_BLOCKS {
+                Some(RiskSeverity::Medium)
+            } else {
+                None
+            };
+            if let Some(severity) = severity {
+                risk(
+                    RiskKind::Timing,
+                    severity,
+                    format!(
+                        "Swapped coins confirmed {} blocks after the funding, at height {}",
+                        gap, received_at
+                    ),
+                );
+            }
+        }
+        _ => risk(
+            RiskKind::Timing,
+            RiskSeverity::Low,
+            "Funding or swapped coins not confirmed yet, timing not assessed".to_string(),
+        ),
+    }
+
+    // Script types
+    let mut change = HashSet::new();
+    for chain_tx in &graph.funding {
+        let txid = chain_tx.tx.compute_txid();
+        for (vout, output) in chain_tx.tx.output.iter().enumerate() {
+            if !output.script_pubkey.is_p2wsh() {
+                change.insert(OutPoint {
+                    txid,
+                    vout: vout as u32,
+                });
+                risk(
+                    RiskKind::ScriptType,
+                    RiskSeverity::Medium,
+                    format!(
+                        "Change {}:{} of the funding is {}, the swap outputs P2WSH: the change is identifiable",
+                        txid,
+                        vout,
+                        script_type(&output.script_pubkey)
+                    ),
+                );
+            }
+        }
+        let input_types = chain_tx
+            .tx
+            .input
+            .iter()
+            .filter_map(|input| graph.prevouts.get(&input.previous_output))
+            .map(|prevout| script_type(&prevout.script_pubkey))
+            .collect::<HashSet<_>>();
+        if input_types.len() > 1 {
+            let mut input_types = input_types.into_iter().collect::<Vec<_>>();
+            input_types.sort();
+            risk(
+                RiskKind::ScriptType,
+                RiskSeverity::Low,
+                format!(
+                    "Funding tx {} spends inputs of mixed script types: {}",
+                    txid,
+                    input_types.join(", ")
+                ),
+            );
+        }
+    }
+
+    // Co-spends
+    for chain_tx in &graph.funding {
+        let origins = chain_tx
+            .tx
+            .input
+            .iter()
+            .map(|input| input.previous_output.txid)
+            .collect::<HashSet<Txid>>();
+        if origins.len() > 1 {
+            risk(
+                RiskKind::CoSpend,
+                RiskSeverity::Medium,
+                format!(
+                    "Funding tx {} spends coins of {} different txs together, linking them",
+                    chain_tx.tx.compute_txid(),
+                    origins.len()
+                ),
+            );
+        }
+    }
+    let swapped = descendants(
+        record.resulting_utxos.iter().cloned().collect(),
+        &graph.spends,
+    );
+    let pre_swap = descendants(change, &graph.spends);
+    for tx in &graph.spends {
+        let count = |coins: &HashSet<OutPoint>| {
+            tx.input
+                .iter()
+                .filter(|input| coins.contains(&input.previous_output))
+                .count()
+        };
+        let (swapped_count, pre_swap_count) = (count(&swapped), count(&pre_swap));
+        let others = tx.input.len() - swapped_count - pre_swap_count;
+        if swapped_count == 0 {
+            continue;
+        }
+        let txid = tx.compute_txid();
+        if pre_swap_count > 0 {
+            risk(
+                RiskKind::CoSpend,
+                RiskSeverity::High,
+                format!(
+                    "Tx {} spends swapped coins together with the change of the funding, linking both sides of the swap",
+                    txid
+                ),
+            );
+        } else if others > 0 {
+            risk(
+                RiskKind::CoSpend,
+                RiskSeverity::Medium,
+                format!(
+                    "Tx {} spends swapped coins together with {} other wallet coins",
+                    txid, others
+                ),
+            );
+        } else if swapped_count > 1 && record.resulting_utxos.len() > 1 {
+            risk(
+                RiskKind::CoSpend,
+                RiskSeverity::Medium,
+                format!(
+                    "Tx {} spends {} swapped coins together, joining the split amounts",
+                    txid, swapped_count
+                ),
+            );
+        }
+    }
+
+    risks
+}
+
+impl Taker {
+    /// Analyzes the transaction graph of a completed swap, and reports the risks left of linking the coins sent
+    /// to the coins received. See the [module docs](crate::taker::privacy).
+    pub fn privacy_report(&self, swap_id: &str) -> Result<PrivacyReport, TakerError> {
+        let history = self.read_swap_history()?;
+        let record = history
+            .record(swap_id)
+            .ok_or_else(|| TakerError::UnknownSwap(swap_id.to_string()))?;
+
+        let mut graph = SwapGraph::default();
+        let funding_txids = record
+            .outputs
+            .iter()
+            .map(|outpoint| outpoint.txid)
+            .collect::<HashSet<_>>();
+        for txid in &funding_txids {
+            let chain_tx = self.chain_tx(txid)?;
+            for input in &chain_tx.tx.input {
+                let prev = self.chain_tx(&input.previous_output.txid)?;
+                if let Some(output) = prev.tx.output.get(input.previous_output.vout as usize) {
+                    graph.prevouts.insert(input.previous_output, output.clone());
+                }
+            }
+            graph.funding.push(chain_tx);
+        }
+        let incoming_txids = record
+            .resulting_utxos
+            .iter()
+            .map(|outpoint| outpoint.txid)
+            .collect::<HashSet<_>>();
+        for txid in &incoming_txids {
+            graph.incoming.push(self.chain_tx(txid)?);
+        }
+        for tx in self.get_wallet().list_transactions()? {
+            if tx.time >= record.started_at && !funding_txids.contains(&tx.txid) {
+                graph.spends.push(self.chain_tx(&tx.txid)?.tx);
+            }
+        }
+
+        Ok(PrivacyReport::new(
+            swap_id.to_string(),
+            analyze(record, &graph),
+        ))
+    }
+
+    /// A wallet tx, watch-only included, and the height it confirmed at.
+    fn chain_tx(&self, txid: &Txid) -> Result<ChainTx, TakerError> {
+        let result = self
+            .get_wallet()
+            .rpc
+            .get_transaction(txid, Some(true))
+            .map_err(WalletError::Rpc)?;
+        Ok(ChainTx {
+            tx: result.transaction().map_err(WalletError::from)?,
+            height: result.info.blockheight,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{
+        absolute::LockTime, hashes::Hash, transaction::Version, ScriptBuf, Sequence, TxIn,
+        WPubkeyHash, WScriptHash, Witness,
+    };
+
+    use super::*;
+
+    fn tx(inputs: Vec<OutPoint>, outputs: Vec<(u64, ScriptBuf)>) -> Transaction {
+        Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: inputs
+                .into_iter()
+                .map(|previous_output| TxIn {
+                    previous_output,
+                    script_sig: ScriptBuf::new(),
+                    sequence: Sequence::MAX,
+                    witness: Witness::new(),
+                })
+                .collect(),
+            output: outputs
+                .into_iter()
+                .map(|(value, script_pubkey)| TxOut {
+                    value: Amount::from_sat(value),
+                    script_pubkey,
+                })
+                .collect(),
+        }
+    }
+
+    fn outpoint(tx: &Transaction, vout: u32) -> OutPoint {
+        OutPoint {
+            txid: tx.compute_txid(),
+            vout,
+        }
+    }
+
+    fn record(send_amount_sats: u64, resulting_utxos: Vec<OutPoint>) -> SwapRecord {
+        SwapRecord {
+            id: "0123456789abcdef".to_string(),
+            started_at: 0,
+            completed_at: 0,
+            send_amount_sats,
+            inputs: vec![],
+            outputs: vec![],
+            makers: vec![],
+            miner_fees_sats: 0,
+            resulting_utxos,
+            memo: None,
+            receipts: vec![],
+        }
+    }
+
+    fn kinds(risks: &[PrivacyRisk]) -> Vec<(RiskKind, RiskSeverity)> {
+        risks.iter().map(|r| (r.kind, r.severity)).collect()
+    }
+
+    #[test]
+    fn test_privacy_analysis() {
+        let p2wpkh = ScriptBuf::new_p2wpkh(&WPubkeyHash::all_zeros());
+        let p2wsh = ScriptBuf::new_p2wsh(&WScriptHash::all_zeros());
+        let deposit = tx(vec![], vec![(1_000_000, p2wpkh.clone())]);
+        let funding = tx(
+            vec![outpoint(&deposit, 0)],
+            vec![(500_000, p2wsh.clone()), (499_000, p2wpkh.clone())],
+        );
+        let incoming = tx(
+            vec![OutPoint::null()],
+            vec![(497_000, p2wsh.clone()), (3_000_000, p2wpkh.clone())],
+        );
+        let mut graph = SwapGraph {
+            funding: vec![ChainTx {
+                tx: funding.clone(),
+                height: Some(100),
+            }],
+            prevouts: HashMap::from([(outpoint(&deposit, 0), deposit.output[0].clone())]),
+            incoming: vec![ChainTx {
+                tx: incoming.clone(),
+                height: Some(130),
+            }],
+            spends: vec![],
+        };
+        let swap = record(500_000, vec![outpoint(&incoming, 0)]);
+
+        // 0.6% apart, a round amount, and an identifiable change.
+        let report = PrivacyReport::new(swap.id.clone(), analyze(&swap, &graph));
+        assert_eq!(
+            kinds(&report.risks),
+            vec![
+                (RiskKind::AmountCorrelation, RiskSeverity::Medium),
+                (RiskKind::ScriptType, RiskSeverity::Medium),
+                (RiskKind::RoundAmount, RiskSeverity::Low),
+            ]
+        );
+        assert_eq!(report.score, 65);
+
+        // Received right after the funding, unconfirmed.
+        graph.incoming[0].height = Some(101);
+        assert!(kinds(&analyze(&swap, &graph)).contains(&(RiskKind::Timing, RiskSeverity::High)));
+        graph.incoming[0].height = None;
+        assert!(kinds(&analyze(&swap, &graph)).contains(&(RiskKind::Timing, RiskSeverity::Low)));
+
+        // Swapped coins, transferred, then spent with the change of the funding.
+        let transfer = tx(
+            vec![outpoint(&incoming, 0)],
+            vec![(496_000, p2wpkh.clone())],
+        );
+        let cospend = tx(
+            vec![outpoint(&transfer, 0), outpoint(&funding, 1)],
+            vec![(990_000, p2wpkh.clone())],
+        );
+        graph.spends = vec![cospend, transfer];
+        let risks = kinds(&analyze(&swap, &graph));
+        assert!(risks.contains(&(RiskKind::CoSpend, RiskSeverity::High)));
+
+        // Swapped coins spent with some other coin.
+        graph.spends = vec![tx(
+            vec![outpoint(&incoming, 0), outpoint(&incoming, 1)],
+            vec![(3_490_000, p2wpkh)],
+        )];
+        let risks = kinds(&analyze(&swap, &graph));
+        assert!(risks.contains(&(RiskKind::CoSpend, RiskSeverity::Medium)));
+        assert!(!risks.contains(&(RiskKind::CoSpend, RiskSeverity::High)));
+    }
+}
@@ -0,0 +1,161 @@
+//! Fee previews of swaps, before committing to them.
+//!
+//! [Taker::quote] syncs the offer book and picks the route a swap with the same [SwapParams] would start with,
+//! then prices it from the offers: the fee of each maker, the miner fees of the funding txs of every hop, and
+//! how long the coins may stay locked if the swap has to be recovered. Nothing is broadcast and no maker is
+//! contacted past fetching its offer, so a UI can show the quote and ask the user to confirm before calling
+//! [Taker::do_coinswap].
+//!
+//! The makers are picked hop by hop while swapping, so the route of the swap may still differ from the quoted
+//! one if the offer book changes, or a maker doesn't answer.
+
+use bitcoin::Amount;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    api::{SwapParams, Taker, MINER_FEE},
+    error::TakerError,
+    offers::OfferAndAddress,
+};
+use crate::protocol::contract::contract_fee;
+
+/// A maker of the quoted route.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuotedMaker {
+    /// Address of the maker.
+    pub address: String,
+    /// Amount the maker receives from the previous hop, in sats.
+    pub amount_sats: u64,
+    /// Fee of the maker, in sats.
+    pub fee_sats: u64,
+    /// Miner fees of the maker's funding txs, deducted from the swapped amount, in sats.
+    pub miner_fees_sats: u64,
+    /// Refund locktime of the contracts funding the maker, in blocks.
+    pub refund_locktime: u16,
+}
+
+/// Preview of the fees and timelocks of a swap, see the [module docs](self).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SwapQuote {
+    /// Amount sent into the swap, in sats.
+    pub send_amount_sats: u64,
+    /// Makers of the route, in route order.
+    pub makers: Vec<QuotedMaker>,
+    /// Miner fees of the Taker's own funding txs, in sats.
+    pub miner_fees_sats: u64,
+    /// Amount received at the end of the swap, in sats.
+    pub receive_amount_sats: u64,
+    /// Feerate of the contract txs, in sats/vB. None if the makers don't negotiate it.
+    pub contract_feerate: Option<f64>,
+    /// Fee of each contract tx, only paid if the swap is recovered, in sats.
+    pub contract_fee_sats: u64,
+    /// Refund locktimes of the hops, from the Taker's funding txs to the last hop, in blocks.
+    pub refund_locktimes: Vec<u16>,
+}
+
+impl SwapQuote {
+    /// Total of the maker fees, in sats.
+    pub fn maker_fees_sats(&self) -> u64 {
+        self.makers.iter().map(|m| m.fee_sats).sum()
+    }
+
+    /// Total fee of the swap: maker fees, plus all the miner fees, in sats.
+    pub fn total_fees_sats(&self) -> u64 {
+        self.maker_fees_sats()
+            + self.miner_fees_sats
+            + self.makers.iter().map(|m| m.miner_fees_sats).sum::<u64>()
+    }
+
+    /// Longest the sent coins may stay locked in contracts if the swap is recovered, in blocks.
+    pub fn max_lock_blocks(&self) -> u16 {
+        self.refund_locktimes.first().copied().unwrap_or_default()
+    }
+}
+
+impl Taker {
+    /// Previews the fees and timelocks of a swap, without starting it. See the [module docs](crate::taker::quote).
+    ///
+    /// Fails like [Taker::do_coinswap] would before funding the swap: not enough funds, or not enough makers
+    /// fitting the swap.
+    pub fn quote(&mut self, mut swap_params: SwapParams) -> Result<SwapQuote, TakerError> {
+        self.check_swap_funds(&mut swap_params)?;
+        self.sync_offerbook()?;
+        self.check_maker_count(&swap_params)?;
+
+        let contract_feerate = self.negotiate_contract_feerate()?;
+        let refund_locktimes = self.plan_refund_locktimes(&swap_params, contract_feerate)?;
+
+        let mut route: Vec<&OfferAndAddress> = Vec::new();
+        for _ in 0..swap_params.maker_count {
+            let maker = self
+                .pick_maker(
+                    swap_params.send_amount,
+                    &route,
+                    contract_feerate,
+                    &refund_locktimes,
+                )
+                .ok_or(TakerError::NotEnoughMakersInOfferBook)?;
+            route.push(maker);
+        }
+
+        let funding_fees = MINER_FEE * swap_params.tx_count as u64;
+        let mut amount = swap_params.send_amount.to_sat();
+        let makers = route
+            .iter()
+            .zip(&refund_locktimes)
+            .map(|(oa, &refund_locktime)| {
+                let fee = oa.offer.coinswap_fee(amount, refund_locktime);
+                let maker = QuotedMaker {
+                    address: oa.address.to_string(),
+                    amount_sats: amount,
+                    fee_sats: fee,
+                    miner_fees_sats: funding_fees,
+                    refund_locktime,
+                };
+                // Each maker deducts its fee and the miner fees of its funding txs for the next hop.
+                amount = amount.saturating_sub(fee + funding_fees);
+                maker
+            })
+            .collect();
+
+        Ok(SwapQuote {
+            send_amount_sats: swap_params.send_amount.to_sat(),
+            makers,
+            miner_fees_sats: funding_fees,
+            receive_amount_sats: amount,
+            contract_feerate,
+            contract_fee_sats: contract_feerate
+                .map_or(Amount::from_sat(MINER_FEE), contract_fee)
+                .to_sat(),
+            refund_locktimes,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quote_totals() {
+        let maker = |fee_sats| QuotedMaker {
+            address: "127.0.0.1:6102".to_string(),
+            amount_sats: 100_000,
+            fee_sats,
+            miner_fees_sats: 300,
+            refund_locktime: 40,
+        };
+        let quote = SwapQuote {
+            send_amount_sats: 100_000,
+            makers: vec![maker(1_000), maker(500)],
+            miner_fees_sats: 300,
+            receive_amount_sats: 97_600,
+            contract_feerate: None,
+            contract_fee_sats: 300,
+            refund_locktimes: vec![40, 20],
+        };
+        assert_eq!(quote.maker_fees_sats(), 1_500);
+        assert_eq!(quote.total_fees_sats(), 2_400);
+        assert_eq!(quote.max_lock_blocks(), 40);
+    }
+}
@@ -0,0 +1,261 @@
+//! Swap reports.
+//!
+//! Every completed swap is recorded in `swap_history.dat`, in the Taker's data directory.
+//! [Taker::export_report](super::Taker::export_report) exports the records of a time range as JSON or CSV,
+//! for accounting or for measurements of the market.
+
+use std::{
+    fs::{read, File},
+    io::BufWriter,
+    ops::RangeBounds,
+    path::Path,
+    str::FromStr,
+};
+
+use bitcoin::OutPoint;
+use serde::{Deserialize, Serialize};
+
+use crate::protocol::messages::SignedReceipt;
+
+use super::error::TakerError;
+
+/// Fee paid to one maker of a swap.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MakerFeeRecord {
+    /// Address of the maker.
+    pub address: String,
+    /// Fee earned by the maker, in sats.
+    pub fee_sats: u64,
+    /// Miner fees of the maker's funding txs, deducted from the swapped amount, in sats.
+    pub miner_fees_sats: u64,
+}
+
+/// Record of a completed swap.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SwapRecord {
+    /// Unique id of the swap.
+    pub id: String,
+    /// Unix timestamp of the start of the swap.
+    pub started_at: u64,
+    /// Unix timestamp of the completion of the swap.
+    pub completed_at: u64,
+    /// Amount sent into the swap, in sats.
+    pub send_amount_sats: u64,
+    /// Wallet utxos spent by the funding txs.
+    pub inputs: Vec<OutPoint>,
+    /// Outputs of the funding txs, including change.
+    pub outputs: Vec<OutPoint>,
+    /// Fees paid to each maker, in route order.
+    pub makers: Vec<MakerFeeRecord>,
+    /// Miner fees of the Taker's own funding txs, in sats.
+    pub miner_fees_sats: u64,
+    /// Swap coins received at the end of the swap.
+    pub resulting_utxos: Vec<OutPoint>,
+    /// Memo of the operator on the swap, from the wallet.
+    #[serde(default)]
+    pub memo: Option<String>,
+    /// Receipts of the makers, signed by them and countersigned, in settlement order.
+    #[serde(default)]
+    pub receipts: Vec<SignedReceipt>,
+}
+
+impl SwapRecord {
+    /// Total of the maker fees, in sats.
+    pub fn maker_fees_sats(&self) -> u64 {
+        self.makers.iter().map(|m| m.fee_sats).sum()
+    }
+
+    /// Total fee of the swap: maker fees, plus all the miner fees, in sats.
+    pub fn total_fees_sats(&self) -> u64 {
+        self.maker_fees_sats()
+            + self.miner_fees_sats
+            + self.makers.iter().map(|m| m.miner_fees_sats).sum::<u64>()
+    }
+}
+
+/// Format of an exported report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReportFormat {
+    /// A JSON array of [SwapRecord]s.
+    #[default]
+    Json,
+    /// CSV with one swap per row. List fields are separated by `;`.
+    Csv,
+}
+
+impl FromStr for ReportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "json" => Ok(Self::Json),
+            "csv" => Ok(Self::Csv),
+            _ => Err(format!("Unknown report format: {}", s)),
+        }
+    }
+}
+
+/// All swaps completed by the Taker.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct SwapHistory {
+    records: Vec<SwapRecord>,
+}
+
+impl SwapHistory {
+    /// Reads the history from disk. Empty if the file doesn't exist yet.
+    pub(crate) fn read_from_disk(path: &Path) -> Result<Self, TakerError> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Ok(serde_cbor::from_slice(&read(path)?)?)
+    }
+
+    /// Writes the history to disk.
+    pub(crate) fn write_to_disk(&self, path: &Path) -> Result<(), TakerError> {
+        let writer = BufWriter::new(File::create(path)?);
+        Ok(serde_cbor::to_writer(writer, &self)?)
+    }
+
+    /// Appends a record and saves the history.
+    pub(crate) fn append(path: &Path, record: SwapRecord) -> Result<(), TakerError> {
+        let mut history = Self::read_from_disk(path)?;
+        history.records.push(record);
+        history.write_to_disk(path)
+    }
+
+    /// Record of the swap with the given id.
+    pub(crate) fn record(&self, id: &str) -> Option<&SwapRecord> {
+        self.records.iter().find(|record| record.id == id)
+    }
+
+    /// Records of the swaps completed in the given range of unix timestamps.
+    pub(crate) fn records_in(&self, range: impl RangeBounds<u64>) -> Vec<&SwapRecord> {
+        self.records
+            .iter()
+            .filter(|record| range.contains(&record.completed_at))
+            .collect()
+    }
+}
+
+/// Renders the records in the given format.
+pub(crate) fn render_report(
+    records: &[SwapRecord],
+    format: ReportFormat,
+) -> Result<String, TakerError> {
+    match format {
+        ReportFormat::Json => Ok(serde_json::to_string_pretty(records)?),
+        ReportFormat::Csv => {
+            let mut csv = "id,started_at,completed_at,send_amount_sats,maker_fees_sats,miner_fees_sats,total_fees_sats,inputs,outputs,makers,resulting_utxos,memo\n".to_string();
+            for record in records {
+                csv.push_str(&format!(
+                    "{},{},{},{},{},{},{},{},{},{},{},{}\n",
+                    record.id,
+                    record.started_at,
+                    record.completed_at,
+                    record.send_amount_sats,
+                    record.maker_fees_sats(),
+                    record.miner_fees_sats,
+                    record.total_fees_sats(),
+                    join(&record.inputs),
+                    join(&record.outputs),
+                    record
+                        .makers
+                        .iter()
+                        .map(|m| format!("{}={}", m.address, m.fee_sats))
+                        .collect::<Vec<_>>()
+                        .join(";"),
+                    join(&record.resulting_utxos),
+                    quote(record.memo.as_deref().unwrap_or_default()),
+                ));
+            }
+            Ok(csv)
+        }
+    }
+}
+
+fn join(outpoints: &[OutPoint]) -> String {
+    outpoints
+        .iter()
+        .map(|op| op.to_string())
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+/// Quotes a free-text CSV field.
+fn quote(field: &str) -> String {
+    format!("\"{}\"", field.replace('"', "\"\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(id: &str, completed_at: u64) -> SwapRecord {
+        SwapRecord {
+            id: id.to_string(),
+            started_at: completed_at - 600,
+            completed_at,
+            send_amount_sats: 500_000,
+            inputs: vec![OutPoint::null()],
+            outputs: vec![OutPoint::null(), OutPoint::null()],
+            makers: vec![
+                MakerFeeRecord {
+                    address: "127.0.0.1:6102".to_string(),
+                    fee_sats: 1_500,
+                    miner_fees_sats: 1_000,
+                },
+                MakerFeeRecord {
+                    address: "127.0.0.1:16102".to_string(),
+                    fee_sats: 1_200,
+                    miner_fees_sats: 1_000,
+                },
+            ],
+            miner_fees_sats: 1_000,
+            resulting_utxos: vec![OutPoint::null()],
+            memo: None,
+            receipts: vec![],
+        }
+    }
+
+    #[test]
+    fn test_swap_report() {
+        let history = SwapHistory {
+            records: vec![record("a", 1_000), record("b", 2_000), record("c", 3_000)],
+        };
+        assert_eq!(history.records_in(..).len(), 3);
+        assert_eq!(history.records_in(1_500..).len(), 2);
+        let mut range = history
+            .records_in(1_000..3_000)
+            .into_iter()
+            .cloned()
+            .collect::<Vec<_>>();
+        assert_eq!(
+            range.iter().map(|r| r.id.as_str()).collect::<Vec<_>>(),
+            ["a", "b"]
+        );
+        assert_eq!(range[0].maker_fees_sats(), 2_700);
+        assert_eq!(range[0].total_fees_sats(), 5_700);
+
+        let json = render_report(&range, ReportFormat::Json).unwrap();
+        let parsed: Vec<SwapRecord> = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, vec![record("a", 1_000), record("b", 2_000)]);
+
+        let csv = render_report(&range, ReportFormat::Csv).unwrap();
+        let lines = csv.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1].starts_with("a,400,1000,500000,2700,1000,5700,"));
+        assert!(lines[1].contains("127.0.0.1:6102=1500;127.0.0.1:16102=1200"));
+        assert!(lines[1].ends_with(",\"\""));
+
+        range[1].memo = Some("consolidation, \"manual\"".to_string());
+        let csv = render_report(&range, ReportFormat::Csv).unwrap();
+        assert!(csv
+            .lines()
+            .nth(2)
+            .unwrap()
+            .ends_with(",\"consolidation, \"\"manual\"\"\""));
+
+        assert_eq!("CSV".parse::<ReportFormat>().unwrap(), ReportFormat::Csv);
+        assert!("xml".parse::<ReportFormat>().is_err());
+    }
+}
@@ -1,23 +1,19 @@
 //! Various operational routines/functions.
 //!
 //! It includes functions for handshaking, requesting contract signatures, sending proofs of funding, and downloading maker offers.
-//! It also defines structs for contract transactions and contract information.
-//! Notable types include [ContractTransaction], [ContractsInfo], [ThisMakerInfo], and [NextMakerInfo].
+//! Notable types include [ThisMakerInfo] and [NextMakerInfo].
 //! It also handles downloading maker offers with retry mechanisms and implements the necessary message structures
 //! for communication between taker and maker.
 
-use serde::{Deserialize, Serialize};
 #[cfg(feature = "tor")]
 use socks::Socks5Stream;
-use std::{net::TcpStream, thread::sleep, time::Duration};
+use std::{net::TcpStream, thread::sleep};
 
 use crate::{
     protocol::{
-        contract::{
-            calculate_coinswap_fee, create_contract_redeemscript, find_funding_output_index,
-            validate_contract_tx,
-        },
+        contract::{create_contract_redeemscript, find_funding_output_index, validate_contract_tx},
         error::ProtocolError,
+        machine::{taker_transition, AwaitedReply, TakerAction, PROTOCOL_VERSION},
         messages::{
             ContractSigsAsRecvrAndSender, ContractSigsForRecvr, ContractSigsForSender,
             ContractTxInfoForRecvr, ContractTxInfoForSender, FundingTxInfo, GiveOffer,
@@ -25,89 +21,121 @@ use crate::{
             ProofOfFunding, ReqContractSigsForRecvr, ReqContractSigsForSender, TakerHello,
             TakerToMakerMessage,
         },
-        Hash160,
+        session::{new_session_key, new_session_nonce, Session, SessionNonce, Transcript},
+        wire::decode,
+        Hash160, ProtocolPhase,
     },
     taker::api::MINER_FEE,
-    utill::{read_message, send_message, ConnectionType},
+    utill::{par_try_for_each, read_message_with_limit, send_message, ConnectionType},
     wallet::WalletError,
 };
 use bitcoin::{secp256k1::SecretKey, Amount, PublicKey, ScriptBuf, Transaction};
 
 use super::{
     config::TakerConfig,
+    connection::MakerConnection,
     error::TakerError,
     offers::{MakerAddress, OfferAndAddress},
 };
 
-use crate::taker::api::{
-    FIRST_CONNECT_ATTEMPTS, FIRST_CONNECT_ATTEMPT_TIMEOUT_SEC, FIRST_CONNECT_SLEEP_DELAY_SEC,
-};
-
 use crate::wallet::SwapCoin;
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
-pub(crate) struct ContractTransaction {
-    pub(crate) tx: Transaction,
-    pub(crate) redeemscript: ScriptBuf,
-    pub(crate) hashlock_spend_without_preimage: Option<Transaction>,
-    pub(crate) timelock_spend: Option<Transaction>,
-    pub(crate) timelock_spend_broadcasted: bool,
+/// Make a handshake with a maker.
+/// Ensures that the Maker is alive and responding, and opens the session of the connection. The later messages of
+/// the connection are sent and read in it, and recorded in the `transcript` if given.
+///
+// In future, handshake can be used to find protocol compatibility across multiple versions.
+pub(crate) fn handshake_maker(
+    socket: &mut TcpStream,
+    max_message_size: u32,
+    transcript: Option<&Transcript>,
+) -> Result<Session, TakerError> {
+    let handshake = Handshake::new();
+    send_message(socket, handshake.hello())?;
+    let msg_bytes = read_message_with_limit(socket, max_message_size)?;
+    handshake.open_session(decode(&msg_bytes)?, transcript)
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
-pub(crate) struct ContractsInfo {
-    pub(crate) contract_txes: Vec<ContractTransaction>,
-    pub(crate) wallet_label: String,
+/// A handshake with a maker: our hello, and the secrets to open its session with the reply of the maker.
+pub(crate) struct Handshake {
+    hello: TakerToMakerMessage,
+    nonce: SessionNonce,
+    secret: SecretKey,
 }
 
-/// Make a handshake with a maker.
-/// Ensures that the Maker is alive and responding.
-///
-// In future, handshake can be used to find protocol compatibility across multiple versions.
-pub(crate) fn handshake_maker(socket: &mut TcpStream) -> Result<(), TakerError> {
-    send_message(
-        socket,
-        &TakerToMakerMessage::TakerHello(TakerHello {
-            protocol_version_min: 1,
-            protocol_version_max: 1,
-        }),
-    )?;
-    let msg_bytes = read_message(socket)?;
-    let msg: MakerToTakerMessage = serde_cbor::from_slice(&msg_bytes)?;
-
-    // Check that protocol version is always 1.
-    match msg {
-        MakerToTakerMessage::MakerHello(m) => {
-            if m.protocol_version_max == 1 && m.protocol_version_min == 1 {
-                Ok(())
-            } else {
-                Err(ProtocolError::WrongMessage {
-                    expected: "Only protocol version 1 is allowed".to_string(),
-                    received: format!(
-                        "min/max version  = {}/{}",
-                        m.protocol_version_min, m.protocol_version_max
-                    ),
-                }
-                .into())
-            }
+impl Handshake {
+    pub(crate) fn new() -> Self {
+        let nonce = new_session_nonce();
+        let (secret, session_key) = new_session_key();
+        let hello = TakerToMakerMessage::TakerHello(TakerHello {
+            protocol_version_min: PROTOCOL_VERSION,
+            protocol_version_max: PROTOCOL_VERSION,
+            session_nonce: Some(nonce),
+            session_key: Some(session_key),
+        });
+        Self {
+            hello,
+            nonce,
+            secret,
         }
-        any => Err((ProtocolError::WrongMessage {
-            expected: "MakerHello".to_string(),
-            received: format!("{}", any),
-        })
-        .into()),
+    }
+
+    /// Secret of the session key sent in the hello.
+    pub(crate) fn session_secret(&self) -> SecretKey {
+        self.secret
+    }
+
+    /// The hello to send, in the clear.
+    pub(crate) fn hello(&self) -> &TakerToMakerMessage {
+        &self.hello
+    }
+
+    /// Opens the session upon the `reply` of the maker to our hello, recorded in the `transcript` if given.
+    pub(crate) fn open_session(
+        self,
+        reply: MakerToTakerMessage,
+        transcript: Option<&Transcript>,
+    ) -> Result<Session, TakerError> {
+        let TakerAction::OpenSession(maker_nonce, identity) =
+            taker_transition(&awaited_reply(&self.hello), reply)?
+        else {
+            unreachable!("a MakerHello opens the session")
+        };
+        let mut session = Session::new(&self.nonce, &maker_nonce);
+        session.authenticate(&identity, &self.secret)?;
+        if let Some(transcript) = transcript {
+            session.record_into(transcript);
+        }
+        Ok(session)
     }
 }
 
-/// Request signatures for sender side of the hop. Attempt once.
-pub(crate) fn req_sigs_for_sender_once<S: SwapCoin>(
+/// The reply awaited after `request`, which must await one.
+pub(crate) fn awaited_reply(request: &TakerToMakerMessage) -> AwaitedReply {
+    AwaitedReply::after(request).expect("request awaiting a reply")
+}
+
+/// Sends `request` in the session, and reads the action upon the reply of the maker.
+fn request_maker(
     socket: &mut TcpStream,
+    session: &mut Session,
+    request: &TakerToMakerMessage,
+    max_message_size: u32,
+) -> Result<TakerAction, TakerError> {
+    session.send(socket, request)?;
+    let msg_bytes = read_message_with_limit(socket, max_message_size)?;
+    let msg: MakerToTakerMessage = session.decode(&msg_bytes)?;
+    Ok(taker_transition(&awaited_reply(request), msg)?)
+}
+
+/// The request of the contract signatures of the sender side of the hop, for `outgoing_swapcoins`.
+pub(crate) fn contract_sigs_request_for_sender<S: SwapCoin>(
     outgoing_swapcoins: &[S],
     maker_multisig_nonces: &[SecretKey],
     maker_hashlock_nonces: &[SecretKey],
     locktime: u16,
-) -> Result<ContractSigsForSender, TakerError> {
-    handshake_maker(socket)?;
+    contract_feerate: Option<f64>,
+) -> Result<ReqContractSigsForSender, WalletError> {
     let txs_info = maker_multisig_nonces
         .iter()
         .zip(maker_hashlock_nonces.iter())
@@ -125,57 +153,53 @@ pub(crate) fn req_sigs_for_sender_once<S: SwapCoin>(
             },
         )
         .collect::<Result<Vec<ContractTxInfoForSender>, WalletError>>()?;
+    Ok(ReqContractSigsForSender {
+        txs_info,
+        hashvalue: outgoing_swapcoins[0].get_hashvalue()?,
+        locktime,
+        contract_feerate_sat_vb: contract_feerate,
+    })
+}
 
-    send_message(
-        socket,
-        &TakerToMakerMessage::ReqContractSigsForSender(ReqContractSigsForSender {
-            txs_info,
-            hashvalue: outgoing_swapcoins[0].get_hashvalue()?,
-            locktime,
-        }),
-    )?;
-
-    let msg_bytes = read_message(socket)?;
-    let msg: MakerToTakerMessage = serde_cbor::from_slice(&msg_bytes)?;
-    let contract_sigs_for_sender = match msg {
-        MakerToTakerMessage::RespContractSigsForSender(m) => {
-            if m.sigs.len() != outgoing_swapcoins.len() {
-                return Err((ProtocolError::WrongNumOfSigs {
-                    expected: outgoing_swapcoins.len(),
-                    received: m.sigs.len(),
-                })
-                .into());
-            } else {
-                m
-            }
-        }
-        any => {
-            return Err((ProtocolError::WrongMessage {
-                expected: "RespContractSigsForSender".to_string(),
-                received: format!("{}", any),
-            })
-            .into());
-        }
+/// Request signatures for sender side of the hop. Attempt once.
+pub(crate) async fn req_sigs_for_sender_once<S: SwapCoin + Sync>(
+    connection: &mut MakerConnection,
+    outgoing_swapcoins: &[S],
+    maker_multisig_nonces: &[SecretKey],
+    maker_hashlock_nonces: &[SecretKey],
+    locktime: u16,
+    contract_feerate: Option<f64>,
+) -> Result<ContractSigsForSender, TakerError> {
+    let request = TakerToMakerMessage::ReqContractSigsForSender(contract_sigs_request_for_sender(
+        outgoing_swapcoins,
+        maker_multisig_nonces,
+        maker_hashlock_nonces,
+        locktime,
+        contract_feerate,
+    )?);
+    let TakerAction::VerifySenderSigs(contract_sigs_for_sender) =
+        connection.request(&request).await?
+    else {
+        unreachable!("sender signatures are verified")
     };
 
-    for (sig, outgoing_swapcoin) in contract_sigs_for_sender
+    let to_verify = contract_sigs_for_sender
         .sigs
         .iter()
         .zip(outgoing_swapcoins.iter())
-    {
-        outgoing_swapcoin.verify_contract_tx_sender_sig(sig)?;
-    }
+        .collect::<Vec<_>>();
+    par_try_for_each(&to_verify, |(sig, outgoing_swapcoin)| {
+        outgoing_swapcoin.verify_contract_tx_sender_sig(sig)
+    })?;
     Ok(contract_sigs_for_sender)
 }
 
 /// Request signatures for receiver side of the hop. Attempt once.
-pub(crate) fn req_sigs_for_recvr_once<S: SwapCoin>(
-    socket: &mut TcpStream,
+pub(crate) async fn req_sigs_for_recvr_once<S: SwapCoin + Sync>(
+    connection: &mut MakerConnection,
     incoming_swapcoins: &[S],
     receivers_contract_txes: &[Transaction],
 ) -> Result<ContractSigsForRecvr, TakerError> {
-    handshake_maker(socket)?;
-
     let txs_info = incoming_swapcoins
         .iter()
         .zip(receivers_contract_txes.iter())
@@ -185,41 +209,22 @@ pub(crate) fn req_sigs_for_recvr_once<S: SwapCoin>(
         })
         .collect::<Vec<ContractTxInfoForRecvr>>();
 
-    send_message(
-        socket,
-        &TakerToMakerMessage::ReqContractSigsForRecvr(ReqContractSigsForRecvr { txs: txs_info }),
-    )?;
-
-    let msg_bytes = read_message(socket)?;
-    let msg: MakerToTakerMessage = serde_cbor::from_slice(&msg_bytes)?;
-    let contract_sigs_for_recvr = match msg {
-        MakerToTakerMessage::RespContractSigsForRecvr(m) => {
-            if m.sigs.len() != incoming_swapcoins.len() {
-                return Err((ProtocolError::WrongNumOfSigs {
-                    expected: incoming_swapcoins.len(),
-                    received: m.sigs.len(),
-                })
-                .into());
-            } else {
-                m
-            }
-        }
-        any => {
-            return Err((ProtocolError::WrongMessage {
-                expected: "ContractSigsForRecvr".to_string(),
-                received: format!("{}", any),
-            })
-            .into());
-        }
+    let request =
+        TakerToMakerMessage::ReqContractSigsForRecvr(ReqContractSigsForRecvr { txs: txs_info });
+    let TakerAction::VerifyReceiverSigs(contract_sigs_for_recvr) =
+        connection.request(&request).await?
+    else {
+        unreachable!("receiver signatures are verified")
     };
 
-    for (sig, swapcoin) in contract_sigs_for_recvr
+    let to_verify = contract_sigs_for_recvr
         .sigs
         .iter()
         .zip(incoming_swapcoins.iter())
-    {
-        swapcoin.verify_contract_tx_receiver_sig(sig)?;
-    }
+        .collect::<Vec<_>>();
+    par_try_for_each(&to_verify, |(sig, swapcoin)| {
+        swapcoin.verify_contract_tx_receiver_sig(sig)
+    })?;
     Ok(contract_sigs_for_recvr)
 }
 
@@ -241,12 +246,13 @@ pub struct NextMakerInfo {
 }
 
 /// [Internal] Send a Proof funding to the maker and init next hop.
-pub(crate) fn send_proof_of_funding_and_init_next_hop(
-    socket: &mut TcpStream,
+pub(crate) async fn send_proof_of_funding_and_init_next_hop(
+    connection: &mut MakerConnection,
     tmi: ThisMakerInfo,
     npi: NextMakerInfo,
     hashvalue: Hash160,
     id: String,
+    contract_feerate: Option<f64>,
 ) -> Result<(ContractSigsAsRecvrAndSender, Vec<ScriptBuf>), TakerError> {
     // Send POF
     let next_coinswap_info = npi
@@ -266,39 +272,15 @@ pub(crate) fn send_proof_of_funding_and_init_next_hop(
         next_coinswap_info,
         refund_locktime: tmi.this_maker_refund_locktime,
         contract_feerate: MINER_FEE,
+        contract_feerate_sat_vb: contract_feerate,
         id,
     });
 
-    send_message(socket, &pof_msg)?;
-
     // Recv ContractSigsAsRecvrAndSender.
-    let msg_bytes = read_message(socket)?;
-    let msg: MakerToTakerMessage = serde_cbor::from_slice(&msg_bytes)?;
-    let contract_sigs_as_recvr_and_sender = match msg {
-        MakerToTakerMessage::ReqContractSigsAsRecvrAndSender(m) => {
-            if m.receivers_contract_txs.len() != tmi.funding_tx_infos.len() {
-                return Err((ProtocolError::WrongNumOfContractTxs {
-                    expected: tmi.funding_tx_infos.len(),
-                    received: m.receivers_contract_txs.len(),
-                })
-                .into());
-            } else if m.senders_contract_txs_info.len() != npi.next_peer_multisig_pubkeys.len() {
-                return Err((ProtocolError::WrongNumOfContractTxs {
-                    expected: m.senders_contract_txs_info.len(),
-                    received: npi.next_peer_multisig_pubkeys.len(),
-                })
-                .into());
-            } else {
-                m
-            }
-        }
-        any => {
-            return Err((ProtocolError::WrongMessage {
-                expected: "ContractSigsAsRecvrAndSender".to_string(),
-                received: format!("{}", any),
-            })
-            .into());
-        }
+    let TakerAction::SignNextHop(contract_sigs_as_recvr_and_sender) =
+        connection.request(&pof_msg).await?
+    else {
+        unreachable!("the next hop is signed")
     };
 
     let funding_tx_values = tmi
@@ -324,13 +306,10 @@ pub(crate) fn send_proof_of_funding_and_init_next_hop(
         .map(|i| i.funding_amount)
         .sum::<Amount>();
 
-    let coinswap_fees = calculate_coinswap_fee(
-        this_amount,
-        tmi.this_maker_refund_locktime,
-        tmi.this_maker.offer.base_fee,
-        tmi.this_maker.offer.amount_relative_fee_pct,
-        tmi.this_maker.offer.time_relative_fee_pct,
-    );
+    let coinswap_fees = tmi
+        .this_maker
+        .offer
+        .coinswap_fee(this_amount, tmi.this_maker_refund_locktime);
 
     let miner_fees_paid_by_taker = (tmi.funding_tx_infos.len() as u64) * MINER_FEE;
     let calculated_next_amount = this_amount - coinswap_fees - miner_fees_paid_by_taker;
@@ -392,8 +371,8 @@ pub(crate) fn send_proof_of_funding_and_init_next_hop(
 }
 
 /// Send hash preimage via the writer and read the response.
-pub(crate) fn send_hash_preimage_and_get_private_keys(
-    socket: &mut TcpStream,
+pub(crate) async fn send_hash_preimage_and_get_private_keys(
+    connection: &mut MakerConnection,
     senders_multisig_redeemscripts: &[ScriptBuf],
     receivers_multisig_redeemscripts: &[ScriptBuf],
     preimage: &Preimage,
@@ -404,40 +383,21 @@ pub(crate) fn send_hash_preimage_and_get_private_keys(
         preimage: *preimage,
     });
 
-    send_message(socket, &hash_preimage_msg)?;
-
-    let msg_bytes = read_message(socket)?;
-    let msg: MakerToTakerMessage = serde_cbor::from_slice(&msg_bytes)?;
-    let privkey_handover = match msg {
-        MakerToTakerMessage::RespPrivKeyHandover(m) => {
-            if m.multisig_privkeys.len() != receivers_multisig_redeemscripts.len() {
-                return Err((ProtocolError::WrongNumOfPrivkeys {
-                    expected: receivers_multisig_redeemscripts.len(),
-                    received: m.multisig_privkeys.len(),
-                })
-                .into());
-            } else {
-                m
-            }
-        }
-        any => {
-            return Err((ProtocolError::WrongMessage {
-                expected: "PrivkeyHandover".to_string(),
-                received: format!("{}", any),
-            })
-            .into());
-        }
+    let TakerAction::TakeOverPrivkeys(privkey_handover) =
+        connection.request(&hash_preimage_msg).await?
+    else {
+        unreachable!("the privkeys are taken over")
     };
 
     Ok(privkey_handover)
 }
 
-fn download_maker_offer_attempt_once(
+/// Connects to a maker and makes the handshake. Returns the connection, and its session.
+pub(crate) fn connect_and_handshake_maker(
     addr: &MakerAddress,
     config: &TakerConfig,
-) -> Result<Offer, TakerError> {
+) -> Result<(TcpStream, Session), TakerError> {
     let maker_addr = addr.to_string();
-    log::info!("Attempting to download Offer from {}", maker_addr);
     let mut socket = match config.connection_type {
         ConnectionType::CLEARNET => TcpStream::connect(&maker_addr)?,
         #[cfg(feature = "tor")]
@@ -448,26 +408,51 @@ fn download_maker_offer_attempt_once(
         .into_inner(),
     };
 
-    socket.set_read_timeout(Some(Duration::from_secs(FIRST_CONNECT_ATTEMPT_TIMEOUT_SEC)))?;
-    socket.set_write_timeout(Some(Duration::from_secs(FIRST_CONNECT_ATTEMPT_TIMEOUT_SEC)))?;
+    socket.set_read_timeout(Some(config.connect_timeout()))?;
+    socket.set_write_timeout(Some(config.connect_timeout()))?;
 
-    handshake_maker(&mut socket)?;
+    let session = handshake_maker(&mut socket, config.max_message_size, None)?;
 
-    send_message(&mut socket, &TakerToMakerMessage::ReqGiveOffer(GiveOffer))?;
+    Ok((socket, session))
+}
 
-    let msg_bytes = read_message(&mut socket)?;
-    let msg: MakerToTakerMessage = serde_cbor::from_slice(&msg_bytes)?;
-    let offer = match msg {
-        MakerToTakerMessage::RespOffer(offer) => offer,
-        msg => {
-            return Err(ProtocolError::WrongMessage {
-                expected: "RespOffer".to_string(),
-                received: format!("{}", msg),
-            }
-            .into());
-        }
+pub(crate) fn download_maker_offer_attempt_once(
+    addr: &MakerAddress,
+    config: &TakerConfig,
+) -> Result<Offer, TakerError> {
+    let maker_addr = addr.to_string();
+    log::info!("Attempting to download Offer from {}", maker_addr);
+    let (mut socket, mut session) = connect_and_handshake_maker(addr, config)?;
+    socket.set_read_timeout(Some(config.phase_timeout(ProtocolPhase::OfferFetch)))?;
+
+    let request = TakerToMakerMessage::ReqGiveOffer(GiveOffer);
+    let TakerAction::TakeOffer(offer) =
+        request_maker(&mut socket, &mut session, &request, config.max_message_size)?
+    else {
+        unreachable!("offers are taken")
     };
 
+    // The identity bound to the offer must be the one holding the session.
+    let identity = offer.identity.as_ref().map(|binding| &binding.identity);
+    if identity.is_none() || session.peer_identity() != identity {
+        return Err(ProtocolError::WrongMessage {
+            expected: format!("Offer from the identity {:?}", identity),
+            received: format!("Session with the identity {:?}", session.peer_identity()),
+        }
+        .into());
+    }
+
+    if !offer.has_valid_contract_feerates() {
+        return Err(ProtocolError::WrongMessage {
+            expected: "Offer with a valid contract feerate range".to_string(),
+            received: format!(
+                "Contract feerates {}..={} sat/vb",
+                offer.min_contract_feerate, offer.max_contract_feerate
+            ),
+        }
+        .into());
+    }
+
     log::info!("Got offer from : {} ", maker_addr);
 
     Ok(*offer)
@@ -484,15 +469,15 @@ pub(crate) fn download_maker_offer(
         match download_maker_offer_attempt_once(&address, &config) {
             Ok(offer) => return Some(OfferAndAddress { offer, address }),
             Err(e) => {
-                if ii <= FIRST_CONNECT_ATTEMPTS {
+                if ii <= config.first_connect_attempts {
                     log::warn!(
                         "Failed to request offer from maker {}, with error: {:?} reattempting {} of {}",
                         address,
                         e,
                         ii,
-                        FIRST_CONNECT_ATTEMPTS
+                        config.first_connect_attempts
                     );
-                    sleep(Duration::from_secs(FIRST_CONNECT_SLEEP_DELAY_SEC));
+                    sleep(config.retry_delay(ii));
                     continue;
                 } else {
                     log::error!(
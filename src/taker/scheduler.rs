@@ -0,0 +1,186 @@
+//! Scheduled and recurring swaps.
+//!
+//! A [SwapSchedule] describes when swaps should run and how much each one swaps. [Taker::run_schedule]
+//! keeps running swaps as per the schedule, so liquidity can be drip-fed through the market without user
+//! interaction.
+
+use std::{
+    sync::atomic::{AtomicBool, Ordering::Relaxed},
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use bitcoin::{
+    secp256k1::rand::{rngs::OsRng, RngCore},
+    Amount,
+};
+
+use super::{api::SwapParams, error::TakerError, Taker};
+
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// When the scheduled swaps run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScheduleTiming {
+    /// Every fixed interval.
+    Every(Duration),
+    /// After a random delay between `min` and `max`, for every run.
+    RandomInterval {
+        /// Minimum delay.
+        min: Duration,
+        /// Maximum delay.
+        max: Duration,
+    },
+    /// Every day at the given UTC time.
+    DailyAt {
+        /// Hour, 0-23.
+        hour: u8,
+        /// Minute, 0-59.
+        minute: u8,
+    },
+}
+
+/// A recurring swap schedule.
+#[derive(Debug, Clone, Copy)]
+pub struct SwapSchedule {
+    /// When the swaps run.
+    pub timing: ScheduleTiming,
+    /// Minimum amount of a run.
+    pub min_amount: Amount,
+    /// Maximum amount of a run. Each run swaps a random amount between the minimum and this.
+    pub max_amount: Amount,
+    /// Parameters of each swap. `send_amount` is overwritten for every run.
+    pub swap_params: SwapParams,
+    /// Number of runs. Runs forever if `None`.
+    pub runs: Option<u32>,
+}
+
+impl SwapSchedule {
+    /// Delay until the next run, from `now`.
+    pub(crate) fn next_delay(&self, now: SystemTime) -> Duration {
+        match self.timing {
+            ScheduleTiming::Every(interval) => interval,
+            ScheduleTiming::RandomInterval { min, max } => {
+                let spread = max.saturating_sub(min).as_secs();
+                min + Duration::from_secs(OsRng.next_u64() % (spread + 1))
+            }
+            ScheduleTiming::DailyAt { hour, minute } => {
+                let now = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+                let target = (hour as u64 % 24) * 3600 + (minute as u64 % 60) * 60;
+                let today = now % SECS_PER_DAY;
+                let delay = if target > today {
+                    target - today
+                } else {
+                    SECS_PER_DAY - today + target
+                };
+                Duration::from_secs(delay)
+            }
+        }
+    }
+
+    /// Random amount of the next run.
+    pub(crate) fn next_amount(&self) -> Amount {
+        let min = self.min_amount.to_sat();
+        let spread = self.max_amount.to_sat().saturating_sub(min);
+        Amount::from_sat(min + OsRng.next_u64() % (spread + 1))
+    }
+}
+
+impl Taker {
+    /// Runs swaps as per the [SwapSchedule], until all runs are done or `shutdown` is set.
+    ///
+    /// A failed run is logged and the schedule continues. Failed swaps are recovered by the Taker as usual.
+    /// The offer book is kept up to date between the runs with a subscription to a directory, see
+    /// [Taker::subscribe_offerbook], or by polling the directories if none supports it.
+    pub fn run_schedule(
+        &mut self,
+        schedule: &SwapSchedule,
+        shutdown: &AtomicBool,
+    ) -> Result<(), TakerError> {
+        if let Err(e) = self.subscribe_offerbook() {
+            log::warn!(
+                "Could not subscribe to the offer book, polling the directories: {:?}",
+                e
+            );
+        }
+
+        let mut run = 0;
+        while schedule.runs.is_none_or(|runs| run < runs) {
+            let delay = schedule.next_delay(SystemTime::now());
+            log::info!("Next scheduled swap in {} secs", delay.as_secs());
+            let start = Instant::now();
+            while start.elapsed() < delay {
+                if shutdown.load(Relaxed) {
+                    log::info!("Swap schedule stopped.");
+                    return Ok(());
+                }
+                thread::sleep(Duration::from_secs(1));
+            }
+
+            run += 1;
+            let swap_params = SwapParams {
+                send_amount: schedule.next_amount(),
+                ..schedule.swap_params
+            };
+            log::info!(
+                "Starting scheduled swap {} of {} sats",
+                run,
+                swap_params.send_amount.to_sat()
+            );
+            if let Err(e) = self.do_coinswap(swap_params) {
+                log::error!("Scheduled swap {} failed: {:?}", run, e);
+            }
+        }
+        log::info!("Swap schedule completed.");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schedule(timing: ScheduleTiming) -> SwapSchedule {
+        SwapSchedule {
+            timing,
+            min_amount: Amount::from_sat(100_000),
+            max_amount: Amount::from_sat(200_000),
+            swap_params: SwapParams::default(),
+            runs: Some(1),
+        }
+    }
+
+    #[test]
+    fn test_schedule_timing() {
+        let now = UNIX_EPOCH + Duration::from_secs(10 * SECS_PER_DAY + 12 * 3600);
+
+        let every = schedule(ScheduleTiming::Every(Duration::from_secs(60)));
+        assert_eq!(every.next_delay(now), Duration::from_secs(60));
+
+        let random = schedule(ScheduleTiming::RandomInterval {
+            min: Duration::from_secs(60),
+            max: Duration::from_secs(120),
+        });
+        let delay = random.next_delay(now);
+        assert!(delay >= Duration::from_secs(60) && delay <= Duration::from_secs(120));
+
+        // Later today.
+        let evening = schedule(ScheduleTiming::DailyAt {
+            hour: 18,
+            minute: 30,
+        });
+        assert_eq!(
+            evening.next_delay(now),
+            Duration::from_secs(6 * 3600 + 1800)
+        );
+
+        // Already passed today, runs tomorrow.
+        let morning = schedule(ScheduleTiming::DailyAt { hour: 6, minute: 0 });
+        assert_eq!(morning.next_delay(now), Duration::from_secs(18 * 3600));
+
+        for _ in 0..100 {
+            let amount = every.next_amount();
+            assert!(amount >= every.min_amount && amount <= every.max_amount);
+        }
+    }
+}
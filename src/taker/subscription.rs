@@ -0,0 +1,166 @@
+//! Offer book subscription of long running takers.
+//!
+//! [Taker::subscribe_offerbook](super::Taker::subscribe_offerbook) keeps a connection open to a directory,
+//! which streams the makers joining, leaving and updating, see [crate::market::subscription]. The following
+//! offer book syncs then only download the offers of the makers which changed, or whose offer expired, instead
+//! of asking the directories for the full list each time: fewer round trips over Tor.
+//!
+//! The updates are read by a background thread. Once the connection drops, the next sync polls the
+//! directories and subscribes again.
+
+use std::{
+    sync::{
+        mpsc::{self, Receiver, TryRecvError},
+        Mutex,
+    },
+    thread::Builder,
+};
+
+use crate::{
+    market::subscription::{OfferBookUpdate, HEARTBEAT_INTERVAL},
+    protocol::{messages::DnsRequest, wire::decode},
+    utill::{read_message, send_message, ConnectionType},
+};
+
+use super::{
+    error::TakerError,
+    offers::{connect_to_dns, MakerAddress},
+};
+
+/// Heartbeats missed before the subscription is considered dead.
+const MISSED_HEARTBEATS: u32 = 3;
+
+/// An open offer book subscription to a directory.
+pub(crate) struct OfferBookSubscription {
+    /// Address of the directory.
+    pub(crate) directory: String,
+    /// Behind a lock, for the Taker to be shared across the threads of an async runtime.
+    updates: Mutex<Receiver<OfferBookUpdate>>,
+}
+
+impl OfferBookSubscription {
+    /// The updates received since the last call. `None` once the subscription dropped: updates may have been
+    /// missed, the offer book must be synced in full.
+    pub(crate) fn pending_updates(&self) -> Option<Vec<OfferBookUpdate>> {
+        let receiver = self
+            .updates
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let mut updates = Vec::new();
+        loop {
+            match receiver.try_recv() {
+                Ok(OfferBookUpdate::Heartbeat) => continue,
+                Ok(update) => updates.push(update),
+                Err(TryRecvError::Empty) => return Some(updates),
+                Err(TryRecvError::Disconnected) => return None,
+            }
+        }
+    }
+}
+
+/// Subscribes to the offer book updates of a directory. Returns the makers it lists, and the subscription.
+pub(crate) fn subscribe_to_directory(
+    socks_port: Option<u16>,
+    directory: String,
+    connection_type: ConnectionType,
+) -> Result<(Vec<MakerAddress>, OfferBookSubscription), TakerError> {
+    let mut stream = connect_to_dns(socks_port, &directory, connection_type)?;
+    send_message(&mut stream, &DnsRequest::Subscribe)?;
+    let addresses: Vec<String> = decode(&read_message(&mut stream)?)?;
+    let addresses = addresses
+        .iter()
+        .map(|address| MakerAddress::new(address))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    stream.set_read_timeout(Some(HEARTBEAT_INTERVAL * MISSED_HEARTBEATS))?;
+    let (updates_writer, updates) = mpsc::channel();
+    let thread_directory = directory.clone();
+    Builder::new()
+        .name(format!("offerbook_subscription_thread_{}", directory))
+        .spawn(move || loop {
+            let update = read_message(&mut stream)
+                .map_err(TakerError::from)
+                .and_then(|buf| Ok(decode::<OfferBookUpdate>(&buf)?));
+            match update {
+                Ok(update) => {
+                    if updates_writer.send(update).is_err() {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    log::warn!(
+                        "Offer book subscription to {} dropped: {:?}",
+                        thread_directory,
+                        e
+                    );
+                    break;
+                }
+            }
+        })?;
+
+    Ok((
+        addresses,
+        OfferBookSubscription {
+            directory,
+            updates: Mutex::new(updates),
+        },
+    ))
+}
+
+/// The net effect of offer book updates: the makers whose offer must be downloaded again, and the makers gone.
+pub(crate) fn offerbook_changes(
+    updates: &[OfferBookUpdate],
+) -> (Vec<MakerAddress>, Vec<MakerAddress>) {
+    let mut changed = Vec::new();
+    let mut left = Vec::new();
+    for update in updates {
+        let (address, is_left) = match update {
+            OfferBookUpdate::Joined(address) | OfferBookUpdate::Updated(address) => {
+                (address, false)
+            }
+            OfferBookUpdate::Left(address) => (address, true),
+            OfferBookUpdate::Heartbeat => continue,
+        };
+        let address = match MakerAddress::new(address) {
+            Ok(address) => address,
+            Err(_) => {
+                log::warn!("Ignoring update of invalid maker address {}", address);
+                continue;
+            }
+        };
+        changed.retain(|pending| pending != &address);
+        left.retain(|pending| pending != &address);
+        if is_left {
+            left.push(address);
+        } else {
+            changed.push(address);
+        }
+    }
+    (changed, left)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_offerbook_changes() {
+        let address = |address: &str| MakerAddress::new(address).unwrap();
+        let updates = [
+            OfferBookUpdate::Joined("maker1:6102".to_string()),
+            OfferBookUpdate::Heartbeat,
+            OfferBookUpdate::Left("maker2:6102".to_string()),
+            OfferBookUpdate::Updated("maker3:6102".to_string()),
+            // Back after leaving, and gone after joining.
+            OfferBookUpdate::Joined("maker2:6102".to_string()),
+            OfferBookUpdate::Left("maker1:6102".to_string()),
+            OfferBookUpdate::Joined("invalid".to_string()),
+        ];
+        let (changed, left) = offerbook_changes(&updates);
+        assert_eq!(
+            changed,
+            vec![address("maker3:6102"), address("maker2:6102")]
+        );
+        assert_eq!(left, vec![address("maker1:6102")]);
+    }
+}
@@ -0,0 +1,406 @@
+//! Terminal view of a Taker's swap.
+//!
+//! Driven by the Taker's [Event] stream: renders the hop graph of the route, the progress through the
+//! swap phases, the funding confirmations pending and the fees accrued so far. Used by `taker coinswap --tui`.
+//!
+//! Only available with the `tui` feature.
+
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicBool, Ordering::Relaxed},
+        mpsc::{Receiver, TryRecvError},
+    },
+    time::Duration,
+};
+
+use bitcoin::{Amount, Txid};
+use ratatui::{
+    crossterm::event::{self, Event as TermEvent, KeyCode, KeyEventKind},
+    layout::{Constraint, Direction, Layout},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Paragraph, Row, Table},
+    DefaultTerminal, Frame,
+};
+
+use crate::events::{Event, SwapPhase};
+
+use super::error::TakerError;
+
+/// Refresh interval of the view.
+const REFRESH_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Number of events kept for the event log.
+const MAX_LOG_LINES: usize = 100;
+
+/// Outcome of the swap, as far as the events tell.
+#[derive(Debug, Clone, PartialEq)]
+enum SwapStatus {
+    Running,
+    Completed,
+    Recovering(String),
+}
+
+/// State of the swap, built from the events.
+#[derive(Debug)]
+struct SwapView {
+    swap_id: Option<String>,
+    amount: Amount,
+    status: SwapStatus,
+    /// Makers of the route, in hop order.
+    route: Vec<String>,
+    phase: Option<SwapPhase>,
+    /// Latest `(confirmations, required)` of each funding tx.
+    confirmations: BTreeMap<Txid, (u32, u32)>,
+    maker_fees: Amount,
+    miner_fees: Amount,
+    log: Vec<String>,
+}
+
+impl Default for SwapView {
+    fn default() -> Self {
+        Self {
+            swap_id: None,
+            amount: Amount::ZERO,
+            status: SwapStatus::Running,
+            route: Vec::new(),
+            phase: None,
+            confirmations: BTreeMap::new(),
+            maker_fees: Amount::ZERO,
+            miner_fees: Amount::ZERO,
+            log: Vec::new(),
+        }
+    }
+}
+
+impl SwapView {
+    fn apply(&mut self, event: Event) {
+        self.log.push(event.to_string());
+        if self.log.len() > MAX_LOG_LINES {
+            self.log.remove(0);
+        }
+
+        match event {
+            Event::SwapStarted { swap_id, amount } => {
+                *self = Self {
+                    swap_id: Some(swap_id),
+                    amount,
+                    log: std::mem::take(&mut self.log),
+                    ..Self::default()
+                };
+            }
+            Event::SwapCompleted { .. } => self.status = SwapStatus::Completed,
+            Event::RecoveryInitiated { reason, .. } => self.status = SwapStatus::Recovering(reason),
+            Event::SwapPhaseChanged { phase, .. } => self.phase = Some(phase),
+            Event::HopEstablished { hop, maker, .. } => {
+                if hop < self.route.len() {
+                    self.route[hop] = maker;
+                } else {
+                    self.route.push(maker);
+                }
+            }
+            Event::FundingConfirmations {
+                txid,
+                confirmations,
+                required,
+                ..
+            } => {
+                self.confirmations.insert(txid, (confirmations, required));
+            }
+            Event::FeesAccrued {
+                maker_fees,
+                miner_fees,
+                ..
+            } => {
+                self.maker_fees = maker_fees;
+                self.miner_fees = miner_fees;
+            }
+            _ => {}
+        }
+    }
+
+    /// Hop currently being set up. `None` before the first maker is funded, and once all hops are set up.
+    fn active_hop(&self) -> Option<usize> {
+        match self.phase {
+            Some(SwapPhase::FirstHopFunding) => Some(0),
+            Some(SwapPhase::HopSetup { hop, .. }) => Some(hop + 1),
+            _ => None,
+        }
+    }
+
+    /// Number of makers of the route, once known.
+    fn maker_count(&self) -> usize {
+        match self.phase {
+            Some(SwapPhase::HopSetup { maker_count, .. }) => maker_count,
+            _ => self.route.len(),
+        }
+    }
+}
+
+/// Renders the swap progress published on `events` until `finished` is set, then waits for a key press.
+/// Pressing `q` or `Esc` closes the view early, the swap keeps running.
+pub fn run_swap_dashboard(
+    events: Receiver<Event>,
+    finished: &AtomicBool,
+) -> Result<(), TakerError> {
+    let mut terminal = ratatui::init();
+    let result = dashboard_loop(&mut terminal, &events, finished);
+    ratatui::restore();
+    result
+}
+
+fn dashboard_loop(
+    terminal: &mut DefaultTerminal,
+    events: &Receiver<Event>,
+    finished: &AtomicBool,
+) -> Result<(), TakerError> {
+    let mut view = SwapView::default();
+    loop {
+        loop {
+            match events.try_recv() {
+                Ok(event) => view.apply(event),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    finished.store(true, Relaxed);
+                    break;
+                }
+            }
+        }
+        let done = finished.load(Relaxed);
+        terminal.draw(|frame| draw(frame, &view, done))?;
+
+        if event::poll(REFRESH_INTERVAL)? {
+            if let TermEvent::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press
+                    && (done || matches!(key.code, KeyCode::Char('q') | KeyCode::Esc))
+                {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+fn draw(frame: &mut Frame, view: &SwapView, done: bool) {
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(5),
+            Constraint::Length(view.maker_count() as u16 + 4),
+            Constraint::Length(view.confirmations.len().max(1) as u16 + 3),
+            Constraint::Min(5),
+        ])
+        .split(frame.area());
+
+    let (status, status_style) = match (&view.status, done) {
+        (SwapStatus::Completed, _) => ("Completed".to_string(), Color::Green),
+        (SwapStatus::Recovering(reason), _) => (format!("Recovering: {}", reason), Color::Red),
+        (SwapStatus::Running, true) => ("Stopped".to_string(), Color::Red),
+        (SwapStatus::Running, false) => ("Running".to_string(), Color::Yellow),
+    };
+    let footer = if done {
+        " press any key to exit "
+    } else {
+        " q to close "
+    };
+    let header = vec![
+        Line::from(vec![
+            Span::raw(format!(
+                "Swap {} | {} | ",
+                view.swap_id.as_deref().unwrap_or("-"),
+                view.amount
+            )),
+            Span::styled(status, Style::default().fg(status_style)),
+        ]),
+        Line::from(format!(
+            "Fees | makers {} | miners {} | total {}",
+            view.maker_fees,
+            view.miner_fees,
+            view.maker_fees + view.miner_fees
+        )),
+        Line::from(hop_graph(view)),
+    ];
+    frame.render_widget(
+        Paragraph::new(header).block(Block::default().borders(Borders::ALL).title(footer)),
+        rows[0],
+    );
+
+    frame.render_widget(
+        Paragraph::new(phase_lines(view))
+            .block(Block::default().borders(Borders::ALL).title(" Phases ")),
+        rows[1],
+    );
+
+    let confirmations = Table::new(
+        view.confirmations
+            .iter()
+            .map(|(txid, (confirmations, required))| {
+                let style = if confirmations >= required {
+                    Style::default().fg(Color::Green)
+                } else {
+                    Style::default()
+                };
+                Row::new(vec![
+                    txid.to_string(),
+                    format!("{}/{}", confirmations, required),
+                ])
+                .style(style)
+            }),
+        [Constraint::Length(66), Constraint::Length(8)],
+    )
+    .header(
+        Row::new(vec!["Funding txid", "Confs"])
+            .style(Style::default().add_modifier(Modifier::BOLD)),
+    )
+    .block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(" Funding confirmations "),
+    );
+    frame.render_widget(confirmations, rows[2]);
+
+    // Show as many of the latest events as fit.
+    let log_height = rows[3].height.saturating_sub(2) as usize;
+    let log = view
+        .log
+        .iter()
+        .skip(view.log.len().saturating_sub(log_height))
+        .map(|line| Line::from(line.as_str()))
+        .collect::<Vec<_>>();
+    frame.render_widget(
+        Paragraph::new(log).block(Block::default().borders(Borders::ALL).title(" Events ")),
+        rows[3],
+    );
+}
+
+/// `Taker -> maker -> ... -> Taker`, with the hop being set up highlighted.
+fn hop_graph(view: &SwapView) -> Vec<Span<'static>> {
+    let highlight = Style::default()
+        .fg(Color::Yellow)
+        .add_modifier(Modifier::BOLD);
+    let mut spans = vec![Span::raw("Taker")];
+    for index in 0..view.maker_count() {
+        let arrow = if view.active_hop() == Some(index) {
+            Span::styled(" ==> ", highlight)
+        } else {
+            Span::raw(" --> ")
+        };
+        spans.push(arrow);
+        spans.push(Span::raw(
+            view.route
+                .get(index)
+                .cloned()
+                .unwrap_or_else(|| "?".to_string()),
+        ));
+    }
+    if !view.route.is_empty() {
+        let arrow = if view.active_hop() == Some(view.maker_count()) {
+            Span::styled(" ==> ", highlight)
+        } else {
+            Span::raw(" --> ")
+        };
+        spans.push(arrow);
+        spans.push(Span::raw("Taker"));
+    }
+    spans
+}
+
+/// One line per phase, marked done, ongoing or pending.
+fn phase_lines(view: &SwapView) -> Vec<Line<'static>> {
+    let maker_count = view.maker_count();
+    let phases = std::iter::once(SwapPhase::FirstHopFunding)
+        .chain((0..maker_count).map(|hop| SwapPhase::HopSetup { hop, maker_count }))
+        .chain(std::iter::once(SwapPhase::Settlement))
+        .collect::<Vec<_>>();
+    let current = view
+        .phase
+        .and_then(|phase| phases.iter().position(|p| *p == phase));
+
+    phases
+        .iter()
+        .enumerate()
+        .map(|(index, phase)| {
+            let (mark, style) = match current {
+                _ if view.status == SwapStatus::Completed => ("[x]", Color::Green),
+                Some(current) if index < current => ("[x]", Color::Green),
+                Some(current) if index == current => ("[>]", Color::Yellow),
+                _ => ("[ ]", Color::Reset),
+            };
+            Line::styled(format!("{} {}", mark, phase), Style::default().fg(style))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::hashes::Hash;
+
+    #[test]
+    fn test_swap_view_updates() {
+        let swap_id = "abcd".to_string();
+        let mut view = SwapView::default();
+        view.apply(Event::SwapStarted {
+            swap_id: swap_id.clone(),
+            amount: Amount::from_sat(500_000),
+        });
+        view.apply(Event::SwapPhaseChanged {
+            swap_id: swap_id.clone(),
+            phase: SwapPhase::FirstHopFunding,
+        });
+        view.apply(Event::HopEstablished {
+            swap_id: swap_id.clone(),
+            hop: 0,
+            maker: "127.0.0.1:6102".to_string(),
+        });
+        assert_eq!(view.active_hop(), Some(0));
+
+        view.apply(Event::SwapPhaseChanged {
+            swap_id: swap_id.clone(),
+            phase: SwapPhase::HopSetup {
+                hop: 0,
+                maker_count: 2,
+            },
+        });
+        view.apply(Event::HopEstablished {
+            swap_id: swap_id.clone(),
+            hop: 1,
+            maker: "127.0.0.1:16102".to_string(),
+        });
+        let txid = Txid::all_zeros();
+        for confirmations in 0..2 {
+            view.apply(Event::FundingConfirmations {
+                swap_id: swap_id.clone(),
+                txid,
+                confirmations,
+                required: 1,
+            });
+        }
+        view.apply(Event::FeesAccrued {
+            swap_id: swap_id.clone(),
+            maker_fees: Amount::from_sat(1_500),
+            miner_fees: Amount::from_sat(2_000),
+        });
+
+        assert_eq!(view.route, ["127.0.0.1:6102", "127.0.0.1:16102"]);
+        assert_eq!(view.maker_count(), 2);
+        assert_eq!(view.active_hop(), Some(1));
+        assert_eq!(view.confirmations.get(&txid), Some(&(1, 1)));
+        assert_eq!(view.maker_fees + view.miner_fees, Amount::from_sat(3_500));
+        assert_eq!(phase_lines(&view).len(), 4);
+        assert_eq!(view.log.len(), 8);
+
+        view.apply(Event::SwapCompleted { swap_id });
+        assert_eq!(view.status, SwapStatus::Completed);
+
+        // A new swap resets the view, but keeps the event log.
+        view.apply(Event::SwapStarted {
+            swap_id: "efgh".to_string(),
+            amount: Amount::from_sat(100_000),
+        });
+        assert!(view.route.is_empty());
+        assert_eq!(view.status, SwapStatus::Running);
+        assert_eq!(view.log.len(), 10);
+    }
+}
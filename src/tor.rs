@@ -10,6 +10,8 @@ use std::{
 
 use libtor::{HiddenServiceVersion, LogDestination, LogLevel, Tor, TorAddress, TorFlag};
 
+use crate::wallet::WALLET_PASSPHRASE_ENV;
+
 /// Used as the main function in tor binary
 pub fn start_tor(socks_port: u16, port: u16, base_dir: String) -> Result<(), libtor::Error> {
     let hs_string = format!("{}/hs-dir/", base_dir);
@@ -43,6 +45,7 @@ pub fn spawn_tor(socks_port: u16, port: u16, base_dir: String) -> Result<Child,
             "-d",
             &base_dir,
         ])
+        .env_remove(WALLET_PASSPHRASE_ENV)
         .stdout(std::process::Stdio::piped())
         .stderr(std::process::Stdio::piped())
         .spawn()?;
@@ -1,35 +1,44 @@
 //! Various utility and helper functions for both Taker and Maker.
 
+#[cfg(feature = "maker")]
+use bitcoin::key::{rand::thread_rng, Keypair};
+#[cfg(feature = "directory")]
+use bitcoin::secp256k1::ecdsa::Signature;
 use bitcoin::{
     absolute::LockTime,
     hashes::Hash,
-    key::{rand::thread_rng, Keypair},
     secp256k1::{Message, Secp256k1, SecretKey},
-    Address, Amount, PublicKey, ScriptBuf, Transaction, WitnessProgram, WitnessVersion,
+    Address, Amount, Network, PublicKey, ScriptBuf, Transaction, WitnessProgram, WitnessVersion,
 };
 use bitcoind::bitcoincore_rpc::json::ListUnspentResultEntry;
 use log::LevelFilter;
-use log4rs::{
-    append::{console::ConsoleAppender, file::FileAppender},
-    config::{Appender, Logger, Root},
-    Config,
-};
 use serde::{Deserialize, Serialize};
 use std::{
+    cell::RefCell,
+    convert::TryFrom,
     env, fmt,
-    io::{BufReader, BufWriter, ErrorKind, Read},
+    io::{BufWriter, ErrorKind, Read},
     net::TcpStream,
     path::{Path, PathBuf},
     str::FromStr,
-    sync::Once,
+    sync::{Mutex, Once},
+};
+use tracing_subscriber::{
+    filter::{LevelFilter as TraceLevelFilter, Targets},
+    fmt as trace_fmt,
+    layer::SubscriberExt,
+    util::SubscriberInitExt,
+    Layer,
 };
 
+#[cfg(any(feature = "maker", feature = "directory"))]
+use std::time::Instant;
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     fs::{self, File},
-    io::{self, BufRead, Write},
+    io::{self, Write},
     thread,
-    time::Duration,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use crate::{
@@ -38,6 +47,7 @@ use crate::{
         contract::derive_maker_pubkey_and_nonce,
         error::ProtocolError,
         messages::{FidelityProof, MultisigPrivkey},
+        wire,
     },
     wallet::{fidelity_redeemscript, FidelityError, SwapCoin, UTXOSpendInfo, WalletError},
 };
@@ -53,12 +63,17 @@ const CHECKSUM_FINAL_XOR_VALUE: u64 = 1;
 /// Global timeout for all network connections.
 pub(crate) const NET_TIMEOUT: Duration = Duration::from_secs(60);
 
-/// Used as delays on reattempting some network communications.
-pub(crate) const GLOBAL_PAUSE: Duration = Duration::from_secs(10);
-
 /// Global heartbeat interval used during waiting periods in critical situations.
 pub(crate) const HEART_BEAT_INTERVAL: Duration = Duration::from_secs(3);
 
+/// Current unix timestamp, in seconds.
+pub(crate) fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
 /// Number of confirmation required funding transaction.
 pub const REQUIRED_CONFIRMS: u32 = 1;
 
@@ -101,6 +116,7 @@ impl fmt::Display for ConnectionType {
 }
 
 /// Read the tor address given a tor directory path
+#[cfg(any(feature = "maker", feature = "tor"))]
 pub(crate) fn get_tor_hostname(tor_dir: &Path) -> io::Result<String> {
     let hostname_file_path = tor_dir.join("hs-dir").join("hostname");
     let mut hostname_file = File::open(hostname_file_path)?;
@@ -140,101 +156,116 @@ pub(crate) fn get_dns_dir() -> PathBuf {
     get_data_dir().join("dns")
 }
 
+/// The tracing level filter of a [LevelFilter].
+fn trace_level(filter: LevelFilter) -> TraceLevelFilter {
+    match filter {
+        LevelFilter::Off => TraceLevelFilter::OFF,
+        LevelFilter::Error => TraceLevelFilter::ERROR,
+        LevelFilter::Warn => TraceLevelFilter::WARN,
+        LevelFilter::Info => TraceLevelFilter::INFO,
+        LevelFilter::Debug => TraceLevelFilter::DEBUG,
+        LevelFilter::Trace => TraceLevelFilter::TRACE,
+    }
+}
+
+/// A log file, appended to. Its directory is created if missing.
+fn log_file(path: PathBuf) -> Mutex<File> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).unwrap();
+    }
+    let file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .unwrap();
+    Mutex::new(file)
+}
+
 /// Sets up the logger for the taker component.
 ///
 /// This method initializes the logging configuration for the taker, directing logs to both
-/// the console and a file. It sets the `RUST_LOG` environment variable to provide default
-/// log levels and configures log4rs with the specified filter level for fine-grained control
+/// the console and a file, with the specified filter level for fine-grained control
 /// of log verbosity.
+///
+/// Logs are tracing events, the `log` records included. Each line shows the spans it's emitted in, like
+/// `swap{id=..}:peer{address=..}`, so a single swap can be filtered out of the log file.
 pub fn setup_taker_logger(filter: LevelFilter, is_stdout: bool) {
     Once::new().call_once(|| {
         // TODO: Get the custom datadir instead of the default.
-        let log_dir = get_taker_dir().join("debug.log");
-
-        let file_appender = FileAppender::builder().build(log_dir).unwrap();
-        let stdout = ConsoleAppender::builder().build();
-
-        let config =
-            Config::builder().appender(Appender::builder().build("file", Box::new(file_appender)));
-
-        let config = if is_stdout {
-            config.appender(Appender::builder().build("stdout", Box::new(stdout)))
-            //.logger(Logger::builder().appender("stdout").build("stdout", filter))
-        } else {
-            config
-        };
-
-        // Add appenders to the root logger
-        let root_logger = if is_stdout {
-            Root::builder()
-                .appender("file")
-                .appender("stdout")
-                .build(filter)
-        } else {
-            Root::builder().appender("file").build(filter)
-        };
-
-        let config = config.build(root_logger).unwrap();
-
-        log4rs::init_config(config).unwrap();
+        let log_file = log_file(get_taker_dir().join("debug.log"));
+
+        let file = trace_fmt::layer()
+            .with_ansi(false)
+            .with_writer(log_file)
+            .with_filter(trace_level(filter));
+        let stdout = is_stdout.then(|| trace_fmt::layer().with_filter(trace_level(filter)));
+
+        tracing_subscriber::registry()
+            .with(file)
+            .with(stdout)
+            .init();
     })
 }
 
 /// Sets up the logger for the maker component.
 ///
 /// This method initializes the logging configuration for the maker, directing logs to both
-/// the console and a file. It sets the `RUST_LOG` environment variable to provide default
-/// log levels and configures log4rs with the specified filter level for fine-grained control
-/// of log verbosity.
-pub fn setup_maker_logger(filter: LevelFilter) {
+/// the console and a file, with the specified filter level for fine-grained control
+/// of log verbosity. The lines of a taker connection show its `connection{peer=.. swap_id=..}` span.
+///
+/// Without `is_stdout`, logs only go to the file. Used when the terminal shows the dashboard.
+pub fn setup_maker_logger(filter: LevelFilter, is_stdout: bool) {
     Once::new().call_once(|| {
         // TODO: Get the custom datadir instead of the default.
-        let log_dir = get_maker_dir().join("debug.log");
-
-        let stdout = ConsoleAppender::builder().build();
-        let file_appender = FileAppender::builder().build(log_dir).unwrap();
-
-        let config = Config::builder()
-            .appender(Appender::builder().build("stdout", Box::new(stdout)))
-            .appender(Appender::builder().build("file", Box::new(file_appender)))
-            .logger(
-                Logger::builder()
-                    .appender("file")
-                    .build("coinswap::maker", filter),
-            )
-            .build(Root::builder().appender("stdout").build(filter))
-            .unwrap();
-
-        log4rs::init_config(config).unwrap();
+        let log_file = log_file(get_maker_dir().join("debug.log"));
+
+        let registry = tracing_subscriber::registry();
+        let file = trace_fmt::layer().with_ansi(false).with_writer(log_file);
+        if is_stdout {
+            registry
+                .with(file.with_filter(
+                    Targets::new().with_target("coinswap::maker", trace_level(filter)),
+                ))
+                .with(trace_fmt::layer().with_filter(trace_level(filter)))
+                .init();
+        } else {
+            registry.with(file.with_filter(trace_level(filter))).init();
+        }
     })
 }
 
 /// Sets up the logger for the directory component.
 ///
 /// This method initializes the logging configuration for the directory, directing logs to both
-/// the console and a file. It sets the `RUST_LOG` environment variable to provide default
-/// log levels and configures log4rs with the specified filter level for fine-grained control
+/// the console and a file, with the specified filter level for fine-grained control
 /// of log verbosity.
 pub fn setup_directory_logger(filter: LevelFilter) {
     Once::new().call_once(|| {
         // TODO: Get the custom datadir instead of the default.
-        let log_dir = get_dns_dir().join("debug.log");
-
-        let stdout = ConsoleAppender::builder().build();
-        let file_appender = FileAppender::builder().build(log_dir).unwrap();
-
-        let config = Config::builder()
-            .appender(Appender::builder().build("stdout", Box::new(stdout)))
-            .appender(Appender::builder().build("file", Box::new(file_appender)))
-            .logger(
-                Logger::builder()
-                    .appender("file")
-                    .build("coinswap::market", filter),
+        let log_file = log_file(get_dns_dir().join("debug.log"));
+
+        tracing_subscriber::registry()
+            .with(
+                trace_fmt::layer()
+                    .with_ansi(false)
+                    .with_writer(log_file)
+                    .with_filter(
+                        Targets::new().with_target("coinswap::market", trace_level(filter)),
+                    ),
             )
-            .build(Root::builder().appender("stdout").build(filter))
-            .unwrap();
+            .with(trace_fmt::layer().with_filter(trace_level(filter)))
+            .init();
+    })
+}
 
-        log4rs::init_config(config).unwrap();
+/// Sets up the logger for the recovery app.
+///
+/// Logs only go to the console, so recovery doesn't depend on any data directory.
+pub fn setup_recovery_logger(filter: LevelFilter) {
+    Once::new().call_once(|| {
+        tracing_subscriber::registry()
+            .with(trace_fmt::layer().with_filter(trace_level(filter)))
+            .init();
     })
 }
 
@@ -244,77 +275,186 @@ pub fn setup_directory_logger(filter: LevelFilter) {
 pub fn setup_logger(filter: LevelFilter) {
     Once::new().call_once(|| {
         env::set_var("RUST_LOG", "coinswap=info");
-        let taker_log_dir = get_taker_dir().join("debug.log");
-        let maker_log_dir = get_maker_dir().join("debug.log");
-        let directory_log_dir = get_dns_dir().join("debug.log");
-
-        let stdout = ConsoleAppender::builder().build();
-        let taker = FileAppender::builder().build(taker_log_dir).unwrap();
-        let maker = FileAppender::builder().build(maker_log_dir).unwrap();
-        let directory = FileAppender::builder().build(directory_log_dir).unwrap();
-        let config = Config::builder()
-            .appender(Appender::builder().build("stdout", Box::new(stdout)))
-            .appender(Appender::builder().build("taker", Box::new(taker)))
-            .appender(Appender::builder().build("maker", Box::new(maker)))
-            .appender(Appender::builder().build("directory", Box::new(directory)))
-            .logger(
-                Logger::builder()
-                    .appender("taker")
-                    .build("coinswap::taker", filter),
+        let taker = log_file(get_taker_dir().join("debug.log"));
+        let maker = log_file(get_maker_dir().join("debug.log"));
+        let directory = log_file(get_dns_dir().join("debug.log"));
+
+        tracing_subscriber::registry()
+            .with(trace_fmt::layer().with_filter(trace_level(filter)))
+            .with(
+                trace_fmt::layer()
+                    .with_ansi(false)
+                    .with_writer(taker)
+                    .with_filter(
+                        Targets::new().with_target("coinswap::taker", trace_level(filter)),
+                    ),
             )
-            .logger(
-                Logger::builder()
-                    .appender("maker")
-                    .build("coinswap::maker", log::LevelFilter::Info),
+            .with(
+                trace_fmt::layer()
+                    .with_ansi(false)
+                    .with_writer(maker)
+                    .with_filter(
+                        Targets::new().with_target("coinswap::maker", TraceLevelFilter::INFO),
+                    ),
             )
-            .logger(
-                Logger::builder()
-                    .appender("directory")
-                    .build("coinswap::market", log::LevelFilter::Info),
+            .with(
+                trace_fmt::layer()
+                    .with_ansi(false)
+                    .with_writer(directory)
+                    .with_filter(
+                        Targets::new().with_target("coinswap::market", TraceLevelFilter::INFO),
+                    ),
             )
-            .build(Root::builder().appender("stdout").build(filter))
-            .unwrap();
-        log4rs::init_config(config).unwrap();
+            .init();
     });
 }
 
-/// Send a length-appended Protocol or RPC Message through a stream.
-/// The first byte sent is the length of the actual message.
+/// Default maximum size of a network message, in bytes.
+/// The largest protocol messages, carrying the contract transactions of a hop, are a few kilobytes.
+pub const DEFAULT_MAX_MESSAGE_SIZE: u32 = 4 * 1024 * 1024;
+
+/// Message bodies are read in chunks of this size.
+const READ_CHUNK_SIZE: usize = 8 * 1024;
+
+/// Send a length-appended Protocol or RPC Message through a stream, in the versioned [wire] format.
+/// The first 4 bytes sent are the big-endian length of the actual message.
 pub fn send_message(
     socket_writer: &mut TcpStream,
     message: &impl serde::Serialize,
 ) -> Result<(), NetError> {
+    send_message_counted(socket_writer, message).map(|_| ())
+}
+
+/// Sends a message like [send_message], returning the bytes sent, with the length prefix.
+pub(crate) fn send_message_counted(
+    socket_writer: &mut TcpStream,
+    message: &impl serde::Serialize,
+) -> Result<usize, NetError> {
+    send_frame(socket_writer, &wire::encode(message)?)
+}
+
+/// Sends an encoded message, with its length prefix. Returns the bytes sent.
+pub(crate) fn send_frame(
+    socket_writer: &mut TcpStream,
+    msg_bytes: &[u8],
+) -> Result<usize, NetError> {
     let mut writer = BufWriter::new(socket_writer);
-    let msg_bytes = serde_cbor::ser::to_vec(message)?;
-    let msg_len = (msg_bytes.len() as u32).to_be_bytes();
+    let msg_len = u32::try_from(msg_bytes.len())
+        .map_err(|_| NetError::MessageTooLarge {
+            size: msg_bytes.len() as u64,
+            limit: u32::MAX as u64,
+        })?
+        .to_be_bytes();
     let mut to_send = Vec::with_capacity(msg_bytes.len() + msg_len.len());
     to_send.extend(msg_len);
     to_send.extend(msg_bytes);
     writer.write_all(&to_send)?;
     writer.flush()?;
-    Ok(())
+    Ok(to_send.len())
 }
 
-/// Reads a response byte_array from a given stream.
-/// Response can be any length-appended data, where the first byte is the length of the actual message.
+/// Path of an HTTP GET request, without its query.
+#[cfg(any(feature = "maker", feature = "directory"))]
+pub(crate) fn http_request_path(request: &str) -> Option<&str> {
+    let mut parts = request.lines().next()?.split_whitespace();
+    if parts.next() != Some("GET") {
+        return None;
+    }
+    parts.next()?.split('?').next()
+}
+
+/// Sends an HTTP response, closing the connection.
+#[cfg(any(feature = "maker", feature = "directory"))]
+pub(crate) fn send_http_response(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &str,
+) -> io::Result<()> {
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes())?;
+    stream.flush()
+}
+
+/// Reads a response byte_array from a given stream, of at most [DEFAULT_MAX_MESSAGE_SIZE] bytes.
+/// Response can be any length-appended data, where the first 4 bytes are the big-endian length of the actual message.
 pub fn read_message(reader: &mut TcpStream) -> Result<Vec<u8>, NetError> {
-    let mut reader = BufReader::new(reader);
+    read_message_with_limit(reader, DEFAULT_MAX_MESSAGE_SIZE)
+}
+
+/// Reads a length-appended message of at most `max_size` bytes from a given stream.
+///
+/// Larger frames are rejected from their length prefix, before reading the body. The buffer only grows with the
+/// bytes actually received, so a peer announcing a large frame can't make us allocate it without sending it.
+pub fn read_message_with_limit(reader: &mut TcpStream, max_size: u32) -> Result<Vec<u8>, NetError> {
+    read_frame(reader, max_size)
+}
+
+/// Reads a length-appended message of at most `max_size` bytes, fully received before the `deadline`.
+///
+/// Unlike a read timeout, which restarts with every byte received, the deadline bounds the whole message, so a
+/// peer trickling bytes can't hold the connection open.
+#[cfg(any(feature = "maker", feature = "directory"))]
+pub(crate) fn read_message_before(
+    reader: &mut TcpStream,
+    max_size: u32,
+    deadline: Instant,
+) -> Result<Vec<u8>, NetError> {
+    read_frame(&mut DeadlineReader { reader, deadline }, max_size)
+}
+
+/// A stream whose reads time out at a fixed deadline.
+#[cfg(any(feature = "maker", feature = "directory"))]
+pub(crate) struct DeadlineReader<'a> {
+    pub(crate) reader: &'a mut TcpStream,
+    pub(crate) deadline: Instant,
+}
+
+#[cfg(any(feature = "maker", feature = "directory"))]
+impl Read for DeadlineReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(io::Error::new(ErrorKind::TimedOut, "read deadline passed"));
+        }
+        self.reader.set_read_timeout(Some(remaining))?;
+        self.reader.read(buf)
+    }
+}
+
+/// Reads a length-prefixed frame. A read timing out fails it: on Unix a timeout surfaces as
+/// [ErrorKind::WouldBlock], which must not be retried, or a stalled peer would hold the read forever.
+/// Streams accepted from a non-blocking listener must be set back to blocking before reading.
+fn read_frame<R: Read>(reader: &mut R, max_size: u32) -> Result<Vec<u8>, NetError> {
+    // Unbuffered, so nothing past this frame is consumed from the stream.
     // length of incoming data
     let mut len_buff = [0u8; 4];
     reader.read_exact(&mut len_buff)?; // This can give UnexpectedEOF error if theres no data to read
-    let length = u32::from_be_bytes(len_buff);
+    let length = u32::from_be_bytes(len_buff) as usize;
+
+    if length > max_size as usize {
+        return Err(NetError::MessageTooLarge {
+            size: length as u64,
+            limit: max_size as u64,
+        });
+    }
 
     // the actual data
-    let mut buffer = vec![0; length as usize];
-    let mut total_read = 0;
+    let mut buffer = Vec::with_capacity(length.min(READ_CHUNK_SIZE));
+    let mut chunk = [0u8; READ_CHUNK_SIZE];
 
-    while total_read < length as usize {
-        match reader.read(&mut buffer[total_read..]) {
+    while buffer.len() < length {
+        let to_read = (length - buffer.len()).min(READ_CHUNK_SIZE);
+        match reader.read(&mut chunk[..to_read]) {
             Ok(0) => return Err(NetError::ReachedEOF), // Connection closed
-            Ok(n) => total_read += n,
-            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::Interrupted) => {
-                continue
-            }
+            Ok(n) => buffer.extend_from_slice(&chunk[..n]),
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
             Err(e) => return Err(e.into()),
         }
     }
@@ -332,6 +472,37 @@ pub(crate) fn check_and_apply_maker_private_keys<S: SwapCoin>(
     Ok(())
 }
 
+/// Run the fallible `f` over all `items`, spread across up to [`thread::available_parallelism`]
+/// scoped threads. Returns the first error found.
+///
+/// Used to verify signatures and contracts of a hop concurrently, which otherwise adds up for large
+/// `tx_count` swaps.
+pub(crate) fn par_try_for_each<T, E, F>(items: &[T], f: F) -> Result<(), E>
+where
+    T: Sync,
+    E: Send,
+    F: Fn(&T) -> Result<(), E> + Sync,
+{
+    let threads = thread::available_parallelism()
+        .map_or(1, |n| n.get())
+        .min(items.len());
+    if threads <= 1 {
+        return items.iter().try_for_each(f);
+    }
+
+    let chunk_size = items.len().div_ceil(threads);
+    let f = &f;
+    thread::scope(|s| {
+        let handles = items
+            .chunks(chunk_size)
+            .map(|chunk| s.spawn(move || chunk.iter().try_for_each(f)))
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .try_for_each(|h| h.join().expect("verification thread panicked"))
+    })
+}
+
 /// Generate The Maker's Multisig and HashLock keys and respective nonce values.
 /// Nonce values are random integers and resulting Pubkeys are derived by tweaking
 ///
@@ -404,6 +575,7 @@ pub(crate) fn get_hd_path_from_descriptor(descriptor: &str) -> Option<(&str, u32
 }
 
 /// Generates a keypair using the secp256k1 elliptic curve.
+#[cfg(feature = "maker")]
 pub(crate) fn generate_keypair() -> (PublicKey, SecretKey) {
     let keypair = Keypair::new(&Secp256k1::new(), &mut thread_rng());
     let pubkey = PublicKey {
@@ -424,30 +596,199 @@ pub(crate) fn redeemscript_to_scriptpubkey(
     Ok(ScriptBuf::new_witness_program(&witness_program))
 }
 
-/// Parses a TOML file into a HashMap of key-value pairs.
-pub(crate) fn parse_toml<P: AsRef<Path>>(path: P) -> io::Result<HashMap<String, String>> {
-    let content = fs::read_to_string(path)?;
+/// The settings of a config file, with their overrides from the environment.
+///
+/// Each `key = value` line of the file sets a key, `#` comments are skipped. The keys may follow the `[section]`
+/// header of the config, like `[taker_config]`, but another section is an error. An environment variable named
+/// after a key, upper-cased and prefixed, overrides it: `COINSWAP_MAKER_NETWORK_PORT` sets the `network_port` of
+/// the maker. CLI flags are applied by the apps over the loaded config, so a setting is taken from the CLI flag,
+/// the environment, the file, and the default, in that order.
+///
+/// Once the config read its keys, [ConfigMap::reject_unknown_keys] fails on the keys it didn't read, like a typo
+/// which would otherwise leave the setting at its default.
+#[derive(Debug, Default)]
+pub(crate) struct ConfigMap {
+    values: HashMap<String, String>,
+    env_prefix: String,
+    /// Keys set from the environment.
+    env_keys: Vec<String>,
+    /// Keys read by the config.
+    read_keys: RefCell<HashSet<String>>,
+}
 
-    let mut config_map = HashMap::new();
+impl ConfigMap {
+    /// Loads the config file, with the overrides of the environment variables starting with `env_prefix`. The
+    /// keys of the file may follow the header of `section`.
+    pub(crate) fn load<P: AsRef<Path>>(
+        path: P,
+        section: &str,
+        env_prefix: &str,
+    ) -> io::Result<Self> {
+        let content = fs::read_to_string(path)?;
+        Self::parse(&content, section, env_prefix, env::vars())
+    }
 
-    for line in content.lines().filter(|line| !line.is_empty()) {
-        if let Some((key, value)) = line.split_once('=') {
-            config_map.insert(key.trim().to_string(), value.trim().to_string());
+    fn parse(
+        content: &str,
+        section: &str,
+        env_prefix: &str,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> io::Result<Self> {
+        let mut config_map = Self {
+            env_prefix: env_prefix.to_string(),
+            ..Self::default()
+        };
+        for line in content.lines().map(str::trim) {
+            if line.starts_with('#') {
+                continue;
+            }
+            if let Some(header) = line.strip_prefix('[') {
+                if header.trim_end_matches(']').trim() != section {
+                    return Err(io::Error::new(
+                        ErrorKind::InvalidData,
+                        format!(
+                            "Unknown config section {}, expected [{}] or none",
+                            line, section
+                        ),
+                    ));
+                }
+                continue;
+            }
+            if let Some((key, value)) = line.split_once('=') {
+                config_map.insert(key.trim().to_string(), value);
+            }
+        }
+        for (name, value) in vars {
+            if let Some(key) = name.strip_prefix(env_prefix) {
+                let key = key.to_lowercase();
+                config_map.insert(key.clone(), &value);
+                config_map.env_keys.push(key);
+            }
+        }
+        Ok(config_map)
+    }
+
+    fn insert(&mut self, key: String, value: &str) {
+        // Values may be followed by a comment, and quotes are optional around them.
+        let value = value.split(" #").next().unwrap_or_default();
+        let value = value.trim().trim_matches('"').trim();
+        self.values.insert(key, value.to_string());
+    }
+
+    /// The value of a key, if set. The key is marked as read.
+    fn value(&self, key: &str) -> Option<&String> {
+        if let Ok(mut read_keys) = self.read_keys.try_borrow_mut() {
+            read_keys.insert(key.to_string());
         }
+        self.values.get(key)
     }
 
-    Ok(config_map)
+    /// Fails with an error naming the first key set but never read by the config, an unknown or misspelled key.
+    pub(crate) fn reject_unknown_keys(&self) -> io::Result<()> {
+        let read_keys = self.read_keys.borrow();
+        let mut unknown = self
+            .values
+            .keys()
+            .filter(|key| !read_keys.contains(*key))
+            .collect::<Vec<_>>();
+        unknown.sort();
+        match unknown.first() {
+            Some(key) => Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Unknown config key `{}`, from the {}",
+                    key,
+                    self.source(key)
+                ),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Where the value of a key comes from, for the error messages.
+    fn source(&self, key: &str) -> String {
+        if self.env_keys.iter().any(|env_key| env_key == key) {
+            format!(
+                "environment variable {}{}",
+                self.env_prefix,
+                key.to_uppercase()
+            )
+        } else {
+            "config file".to_string()
+        }
+    }
+
+    /// The value of a key, or the default if it's unset or empty. Fails with an error naming the key if the value
+    /// is invalid.
+    pub(crate) fn get<T: FromStr>(&self, key: &str, default: T) -> io::Result<T> {
+        match self.value(key).filter(|value| !value.is_empty()) {
+            Some(value) => value.parse::<T>().map_err(|_| {
+                io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "Invalid value {:?} for config key `{}`, from the {}",
+                        value,
+                        key,
+                        self.source(key)
+                    ),
+                )
+            }),
+            None => Ok(default),
+        }
+    }
+
+    /// The comma separated list of a key, or the default if it's unset.
+    pub(crate) fn get_list(&self, key: &str, default: Vec<String>) -> Vec<String> {
+        self.value(key)
+            .map(|value| parse_list(value))
+            .unwrap_or(default)
+    }
+
+    /// The comma separated list of a key, each entry parsed, or the default if it's unset. Fails with an error
+    /// naming the key if an entry is invalid.
+    #[cfg(feature = "maker")]
+    pub(crate) fn get_parsed_list<T: FromStr>(
+        &self,
+        key: &str,
+        default: Vec<T>,
+    ) -> io::Result<Vec<T>> {
+        match self.value(key) {
+            Some(value) => parse_list(value)
+                .iter()
+                .map(|item| {
+                    item.parse::<T>().map_err(|_| {
+                        io::Error::new(
+                            ErrorKind::InvalidData,
+                            format!(
+                                "Invalid entry {:?} for config key `{}`, from the {}",
+                                item,
+                                key,
+                                self.source(key)
+                            ),
+                        )
+                    })
+                })
+                .collect(),
+            None => Ok(default),
+        }
+    }
 }
 
-/// Parses a value of type T from an Option<&String>, returning the default if parsing fails or is None
-pub(crate) fn parse_field<T: std::str::FromStr>(value: Option<&String>, default: T) -> T {
+/// Parses a comma separated list, ignoring empty entries.
+pub(crate) fn parse_list(value: &str) -> Vec<String> {
     value
-        .and_then(|value| value.parse::<T>().ok())
-        .unwrap_or(default)
+        .split(',')
+        .map(|item| item.trim().trim_matches('"'))
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+        .collect()
 }
 
 /// Function to check if tor log contains a pattern
+#[cfg(feature = "tor")]
 pub(crate) fn monitor_log_for_completion(log_file: &Path, pattern: &str) -> io::Result<()> {
+    use std::io::BufRead;
+
     // TODO: Make this logic work for existing file with previous logs.
     let mut last_size = 0;
 
@@ -507,11 +848,12 @@ pub struct UTXO {
     amount: Amount,
     confirmations: u32,
     utxo_type: String,
+    frozen: bool,
 }
 
 impl UTXO {
-    /// Creates an UTXO from detailed internal utxo data
-    pub fn from_utxo_data(data: (ListUnspentResultEntry, UTXOSpendInfo)) -> Self {
+    /// Creates an UTXO from detailed internal utxo data, and whether it's frozen.
+    pub fn from_utxo_data(data: (ListUnspentResultEntry, UTXOSpendInfo), frozen: bool) -> Self {
         let addr = data
             .0
             .address
@@ -523,6 +865,7 @@ impl UTXO {
             amount: data.0.amount,
             confirmations: data.0.confirmations,
             utxo_type: data.1.to_string(),
+            frozen,
         }
     }
 }
@@ -584,6 +927,62 @@ pub fn parse_proxy_auth(s: &str) -> Result<(String, String), NetError> {
     Ok((user, passwd))
 }
 
+/// Default Bitcoin Core RPC address for a network.
+///
+/// Custom signets use the [`Network::Signet`] defaults too. They only differ by the node's
+/// `-signetchallenge`, and the apps get everything else from the node.
+pub fn default_rpc_address(network: Network) -> String {
+    let port = match network {
+        Network::Bitcoin => 8332,
+        Network::Testnet => 18332,
+        Network::Testnet4 => 48332,
+        Network::Signet => 38332,
+        _ => 18443,
+    };
+    format!("127.0.0.1:{}", port)
+}
+
+/// Directory server of the public Testnet4 market.
+pub const TESTNET4_DIRECTORY_ADDRESS: &str =
+    "bhbzkndgad52ojm75w4goii7xsi6ou73fzyvorxas7swg2snlto4c4ad.onion:8080";
+
+/// Well known directory servers of each network, bundled to bootstrap the offerbook of a first run.
+const BOOTSTRAP_DIRECTORIES: &[(Network, &[&str])] =
+    &[(Network::Testnet4, &[TESTNET4_DIRECTORY_ADDRESS])];
+
+/// Well known directory servers to bootstrap the offerbook from, for a network. Empty if none is known.
+pub fn bootstrap_directory_addresses(network: Network) -> &'static [&'static str] {
+    BOOTSTRAP_DIRECTORIES
+        .iter()
+        .find(|(bootstrap_network, _)| *bootstrap_network == network)
+        .map_or(&[], |(_, addresses)| addresses)
+}
+
+/// Well known directory server to bootstrap the offerbook from, for a network.
+pub fn bootstrap_directory_address(network: Network) -> Option<&'static str> {
+    bootstrap_directory_addresses(network).first().copied()
+}
+
+/// Picks the directory server to use on a network.
+///
+/// Default configs point to the Testnet4 directory. On other networks, it gets replaced by the
+/// network's bootstrap directory. Addresses set by the user are kept as is.
+pub(crate) fn directory_address_for_network(configured: &str, network: Network) -> String {
+    if configured != TESTNET4_DIRECTORY_ADDRESS || network == Network::Testnet4 {
+        return configured.to_string();
+    }
+    match bootstrap_directory_address(network) {
+        Some(address) => address.to_string(),
+        None => {
+            log::warn!(
+                "No known directory server for {}, set `directory_server_address` in the config",
+                network
+            );
+            configured.to_string()
+        }
+    }
+}
+
 /// Dns request metadata
 #[derive(Serialize, Deserialize, Debug)]
 pub struct DnsMetadata {
@@ -669,9 +1068,34 @@ pub(crate) fn verify_fidelity_checks(
     Ok(())
 }
 
+/// Seconds a signed directory removal stays valid, so it can't be replayed once the maker registers again.
+#[cfg(feature = "directory")]
+pub(crate) const DIRECTORY_REMOVAL_VALIDITY_SECS: u64 = 10 * 60;
+
+/// Verifies a maker's request, signed with its bond key at unix time `timestamp`, to remove its address from the directory.
+#[cfg(feature = "directory")]
+pub(crate) fn verify_directory_removal(
+    proof: &FidelityProof,
+    addr: &str,
+    timestamp: u64,
+    sig: &Signature,
+    now: u64,
+) -> Result<(), WalletError> {
+    if now.abs_diff(timestamp) > DIRECTORY_REMOVAL_VALIDITY_SECS {
+        return Err(FidelityError::General("Stale directory removal request".to_string()).into());
+    }
+
+    let secp = Secp256k1::new();
+    let removal_hash = proof.bond.generate_removal_hash(addr, timestamp);
+    let removal_message = Message::from_digest_slice(removal_hash.as_byte_array())?;
+    secp.verify_ecdsa(&removal_message, sig, &proof.bond.pubkey.inner)?;
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
-    use std::net::TcpListener;
+    use std::{net::TcpListener, time::Instant};
 
     use bitcoin::{
         blockdata::{opcodes::all, script::Builder},
@@ -691,12 +1115,14 @@ mod tests {
         let message = MakerToTakerMessage::MakerHello(MakerHello {
             protocol_version_min: 1,
             protocol_version_max: 100,
+            session_nonce: None,
+            identity: None,
         });
 
         thread::spawn(move || {
             let (mut socket, _) = listener.accept().unwrap();
             let msg_bytes = read_message(&mut socket).unwrap();
-            let msg: MakerToTakerMessage = serde_cbor::from_slice(&msg_bytes).unwrap();
+            let msg: MakerToTakerMessage = wire::decode(&msg_bytes).unwrap();
 
             if let MakerToTakerMessage::MakerHello(hello) = msg {
                 assert!(hello.protocol_version_min == 1 && hello.protocol_version_max == 100);
@@ -712,6 +1138,117 @@ mod tests {
         send_message(&mut stream, &message).unwrap();
     }
 
+    #[test]
+    fn test_read_message_size_limit() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let writer = thread::spawn(move || {
+            let mut stream = TcpStream::connect(address).unwrap();
+            send_message(&mut stream, &vec![7u8; 1000]).unwrap();
+            // Only the length prefix of a huge frame.
+            stream.write_all(&u32::MAX.to_be_bytes()).unwrap();
+        });
+
+        let (mut socket, _) = listener.accept().unwrap();
+        writer.join().unwrap();
+
+        let msg_bytes = read_message_with_limit(&mut socket, 2000).unwrap();
+        let msg: Vec<u8> = wire::decode(&msg_bytes).unwrap();
+        assert_eq!(msg, vec![7u8; 1000]);
+
+        match read_message(&mut socket) {
+            Err(NetError::MessageTooLarge { size, limit }) => {
+                assert_eq!(size, u32::MAX as u64);
+                assert_eq!(limit, DEFAULT_MAX_MESSAGE_SIZE as u64);
+            }
+            other => panic!("Expected MessageTooLarge, got {:?}", other),
+        }
+    }
+
+    #[cfg(any(feature = "maker", feature = "directory"))]
+    #[test]
+    fn test_read_message_deadline() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let writer = thread::spawn(move || {
+            let mut stream = TcpStream::connect(address).unwrap();
+            send_message(&mut stream, &vec![7u8; 10]).unwrap();
+            // Trickle a frame, one byte at a time.
+            for byte in 100u32.to_be_bytes() {
+                stream.write_all(&[byte]).unwrap();
+                thread::sleep(Duration::from_millis(200));
+            }
+            thread::sleep(Duration::from_secs(1));
+        });
+
+        let (mut socket, _) = listener.accept().unwrap();
+        let deadline = Instant::now() + Duration::from_millis(500);
+
+        let msg_bytes = read_message_before(&mut socket, 2000, deadline).unwrap();
+        let msg: Vec<u8> = wire::decode(&msg_bytes).unwrap();
+        assert_eq!(msg, vec![7u8; 10]);
+
+        match read_message_before(&mut socket, 2000, deadline) {
+            Err(NetError::IO(e)) => {
+                assert!(matches!(
+                    e.kind(),
+                    ErrorKind::TimedOut | ErrorKind::WouldBlock
+                ))
+            }
+            other => panic!("Expected a timeout, got {:?}", other),
+        }
+        assert!(Instant::now() < deadline + Duration::from_millis(500));
+        writer.join().unwrap();
+    }
+
+    #[test]
+    fn test_read_message_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let writer = thread::spawn(move || {
+            let mut stream = TcpStream::connect(address).unwrap();
+            // The length prefix, then a stall in the body.
+            stream.write_all(&100u32.to_be_bytes()).unwrap();
+            stream.write_all(&[1; 10]).unwrap();
+            thread::sleep(Duration::from_secs(2));
+        });
+
+        let (mut socket, _) = listener.accept().unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_millis(200)))
+            .unwrap();
+        let start = Instant::now();
+        match read_message(&mut socket) {
+            Err(NetError::IO(e)) => {
+                assert!(matches!(
+                    e.kind(),
+                    ErrorKind::TimedOut | ErrorKind::WouldBlock
+                ))
+            }
+            other => panic!("Expected a timeout, got {:?}", other),
+        }
+        assert!(start.elapsed() < Duration::from_secs(1));
+        writer.join().unwrap();
+    }
+
+    #[cfg(any(feature = "maker", feature = "directory"))]
+    #[test]
+    fn test_http_request_path() {
+        assert_eq!(
+            http_request_path("GET /ready?verbose=1 HTTP/1.1\r\nHost: localhost\r\n\r\n"),
+            Some("/ready")
+        );
+        assert_eq!(
+            http_request_path("GET /health HTTP/1.0\r\n\r\n"),
+            Some("/health")
+        );
+        assert_eq!(http_request_path("POST /health HTTP/1.1\r\n\r\n"), None);
+        assert_eq!(http_request_path(""), None);
+    }
+
     #[test]
     fn test_redeemscript_to_scriptpubkey_custom() {
         // Create a custom puzzle script
@@ -845,4 +1382,148 @@ mod tests {
             .unwrap();
         assert_eq!(returned_pubkey.to_string(), tweaked_pubkey.to_string());
     }
+
+    #[test]
+    fn test_par_try_for_each() {
+        let items = (0..100u32).collect::<Vec<_>>();
+        assert!(par_try_for_each(&items, |i| if *i < 100 { Ok(()) } else { Err(*i) }).is_ok());
+        assert_eq!(
+            par_try_for_each(&items, |i| if *i == 42 { Err(*i) } else { Ok(()) }),
+            Err(42)
+        );
+        assert!(par_try_for_each(&[] as &[u32], |_| Err(())).is_ok());
+    }
+
+    #[test]
+    fn test_directory_address_for_network() {
+        assert_eq!(
+            directory_address_for_network(TESTNET4_DIRECTORY_ADDRESS, Network::Testnet4),
+            TESTNET4_DIRECTORY_ADDRESS
+        );
+        assert_eq!(
+            directory_address_for_network("127.0.0.1:8080", Network::Signet),
+            "127.0.0.1:8080"
+        );
+        assert_eq!(
+            bootstrap_directory_address(Network::Testnet4),
+            Some(TESTNET4_DIRECTORY_ADDRESS)
+        );
+        assert_eq!(bootstrap_directory_address(Network::Regtest), None);
+        assert_eq!(
+            bootstrap_directory_addresses(Network::Testnet4),
+            &[TESTNET4_DIRECTORY_ADDRESS]
+        );
+        assert!(bootstrap_directory_addresses(Network::Regtest).is_empty());
+    }
+
+    #[cfg(all(feature = "maker", feature = "directory"))]
+    #[test]
+    fn test_verify_directory_removal() {
+        let secp = Secp256k1::new();
+        let (pubkey, privkey) = generate_keypair();
+        let bond = crate::wallet::FidelityBond {
+            outpoint: bitcoin::OutPoint::null(),
+            amount: Amount::from_sat(50_000),
+            lock_time: LockTime::from_height(500).unwrap(),
+            pubkey,
+            conf_height: 100,
+            cert_expiry: 1,
+        };
+        let cert_hash = bond.generate_cert_hash("maker.onion:6102");
+        let cert_sig = secp.sign_ecdsa(
+            &Message::from_digest_slice(cert_hash.as_byte_array()).unwrap(),
+            &privkey,
+        );
+        let proof = FidelityProof {
+            bond,
+            cert_hash,
+            cert_sig,
+        };
+
+        let now = 1_700_000_000;
+        let removal_hash = proof.bond.generate_removal_hash("maker.onion:6102", now);
+        let sig = secp.sign_ecdsa(
+            &Message::from_digest_slice(removal_hash.as_byte_array()).unwrap(),
+            &privkey,
+        );
+
+        assert!(verify_directory_removal(&proof, "maker.onion:6102", now, &sig, now + 5).is_ok());
+        // Signed for another address.
+        assert!(verify_directory_removal(&proof, "other.onion:6102", now, &sig, now).is_err());
+        // Replayed later.
+        assert!(verify_directory_removal(
+            &proof,
+            "maker.onion:6102",
+            now,
+            &sig,
+            now + DIRECTORY_REMOVAL_VALIDITY_SECS + 1
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_config_map() {
+        let content = "
+            # Listening port = 1
+            [maker_config]
+            network_port = 6102
+            socks_port = \"19050\" # Tor
+            banned_peers = 10.0.0.1, 10.0.0.2
+            notify_script =
+        ";
+        let vars = vec![
+            ("COINSWAP_TEST_SOCKS_PORT".to_string(), "19051".to_string()),
+            ("COINSWAP_TEST_BASE_FEE".to_string(), "fee".to_string()),
+            ("OTHER_NETWORK_PORT".to_string(), "1".to_string()),
+        ];
+        let config_map = ConfigMap::parse(content, "maker_config", "COINSWAP_TEST_", vars).unwrap();
+
+        assert!(!config_map.values.contains_key("# Listening port"));
+        assert_eq!(config_map.get("network_port", 0u16).unwrap(), 6102);
+        // The environment overrides the file.
+        assert_eq!(config_map.get("socks_port", 0u16).unwrap(), 19051);
+        assert_eq!(config_map.get("rpc_port", 6103u16).unwrap(), 6103);
+        assert_eq!(
+            config_map
+                .get("notify_script", "default".to_string())
+                .unwrap(),
+            "default"
+        );
+        assert_eq!(
+            config_map.get_list("banned_peers", Vec::new()),
+            vec!["10.0.0.1", "10.0.0.2"]
+        );
+
+        let error = config_map.get("base_fee", 0u64).unwrap_err().to_string();
+        assert!(error.contains("`base_fee`"));
+        assert!(error.contains("COINSWAP_TEST_BASE_FEE"));
+        let error = config_map
+            .get("network_port", false)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("`network_port`"));
+        assert!(error.contains("config file"));
+
+        // Every key set was read.
+        assert!(config_map.reject_unknown_keys().is_ok());
+        // A misspelled key is never read.
+        let config_map = ConfigMap::parse(
+            "network_prot = 6102",
+            "maker_config",
+            "COINSWAP_TEST_",
+            vec![],
+        )
+        .unwrap();
+        assert_eq!(config_map.get("network_port", 6103u16).unwrap(), 6103);
+        let error = config_map.reject_unknown_keys().unwrap_err().to_string();
+        assert!(error.contains("`network_prot`"));
+        // Keys of another section aren't the config's.
+        assert!(ConfigMap::parse(
+            "[taker_config]\nnetwork_port = 6102",
+            "maker_config",
+            "COINSWAP_TEST_",
+            vec![]
+        )
+        .is_err());
+    }
 }
@@ -3,34 +3,50 @@
 //! Currently, wallet synchronization is exclusively performed through RPC for makers.
 //! In the future, takers might adopt alternative synchronization methods, such as lightweight wallet solutions.
 
-use std::{convert::TryFrom, fmt::Display, path::PathBuf, str::FromStr};
+use std::{cmp::Reverse, convert::TryFrom, fmt::Display, path::PathBuf, str::FromStr, sync::Arc};
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use bip39::Mnemonic;
+#[cfg(feature = "maker")]
+use bitcoin::Script;
 use bitcoin::{
-    bip32::{ChildNumber, DerivationPath, Xpriv, Xpub},
+    bip32::{DerivationPath, Xpriv, Xpub},
     hashes::hash160::Hash as Hash160,
+    key::{Keypair, TapTweak},
     secp256k1,
     secp256k1::{Secp256k1, SecretKey},
-    sighash::{EcdsaSighashType, SighashCache},
-    Address, Amount, OutPoint, PublicKey, Script, ScriptBuf, Transaction, Txid,
+    sighash::{EcdsaSighashType, Prevouts, SighashCache, TapSighashType},
+    taproot, Address, AddressType, Amount, FeeRate, OutPoint, PublicKey, ScriptBuf, Transaction,
+    TxOut, Txid,
+};
+use bitcoind::bitcoincore_rpc::{
+    bitcoincore_rpc_json::{EstimateMode, GetAddressInfoResult, ListUnspentResultEntry},
+    Client, RpcApi,
 };
-use bitcoind::bitcoincore_rpc::{bitcoincore_rpc_json::ListUnspentResultEntry, Client, RpcApi};
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use std::path::Path;
 
 use crate::{
+    events::{Event, EventBus},
     protocol::contract,
     utill::{
-        compute_checksum, generate_keypair, get_hd_path_from_descriptor,
-        redeemscript_to_scriptpubkey,
+        compute_checksum, get_hd_path_from_descriptor, redeemscript_to_scriptpubkey, unix_time,
     },
 };
 
+#[cfg(feature = "maker")]
+use super::paths::TWEAKABLE_KEY_PATH;
 use super::{
+    backend::BlockchainBackend,
+    encryption::{wallet_passphrase, SwapStateKey},
     error::WalletError,
-    rpc::RPCConfig,
+    fees::FeeEstimator,
+    funding::AmountDistribution,
+    funding::InputConstraints,
+    paths::{scan_swap_key_indexes, KeyClass, SEED_ACCOUNT_PATH, TAPROOT_ACCOUNT_PATH},
+    rpc::{batch_call, RPCConfig},
     storage::WalletStore,
     swapcoin::{IncomingSwapCoin, OutgoingSwapCoin, SwapCoin, WalletSwapCoin},
 };
@@ -39,16 +55,31 @@ use super::{
 // data in the bitcoin core wallet
 // for example which privkey corresponds to a scriptpubkey is stored in hd paths
 
-const HARDENDED_DERIVATION: &str = "m/84'/1'/0'";
+/// Default number of unused addresses scanned past the last used one, on each keychain.
+#[cfg(feature = "integration-test")]
+pub const DEFAULT_GAP_LIMIT: u32 = 10;
+/// Default number of unused addresses scanned past the last used one, on each keychain.
+#[cfg(not(feature = "integration-test"))]
+pub const DEFAULT_GAP_LIMIT: u32 = 5000;
+
+/// Confirmation targets, in blocks, [Wallet::blocks_to_confirm] looks up estimates for.
+const CONFIRMATION_TARGETS: [u16; 9] = [2, 3, 6, 12, 24, 48, 144, 504, 1008];
 
 /// Represents a Bitcoin wallet with associated functionality and data.
 pub struct Wallet {
     pub(crate) rpc: Client,
-    wallet_file_path: PathBuf,
+    /// Chain queries and broadcasts of the swaps, see [Wallet::set_blockchain_backend].
+    pub(crate) chain: Arc<dyn BlockchainBackend>,
+    pub(super) wallet_file_path: PathBuf,
     pub(crate) store: WalletStore,
+    /// Source of feerate estimates tried before the node, see [Wallet::set_fee_estimator].
+    pub(crate) fee_estimator: Option<Box<dyn FeeEstimator>>,
+    /// Utxos contributed to payjoin proposals, kept locked until the payjoin or its fallback is broadcasted. Like
+    /// the locks of the node, they don't survive a restart.
+    pub(crate) payjoin_inputs: HashSet<OutPoint>,
 }
 
-/// Speicfy the keychain derivation path from [`HARDENDED_DERIVATION`]
+/// Speicfy the keychain derivation path from the account of the [ScriptType].
 /// Each kind represents an unhardened index value. Starting with External = 0.
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
 pub(crate) enum KeychainKind {
@@ -57,61 +88,135 @@ pub(crate) enum KeychainKind {
 }
 
 impl KeychainKind {
-    fn index_num(&self) -> u32 {
+    pub(crate) fn index_num(&self) -> u32 {
         match self {
             Self::External => 0,
             Self::Internal => 1,
         }
     }
+
+    /// Keychain of a derivation branch.
+    fn of_branch(branch: u32) -> Self {
+        if branch == Self::Internal.index_num() {
+            Self::Internal
+        } else {
+            Self::External
+        }
+    }
 }
 
-const WATCH_ONLY_SWAPCOIN_LABEL: &str = "watchonly_swapcoin_label";
+/// Derivation branch of a seed coin path, as `m/<account>/<branch>/<index>`.
+fn path_branch(path: &str) -> Option<u32> {
+    path.split('/')
+        .rev()
+        .nth(1)
+        .and_then(|branch| branch.parse().ok())
+}
 
-/// Enum representing different types of addresses to display.
-#[derive(Clone, PartialEq, Debug)]
-pub(crate) enum DisplayAddressType {
-    /// Display all types of addresses.
-    All,
-    /// Display information related to the master key.
-    MasterKey,
-    /// Display addresses derived from the seed.
-    Seed,
-    /// Display information related to incoming swap transactions.
-    IncomingSwap,
-    /// Display information related to outgoing swap transactions.
-    OutgoingSwap,
-    /// Display information related to swap transactions (both incoming and outgoing).
-    Swap,
-    /// Display information related to incoming contract transactions.
-    IncomingContract,
-    /// Display information related to outgoing contract transactions.
-    OutgoingContract,
-    /// Display information related to contract transactions (both incoming and outgoing).
-    Contract,
-    /// Display information related to fidelity bonds.
-    FidelityBond,
+/// Script type of the wallet's single signature addresses.
+///
+/// The wallet derives and tracks both types, each under its own account: P2WPKH under [`SEED_ACCOUNT_PATH`]
+/// as in BIP84, P2TR under [`TAPROOT_ACCOUNT_PATH`] as in BIP86. The external and internal branches of both
+/// accounts share one address index, so switching types doesn't reuse an index.
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+pub enum ScriptType {
+    /// Native segwit v0 addresses.
+    #[default]
+    P2wpkh,
+    /// Taproot addresses, spent via the key path.
+    P2tr,
 }
 
-impl FromStr for DisplayAddressType {
-    type Err = &'static str;
+impl ScriptType {
+    /// Account the keys of the type are derived under.
+    pub(super) fn account_path(&self) -> &'static str {
+        match self {
+            Self::P2wpkh => SEED_ACCOUNT_PATH,
+            Self::P2tr => TAPROOT_ACCOUNT_PATH,
+        }
+    }
+
+    /// Path of the seed coin at `index` of the keychain, from the master key.
+    pub(super) fn coin_path(&self, keychain: KeychainKind, index: u32) -> String {
+        format!("{}/{}/{}", self.account_path(), keychain.index_num(), index)
+    }
+
+    /// Script type of a seed coin path, as `m/<account>/<branch>/<index>`.
+    fn from_path(path: &str) -> Self {
+        if path.starts_with(&format!("{}/", TAPROOT_ACCOUNT_PATH)) {
+            Self::P2tr
+        } else {
+            Self::P2wpkh
+        }
+    }
+
+    /// Script type of an address, if it's one the wallet can derive.
+    pub(crate) fn of_address(address: &Address) -> Option<Self> {
+        match address.address_type() {
+            Some(AddressType::P2wpkh) => Some(Self::P2wpkh),
+            Some(AddressType::P2tr) => Some(Self::P2tr),
+            _ => None,
+        }
+    }
+
+    /// Script type of a script pubkey, if it's one the wallet can derive.
+    #[cfg(feature = "maker")]
+    pub(crate) fn of_script(script: &Script) -> Option<Self> {
+        if script.is_p2wpkh() {
+            Some(Self::P2wpkh)
+        } else if script.is_p2tr() {
+            Some(Self::P2tr)
+        } else {
+            None
+        }
+    }
+
+    /// Virtual size of an input of the type, spent via the key path.
+    #[cfg(feature = "maker")]
+    pub(crate) fn input_vsize(&self) -> u64 {
+        match self {
+            Self::P2wpkh => 68,
+            Self::P2tr => 58,
+        }
+    }
+
+    /// Descriptor of the type for the key expression.
+    fn descriptor(&self, key: &str) -> String {
+        match self {
+            Self::P2wpkh => format!("wpkh({})", key),
+            Self::P2tr => format!("tr({})", key),
+        }
+    }
+}
+
+impl FromStr for ScriptType {
+    type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(match s {
-            "all" => DisplayAddressType::All,
-            "masterkey" => DisplayAddressType::MasterKey,
-            "seed" => DisplayAddressType::Seed,
-            "incomingswap" => DisplayAddressType::IncomingSwap,
-            "outgoingswap" => DisplayAddressType::OutgoingSwap,
-            "swap" => DisplayAddressType::Swap,
-            "incomingcontract" => DisplayAddressType::IncomingContract,
-            "outgoingcontract" => DisplayAddressType::OutgoingContract,
-            "contract" => DisplayAddressType::Contract,
-            "fidelitybond" => DisplayAddressType::FidelityBond,
-            _ => Err("unknown type")?,
-        })
+        match s.to_lowercase().as_str() {
+            "p2wpkh" => Ok(Self::P2wpkh),
+            "p2tr" => Ok(Self::P2tr),
+            _ => Err(format!(
+                "Unknown script type {}, expected p2wpkh or p2tr",
+                s
+            )),
+        }
+    }
+}
+
+impl Display for ScriptType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::P2wpkh => write!(f, "p2wpkh"),
+            Self::P2tr => write!(f, "p2tr"),
+        }
     }
 }
 
+const WATCH_ONLY_SWAPCOIN_LABEL: &str = "watchonly_swapcoin_label";
+
 /// Enum representing additional data needed to spend a UTXO, in addition to `ListUnspentResultEntry`.
 // data needed to find information  in addition to ListUnspentResultEntry
 // about a UTXO required to spend it
@@ -137,6 +242,18 @@ pub enum UTXOSpendInfo {
     FidelityBondCoin { index: u32, input_value: Amount },
 }
 
+impl UTXOSpendInfo {
+    /// Whether this is a seed coin of the internal keychain, our change.
+    pub(crate) fn is_change(&self) -> bool {
+        match self {
+            UTXOSpendInfo::SeedCoin { path, .. } => {
+                path_branch(path).map(KeychainKind::of_branch) == Some(KeychainKind::Internal)
+            }
+            _ => false,
+        }
+    }
+}
+
 impl Display for UTXOSpendInfo {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match &self {
@@ -161,7 +278,10 @@ pub struct Balances {
     pub contract: Amount,
     /// All coins locked in fidelity bonds.
     pub fidelity: Amount,
-    /// Spendable amount in wallet (regular + swap balance).
+    /// Regular and swap coins frozen by the operator, see [Wallet::freeze].
+    #[serde(default)]
+    pub frozen: Amount,
+    /// Spendable amount in wallet (regular + swap balance, frozen coins excluded).
     pub spendable: Amount,
 }
 
@@ -171,17 +291,86 @@ impl Wallet {
     /// The path should include the full path for a wallet file.
     /// If the wallet file doesn't exist it will create a new wallet file.
     pub fn init(path: &Path, rpc_config: &RPCConfig) -> Result<Self, WalletError> {
+        let (wallet, mnemonic) = Self::create(path, rpc_config)?;
+        let words = mnemonic.words().collect::<Vec<_>>();
+        log::info!("Backup the Wallet Mnemonics. \n {:?}", words);
+        Ok(wallet)
+    }
+
+    /// Create a wallet at a given path from a new 12 words mnemonic, returned to be backed up.
+    /// The wallet has no history before the current block height.
+    pub(crate) fn create(
+        path: &Path,
+        rpc_config: &RPCConfig,
+    ) -> Result<(Self, Mnemonic), WalletError> {
+        let mnemonic = Mnemonic::generate(12)?;
+        let wallet_birthday = Client::try_from(rpc_config)?.get_block_count()?;
+        let wallet =
+            Self::from_mnemonic(path, rpc_config, &mnemonic, Some(wallet_birthday), false)?;
+        Ok((wallet, mnemonic))
+    }
+
+    /// Restore a wallet from its mnemonic at a given path. A wallet file already there is only overwritten with
+    /// `force`.
+    ///
+    /// The wallet is synced from the `wallet_birthday` height, or from genesis if unknown.
+    /// Only the seed coins are found again: swap coins and fidelity bonds are only kept in the wallet file. The
+    /// counters of the swap and contract keys are found again in the wallet's transactions, so that the keys
+    /// handed over in past swaps aren't used again, see [scan_swap_key_indexes].
+    pub(crate) fn restore(
+        path: &Path,
+        rpc_config: &RPCConfig,
+        mnemonic: &Mnemonic,
+        wallet_birthday: Option<u64>,
+        force: bool,
+    ) -> Result<Self, WalletError> {
+        let mut wallet = Self::from_mnemonic(path, rpc_config, mnemonic, wallet_birthday, force)?;
+        // The node's rescan of the seed descriptors finds the wallet's transactions since the birthday.
+        wallet.sync()?;
+        let since = wallet.rpc.get_block_hash(wallet_birthday.unwrap_or(0))?;
+        let mut seen = HashSet::new();
+        let txs = wallet
+            .rpc
+            .list_since_block(Some(&since), None, Some(true), None)?
+            .transactions
+            .into_iter()
+            .map(|entry| entry.info.txid)
+            .filter(|txid| seen.insert(*txid))
+            .map(|txid| {
+                Ok(wallet
+                    .rpc
+                    .get_transaction(&txid, Some(true))?
+                    .transaction()?)
+            })
+            .collect::<Result<Vec<_>, WalletError>>()?;
+        let (swap_key_index, contract_key_index) =
+            scan_swap_key_indexes(&wallet.store.master_key, &txs)?;
+        wallet.store.swap_key_index = swap_key_index;
+        wallet.store.contract_key_index = contract_key_index;
+        wallet.save_to_disk()?;
+        Ok(wallet)
+    }
+
+    /// A wallet with the seed of `mnemonic` at a given path. Errors if a wallet file is already there, unless
+    /// `overwrite`.
+    fn from_mnemonic(
+        path: &Path,
+        rpc_config: &RPCConfig,
+        mnemonic: &Mnemonic,
+        wallet_birthday: Option<u64>,
+        overwrite: bool,
+    ) -> Result<Self, WalletError> {
+        if path.exists() && !overwrite {
+            return Err(WalletError::General(format!(
+                "Wallet file {:?} already exists, not overwriting it",
+                path
+            )));
+        }
         let rpc = Client::try_from(rpc_config)?;
         let network = rpc.get_blockchain_info()?.chain;
 
         // Generate Master key
-        let master_key = {
-            let mnemonic = Mnemonic::generate(12)?;
-            let words = mnemonic.words().collect::<Vec<_>>();
-            log::info!("Backup the Wallet Mnemonics. \n {:?}", words);
-            let seed = mnemonic.to_entropy();
-            Xpriv::new_master(network, &seed)?
-        };
+        let master_key = Xpriv::new_master(network, &mnemonic.to_entropy())?;
 
         // Initialise wallet
         let file_name = path
@@ -191,20 +380,40 @@ impl Wallet {
             .expect("expected")
             .to_string();
 
-        let wallet_birthday = rpc.get_block_count()?;
-        let store = WalletStore::init(file_name, path, network, master_key, Some(wallet_birthday))?;
+        let swap_state_key = wallet_passphrase().map(|passphrase| SwapStateKey::new(&passphrase));
+        let store = WalletStore::init(
+            file_name,
+            path,
+            network,
+            master_key,
+            wallet_birthday,
+            swap_state_key,
+        )?;
 
         Ok(Self {
             rpc,
+            chain: Arc::new(Client::try_from(rpc_config)?),
             wallet_file_path: path.to_path_buf(),
             store,
+            fee_estimator: None,
+            payjoin_inputs: HashSet::new(),
         })
     }
 
     /// Load wallet data from file and connects to a core RPC.
-    /// The core rpc wallet name, and wallet_id field in the file should match.
+    /// The core rpc wallet name, and wallet_id field in the file should match. The swap coins are decrypted with
+    /// the passphrase read by [load_passphrase_file](super::load_passphrase_file), or else the one of the
+    /// [WALLET_PASSPHRASE_ENV](super::WALLET_PASSPHRASE_ENV) environment variable, if set. A wallet file in the clear
+    /// stays so until [Wallet::encrypt_swap_state] is called.
     pub(crate) fn load(path: &Path, rpc_config: &RPCConfig) -> Result<Wallet, WalletError> {
-        let store = WalletStore::read_from_disk(path)?;
+        let passphrase = wallet_passphrase();
+        let store = WalletStore::read_from_disk(path, passphrase.as_ref().map(|p| p.as_str()))?;
+        if passphrase.is_some() && store.swap_state_key.is_none() {
+            log::warn!(
+                "Wallet file {} is stored in the clear, the passphrase isn't used. Encrypt the wallet to use it.",
+                store.file_name
+            );
+        }
         if rpc_config.wallet_name != store.file_name {
             return Err(WalletError::General(format!(
                 "Wallet name of database file and core missmatch, expected {}, found {}",
@@ -218,8 +427,8 @@ impl Wallet {
         if store.network != network {
             log::error!(
                 "Wallet file is created for {}, backend Bitcoin Core is running on {}",
-                store.network.to_string(),
-                network.to_string()
+                store.network,
+                network
             );
             return Err(WalletError::General("Wrong Bitcoin Network".to_string()));
         }
@@ -231,11 +440,23 @@ impl Wallet {
             store.outgoing_swapcoins.len()
         );
 
-        Ok(Self {
+        let mut wallet = Self {
             rpc,
+            chain: Arc::new(Client::try_from(rpc_config)?),
             wallet_file_path: path.to_path_buf(),
             store,
-        })
+            fee_estimator: None,
+            payjoin_inputs: HashSet::new(),
+        };
+        wallet.migrate()?;
+        Ok(wallet)
+    }
+
+    /// Sets the backend of the chain queries and broadcasts of the swaps. The wallet's node by default.
+    ///
+    /// The wallet itself, its coins and addresses, stays with the node.
+    pub fn set_blockchain_backend(&mut self, backend: Arc<dyn BlockchainBackend>) {
+        self.chain = backend;
     }
 
     /// Update external index and saves to disk.
@@ -298,6 +519,23 @@ impl Wallet {
             .insert(coin.get_multisig_redeemscript(), coin.clone());
     }
 
+    /// Remembers the hashvalue of the contracts of a swap, to find its swap coins by the swap id.
+    pub(crate) fn record_swap_id(
+        &mut self,
+        swap_id: &str,
+        hashvalue: Hash160,
+    ) -> Result<(), WalletError> {
+        self.store
+            .swap_hashvalues
+            .insert(swap_id.to_string(), hashvalue);
+        self.save_to_disk()
+    }
+
+    /// Hashvalue of the contracts of a swap, by its id.
+    pub(crate) fn swap_hashvalue(&self, swap_id: &str) -> Option<Hash160> {
+        self.store.swap_hashvalues.get(swap_id).copied()
+    }
+
     /// Removes an incoming swap coin with the specified multisig redeem script from the wallet.
     pub(crate) fn remove_incoming_swapcoin(
         &mut self,
@@ -320,35 +558,41 @@ impl Wallet {
     }
 
     /// Calculates the total balances of different categories in the wallet.
-    /// Includes regular, swap, contract, fidelitly and spendable (regular + swap) utxos.
+    /// Includes regular, swap, contract, fidelitly, frozen and spendable (regular + swap, not frozen) utxos.
     /// Optionally takes in a list of UTXOs to reduce rpc call. If None is provided, the full list is fetched from core rpc.
     pub fn get_balances(
         &self,
         all_utxos: Option<&Vec<ListUnspentResultEntry>>,
     ) -> Result<Balances, WalletError> {
-        let regular = self
-            .list_descriptor_utxo_spend_info(all_utxos)?
+        let regular_utxos = self.list_descriptor_utxo_spend_info(all_utxos)?;
+        let regular = regular_utxos
             .iter()
             .fold(Amount::ZERO, |sum, (utxo, _)| sum + utxo.amount);
         let contract = self
             .list_live_timelock_contract_spend_info(all_utxos)?
             .iter()
             .fold(Amount::ZERO, |sum, (utxo, _)| sum + utxo.amount);
-        let swap = self
-            .list_incoming_swap_coin_utxo_spend_info(all_utxos)?
+        let swap_utxos = self.list_incoming_swap_coin_utxo_spend_info(all_utxos)?;
+        let swap = swap_utxos
             .iter()
             .fold(Amount::ZERO, |sum, (utxo, _)| sum + utxo.amount);
         let fidelity = self
             .list_fidelity_spend_info(all_utxos)?
             .iter()
             .fold(Amount::ZERO, |sum, (utxo, _)| sum + utxo.amount);
-        let spendable = regular + swap;
+        let frozen = regular_utxos
+            .iter()
+            .chain(swap_utxos.iter())
+            .filter(|(utxo, _)| self.is_frozen(utxo))
+            .fold(Amount::ZERO, |sum, (utxo, _)| sum + utxo.amount);
+        let spendable = regular + swap - frozen;
 
         Ok(Balances {
             regular,
             swap,
             contract,
             fidelity,
+            frozen,
             spendable,
         })
     }
@@ -370,6 +614,7 @@ impl Wallet {
     /// - Case 3: Previous output has an entry that doesn't match the contract => Reject
     ///
     /// The two cases are mostly the same, except for Case 1 in `proofoffunding`, which shouldn't happen.
+    #[cfg(feature = "maker")]
     pub(crate) fn does_prevout_match_cached_contract(
         &self,
         prevout: &OutPoint,
@@ -382,17 +627,53 @@ impl Wallet {
         })
     }
 
-    /// Dynamic address import count function. 10 for tests, 5000 for production.
+    /// Height of the last block the wallet synced up to. None before the first sync.
+    #[cfg(feature = "maker")]
+    pub(crate) fn last_synced_height(&self) -> Option<u64> {
+        self.store.last_synced_height
+    }
+
+    /// Number of addresses imported into the node per keychain. At least the gap limit, extended as
+    /// addresses get used.
     pub(crate) fn get_addrss_import_count(&self) -> u32 {
-        if cfg!(feature = "integration-test") {
-            10
-        } else {
-            5000
+        self.store.import_count.max(self.store.gap_limit)
+    }
+
+    /// Sets the number of unused addresses scanned past the last used one. Applies from the next sync.
+    pub fn set_gap_limit(&mut self, gap_limit: u32) -> Result<(), WalletError> {
+        if gap_limit == 0 {
+            return Err(WalletError::General(
+                "Gap limit must be positive".to_string(),
+            ));
+        }
+        self.store.gap_limit = gap_limit;
+        self.save_to_disk()
+    }
+
+    /// Extends the imported address range, when the used addresses come within the gap limit of its end.
+    /// Returns whether the range was extended. The new addresses are imported on the next sync.
+    pub(super) fn extend_import_range(&mut self) -> Result<bool, WalletError> {
+        let next_unused = self
+            .find_hd_next_index(KeychainKind::External)?
+            .max(self.find_hd_next_index(KeychainKind::Internal)?)
+            .max(self.store.external_index);
+        let needed = next_unused + self.store.gap_limit;
+        if needed <= self.get_addrss_import_count() {
+            return Ok(false);
         }
+        log::info!(
+            "Used addresses reached index {}, extending the scanned range to {} addresses",
+            next_unused,
+            needed
+        );
+        self.store.import_count = needed;
+        self.save_to_disk()?;
+        Ok(true)
     }
 
     /// Stores an entry into [`WalletStore`]'s prevout-to-contract map.
     /// If the prevout already existed with a contract script, this will update the existing contract.
+    #[cfg(feature = "maker")]
     pub(crate) fn cache_prevout_to_contract(
         &mut self,
         prevout: OutPoint,
@@ -409,64 +690,71 @@ impl Wallet {
 
     //pub(crate) fn get_recovery_phrase_from_file()
 
-    /// Wallet descriptors are derivable. Currently only supports two KeychainKind. Internal and External.
-    fn get_wallet_descriptors(&self) -> Result<HashMap<KeychainKind, String>, WalletError> {
+    /// Wallet descriptors are derivable. Internal and External keychains, for every [ScriptType].
+    pub(crate) fn get_wallet_descriptors(
+        &self,
+    ) -> Result<HashMap<(ScriptType, KeychainKind), String>, WalletError> {
         let secp = Secp256k1::new();
-        let wallet_xpub = Xpub::from_priv(
-            &secp,
-            &self
-                .store
-                .master_key
-                .derive_priv(&secp, &DerivationPath::from_str(HARDENDED_DERIVATION)?)?,
-        );
 
-        // Get descriptors for external and internal keychain. Other chains are not supported yet.
-        [KeychainKind::External, KeychainKind::Internal]
+        // Get descriptors for external and internal keychain of both script types.
+        [ScriptType::P2wpkh, ScriptType::P2tr]
             .iter()
-            .map(|keychain| {
+            .flat_map(|script_type| {
+                [KeychainKind::External, KeychainKind::Internal]
+                    .iter()
+                    .map(move |keychain| (*script_type, *keychain))
+            })
+            .map(|(script_type, keychain)| {
+                let account_xpub = Xpub::from_priv(
+                    &secp,
+                    &self.store.master_key.derive_priv(
+                        &secp,
+                        &DerivationPath::from_str(script_type.account_path())?,
+                    )?,
+                );
                 let descriptor_without_checksum =
-                    format!("wpkh({}/{}/*)", wallet_xpub, keychain.index_num());
+                    script_type.descriptor(&format!("{}/{}/*", account_xpub, keychain.index_num()));
                 let decriptor = format!(
                     "{}#{}",
                     descriptor_without_checksum,
                     compute_checksum(&descriptor_without_checksum)?
                 );
-                Ok((*keychain, decriptor))
+                Ok(((script_type, keychain), decriptor))
             })
             .collect()
     }
 
     /// Checks if the addresses derived from the wallet descriptor is imported upto full index range.
     /// Returns the list of descriptors not imported yet
-    /// Index range depend on [`WalletMode`].
-    /// Normal => 5000
-    /// Test => 6
+    /// The index range is [Wallet::get_addrss_import_count].
     pub(super) fn get_unimported_wallet_desc(&self) -> Result<Vec<String>, WalletError> {
-        let mut unimported = Vec::new();
-        for (_, descriptor) in self.get_wallet_descriptors()? {
-            let first_addr = self.rpc.derive_addresses(&descriptor, Some([0, 0]))?[0].clone();
+        let descriptors = self
+            .get_wallet_descriptors()?
+            .into_values()
+            .collect::<Vec<_>>();
+        let last_index = self.get_addrss_import_count() - 1;
 
-            let last_index = self.get_addrss_import_count() - 1;
-            let last_addr = self
-                .rpc
-                .derive_addresses(&descriptor, Some([last_index, last_index]))?[0]
-                .clone();
+        // Derive the first and last address of each descriptor, and query their import status,
+        // in two batched round trips instead of four calls per descriptor.
+        let derive_params = descriptors
+            .iter()
+            .flat_map(|d| [json!([d, [0, 0]]), json!([d, [last_index, last_index]])])
+            .collect::<Vec<_>>();
+        let derived: Vec<Vec<String>> = batch_call(&self.rpc, "deriveaddresses", &derive_params)?;
 
-            let first_addr_imported = self
-                .rpc
-                .get_address_info(&first_addr.assume_checked())?
-                .is_watchonly
-                .unwrap_or(false);
-            let last_addr_imported = self
-                .rpc
-                .get_address_info(&last_addr.assume_checked())?
-                .is_watchonly
-                .unwrap_or(false);
+        let info_params = derived
+            .iter()
+            .map(|addrs| json!([addrs[0]]))
+            .collect::<Vec<_>>();
+        let infos: Vec<GetAddressInfoResult> =
+            batch_call(&self.rpc, "getaddressinfo", &info_params)?;
 
-            if !first_addr_imported || !last_addr_imported {
-                unimported.push(descriptor);
-            }
-        }
+        let unimported = descriptors
+            .into_iter()
+            .zip(infos.chunks(2))
+            .filter(|(_, infos)| !infos.iter().all(|i| i.is_watchonly.unwrap_or(false)))
+            .map(|(descriptor, _)| descriptor)
+            .collect();
 
         Ok(unimported)
     }
@@ -483,7 +771,8 @@ impl Wallet {
         m_xpub.fingerprint().to_string()
     }
 
-    /// Locks the fidelity and live_contract utxos which are not considered for spending from the wallet.
+    /// Locks the fidelity and live_contract utxos which are not considered for spending from the wallet, and the
+    /// frozen and payjoin ones.
     pub fn lock_unspendable_utxos(&self) -> Result<(), WalletError> {
         self.rpc.unlock_unspent_all()?;
 
@@ -493,9 +782,12 @@ impl Wallet {
         let utxos_to_lock = &all_unspents
             .into_iter()
             .filter(|u| {
-                self.check_descriptor_utxo_or_swap_coin(u)
-                    .unwrap()
-                    .is_none()
+                self.is_frozen(u)
+                    || self.payjoin_inputs.contains(&OutPoint::new(u.txid, u.vout))
+                    || self
+                        .check_descriptor_utxo_or_swap_coin(u)
+                        .unwrap()
+                        .is_none()
             })
             .map(|u| OutPoint {
                 txid: u.txid,
@@ -566,15 +858,19 @@ impl Wallet {
                 let (fingerprint, addr_type, index) = ret;
 
                 let secp = Secp256k1::new();
-                let master_private_key = self
-                    .store
-                    .master_key
-                    .derive_priv(&secp, &DerivationPath::from_str(HARDENDED_DERIVATION)?)?;
-                if fingerprint == master_private_key.fingerprint(&secp).to_string() {
-                    return Ok(Some(UTXOSpendInfo::SeedCoin {
-                        path: format!("m/{}/{}", addr_type, index),
-                        input_value: utxo.amount,
-                    }));
+                // The fingerprint is the one of the account the descriptor was imported from.
+                for script_type in [ScriptType::P2wpkh, ScriptType::P2tr] {
+                    let account_key = self.store.master_key.derive_priv(
+                        &secp,
+                        &DerivationPath::from_str(script_type.account_path())?,
+                    )?;
+                    if fingerprint == account_key.fingerprint(&secp).to_string() {
+                        return Ok(Some(UTXOSpendInfo::SeedCoin {
+                            path: script_type
+                                .coin_path(KeychainKind::of_branch(addr_type), index as u32),
+                            input_value: utxo.amount,
+                        }));
+                    }
                 }
             } else {
                 //utxo might be one of our swapcoins
@@ -622,6 +918,9 @@ impl Wallet {
         let all_utxos = self
             .rpc
             .list_unspent(Some(0), Some(9999999), None, None, None)?;
+        // Don't offer the payjoin inputs again.
+        #[cfg(feature = "maker")]
+        self.lock_payjoin_inputs(&all_utxos)?;
         Ok(all_utxos)
     }
 
@@ -760,8 +1059,8 @@ impl Wallet {
         let unfinished_incomins = self
             .store
             .incoming_swapcoins
-            .iter()
-            .filter_map(|(_, ic)| {
+            .values()
+            .filter_map(|ic| {
                 if ic.other_privkey.is_none() {
                     Some(ic.clone())
                 } else {
@@ -772,8 +1071,8 @@ impl Wallet {
         let unfinished_outgoings = self
             .store
             .outgoing_swapcoins
-            .iter()
-            .filter_map(|(_, oc)| {
+            .values()
+            .filter_map(|oc| {
                 if oc.hash_preimage.is_none() {
                     Some(oc.clone())
                 } else {
@@ -817,7 +1116,8 @@ impl Wallet {
                 continue;
             }
             let (_, addr_type, index) = ret.expect("its not none");
-            if addr_type != keychain.index_num() {
+            // The script types share the index of the keychain.
+            if KeychainKind::of_branch(addr_type) != keychain {
                 continue;
             }
             max_index = std::cmp::max(max_index, index);
@@ -826,34 +1126,94 @@ impl Wallet {
     }
 
     /// Gets the next external address from the HD keychain.
+    ///
+    /// Every address is handed out once. Addresses which already received coins, e.g. from an older copy
+    /// of the wallet, are skipped.
     pub fn get_next_external_address(&mut self) -> Result<Address, WalletError> {
         let descriptors = self.get_wallet_descriptors()?;
         let receive_branch_descriptor = descriptors
-            .get(&KeychainKind::External)
+            .get(&(self.store.script_type, KeychainKind::External))
             .expect("external keychain expected");
-        let receive_address = self.rpc.derive_addresses(
-            receive_branch_descriptor,
-            Some([self.store.external_index, self.store.external_index]),
-        )?[0]
-            .clone();
-        self.update_external_index(self.store.external_index + 1)?;
-        Ok(receive_address.assume_checked()) // TODO: should we check the network or just assume_checked?
+        loop {
+            let receive_address = self.rpc.derive_addresses(
+                receive_branch_descriptor,
+                Some([self.store.external_index, self.store.external_index]),
+            )?[0]
+                .clone()
+                .assume_checked(); // TODO: should we check the network or just assume_checked?
+            self.update_external_index(self.store.external_index + 1)?;
+            if self.is_address_used(&receive_address) {
+                log::warn!("Skipping already used address {}", receive_address);
+                continue;
+            }
+            return Ok(receive_address);
+        }
+    }
+
+    /// Checks if the script belongs to one of the receive addresses handed out so far.
+    #[cfg(feature = "maker")]
+    pub(crate) fn is_receive_script(&self, script: &Script) -> Result<bool, WalletError> {
+        if self.store.external_index == 0 {
+            return Ok(false);
+        }
+        let descriptors = self.get_wallet_descriptors()?;
+        for script_type in [ScriptType::P2wpkh, ScriptType::P2tr] {
+            let receive_branch_descriptor = descriptors
+                .get(&(script_type, KeychainKind::External))
+                .expect("external keychain expected");
+            let addresses = self.rpc.derive_addresses(
+                receive_branch_descriptor,
+                Some([0, self.store.external_index - 1]),
+            )?;
+            if addresses
+                .into_iter()
+                .any(|addr| addr.assume_checked().script_pubkey().as_script() == script)
+            {
+                return Ok(true);
+            }
+        }
+        Ok(false)
     }
 
-    /// Gets the next internal addresses from the HD keychain.
+    /// Gets the next internal addresses from the HD keychain, of the wallet's script type.
     pub(crate) fn get_next_internal_addresses(
         &self,
         count: u32,
     ) -> Result<Vec<Address>, WalletError> {
-        let next_change_addr_index = self.find_hd_next_index(KeychainKind::Internal)?;
+        self.get_next_internal_addresses_typed(count, self.store.script_type)
+    }
+
+    /// Gets the next internal addresses from the HD keychain, of the given script type.
+    pub(crate) fn get_next_internal_addresses_typed(
+        &self,
+        count: u32,
+        script_type: ScriptType,
+    ) -> Result<Vec<Address>, WalletError> {
+        let next_change_addr_index = self.next_internal_index()?;
+        self.derive_internal_addresses(next_change_addr_index, count, script_type)
+    }
+
+    /// Next unused internal index. Skips the addresses reserved for claims, which may not have
+    /// received their coins yet.
+    fn next_internal_index(&self) -> Result<u32, WalletError> {
+        Ok(self
+            .find_hd_next_index(KeychainKind::Internal)?
+            .max(self.store.internal_index))
+    }
+
+    fn derive_internal_addresses(
+        &self,
+        from: u32,
+        count: u32,
+        script_type: ScriptType,
+    ) -> Result<Vec<Address>, WalletError> {
         let descriptors = self.get_wallet_descriptors()?;
         let change_branch_descriptor = descriptors
-            .get(&KeychainKind::Internal)
+            .get(&(script_type, KeychainKind::Internal))
             .expect("Internal Keychain expected");
-        let addresses = self.rpc.derive_addresses(
-            change_branch_descriptor,
-            Some([next_change_addr_index, next_change_addr_index + count]),
-        )?;
+        let addresses = self
+            .rpc
+            .derive_addresses(change_branch_descriptor, Some([from, from + count]))?;
 
         Ok(addresses
             .into_iter()
@@ -861,6 +1221,34 @@ impl Wallet {
             .collect())
     }
 
+    /// Reserves a fresh internal address of the given script type, to claim a contract to.
+    ///
+    /// Unlike [Wallet::get_next_internal_addresses], the index is advanced and saved, so every claim
+    /// pays to its own address even if none of them is broadcasted yet.
+    pub(crate) fn reserve_internal_address(
+        &mut self,
+        script_type: ScriptType,
+    ) -> Result<Address, WalletError> {
+        let index = self.next_internal_index()?;
+        let address = self
+            .derive_internal_addresses(index, 1, script_type)?
+            .remove(0);
+        self.store.internal_index = index + 1;
+        self.save_to_disk()?;
+        Ok(address)
+    }
+
+    /// Script type of the receive and change addresses.
+    pub fn script_type(&self) -> ScriptType {
+        self.store.script_type
+    }
+
+    /// Sets the script type of the receive and change addresses. Coins of both types stay spendable.
+    pub fn set_script_type(&mut self, script_type: ScriptType) -> Result<(), WalletError> {
+        self.store.script_type = script_type;
+        self.save_to_disk()
+    }
+
     /// Refreshes the offer maximum size cache based on the current wallet's unspent transaction outputs (UTXOs).
     pub(crate) fn refresh_offer_maxsize_cache(&mut self) -> Result<(), WalletError> {
         let balance = self.get_balances(None)?.spendable;
@@ -869,12 +1257,13 @@ impl Wallet {
     }
 
     /// Gets a tweakable key pair from the master key of the wallet.
+    #[cfg(feature = "maker")]
     pub(crate) fn get_tweakable_keypair(&self) -> Result<(SecretKey, PublicKey), WalletError> {
         let secp = Secp256k1::new();
         let privkey = self
             .store
             .master_key
-            .derive_priv(&secp, &[ChildNumber::from_hardened_idx(0)?])?
+            .derive_priv(&secp, &DerivationPath::from_str(TWEAKABLE_KEY_PATH)?)?
             .private_key;
 
         let public_key = PublicKey {
@@ -889,15 +1278,28 @@ impl Wallet {
         &self,
         tx: &mut Transaction,
         inputs_info: impl Iterator<Item = UTXOSpendInfo>,
+    ) -> Result<(), WalletError> {
+        self.sign_inputs(tx, inputs_info.enumerate())
+    }
+
+    /// Signs the inputs at the given indexes, leaving the other inputs untouched.
+    pub(crate) fn sign_inputs(
+        &self,
+        tx: &mut Transaction,
+        inputs_info: impl Iterator<Item = (usize, UTXOSpendInfo)>,
     ) -> Result<(), WalletError> {
         let secp = Secp256k1::new();
-        let master_private_key = self
-            .store
-            .master_key
-            .derive_priv(&secp, &DerivationPath::from_str(HARDENDED_DERIVATION)?)?;
+        // Seed coin paths are from the master key.
+        let master_private_key = self.store.master_key;
         let tx_clone = tx.clone();
-
-        for (ix, (input, input_info)) in tx.input.iter_mut().zip(inputs_info).enumerate() {
+        // All the spent outputs are committed to by taproot signatures. Fetched once, if needed.
+        let mut prevouts: Option<Vec<TxOut>> = None;
+
+        for (ix, input_info) in inputs_info {
+            let input = match tx.input.get_mut(ix) {
+                Some(input) => input,
+                None => break,
+            };
             match input_info {
                 UTXOSpendInfo::OutgoingSwapCoin { .. } => {
                     return Err(WalletError::General(
@@ -911,6 +1313,35 @@ impl Wallet {
                         .expect("incoming swapcoin missing")
                         .sign_transaction_input(ix, &tx_clone, input, &multisig_redeemscript)?;
                 }
+                UTXOSpendInfo::SeedCoin { path, .. }
+                    if ScriptType::from_path(&path) == ScriptType::P2tr =>
+                {
+                    let privkey = master_private_key
+                        .derive_priv(&secp, &DerivationPath::from_str(&path)?)?
+                        .private_key;
+                    if prevouts.is_none() {
+                        prevouts = Some(self.get_prevouts(&tx_clone)?);
+                    }
+                    let sighash = SighashCache::new(&tx_clone).taproot_key_spend_signature_hash(
+                        ix,
+                        &Prevouts::All(prevouts.as_ref().expect("fetched above")),
+                        TapSighashType::Default,
+                    )?;
+                    let keypair = Keypair::from_secret_key(&secp, &privkey)
+                        .tap_tweak(&secp, None)
+                        .to_keypair();
+                    let signature = secp.sign_schnorr_no_aux_rand(
+                        &secp256k1::Message::from_digest_slice(&sighash[..])?,
+                        &keypair,
+                    );
+                    input.witness.push(
+                        taproot::Signature {
+                            signature,
+                            sighash_type: TapSighashType::Default,
+                        }
+                        .to_vec(),
+                    );
+                }
                 UTXOSpendInfo::SeedCoin { path, input_value } => {
                     let privkey = master_private_key
                         .derive_priv(&secp, &DerivationPath::from_str(&path)?)?
@@ -975,24 +1406,23 @@ impl Wallet {
         Ok(())
     }
 
-    /// Largerst to lowest coinselect algorithm
+    /// Largerst to lowest coinselect algorithm. Dust and frozen UTXOs are never selected.
     pub fn coin_select(
         &self,
         amount: Amount,
     ) -> Result<Vec<(ListUnspentResultEntry, UTXOSpendInfo)>, WalletError> {
-        let all_utxos = self.get_all_locked_utxo()?;
-
-        let mut seed_coin_utxo = self.list_descriptor_utxo_spend_info(Some(&all_utxos))?;
-        let mut swap_coin_utxo = self.list_incoming_swap_coin_utxo_spend_info(Some(&all_utxos))?;
-        seed_coin_utxo.append(&mut swap_coin_utxo);
-
-        // Fetch utxos, filter out existing fidelity coins
-        let mut unspents = seed_coin_utxo
-            .into_iter()
-            .filter(|(_, spend_info)| !matches!(spend_info, UTXOSpendInfo::FidelityBondCoin { .. }))
-            .collect::<Vec<_>>();
+        self.coin_select_constrained(amount, &InputConstraints::default())
+    }
 
-        unspents.sort_by(|a, b| b.0.amount.cmp(&a.0.amount));
+    /// [Wallet::coin_select] among the UTXOs meeting the [InputConstraints]. The selection falls short of `amount`
+    /// when too few of them are left.
+    pub(crate) fn coin_select_constrained(
+        &self,
+        amount: Amount,
+        constraints: &InputConstraints,
+    ) -> Result<Vec<(ListUnspentResultEntry, UTXOSpendInfo)>, WalletError> {
+        let mut unspents = self.selectable_utxos(constraints)?;
+        unspents.sort_by_key(|(utxo, _)| Reverse(utxo.amount));
 
         let mut selected_utxo = Vec::new();
         let mut remaining = amount;
@@ -1010,6 +1440,80 @@ impl Wallet {
         Ok(selected_utxo)
     }
 
+    /// Total of the UTXOs [Wallet::coin_select_constrained] selects from, all a swap can spend. Unlike the
+    /// spendable balance, it leaves out the dust and frozen UTXOs, and the ones outside the [InputConstraints].
+    pub(crate) fn selectable_balance(
+        &self,
+        constraints: &InputConstraints,
+    ) -> Result<Amount, WalletError> {
+        Ok(self
+            .selectable_utxos(constraints)?
+            .iter()
+            .map(|(utxo, _)| utxo.amount)
+            .sum())
+    }
+
+    /// The UTXOs the coin selections pick from: neither fidelity bonds, dust nor frozen, and meeting the
+    /// [InputConstraints].
+    fn selectable_utxos(
+        &self,
+        constraints: &InputConstraints,
+    ) -> Result<Vec<(ListUnspentResultEntry, UTXOSpendInfo)>, WalletError> {
+        let all_utxos = self.get_all_locked_utxo()?;
+
+        let mut seed_coin_utxo = self.list_descriptor_utxo_spend_info(Some(&all_utxos))?;
+        let mut swap_coin_utxo = self.list_incoming_swap_coin_utxo_spend_info(Some(&all_utxos))?;
+        seed_coin_utxo.append(&mut swap_coin_utxo);
+
+        // Fetch utxos, filter out existing fidelity coins and dust
+        let mut unspents = seed_coin_utxo
+            .into_iter()
+            .filter(|(_, spend_info)| !matches!(spend_info, UTXOSpendInfo::FidelityBondCoin { .. }))
+            .filter(|(utxo, _)| !self.is_dust(utxo.amount, &utxo.script_pub_key))
+            .filter(|(utxo, _)| !self.is_frozen(utxo))
+            .collect::<Vec<_>>();
+
+        if *constraints != InputConstraints::default() {
+            let now = unix_time();
+            let mut allowed = Vec::new();
+            for (utxo, spend_info) in unspents {
+                let is_change = spend_info.is_change();
+                let confirmed_at = if constraints.min_age_secs > 0 && utxo.confirmations > 0 {
+                    self.rpc
+                        .get_transaction(&utxo.txid, Some(true))?
+                        .info
+                        .blocktime
+                } else {
+                    None
+                };
+                if constraints.allows(utxo.confirmations, is_change, confirmed_at, now) {
+                    allowed.push((utxo, spend_info));
+                } else {
+                    log::info!(
+                        "Not funding the swap with {}:{}, outside the input constraints",
+                        utxo.txid,
+                        utxo.vout
+                    );
+                }
+            }
+            unspents = allowed;
+        }
+        Ok(unspents)
+    }
+
+    /// Outputs spent by the inputs of the transaction, confirmed or in the mempool.
+    fn get_prevouts(&self, tx: &Transaction) -> Result<Vec<TxOut>, WalletError> {
+        tx.input
+            .iter()
+            .map(|input| {
+                let prevout = input.previous_output;
+                self.chain
+                    .unspent_output(&prevout, true)?
+                    .ok_or_else(|| WalletError::General(format!("Unknown prevout {}", prevout)))
+            })
+            .collect()
+    }
+
     pub(crate) fn get_utxo(
         &self,
         (txid, vout): (Txid, u32),
@@ -1033,7 +1537,7 @@ impl Wallet {
         &mut self,
         other_pubkey: &PublicKey,
     ) -> Result<(Address, SecretKey), WalletError> {
-        let (my_pubkey, my_privkey) = generate_keypair();
+        let (my_pubkey, my_privkey) = self.next_swap_keypairs(KeyClass::Swap, 1)?[0];
 
         let descriptor = self
             .rpc
@@ -1042,7 +1546,7 @@ impl Wallet {
                 my_pubkey, other_pubkey
             ))?
             .descriptor;
-        self.import_descriptors(&[descriptor.clone()], None)?;
+        self.import_descriptors(std::slice::from_ref(&descriptor), None)?;
 
         //redeemscript and descriptor show up in `getaddressinfo` only after
         // the address gets outputs on it-
@@ -1058,6 +1562,7 @@ impl Wallet {
 
     /// Initialize a Coinswap with the Other party.
     /// Returns, the Funding Transactions, [`OutgoingSwapCoin`]s and the Total Miner fees.
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn initalize_coinswap(
         &mut self,
         total_coinswap_amount: Amount,
@@ -1066,6 +1571,9 @@ impl Wallet {
         hashvalue: Hash160,
         locktime: u16,
         fee_rate: Amount,
+        contract_fee: Amount,
+        amount_distribution: AmountDistribution,
+        input_constraints: InputConstraints,
     ) -> Result<(Vec<Transaction>, Vec<OutgoingSwapCoin>, Amount), WalletError> {
         let (coinswap_addresses, my_multisig_privkeys): (Vec<_>, Vec<_>) = other_multisig_pubkeys
             .iter()
@@ -1074,8 +1582,13 @@ impl Wallet {
             .into_iter()
             .unzip();
 
-        let create_funding_txes_result =
-            self.create_funding_txes(total_coinswap_amount, &coinswap_addresses, fee_rate)?;
+        let create_funding_txes_result = self.create_funding_txes(
+            total_coinswap_amount,
+            &coinswap_addresses,
+            fee_rate,
+            amount_distribution,
+            input_constraints,
+        )?;
         //for sweeping there would be another function, probably
         //probably have an enum called something like SendAmount which can be
         // an integer but also can be Sweep
@@ -1097,7 +1610,8 @@ impl Wallet {
             .zip(other_multisig_pubkeys.iter())
             .zip(hashlock_pubkeys.iter())
         {
-            let (timelock_pubkey, timelock_privkey) = generate_keypair();
+            let (timelock_pubkey, timelock_privkey) =
+                self.next_swap_keypairs(KeyClass::Contract, 1)?[0];
             let contract_redeemscript = contract::create_contract_redeemscript(
                 hashlock_pubkey,
                 &timelock_pubkey,
@@ -1112,7 +1626,7 @@ impl Wallet {
                 },
                 funding_amount,
                 &contract_redeemscript,
-                fee_rate,
+                contract_fee,
             )?;
 
             // self.import_wallet_contract_redeemscript(&contract_redeemscript)?;
@@ -1237,8 +1751,152 @@ impl Wallet {
         Ok(descriptors_to_import)
     }
 
-    /// Uses internal RPC client to braodcast a transaction
+    /// Broadcasts a transaction through the chain backend.
     pub fn send_tx(&self, tx: &Transaction) -> Result<Txid, WalletError> {
-        Ok(self.rpc.send_raw_transaction(tx)?)
+        self.chain.broadcast(tx)
+    }
+
+    /// Blocks a transaction paying `feerate` sats/vB is expected to take to confirm, by the node's conservative
+    /// estimates. `u16::MAX` if it isn't expected to confirm within [CONFIRMATION_TARGETS]. None if the node
+    /// has no estimate.
+    pub(crate) fn blocks_to_confirm(&self, feerate: f64) -> Result<Option<u16>, WalletError> {
+        for conf_target in CONFIRMATION_TARGETS {
+            let estimate = self
+                .rpc
+                .estimate_smart_fee(conf_target, Some(EstimateMode::Conservative))?
+                .fee_rate;
+            match estimate {
+                // Estimates are in sats/kvB.
+                Some(fee_rate) if fee_rate.to_sat() as f64 / 1000.0 <= feerate => {
+                    return Ok(Some(conf_target))
+                }
+                Some(_) => continue,
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(u16::MAX))
+    }
+
+    /// Compare the feerate of a transaction against the estimate for confirming within `conf_target` blocks.
+    ///
+    /// Returns `Some((tx_feerate, estimated_feerate))` if the transaction pays less than the estimate.
+    /// Returns `None` if the feerate is sufficient, or can't be determined (unknown inputs, or no estimate available).
+    pub(crate) fn check_tx_feerate(
+        &self,
+        tx: &Transaction,
+        conf_target: u16,
+    ) -> Result<Option<(FeeRate, FeeRate)>, WalletError> {
+        let estimated = match self.estimate_feerate(conf_target)? {
+            // 1 vB is 4 weight units.
+            Some(feerate) => FeeRate::from_sat_per_kwu((feerate * 250.0) as u64),
+            None => return Ok(None),
+        };
+
+        let mut input_value = Amount::ZERO;
+        for input in &tx.input {
+            match self.chain.unspent_output(&input.previous_output, true)? {
+                Some(txout) => input_value += txout.value,
+                None => return Ok(None),
+            }
+        }
+        let output_value = tx.output.iter().map(|o| o.value).sum::<Amount>();
+        let feerate = input_value
+            .checked_sub(output_value)
+            .unwrap_or(Amount::ZERO)
+            / tx.weight();
+
+        Ok((feerate < estimated).then_some((feerate, estimated)))
+    }
+
+    /// Sanity check the feerate of a pre-signed transaction before broadcasting it.
+    ///
+    /// If it pays less than the estimate for confirming within `conf_target` blocks,
+    /// warn loudly, publish [Event::LowFeerate] and return the estimated feerate. Never blocks the broadcast.
+    pub(crate) fn check_broadcast_feerate(
+        &self,
+        tx: &Transaction,
+        conf_target: u16,
+        events: &EventBus,
+    ) -> Option<FeeRate> {
+        match self.check_tx_feerate(tx, conf_target) {
+            Ok(Some((feerate, estimated))) => {
+                log::warn!(
+                    "Transaction {} pays {} sat/vb, below the estimate of {} sat/vb. It might not confirm in time!",
+                    tx.compute_txid(),
+                    feerate.to_sat_per_vb_ceil(),
+                    estimated.to_sat_per_vb_ceil()
+                );
+                events.publish(Event::LowFeerate {
+                    txid: tx.compute_txid(),
+                    feerate,
+                    estimated,
+                });
+                Some(estimated)
+            }
+            Ok(None) => None,
+            Err(e) => {
+                log::debug!("Feerate check skipped: {:?}", e);
+                None
+            }
+        }
+    }
+
+    /// Speed up an unconfirmed transaction, by spending its wallet output at `outpoint` with a CPFP child.
+    pub(crate) fn bump_fee_with_cpfp(
+        &mut self,
+        outpoint: OutPoint,
+        feerate: FeeRate,
+    ) -> Result<Txid, WalletError> {
+        let child = self.cpfp(outpoint, feerate)?;
+        let txid = self.send_tx(&child)?;
+        log::info!("Broadcasted CPFP child {} for {}", txid, outpoint.txid);
+        Ok(txid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_script_type() {
+        assert_eq!(ScriptType::from_str("P2TR").unwrap(), ScriptType::P2tr);
+        assert!(ScriptType::from_str("p2sh").is_err());
+        for script_type in [ScriptType::P2wpkh, ScriptType::P2tr] {
+            assert_eq!(
+                ScriptType::from_str(&script_type.to_string()).unwrap(),
+                script_type
+            );
+        }
+
+        let p2tr_change = ScriptType::P2tr.coin_path(KeychainKind::Internal, 7);
+        assert_eq!(p2tr_change, "m/86'/1'/0'/1/7");
+        assert_eq!(ScriptType::from_path(&p2tr_change), ScriptType::P2tr);
+        assert_eq!(
+            ScriptType::from_path(&ScriptType::P2wpkh.coin_path(KeychainKind::Internal, 7)),
+            ScriptType::P2wpkh
+        );
+        assert_eq!(
+            path_branch(&p2tr_change).map(KeychainKind::of_branch),
+            Some(KeychainKind::Internal)
+        );
+
+        let p2tr =
+            Address::from_str("bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr")
+                .unwrap()
+                .assume_checked();
+        assert_eq!(ScriptType::of_address(&p2tr), Some(ScriptType::P2tr));
+        let p2sh = Address::from_str("32iVBEu4dxkUQk9dJbZUiBiQdmypcEyJRf")
+            .unwrap()
+            .assume_checked();
+        assert_eq!(ScriptType::of_address(&p2sh), None);
+        #[cfg(feature = "maker")]
+        {
+            assert_eq!(
+                ScriptType::of_script(&p2tr.script_pubkey()),
+                Some(ScriptType::P2tr)
+            );
+            assert_eq!(ScriptType::of_script(&p2sh.script_pubkey()), None);
+        }
     }
 }
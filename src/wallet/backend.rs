@@ -0,0 +1,332 @@
+//! The blockchain backend used to watch and broadcast transactions.
+//!
+//! [BlockchainBackend] is the small set of chain queries the swap state machines rely on. The
+//! [Wallet](super::Wallet) makes them through its backend, see [Wallet::set_blockchain_backend](super::Wallet::set_blockchain_backend),
+//! and so do the Maker and the Taker through their wallet, and the watchtower. It's implemented by the
+//! Bitcoin Core RPC [Client], and by `MockBlockchain`, an in-memory chain and mempool for fast protocol
+//! tests without bitcoind. The mock is only compiled for tests and benchmarks.
+
+#[cfg(any(test, feature = "integration-test", feature = "bench"))]
+use std::{
+    collections::HashMap,
+    sync::{Mutex, MutexGuard},
+};
+
+#[cfg(any(test, feature = "integration-test", feature = "bench"))]
+use bitcoin::relative;
+use bitcoin::{OutPoint, ScriptBuf, Transaction, TxOut, Txid};
+use bitcoind::bitcoincore_rpc::{Client, RpcApi};
+
+use super::{FeeEstimator, WalletError};
+
+/// Chain queries needed to watch contracts and broadcast their spends.
+pub trait BlockchainBackend: Send + Sync {
+    /// Height of the chain tip.
+    fn block_count(&self) -> Result<u64, WalletError>;
+
+    /// Transactions of the block at `height`.
+    fn block_txs(&self, height: u64) -> Result<Vec<Transaction>, WalletError>;
+
+    /// Confirmations of a transaction. `Some(0)` if it's in the mempool, `None` if the backend
+    /// doesn't know it.
+    fn tx_confirmations(&self, txid: &Txid) -> Result<Option<u32>, WalletError>;
+
+    /// The output at `outpoint`, if it exists and is unspent. With `include_mempool`, spends
+    /// waiting in the mempool count as spent, and outputs of the mempool as existing.
+    fn unspent_output(
+        &self,
+        outpoint: &OutPoint,
+        include_mempool: bool,
+    ) -> Result<Option<TxOut>, WalletError>;
+
+    /// Returns true if `outpoint` exists and is unspent. With `include_mempool`, spends waiting
+    /// in the mempool count as spent.
+    fn is_unspent(&self, outpoint: &OutPoint, include_mempool: bool) -> Result<bool, WalletError> {
+        Ok(self.unspent_output(outpoint, include_mempool)?.is_some())
+    }
+
+    /// Txids of the transactions waiting in the mempool.
+    fn mempool_txids(&self) -> Result<Vec<Txid>, WalletError>;
+
+    /// Broadcasts a transaction.
+    fn broadcast(&self, tx: &Transaction) -> Result<Txid, WalletError>;
+
+    /// Whether broadcasted transactions are relayed to the network.
+    fn relays(&self) -> Result<bool, WalletError> {
+        Ok(true)
+    }
+
+    /// Feerate for confirming within `conf_target` blocks, in sats/vB. None if there's no estimate.
+    fn estimate_feerate(&self, conf_target: u16) -> Result<Option<f64>, WalletError>;
+}
+
+impl BlockchainBackend for Client {
+    fn block_count(&self) -> Result<u64, WalletError> {
+        Ok(self.get_block_count()?)
+    }
+
+    fn block_txs(&self, height: u64) -> Result<Vec<Transaction>, WalletError> {
+        Ok(self.get_block(&self.get_block_hash(height)?)?.txdata)
+    }
+
+    fn tx_confirmations(&self, txid: &Txid) -> Result<Option<u32>, WalletError> {
+        // Failure here means the transaction isn't broadcasted.
+        Ok(self
+            .get_raw_transaction_info(txid, None)
+            .ok()
+            .map(|info| info.confirmations.unwrap_or(0)))
+    }
+
+    fn unspent_output(
+        &self,
+        outpoint: &OutPoint,
+        include_mempool: bool,
+    ) -> Result<Option<TxOut>, WalletError> {
+        Ok(self
+            .get_tx_out(&outpoint.txid, outpoint.vout, Some(include_mempool))?
+            .map(|txout| TxOut {
+                value: txout.value,
+                script_pubkey: ScriptBuf::from(txout.script_pub_key.hex),
+            }))
+    }
+
+    fn mempool_txids(&self) -> Result<Vec<Txid>, WalletError> {
+        Ok(self.get_raw_mempool()?)
+    }
+
+    fn broadcast(&self, tx: &Transaction) -> Result<Txid, WalletError> {
+        Ok(self.send_raw_transaction(tx)?)
+    }
+
+    /// The node doesn't relay without peers.
+    fn relays(&self) -> Result<bool, WalletError> {
+        Ok(self.get_connection_count()? != 0)
+    }
+
+    fn estimate_feerate(&self, conf_target: u16) -> Result<Option<f64>, WalletError> {
+        FeeEstimator::estimate(self, conf_target)
+    }
+}
+
+#[cfg(any(test, feature = "integration-test", feature = "bench"))]
+#[derive(Debug, Default)]
+struct MockState {
+    blocks: Vec<Vec<Transaction>>,
+    mempool: Vec<Transaction>,
+    /// Height of the block including each confirmed transaction.
+    confirmed: HashMap<Txid, u64>,
+    /// Feerate estimate for any confirmation target, in sats/vB.
+    feerate_estimate: Option<f64>,
+}
+
+#[cfg(any(test, feature = "integration-test", feature = "bench"))]
+impl MockState {
+    fn find_tx(&self, txid: &Txid) -> Option<&Transaction> {
+        self.blocks
+            .iter()
+            .flatten()
+            .chain(self.mempool.iter())
+            .find(|tx| tx.compute_txid() == *txid)
+    }
+
+    fn is_spent(&self, outpoint: &OutPoint, include_mempool: bool) -> bool {
+        let mempool: &[Transaction] = if include_mempool { &self.mempool } else { &[] };
+        self.blocks
+            .iter()
+            .flatten()
+            .chain(mempool.iter())
+            .flat_map(|tx| tx.input.iter())
+            .any(|input| input.previous_output == *outpoint)
+    }
+
+    fn tip(&self) -> u64 {
+        self.blocks.len() as u64
+    }
+}
+
+#[cfg(any(test, feature = "integration-test", feature = "bench"))]
+/// An in-memory blockchain and mempool implementing [BlockchainBackend].
+///
+/// It doesn't validate scripts or amounts. It rejects double spends and transactions spending
+/// height-based relative timelocks that have not matured, which is what the swap protocol depends on.
+/// Transactions without a known parent (like funding transactions) are accepted as is.
+#[derive(Debug, Default)]
+pub struct MockBlockchain {
+    state: Mutex<MockState>,
+}
+
+#[cfg(any(test, feature = "integration-test", feature = "bench"))]
+impl MockBlockchain {
+    /// Creates an empty chain, with the tip at height 0.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, MockState>, WalletError> {
+        self.state
+            .lock()
+            .map_err(|_| WalletError::General("Mock blockchain mutex poisoned".to_string()))
+    }
+
+    /// Mines `count` blocks. The first one includes the whole mempool.
+    pub fn mine(&self, count: u64) -> Result<(), WalletError> {
+        let mut state = self.lock()?;
+        for _ in 0..count {
+            let txs = std::mem::take(&mut state.mempool);
+            let height = state.tip() + 1;
+            for tx in &txs {
+                state.confirmed.insert(tx.compute_txid(), height);
+            }
+            state.blocks.push(txs);
+        }
+        Ok(())
+    }
+
+    /// Sets the feerate estimate, in sats/vB, returned for any confirmation target.
+    pub fn set_feerate_estimate(&self, feerate: Option<f64>) -> Result<(), WalletError> {
+        self.lock()?.feerate_estimate = feerate;
+        Ok(())
+    }
+}
+
+#[cfg(any(test, feature = "integration-test", feature = "bench"))]
+impl BlockchainBackend for MockBlockchain {
+    fn block_count(&self) -> Result<u64, WalletError> {
+        Ok(self.lock()?.tip())
+    }
+
+    fn block_txs(&self, height: u64) -> Result<Vec<Transaction>, WalletError> {
+        let state = self.lock()?;
+        height
+            .checked_sub(1)
+            .and_then(|index| state.blocks.get(index as usize))
+            .cloned()
+            .ok_or_else(|| WalletError::General(format!("No block at height {}", height)))
+    }
+
+    fn tx_confirmations(&self, txid: &Txid) -> Result<Option<u32>, WalletError> {
+        let state = self.lock()?;
+        if let Some(height) = state.confirmed.get(txid) {
+            return Ok(Some((state.tip() - height + 1) as u32));
+        }
+        Ok(state
+            .mempool
+            .iter()
+            .any(|tx| tx.compute_txid() == *txid)
+            .then_some(0))
+    }
+
+    fn unspent_output(
+        &self,
+        outpoint: &OutPoint,
+        include_mempool: bool,
+    ) -> Result<Option<TxOut>, WalletError> {
+        let state = self.lock()?;
+        let output = match state.find_tx(&outpoint.txid) {
+            Some(tx) => tx.output.get(outpoint.vout as usize).cloned(),
+            None => None,
+        };
+        let in_mempool = !state.confirmed.contains_key(&outpoint.txid);
+        if (in_mempool && !include_mempool) || state.is_spent(outpoint, include_mempool) {
+            return Ok(None);
+        }
+        Ok(output)
+    }
+
+    fn mempool_txids(&self) -> Result<Vec<Txid>, WalletError> {
+        Ok(self
+            .lock()?
+            .mempool
+            .iter()
+            .map(|tx| tx.compute_txid())
+            .collect())
+    }
+
+    fn broadcast(&self, tx: &Transaction) -> Result<Txid, WalletError> {
+        let mut state = self.lock()?;
+        let txid = tx.compute_txid();
+        if state.find_tx(&txid).is_some() {
+            return Err(WalletError::General("txn-already-known".to_string()));
+        }
+
+        for input in &tx.input {
+            if state.is_spent(&input.previous_output, true) {
+                return Err(WalletError::General("txn-mempool-conflict".to_string()));
+            }
+            if let Some(relative::LockTime::Blocks(height)) = input.sequence.to_relative_lock_time()
+            {
+                let confirmations = state
+                    .confirmed
+                    .get(&input.previous_output.txid)
+                    .map_or(0, |h| state.tip() - h + 1);
+                // BIP68: spendable in the block at parent height + locktime.
+                if confirmations < height.value() as u64 {
+                    return Err(WalletError::General("non-BIP68-final".to_string()));
+                }
+            }
+        }
+
+        state.mempool.push(tx.clone());
+        Ok(txid)
+    }
+
+    fn estimate_feerate(&self, _conf_target: u16) -> Result<Option<f64>, WalletError> {
+        Ok(self.lock()?.feerate_estimate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::{
+        absolute::LockTime, transaction::Version, Amount, ScriptBuf, Sequence, TxIn, TxOut,
+    };
+
+    fn spend(outpoint: OutPoint, sequence: Sequence) -> Transaction {
+        Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: outpoint,
+                sequence,
+                ..Default::default()
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(1000),
+                script_pubkey: ScriptBuf::new(),
+            }],
+        }
+    }
+
+    #[test]
+    fn test_mock_blockchain() {
+        let chain = MockBlockchain::new();
+        let parent = spend(OutPoint::null(), Sequence::MAX);
+        let parent_txid = chain.broadcast(&parent).unwrap();
+        assert_eq!(chain.tx_confirmations(&parent_txid).unwrap(), Some(0));
+
+        let outpoint = OutPoint::new(parent_txid, 0);
+        assert!(chain.is_unspent(&outpoint, true).unwrap());
+        assert!(!chain.is_unspent(&outpoint, false).unwrap());
+
+        chain.mine(1).unwrap();
+        assert_eq!(chain.block_count().unwrap(), 1);
+        assert_eq!(chain.tx_confirmations(&parent_txid).unwrap(), Some(1));
+        assert_eq!(chain.block_txs(1).unwrap(), vec![parent]);
+
+        // Relative timelock of 3 blocks isn't final at 1 confirmation.
+        let timelocked = spend(outpoint, Sequence::from_height(3));
+        assert!(chain.broadcast(&timelocked).is_err());
+        chain.mine(2).unwrap();
+        chain.broadcast(&timelocked).unwrap();
+
+        // Double spend is rejected.
+        assert!(chain.broadcast(&spend(outpoint, Sequence::MAX)).is_err());
+        assert!(!chain.is_unspent(&outpoint, true).unwrap());
+        assert!(chain.is_unspent(&outpoint, false).unwrap());
+
+        chain.mine(1).unwrap();
+        assert!(!chain.is_unspent(&outpoint, false).unwrap());
+        assert!(chain.mempool_txids().unwrap().is_empty());
+    }
+}
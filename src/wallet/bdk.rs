@@ -0,0 +1,105 @@
+//! BDK compatibility layer.
+//!
+//! Lets downstream wallets built on [BDK](https://bitcoindevkit.org) use the coinswap wallet's seed
+//! keychains, without keeping a second UTXO store. The BDK wallet is created watch-only from the same
+//! descriptors, so it tracks the very same seed coins. Its utxos can be turned into [UTXOSpendInfo]s
+//! and PSBTs built by BDK are signed by the coinswap wallet, which keeps the keys.
+//!
+//! Swap and contract coins are not descriptor coins and stay tracked by the coinswap wallet only. Neither are
+//! the P2TR seed coins: a BDK wallet has a single pair of keychains, which are the P2WPKH ones.
+//!
+//! Only available with the `bdk` feature.
+
+use bdk_wallet::{KeychainKind as BdkKeychainKind, LocalOutput, Wallet as BdkWallet};
+use bitcoin::Psbt;
+
+use super::{
+    api::{KeychainKind, ScriptType, UTXOSpendInfo},
+    error::WalletError,
+    Wallet,
+};
+
+impl Wallet {
+    /// Public descriptors of the P2WPKH seed keychains, as `(external, internal)`.
+    ///
+    /// The P2TR keychains aren't exported, so the BDK wallet doesn't track the P2TR seed coins.
+    pub fn bdk_descriptors(&self) -> Result<(String, String), WalletError> {
+        let mut descriptors = self.get_wallet_descriptors()?;
+        let mut take = |keychain| {
+            descriptors
+                .remove(&(ScriptType::P2wpkh, keychain))
+                .ok_or_else(|| {
+                    WalletError::General(format!("Missing {:?} P2WPKH descriptor", keychain))
+                })
+        };
+        Ok((take(KeychainKind::External)?, take(KeychainKind::Internal)?))
+    }
+
+    /// Creates an in-memory, watch-only BDK wallet tracking the seed keychains of this wallet.
+    ///
+    /// Chain syncing is left to the caller, using any of the BDK chain sources.
+    pub fn to_bdk_wallet(&self) -> Result<BdkWallet, WalletError> {
+        let (external, internal) = self.bdk_descriptors()?;
+        BdkWallet::create(external, internal)
+            .network(self.store.network)
+            .create_wallet_no_persist()
+            .map_err(|e| WalletError::General(format!("Failed creating BDK wallet: {}", e)))
+    }
+
+    /// Lists the unspent outputs of the BDK wallet, along with their [UTXOSpendInfo] in this wallet.
+    pub fn bdk_utxo_spend_info(&self, bdk_wallet: &BdkWallet) -> Vec<(LocalOutput, UTXOSpendInfo)> {
+        bdk_wallet
+            .list_unspent()
+            .map(|utxo| {
+                let spend_info = bdk_spend_info(&utxo);
+                (utxo, spend_info)
+            })
+            .collect()
+    }
+
+    /// Signs and finalizes all inputs of a PSBT spending utxos of the BDK wallet.
+    /// Inputs not owned by the BDK wallet are left untouched.
+    pub fn sign_bdk_psbt(
+        &self,
+        bdk_wallet: &BdkWallet,
+        psbt: &mut Psbt,
+    ) -> Result<(), WalletError> {
+        let inputs_info = psbt
+            .unsigned_tx
+            .input
+            .iter()
+            .enumerate()
+            .filter_map(|(ix, txin)| {
+                bdk_wallet
+                    .get_utxo(txin.previous_output)
+                    .map(|utxo| (ix, utxo))
+            })
+            .collect::<Vec<_>>();
+
+        let mut tx = psbt.unsigned_tx.clone();
+        self.sign_inputs(
+            &mut tx,
+            inputs_info
+                .iter()
+                .map(|(ix, utxo)| (*ix, bdk_spend_info(utxo))),
+        )?;
+
+        for (ix, utxo) in inputs_info {
+            psbt.inputs[ix].witness_utxo = Some(utxo.txout.clone());
+            psbt.inputs[ix].final_script_witness = Some(tx.input[ix].witness.clone());
+        }
+        Ok(())
+    }
+}
+
+/// Spend info of a BDK utxo. BDK keychains map to the same derivation branches of the P2WPKH account as ours.
+fn bdk_spend_info(utxo: &LocalOutput) -> UTXOSpendInfo {
+    let keychain = match utxo.keychain {
+        BdkKeychainKind::External => KeychainKind::External,
+        BdkKeychainKind::Internal => KeychainKind::Internal,
+    };
+    UTXOSpendInfo::SeedCoin {
+        path: ScriptType::P2wpkh.coin_path(keychain, utxo.derivation_index),
+        input_value: utxo.txout.value,
+    }
+}
@@ -0,0 +1,116 @@
+//! Change output fingerprinting.
+//!
+//! Chain analysis tells the change of a transaction apart from its payment by the output position, the
+//! script type and the amount. Every transaction built by the wallet (funding txs, sweeps, direct sends)
+//! places its change at a random position, and can round change amounts down to a multiple of a
+//! configured unit, leaving the remainder to the miners, so that the change isn't the one non-round output.
+//!
+//! Direct sends to P2WPKH or P2TR get change of the same type. Funding txs pay to P2WSH multisigs, which
+//! the wallet can't match, their change is of the wallet's [ScriptType](super::ScriptType).
+//!
+//! Outputs below the dust threshold cost more to spend than they are worth. Such change is left to the
+//! miners instead, and such UTXOs are left out of coin selection. They are only spent when consolidating,
+//! see [Wallet::consolidate].
+
+use bitcoin::{
+    secp256k1::rand::{thread_rng, Rng},
+    Address, Amount, Script, TxOut,
+};
+
+use super::{error::WalletError, Wallet};
+
+/// Default dust threshold, in sats. The dust limit of P2PKH outputs, the highest of the standard types.
+pub const DEFAULT_DUST_THRESHOLD: u64 = 546;
+
+impl Wallet {
+    /// Sets the unit change amounts are rounded down to, in sats. 0 disables rounding.
+    pub fn set_change_rounding(&mut self, unit_sats: u64) -> Result<(), WalletError> {
+        self.store.change_rounding = unit_sats;
+        self.save_to_disk()
+    }
+
+    /// Sets the amount below which outputs are considered dust, in sats.
+    pub fn set_dust_threshold(&mut self, threshold_sats: u64) -> Result<(), WalletError> {
+        self.store.dust_threshold = threshold_sats;
+        self.save_to_disk()
+    }
+
+    /// Amount below which outputs are considered dust.
+    pub fn dust_threshold(&self) -> Amount {
+        Amount::from_sat(self.store.dust_threshold)
+    }
+
+    /// Whether an output of `amount` would be dust. The standard dust limit of `script_pubkey` applies
+    /// when above the configured threshold.
+    pub(crate) fn is_dust(&self, amount: Amount, script_pubkey: &Script) -> bool {
+        amount < self.dust_threshold().max(script_pubkey.minimal_non_dust())
+    }
+
+    /// Change amount after rounding. The amount rounded off is added to the fee.
+    pub(super) fn round_change(&self, change: Amount) -> Amount {
+        round_down(change, self.store.change_rounding)
+    }
+
+    /// Logs a change script type differing from the destination's, which gives the change away.
+    pub(super) fn check_change_type(destination: &Address, change: &Address) {
+        if destination.address_type() != change.address_type() {
+            log::debug!(
+                "Change type {:?} differs from destination type {:?}",
+                change.address_type(),
+                destination.address_type()
+            );
+        }
+    }
+}
+
+/// Rounds down to a multiple of `unit_sats`. Amounts smaller than the unit are kept as they are.
+fn round_down(amount: Amount, unit_sats: u64) -> Amount {
+    if unit_sats == 0 || amount.to_sat() < unit_sats {
+        amount
+    } else {
+        Amount::from_sat(amount.to_sat() - amount.to_sat() % unit_sats)
+    }
+}
+
+/// Inserts the change output at a random position. Returns its position.
+pub(super) fn insert_change(outputs: &mut Vec<TxOut>, change: TxOut) -> usize {
+    let pos = thread_rng().gen_range(0..=outputs.len());
+    outputs.insert(pos, change);
+    pos
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::ScriptBuf;
+
+    use super::*;
+
+    #[test]
+    fn test_change_fingerprint() {
+        let change = Amount::from_sat(123_456);
+        assert_eq!(round_down(change, 0), change);
+        assert_eq!(round_down(change, 1_000), Amount::from_sat(123_000));
+        assert_eq!(round_down(change, 200_000), change);
+
+        let payment = TxOut {
+            value: Amount::from_sat(50_000),
+            script_pubkey: ScriptBuf::new(),
+        };
+        let mut seen = [false; 2];
+        for _ in 0..100 {
+            let mut outputs = vec![payment.clone()];
+            let pos = insert_change(
+                &mut outputs,
+                TxOut {
+                    value: change,
+                    script_pubkey: ScriptBuf::new(),
+                },
+            );
+            assert_eq!(outputs.len(), 2);
+            assert_eq!(outputs[pos].value, change);
+            assert_eq!(outputs[1 - pos], payment);
+            seen[pos] = true;
+        }
+        assert_eq!(seen, [true, true]);
+    }
+}
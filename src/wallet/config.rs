@@ -0,0 +1,85 @@
+//! Wallet settings, shared by the Maker and Taker configs.
+
+use std::io;
+
+use crate::utill::ConfigMap;
+
+use super::{
+    FeeSource, ScriptType, Wallet, WalletError, DEFAULT_DUST_THRESHOLD, DEFAULT_GAP_LIMIT,
+};
+
+/// Settings of the wallet, read from the `config.toml` of the Maker or Taker.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WalletConfig {
+    /// Number of unused wallet addresses scanned past the last used one.
+    pub gap_limit: u32,
+    /// Unit change amounts are rounded down to, in sats. 0 disables rounding.
+    pub change_rounding_sats: u64,
+    /// Outputs below this amount are dust, in sats.
+    pub dust_threshold_sats: u64,
+    /// Script type of the wallet's receive and change addresses, `p2wpkh` or `p2tr`.
+    pub script_type: ScriptType,
+    /// Source of the feerate estimates: `bitcoind`, `mempool` (over Tor), or `static:<sats/vB>`.
+    pub fee_source: FeeSource,
+}
+
+impl Default for WalletConfig {
+    fn default() -> Self {
+        Self {
+            gap_limit: DEFAULT_GAP_LIMIT,
+            change_rounding_sats: 0,
+            dust_threshold_sats: DEFAULT_DUST_THRESHOLD,
+            script_type: ScriptType::default(),
+            fee_source: FeeSource::default(),
+        }
+    }
+}
+
+impl WalletConfig {
+    /// Reads the wallet settings of a Maker or Taker config.
+    pub(crate) fn from_config_map(config_map: &ConfigMap) -> io::Result<Self> {
+        let default_config = Self::default();
+        Ok(Self {
+            gap_limit: config_map.get("gap_limit", default_config.gap_limit)?,
+            change_rounding_sats: config_map
+                .get("change_rounding_sats", default_config.change_rounding_sats)?,
+            dust_threshold_sats: config_map
+                .get("dust_threshold_sats", default_config.dust_threshold_sats)?,
+            script_type: config_map.get("script_type", default_config.script_type)?,
+            fee_source: config_map.get("fee_source", default_config.fee_source)?,
+        })
+    }
+
+    /// The `key = value` lines of the settings, for writing the config file.
+    pub(crate) fn to_toml(&self) -> String {
+        format!(
+            "gap_limit = {}
+change_rounding_sats = {}
+dust_threshold_sats = {}
+script_type = {}
+fee_source = {}",
+            self.gap_limit,
+            self.change_rounding_sats,
+            self.dust_threshold_sats,
+            self.script_type,
+            self.fee_source,
+        )
+    }
+}
+
+impl Wallet {
+    /// Applies the wallet settings. mempool.space fee estimates are fetched through the Tor `socks_port`, if
+    /// any.
+    pub(crate) fn apply_config(
+        &mut self,
+        config: &WalletConfig,
+        socks_port: Option<u16>,
+    ) -> Result<(), WalletError> {
+        self.set_gap_limit(config.gap_limit)?;
+        self.set_change_rounding(config.change_rounding_sats)?;
+        self.set_dust_threshold(config.dust_threshold_sats)?;
+        self.set_script_type(config.script_type)?;
+        self.set_fee_estimator(config.fee_source.estimator(self.store.network, socks_port));
+        Ok(())
+    }
+}
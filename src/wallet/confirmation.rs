@@ -0,0 +1,100 @@
+//! Reorg-aware confirmation tracking.
+//!
+//! Confirmation counts alone can't tell a reorg apart: a tx reorged into another block only shows a lower count,
+//! if any. [ConfirmationTracker] remembers the block each tx confirmed in, and reports the tx as reorged when
+//! the node later sees it in another block, or back in the mempool. The swap state built on that confirmation
+//! must then be reverted, and the confirmation waited for again.
+
+use std::collections::HashMap;
+
+use bitcoin::{BlockHash, Txid};
+
+/// Change of the confirmation of a tx, since the last update.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ConfirmationUpdate {
+    /// The tx isn't confirmed.
+    Unconfirmed,
+    /// The tx is confirmed, in the same block as before if it was already.
+    Confirmed,
+    /// The block the tx confirmed in is not in the chain anymore. The tx is now in another block, or not
+    /// confirmed.
+    Reorged {
+        /// Block the tx confirmed in before.
+        blockhash: BlockHash,
+    },
+}
+
+/// Blocks the watched txs confirmed in, see the [module docs](self).
+#[derive(Debug, Default)]
+pub(crate) struct ConfirmationTracker {
+    confirmed: HashMap<Txid, BlockHash>,
+}
+
+impl ConfirmationTracker {
+    /// Records the block a tx is confirmed in, as reported by the node: None if it's unconfirmed, or unknown
+    /// to the node.
+    pub(crate) fn update(
+        &mut self,
+        txid: Txid,
+        blockhash: Option<BlockHash>,
+    ) -> ConfirmationUpdate {
+        let previous = match blockhash {
+            Some(blockhash) => self.confirmed.insert(txid, blockhash),
+            None => self.confirmed.remove(&txid),
+        };
+        match (previous, blockhash) {
+            (Some(previous), Some(current)) if previous == current => ConfirmationUpdate::Confirmed,
+            (Some(previous), _) => ConfirmationUpdate::Reorged {
+                blockhash: previous,
+            },
+            (None, Some(_)) => ConfirmationUpdate::Confirmed,
+            (None, None) => ConfirmationUpdate::Unconfirmed,
+        }
+    }
+
+    /// Block a tx confirmed in, as last seen.
+    pub(crate) fn blockhash(&self, txid: &Txid) -> Option<BlockHash> {
+        self.confirmed.get(txid).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::hashes::Hash;
+
+    use super::*;
+
+    #[test]
+    fn test_confirmation_tracker() {
+        let txid = Txid::all_zeros();
+        let block_a = BlockHash::from_byte_array([1; 32]);
+        let block_b = BlockHash::from_byte_array([2; 32]);
+
+        let mut tracker = ConfirmationTracker::default();
+        assert_eq!(tracker.update(txid, None), ConfirmationUpdate::Unconfirmed);
+        assert_eq!(
+            tracker.update(txid, Some(block_a)),
+            ConfirmationUpdate::Confirmed
+        );
+        assert_eq!(
+            tracker.update(txid, Some(block_a)),
+            ConfirmationUpdate::Confirmed
+        );
+        assert_eq!(tracker.blockhash(&txid), Some(block_a));
+
+        // Reorged into another block.
+        assert_eq!(
+            tracker.update(txid, Some(block_b)),
+            ConfirmationUpdate::Reorged { blockhash: block_a }
+        );
+        assert_eq!(tracker.blockhash(&txid), Some(block_b));
+
+        // Reorged back into the mempool.
+        assert_eq!(
+            tracker.update(txid, None),
+            ConfirmationUpdate::Reorged { blockhash: block_b }
+        );
+        assert_eq!(tracker.blockhash(&txid), None);
+        assert_eq!(tracker.update(txid, None), ConfirmationUpdate::Unconfirmed);
+    }
+}
@@ -0,0 +1,287 @@
+//! Conflicts between the contract txs and the other spends of their funding.
+//!
+//! A contract tx and the cooperative spend of a completed swap both spend the 2of2 funding output. Once one of
+//! them is in the mempool or a block, the node rejects the other. Rebroadcasting a rejected contract, or waiting
+//! for it to confirm before claiming its timelock, then never ends. [Wallet::contract_conflict] tells these
+//! conflicts apart from the other broadcast failures, so that the recovery settles the swapcoin instead.
+
+use bitcoin::Transaction;
+
+use super::{Wallet, WalletError};
+
+/// Why a contract tx is rejected by the node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ContractConflict {
+    /// The contract tx itself is in the mempool or a block already.
+    ContractBroadcast,
+    /// The funding output is spent by another tx, like the cooperative spend, or the funding tx is gone. The
+    /// contract can never confirm.
+    FundingSpent,
+}
+
+/// The conflict of a contract tx, from whether the node knows it, and whether its funding output is unspent.
+fn classify(contract_known: bool, funding_unspent: bool) -> Option<ContractConflict> {
+    match (contract_known, funding_unspent) {
+        (true, _) => Some(ContractConflict::ContractBroadcast),
+        (false, false) => Some(ContractConflict::FundingSpent),
+        (false, true) => None,
+    }
+}
+
+impl Wallet {
+    /// The conflict keeping `contract_tx` out of the mempool. None if its funding output is still unspent: the
+    /// contract can still be broadcast, a rejection has another cause.
+    pub(crate) fn contract_conflict(
+        &self,
+        contract_tx: &Transaction,
+    ) -> Result<Option<ContractConflict>, WalletError> {
+        let contract_known = self
+            .chain
+            .tx_confirmations(&contract_tx.compute_txid())?
+            .is_some();
+        // Contracts spend their funding output alone.
+        let funding_unspent = self
+            .chain
+            .is_unspent(&contract_tx.input[0].previous_output, true)?;
+        Ok(classify(contract_known, funding_unspent))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        broadcast::Broadcaster,
+        events::{Event, EventBus},
+        protocol::contract::{
+            create_contract_redeemscript, create_multisig_redeemscript, create_senders_contract_tx,
+        },
+        wallet::{
+            broadcasted_txids, storage::WalletStore, BlockchainBackend, MockBlockchain,
+            OutgoingSwapCoin,
+        },
+    };
+    use bitcoin::{
+        absolute::LockTime,
+        bip32::Xpriv,
+        hashes::{hash160::Hash as Hash160, Hash},
+        secp256k1::{Secp256k1, SecretKey},
+        transaction::Version,
+        Address, Amount, FeeRate, Network, OutPoint, PublicKey, ScriptBuf, Sequence, TxIn, TxOut,
+        Txid,
+    };
+    use bitcoind::{
+        bitcoincore_rpc::{Auth, Client},
+        tempfile::tempdir,
+    };
+    use std::{collections::HashSet, path::Path, sync::Arc};
+
+    const FUNDING_AMOUNT: Amount = Amount::from_sat(100_000);
+
+    /// A wallet in `dir` making its chain queries on `chain`. The node of the wallet is never reached.
+    fn mock_wallet(dir: &Path, chain: Arc<MockBlockchain>) -> Wallet {
+        let wallet_file_path = dir.join("mock-wallet");
+        let store = WalletStore::init(
+            "mock-wallet".to_string(),
+            &wallet_file_path,
+            Network::Regtest,
+            Xpriv::new_master(Network::Regtest, &[1; 32]).unwrap(),
+            Some(0),
+            None,
+        )
+        .unwrap();
+        Wallet {
+            rpc: Client::new("http://127.0.0.1:1", Auth::None).unwrap(),
+            chain,
+            wallet_file_path,
+            store,
+            fee_estimator: None,
+            payjoin_inputs: HashSet::new(),
+        }
+    }
+
+    fn pubkey(privkey: &SecretKey) -> PublicKey {
+        PublicKey {
+            compressed: true,
+            inner: privkey.public_key(&Secp256k1::new()),
+        }
+    }
+
+    /// Spends `input` to `script_pubkey`, paying 1000 sats of fee.
+    fn spend(input: OutPoint, value: Amount, script_pubkey: ScriptBuf) -> Transaction {
+        Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: input,
+                sequence: Sequence::ZERO,
+                ..Default::default()
+            }],
+            output: vec![TxOut {
+                value: value - Amount::from_sat(1000),
+                script_pubkey,
+            }],
+        }
+    }
+
+    /// A confirmed 2of2 funding tx, and the outgoing swapcoin of its contract with a locktime of 3 blocks.
+    fn funded_swapcoin(chain: &MockBlockchain, seed: u8) -> (Transaction, OutgoingSwapCoin) {
+        let my_privkey = SecretKey::from_slice(&[seed; 32]).unwrap();
+        let other_privkey = SecretKey::from_slice(&[seed + 1; 32]).unwrap();
+        let timelock_privkey = SecretKey::from_slice(&[seed + 2; 32]).unwrap();
+        let hashlock_privkey = SecretKey::from_slice(&[seed + 3; 32]).unwrap();
+
+        let multisig_redeemscript =
+            create_multisig_redeemscript(&pubkey(&my_privkey), &pubkey(&other_privkey));
+        let funding_tx = spend(
+            OutPoint::new(Txid::from_byte_array([seed; 32]), 0),
+            FUNDING_AMOUNT + Amount::from_sat(1000),
+            ScriptBuf::new_p2wsh(&multisig_redeemscript.wscript_hash()),
+        );
+        chain.broadcast(&funding_tx).unwrap();
+        chain.mine(1).unwrap();
+
+        let contract_redeemscript = create_contract_redeemscript(
+            &pubkey(&hashlock_privkey),
+            &pubkey(&timelock_privkey),
+            &Hash160::hash(&[seed; 32]),
+            &3,
+        );
+        let contract_tx = create_senders_contract_tx(
+            OutPoint::new(funding_tx.compute_txid(), 0),
+            FUNDING_AMOUNT,
+            &contract_redeemscript,
+            Amount::from_sat(1000),
+        )
+        .unwrap();
+        let swapcoin = OutgoingSwapCoin::new(
+            my_privkey,
+            pubkey(&other_privkey),
+            contract_tx,
+            contract_redeemscript,
+            timelock_privkey,
+            FUNDING_AMOUNT,
+        )
+        .unwrap();
+        (funding_tx, swapcoin)
+    }
+
+    #[test]
+    fn test_premature_contract_broadcast_on_mock_chain() {
+        let temp_dir = tempdir().unwrap();
+        let chain = Arc::new(MockBlockchain::new());
+        let wallet = mock_wallet(temp_dir.path(), chain.clone());
+        let (_, swapcoin) = funded_swapcoin(&chain, 1);
+        let contract_tx = &swapcoin.contract_tx;
+        let contract_txid = contract_tx.compute_txid();
+
+        // Nothing broadcasted yet, the contract could still be.
+        assert!(broadcasted_txids(&*wallet.chain, &[contract_txid])
+            .unwrap()
+            .is_empty());
+        assert_eq!(wallet.contract_conflict(contract_tx).unwrap(), None);
+
+        // The other party broadcasts the contract. It's caught in the mempool, before its first confirmation.
+        chain.broadcast(contract_tx).unwrap();
+        assert_eq!(
+            broadcasted_txids(&*wallet.chain, &[contract_txid]).unwrap(),
+            vec![contract_txid]
+        );
+        assert_eq!(
+            wallet.contract_conflict(contract_tx).unwrap(),
+            Some(ContractConflict::ContractBroadcast)
+        );
+        assert!(Broadcaster::local()
+            .broadcast(&*wallet.chain, contract_tx)
+            .is_err());
+
+        // The timelock spend of the contract is rejected until its locktime has passed.
+        let destination = Address::p2wsh(&ScriptBuf::new(), Network::Regtest);
+        let timelock_spend = swapcoin.create_timelock_spend(&destination).unwrap();
+        chain.mine(2).unwrap();
+        assert!(Broadcaster::local()
+            .broadcast(&*wallet.chain, &timelock_spend)
+            .is_err());
+        chain.mine(1).unwrap();
+        assert_eq!(
+            Broadcaster::local()
+                .broadcast(&*wallet.chain, &timelock_spend)
+                .unwrap(),
+            timelock_spend.compute_txid()
+        );
+        assert!(!wallet
+            .chain
+            .is_unspent(&OutPoint::new(contract_txid, 0), true)
+            .unwrap());
+    }
+
+    #[test]
+    fn test_contract_conflicting_cooperative_spend_on_mock_chain() {
+        let temp_dir = tempdir().unwrap();
+        let chain = Arc::new(MockBlockchain::new());
+        let wallet = mock_wallet(temp_dir.path(), chain.clone());
+        let (funding_tx, swapcoin) = funded_swapcoin(&chain, 11);
+
+        // The swap completed, and the funding went to the other party cooperatively.
+        let cooperative_spend = spend(
+            OutPoint::new(funding_tx.compute_txid(), 0),
+            FUNDING_AMOUNT,
+            ScriptBuf::new(),
+        );
+        chain.broadcast(&cooperative_spend).unwrap();
+        assert_eq!(
+            wallet.contract_conflict(&swapcoin.contract_tx).unwrap(),
+            Some(ContractConflict::FundingSpent)
+        );
+        assert!(Broadcaster::local()
+            .broadcast(&*wallet.chain, &swapcoin.contract_tx)
+            .is_err());
+        chain.mine(1).unwrap();
+        assert_eq!(
+            wallet.contract_conflict(&swapcoin.contract_tx).unwrap(),
+            Some(ContractConflict::FundingSpent)
+        );
+    }
+
+    #[test]
+    fn test_low_contract_feerate_on_mock_chain() {
+        let temp_dir = tempdir().unwrap();
+        let chain = Arc::new(MockBlockchain::new());
+        let wallet = mock_wallet(temp_dir.path(), chain.clone());
+        let (_, swapcoin) = funded_swapcoin(&chain, 21);
+        let events = EventBus::default();
+        let receiver = events.subscribe();
+
+        // The contract pays 1000 sats, above 10 sat/vB unsigned.
+        chain.set_feerate_estimate(Some(5.0)).unwrap();
+        assert_eq!(
+            wallet.check_broadcast_feerate(&swapcoin.contract_tx, 10, &events),
+            None
+        );
+        assert!(receiver.try_recv().is_err());
+
+        chain.set_feerate_estimate(Some(20.0)).unwrap();
+        assert_eq!(
+            wallet.check_broadcast_feerate(&swapcoin.contract_tx, 10, &events),
+            Some(FeeRate::from_sat_per_vb_u32(20))
+        );
+        assert!(matches!(
+            receiver.try_recv(),
+            Ok(Event::LowFeerate { txid, .. }) if txid == swapcoin.contract_tx.compute_txid()
+        ));
+    }
+
+    #[test]
+    fn test_classify_conflict() {
+        assert_eq!(
+            classify(true, false),
+            Some(ContractConflict::ContractBroadcast)
+        );
+        assert_eq!(
+            classify(true, true),
+            Some(ContractConflict::ContractBroadcast)
+        );
+        assert_eq!(classify(false, false), Some(ContractConflict::FundingSpent));
+        assert_eq!(classify(false, true), None);
+    }
+}
@@ -7,14 +7,33 @@
 use std::{num::ParseIntError, str::FromStr};
 
 use bitcoin::{
-    absolute::LockTime, transaction::Version, Address, Amount, Network, OutPoint, ScriptBuf,
-    Sequence, Transaction, TxIn, TxOut, Witness,
+    absolute::LockTime, transaction::Version, Address, Amount, FeeRate, Network, OutPoint,
+    ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, Witness,
 };
 use bitcoind::bitcoincore_rpc::{json::ListUnspentResultEntry, RawTx, RpcApi};
+use serde::{Deserialize, Serialize};
 
-use crate::wallet::api::UTXOSpendInfo;
+use crate::wallet::api::{ScriptType, UTXOSpendInfo};
 
-use super::{error::WalletError, Wallet};
+use super::{change::insert_change, error::WalletError, Wallet};
+
+/// An unconfirmed coin received by the wallet, whose parent tx pays too low a feerate to confirm soon.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StuckDeposit {
+    /// The wallet outpoint.
+    pub outpoint: OutPoint,
+    /// Value of the coin.
+    pub amount: Amount,
+    /// `regular` for a deposit, `incoming-swap` for the coin of a completed swap.
+    pub kind: String,
+    /// Feerate of the parent tx with its unconfirmed ancestors, in sats/vB.
+    pub feerate: f64,
+}
+
+/// Whether a package paying `fee` for `vsize` vbytes pays less than `target_feerate`.
+fn pays_below(fee: Amount, vsize: u64, target_feerate: FeeRate) -> bool {
+    fee.to_sat() < target_feerate.to_sat_per_vb_ceil() * vsize
+}
 
 /// Represents options for specifying the amount to be sent in a transaction.
 #[derive(Debug, Clone, PartialEq)]
@@ -80,7 +99,8 @@ impl Wallet {
     /// - If [SendAmount::Max] is used, the function creates a transaction for the maximum possible
     ///   value to the specified destination.
     /// - If [SendAmount::Amount] is used, a custom value is sent, and any remaining funds
-    ///    are held in a change address, if applicable.
+    ///   are held in a change address, if applicable. The change goes at a random position, and is rounded
+    ///   as set by [Wallet::set_change_rounding].
     pub fn spend_from_wallet(
         &mut self,
         fee: Amount,
@@ -91,7 +111,7 @@ impl Wallet {
         log::info!("Creating Direct-Spend from Wallet.");
 
         // Set the Anti-Fee-Snipping locktime
-        let current_height = self.rpc.get_block_count()?;
+        let current_height = self.chain.block_count()?;
         let lock_time = LockTime::from_height(current_height as u32)?;
 
         let mut tx = Transaction {
@@ -137,12 +157,14 @@ impl Wallet {
         let dest_addr = match destination {
             Destination::Wallet => self.get_next_internal_addresses(1)?[0].clone(),
             Destination::Address(a) => {
-                //testnet and signet addresses have the same vbyte
+                //testnet, testnet4 and signet addresses have the same vbyte
                 //so a.network is always testnet even if the address is signet
                 let testnet_signet_type = (a.as_unchecked().is_valid_for_network(Network::Testnet)
                     || a.as_unchecked().is_valid_for_network(Network::Signet))
-                    && (self.store.network == Network::Testnet
-                        || self.store.network == Network::Signet);
+                    && matches!(
+                        self.store.network,
+                        Network::Testnet | Network::Testnet4 | Network::Signet
+                    );
                 if !a.as_unchecked().is_valid_for_network(self.store.network)
                     && !testnet_signet_type
                 {
@@ -169,16 +191,23 @@ impl Wallet {
 
         tx.output.push(txout);
 
-        // Only include change if remaining > dust
+        // Only include change if remaining is above the dust threshold, else it goes to the fee.
         if let SendAmount::Amount(amount) = send_amount {
-            let internal_spk = self.get_next_internal_addresses(1)?[0].script_pubkey();
-            let remaining = total_input_value - amount - fee;
-            if remaining > internal_spk.minimal_non_dust() {
+            // Change of the same type as the destination, when the wallet can derive it.
+            let change_type = ScriptType::of_address(&dest_addr).unwrap_or(self.store.script_type);
+            let change_addr = self.get_next_internal_addresses_typed(1, change_type)?[0].clone();
+            Wallet::check_change_type(&dest_addr, &change_addr);
+            let internal_spk = change_addr.script_pubkey();
+            let remaining = self.round_change(total_input_value - amount - fee);
+            if !self.is_dust(remaining, &internal_spk) {
                 log::info!("Adding Change {}: {}", internal_spk, remaining);
-                tx.output.push(TxOut {
-                    script_pubkey: internal_spk,
-                    value: remaining,
-                });
+                insert_change(
+                    &mut tx.output,
+                    TxOut {
+                        script_pubkey: internal_spk,
+                        value: remaining,
+                    },
+                );
             } else {
                 log::info!(
                     "Remaining change {} sats is below dust threshold. Skipping change output.",
@@ -194,12 +223,193 @@ impl Wallet {
         log::debug!("Signed Transaction : {:?}", tx.raw_hex());
         Ok(tx)
     }
+
+    /// Consolidates all the regular wallet coins, dust included, into one internal address.
+    ///
+    /// Coin selection never picks dust, so this is the only way dust UTXOs get spent. Swap coins are left
+    /// alone, spending them along with the seed coins would link them together. So are the frozen coins.
+    pub fn consolidate(&mut self, fee: Amount) -> Result<Transaction, WalletError> {
+        let mut coins = self.list_descriptor_utxo_spend_info(None)?;
+        coins.retain(|(utxo, _)| !self.is_frozen(utxo));
+        let total = coins.iter().map(|(utxo, _)| utxo.amount).sum::<Amount>();
+        if coins.len() < 2 || total <= fee {
+            return Err(WalletError::General(format!(
+                "Nothing to consolidate: {} coins worth {}",
+                coins.len(),
+                total
+            )));
+        }
+        log::info!("Consolidating {} coins worth {}", coins.len(), total);
+        self.spend_from_wallet(fee, SendAmount::Max, Destination::Wallet, &coins)
+    }
+
+    /// Create a Child-Pays-For-Parent transaction for a stuck, unconfirmed parent.
+    ///
+    /// Spends the wallet output at `outpoint` to an internal address, with a fee high enough for the
+    /// package (parent + child) to pay `target_feerate`. The returned transaction is signed, but not broadcasted.
+    ///
+    /// ### Note
+    /// Contract outputs can't be used as the CPFP input. Both the hashlock and timelock branches are
+    /// encumbered by relative timelocks (`OP_CSV`), and are not spendable until the contract confirms.
+    /// To speed up a stuck contract, bump one of its wallet-owned siblings (ex: change of the funding tx)
+    /// or the claim transaction spending it.
+    pub fn cpfp(
+        &mut self,
+        outpoint: OutPoint,
+        target_feerate: FeeRate,
+    ) -> Result<Transaction, WalletError> {
+        let coin = self
+            .list_all_utxo_spend_info(None)?
+            .into_iter()
+            .find(|(utxo, _)| utxo.txid == outpoint.txid && utxo.vout == outpoint.vout)
+            .ok_or_else(|| {
+                WalletError::General(format!("Outpoint {} not found in wallet", outpoint))
+            })?;
+
+        if let UTXOSpendInfo::FidelityBondCoin { .. }
+        | UTXOSpendInfo::HashlockContract { .. }
+        | UTXOSpendInfo::TimelockContract { .. } = coin.1
+        {
+            return Err(WalletError::General(
+                "Fidelity bond and contract outputs can't be spent by a CPFP child".to_string(),
+            ));
+        }
+
+        let parent = self.rpc.get_mempool_entry(&outpoint.txid).map_err(|_| {
+            WalletError::General(format!("Parent tx {} is not in the mempool", outpoint.txid))
+        })?;
+
+        let available = coin.0.amount;
+        let dust_limit = coin.0.script_pub_key.minimal_non_dust();
+        let coins = [coin];
+
+        // Sign once to measure the child size, then create the final child with the required fee.
+        let child_vsize = self
+            .spend_from_wallet(Amount::ZERO, SendAmount::Max, Destination::Wallet, &coins)?
+            .vsize() as u64;
+        let package_fee =
+            Amount::from_sat(target_feerate.to_sat_per_vb_ceil() * (parent.vsize + child_vsize));
+
+        let child_fee = match package_fee.checked_sub(parent.fees.base) {
+            Some(fee) if fee.to_sat() >= child_vsize => fee,
+            _ => {
+                return Err(WalletError::General(format!(
+                    "Parent tx {} already pays the target feerate",
+                    outpoint.txid
+                )))
+            }
+        };
+
+        if child_fee + dust_limit > available {
+            return Err(WalletError::InsufficientFund {
+                available: available.to_sat(),
+                required: (child_fee + dust_limit).to_sat(),
+            });
+        }
+
+        log::info!(
+            "Creating CPFP child for {} | package feerate {} sat/vb | child fee {}",
+            outpoint.txid,
+            target_feerate.to_sat_per_vb_ceil(),
+            child_fee
+        );
+        self.spend_from_wallet(child_fee, SendAmount::Max, Destination::Wallet, &coins)
+    }
+
+    /// Lists the unconfirmed coins received by the wallet, whose parent tx, with its unconfirmed ancestors, pays
+    /// below `target_feerate`.
+    ///
+    /// These are the operator deposits, on the external keychain, and the coins of completed incoming swaps. The
+    /// change of our own txs, and frozen coins, are left out. A parent paying several of our coins is listed once.
+    pub fn list_stuck_deposits(
+        &self,
+        target_feerate: FeeRate,
+    ) -> Result<Vec<StuckDeposit>, WalletError> {
+        let mut stuck = Vec::<StuckDeposit>::new();
+        for (utxo, spend_info) in self.list_all_utxo_spend_info(None)? {
+            if utxo.confirmations > 0
+                || self.is_frozen(&utxo)
+                || stuck
+                    .iter()
+                    .any(|deposit| deposit.outpoint.txid == utxo.txid)
+            {
+                continue;
+            }
+            let is_deposit = match &spend_info {
+                UTXOSpendInfo::SeedCoin { .. } => !spend_info.is_change(),
+                // Spendable alone once the swap completed.
+                UTXOSpendInfo::IncomingSwapCoin {
+                    multisig_redeemscript,
+                } => self
+                    .store
+                    .incoming_swapcoins
+                    .get(multisig_redeemscript)
+                    .is_some_and(|coin| coin.other_privkey.is_some()),
+                _ => false,
+            };
+            if !is_deposit {
+                continue;
+            }
+            // Gone from the mempool, nothing to bump.
+            let parent = match self.rpc.get_mempool_entry(&utxo.txid) {
+                Ok(parent) => parent,
+                Err(_) => continue,
+            };
+            if pays_below(parent.fees.ancestor, parent.ancestor_size, target_feerate) {
+                stuck.push(StuckDeposit {
+                    outpoint: OutPoint::new(utxo.txid, utxo.vout),
+                    amount: utxo.amount,
+                    kind: spend_info.to_string(),
+                    feerate: parent.fees.ancestor.to_sat() as f64 / parent.ancestor_size as f64,
+                });
+            }
+        }
+        Ok(stuck)
+    }
+
+    /// Bumps the stuck deposits of [Wallet::list_stuck_deposits] to `target_feerate`, each by a [Wallet::cpfp]
+    /// child spending it to ourselves, and broadcasts the children. Returns their txids.
+    ///
+    /// Deposits too small to pay for their child are skipped.
+    pub fn bump_stuck_deposits(
+        &mut self,
+        target_feerate: FeeRate,
+    ) -> Result<Vec<Txid>, WalletError> {
+        let mut txids = Vec::new();
+        for deposit in self.list_stuck_deposits(target_feerate)? {
+            let child = match self.cpfp(deposit.outpoint, target_feerate) {
+                Ok(child) => child,
+                Err(e) => {
+                    log::warn!("Can't bump deposit {}: {:?}", deposit.outpoint, e);
+                    continue;
+                }
+            };
+            // Broadcast before the next child, which would otherwise reuse its change address.
+            let txid = self.send_tx(&child)?;
+            log::info!(
+                "Bumped deposit {} from {:.2} sat/vb with child {}",
+                deposit.outpoint,
+                deposit.feerate,
+                txid
+            );
+            txids.push(txid);
+        }
+        Ok(txids)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_pays_below() {
+        let target = FeeRate::from_sat_per_vb_u32(5);
+        assert!(pays_below(Amount::from_sat(499), 100, target));
+        assert!(!pays_below(Amount::from_sat(500), 100, target));
+        assert!(!pays_below(Amount::from_sat(1000), 150, target));
+    }
+
     #[test]
     fn test_send_amount_parsing() {
         assert_eq!(SendAmount::from_str("max").unwrap(), SendAmount::Max);
@@ -0,0 +1,379 @@
+//! Encryption of the secrets in the wallet file.
+//!
+//! The master key of the wallet file derives every key of the wallet, and its swap coins hold the contract keys
+//! and preimages of the ongoing swaps: anyone reading them can spend the funds and claim the contracts. With a
+//! wallet passphrase, set in the [WALLET_PASSPHRASE_ENV] environment variable or read from a file with
+//! [load_passphrase_file], they're sealed with ChaCha20-Poly1305, under a key derived from the passphrase with
+//! PBKDF2-HMAC-SHA256. The plaintext buffers and the decoded secrets are wiped after use. The rest of the wallet
+//! file, like the indexes and the sync state, stays in the clear.
+//!
+//! New wallets are encrypted when created with a passphrase. A wallet file stored in the clear stays so, even when
+//! loaded with a passphrase, until it's encrypted explicitly with [Wallet::encrypt_swap_state].
+//!
+//! A passphrase read from a file is only kept in the memory of the process, never in its environment, so the
+//! processes it spawns don't inherit it. They're spawned without [WALLET_PASSPHRASE_ENV] either.
+//!
+//! Other files of the data directory holding swap secrets, like the dispute bundles of the Taker, are sealed under
+//! the same key with [Wallet::seal].
+
+use std::{convert::TryFrom, fmt, fs, path::Path, sync::Mutex};
+
+use bitcoin::secp256k1::rand::{thread_rng, RngCore};
+use chacha20poly1305::{
+    aead::{Aead, KeyInit},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use pbkdf2::pbkdf2_hmac;
+use serde_cbor::Value;
+use sha2::Sha256;
+use zeroize::{Zeroize, Zeroizing};
+
+use super::{migration::RawWalletFile, Wallet, WalletError};
+
+/// Environment variable holding the wallet passphrase.
+pub const WALLET_PASSPHRASE_ENV: &str = "COINSWAP_WALLET_PASSPHRASE";
+
+/// PBKDF2 rounds deriving the key from the passphrase.
+const KDF_ROUNDS: u32 = 100_000;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+/// Field of the wallet file holding the sealed swap state.
+const SEALED_FIELD: &str = "sealed_swap_state";
+/// Fields of the wallet file sealed in the swap state.
+const SWAP_STATE_FIELDS: [&str; 3] = ["master_key", "incoming_swapcoins", "outgoing_swapcoins"];
+
+/// Wallet passphrase read by [load_passphrase_file].
+static LOADED_PASSPHRASE: Mutex<Option<Zeroizing<String>>> = Mutex::new(None);
+
+/// Reads the wallet passphrase from the first line of the file at `path`, for the wallets loaded afterwards by this
+/// process. It overrides [WALLET_PASSPHRASE_ENV], and is kept in memory only: out of the shell history, and of the
+/// environment of the parent shell and of the child processes.
+pub fn load_passphrase_file(path: &Path) -> Result<(), WalletError> {
+    let content = Zeroizing::new(fs::read_to_string(path)?);
+    let passphrase = content.lines().next().unwrap_or_default();
+    if passphrase.is_empty() {
+        return Err(WalletError::General(format!(
+            "No wallet passphrase in {:?}",
+            path
+        )));
+    }
+    *LOADED_PASSPHRASE
+        .lock()
+        .map_err(|_| WalletError::General("Passphrase mutex poisoned".to_string()))? =
+        Some(Zeroizing::new(passphrase.to_string()));
+    Ok(())
+}
+
+/// The wallet passphrase, from [load_passphrase_file] or else [WALLET_PASSPHRASE_ENV]. None if unset or empty.
+pub(crate) fn wallet_passphrase() -> Option<Zeroizing<String>> {
+    let loaded = LOADED_PASSPHRASE
+        .lock()
+        .ok()
+        .and_then(|passphrase| passphrase.clone());
+    loaded
+        .or_else(|| {
+            std::env::var(WALLET_PASSPHRASE_ENV)
+                .ok()
+                .map(Zeroizing::new)
+        })
+        .filter(|passphrase| !passphrase.is_empty())
+}
+
+/// Key of the swap state, derived from the wallet passphrase.
+#[derive(Clone, PartialEq)]
+pub(crate) struct SwapStateKey {
+    key: Zeroizing<[u8; 32]>,
+    salt: [u8; SALT_LEN],
+}
+
+impl fmt::Debug for SwapStateKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SwapStateKey(..)")
+    }
+}
+
+impl SwapStateKey {
+    /// Derives a key from the passphrase, with a new salt.
+    pub(crate) fn new(passphrase: &str) -> Self {
+        let mut salt = [0u8; SALT_LEN];
+        thread_rng().fill_bytes(&mut salt);
+        Self::derive(passphrase, salt)
+    }
+
+    fn derive(passphrase: &str, salt: [u8; SALT_LEN]) -> Self {
+        Self {
+            key: derive_key(passphrase.as_bytes(), &salt, KDF_ROUNDS),
+            salt,
+        }
+    }
+
+    fn cipher(&self) -> ChaCha20Poly1305 {
+        ChaCha20Poly1305::new(Key::from_slice(self.key.as_ref()))
+    }
+}
+
+/// PBKDF2-HMAC-SHA256, with a 32 bytes output.
+fn derive_key(passphrase: &[u8], salt: &[u8], rounds: u32) -> Zeroizing<[u8; 32]> {
+    let mut key = Zeroizing::new([0u8; 32]);
+    pbkdf2_hmac::<Sha256>(passphrase, salt, rounds, key.as_mut());
+    key
+}
+
+/// Wipes the byte strings and texts of a decoded CBOR map, and empties it.
+pub(super) fn wipe(map: &mut RawWalletFile) {
+    for (mut key, mut value) in std::mem::take(map) {
+        wipe_value(&mut key);
+        wipe_value(&mut value);
+    }
+}
+
+fn wipe_value(value: &mut Value) {
+    match value {
+        Value::Bytes(bytes) => bytes.zeroize(),
+        Value::Text(text) => text.zeroize(),
+        Value::Array(values) => values.iter_mut().for_each(wipe_value),
+        Value::Map(map) => wipe(map),
+        Value::Tag(_, value) => wipe_value(value),
+        _ => {}
+    }
+}
+
+fn text(field: &str) -> Value {
+    Value::Text(field.to_string())
+}
+
+fn invalid_sealed_state() -> WalletError {
+    WalletError::General("Invalid sealed swap state in the wallet file".to_string())
+}
+
+fn bytes_field<'a>(sealed: &'a RawWalletFile, field: &str) -> Result<&'a [u8], WalletError> {
+    match sealed.get(&text(field)) {
+        Some(Value::Bytes(bytes)) => Ok(bytes),
+        _ => Err(invalid_sealed_state()),
+    }
+}
+
+/// Encrypts `plaintext` with `key`, into a sealed map holding the salt of the key.
+fn seal(key: &SwapStateKey, plaintext: &[u8]) -> Result<RawWalletFile, WalletError> {
+    let mut nonce = [0u8; NONCE_LEN];
+    thread_rng().fill_bytes(&mut nonce);
+    let ciphertext = key
+        .cipher()
+        .encrypt(Nonce::from_slice(&nonce), plaintext)
+        .map_err(|_| WalletError::General("Failed encrypting the swap state".to_string()))?;
+
+    let mut sealed = RawWalletFile::new();
+    sealed.insert(text("salt"), Value::Bytes(key.salt.to_vec()));
+    sealed.insert(text("nonce"), Value::Bytes(nonce.to_vec()));
+    sealed.insert(text("ciphertext"), Value::Bytes(ciphertext));
+    Ok(sealed)
+}
+
+fn sealed_salt(sealed: &RawWalletFile) -> Result<[u8; SALT_LEN], WalletError> {
+    <[u8; SALT_LEN]>::try_from(bytes_field(sealed, "salt")?).map_err(|_| invalid_sealed_state())
+}
+
+/// Decrypts a sealed map with `key`.
+fn open(key: &SwapStateKey, sealed: &RawWalletFile) -> Result<Zeroizing<Vec<u8>>, WalletError> {
+    let nonce = bytes_field(sealed, "nonce")?;
+    if nonce.len() != NONCE_LEN {
+        return Err(invalid_sealed_state());
+    }
+    let plaintext = key
+        .cipher()
+        .decrypt(Nonce::from_slice(nonce), bytes_field(sealed, "ciphertext")?)
+        .map_err(|_| {
+            WalletError::General("Wrong wallet passphrase, or corrupted swap state".to_string())
+        })?;
+    Ok(Zeroizing::new(plaintext))
+}
+
+/// Moves the swap state of a raw wallet file, the master key and the swap coins, into its sealed field, encrypted
+/// with `key`.
+pub(super) fn seal_swap_state(
+    file: &mut RawWalletFile,
+    key: &SwapStateKey,
+) -> Result<(), WalletError> {
+    let mut state = RawWalletFile::new();
+    for field in SWAP_STATE_FIELDS {
+        if let Some(value) = file.remove(&text(field)) {
+            state.insert(text(field), value);
+        }
+    }
+    let plaintext = Zeroizing::new(serde_cbor::to_vec(&state)?);
+    wipe(&mut state);
+
+    file.insert(text(SEALED_FIELD), Value::Map(seal(key, &plaintext)?));
+    Ok(())
+}
+
+/// Decrypts the sealed swap state of a raw wallet file back into its fields, if sealed. Returns the key of the
+/// passphrase, to seal the state again on save, or None if the file is in the clear. The caller wipes the file with
+/// [wipe] once read.
+pub(super) fn unseal_swap_state(
+    file: &mut RawWalletFile,
+    passphrase: Option<&str>,
+) -> Result<Option<SwapStateKey>, WalletError> {
+    let sealed = match file.remove(&text(SEALED_FIELD)) {
+        Some(Value::Map(sealed)) => sealed,
+        Some(_) => return Err(invalid_sealed_state()),
+        None => return Ok(None),
+    };
+    let passphrase = passphrase.ok_or_else(|| {
+        WalletError::General(format!(
+            "The master key and swap coins of the wallet file are encrypted. Set the wallet passphrase in {}, or pass a \
+             passphrase file.",
+            WALLET_PASSPHRASE_ENV
+        ))
+    })?;
+
+    let key = SwapStateKey::derive(passphrase, sealed_salt(&sealed)?);
+    let plaintext = open(&key, &sealed)?;
+    file.extend(serde_cbor::from_slice::<RawWalletFile>(&plaintext)?);
+    Ok(Some(key))
+}
+
+impl Wallet {
+    /// Sets the passphrase encrypting the master key and the swap coins of the wallet file, and saves it. None to
+    /// store them in the clear.
+    pub fn set_passphrase(&mut self, passphrase: Option<&str>) -> Result<(), WalletError> {
+        self.store.swap_state_key = passphrase.map(SwapStateKey::new);
+        self.save_to_disk()
+    }
+
+    /// Encrypts the master key and the swap coins of a wallet stored in the clear with the passphrase read by
+    /// [load_passphrase_file], or else the one of [WALLET_PASSPHRASE_ENV], and saves it.
+    pub fn encrypt_swap_state(&mut self) -> Result<(), WalletError> {
+        if self.store.swap_state_key.is_some() {
+            return Err(WalletError::General(
+                "The wallet file is already encrypted".to_string(),
+            ));
+        }
+        let passphrase = wallet_passphrase().ok_or_else(|| {
+            WalletError::General(format!(
+                "No wallet passphrase. Set it in {}, or pass a passphrase file.",
+                WALLET_PASSPHRASE_ENV
+            ))
+        })?;
+        self.set_passphrase(Some(&passphrase))
+    }
+
+    /// Encrypts `plaintext` under the key of the wallet passphrase, for a file of the data directory. None if the
+    /// wallet has no passphrase.
+    pub(crate) fn seal(&self, plaintext: &[u8]) -> Result<Option<Vec<u8>>, WalletError> {
+        match &self.store.swap_state_key {
+            Some(key) => Ok(Some(serde_cbor::to_vec(&seal(key, plaintext)?)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Decrypts a file sealed with [Wallet::seal]. Files sealed before the last change of passphrase can't be read.
+    pub(crate) fn unseal(&self, sealed: &[u8]) -> Result<Zeroizing<Vec<u8>>, WalletError> {
+        let sealed = serde_cbor::from_slice::<RawWalletFile>(sealed)?;
+        match &self.store.swap_state_key {
+            Some(key) if sealed_salt(&sealed)? == key.salt => open(key, &sealed),
+            Some(_) => Err(WalletError::General(
+                "The file was sealed under a previous wallet passphrase".to_string(),
+            )),
+            None => Err(WalletError::General(format!(
+                "The file is encrypted. Set the wallet passphrase in {}, or pass a passphrase file.",
+                WALLET_PASSPHRASE_ENV
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoind::tempfile::tempdir;
+    use std::collections::BTreeMap;
+
+    /// Clears the passphrase loaded by a test, so it doesn't leak into the other tests of the process.
+    struct ClearLoadedPassphrase;
+
+    impl Drop for ClearLoadedPassphrase {
+        fn drop(&mut self) {
+            if let Ok(mut passphrase) = LOADED_PASSPHRASE.lock() {
+                *passphrase = None;
+            }
+        }
+    }
+
+    #[test]
+    fn test_load_passphrase_file() {
+        let _clear = ClearLoadedPassphrase;
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("passphrase");
+        fs::write(&path, "").unwrap();
+        assert!(load_passphrase_file(&path).is_err());
+
+        fs::write(&path, "correct horse\nbattery staple\n").unwrap();
+        load_passphrase_file(&path).unwrap();
+        assert_eq!(wallet_passphrase().unwrap().as_str(), "correct horse");
+        // Kept out of the environment inherited by child processes.
+        assert!(std::env::var(WALLET_PASSPHRASE_ENV).is_err());
+    }
+
+    #[test]
+    fn test_derive_key() {
+        // The RFC 6070 inputs, with SHA256.
+        let vectors = [
+            (
+                1,
+                "120fb6cffcf8b32c43e7225256c4f837a86548c92ccc35480805987cb70be17b",
+            ),
+            (
+                2,
+                "ae4d0c95af6b46d32d0adff928f06dd02a303f8ef3c251dfd6e2d85a95474c43",
+            ),
+        ];
+        for (rounds, expected) in vectors {
+            let output = derive_key(b"password", b"salt", rounds);
+            let hex = output
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<String>();
+            assert_eq!(hex, expected);
+        }
+    }
+
+    #[test]
+    fn test_seal_swap_state() {
+        let mut file = RawWalletFile::new();
+        file.insert(text("file_name"), text("wallet"));
+        file.insert(text("master_key"), text("tprv"));
+        let mut swapcoins = RawWalletFile::new();
+        swapcoins.insert(Value::Bytes(vec![1, 2]), Value::Bytes(vec![0xaa; 32]));
+        file.insert(text("incoming_swapcoins"), Value::Map(swapcoins));
+        file.insert(text("outgoing_swapcoins"), Value::Map(BTreeMap::new()));
+        let original = file.clone();
+
+        // Nothing to unseal, and a file in the clear isn't encrypted just because a passphrase is set.
+        assert_eq!(unseal_swap_state(&mut file, None).unwrap(), None);
+        assert_eq!(
+            unseal_swap_state(&mut file, Some("correct horse")).unwrap(),
+            None
+        );
+        assert_eq!(file, original);
+
+        let key = SwapStateKey::new("correct horse");
+        seal_swap_state(&mut file, &key).unwrap();
+        assert_ne!(file, original);
+        assert!(SWAP_STATE_FIELDS
+            .iter()
+            .all(|field| !file.contains_key(&text(field))));
+
+        let encoded = serde_cbor::to_vec(&file).unwrap();
+        let read = |passphrase| {
+            let mut file = serde_cbor::from_slice::<RawWalletFile>(&encoded).unwrap();
+            unseal_swap_state(&mut file, passphrase).map(|key| (file, key))
+        };
+        assert!(read(None).is_err());
+        assert!(read(Some("wrong horse")).is_err());
+        let (unsealed, unsealed_key) = read(Some("correct horse")).unwrap();
+        assert_eq!(unsealed, original);
+        assert_eq!(unsealed_key, Some(key));
+    }
+}
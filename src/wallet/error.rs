@@ -1,6 +1,6 @@
 //! All Wallet-related errors.
 
-use crate::protocol::error::ProtocolError;
+use crate::{error::ErrorCode, protocol::error::ProtocolError};
 
 use super::fidelity::FidelityError;
 
@@ -75,6 +75,38 @@ pub enum WalletError {
     },
 }
 
+impl ErrorCode for WalletError {
+    fn code(&self) -> u16 {
+        match self {
+            Self::IO(_) => 3000,
+            Self::Cbor(_) => 3001,
+            Self::Rpc(_) => 3002,
+            Self::BIP32(_) => 3003,
+            Self::BIP39(_) => 3004,
+            Self::General(_) => 3005,
+            Self::Protocol(e) => e.code(),
+            Self::Fidelity(e) => match e {
+                FidelityError::WrongScriptType => 3100,
+                FidelityError::BondDoesNotExist => 3101,
+                FidelityError::BondAlreadySpent => 3102,
+                FidelityError::BondLocktimeExpired => 3103,
+                FidelityError::CertExpired => 3104,
+                FidelityError::InvalidCertHash => 3105,
+                FidelityError::General(_) => 3106,
+            },
+            Self::Locktime(_) => 3006,
+            Self::Secp(_) => 3007,
+            Self::Consensus(_) => 3008,
+            Self::InsufficientFund { .. } => 3009,
+        }
+    }
+
+    fn is_transient(&self) -> bool {
+        // The node may be restarting, or busy.
+        matches!(self, Self::IO(_) | Self::Rpc(_))
+    }
+}
+
 impl From<std::io::Error> for WalletError {
     fn from(e: std::io::Error) -> Self {
         Self::IO(e)
@@ -135,6 +167,12 @@ impl From<bitcoin::sighash::P2wpkhError> for WalletError {
     }
 }
 
+impl From<bitcoin::sighash::TaprootError> for WalletError {
+    fn from(value: bitcoin::sighash::TaprootError) -> Self {
+        Self::Consensus(value.to_string())
+    }
+}
+
 impl From<bitcoin::key::UncompressedPublicKeyError> for WalletError {
     fn from(value: bitcoin::key::UncompressedPublicKeyError) -> Self {
         Self::Consensus(value.to_string())
@@ -152,3 +190,9 @@ impl From<bitcoin::consensus::encode::Error> for WalletError {
         Self::Consensus(value.to_string())
     }
 }
+
+impl From<bitcoin::psbt::Error> for WalletError {
+    fn from(value: bitcoin::psbt::Error) -> Self {
+        Self::Consensus(value.to_string())
+    }
+}
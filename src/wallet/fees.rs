@@ -0,0 +1,299 @@
+//! Fee estimation.
+//!
+//! Feerates are estimated by a [FeeEstimator]: the Bitcoin Core node, the mempool.space API, or a static
+//! override. The wallet asks the [FeeSource] set in the config first, and falls back to the node when it has
+//! no estimate. Before high-value broadcasts, [Wallet::cross_check_feerate] compares the estimate against
+//! the node's, and flags them if they diverge wildly: one of them is broken, stale, or lying.
+
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+use bitcoin::Network;
+use bitcoind::bitcoincore_rpc::{Client, RpcApi};
+use serde_json::Value;
+
+use crate::broadcast::{tor_http_request, MEMPOOL_SPACE_ONION};
+
+use super::{Wallet, WalletError};
+
+/// Estimates diverging by more than this factor are flagged.
+pub const MAX_FEERATE_DIVERGENCE: f64 = 3.0;
+
+/// A source of feerate estimates.
+pub trait FeeEstimator: Send + Sync {
+    /// Name of the source, for the logs.
+    fn name(&self) -> &str;
+
+    /// Feerate for confirming within `conf_target` blocks, in sats/vB. None if the source has no estimate.
+    fn estimate(&self, conf_target: u16) -> Result<Option<f64>, WalletError>;
+}
+
+impl FeeEstimator for Client {
+    fn name(&self) -> &str {
+        "bitcoind"
+    }
+
+    fn estimate(&self, conf_target: u16) -> Result<Option<f64>, WalletError> {
+        // Estimates are in sats/kvB.
+        Ok(self
+            .estimate_smart_fee(conf_target, None)?
+            .fee_rate
+            .map(|fee_rate| fee_rate.to_sat() as f64 / 1000.0))
+    }
+}
+
+/// Estimates of the mempool.space `fees/recommended` API, reached over Tor.
+#[derive(Debug, Clone)]
+pub struct MempoolSpaceEstimator {
+    url: String,
+    socks_port: u16,
+}
+
+impl MempoolSpaceEstimator {
+    /// Estimator for `network`, through the onion service of mempool.space.
+    pub fn new(network: Network, socks_port: u16) -> Self {
+        let network_path = match network {
+            Network::Testnet => "/testnet",
+            Network::Testnet4 => "/testnet4",
+            Network::Signet => "/signet",
+            _ => "",
+        };
+        Self {
+            url: format!(
+                "{}{}/api/v1/fees/recommended",
+                MEMPOOL_SPACE_ONION, network_path
+            ),
+            socks_port,
+        }
+    }
+}
+
+/// Picks the recommended feerate for the confirmation target, from a `fees/recommended` response.
+fn recommended_feerate(response: &Value, conf_target: u16) -> Option<f64> {
+    let field = match conf_target {
+        0..=1 => "fastestFee",
+        2..=3 => "halfHourFee",
+        4..=6 => "hourFee",
+        _ => "economyFee",
+    };
+    response[field].as_f64()
+}
+
+impl FeeEstimator for MempoolSpaceEstimator {
+    fn name(&self) -> &str {
+        "mempool.space"
+    }
+
+    fn estimate(&self, conf_target: u16) -> Result<Option<f64>, WalletError> {
+        let response = tor_http_request(&self.url, self.socks_port, "GET", "")?;
+        let response = serde_json::from_str::<Value>(&response)
+            .map_err(|e| WalletError::General(format!("Invalid fee estimates: {}", e)))?;
+        Ok(recommended_feerate(&response, conf_target))
+    }
+}
+
+/// A static feerate, for all confirmation targets.
+#[derive(Debug, Clone, Copy)]
+pub struct StaticFeeEstimator(pub f64);
+
+impl FeeEstimator for StaticFeeEstimator {
+    fn name(&self) -> &str {
+        "static"
+    }
+
+    fn estimate(&self, _conf_target: u16) -> Result<Option<f64>, WalletError> {
+        Ok(Some(self.0))
+    }
+}
+
+/// Source of the feerate estimates, as set in the config.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum FeeSource {
+    /// The Bitcoin Core node.
+    #[default]
+    Bitcoind,
+    /// The mempool.space API, over Tor.
+    MempoolSpace,
+    /// A static feerate, in sats/vB.
+    Static(f64),
+}
+
+impl FromStr for FeeSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_lowercase();
+        match s.as_str() {
+            "bitcoind" => Ok(Self::Bitcoind),
+            "mempool" => Ok(Self::MempoolSpace),
+            _ => match s.strip_prefix("static:").map(str::parse::<f64>) {
+                Some(Ok(feerate)) if feerate > 0.0 => Ok(Self::Static(feerate)),
+                _ => Err(format!("Unknown fee source: {}", s)),
+            },
+        }
+    }
+}
+
+impl Display for FeeSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Bitcoind => write!(f, "bitcoind"),
+            Self::MempoolSpace => write!(f, "mempool"),
+            Self::Static(feerate) => write!(f, "static:{}", feerate),
+        }
+    }
+}
+
+impl FeeSource {
+    /// The estimator of the source. None for the node, which the wallet always has. mempool.space needs Tor,
+    /// through `socks_port`.
+    pub fn estimator(
+        &self,
+        network: Network,
+        socks_port: Option<u16>,
+    ) -> Option<Box<dyn FeeEstimator>> {
+        match (self, socks_port) {
+            (Self::Bitcoind, _) => None,
+            (Self::MempoolSpace, Some(socks_port)) => {
+                Some(Box::new(MempoolSpaceEstimator::new(network, socks_port)))
+            }
+            (Self::MempoolSpace, None) => {
+                log::warn!("mempool.space fee estimates need Tor. Using the node's estimates.");
+                None
+            }
+            (Self::Static(feerate), _) => Some(Box::new(StaticFeeEstimator(*feerate))),
+        }
+    }
+}
+
+/// Estimates of two sources diverging by more than [MAX_FEERATE_DIVERGENCE].
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeerateDivergence {
+    /// Estimate of the configured source, in sats/vB.
+    pub estimate: f64,
+    /// Estimate of the node, in sats/vB.
+    pub node_estimate: f64,
+}
+
+/// Flags the estimates if one is more than [MAX_FEERATE_DIVERGENCE] times the other.
+fn check_divergence(estimate: f64, node_estimate: f64) -> Option<FeerateDivergence> {
+    let (low, high) = if estimate < node_estimate {
+        (estimate, node_estimate)
+    } else {
+        (node_estimate, estimate)
+    };
+    (high > low * MAX_FEERATE_DIVERGENCE).then_some(FeerateDivergence {
+        estimate,
+        node_estimate,
+    })
+}
+
+impl Wallet {
+    /// Sets the source of the feerate estimates, tried before the node. None to use the node only.
+    pub fn set_fee_estimator(&mut self, estimator: Option<Box<dyn FeeEstimator>>) {
+        if let Some(estimator) = &estimator {
+            log::info!("Using {} fee estimates", estimator.name());
+        }
+        self.fee_estimator = estimator;
+    }
+
+    /// Feerate estimate for confirming within `conf_target` blocks, in sats/vB, from the configured source, or
+    /// the node if it has none. None if neither has an estimate.
+    pub(crate) fn estimate_feerate(&self, conf_target: u16) -> Result<Option<f64>, WalletError> {
+        if let Some(estimator) = &self.fee_estimator {
+            match estimator.estimate(conf_target) {
+                Ok(Some(feerate)) => return Ok(Some(feerate)),
+                Ok(None) => log::debug!("No {} fee estimate, using the node's", estimator.name()),
+                Err(e) => log::warn!(
+                    "Failed getting {} fee estimate, using the node's | {:?}",
+                    estimator.name(),
+                    e
+                ),
+            }
+        }
+        self.chain.estimate_feerate(conf_target)
+    }
+
+    /// Cross-checks the estimate of the configured source against the node's, before a high-value broadcast.
+    /// Logs a warning, and returns the estimates, if they diverge wildly.
+    pub fn cross_check_feerate(
+        &self,
+        conf_target: u16,
+    ) -> Result<Option<FeerateDivergence>, WalletError> {
+        let estimator = match &self.fee_estimator {
+            Some(estimator) => estimator,
+            None => return Ok(None),
+        };
+        let (estimate, node_estimate) = match (
+            estimator.estimate(conf_target)?,
+            self.chain.estimate_feerate(conf_target)?,
+        ) {
+            (Some(estimate), Some(node_estimate)) => (estimate, node_estimate),
+            _ => return Ok(None),
+        };
+        let divergence = check_divergence(estimate, node_estimate);
+        if divergence.is_some() {
+            log::warn!(
+                "Fee estimates diverge! {} estimates {:.1} sats/vB, the node {:.1} sats/vB. Check both before broadcasting.",
+                estimator.name(),
+                estimate,
+                node_estimate
+            );
+        }
+        Ok(divergence)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fee_sources() {
+        assert_eq!(
+            FeeSource::from_str("bitcoind").unwrap(),
+            FeeSource::Bitcoind
+        );
+        assert_eq!(
+            FeeSource::from_str("Mempool").unwrap(),
+            FeeSource::MempoolSpace
+        );
+        assert_eq!(
+            FeeSource::from_str("static:2.5").unwrap(),
+            FeeSource::Static(2.5)
+        );
+        assert!(FeeSource::from_str("static:0").is_err());
+        assert!(FeeSource::from_str("esplora").is_err());
+        for source in [
+            FeeSource::Bitcoind,
+            FeeSource::MempoolSpace,
+            FeeSource::Static(4.0),
+        ] {
+            assert_eq!(FeeSource::from_str(&source.to_string()).unwrap(), source);
+        }
+
+        let response = serde_json::json!({
+            "fastestFee": 20,
+            "halfHourFee": 12,
+            "hourFee": 8,
+            "economyFee": 3,
+            "minimumFee": 1
+        });
+        assert_eq!(recommended_feerate(&response, 1), Some(20.0));
+        assert_eq!(recommended_feerate(&response, 3), Some(12.0));
+        assert_eq!(recommended_feerate(&response, 6), Some(8.0));
+        assert_eq!(recommended_feerate(&response, 144), Some(3.0));
+
+        assert_eq!(check_divergence(10.0, 25.0), None);
+        assert_eq!(
+            check_divergence(100.0, 25.0),
+            Some(FeerateDivergence {
+                estimate: 100.0,
+                node_estimate: 25.0
+            })
+        );
+        assert!(check_divergence(2.0, 7.0).is_some());
+    }
+}
@@ -1,24 +1,24 @@
 use std::{
+    cmp::Reverse,
     collections::HashMap,
-    str::FromStr,
     thread,
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use crate::{
     protocol::messages::FidelityProof,
-    taker::api::MINER_FEE,
     utill::{redeemscript_to_scriptpubkey, verify_fidelity_checks},
-    wallet::{UTXOSpendInfo, Wallet},
+    wallet::{UTXOSpendInfo, Wallet, MINER_FEE},
 };
 
+#[cfg(feature = "maker")]
+use bitcoin::secp256k1::{ecdsa::Signature, Message};
 use bitcoin::{
     absolute::LockTime,
-    bip32::{ChildNumber, DerivationPath},
     hashes::{sha256d, Hash},
     opcodes::all::{OP_CHECKSIGVERIFY, OP_CLTV},
     script::{Builder, Instruction},
-    secp256k1::{Keypair, Message, Secp256k1},
+    secp256k1::{Keypair, Secp256k1},
     transaction::Version,
     Address, Amount, OutPoint, PublicKey, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid,
     Witness,
@@ -26,7 +26,7 @@ use bitcoin::{
 use bitcoind::bitcoincore_rpc::RpcApi;
 use serde::{Deserialize, Serialize};
 
-use super::WalletError;
+use super::{paths::KeyClass, WalletError};
 
 // To (strongly) disincentivize Sybil behavior, the value assessment of the bond
 // is based on the (time value of the bond)^x here x is the bond_value_exponent,
@@ -40,9 +40,6 @@ const BOND_VALUE_EXPONENT: f64 = 1.3;
 // Set as a real number, i.e. 1 = 100% and 0.01 = 1%
 const BOND_VALUE_INTEREST_RATE: f64 = 0.015;
 
-/// Constant representing the derivation path for fidelity addresses.
-const FIDELITY_DERIVATION_PATH: &str = "m/84'/0'/0'/2";
-
 /// Error structure defining possible fidelity related errors
 #[derive(Debug)]
 pub enum FidelityError {
@@ -118,6 +115,22 @@ pub(crate) fn calculate_fidelity_value(
     Amount::from_sat(((value.to_sat() as f64) * timevalue).powf(BOND_VALUE_EXPONENT) as u64)
 }
 
+/// Estimates the unix time of a bond locktime. Block heights are estimated from the tip, at 10 minutes per block.
+/// None if a height locktime is already reached.
+pub(crate) fn estimate_locktime_timestamp(
+    lock_time: LockTime,
+    tip_height: u64,
+    tip_time: u64,
+) -> Option<u64> {
+    match lock_time {
+        // Estimated locktime from block height = [current-time + (maturity-height - block-count) * 10 * 60] sec
+        LockTime::Blocks(blocks) => (blocks.to_consensus_u32() as u64)
+            .checked_sub(tip_height)
+            .map(|height_diff| tip_time + height_diff * 10 * 60),
+        LockTime::Seconds(sec) => Some(sec.to_consensus_u32() as u64),
+    }
+}
+
 /// Structure describing a Fidelity Bond.
 /// Fidelity Bonds are described in https://github.com/JoinMarket-Org/joinmarket-clientserver/blob/master/docs/fidelity-bonds.md
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, PartialOrd, Hash)]
@@ -147,19 +160,32 @@ impl FidelityBond {
 
     /// Generate the bond's certificate hash.
     pub(crate) fn generate_cert_hash(&self, addr: &str) -> sha256d::Hash {
-        let cert_msg_str = format!(
+        signed_message_hash(&format!(
             "fidelity-bond-cert|{}|{}|{}|{}|{}|{}",
             self.outpoint, self.pubkey, self.cert_expiry, self.lock_time, self.amount, addr
-        );
-        let cert_msg = cert_msg_str.as_bytes();
-        let mut btc_signed_msg = Vec::<u8>::new();
-        btc_signed_msg.extend("\x18Bitcoin Signed Message:\n".as_bytes());
-        btc_signed_msg.push(cert_msg.len() as u8);
-        btc_signed_msg.extend(cert_msg);
-        sha256d::Hash::hash(&btc_signed_msg)
+        ))
+    }
+
+    /// Generate the hash signed to remove the bond's address from the directory, at the given unix time.
+    #[cfg(any(feature = "maker", feature = "directory"))]
+    pub(crate) fn generate_removal_hash(&self, addr: &str, timestamp: u64) -> sha256d::Hash {
+        signed_message_hash(&format!(
+            "fidelity-bond-remove|{}|{}|{}",
+            self.outpoint, addr, timestamp
+        ))
     }
 }
 
+/// Hash of a message in the Bitcoin signed message format.
+pub(super) fn signed_message_hash(msg: &str) -> sha256d::Hash {
+    let msg = msg.as_bytes();
+    let mut btc_signed_msg = Vec::<u8>::new();
+    btc_signed_msg.extend("\x18Bitcoin Signed Message:\n".as_bytes());
+    btc_signed_msg.push(msg.len() as u8);
+    btc_signed_msg.extend(msg);
+    sha256d::Hash::hash(&btc_signed_msg)
+}
+
 // Wallet APIs related to fidelity bonds.
 impl Wallet {
     /// Get a reference to the fidelity bond store
@@ -205,14 +231,10 @@ impl Wallet {
     pub(crate) fn get_fidelity_keypair(&self, index: u32) -> Result<Keypair, WalletError> {
         let secp = Secp256k1::new();
 
-        let derivation_path = DerivationPath::from_str(FIDELITY_DERIVATION_PATH)?;
-
-        let child_derivation_path = derivation_path.child(ChildNumber::Normal { index });
-
         Ok(self
             .store
             .master_key
-            .derive_priv(&secp, &child_derivation_path)?
+            .derive_priv(&secp, &KeyClass::Fidelity.path(index)?)?
             .to_keypair(&secp))
     }
 
@@ -227,7 +249,7 @@ impl Wallet {
     }
 
     /// Get the next fidelity bond address. If no fidelity bond is created
-    /// returned address will be derived from index 0, of the [FIDELITY_DERIVATION_PATH]
+    /// returned address will be derived from index 0, of the [FIDELITY_PATH](super::paths::FIDELITY_PATH)
     pub(crate) fn get_next_fidelity_address(
         &self,
         locktime: LockTime,
@@ -275,25 +297,11 @@ impl Wallet {
 
         let confirmation_time = self.rpc.get_block_header_info(&hash)?.time as u64;
 
-        let locktime = match bond.lock_time {
-            LockTime::Blocks(blocks) => {
-                let tip_hash = self.rpc.get_blockchain_info()?.best_block_hash;
-                let (tip_height, tip_time) = {
-                    let info = self.rpc.get_block_header_info(&tip_hash)?;
-                    (info.height, info.time as u64)
-                };
-                // Estimated locktime from block height = [current-time + (maturity-height - block-count) * 10 * 60] sec
-                let height_diff =
-                    if let Some(x) = blocks.to_consensus_u32().checked_sub(tip_height as u32) {
-                        x as u64
-                    } else {
-                        return Err(FidelityError::BondLocktimeExpired.into());
-                    };
-
-                tip_time + (height_diff * 10 * 60)
-            }
-            LockTime::Seconds(sec) => sec.to_consensus_u32() as u64,
-        };
+        let tip_hash = self.rpc.get_blockchain_info()?.best_block_hash;
+        let tip = self.rpc.get_block_header_info(&tip_hash)?;
+        let locktime =
+            estimate_locktime_timestamp(bond.lock_time, tip.height as u64, tip.time as u64)
+                .ok_or(FidelityError::BondLocktimeExpired)?;
 
         let bond_value =
             calculate_fidelity_value(bond.amount, locktime, confirmation_time, current_time);
@@ -317,13 +325,14 @@ impl Wallet {
         let mut swap_coin_utxo = self.list_incoming_swap_coin_utxo_spend_info(Some(&all_utxos))?;
         seed_coin_utxo.append(&mut swap_coin_utxo);
 
-        // Fetch utxos, filter out existing fidelity coins
+        // Fetch utxos, filter out existing fidelity coins and frozen coins
         let mut unspents = seed_coin_utxo
             .into_iter()
             .filter(|(_, spend_info)| !matches!(spend_info, UTXOSpendInfo::FidelityBondCoin { .. }))
+            .filter(|(utxo, _)| !self.is_frozen(utxo))
             .collect::<Vec<_>>();
 
-        unspents.sort_by(|a, b| b.0.amount.cmp(&a.0.amount));
+        unspents.sort_by_key(|(utxo, _)| Reverse(utxo.amount));
 
         let mut selected_utxo = Vec::new();
         let mut remaining = amount;
@@ -372,7 +381,7 @@ impl Wallet {
         if let Some(change) = change_amount {
             let change_addrs = self.get_next_internal_addresses(1)?[0].script_pubkey();
             // check for dust
-            if change > change_addrs.minimal_non_dust() {
+            if !self.is_dust(change, &change_addrs) {
                 tx_outs.push(TxOut {
                     value: change,
                     script_pubkey: change_addrs,
@@ -381,7 +390,7 @@ impl Wallet {
         }
 
         // Set the Anti-Fee Snipping Locktime
-        let current_height = self.rpc.get_block_count()?;
+        let current_height = self.chain.block_count()?;
         let lock_time = LockTime::from_height(current_height as u32)?;
 
         let mut tx = Transaction {
@@ -451,6 +460,30 @@ impl Wallet {
     /// This functions creates a spending transaction from the fidelity bond, signs and broadcasts it.
     /// Returns the txid of the spending tx, and mark the bond as spent.
     pub fn redeem_fidelity(&mut self, index: u32) -> Result<Txid, WalletError> {
+        let tx = self.create_fidelity_redeem_tx(index)?;
+        let txid = self.send_tx(&tx)?;
+
+        log::info!("Fidelity redeem transaction broadcasted. txid: {}", txid);
+
+        // No need to wait for confirmation as that will delay the rpc call. Just send back the txid.
+
+        // mark is_spent
+        {
+            let (_, _, is_spent) = self
+                .store
+                .fidelity_bond
+                .get_mut(&index)
+                .ok_or(FidelityError::BondDoesNotExist)?;
+
+            *is_spent = true;
+        }
+
+        Ok(txid)
+    }
+
+    /// Create a fully signed transaction spending a Fidelity Bond to the wallet. Only valid once the
+    /// bond's locktime has passed.
+    pub(crate) fn create_fidelity_redeem_tx(&self, index: u32) -> Result<Transaction, WalletError> {
         let (bond, _, is_spent) = self
             .store
             .fidelity_bond
@@ -493,27 +526,11 @@ impl Wallet {
 
         self.sign_transaction(&mut tx, vec![utxo_spend_info].into_iter())?;
 
-        let txid = self.send_tx(&tx)?;
-
-        log::info!("Fidelity redeem transaction broadcasted. txid: {}", txid);
-
-        // No need to wait for confirmation as that will delay the rpc call. Just send back the txid.
-
-        // mark is_spent
-        {
-            let (_, _, is_spent) = self
-                .store
-                .fidelity_bond
-                .get_mut(&index)
-                .ok_or(FidelityError::BondDoesNotExist)?;
-
-            *is_spent = true;
-        }
-
-        Ok(txid)
+        Ok(tx)
     }
 
     /// Generate a [FidelityProof] for bond at a given index and a specific onion address.
+    #[cfg(feature = "maker")]
     pub(crate) fn generate_fidelity_proof(
         &self,
         index: u32,
@@ -547,6 +564,29 @@ impl Wallet {
         })
     }
 
+    /// Signs the removal of our address from the directory, with the key of the bond in the proof.
+    #[cfg(feature = "maker")]
+    pub(crate) fn sign_directory_removal(
+        &self,
+        proof: &FidelityProof,
+        maker_addr: &str,
+        timestamp: u64,
+    ) -> Result<Signature, WalletError> {
+        let index = self
+            .store
+            .fidelity_bond
+            .iter()
+            .find(|(_, (bond, _, _))| bond.outpoint == proof.bond.outpoint)
+            .map(|(i, _)| *i)
+            .ok_or(FidelityError::BondDoesNotExist)?;
+        let fidelity_privkey = self.get_fidelity_keypair(index)?.secret_key();
+        let removal_hash = proof.bond.generate_removal_hash(maker_addr, timestamp);
+        Ok(Secp256k1::new().sign_ecdsa(
+            &Message::from_digest_slice(removal_hash.as_byte_array())?,
+            &fidelity_privkey,
+        ))
+    }
+
     /// Verify a [FidelityProof] received from the directory servers.
     pub(crate) fn verify_fidelity_proof(
         &self,
@@ -555,14 +595,14 @@ impl Wallet {
     ) -> Result<(), WalletError> {
         let txid = proof.bond.outpoint.txid;
         let transaction = self.rpc.get_raw_transaction(&txid, None)?;
-        let current_height = self.rpc.get_block_count()?;
+        let current_height = self.chain.block_count()?;
 
         verify_fidelity_checks(proof, onion_addr, transaction, current_height)
     }
 
     /// Calculate the expiry value. This depends on the current block height.
     pub(crate) fn get_fidelity_expiry(&self) -> Result<u64, WalletError> {
-        let current_height = self.rpc.get_block_count()?;
+        let current_height = self.chain.block_count()?;
         Ok((current_height + 2) /* safety buffer */ / 2016 + 5)
     }
 }
@@ -697,6 +737,8 @@ mod test {
 
 #[test]
 fn test_fidleity_redeemscripts() {
+    use std::str::FromStr;
+
     let test_data = [
         (
             (
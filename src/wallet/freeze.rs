@@ -0,0 +1,60 @@
+//! Frozen UTXOs.
+//!
+//! Operators freeze the wallet coins they don't want spent, e.g. coins of a known origin kept apart from the
+//! swapped ones, or coins reserved for a later payment. Frozen coins are never picked by the automatic coin
+//! selections: swap funding, fidelity bonds, consolidation, payjoin and sweeps. They still count in the
+//! balances, as [Balances::frozen](super::Balances), but not in the spendable balance.
+//!
+//! The frozen outpoints are saved in the wallet file, and stay frozen until unfrozen, or spent manually.
+
+use bitcoin::OutPoint;
+use bitcoind::bitcoincore_rpc::json::ListUnspentResultEntry;
+
+use super::{Wallet, WalletError};
+
+impl Wallet {
+    /// Freezes a wallet UTXO, excluding it from the automatic coin selections.
+    pub fn freeze(&mut self, outpoint: OutPoint) -> Result<(), WalletError> {
+        let is_wallet_utxo = self
+            .list_all_utxo_spend_info(None)?
+            .iter()
+            .any(|(utxo, _)| utxo.txid == outpoint.txid && utxo.vout == outpoint.vout);
+        if !is_wallet_utxo {
+            return Err(WalletError::General(format!(
+                "{} isn't an unspent output of the wallet",
+                outpoint
+            )));
+        }
+        if self.store.frozen_utxos.insert(outpoint) {
+            log::info!("Froze UTXO {}", outpoint);
+        }
+        self.save_to_disk()
+    }
+
+    /// Unfreezes a UTXO frozen by [Wallet::freeze].
+    pub fn unfreeze(&mut self, outpoint: OutPoint) -> Result<(), WalletError> {
+        if !self.store.frozen_utxos.remove(&outpoint) {
+            return Err(WalletError::General(format!(
+                "UTXO {} isn't frozen",
+                outpoint
+            )));
+        }
+        log::info!("Unfroze UTXO {}", outpoint);
+        self.save_to_disk()
+    }
+
+    /// The frozen outpoints, spent ones included.
+    pub fn frozen_utxos(&self) -> Vec<OutPoint> {
+        let mut frozen = self.store.frozen_utxos.iter().copied().collect::<Vec<_>>();
+        frozen.sort();
+        frozen
+    }
+
+    /// Whether the UTXO is frozen.
+    pub fn is_frozen(&self, utxo: &ListUnspentResultEntry) -> bool {
+        self.store.frozen_utxos.contains(&OutPoint {
+            txid: utxo.txid,
+            vout: utxo.vout,
+        })
+    }
+}
@@ -15,12 +15,39 @@ use bitcoind::bitcoincore_rpc::{json::CreateRawTransactionInput, RpcApi};
 
 use bitcoin::secp256k1::rand::{rngs::OsRng, RngCore};
 
-use crate::taker::api::MINER_FEE;
-
-use super::Wallet;
+use super::{change::insert_change, Wallet};
 
 use super::error::WalletError;
 
+// TODO: Maker should decide this miner fee
+// This fee is used for both funding and contract txs.
+#[cfg(feature = "integration-test")]
+pub(crate) const MINER_FEE: u64 = 1000;
+
+/// This fee is used for both funding and contract txs.
+#[cfg(not(feature = "integration-test"))]
+pub(crate) const MINER_FEE: u64 = 300; // around 2 sats/vb for funding tx
+
+/// How the swap amount is distributed across the `tx_count` funding transactions of a hop.
+///
+/// Output amounts are visible on chain, so the distribution should avoid making the funding
+/// transactions of a swap trivially linkable to each other.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum AmountDistribution {
+    /// Uniformly random splits.
+    #[default]
+    Random,
+    /// Equal splits. The rounding remainder goes to one of the outputs.
+    Equal,
+    /// Equal splits, each randomly moved by up to `tolerance_pct` percent (below 100) of the equal share.
+    Randomized {
+        /// Maximum deviation from the equal share, in percent.
+        tolerance_pct: u8,
+    },
+    /// Power-of-two denominations. One output carries the remainder.
+    PowerOfTwo,
+}
+
 #[derive(Debug)]
 pub(crate) struct CreateFundingTxesResult {
     pub(crate) funding_txes: Vec<Transaction>,
@@ -28,6 +55,39 @@ pub(crate) struct CreateFundingTxesResult {
     pub(crate) total_miner_fee: u64,
 }
 
+/// Constraints on the wallet coins spent by the funding txs of a swap.
+///
+/// An unconfirmed coin disappears if one of its ancestors is replaced, and the funding tx spending it with it, failing
+/// the swap after the contracts were signed. No constraints by default.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct InputConstraints {
+    /// Fewest confirmations of the coins.
+    pub(crate) min_confirmations: u32,
+    /// Fewest seconds since the coins confirmed.
+    pub(crate) min_age_secs: u64,
+    /// Don't spend the unconfirmed change of our own txs, even without `min_confirmations`.
+    pub(crate) forbid_unconfirmed_change: bool,
+}
+
+impl InputConstraints {
+    /// Whether a coin of `confirmations`, confirmed at unix time `confirmed_at`, can be spent at `now`.
+    pub(crate) fn allows(
+        &self,
+        confirmations: u32,
+        is_change: bool,
+        confirmed_at: Option<u64>,
+        now: u64,
+    ) -> bool {
+        if confirmations < self.min_confirmations
+            || (confirmations == 0 && is_change && self.forbid_unconfirmed_change)
+        {
+            return false;
+        }
+        self.min_age_secs == 0
+            || matches!(confirmed_at, Some(time) if now.saturating_sub(time) >= self.min_age_secs)
+    }
+}
+
 impl Wallet {
     // Attempts to create the funding transactions.
     /// Returns Ok(None) if there was no error but the wallet was unable to create funding txes
@@ -36,8 +96,16 @@ impl Wallet {
         coinswap_amount: Amount,
         destinations: &[Address],
         fee_rate: Amount,
+        amount_distribution: AmountDistribution,
+        input_constraints: InputConstraints,
     ) -> Result<CreateFundingTxesResult, WalletError> {
-        let ret = self.create_funding_txes_random_amounts(coinswap_amount, destinations, fee_rate);
+        let ret = self.create_funding_txes_random_amounts(
+            coinswap_amount,
+            destinations,
+            fee_rate,
+            amount_distribution,
+            input_constraints,
+        );
         if ret.is_ok() {
             log::info!(target: "wallet", "created funding txes with random amounts");
             return ret;
@@ -122,18 +190,84 @@ impl Wallet {
         Ok(output_values)
     }
 
+    /// Splits `total_amount` into `count` output values following `distribution`.
+    pub(crate) fn split_amount(
+        count: usize,
+        total_amount: Amount,
+        distribution: AmountDistribution,
+    ) -> Result<Vec<u64>, WalletError> {
+        if count == 0 {
+            return Err(WalletError::General(
+                "Can't split amount into zero outputs".to_string(),
+            ));
+        }
+        let total = total_amount.to_sat();
+        let share = total / count as u64;
+
+        let mut output_values = match distribution {
+            AmountDistribution::Random => {
+                return Wallet::generate_amount_fractions(count, total_amount)
+            }
+            AmountDistribution::Equal => vec![share; count],
+            AmountDistribution::Randomized { tolerance_pct } => {
+                if tolerance_pct >= 100 {
+                    return Err(WalletError::General(format!(
+                        "Amount tolerance must be below 100%, got {}%",
+                        tolerance_pct
+                    )));
+                }
+                let max_deviation = share * tolerance_pct as u64 / 100;
+                (0..count)
+                    .map(|_| {
+                        let deviation = OsRng.next_u64() % (2 * max_deviation + 1);
+                        share - max_deviation + deviation
+                    })
+                    .collect()
+            }
+            AmountDistribution::PowerOfTwo => {
+                // Largest power of two not above the equal share, so the remainder is never negative.
+                let denomination = match share.checked_ilog2() {
+                    Some(exp) => 1u64 << exp,
+                    None => 0,
+                };
+                vec![denomination; count]
+            }
+        };
+
+        // Put the remainder into a random output, so its position doesn't leak.
+        let remainder_pos = (OsRng.next_u32() as usize) % count;
+        output_values[remainder_pos] = 0;
+        output_values[remainder_pos] = total
+            .checked_sub(output_values.iter().sum::<u64>())
+            .ok_or_else(|| {
+                WalletError::General("Amount tolerance too large for the split".to_string())
+            })?;
+
+        if output_values.iter().any(|v| *v <= 5000) {
+            return Err(WalletError::General(
+                "Unable to split amount, probably amount too small".to_string(),
+            ));
+        }
+        assert_eq!(output_values.iter().sum::<u64>(), total);
+
+        Ok(output_values)
+    }
+
     /// This function creates funding txes by
-    /// Randomly generating some satoshi amounts and send them into
+    /// Splitting the amount as per the [AmountDistribution] (randomly by default) and send them into
     /// walletcreatefundedpsbt to create txes that create change
     fn create_funding_txes_random_amounts(
         &self,
         coinswap_amount: Amount,
         destinations: &[Address],
         fee_rate: Amount,
+        amount_distribution: AmountDistribution,
+        input_constraints: InputConstraints,
     ) -> Result<CreateFundingTxesResult, WalletError> {
         let change_addresses = self.get_next_internal_addresses(destinations.len() as u32)?;
 
-        let output_values = Wallet::generate_amount_fractions(destinations.len(), coinswap_amount)?;
+        let output_values =
+            Wallet::split_amount(destinations.len(), coinswap_amount, amount_distribution)?;
 
         self.lock_unspendable_utxos()?;
 
@@ -150,22 +284,44 @@ impl Wallet {
 
             let fee = fee_rate;
             let remaining = Amount::from_sat(output_value);
-            let selected_utxo = self.coin_select(remaining)?;
+            let selected_utxo = self.coin_select_constrained(remaining, &input_constraints)?;
             let total_input_amount = selected_utxo.iter().fold(Amount::ZERO, |acc, (unspet, _)| {
                 acc.checked_add(unspet.amount)
                     .expect("Amount sum overflowed")
             });
+            // Not enough coins left meeting the constraints.
+            if total_input_amount < remaining + fee {
+                return Err(WalletError::InsufficientFund {
+                    available: total_input_amount.to_sat(),
+                    required: (remaining + fee).to_sat(),
+                });
+            }
             let change_amount = total_input_amount.checked_sub(remaining + fee);
             let mut tx_outs = vec![TxOut {
                 value: Amount::from_sat(output_value),
                 script_pubkey: address.script_pubkey(),
             }];
 
+            // No change output when sweeping. Dust change goes to the miners.
+            let mut payment_pos = 0;
+            let mut change_to_fee = Amount::ZERO;
             if let Some(change) = change_amount {
-                tx_outs.push(TxOut {
-                    value: change,
-                    script_pubkey: change_address.script_pubkey(),
-                });
+                let rounded = self.round_change(change);
+                if self.is_dust(rounded, &change_address.script_pubkey()) {
+                    change_to_fee = change;
+                } else {
+                    change_to_fee = change - rounded;
+                    let change_pos = insert_change(
+                        &mut tx_outs,
+                        TxOut {
+                            value: rounded,
+                            script_pubkey: change_address.script_pubkey(),
+                        },
+                    );
+                    if change_pos == 0 {
+                        payment_pos = 1;
+                    }
+                }
             }
             let tx_inputs = selected_utxo
                 .iter()
@@ -178,7 +334,7 @@ impl Wallet {
                 .collect::<Vec<_>>();
 
             // Set the Anti-Fee-Snipping locktime
-            let current_height = self.rpc.get_block_count()?;
+            let current_height = self.chain.block_count()?;
 
             let lock_time = LockTime::from_height(current_height as u32)?;
 
@@ -216,11 +372,9 @@ impl Wallet {
                     .collect::<Vec<OutPoint>>(),
             )?;
 
-            let payment_pos = 0;
-
             funding_txes.push(funding_tx);
             payment_output_positions.push(payment_pos);
-            total_miner_fee += fee_rate.to_sat();
+            total_miner_fee += (fee_rate + change_to_fee).to_sat();
         }
 
         Ok(CreateFundingTxesResult {
@@ -248,7 +402,7 @@ impl Wallet {
         let first_tx_input = utxos.next().unwrap();
 
         // Set the Anti-Fee-Snipping locktime
-        let current_height = self.rpc.get_block_count()?;
+        let current_height = self.chain.block_count()?;
         let lock_time = LockTime::from_height(current_height as u32)?;
 
         for _ in 0..destinations.len() - 2 {
@@ -360,10 +514,22 @@ impl Wallet {
                 script_pubkey: address.script_pubkey(),
             });
         }
-        tx_outs.push(TxOut {
-            value: Amount::from_sat(change_amount),
-            script_pubkey: change_address.script_pubkey(),
-        });
+        let change = Amount::from_sat(change_amount);
+        let mut rounded = self.round_change(change);
+        let mut payment_pos = 0;
+        if self.is_dust(rounded, &change_address.script_pubkey()) {
+            // Dust change goes to the miners.
+            rounded = Amount::ZERO;
+        } else if insert_change(
+            &mut tx_outs,
+            TxOut {
+                value: rounded,
+                script_pubkey: change_address.script_pubkey(),
+            },
+        ) == 0
+        {
+            payment_pos = 1;
+        }
         let mut funding_tx = Transaction {
             input: tx_inputs,
             output: tx_outs,
@@ -373,10 +539,10 @@ impl Wallet {
         let mut info = iter::once(self.get_utxo((first_txid, first_vout))?.unwrap());
         self.sign_transaction(&mut funding_tx, &mut info)?;
 
-        total_miner_fee += fee_rate.to_sat();
+        total_miner_fee += (fee_rate + change - rounded).to_sat();
 
         funding_txes.push(funding_tx);
-        payment_output_positions.push(1);
+        payment_output_positions.push(payment_pos);
 
         Ok(CreateFundingTxesResult {
             funding_txes,
@@ -420,11 +586,17 @@ impl Wallet {
             script_pubkey: destinations[0].script_pubkey(),
         }];
 
-        if let Some(change) = change_amount {
-            tx_outs.push(TxOut {
-                value: change,
-                script_pubkey: change_address.script_pubkey(),
-            });
+        if let Some(change) = change_amount
+            .map(|change| self.round_change(change))
+            .filter(|change| !self.is_dust(*change, &change_address.script_pubkey()))
+        {
+            insert_change(
+                &mut tx_outs,
+                TxOut {
+                    value: change,
+                    script_pubkey: change_address.script_pubkey(),
+                },
+            );
         }
 
         let tx_inputs = selected_utxo
@@ -438,7 +610,7 @@ impl Wallet {
             .collect::<Vec<_>>();
 
         // Set the Anti-Fee-Snipping locktime
-        let current_height = self.rpc.get_block_count()?;
+        let current_height = self.chain.block_count()?;
         let lock_time = LockTime::from_height(current_height as u32)?;
 
         let mut funding_tx = Transaction {
@@ -485,6 +657,7 @@ impl Wallet {
         let mut seed_coin_utxo = self.list_descriptor_utxo_spend_info(Some(&all_utxos))?;
         let mut swap_coin_utxo = self.list_swap_coin_utxo_spend_info(Some(&all_utxos))?;
         seed_coin_utxo.append(&mut swap_coin_utxo);
+        seed_coin_utxo.retain(|(utxo, _)| !self.is_frozen(utxo));
 
         let mut list_unspent_result = seed_coin_utxo;
         if list_unspent_result.len() < destinations.len() {
@@ -546,3 +719,69 @@ impl Wallet {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_amount() {
+        let total = Amount::from_sat(500_000);
+
+        let equal = Wallet::split_amount(3, total, AmountDistribution::Equal).unwrap();
+        assert_eq!(equal.iter().sum::<u64>(), total.to_sat());
+        assert_eq!(equal.iter().filter(|v| **v == 166_666).count(), 2);
+
+        let randomized = Wallet::split_amount(
+            3,
+            total,
+            AmountDistribution::Randomized { tolerance_pct: 10 },
+        )
+        .unwrap();
+        assert_eq!(randomized.iter().sum::<u64>(), total.to_sat());
+        assert!(randomized.iter().all(|v| *v >= 133_333 && *v <= 200_000));
+
+        let power_of_two = Wallet::split_amount(3, total, AmountDistribution::PowerOfTwo).unwrap();
+        assert_eq!(power_of_two.iter().sum::<u64>(), total.to_sat());
+        assert_eq!(power_of_two.iter().filter(|v| **v == 131_072).count(), 2);
+
+        assert!(Wallet::split_amount(
+            3,
+            total,
+            AmountDistribution::Randomized { tolerance_pct: 100 }
+        )
+        .is_err());
+        assert!(
+            Wallet::split_amount(3, Amount::from_sat(9000), AmountDistribution::Equal).is_err()
+        );
+    }
+
+    #[test]
+    fn test_input_constraints() {
+        let now = 1_700_000_000;
+        assert!(InputConstraints::default().allows(0, true, None, now));
+
+        let confirmed = InputConstraints {
+            min_confirmations: 2,
+            ..Default::default()
+        };
+        assert!(!confirmed.allows(1, false, Some(now), now));
+        assert!(confirmed.allows(2, false, Some(now), now));
+
+        let no_change = InputConstraints {
+            forbid_unconfirmed_change: true,
+            ..Default::default()
+        };
+        assert!(!no_change.allows(0, true, None, now));
+        assert!(no_change.allows(0, false, None, now));
+        assert!(no_change.allows(1, true, Some(now), now));
+
+        let aged = InputConstraints {
+            min_age_secs: 3600,
+            ..Default::default()
+        };
+        assert!(!aged.allows(0, false, None, now));
+        assert!(!aged.allows(3, false, Some(now - 600), now));
+        assert!(aged.allows(3, false, Some(now - 3600), now));
+    }
+}
@@ -0,0 +1,338 @@
+//! Wallet transaction history.
+//!
+//! Lists the transactions touching the wallet, confirmed or not, each tagged with what it did for the wallet:
+//! a deposit, a swap funding, a contract, and so on. Transactions are tagged by the coins they spend and pay
+//! to. The swapcoins and fidelity bonds known to the wallet are matched first. Swaps already cleared from the
+//! wallet are still recognized by their 2-of-2 multisig and P2WSH scripts.
+
+use std::{
+    cmp::Reverse,
+    collections::HashSet,
+    fmt::{self, Display},
+};
+
+use bitcoin::{Amount, OutPoint, Script, ScriptBuf, SignedAmount, Transaction, Txid};
+use bitcoind::bitcoincore_rpc::{json::GetTransactionResultDetailCategory, RpcApi};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    protocol::contract::check_reedemscript_is_multisig, utill::redeemscript_to_scriptpubkey,
+};
+
+use super::{storage::WalletStore, MemoTarget, SwapCoin, Wallet, WalletError};
+
+/// Most transactions fetched from the node for the history.
+const HISTORY_LIMIT: usize = 100_000;
+
+/// What a transaction did for the wallet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TxCategory {
+    /// Coins received on a wallet address.
+    Deposit,
+    /// Coins sent out of the wallet, outside of a swap.
+    Send,
+    /// Funding of a swap, paying our coins to a 2-of-2 multisig.
+    SwapFunding,
+    /// Funding of a swap by the other party, paying to a 2-of-2 multisig we hold a key of.
+    SwapIncoming,
+    /// A contract tx, or a spend of a contract output by hashlock or timelock.
+    Contract,
+    /// Creation or redemption of a fidelity bond.
+    Fidelity,
+    /// Swept swap coins, spent from a 2-of-2 multisig into the wallet.
+    Sweep,
+}
+
+impl Display for TxCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Deposit => write!(f, "deposit"),
+            Self::Send => write!(f, "send"),
+            Self::SwapFunding => write!(f, "swap-funding"),
+            Self::SwapIncoming => write!(f, "swap-incoming"),
+            Self::Contract => write!(f, "contract"),
+            Self::Fidelity => write!(f, "fidelity"),
+            Self::Sweep => write!(f, "sweep"),
+        }
+    }
+}
+
+/// A transaction touching the wallet.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WalletTransaction {
+    /// Txid of the transaction.
+    pub txid: Txid,
+    /// What the transaction did for the wallet.
+    pub category: TxCategory,
+    /// Change of the wallet balance, fee included. Watch-only swap coins are counted.
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub amount: SignedAmount,
+    /// Fee paid by the wallet. None if the wallet didn't fund the transaction.
+    pub fee: Option<Amount>,
+    /// Confirmations of the transaction. 0 if unconfirmed, negative if conflicted.
+    pub confirmations: i32,
+    /// Height of the confirming block.
+    pub block_height: Option<u32>,
+    /// Unix time the transaction was confirmed at, or first seen if unconfirmed.
+    pub time: u64,
+    /// Memo of the operator on the transaction.
+    #[serde(default)]
+    pub memo: Option<String>,
+}
+
+impl WalletTransaction {
+    /// Whether the transaction is confirmed.
+    pub fn is_confirmed(&self) -> bool {
+        self.confirmations > 0
+    }
+}
+
+/// The coins of the swaps and fidelity bonds known to the wallet.
+#[derive(Debug, Default)]
+struct KnownCoins {
+    fidelity_scripts: HashSet<ScriptBuf>,
+    fidelity_outpoints: HashSet<OutPoint>,
+    incoming_multisigs: HashSet<ScriptBuf>,
+    outgoing_multisigs: HashSet<ScriptBuf>,
+    contract_txids: HashSet<Txid>,
+    contract_prevouts: HashSet<OutPoint>,
+}
+
+impl KnownCoins {
+    fn from_store(store: &WalletStore) -> Result<Self, WalletError> {
+        let mut known = KnownCoins {
+            contract_prevouts: store.prevout_to_contract_map.keys().cloned().collect(),
+            ..Default::default()
+        };
+        for (bond, script_pubkey, _) in store.fidelity_bond.values() {
+            known.fidelity_scripts.insert(script_pubkey.clone());
+            known.fidelity_outpoints.insert(bond.outpoint);
+        }
+        for coin in store.incoming_swapcoins.values() {
+            known
+                .incoming_multisigs
+                .insert(redeemscript_to_scriptpubkey(
+                    &coin.get_multisig_redeemscript(),
+                )?);
+            known.contract_txids.insert(coin.contract_tx.compute_txid());
+        }
+        for coin in store.outgoing_swapcoins.values() {
+            known
+                .outgoing_multisigs
+                .insert(redeemscript_to_scriptpubkey(
+                    &coin.get_multisig_redeemscript(),
+                )?);
+            known.contract_txids.insert(coin.contract_tx.compute_txid());
+        }
+        Ok(known)
+    }
+
+    /// Tags a transaction, from the coins it spends and pays to.
+    ///
+    /// `net` is the change of the wallet balance, and `receives_p2wsh` whether the wallet watches any P2WSH output
+    /// of it, which only swap multisigs and contracts are.
+    fn categorize(&self, tx: &Transaction, net: SignedAmount, receives_p2wsh: bool) -> TxCategory {
+        let spends = |outpoints: &HashSet<OutPoint>| {
+            tx.input
+                .iter()
+                .any(|input| outpoints.contains(&input.previous_output))
+        };
+        let pays = |scripts: &HashSet<ScriptBuf>| {
+            tx.output
+                .iter()
+                .any(|output| scripts.contains(&output.script_pubkey))
+        };
+        let spends_multisig = tx.input.iter().any(|input| {
+            input.witness.last().is_some_and(|script| {
+                check_reedemscript_is_multisig(Script::from_bytes(script)).is_ok()
+            })
+        });
+        let pays_p2wsh = tx
+            .output
+            .iter()
+            .any(|output| output.script_pubkey.is_p2wsh());
+
+        if pays(&self.fidelity_scripts) || spends(&self.fidelity_outpoints) {
+            TxCategory::Fidelity
+        } else if self.contract_txids.contains(&tx.compute_txid())
+            || spends(&self.contract_prevouts)
+            || tx
+                .input
+                .iter()
+                .any(|input| self.contract_txids.contains(&input.previous_output.txid))
+            || (spends_multisig && pays_p2wsh)
+        {
+            TxCategory::Contract
+        } else if spends_multisig {
+            TxCategory::Sweep
+        } else if pays(&self.outgoing_multisigs) || (net.is_negative() && pays_p2wsh) {
+            TxCategory::SwapFunding
+        } else if pays(&self.incoming_multisigs) || receives_p2wsh {
+            TxCategory::SwapIncoming
+        } else if net.is_negative() {
+            TxCategory::Send
+        } else {
+            TxCategory::Deposit
+        }
+    }
+}
+
+impl Wallet {
+    /// Lists the transactions touching the wallet, confirmed or not, newest first.
+    pub fn list_transactions(&self) -> Result<Vec<WalletTransaction>, WalletError> {
+        let known = KnownCoins::from_store(&self.store)?;
+
+        let mut seen = HashSet::new();
+        let txids = self
+            .rpc
+            .list_transactions(None, Some(HISTORY_LIMIT), Some(0), Some(true))?
+            .into_iter()
+            .map(|entry| entry.info.txid)
+            .filter(|txid| seen.insert(*txid))
+            .collect::<Vec<_>>();
+
+        let mut history = txids
+            .iter()
+            .map(|txid| {
+                let result = self.rpc.get_transaction(txid, Some(true))?;
+                let tx = result.transaction()?;
+                // The fee is reported negative, and not counted in the amount.
+                let net = result.amount + result.fee.unwrap_or(SignedAmount::ZERO);
+                let fee = result.fee.map(|fee| fee.unsigned_abs());
+                let receives_p2wsh = result.details.iter().any(|detail| {
+                    detail.category == GetTransactionResultDetailCategory::Receive
+                        && detail.address.as_ref().is_some_and(|address| {
+                            address.assume_checked_ref().script_pubkey().is_p2wsh()
+                        })
+                });
+                Ok(WalletTransaction {
+                    txid: *txid,
+                    category: known.categorize(&tx, net, receives_p2wsh),
+                    amount: net,
+                    fee,
+                    confirmations: result.info.confirmations,
+                    block_height: result.info.blockheight,
+                    time: result.info.blocktime.unwrap_or(result.info.time),
+                    memo: self.memo(&MemoTarget::Tx(*txid)).map(str::to_string),
+                })
+            })
+            .collect::<Result<Vec<_>, WalletError>>()?;
+
+        // Unconfirmed first, then by confirmation height.
+        history.sort_by_key(|tx| {
+            (
+                Reverse(tx.block_height.unwrap_or(u32::MAX)),
+                Reverse(tx.time),
+            )
+        });
+        Ok(history)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{
+        absolute::LockTime, hashes::Hash, transaction::Version, PublicKey, Sequence, TxIn, TxOut,
+        Witness,
+    };
+
+    use crate::protocol::contract::create_multisig_redeemscript;
+
+    use super::*;
+
+    fn tx(inputs: Vec<TxIn>, outputs: Vec<ScriptBuf>) -> Transaction {
+        Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: inputs,
+            output: outputs
+                .into_iter()
+                .map(|script_pubkey| TxOut {
+                    value: Amount::from_sat(10_000),
+                    script_pubkey,
+                })
+                .collect(),
+        }
+    }
+
+    fn input(vout: u32, witness: Witness) -> TxIn {
+        TxIn {
+            previous_output: OutPoint::new(Txid::all_zeros(), vout),
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::MAX,
+            witness,
+        }
+    }
+
+    #[test]
+    fn test_categorize() {
+        let pubkey = PublicKey::from_slice(&[0x02; 33]).unwrap();
+        let multisig = create_multisig_redeemscript(&pubkey, &pubkey);
+        let multisig_spk = redeemscript_to_scriptpubkey(&multisig).unwrap();
+        let wallet_spk = ScriptBuf::new_p2wpkh(&pubkey.wpubkey_hash().unwrap());
+        let multisig_witness = Witness::from_slice(&[vec![], vec![], multisig.to_bytes()]);
+        let fee = SignedAmount::from_sat(-500);
+        let received = SignedAmount::from_sat(10_000);
+
+        let mut known = KnownCoins::default();
+        assert_eq!(
+            known.categorize(
+                &tx(vec![input(0, Witness::new())], vec![wallet_spk.clone()]),
+                received,
+                false
+            ),
+            TxCategory::Deposit
+        );
+        assert_eq!(
+            known.categorize(
+                &tx(vec![input(0, Witness::new())], vec![wallet_spk.clone()]),
+                fee,
+                false
+            ),
+            TxCategory::Send
+        );
+
+        // Swaps cleared from the wallet are recognized by their scripts.
+        let funding = tx(vec![input(0, Witness::new())], vec![multisig_spk.clone()]);
+        assert_eq!(
+            known.categorize(&funding, fee, true),
+            TxCategory::SwapFunding
+        );
+        assert_eq!(
+            known.categorize(&funding, received, true),
+            TxCategory::SwapIncoming
+        );
+        let sweep = tx(
+            vec![input(0, multisig_witness.clone())],
+            vec![wallet_spk.clone()],
+        );
+        assert_eq!(known.categorize(&sweep, received, false), TxCategory::Sweep);
+        let contract = tx(vec![input(0, multisig_witness)], vec![multisig_spk.clone()]);
+        assert_eq!(known.categorize(&contract, fee, true), TxCategory::Contract);
+
+        // Known coins take precedence.
+        known
+            .fidelity_outpoints
+            .insert(OutPoint::new(Txid::all_zeros(), 1));
+        let redeem = tx(vec![input(1, Witness::new())], vec![wallet_spk.clone()]);
+        assert_eq!(
+            known.categorize(&redeem, received, false),
+            TxCategory::Fidelity
+        );
+        known.incoming_multisigs.insert(multisig_spk);
+        assert_eq!(
+            known.categorize(&funding, SignedAmount::ZERO, false),
+            TxCategory::SwapIncoming
+        );
+        known.contract_txids.insert(Txid::all_zeros());
+        assert_eq!(
+            known.categorize(
+                &tx(vec![input(2, Witness::new())], vec![wallet_spk]),
+                received,
+                false
+            ),
+            TxCategory::Contract
+        );
+    }
+}
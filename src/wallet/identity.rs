@@ -0,0 +1,405 @@
+//! Long-term identity keys of the makers.
+//!
+//! A maker's fidelity bonds come and go, and so do its addresses. Its identity key stays, so that the directory
+//! and the takers can recognize the maker across them. The identity key is bound to the current bond by a
+//! signature of the bond key. It signs the directory registrations, the sessions of the Maker's hellos and the
+//! receipts of the swaps.
+//!
+//! The identity is derived from the seed at [KeyClass::Identity], at an index bumped by a rotation. The previous
+//! identity signs over to the new one, and the directory moves the reputation earned by the previous identity,
+//! its uptime record, to the new one.
+
+#[cfg(feature = "maker")]
+use bitcoin::secp256k1::{ecdh::SharedSecret, Keypair};
+use bitcoin::{
+    hashes::{sha256d, Hash},
+    secp256k1::{self, ecdsa::Signature, Message, Secp256k1, SecretKey},
+    PublicKey,
+};
+
+#[cfg(feature = "maker")]
+use crate::protocol::messages::FidelityProof;
+#[cfg(any(feature = "maker", feature = "directory"))]
+use crate::protocol::messages::IdentityProof;
+use crate::protocol::messages::{IdentityBinding, IdentitySignature, SignedReceipt, SwapReceipt};
+
+use super::{
+    fidelity::{signed_message_hash, FidelityBond},
+    WalletError,
+};
+#[cfg(feature = "maker")]
+use super::{FidelityError, KeyClass, Wallet};
+
+/// Hash signed by the bond key, binding the identity to the bond.
+fn binding_hash(bond: &FidelityBond, identity: &PublicKey) -> sha256d::Hash {
+    signed_message_hash(&format!(
+        "maker-identity-bind|{}|{}",
+        bond.outpoint, identity
+    ))
+}
+
+/// Hash of a directory registration, signed by the identity key.
+#[cfg(any(feature = "maker", feature = "directory"))]
+fn advertisement_hash(identity: &PublicKey, addr: &str, expires_at: u64) -> sha256d::Hash {
+    signed_message_hash(&format!(
+        "maker-identity-advertise|{}|{}|{}",
+        identity, addr, expires_at
+    ))
+}
+
+/// Hash signed by the previous identity key, handing its reputation over to the new identity.
+#[cfg(any(feature = "maker", feature = "directory"))]
+fn rotation_hash(previous: &PublicKey, identity: &PublicKey) -> sha256d::Hash {
+    signed_message_hash(&format!("maker-identity-rotate|{}|{}", previous, identity))
+}
+
+/// Hash of a session id, signed by the identity key in the hello.
+fn session_hash(session_id: &[u8; 32]) -> sha256d::Hash {
+    let mut message = b"maker-identity-session|".to_vec();
+    message.extend(session_id);
+    sha256d::Hash::hash(&message)
+}
+
+/// Hash of a swap receipt, signed by the maker and the taker.
+fn receipt_hash(receipt: &SwapReceipt) -> sha256d::Hash {
+    let txids = |txids: &[bitcoin::Txid]| {
+        txids
+            .iter()
+            .map(|txid| txid.to_string())
+            .collect::<Vec<_>>()
+            .join(",")
+    };
+    signed_message_hash(&format!(
+        "maker-swap-receipt|{}|{}|{}|{}|{}|{}|{}|{}",
+        receipt.swap_id,
+        receipt.incoming_sats,
+        receipt.outgoing_sats,
+        receipt.miner_fees_sats,
+        txids(&receipt.incoming_funding_txids),
+        txids(&receipt.outgoing_funding_txids),
+        receipt.completed_at,
+        receipt.taker_key
+    ))
+}
+
+fn sign(hash: sha256d::Hash, key: &SecretKey) -> Result<Signature, WalletError> {
+    Ok(Secp256k1::new().sign_ecdsa(&Message::from_digest_slice(hash.as_byte_array())?, key))
+}
+
+fn verify(hash: sha256d::Hash, sig: &Signature, key: &PublicKey) -> Result<(), WalletError> {
+    Secp256k1::verification_only().verify_ecdsa(
+        &Message::from_digest_slice(hash.as_byte_array())?,
+        sig,
+        &key.inner,
+    )?;
+    Ok(())
+}
+
+/// Verifies the identity of a maker is bound to its bond.
+pub(crate) fn verify_identity_binding(
+    binding: &IdentityBinding,
+    bond: &FidelityBond,
+) -> Result<(), WalletError> {
+    verify(
+        binding_hash(bond, &binding.identity),
+        &binding.sig,
+        &bond.pubkey,
+    )
+}
+
+/// Verifies the identity in a directory registration: bound to the bond, signing the address and expiry, and
+/// signed over by the previous identity after a rotation.
+#[cfg(feature = "directory")]
+pub(crate) fn verify_identity_proof(
+    proof: &IdentityProof,
+    bond: &FidelityBond,
+    addr: &str,
+    expires_at: u64,
+) -> Result<(), WalletError> {
+    let identity = &proof.binding.identity;
+    verify_identity_binding(&proof.binding, bond)?;
+    verify(
+        advertisement_hash(identity, addr, expires_at),
+        &proof.advertisement_sig,
+        identity,
+    )?;
+    if let Some(rotation) = &proof.rotation {
+        verify(
+            rotation_hash(&rotation.identity, identity),
+            &rotation.sig,
+            &rotation.identity,
+        )?;
+    }
+    Ok(())
+}
+
+/// Verifies the signature of a session by the identity key of the Maker.
+pub(crate) fn verify_session_signature(
+    signature: &IdentitySignature,
+    session_id: &[u8; 32],
+) -> Result<(), WalletError> {
+    verify(
+        session_hash(session_id),
+        &signature.sig,
+        &signature.identity,
+    )
+}
+
+/// Verifies the signature of the maker on a receipt, and the countersignature of the taker if present, which
+/// must be by the taker key of the receipt.
+pub(crate) fn verify_receipt(receipt: &SignedReceipt) -> Result<(), WalletError> {
+    let hash = receipt_hash(&receipt.receipt);
+    verify(hash, &receipt.maker_sig.sig, &receipt.maker_sig.identity)?;
+    if let Some(taker_sig) = &receipt.taker_sig {
+        if taker_sig.identity != receipt.receipt.taker_key {
+            return Err(secp256k1::Error::IncorrectSignature.into());
+        }
+        verify(hash, &taker_sig.sig, &taker_sig.identity)?;
+    }
+    Ok(())
+}
+
+impl SignedReceipt {
+    /// Countersigns the receipt with the secret of its taker key, the session key of the taker's hello.
+    pub(crate) fn countersign(&mut self, key: &SecretKey) -> Result<(), WalletError> {
+        self.taker_sig = Some(IdentitySignature {
+            identity: PublicKey::new(key.public_key(&Secp256k1::new())),
+            sig: sign(receipt_hash(&self.receipt), key)?,
+        });
+        Ok(())
+    }
+}
+
+#[cfg(feature = "maker")]
+impl Wallet {
+    fn identity_keypair_at(&self, index: u32) -> Result<Keypair, WalletError> {
+        let secp = Secp256k1::new();
+        Ok(self
+            .store
+            .master_key
+            .derive_priv(&secp, &KeyClass::Identity.path(index)?)?
+            .to_keypair(&secp))
+    }
+
+    /// The current identity key of the maker.
+    pub(crate) fn identity(&self) -> Result<PublicKey, WalletError> {
+        Ok(PublicKey::new(
+            self.identity_keypair_at(self.store.identity_index)?
+                .public_key(),
+        ))
+    }
+
+    /// Moves to a new identity key. The next registrations hand the reputation of the previous identity over
+    /// to the new one. Returns the new identity.
+    pub(crate) fn rotate_identity(&mut self) -> Result<PublicKey, WalletError> {
+        self.store.identity_index += 1;
+        self.save_to_disk()?;
+        self.identity()
+    }
+
+    /// Binds the current identity to the bond, signing with the bond key.
+    pub(crate) fn identity_binding(
+        &self,
+        bond: &FidelityBond,
+    ) -> Result<IdentityBinding, WalletError> {
+        let index = self
+            .store
+            .fidelity_bond
+            .iter()
+            .find(|(_, (b, _, _))| b.outpoint == bond.outpoint)
+            .map(|(i, _)| *i)
+            .ok_or(FidelityError::BondDoesNotExist)?;
+        let identity = self.identity()?;
+        let sig = sign(
+            binding_hash(bond, &identity),
+            &self.get_fidelity_keypair(index)?.secret_key(),
+        )?;
+        Ok(IdentityBinding { identity, sig })
+    }
+
+    /// The identity of a directory registration at `addr`, expiring at `expires_at`.
+    pub(crate) fn identity_proof(
+        &self,
+        fidelity: &FidelityProof,
+        addr: &str,
+        expires_at: u64,
+    ) -> Result<IdentityProof, WalletError> {
+        let index = self.store.identity_index;
+        let binding = self.identity_binding(&fidelity.bond)?;
+        let advertisement_sig = sign(
+            advertisement_hash(&binding.identity, addr, expires_at),
+            &self.identity_keypair_at(index)?.secret_key(),
+        )?;
+        let rotation = if index == 0 {
+            None
+        } else {
+            let previous = self.identity_keypair_at(index - 1)?;
+            Some(IdentitySignature {
+                identity: PublicKey::new(previous.public_key()),
+                sig: sign(
+                    rotation_hash(&PublicKey::new(previous.public_key()), &binding.identity),
+                    &previous.secret_key(),
+                )?,
+            })
+        };
+        Ok(IdentityProof {
+            binding,
+            advertisement_sig,
+            rotation,
+        })
+    }
+
+    /// Issues a receipt of a swap, signed with the current identity key.
+    pub(crate) fn sign_receipt(&self, receipt: SwapReceipt) -> Result<SignedReceipt, WalletError> {
+        let keypair = self.identity_keypair_at(self.store.identity_index)?;
+        let maker_sig = IdentitySignature {
+            identity: PublicKey::new(keypair.public_key()),
+            sig: sign(receipt_hash(&receipt), &keypair.secret_key())?,
+        };
+        Ok(SignedReceipt {
+            receipt,
+            maker_sig,
+            taker_sig: None,
+        })
+    }
+
+    /// Signs a session with the current identity key.
+    pub(crate) fn sign_session(
+        &self,
+        session_id: &[u8; 32],
+    ) -> Result<IdentitySignature, WalletError> {
+        let keypair = self.identity_keypair_at(self.store.identity_index)?;
+        Ok(IdentitySignature {
+            identity: PublicKey::new(keypair.public_key()),
+            sig: sign(session_hash(session_id), &keypair.secret_key())?,
+        })
+    }
+
+    /// The secret shared by the session key of the Taker and the current identity key, authenticating the
+    /// envelopes of the session.
+    pub(crate) fn session_secret(
+        &self,
+        session_key: &PublicKey,
+    ) -> Result<SharedSecret, WalletError> {
+        let keypair = self.identity_keypair_at(self.store.identity_index)?;
+        Ok(SharedSecret::new(&session_key.inner, &keypair.secret_key()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "directory")]
+    use std::str::FromStr;
+
+    #[cfg(feature = "directory")]
+    use bitcoin::{absolute::LockTime, Amount, OutPoint};
+
+    use super::*;
+
+    fn keypair(byte: u8) -> (SecretKey, PublicKey) {
+        let secret = SecretKey::from_slice(&[byte; 32]).unwrap();
+        let public = PublicKey::new(secret.public_key(&Secp256k1::new()));
+        (secret, public)
+    }
+
+    #[cfg(feature = "directory")]
+    #[test]
+    fn test_identity_proof() {
+        let (bond_key, bond_pubkey) = keypair(1);
+        let (previous_key, previous) = keypair(2);
+        let (identity_key, identity) = keypair(3);
+        let bond = FidelityBond {
+            outpoint: OutPoint::from_str(
+                "c3a04e4bdf3c8684c5cf5c8b2f3c43009670bc194ac6c856b3ec9d3a7a6e2602:0",
+            )
+            .unwrap(),
+            amount: Amount::from_sat(100_000),
+            lock_time: LockTime::from_height(1000).unwrap(),
+            pubkey: bond_pubkey,
+            conf_height: 100,
+            cert_expiry: 1,
+        };
+        let addr = "maker.onion:6102";
+
+        let proof = IdentityProof {
+            binding: IdentityBinding {
+                identity,
+                sig: sign(binding_hash(&bond, &identity), &bond_key).unwrap(),
+            },
+            advertisement_sig: sign(advertisement_hash(&identity, addr, 500), &identity_key)
+                .unwrap(),
+            rotation: Some(IdentitySignature {
+                identity: previous,
+                sig: sign(rotation_hash(&previous, &identity), &previous_key).unwrap(),
+            }),
+        };
+        assert!(verify_identity_proof(&proof, &bond, addr, 500).is_ok());
+
+        // The advertisement can't be moved to another address or expiry.
+        assert!(verify_identity_proof(&proof, &bond, "other.onion:6102", 500).is_err());
+        assert!(verify_identity_proof(&proof, &bond, addr, 600).is_err());
+
+        // An identity not bound by the bond key.
+        let mut unbound = proof.clone();
+        unbound.binding.sig = sign(binding_hash(&bond, &identity), &identity_key).unwrap();
+        assert!(verify_identity_proof(&unbound, &bond, addr, 500).is_err());
+
+        // A rotation not signed by the previous identity can't take over its reputation.
+        let mut stolen = proof.clone();
+        stolen.rotation = Some(IdentitySignature {
+            identity: previous,
+            sig: sign(rotation_hash(&previous, &identity), &identity_key).unwrap(),
+        });
+        assert!(verify_identity_proof(&stolen, &bond, addr, 500).is_err());
+
+        // Session signatures.
+        let signature = IdentitySignature {
+            identity,
+            sig: sign(session_hash(&[7; 32]), &identity_key).unwrap(),
+        };
+        assert!(verify_session_signature(&signature, &[7; 32]).is_ok());
+        assert!(verify_session_signature(&signature, &[8; 32]).is_err());
+    }
+
+    #[test]
+    fn test_receipt_signatures() {
+        let (maker_key, maker) = keypair(4);
+        let (taker_key, taker) = keypair(5);
+        let receipt = SwapReceipt {
+            swap_id: "swap".to_string(),
+            incoming_sats: 100_000,
+            outgoing_sats: 98_000,
+            miner_fees_sats: 1_000,
+            incoming_funding_txids: Vec::new(),
+            outgoing_funding_txids: Vec::new(),
+            completed_at: 500,
+            taker_key: taker,
+        };
+        let mut signed = SignedReceipt {
+            maker_sig: IdentitySignature {
+                identity: maker,
+                sig: sign(receipt_hash(&receipt), &maker_key).unwrap(),
+            },
+            receipt,
+            taker_sig: None,
+        };
+        assert!(verify_receipt(&signed).is_ok());
+        signed.countersign(&taker_key).unwrap();
+        assert!(verify_receipt(&signed).is_ok());
+
+        // Neither side can change what was charged after signing.
+        let mut altered = signed.clone();
+        altered.receipt.outgoing_sats = 90_000;
+        assert!(verify_receipt(&altered).is_err());
+
+        // The maker can't forge the countersignature with a key it knows, like the taker's handed over
+        // multisig keys.
+        let (handed_over_key, _) = keypair(6);
+        let mut forged = signed.clone();
+        forged.countersign(&handed_over_key).unwrap();
+        assert!(verify_receipt(&forged).is_err());
+        let mut forged = signed.clone();
+        forged.countersign(&maker_key).unwrap();
+        assert!(verify_receipt(&forged).is_err());
+    }
+}
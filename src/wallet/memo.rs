@@ -0,0 +1,96 @@
+//! Memos on transactions and swaps.
+//!
+//! Operators attach free-text memos to the transactions and swaps of their wallet, e.g. to record why a manual
+//! sweep or consolidation happened. Memos are saved in the wallet file, and shown in the transaction history
+//! and the swap reports.
+
+use std::{fmt, str::FromStr};
+
+use bitcoin::Txid;
+use serde::{Deserialize, Serialize};
+
+use super::{Wallet, WalletError};
+
+/// Longest memo accepted, in bytes.
+pub const MAX_MEMO_LEN: usize = 1024;
+
+/// What a memo is attached to.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum MemoTarget {
+    /// A wallet transaction.
+    Tx(Txid),
+    /// A swap, by its id.
+    Swap(String),
+}
+
+/// Parses a txid, or else a swap id.
+impl FromStr for MemoTarget {
+    type Err = WalletError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(txid) = Txid::from_str(s) {
+            return Ok(Self::Tx(txid));
+        }
+        if s.is_empty() || !s.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(WalletError::General(format!(
+                "Invalid memo target {:?}, expected a txid or a swap id",
+                s
+            )));
+        }
+        Ok(Self::Swap(s.to_lowercase()))
+    }
+}
+
+impl fmt::Display for MemoTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tx(txid) => write!(f, "tx {}", txid),
+            Self::Swap(id) => write!(f, "swap {}", id),
+        }
+    }
+}
+
+impl Wallet {
+    /// Attaches a memo to a transaction or a swap, replacing its previous memo. An empty memo removes it.
+    pub fn set_memo(&mut self, target: MemoTarget, memo: &str) -> Result<(), WalletError> {
+        let memo = memo.trim();
+        if memo.len() > MAX_MEMO_LEN {
+            return Err(WalletError::General(format!(
+                "Memo of {} bytes is longer than {} bytes",
+                memo.len(),
+                MAX_MEMO_LEN
+            )));
+        }
+        if memo.is_empty() {
+            self.store.memos.remove(&target);
+        } else {
+            self.store.memos.insert(target, memo.to_string());
+        }
+        self.save_to_disk()
+    }
+
+    /// The memo attached to a transaction or a swap.
+    pub fn memo(&self, target: &MemoTarget) -> Option<&str> {
+        self.store.memos.get(target).map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memo_target() {
+        let txid = "c3a04e4bdf3c8684c5cf5c8b2f3c43009670bc194ac6c856b3ec9d3a7a6e2602";
+        assert_eq!(
+            txid.parse::<MemoTarget>().unwrap(),
+            MemoTarget::Tx(Txid::from_str(txid).unwrap())
+        );
+        assert_eq!(
+            "A1B2c3d4e5f60718".parse::<MemoTarget>().unwrap(),
+            MemoTarget::Swap("a1b2c3d4e5f60718".to_string())
+        );
+        assert!("".parse::<MemoTarget>().is_err());
+        assert!("not a swap".parse::<MemoTarget>().is_err());
+    }
+}
@@ -0,0 +1,161 @@
+//! Wallet file format versioning.
+//!
+//! The wallet store carries the version of its file format. Loading a file of an older version runs the
+//! migration of every later version in order, and upgrades the file in place. A copy of the old file is kept next
+//! to it until the upgraded file is saved. A migration has two optional steps:
+//! - on the raw CBOR file, before it's read into the store, for the changes the store can't read as is:
+//!   renamed or restructured fields, encryption.
+//! - on the loaded wallet, for the changes needing the wallet: deriving new keys, warning the user.
+//!
+//! New fields with a default need no migration, `#[serde(default)]` is enough. Files of a newer version than
+//! [WALLET_FILE_VERSION] are refused, rather than losing their new data on the next save.
+
+use std::{collections::BTreeMap, convert::TryFrom, fs};
+
+use serde_cbor::Value;
+
+use super::{Wallet, WalletError};
+
+/// Version of the wallet file format written by this release.
+pub(crate) const WALLET_FILE_VERSION: u32 = 2;
+
+/// Top-level fields of a raw wallet file.
+pub(super) type RawWalletFile = BTreeMap<Value, Value>;
+
+/// Upgrade of the raw wallet file.
+type FileUpgrade = fn(&mut RawWalletFile) -> Result<(), WalletError>;
+
+/// Upgrade of the loaded wallet.
+type WalletUpgrade = fn(&mut Wallet) -> Result<(), WalletError>;
+
+/// Upgrade of the wallet file to a version.
+pub(super) struct Migration {
+    /// Version the file is upgraded to.
+    pub(super) version: u32,
+    /// What changes, for the logs.
+    pub(super) description: &'static str,
+    /// Upgrades the raw file, before it's read into the store.
+    pub(super) file: Option<FileUpgrade>,
+    /// Upgrades the loaded wallet.
+    pub(super) wallet: Option<WalletUpgrade>,
+}
+
+/// Migrations of every version, in order. To change the file format, append one and bump
+/// [WALLET_FILE_VERSION].
+pub(super) const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "Keys derived at fixed paths",
+        file: None,
+        wallet: Some(Wallet::report_legacy_swap_keys),
+    },
+    // Older releases can't read a file with a sealed master key.
+    Migration {
+        version: 2,
+        description: "Master key and swap coins encrypted with the wallet passphrase",
+        file: None,
+        wallet: None,
+    },
+];
+
+/// Version of a raw wallet file. 0 for files from before versioning.
+pub(super) fn file_version(file: &RawWalletFile) -> Result<u32, WalletError> {
+    match file.get(&Value::Text("version".to_string())) {
+        None => Ok(0),
+        Some(Value::Integer(version)) => u32::try_from(*version)
+            .map_err(|_| WalletError::General(format!("Invalid wallet file version {}", version))),
+        Some(_) => Err(WalletError::General(
+            "Invalid wallet file version".to_string(),
+        )),
+    }
+}
+
+/// Runs the raw file steps of the migrations past version `from`. Returns whether the file changed.
+pub(super) fn migrate_file(file: &mut RawWalletFile, from: u32) -> Result<bool, WalletError> {
+    if from > WALLET_FILE_VERSION {
+        return Err(WalletError::General(format!(
+            "Wallet file version {} is newer than the supported version {}. Upgrade the app.",
+            from, WALLET_FILE_VERSION
+        )));
+    }
+    let mut changed = false;
+    for migration in MIGRATIONS.iter().filter(|m| m.version > from) {
+        if let Some(migrate) = migration.file {
+            migrate(file)?;
+            changed = true;
+        }
+    }
+    Ok(changed)
+}
+
+impl Wallet {
+    /// Runs the wallet steps of the migrations past the version of the store, and saves the upgraded file. The
+    /// old file is copied to `<wallet file>.v<version>.bak` while upgrading, and the copy is removed once the
+    /// upgraded file is saved, as it holds the swap coin keys unencrypted.
+    pub(super) fn migrate(&mut self) -> Result<(), WalletError> {
+        let from = self.store.version;
+        if from >= WALLET_FILE_VERSION {
+            return Ok(());
+        }
+        let backup = self
+            .wallet_file_path
+            .with_extension(format!("v{}.bak", from));
+        fs::copy(&self.wallet_file_path, &backup)?;
+        log::info!(
+            "Upgrading wallet file from version {} to {}, old file copied to {}",
+            from,
+            WALLET_FILE_VERSION,
+            backup.display()
+        );
+
+        for migration in MIGRATIONS.iter().filter(|m| m.version > from) {
+            log::info!(
+                "Wallet file version {}: {}",
+                migration.version,
+                migration.description
+            );
+            if let Some(migrate) = migration.wallet {
+                migrate(self)?;
+            }
+            self.store.version = migration.version;
+        }
+        self.save_to_disk()?;
+        fs::remove_file(&backup)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_migrations() {
+        // Versions follow each other, up to the current one.
+        for (i, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.version, i as u32 + 1);
+        }
+        assert_eq!(
+            MIGRATIONS.last().map(|m| m.version),
+            Some(WALLET_FILE_VERSION)
+        );
+
+        let mut file = RawWalletFile::new();
+        assert_eq!(file_version(&file).unwrap(), 0);
+        assert!(migrate_file(&mut file, 0).is_ok());
+
+        file.insert(
+            Value::Text("version".to_string()),
+            Value::Integer(WALLET_FILE_VERSION as i128 + 1),
+        );
+        let version = file_version(&file).unwrap();
+        assert_eq!(version, WALLET_FILE_VERSION + 1);
+        assert!(migrate_file(&mut file, version).is_err());
+
+        file.insert(
+            Value::Text("version".to_string()),
+            Value::Text("1".to_string()),
+        );
+        assert!(file_version(&file).is_err());
+    }
+}
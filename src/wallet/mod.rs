@@ -1,19 +1,75 @@
 //! The Coinswap Wallet (unsecured). Used by both the Taker and Maker.
 
 mod api;
+mod backend;
+#[cfg(feature = "bdk")]
+mod bdk;
+mod change;
+mod config;
+mod confirmation;
+mod conflict;
 mod direct_send;
+mod encryption;
 mod error;
+mod fees;
 mod fidelity;
+mod freeze;
 mod funding;
+mod history;
+mod identity;
+mod memo;
+mod migration;
+mod paths;
+#[cfg(feature = "maker")]
+mod payjoin;
+mod recovery;
+mod reuse;
 mod rpc;
 mod storage;
 mod swapcoin;
+mod transfer;
 
-pub(crate) use api::{Balances, UTXOSpendInfo, Wallet};
-pub use direct_send::{Destination, SendAmount};
+#[cfg(feature = "maker")]
+pub(crate) use api::Balances;
+pub use api::{ScriptType, DEFAULT_GAP_LIMIT};
+pub(crate) use api::{UTXOSpendInfo, Wallet};
+pub use backend::BlockchainBackend;
+#[cfg(any(test, feature = "integration-test", feature = "bench"))]
+pub use backend::MockBlockchain;
+pub use change::DEFAULT_DUST_THRESHOLD;
+pub use config::WalletConfig;
+pub(crate) use confirmation::{ConfirmationTracker, ConfirmationUpdate};
+pub(crate) use conflict::ContractConflict;
+pub use direct_send::{Destination, SendAmount, StuckDeposit};
+pub use encryption::{load_passphrase_file, WALLET_PASSPHRASE_ENV};
 pub use error::WalletError;
+pub use fees::{
+    FeeEstimator, FeeSource, FeerateDivergence, MempoolSpaceEstimator, StaticFeeEstimator,
+    MAX_FEERATE_DIVERGENCE,
+};
+#[cfg(feature = "directory")]
+pub(crate) use fidelity::{calculate_fidelity_value, estimate_locktime_timestamp};
 pub(crate) use fidelity::{fidelity_redeemscript, FidelityBond, FidelityError};
+pub use funding::AmountDistribution;
+pub(crate) use funding::InputConstraints;
+pub(crate) use funding::MINER_FEE;
+pub use history::{TxCategory, WalletTransaction};
+#[cfg(feature = "directory")]
+pub(crate) use identity::verify_identity_proof;
+pub(crate) use identity::{verify_identity_binding, verify_receipt, verify_session_signature};
+pub use memo::{MemoTarget, MAX_MEMO_LEN};
+pub use paths::{
+    KeyClass, CONTRACT_BRANCH, FIDELITY_PATH, IDENTITY_PATH, SEED_ACCOUNT_PATH, SWAP_ACCOUNT_PATH,
+    SWAP_BRANCH, SWAP_KEY_GAP_LIMIT, TAPROOT_ACCOUNT_PATH, TWEAKABLE_KEY_PATH,
+};
+pub use recovery::{FundsRecovery, Recoverable, RecoverableKind, RecoveryStep};
+pub use reuse::ReusedAddress;
+#[cfg(feature = "bench")]
+pub(crate) use rpc::batch_call;
+pub(crate) use rpc::broadcasted_txids;
 pub use rpc::RPCConfig;
 pub(crate) use swapcoin::{
-    IncomingSwapCoin, OutgoingSwapCoin, SwapCoin, WalletSwapCoin, WatchOnlySwapCoin,
+    create_timelock_spend_tx, IncomingSwapCoin, OutgoingSwapCoin, SwapCoin, TIMELOCK_SPEND_VSIZE,
 };
+pub(crate) use swapcoin::{WalletSwapCoin, WatchOnlySwapCoin};
+pub use transfer::transfer_delays;
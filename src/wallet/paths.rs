@@ -0,0 +1,358 @@
+//! BIP32 derivation paths of the wallet keys.
+//!
+//! Every key of the wallet is derived from its seed at a fixed path, so that recovery tooling holding only the
+//! seed knows where to look for each class of keys:
+//!
+//! | Keys                                   | Path                                  |
+//! |----------------------------------------|---------------------------------------|
+//! | Receive addresses (P2WPKH, P2TR)       | `m/84'/1'/0'/0/i`, `m/86'/1'/0'/0/i`  |
+//! | Change addresses (P2WPKH, P2TR)        | `m/84'/1'/0'/1/i`, `m/86'/1'/0'/1/i`  |
+//! | Fidelity bonds                         | `m/84'/0'/0'/2/i`                     |
+//! | Maker's identity keys                  | `m/84'/0'/0'/3/i`                     |
+//! | 2-of-2 multisig keys of swaps          | `m/84'/1'/1'/0/i'`                    |
+//! | Hashlock and timelock keys of contracts| `m/84'/1'/1'/1/i'`                    |
+//! | Maker's tweakable key                  | `m/0'`                                |
+//!
+//! The addresses follow BIP84 for P2WPKH and BIP86 for P2TR, so other wallets holding the seed find them too.
+//!
+//! The maker's swap keys are its tweakable key, tweaked by a nonce kept with the swap coin. The swap and contract
+//! keys are handed over to the counterparty at the end of a swap, so their indexes are hardened: a leaked key
+//! doesn't expose its siblings, even along with the account xpub. Each of the two branches has its own counter,
+//! stored in the wallet file. A wallet restored from its seed finds its counters again in its transactions, see
+//! [scan_swap_key_indexes].
+//!
+//! Swap coins made before the paths were fixed, in wallet files of version 0, hold random keys found only in the
+//! wallet file, see [Wallet::report_legacy_swap_keys].
+
+use std::{collections::HashSet, str::FromStr};
+
+use bitcoin::{
+    bip32::{ChildNumber, DerivationPath, Xpriv},
+    secp256k1::{Secp256k1, SecretKey},
+    PublicKey, Script, Transaction,
+};
+
+use crate::protocol::contract::{
+    check_reedemscript_is_multisig, read_hashlock_pubkey_from_contract,
+    read_hashvalue_from_contract, read_pubkeys_from_multisig_redeemscript,
+    read_timelock_pubkey_from_contract,
+};
+
+use super::{ScriptType, Wallet, WalletError};
+
+/// Account of the P2WPKH receive and change addresses, on branches 0 and 1.
+pub const SEED_ACCOUNT_PATH: &str = "m/84'/1'/0'";
+/// BIP86 account of the P2TR receive and change addresses, on branches 0 and 1.
+pub const TAPROOT_ACCOUNT_PATH: &str = "m/86'/1'/0'";
+/// Branch of the fidelity bond keys.
+pub const FIDELITY_PATH: &str = "m/84'/0'/0'/2";
+/// Branch of the maker's identity keys, one per rotation.
+pub const IDENTITY_PATH: &str = "m/84'/0'/0'/3";
+/// Account of the keys of swaps, on the [SWAP_BRANCH] and [CONTRACT_BRANCH].
+pub const SWAP_ACCOUNT_PATH: &str = "m/84'/1'/1'";
+/// Branch of the 2-of-2 multisig keys of swaps, under [SWAP_ACCOUNT_PATH].
+pub const SWAP_BRANCH: u32 = 0;
+/// Branch of the hashlock and timelock keys of contracts, under [SWAP_ACCOUNT_PATH].
+pub const CONTRACT_BRANCH: u32 = 1;
+/// Path of the maker's tweakable key.
+pub const TWEAKABLE_KEY_PATH: &str = "m/0'";
+/// Swap and contract keys looked up past the last one seen on chain, when restoring a wallet from its seed.
+pub const SWAP_KEY_GAP_LIMIT: u32 = 100;
+
+/// A class of wallet keys, derived at its own path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyClass {
+    /// Keys of the receive addresses of a script type.
+    External(ScriptType),
+    /// Keys of the change addresses of a script type.
+    Internal(ScriptType),
+    /// Keys of the fidelity bonds.
+    Fidelity,
+    /// Identity keys of the maker.
+    Identity,
+    /// 2-of-2 multisig keys of swaps.
+    Swap,
+    /// Hashlock and timelock keys of contracts.
+    Contract,
+}
+
+impl KeyClass {
+    /// Full derivation path of the key at `index`.
+    pub fn path(&self, index: u32) -> Result<DerivationPath, WalletError> {
+        let (base, branch) = match self {
+            Self::External(script_type) => (script_type.account_path(), Some(0)),
+            Self::Internal(script_type) => (script_type.account_path(), Some(1)),
+            Self::Fidelity => (FIDELITY_PATH, None),
+            Self::Identity => (IDENTITY_PATH, None),
+            Self::Swap => (SWAP_ACCOUNT_PATH, Some(SWAP_BRANCH)),
+            Self::Contract => (SWAP_ACCOUNT_PATH, Some(CONTRACT_BRANCH)),
+        };
+        let mut path = DerivationPath::from_str(base)?;
+        if let Some(branch) = branch {
+            path = path.child(ChildNumber::from_normal_idx(branch)?);
+        }
+        let index = match self {
+            // Handed over to the counterparty.
+            Self::Swap | Self::Contract => ChildNumber::from_hardened_idx(index)?,
+            _ => ChildNumber::from_normal_idx(index)?,
+        };
+        Ok(path.child(index))
+    }
+}
+
+/// Path of the contract key at `index`, under [SWAP_ACCOUNT_PATH].
+fn contract_key_subpath(index: u32) -> Result<DerivationPath, WalletError> {
+    Ok(DerivationPath::from(vec![
+        ChildNumber::from_normal_idx(CONTRACT_BRANCH)?,
+        ChildNumber::from_hardened_idx(index)?,
+    ]))
+}
+
+/// Next indexes of the [KeyClass::Swap] and [KeyClass::Contract] keys of a wallet restored from its seed, from
+/// the transactions of the wallet.
+///
+/// The keys show up in the witness scripts of the multisig and contract spends to the wallet: the sweeps of
+/// incoming swap coins, and the recoveries of contracts. Those are found by the node's rescan of the seed
+/// descriptors, without going through every block. Indexes are looked up [SWAP_KEY_GAP_LIMIT] past the last one
+/// found, which also covers the keys of the outgoing swap coins, spent by the counterparty. The keys of swaps
+/// still running aren't on chain yet: the counters resume a gap limit past the last key found, so that they're
+/// never handed out again, and are still looked up by [Wallet::contract_key_subpath].
+pub(crate) fn scan_swap_key_indexes(
+    master_key: &Xpriv,
+    txs: &[Transaction],
+) -> Result<(u32, u32), WalletError> {
+    let mut revealed = HashSet::new();
+    // A P2WSH spend ends with its witness script.
+    for input in txs
+        .iter()
+        .flat_map(|tx| tx.input.iter())
+        .filter(|input| input.witness.len() > 1)
+    {
+        let script = match input.witness.last() {
+            Some(script) => Script::from_bytes(script),
+            None => continue,
+        };
+        if check_reedemscript_is_multisig(script).is_ok() {
+            let (pubkey1, pubkey2) = read_pubkeys_from_multisig_redeemscript(script)?;
+            revealed.extend([pubkey1, pubkey2]);
+        } else if read_hashvalue_from_contract(script).is_ok() {
+            revealed.extend(read_hashlock_pubkey_from_contract(script).ok());
+            revealed.extend(read_timelock_pubkey_from_contract(script).ok());
+        }
+    }
+
+    let secp = Secp256k1::new();
+    let next_index = |class: KeyClass| -> Result<u32, WalletError> {
+        let mut next = 0;
+        let mut index = 0;
+        while index < next + SWAP_KEY_GAP_LIMIT {
+            let privkey = master_key
+                .derive_priv(&secp, &class.path(index)?)?
+                .private_key;
+            let pubkey = PublicKey {
+                compressed: true,
+                inner: privkey.public_key(&secp),
+            };
+            if revealed.contains(&pubkey) {
+                next = index + 1;
+            }
+            index += 1;
+        }
+        Ok(next + SWAP_KEY_GAP_LIMIT)
+    };
+    Ok((next_index(KeyClass::Swap)?, next_index(KeyClass::Contract)?))
+}
+
+impl Wallet {
+    /// Derives the next `count` keys of the [KeyClass::Swap] or [KeyClass::Contract] class.
+    pub(crate) fn next_swap_keypairs(
+        &mut self,
+        class: KeyClass,
+        count: u32,
+    ) -> Result<Vec<(PublicKey, SecretKey)>, WalletError> {
+        let secp = Secp256k1::new();
+        (0..count)
+            .map(|_| {
+                let counter = match class {
+                    KeyClass::Swap => &mut self.store.swap_key_index,
+                    KeyClass::Contract => &mut self.store.contract_key_index,
+                    _ => {
+                        return Err(WalletError::General(format!(
+                            "{:?} keys are not swap keys",
+                            class
+                        )))
+                    }
+                };
+                let index = *counter;
+                *counter += 1;
+                let privkey = self
+                    .store
+                    .master_key
+                    .derive_priv(&secp, &class.path(index)?)?
+                    .private_key;
+                let pubkey = PublicKey {
+                    compressed: true,
+                    inner: privkey.public_key(&secp),
+                };
+                Ok((pubkey, privkey))
+            })
+            .collect()
+    }
+
+    /// Extended private key of [SWAP_ACCOUNT_PATH], the account of the swap and contract keys.
+    pub(crate) fn swap_account_key(&self) -> Result<Xpriv, WalletError> {
+        let secp = Secp256k1::new();
+        Ok(self
+            .store
+            .master_key
+            .derive_priv(&secp, &DerivationPath::from_str(SWAP_ACCOUNT_PATH)?)?)
+    }
+
+    /// Path of the contract key of `pubkey` under [SWAP_ACCOUNT_PATH], to derive it again from
+    /// [Wallet::swap_account_key]. None for keys not derived from the seed, like the ones of legacy swap coins.
+    pub(crate) fn contract_key_subpath(
+        &self,
+        pubkey: &PublicKey,
+    ) -> Result<Option<DerivationPath>, WalletError> {
+        let secp = Secp256k1::new();
+        let account_key = self.swap_account_key()?;
+        // Recent swaps are the likeliest to be asked for.
+        for index in (0..self.store.contract_key_index).rev() {
+            let path = contract_key_subpath(index)?;
+            let privkey = account_key.derive_priv(&secp, &path)?.private_key;
+            if privkey.public_key(&secp) == pubkey.inner {
+                return Ok(Some(path));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Migration of wallet files from before the fixed derivation paths.
+    ///
+    /// Receive, change and fidelity keys were already derived at their paths. The keys of the swap coins in
+    /// the file were random: they stay spendable from the file, but can't be found again from the seed. Swaps
+    /// from now on derive their keys from the seed.
+    pub(crate) fn report_legacy_swap_keys(&mut self) -> Result<(), WalletError> {
+        let legacy_swapcoins =
+            self.store.incoming_swapcoins.len() + self.store.outgoing_swapcoins.len();
+        if legacy_swapcoins != 0 {
+            log::warn!(
+                "{} swap coins have keys not derived from the seed. Keep the wallet file backed up until they're spent.",
+                legacy_swapcoins
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{
+        contract::{create_contract_redeemscript, create_multisig_redeemscript},
+        Hash160,
+    };
+    use bitcoin::{
+        absolute::LockTime, hashes::Hash, transaction::Version, Amount, Network, OutPoint,
+        ScriptBuf, TxIn, TxOut, Txid, Witness,
+    };
+
+    fn pubkey(master_key: &Xpriv, class: KeyClass, index: u32) -> PublicKey {
+        let secp = Secp256k1::new();
+        PublicKey {
+            compressed: true,
+            inner: master_key
+                .derive_priv(&secp, &class.path(index).unwrap())
+                .unwrap()
+                .private_key
+                .public_key(&secp),
+        }
+    }
+
+    /// A P2WSH spend of the `vout` output of a dummy tx, revealing `witness_script`.
+    fn spend(vout: u32, witness_script: ScriptBuf) -> Transaction {
+        Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(Txid::all_zeros(), vout),
+                witness: Witness::from_slice(&[vec![0; 72], witness_script.into_bytes()]),
+                ..Default::default()
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(10_000),
+                script_pubkey: ScriptBuf::new(),
+            }],
+        }
+    }
+
+    #[test]
+    fn test_key_paths() {
+        let paths = [
+            (KeyClass::External(ScriptType::P2wpkh), "m/84'/1'/0'/0/5"),
+            (KeyClass::Internal(ScriptType::P2wpkh), "m/84'/1'/0'/1/5"),
+            (KeyClass::External(ScriptType::P2tr), "m/86'/1'/0'/0/5"),
+            (KeyClass::Internal(ScriptType::P2tr), "m/86'/1'/0'/1/5"),
+            (KeyClass::Fidelity, "m/84'/0'/0'/2/5"),
+            (KeyClass::Identity, "m/84'/0'/0'/3/5"),
+            (KeyClass::Swap, "m/84'/1'/1'/0/5'"),
+            (KeyClass::Contract, "m/84'/1'/1'/1/5'"),
+        ];
+        for (class, path) in paths {
+            assert_eq!(
+                class.path(5).unwrap(),
+                DerivationPath::from_str(path).unwrap()
+            );
+        }
+        assert_eq!(
+            DerivationPath::from_str(SWAP_ACCOUNT_PATH)
+                .unwrap()
+                .extend(contract_key_subpath(5).unwrap()),
+            KeyClass::Contract.path(5).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_restored_swap_key_indexes() {
+        let master_key = Xpriv::new_master(Network::Regtest, &[7; 32]).unwrap();
+        let other_master_key = Xpriv::new_master(Network::Regtest, &[8; 32]).unwrap();
+        let counterparty = pubkey(&other_master_key, KeyClass::Fidelity, 0);
+
+        // A fresh seed resumes a gap limit in, past any swap still running.
+        assert_eq!(
+            scan_swap_key_indexes(&master_key, &[]).unwrap(),
+            (SWAP_KEY_GAP_LIMIT, SWAP_KEY_GAP_LIMIT)
+        );
+
+        // Swap keys 3 and 90 are spent from, 90 being within a gap limit of 3. Key 250 is past the gap.
+        let mut txs = [3, 90, 250]
+            .iter()
+            .enumerate()
+            .map(|(vout, index)| {
+                let multisig = create_multisig_redeemscript(
+                    &counterparty,
+                    &pubkey(&master_key, KeyClass::Swap, *index),
+                );
+                spend(vout as u32, multisig)
+            })
+            .collect::<Vec<_>>();
+        // Contract key 7 is spent from, by timelock.
+        let contract = create_contract_redeemscript(
+            &counterparty,
+            &pubkey(&master_key, KeyClass::Contract, 7),
+            &Hash160::hash(&[1; 32]),
+            &20,
+        );
+        txs.push(spend(10, contract));
+
+        assert_eq!(
+            scan_swap_key_indexes(&master_key, &txs).unwrap(),
+            (91 + SWAP_KEY_GAP_LIMIT, 8 + SWAP_KEY_GAP_LIMIT)
+        );
+        // Nothing of the other seed is spent from.
+        assert_eq!(
+            scan_swap_key_indexes(&other_master_key, &txs).unwrap(),
+            (SWAP_KEY_GAP_LIMIT, SWAP_KEY_GAP_LIMIT)
+        );
+    }
+}
@@ -0,0 +1,206 @@
+//! BIP78 payjoin receiving.
+//!
+//! When the wallet is funded by a payjoin, the receiver adds one of its own inputs to the sender's
+//! transaction. This breaks the common-input-ownership heuristic, so the deposit doesn't reveal which
+//! coins belong to the wallet. The HTTP endpoint lives with the Maker, this module only builds the
+//! payjoin proposal out of the sender's original PSBT.
+
+use std::collections::HashSet;
+
+use bitcoin::{
+    secp256k1::rand::{rngs::OsRng, RngCore},
+    Amount, OutPoint, Psbt, Transaction, Witness,
+};
+use bitcoind::bitcoincore_rpc::{json::ListUnspentResultEntry, RpcApi};
+
+use super::{error::WalletError, ScriptType, Wallet};
+
+/// A payjoin proposal, along with the sender's original transaction.
+#[derive(Debug)]
+pub(crate) struct PayjoinProposal {
+    /// Our input, locked until [Wallet::release_payjoin_input].
+    pub(crate) input: OutPoint,
+    /// The proposal PSBT. Our input is finalized, the sender's inputs need to be signed again.
+    pub(crate) psbt: Psbt,
+    /// The original transaction, to be broadcasted if the sender never broadcasts the payjoin.
+    pub(crate) original_tx: Transaction,
+}
+
+impl Wallet {
+    /// Creates a payjoin proposal from the sender's original PSBT, by contributing one of the wallet's
+    /// descriptor utxos of the same script type as the sender's inputs.
+    ///
+    /// The original PSBT must be fully signed, broadcastable and pay to one of our receive addresses.
+    /// The fee of our extra input is paid out of our output, at the feerate of the original transaction.
+    pub(crate) fn create_payjoin_proposal(
+        &mut self,
+        original: Psbt,
+    ) -> Result<PayjoinProposal, WalletError> {
+        if original.inputs.is_empty()
+            || original.inputs.iter().any(|input| {
+                input.final_script_witness.is_none() && input.final_script_sig.is_none()
+            })
+        {
+            return Err(WalletError::General(
+                "Original PSBT is not finalized".to_string(),
+            ));
+        }
+        let original_tx = original.clone().extract_tx_unchecked_fee_rate();
+
+        // The original must be broadcastable, as it is our fallback.
+        let accepted = self.rpc.test_mempool_accept(&[&original_tx])?;
+        if !accepted.iter().all(|result| result.allowed) {
+            return Err(WalletError::General(
+                "Original transaction is not broadcastable".to_string(),
+            ));
+        }
+
+        let receiver_vout = original_tx
+            .output
+            .iter()
+            .position(|txout| {
+                self.is_receive_script(&txout.script_pubkey)
+                    .unwrap_or(false)
+            })
+            .ok_or_else(|| {
+                WalletError::General("Original PSBT doesn't pay to the wallet".to_string())
+            })?;
+
+        let mut input_value = Amount::ZERO;
+        let mut sender_types = HashSet::new();
+        for input in &original.inputs {
+            match &input.witness_utxo {
+                Some(txout) => {
+                    input_value += txout.value;
+                    sender_types.insert(ScriptType::of_script(&txout.script_pubkey));
+                }
+                None => {
+                    return Err(WalletError::General(
+                        "Original PSBT is missing input utxos".to_string(),
+                    ))
+                }
+            }
+        }
+        let output_value = original_tx
+            .output
+            .iter()
+            .map(|txout| txout.value)
+            .sum::<Amount>();
+        let fee = input_value.checked_sub(output_value).ok_or_else(|| {
+            WalletError::General("Original PSBT spends more than its inputs".to_string())
+        })?;
+        let fee_rate = fee.to_sat() as f64 / original_tx.vsize() as f64;
+
+        let utxos = self.get_all_locked_utxo()?;
+        let seed_coins = self.list_descriptor_utxo_spend_info(Some(&utxos))?;
+
+        // A sender spending our own coins is probing the wallet.
+        if original_tx.input.iter().any(|txin| {
+            utxos.iter().any(|utxo| {
+                txin.previous_output
+                    == OutPoint {
+                        txid: utxo.txid,
+                        vout: utxo.vout,
+                    }
+            })
+        }) {
+            return Err(WalletError::General(
+                "Original PSBT spends wallet utxos".to_string(),
+            ));
+        }
+
+        // As BIP78 recommends, our input is of the script type of the sender's inputs, unless they mix types,
+        // so it doesn't stand out. Its fee is paid at the size of its type.
+        let candidates = seed_coins
+            .into_iter()
+            .filter_map(|(utxo, spend_info)| {
+                let script_type = ScriptType::of_script(&utxo.script_pub_key)?;
+                let input_fee =
+                    Amount::from_sat((fee_rate * script_type.input_vsize() as f64).ceil() as u64);
+                let usable = (sender_types.len() > 1 || sender_types.contains(&Some(script_type)))
+                    && utxo.amount > input_fee
+                    && !self.is_frozen(&utxo)
+                    && !self
+                        .payjoin_inputs
+                        .contains(&OutPoint::new(utxo.txid, utxo.vout));
+                usable.then_some((utxo, spend_info, input_fee))
+            })
+            .collect::<Vec<_>>();
+        if candidates.is_empty() {
+            return Err(WalletError::General(
+                "No wallet utxo to contribute".to_string(),
+            ));
+        }
+        let (utxo, spend_info, input_fee) =
+            &candidates[OsRng.next_u32() as usize % candidates.len()];
+
+        let mut payjoin_tx = original_tx.clone();
+        for txin in payjoin_tx.input.iter_mut() {
+            txin.witness = Witness::new();
+            txin.script_sig = Default::default();
+        }
+        // Place our input at a random position, with the same sequence as the sender's inputs.
+        let our_index = OsRng.next_u32() as usize % (payjoin_tx.input.len() + 1);
+        let mut our_input = payjoin_tx.input[0].clone();
+        our_input.previous_output = OutPoint {
+            txid: utxo.txid,
+            vout: utxo.vout,
+        };
+        payjoin_tx.input.insert(our_index, our_input);
+        payjoin_tx.output[receiver_vout].value += utxo.amount - *input_fee;
+
+        let mut psbt = Psbt::from_unsigned_tx(payjoin_tx.clone())?;
+        self.sign_inputs(
+            &mut payjoin_tx,
+            std::iter::once((our_index, spend_info.clone())),
+        )?;
+        psbt.inputs[our_index].witness_utxo = Some(bitcoin::TxOut {
+            value: utxo.amount,
+            script_pubkey: utxo.script_pub_key.clone(),
+        });
+        psbt.inputs[our_index].final_script_witness =
+            Some(payjoin_tx.input[our_index].witness.clone());
+
+        // Don't offer the same utxo to another sender, nor spend it, meanwhile.
+        let our_outpoint = psbt.unsigned_tx.input[our_index].previous_output;
+        self.rpc.lock_unspent(&[our_outpoint])?;
+        self.payjoin_inputs.insert(our_outpoint);
+
+        log::info!(
+            "Created payjoin proposal for {}, contributing {} sats",
+            original_tx.compute_txid(),
+            utxo.amount.to_sat()
+        );
+
+        Ok(PayjoinProposal {
+            input: our_outpoint,
+            psbt,
+            original_tx,
+        })
+    }
+
+    /// Locks the payjoin inputs among `utxos` again, after the locks of the node were released.
+    pub(crate) fn lock_payjoin_inputs(
+        &self,
+        utxos: &[ListUnspentResultEntry],
+    ) -> Result<(), WalletError> {
+        let payjoin_inputs = utxos
+            .iter()
+            .map(|utxo| OutPoint::new(utxo.txid, utxo.vout))
+            .filter(|outpoint| self.payjoin_inputs.contains(outpoint))
+            .collect::<Vec<_>>();
+        if !payjoin_inputs.is_empty() {
+            self.rpc.lock_unspent(&payjoin_inputs)?;
+        }
+        Ok(())
+    }
+
+    /// Releases a payjoin input, once the payjoin or its fallback is broadcasted. Still unspent if the fallback
+    /// was, it's spendable again.
+    pub(crate) fn release_payjoin_input(&mut self, outpoint: OutPoint) {
+        if self.payjoin_inputs.remove(&outpoint) {
+            // Errors if the payjoin spent it.
+            let _ = self.rpc.unlock_unspent(&[outpoint]);
+        }
+    }
+}
@@ -0,0 +1,303 @@
+//! Funds recovery, straight from a wallet file.
+//!
+//! [FundsRecovery] finds every coin of a wallet which is only recoverable by broadcasting pre-signed or
+//! timelocked transactions: contracts of unfinished swaps, contracts broadcast by a counterparty, and
+//! fidelity bonds. It only needs the wallet file and a Bitcoin Core node, not the maker or taker config,
+//! their data directories or any network connection, so funds can be recovered even when `makerd` or
+//! `taker` won't start. This is what the `coinswap-recover` app runs.
+//!
+//! Each coin is recovered in steps, reported by [FundsRecovery::scan] as [Recoverable]s:
+//! 1. The contract tx of an unfinished swap is broadcast.
+//! 2. Once confirmed, the contract output is claimed to the wallet: by timelock after the contract's
+//!    relative locktime for coins we sent, by hashlock right away for coins we received with a known preimage.
+//!
+//! Fidelity bonds are claimed once their absolute locktime has passed.
+//!
+//! A wallet restored from its seed phrase only has the seed coins: contracts and fidelity bonds are only
+//! kept in the wallet file.
+
+use std::path::Path;
+
+use bip39::Mnemonic;
+use bitcoin::{absolute::LockTime, Amount, OutPoint, Transaction, Txid};
+use bitcoind::bitcoincore_rpc::RpcApi;
+
+use super::{
+    error::WalletError,
+    rpc::RPCConfig,
+    swapcoin::{SwapCoin, WalletSwapCoin},
+    Wallet,
+};
+
+/// Kind of coins to recover.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecoverableKind {
+    /// Contract of coins we sent, claimed back by timelock.
+    TimelockContract,
+    /// Contract of coins we received, claimed by hashlock.
+    HashlockContract,
+    /// Fidelity bond, claimed after its locktime.
+    FidelityBond,
+}
+
+/// Next step of the recovery of a coin.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecoveryStep {
+    /// The contract tx is not on chain yet, it can be broadcast.
+    BroadcastContract(Transaction),
+    /// The contract tx is in the mempool, waiting for a confirmation.
+    AwaitConfirmation,
+    /// The claim tx is not valid before this height or time.
+    AwaitLocktime(LockTime),
+    /// The claim tx can be broadcast.
+    BroadcastClaim(Transaction),
+}
+
+/// A coin to recover, and the next step of its recovery.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recoverable {
+    /// Kind of the coin.
+    pub kind: RecoverableKind,
+    /// The contract or fidelity bond output. Contract outputs don't exist before the contract tx is broadcast.
+    pub outpoint: OutPoint,
+    /// Value of the coin.
+    pub amount: Amount,
+    /// What's left to do.
+    pub step: RecoveryStep,
+}
+
+impl Recoverable {
+    /// The transaction to broadcast now, if any.
+    pub fn tx(&self) -> Option<&Transaction> {
+        match &self.step {
+            RecoveryStep::BroadcastContract(tx) | RecoveryStep::BroadcastClaim(tx) => Some(tx),
+            RecoveryStep::AwaitConfirmation | RecoveryStep::AwaitLocktime(_) => None,
+        }
+    }
+}
+
+/// Recovers the coins of a wallet locked in contracts and fidelity bonds. See the [module docs](self).
+pub struct FundsRecovery {
+    wallet: Wallet,
+}
+
+impl FundsRecovery {
+    /// Opens the wallet file at `path` and syncs it.
+    pub fn open(path: &Path, rpc_config: &RPCConfig) -> Result<Self, WalletError> {
+        let mut rpc_config = rpc_config.clone();
+        rpc_config.wallet_name = wallet_file_name(path)?;
+        let mut wallet = Wallet::load(path, &rpc_config)?;
+        wallet.sync()?;
+        Ok(Self { wallet })
+    }
+
+    /// Restores a wallet from its seed phrase into a new wallet file at `path`, and syncs it from the
+    /// `birthday` height, or from genesis if unknown.
+    pub fn from_seed(
+        path: &Path,
+        rpc_config: &RPCConfig,
+        seed_phrase: &str,
+        birthday: Option<u64>,
+    ) -> Result<Self, WalletError> {
+        let mnemonic = Mnemonic::parse(seed_phrase)?;
+        let mut rpc_config = rpc_config.clone();
+        rpc_config.wallet_name = wallet_file_name(path)?;
+        let mut wallet = Wallet::restore(path, &rpc_config, &mnemonic, birthday, false)?;
+        wallet.sync()?;
+        Ok(Self { wallet })
+    }
+
+    /// Current block height of the node.
+    pub fn block_height(&self) -> Result<u64, WalletError> {
+        self.wallet.chain.block_count()
+    }
+
+    /// Spendable balance of the wallet, once recovered coins are claimed.
+    pub fn spendable_balance(&self) -> Result<Amount, WalletError> {
+        Ok(self.wallet.get_balances(None)?.spendable)
+    }
+
+    /// Lists the coins left to recover, with their next step.
+    ///
+    /// Contracts already claimed, by us or by the counterparty, and spent fidelity bonds are left out.
+    pub fn scan(&self) -> Result<Vec<Recoverable>, WalletError> {
+        let mut recoverables = Vec::new();
+        // Every claim pays to its own address.
+        let mut claim_addresses = self
+            .wallet
+            .get_next_internal_addresses(
+                (self.wallet.store.outgoing_swapcoins.len()
+                    + self.wallet.store.incoming_swapcoins.len()) as u32,
+            )?
+            .into_iter();
+        let mut next_claim_address = move || {
+            claim_addresses
+                .next()
+                .ok_or_else(|| WalletError::General("Out of claim addresses".to_string()))
+        };
+
+        for outgoing in self.wallet.store.outgoing_swapcoins.values() {
+            let contract_tx = match outgoing.get_fully_signed_contract_tx() {
+                Ok(contract_tx) => contract_tx,
+                // Swaps aborted before the contracts were signed have no coins in them.
+                Err(e) => {
+                    log::debug!("Skipping unsigned outgoing contract: {:?}", e);
+                    continue;
+                }
+            };
+            // Swaps are finished once the preimage is revealed.
+            let unfinished = outgoing.hash_preimage.is_none();
+            let claim = |height| -> Result<RecoveryStep, WalletError> {
+                let ready_at = height + outgoing.get_timelock()? as u64;
+                // The claim can be mined in the next block.
+                if self.block_height()? + 1 >= ready_at {
+                    Ok(RecoveryStep::BroadcastClaim(
+                        outgoing.create_timelock_spend(&next_claim_address()?)?,
+                    ))
+                } else {
+                    Ok(RecoveryStep::AwaitLocktime(LockTime::from_height(
+                        ready_at as u32,
+                    )?))
+                }
+            };
+            if let Some(step) = self.contract_step(&contract_tx, unfinished, claim)? {
+                recoverables.push(Recoverable {
+                    kind: RecoverableKind::TimelockContract,
+                    outpoint: contract_outpoint(&outgoing.contract_tx),
+                    amount: outgoing.contract_tx.output[0].value,
+                    step,
+                });
+            }
+        }
+
+        for incoming in self.wallet.store.incoming_swapcoins.values() {
+            let preimage = match incoming.hash_preimage {
+                Some(preimage) => preimage,
+                // The sender takes these back by timelock, they never were ours.
+                None => continue,
+            };
+            let contract_tx = match incoming.get_fully_signed_contract_tx() {
+                Ok(contract_tx) => contract_tx,
+                Err(e) => {
+                    log::debug!("Skipping unsigned incoming contract: {:?}", e);
+                    continue;
+                }
+            };
+            // Swaps are finished once the sender handed over its multisig key.
+            let unfinished = incoming.other_privkey.is_none();
+            let claim = |_| -> Result<RecoveryStep, WalletError> {
+                Ok(RecoveryStep::BroadcastClaim(
+                    incoming.create_hashlock_spend(&next_claim_address()?, &preimage)?,
+                ))
+            };
+            if let Some(step) = self.contract_step(&contract_tx, unfinished, claim)? {
+                recoverables.push(Recoverable {
+                    kind: RecoverableKind::HashlockContract,
+                    outpoint: contract_outpoint(&incoming.contract_tx),
+                    amount: incoming.contract_tx.output[0].value,
+                    step,
+                });
+            }
+        }
+
+        let tip = self.block_height()?;
+        let median_time = self.wallet.rpc.get_blockchain_info()?.median_time;
+        for (index, (bond, _, is_spent)) in self.wallet.get_fidelity_bonds() {
+            if *is_spent {
+                continue;
+            }
+            // Heights or unix times, compared to the median time past like consensus does.
+            let locked_until = bond.lock_time.to_consensus_u32() as u64;
+            let locked = if bond.lock_time.is_block_height() {
+                locked_until > tip
+            } else {
+                locked_until > median_time
+            };
+            let step = if locked {
+                RecoveryStep::AwaitLocktime(bond.lock_time)
+            } else {
+                RecoveryStep::BroadcastClaim(self.wallet.create_fidelity_redeem_tx(*index)?)
+            };
+            recoverables.push(Recoverable {
+                kind: RecoverableKind::FidelityBond,
+                outpoint: bond.outpoint,
+                amount: bond.amount,
+                step,
+            });
+        }
+
+        Ok(recoverables)
+    }
+
+    /// Broadcasts the transaction of the next step of a recovery. Returns its txid.
+    pub fn broadcast(&mut self, recoverable: &Recoverable) -> Result<Txid, WalletError> {
+        let tx = recoverable.tx().ok_or_else(|| {
+            WalletError::General("Nothing to broadcast before the next step".to_string())
+        })?;
+        let txid = self.wallet.send_tx(tx)?;
+        log::info!(
+            "Broadcasted {:?} tx {} for {}",
+            recoverable.kind,
+            txid,
+            recoverable.outpoint
+        );
+
+        if recoverable.kind == RecoverableKind::FidelityBond {
+            if let Some((_, _, is_spent)) = self
+                .wallet
+                .store
+                .fidelity_bond
+                .values_mut()
+                .find(|(bond, _, _)| bond.outpoint == recoverable.outpoint)
+            {
+                *is_spent = true;
+            }
+        }
+        self.wallet.sync()?;
+        self.wallet.save_to_disk()?;
+        Ok(txid)
+    }
+
+    /// Next step of a contract: broadcasting it if unfinished and not on chain, then claiming it once
+    /// confirmed, through `claim` given its confirmation height. None if there's nothing to recover.
+    fn contract_step(
+        &self,
+        contract_tx: &Transaction,
+        unfinished: bool,
+        claim: impl FnOnce(u64) -> Result<RecoveryStep, WalletError>,
+    ) -> Result<Option<RecoveryStep>, WalletError> {
+        let txid = contract_tx.compute_txid();
+        let confirmations = match self.wallet.chain.tx_confirmations(&txid)? {
+            Some(confirmations) => confirmations,
+            // Finished swaps don't need their contracts on chain.
+            None if !unfinished => return Ok(None),
+            None => return Ok(Some(RecoveryStep::BroadcastContract(contract_tx.clone()))),
+        };
+        if !self
+            .wallet
+            .chain
+            .is_unspent(&contract_outpoint(contract_tx), true)?
+        {
+            log::info!("Contract {} already claimed", txid);
+            return Ok(None);
+        }
+        if confirmations == 0 {
+            return Ok(Some(RecoveryStep::AwaitConfirmation));
+        }
+        let height = self.wallet.chain.block_count()? + 1 - confirmations as u64;
+        claim(height).map(Some)
+    }
+}
+
+/// The contract output, the only one of contract txs.
+fn contract_outpoint(contract_tx: &Transaction) -> OutPoint {
+    OutPoint::new(contract_tx.compute_txid(), 0)
+}
+
+/// The Core wallet of a wallet file is named after the file.
+fn wallet_file_name(path: &Path) -> Result<String, WalletError> {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .map(str::to_string)
+        .ok_or_else(|| WalletError::General(format!("Invalid wallet file path {:?}", path)))
+}
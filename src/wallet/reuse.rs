@@ -0,0 +1,71 @@
+//! Address reuse detection.
+//!
+//! Receive addresses are handed out once, in order of their derivation index, and addresses which already
+//! have coins on-chain are skipped. A payer can still send to an old address again, which links all the
+//! payments to it together and undoes the privacy the swaps paid for. Such reuse can't be prevented, only
+//! detected: the wallet warns about every newly reused address after a sync.
+
+use bitcoin::{Address, Amount, Txid};
+use bitcoind::bitcoincore_rpc::RpcApi;
+use serde::{Deserialize, Serialize};
+
+use super::{Wallet, WalletError};
+
+/// A wallet address which received coins in more than one transaction.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReusedAddress {
+    /// The reused address.
+    pub address: String,
+    /// Transactions paying to the address.
+    pub txids: Vec<Txid>,
+    /// Total received on the address, in sats.
+    pub received_sats: u64,
+}
+
+impl Wallet {
+    /// Lists the wallet addresses which received coins in more than one transaction.
+    pub fn list_reused(&self) -> Result<Vec<ReusedAddress>, WalletError> {
+        let mut reused = self
+            .rpc
+            .list_received_by_address(None, Some(0), Some(false), Some(true))?
+            .into_iter()
+            .filter(|received| received.txids.len() > 1)
+            .map(|received| ReusedAddress {
+                address: received.address.assume_checked().to_string(),
+                txids: received.txids,
+                received_sats: received.amount.to_sat(),
+            })
+            .collect::<Vec<_>>();
+        reused.sort_by(|a, b| a.address.cmp(&b.address));
+        Ok(reused)
+    }
+
+    /// Whether the address ever received coins, confirmed or not.
+    ///
+    /// Addresses beyond the range imported into the node have no history the node could see.
+    pub(crate) fn is_address_used(&self, address: &Address) -> bool {
+        match self.rpc.get_received_by_address(address, Some(0)) {
+            Ok(received) => received > Amount::ZERO,
+            Err(e) => {
+                log::debug!("No history for {} in the node: {:?}", address, e);
+                false
+            }
+        }
+    }
+
+    /// Returns the reused addresses not reported before, and remembers them as reported.
+    pub(crate) fn detect_address_reuse(&mut self) -> Result<Vec<ReusedAddress>, WalletError> {
+        let new_reuse = self
+            .list_reused()?
+            .into_iter()
+            .filter(|reused| !self.store.reused_addresses.contains(&reused.address))
+            .collect::<Vec<_>>();
+        if !new_reuse.is_empty() {
+            self.store
+                .reused_addresses
+                .extend(new_reuse.iter().map(|reused| reused.address.clone()));
+            self.save_to_disk()?;
+        }
+        Ok(new_reuse)
+    }
+}
@@ -1,15 +1,16 @@
 //! Manages connection with a Bitcoin Core RPC.
 //!
-use std::{convert::TryFrom, thread};
+use std::{collections::HashSet, convert::TryFrom, thread};
 
-use bitcoind::bitcoincore_rpc::{Auth, Client, RpcApi};
+use bitcoin::{Network, Txid};
+use bitcoind::bitcoincore_rpc::{self, Auth, Client, RpcApi};
 use serde_json::{json, Value};
 
 use crate::{utill::HEART_BEAT_INTERVAL, wallet::api::KeychainKind};
 
-use serde::Deserialize;
+use serde::{de::DeserializeOwned, Deserialize};
 
-use super::{error::WalletError, Wallet};
+use super::{backend::BlockchainBackend, error::WalletError, Wallet};
 
 /// Configuration parameters for connecting to a Bitcoin node via RPC.
 #[derive(Debug, Clone)]
@@ -24,6 +25,10 @@ pub struct RPCConfig {
 
 const RPC_HOSTPORT: &str = "localhost:18443";
 
+/// Number of recent sync checkpoints kept in the wallet file.
+/// A reorg deeper than this falls back to a rescan from the wallet birthday.
+const MAX_SYNC_CHECKPOINTS: usize = 16;
+
 impl Default for RPCConfig {
     fn default() -> Self {
         Self {
@@ -34,6 +39,22 @@ impl Default for RPCConfig {
     }
 }
 
+impl RPCConfig {
+    /// Checks that the backend node runs on the expected `network`. Or else hard error.
+    pub fn check_network(&self, network: Network) -> Result<(), WalletError> {
+        let node_network = Client::try_from(self)?.get_blockchain_info()?.chain;
+        if node_network != network {
+            log::error!(
+                "Expected a {} node, backend Bitcoin Core is running on {}",
+                network,
+                node_network
+            );
+            return Err(WalletError::General("Wrong Bitcoin Network".to_string()));
+        }
+        Ok(())
+    }
+}
+
 impl TryFrom<&RPCConfig> for Client {
     type Error = WalletError;
     fn try_from(config: &RPCConfig) -> Result<Self, WalletError> {
@@ -64,6 +85,71 @@ fn list_wallet_dir(client: &Client) -> Result<Vec<String>, WalletError> {
     Ok(result.wallets.into_iter().map(|n| n.name).collect())
 }
 
+/// Sends a JSON-RPC batch of calls to the same `method`, one per entry of `params`, in a single
+/// round trip. Each entry must be a json array of the call's positional arguments.
+/// Results are returned in the same order as `params`. The batch fails if any of its calls fails,
+/// or isn't answered.
+///
+/// This matters when the node is remote (or behind Tor), where the latency of each round trip
+/// dominates the time taken by a wallet sync.
+pub(crate) fn batch_call<T: DeserializeOwned>(
+    client: &Client,
+    method: &str,
+    params: &[Value],
+) -> Result<Vec<T>, WalletError> {
+    if params.is_empty() {
+        return Ok(Vec::new());
+    }
+    let jsonrpc = client.get_jsonrpc_client();
+    let raw_params = params
+        .iter()
+        .map(|p| {
+            serde_json::value::to_raw_value(p).map_err(|e| WalletError::General(e.to_string()))
+        })
+        .collect::<Result<Vec<_>, WalletError>>()?;
+    let requests = raw_params
+        .iter()
+        .map(|p| jsonrpc.build_request(method, Some(&**p)))
+        .collect::<Vec<_>>();
+
+    let responses = jsonrpc
+        .send_batch(&requests)
+        .map_err(bitcoincore_rpc::Error::from)?;
+
+    responses
+        .into_iter()
+        .map(|response| {
+            let response = response.ok_or_else(|| {
+                WalletError::General(format!("Missing response in {} batch", method))
+            })?;
+            Ok(response
+                .result::<T>()
+                .map_err(bitcoincore_rpc::Error::from)?)
+        })
+        .collect()
+}
+
+/// The txids among `txids` the chain backend knows of, in its mempool or in a block, in the order of `txids`.
+///
+/// The mempool is read in a single call, so a tx is seen as soon as it's relayed, before its first confirmation.
+/// The txids not in the mempool are looked up one by one, which finds the confirmed ones on a node with `-txindex`.
+pub(crate) fn broadcasted_txids(
+    chain: &dyn BlockchainBackend,
+    txids: &[Txid],
+) -> Result<Vec<Txid>, WalletError> {
+    if txids.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mempool = chain.mempool_txids()?.into_iter().collect::<HashSet<_>>();
+    let mut broadcasted = Vec::new();
+    for txid in txids {
+        if mempool.contains(txid) || chain.tx_confirmations(txid)?.is_some() {
+            broadcasted.push(*txid);
+        }
+    }
+    Ok(broadcasted)
+}
+
 impl Wallet {
     /// Sync the wallet with the configured Bitcoin Core RPC. Save data to disk.
     pub fn sync(&mut self) -> Result<(), WalletError> {
@@ -96,14 +182,20 @@ impl Wallet {
         }
 
         let descriptors_to_import = self.descriptors_to_import()?;
+        let (scan_from, reorged) = self.find_sync_start_height()?;
 
-        if descriptors_to_import.is_empty() {
-            return Ok(());
+        if descriptors_to_import.is_empty() && !reorged {
+            // Core keeps tracking already imported descriptors on its own, so there is nothing to
+            // scan. Just move the checkpoint to the current tip.
+            let node_synced = self.rpc.get_block_count()?;
+            self.record_sync_checkpoint(node_synced)?;
+            return self.rescan_if_range_extended();
         }
 
-        log::debug!("Importing Wallet spks/descriptors");
-
-        self.import_descriptors(&descriptors_to_import, None)?;
+        if !descriptors_to_import.is_empty() {
+            log::debug!("Importing Wallet spks/descriptors");
+            self.import_descriptors(&descriptors_to_import, None)?;
+        }
 
         // Now run the scan
         log::debug!("Initializing TxOut scan. This may take a while.");
@@ -111,23 +203,18 @@ impl Wallet {
         // Sometimes in test multiple wallet scans can occur at same time, resulting in error.
         // Just retry after 3 sec.
         loop {
-            let last_synced_height = self
-                .store
-                .last_synced_height
-                .unwrap_or(0)
-                .max(self.store.wallet_birthday.unwrap_or(0));
             let node_synced = self.rpc.get_block_count()?;
             log::debug!(
                 "Re-scanning Blockchain from:{} to:{}",
-                last_synced_height,
+                scan_from,
                 node_synced
             );
-            match self.rpc.rescan_blockchain(
-                Some(last_synced_height as usize),
-                Some(node_synced as usize),
-            ) {
+            match self
+                .rpc
+                .rescan_blockchain(Some(scan_from as usize), Some(node_synced as usize))
+            {
                 Ok(_) => {
-                    self.store.last_synced_height = Some(node_synced);
+                    self.record_sync_checkpoint(node_synced)?;
                     break;
                 }
 
@@ -140,8 +227,69 @@ impl Wallet {
         }
 
         let max_external_index = self.find_hd_next_index(KeychainKind::External)?;
-        self.update_external_index(max_external_index)?;
+        self.update_external_index(max_external_index.max(self.store.external_index))?;
         self.refresh_offer_maxsize_cache()?;
+        self.rescan_if_range_extended()
+    }
+
+    /// Restored wallets may have used addresses past the imported range. When the used addresses come
+    /// within the gap limit of its end, the range is extended and the whole chain is scanned again, as
+    /// the new addresses may have history older than the last sync.
+    fn rescan_if_range_extended(&mut self) -> Result<(), WalletError> {
+        if !self.extend_import_range()? {
+            return Ok(());
+        }
+        self.store.sync_checkpoints.clear();
+        self.store.last_synced_height = None;
+        self.sync()
+    }
+
+    /// Finds the height to resume scanning from, and whether a reorg happened since the last sync.
+    ///
+    /// Walks the persisted checkpoints from the newest and returns the first one still in the node's
+    /// best chain, i.e. the fork point. Checkpoints above it were reorged out and are dropped.
+    /// If none of them survived, scanning restarts from the wallet birthday.
+    fn find_sync_start_height(&mut self) -> Result<(u64, bool), WalletError> {
+        let birthday = self.store.wallet_birthday.unwrap_or(0);
+        let tip = self.rpc.get_block_count()?;
+        let mut reorged = false;
+
+        while let Some(&(height, hash)) = self.store.sync_checkpoints.last() {
+            if height <= tip && self.rpc.get_block_hash(height)? == hash {
+                return Ok((height.max(birthday), reorged));
+            }
+            log::warn!(
+                "Reorg detected: block {} at height {} is no longer in the best chain",
+                hash,
+                height
+            );
+            self.store.sync_checkpoints.pop();
+            reorged = true;
+        }
+
+        if reorged {
+            self.store.last_synced_height = None;
+            return Ok((birthday, true));
+        }
+
+        // Wallets synced before checkpoints were tracked only have the last synced height.
+        Ok((
+            self.store.last_synced_height.unwrap_or(0).max(birthday),
+            false,
+        ))
+    }
+
+    /// Records the block at `height` as the latest sync checkpoint.
+    fn record_sync_checkpoint(&mut self, height: u64) -> Result<(), WalletError> {
+        let hash = self.rpc.get_block_hash(height)?;
+        let checkpoints = &mut self.store.sync_checkpoints;
+        if checkpoints.last() != Some(&(height, hash)) {
+            checkpoints.push((height, hash));
+        }
+        if checkpoints.len() > MAX_SYNC_CHECKPOINTS {
+            checkpoints.drain(..checkpoints.len() - MAX_SYNC_CHECKPOINTS);
+        }
+        self.store.last_synced_height = Some(height);
         Ok(())
     }
 
@@ -151,6 +299,18 @@ impl Wallet {
         while let Err(e) = self.sync() {
             log::error!("Blockchain sync failed. Retrying. | {:?}", e);
         }
+        match self.detect_address_reuse() {
+            Ok(reused) => {
+                for reused in reused {
+                    log::warn!(
+                        "Address reuse detected: {} received coins in {} transactions. Spending them together links the payments.",
+                        reused.address,
+                        reused.txids.len()
+                    );
+                }
+            }
+            Err(e) => log::debug!("Address reuse check failed: {:?}", e),
+        }
     }
 
     /// Import watch addresses into core wallet. Does not check if the address was already imported.
@@ -182,3 +342,213 @@ impl Wallet {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wallet::{storage::WalletStore, MockBlockchain};
+    use bitcoin::{bip32::Xpriv, hashes::Hash, BlockHash};
+    use bitcoind::tempfile::tempdir;
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+        path::Path,
+        sync::Arc,
+    };
+
+    /// A JSON-RPC server answering each request with `respond`, called with the body of the request.
+    fn serve<F: Fn(Value) -> Value + Send + 'static>(respond: F) -> Client {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut reader = BufReader::new(stream.unwrap());
+                // The client keeps the connection open across requests.
+                loop {
+                    let mut content_length = 0;
+                    loop {
+                        let mut line = String::new();
+                        if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                            break;
+                        }
+                        if line == "\r\n" {
+                            break;
+                        }
+                        if let Some(length) = line.to_lowercase().strip_prefix("content-length:") {
+                            content_length = length.trim().parse().unwrap();
+                        }
+                    }
+                    if content_length == 0 {
+                        break;
+                    }
+                    let mut body = vec![0; content_length];
+                    reader.read_exact(&mut body).unwrap();
+                    let body = serde_json::to_string(&respond(
+                        serde_json::from_slice::<Value>(&body).unwrap(),
+                    ))
+                    .unwrap();
+                    write!(
+                        reader.get_mut(),
+                        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                        body.len(),
+                        body
+                    )
+                    .unwrap();
+                }
+            }
+        });
+        Client::new(&format!("http://{}", address), Auth::None).unwrap()
+    }
+
+    /// A JSON-RPC server answering batches with `respond`, called with the requests of the batch.
+    fn serve_batch(respond: fn(Vec<Value>) -> Vec<Value>) -> Client {
+        serve(move |requests| json!(respond(serde_json::from_value(requests).unwrap())))
+    }
+
+    fn result(request: &Value, result: Value) -> Value {
+        json!({"jsonrpc": "2.0", "id": request["id"], "result": result, "error": null})
+    }
+
+    #[test]
+    fn test_batch_call() {
+        // Answered out of order, each call with its first argument.
+        let client = serve_batch(|requests| {
+            requests
+                .iter()
+                .rev()
+                .map(|request| result(request, request["params"][0].clone()))
+                .collect()
+        });
+        let params = (0..5).map(|i| json!([i])).collect::<Vec<_>>();
+        let results: Vec<u32> = batch_call(&client, "echo", &params).unwrap();
+        assert_eq!(results, vec![0, 1, 2, 3, 4]);
+
+        // An empty batch makes no call.
+        let results: Vec<u32> = batch_call(&client, "echo", &[]).unwrap();
+        assert!(results.is_empty());
+    }
+
+    #[test]
+    fn test_batch_call_partial_failure() {
+        // The second call fails.
+        let client = serve_batch(|requests| {
+            vec![
+                result(&requests[0], json!(0)),
+                json!({
+                    "jsonrpc": "2.0",
+                    "id": requests[1]["id"],
+                    "result": null,
+                    "error": {"code": -5, "message": "Invalid address"}
+                }),
+            ]
+        });
+        let params = [json!([0]), json!([1])];
+        assert!(matches!(
+            batch_call::<u32>(&client, "echo", &params),
+            Err(WalletError::Rpc(_))
+        ));
+
+        // The second call isn't answered.
+        let client = serve_batch(|requests| vec![result(&requests[0], json!(0))]);
+        match batch_call::<u32>(&client, "echo", &params) {
+            Err(WalletError::General(message)) => {
+                assert_eq!(message, "Missing response in echo batch")
+            }
+            other => panic!("Expected a missing response, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_broadcasted_txids() {
+        let txid = |byte: u8| Txid::from_byte_array([byte; 32]);
+        let (in_mempool, unknown) = (txid(1), txid(2));
+        // Only the mempool is known to the node.
+        let client = serve(move |request| match request["method"].as_str() {
+            Some("getrawmempool") => result(&request, json!([in_mempool.to_string()])),
+            _ => json!({
+                "jsonrpc": "2.0",
+                "id": request["id"],
+                "result": null,
+                "error": {"code": -5, "message": "No such mempool or blockchain transaction"}
+            }),
+        });
+        assert_eq!(
+            broadcasted_txids(&client, &[unknown, in_mempool]).unwrap(),
+            vec![in_mempool]
+        );
+        assert!(broadcasted_txids(&client, &[unknown]).unwrap().is_empty());
+    }
+
+    /// Hash of the block at `height`, on the chain forked at `fork`.
+    fn block_hash(height: u64, fork: u8) -> BlockHash {
+        let mut hash = [fork; 32];
+        hash[..8].copy_from_slice(&height.to_le_bytes());
+        BlockHash::from_byte_array(hash)
+    }
+
+    /// A wallet in `dir`, on a node with its tip at height 125, reorged above height 110.
+    fn reorged_wallet(dir: &Path, checkpoints: &[u64]) -> Wallet {
+        let rpc = serve(|request| {
+            let height = request["params"][0].as_u64().unwrap_or_default();
+            let answer = match request["method"].as_str().unwrap() {
+                "getblockcount" => json!(125),
+                "getblockhash" => json!(block_hash(height, if height <= 110 { 0 } else { 1 })),
+                method => panic!("Unexpected call {}", method),
+            };
+            result(&request, answer)
+        });
+        let master_key = Xpriv::new_master(Network::Regtest, &[1; 32]).unwrap();
+        let wallet_file_path = dir.join("reorged-wallet");
+        let mut store = WalletStore::init(
+            "reorged-wallet".to_string(),
+            &wallet_file_path,
+            Network::Regtest,
+            master_key,
+            Some(50),
+            None,
+        )
+        .unwrap();
+        // Synced on the chain reorged out.
+        store.sync_checkpoints = checkpoints
+            .iter()
+            .map(|&height| (height, block_hash(height, 0)))
+            .collect();
+        store.last_synced_height = checkpoints.last().copied();
+        Wallet {
+            rpc,
+            chain: Arc::new(MockBlockchain::new()),
+            wallet_file_path,
+            store,
+            fee_estimator: None,
+            payjoin_inputs: HashSet::new(),
+        }
+    }
+
+    #[test]
+    fn test_sync_start_after_reorg() {
+        // The checkpoints above the fork point are rolled back, and the scan resumes from the fork point.
+        let temp_dir = tempdir().unwrap();
+        let mut wallet = reorged_wallet(temp_dir.path(), &[100, 110, 120]);
+        assert_eq!(wallet.find_sync_start_height().unwrap(), (110, true));
+        assert_eq!(
+            wallet.store.sync_checkpoints,
+            vec![(100, block_hash(100, 0)), (110, block_hash(110, 0))]
+        );
+        wallet.record_sync_checkpoint(125).unwrap();
+        assert_eq!(wallet.store.sync_checkpoints.len(), 3);
+        assert_eq!(
+            wallet.store.sync_checkpoints.last(),
+            Some(&(125, block_hash(125, 1)))
+        );
+        assert_eq!(wallet.store.last_synced_height, Some(125));
+
+        // Without a reorg, the scan resumes from the last checkpoint.
+        assert_eq!(wallet.find_sync_start_height().unwrap(), (125, false));
+
+        // Past all the checkpoints, the scan restarts from the birthday.
+        let mut wallet = reorged_wallet(temp_dir.path(), &[115, 120]);
+        assert_eq!(wallet.find_sync_start_height().unwrap(), (50, true));
+        assert!(wallet.store.sync_checkpoints.is_empty());
+        assert_eq!(wallet.store.last_synced_height, None);
+    }
+}
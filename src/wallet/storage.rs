@@ -1,19 +1,32 @@
 //! The Wallet Storage Interface.
 //!
-//! Wallet data is currently written in unencrypted CBOR files which are not directly human readable.
+//! Wallet data is written in CBOR files which are not directly human readable. With a wallet passphrase, the
+//! master key and the swap coins are encrypted, see [encryption](super::encryption).
 
-use bitcoin::{bip32::Xpriv, Network, OutPoint, ScriptBuf};
+use bitcoin::{bip32::Xpriv, BlockHash, Network, OutPoint, ScriptBuf};
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     fs::{self, read, File},
     io::BufWriter,
     path::Path,
 };
+use zeroize::Zeroizing;
 
-use super::{error::WalletError, fidelity::FidelityBond};
+use super::{
+    api::{ScriptType, DEFAULT_GAP_LIMIT},
+    change::DEFAULT_DUST_THRESHOLD,
+    encryption::{seal_swap_state, unseal_swap_state, wipe, SwapStateKey},
+    error::WalletError,
+    fidelity::FidelityBond,
+    migration::{file_version, migrate_file, RawWalletFile, WALLET_FILE_VERSION},
+};
 
-use super::swapcoin::{IncomingSwapCoin, OutgoingSwapCoin};
+use super::{
+    memo::MemoTarget,
+    swapcoin::{IncomingSwapCoin, OutgoingSwapCoin},
+};
+use crate::protocol::Hash160;
 
 /// Represents the internal data store for a Bitcoin wallet.
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
@@ -38,18 +51,78 @@ pub(crate) struct WalletStore {
     pub(super) fidelity_bond: HashMap<u32, (FidelityBond, ScriptBuf, bool)>,
     //TODO: Add last synced height and Wallet birthday.
     pub(super) last_synced_height: Option<u64>,
+    /// Recently synced blocks as (height, hash), oldest first. Used to detect reorgs since the last
+    /// sync and to find the fork point to resume scanning from.
+    #[serde(default)]
+    pub(super) sync_checkpoints: Vec<(u64, BlockHash)>,
 
     pub(super) wallet_birthday: Option<u64>,
+    /// Reused addresses already reported, see [Wallet::detect_address_reuse](super::Wallet).
+    #[serde(default)]
+    pub(super) reused_addresses: HashSet<String>,
+    /// Number of unused addresses scanned past the last used one, on each keychain.
+    #[serde(default = "default_gap_limit")]
+    pub(super) gap_limit: u32,
+    /// Number of addresses imported into the node, on each keychain. Grows with the used addresses.
+    #[serde(default)]
+    pub(super) import_count: u32,
+    /// Unit change amounts are rounded down to, in sats. 0 disables rounding.
+    #[serde(default)]
+    pub(super) change_rounding: u64,
+    /// Outputs below this amount are dust, in sats.
+    #[serde(default = "default_dust_threshold")]
+    pub(super) dust_threshold: u64,
+    /// Script type of the receive and change addresses.
+    #[serde(default)]
+    pub(super) script_type: ScriptType,
+    /// Hashvalue of the contracts of each swap, by swap id.
+    #[serde(default)]
+    pub(super) swap_hashvalues: HashMap<String, Hash160>,
+    /// Memos of the operator on transactions and swaps.
+    #[serde(default)]
+    pub(super) memos: HashMap<MemoTarget, String>,
+    /// UTXOs excluded from the automatic coin selections, see [Wallet::freeze](super::Wallet::freeze).
+    #[serde(default)]
+    pub(super) frozen_utxos: HashSet<OutPoint>,
+    /// Next index of the 2-of-2 multisig keys of swaps, see [KeyClass](super::KeyClass).
+    #[serde(default)]
+    pub(super) swap_key_index: u32,
+    /// Next index of the hashlock and timelock keys of contracts, see [KeyClass](super::KeyClass).
+    #[serde(default)]
+    pub(super) contract_key_index: u32,
+    /// Index of the current identity key of the maker, bumped by each rotation.
+    #[serde(default)]
+    pub(super) identity_index: u32,
+    /// Next internal index not yet reserved for a claim, see [Wallet::reserve_internal_address](super::Wallet).
+    #[serde(default)]
+    pub(super) internal_index: u32,
+    /// Version of the file format, see [WALLET_FILE_VERSION]. 0 for wallet files from before versioning.
+    #[serde(default)]
+    pub(super) version: u32,
+    /// Key encrypting the master key and the swap coins on disk, derived from the wallet passphrase. None to store
+    /// them in the clear.
+    #[serde(skip)]
+    pub(super) swap_state_key: Option<SwapStateKey>,
+}
+
+fn default_gap_limit() -> u32 {
+    DEFAULT_GAP_LIMIT
+}
+
+fn default_dust_threshold() -> u64 {
+    DEFAULT_DUST_THRESHOLD
 }
 
 impl WalletStore {
-    /// Initialize a store at a path (if path already exists, it will overwrite it).
+    /// Initialize a store at a path (if path already exists, it will overwrite it). With a `swap_state_key`, the
+    /// secrets are sealed from the first write.
     pub(crate) fn init(
         file_name: String,
         path: &Path,
         network: Network,
         master_key: Xpriv,
         wallet_birthday: Option<u64>,
+        swap_state_key: Option<SwapStateKey>,
     ) -> Result<Self, WalletError> {
         let store = Self {
             file_name,
@@ -62,32 +135,64 @@ impl WalletStore {
             prevout_to_contract_map: HashMap::new(),
             fidelity_bond: HashMap::new(),
             last_synced_height: None,
+            sync_checkpoints: Vec::new(),
             wallet_birthday,
+            reused_addresses: HashSet::new(),
+            gap_limit: DEFAULT_GAP_LIMIT,
+            import_count: 0,
+            change_rounding: 0,
+            dust_threshold: DEFAULT_DUST_THRESHOLD,
+            script_type: ScriptType::default(),
+            swap_hashvalues: HashMap::new(),
+            memos: HashMap::new(),
+            frozen_utxos: HashSet::new(),
+            swap_key_index: 0,
+            contract_key_index: 0,
+            identity_index: 0,
+            internal_index: 0,
+            version: WALLET_FILE_VERSION,
+            swap_state_key,
         };
 
         std::fs::create_dir_all(path.parent().expect("Path should NOT be root!"))?;
-        // write: overwrites existing file.
         // create: creates new file if doesn't exist.
-        let file = File::create(path)?;
-        let writer = BufWriter::new(file);
-        serde_cbor::to_writer(writer, &store)?;
+        File::create(path)?;
+        store.write_to_disk(path)?;
 
         Ok(store)
     }
 
-    /// Load existing file, updates it, writes it back (errors if path doesn't exist).
+    /// Load existing file, updates it, writes it back (errors if path doesn't exist). The file is truncated, so
+    /// that no tail of a longer, or unsealed, previous content is left behind.
     pub(crate) fn write_to_disk(&self, path: &Path) -> Result<(), WalletError> {
-        let wallet_file = fs::OpenOptions::new().write(true).open(path)?;
+        let wallet_file = fs::OpenOptions::new()
+            .write(true)
+            .truncate(true)
+            .open(path)?;
         let writer = BufWriter::new(wallet_file);
-        Ok(serde_cbor::to_writer(writer, &self)?)
+        match &self.swap_state_key {
+            None => Ok(serde_cbor::to_writer(writer, &self)?),
+            Some(key) => {
+                let plaintext = Zeroizing::new(serde_cbor::to_vec(&self)?);
+                let mut file = serde_cbor::from_slice::<RawWalletFile>(&plaintext)?;
+                seal_swap_state(&mut file, key)?;
+                serde_cbor::to_writer(writer, &file)?;
+                wipe(&mut file);
+                Ok(())
+            }
+        }
     }
 
-    /// Reads from a path (errors if path doesn't exist).
-    pub(crate) fn read_from_disk(path: &Path) -> Result<Self, WalletError> {
+    /// Reads from a path (errors if path doesn't exist). The `passphrase` decrypts the master key and the swap
+    /// coins, and encrypts them again on the next write. A file in the clear is written back in the clear.
+    pub(crate) fn read_from_disk(
+        path: &Path,
+        passphrase: Option<&str>,
+    ) -> Result<Self, WalletError> {
         //let wallet_file = File::open(path)?;
-        let mut reader = read(path)?;
-        let store = match serde_cbor::from_slice::<Self>(&reader) {
-            Ok(store) => store,
+        let mut reader = Zeroizing::new(read(path)?);
+        let mut file = match serde_cbor::from_slice::<RawWalletFile>(&reader) {
+            Ok(file) => file,
             Err(e) => {
                 let err_string = format!("{:?}", e);
                 if err_string.contains("code: TrailingData") {
@@ -95,8 +200,8 @@ impl WalletStore {
                     loop {
                         // pop the last byte and try again.
                         reader.pop();
-                        match serde_cbor::from_slice::<Self>(&reader) {
-                            Ok(store) => break store,
+                        match serde_cbor::from_slice::<RawWalletFile>(&reader) {
+                            Ok(file) => break file,
                             Err(_) => continue,
                         }
                     }
@@ -105,13 +210,37 @@ impl WalletStore {
                 }
             }
         };
+
+        // Older files are upgraded before being read into the store. The upgraded file is encoded again, rather
+        // than read from the CBOR value, to keep the compact encodings of keys and scripts.
+        let version = file_version(&file)?;
+        let migrated = migrate_file(&mut file, version)?;
+        let swap_state_key = unseal_swap_state(&mut file, passphrase)?;
+        if migrated || swap_state_key.is_some() {
+            reader = Zeroizing::new(serde_cbor::to_vec(&file)?);
+        }
+        wipe(&mut file);
+        let mut store = serde_cbor::from_slice::<Self>(&reader)?;
+        store.swap_state_key = swap_state_key;
         Ok(store)
     }
 }
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{
+        protocol::{contract::create_contract_redeemscript, Hash160},
+        wallet::SwapCoin,
+    };
     use bip39::rand::{thread_rng, Rng};
+    use bitcoin::{
+        absolute::LockTime,
+        hashes::Hash,
+        hex::{Case, DisplayHex},
+        secp256k1::{Secp256k1, SecretKey},
+        transaction::Version,
+        Amount, PublicKey, Transaction,
+    };
     use bitcoind::tempfile::tempdir;
 
     #[test]
@@ -130,12 +259,109 @@ mod tests {
             Network::Bitcoin,
             master_key,
             None,
+            None,
         )
         .unwrap();
 
         original_wallet_store.write_to_disk(&file_path).unwrap();
 
-        let read_wallet = WalletStore::read_from_disk(&file_path).unwrap();
+        let read_wallet = WalletStore::read_from_disk(&file_path, None).unwrap();
         assert_eq!(original_wallet_store, read_wallet);
     }
+
+    #[test]
+    fn test_wallet_file_versions() {
+        let temp_dir = tempdir().unwrap();
+        let file_path = temp_dir.path().join("test_wallet.cbor");
+        let master_key = Xpriv::new_master(Network::Regtest, &[7u8; 16]).unwrap();
+
+        let mut store = WalletStore::init(
+            "test_wallet".to_string(),
+            &file_path,
+            Network::Regtest,
+            master_key,
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!(store.version, WALLET_FILE_VERSION);
+
+        // Files from before versioning read as version 0.
+        store.version = 0;
+        store.write_to_disk(&file_path).unwrap();
+        assert_eq!(
+            WalletStore::read_from_disk(&file_path, None).unwrap(),
+            store
+        );
+
+        // Files of a newer version are refused.
+        store.version = WALLET_FILE_VERSION + 1;
+        store.write_to_disk(&file_path).unwrap();
+        assert!(WalletStore::read_from_disk(&file_path, None).is_err());
+    }
+
+    #[test]
+    fn test_sealed_wallet_file() {
+        let temp_dir = tempdir().unwrap();
+        let file_path = temp_dir.path().join("test_wallet.cbor");
+        let master_key = Xpriv::new_master(Network::Regtest, &[7u8; 16]).unwrap();
+
+        let mut store = WalletStore::init(
+            "test_wallet".to_string(),
+            &file_path,
+            Network::Regtest,
+            master_key,
+            None,
+            Some(SwapStateKey::new("correct horse")),
+        )
+        .unwrap();
+
+        let secp = Secp256k1::new();
+        let pubkey = |privkey: &SecretKey| PublicKey::new(privkey.public_key(&secp));
+        let my_privkey = SecretKey::from_slice(&[2; 32]).unwrap();
+        let timelock_privkey = SecretKey::from_slice(&[4; 32]).unwrap();
+        let swapcoin = OutgoingSwapCoin::new(
+            my_privkey,
+            pubkey(&SecretKey::from_slice(&[3; 32]).unwrap()),
+            Transaction {
+                version: Version::TWO,
+                lock_time: LockTime::ZERO,
+                input: vec![],
+                output: vec![],
+            },
+            create_contract_redeemscript(
+                &pubkey(&SecretKey::from_slice(&[1; 32]).unwrap()),
+                &pubkey(&timelock_privkey),
+                &Hash160::hash(&[0; 32]),
+                &2,
+            ),
+            timelock_privkey,
+            Amount::from_sat(10_000),
+        )
+        .unwrap();
+        store
+            .outgoing_swapcoins
+            .insert(swapcoin.get_multisig_redeemscript(), swapcoin);
+        store.write_to_disk(&file_path).unwrap();
+
+        // No secret is left in the file, in raw bytes nor as text.
+        let saved = fs::read(&file_path).unwrap();
+        let contains = |needle: &[u8]| saved.windows(needle.len()).any(|window| window == needle);
+        for secret in [
+            master_key.private_key.secret_bytes(),
+            my_privkey.secret_bytes(),
+            timelock_privkey.secret_bytes(),
+        ] {
+            assert!(!contains(&secret));
+            assert!(!contains(secret.to_hex_string(Case::Lower).as_bytes()));
+        }
+        assert!(!contains(master_key.to_string().as_bytes()));
+
+        assert!(WalletStore::read_from_disk(&file_path, None).is_err());
+        assert!(WalletStore::read_from_disk(&file_path, Some("wrong horse")).is_err());
+        assert_eq!(
+            WalletStore::read_from_disk(&file_path, Some("correct horse")).unwrap(),
+            store
+        );
+    }
 }
@@ -32,6 +32,9 @@ use crate::protocol::{
 
 use super::WalletError;
 
+/// Size of a timelock spend of a contract transaction, in vbytes. Calculated using testmempoolaccept.
+pub(crate) const TIMELOCK_SPEND_VSIZE: u64 = 128;
+
 /// Defines an incoming swapcoin, which can either be currently active or successfully completed.
 ///
 /// ### NOTE:
@@ -359,6 +362,41 @@ impl IncomingSwapCoin {
         )
     }
 
+    /// Create a fully signed transaction claiming the contract output via the hashlock branch.
+    pub(crate) fn create_hashlock_spend(
+        &self,
+        destination_address: &Address,
+        hash_preimage: &Preimage,
+    ) -> Result<Transaction, WalletError> {
+        let miner_fee = 136 * 2; //136 vbytes x 2 sat/vb, size calculated using testmempoolaccept
+        let mut tx = Transaction {
+            input: vec![TxIn {
+                previous_output: OutPoint {
+                    txid: self.contract_tx.compute_txid(),
+                    vout: 0, //contract_tx is one-input-one-output
+                },
+                sequence: Sequence(1), //hashlock spends must have 1 because of the `OP_CSV 1`
+                witness: Witness::new(),
+                script_sig: ScriptBuf::new(),
+            }],
+            output: vec![TxOut {
+                script_pubkey: destination_address.script_pubkey(),
+                value: Amount::from_sat(self.contract_tx.output[0].value.to_sat() - miner_fee),
+            }],
+            lock_time: LockTime::ZERO,
+            version: Version::TWO,
+        };
+        let index = 0;
+        self.sign_hashlocked_transaction_input_given_preimage(
+            index,
+            &tx.clone(),
+            &mut tx.input[0],
+            self.contract_tx.output[0].value,
+            hash_preimage,
+        )?;
+        Ok(tx)
+    }
+
     pub(crate) fn verify_contract_tx_sig(&self, sig: &Signature) -> Result<(), WalletError> {
         Ok(verify_contract_tx_sig(
             &self.contract_tx,
@@ -404,59 +442,41 @@ impl OutgoingSwapCoin {
         input: &mut TxIn,
         input_value: Amount,
     ) -> Result<(), WalletError> {
-        let secp = Secp256k1::new();
-        let sighash = secp256k1::Message::from_digest_slice(
-            &SighashCache::new(tx)
-                .p2wsh_signature_hash(
-                    index,
-                    &self.contract_redeemscript,
-                    input_value,
-                    EcdsaSighashType::All,
-                )
-                .map_err(ProtocolError::Sighash)?[..],
+        sign_timelock_input(
+            index,
+            tx,
+            input,
+            input_value,
+            &self.contract_redeemscript,
+            &self.timelock_privkey,
         )
-        .map_err(ProtocolError::Secp)?;
-
-        let sig_timelock = secp.sign_ecdsa(&sighash, &self.timelock_privkey);
-
-        let mut sig_timelock_bytes = sig_timelock.serialize_der().to_vec();
-        sig_timelock_bytes.push(EcdsaSighashType::All as u8);
-        input.witness.push(sig_timelock_bytes);
-        input.witness.push(Vec::new());
-        input.witness.push(self.contract_redeemscript.to_bytes());
-        Ok(())
     }
 
     pub(crate) fn create_timelock_spend(
         &self,
         destination_address: &Address,
     ) -> Result<Transaction, WalletError> {
-        let miner_fee = 128 * 2; //128 vbytes x 2 sat/vb, size calculated using testmempoolaccept
-        let mut tx = Transaction {
-            input: vec![TxIn {
-                previous_output: OutPoint {
-                    txid: self.contract_tx.compute_txid(),
-                    vout: 0, //contract_tx is one-input-one-output
-                },
-                sequence: Sequence(self.get_timelock()? as u32),
-                witness: Witness::new(),
-                script_sig: ScriptBuf::new(),
-            }],
-            output: vec![TxOut {
-                script_pubkey: destination_address.script_pubkey(),
-                value: Amount::from_sat(self.contract_tx.output[0].value.to_sat() - miner_fee),
-            }],
-            lock_time: LockTime::ZERO,
-            version: Version::TWO,
-        };
-        let index = 0;
-        self.sign_timelocked_transaction_input(
-            index,
-            &tx.clone(),
-            &mut tx.input[0],
+        let miner_fee = Amount::from_sat(TIMELOCK_SPEND_VSIZE * 2); // 2 sat/vb
+        self.create_timelock_spend_with_fee(&destination_address.script_pubkey(), miner_fee)
+    }
+
+    /// Create a fully signed timelock spend of the contract, paying the given miner fee.
+    pub(crate) fn create_timelock_spend_with_fee(
+        &self,
+        destination_script: &Script,
+        miner_fee: Amount,
+    ) -> Result<Transaction, WalletError> {
+        create_timelock_spend_tx(
+            OutPoint {
+                txid: self.contract_tx.compute_txid(),
+                vout: 0, //contract_tx is one-input-one-output
+            },
             self.contract_tx.output[0].value,
-        )?;
-        Ok(tx)
+            &self.contract_redeemscript,
+            &self.timelock_privkey,
+            destination_script,
+            miner_fee,
+        )
     }
 
     //"_with_my_privkey" as opposed to with other_privkey
@@ -634,6 +654,73 @@ impl SwapCoin for WatchOnlySwapCoin {
     }
 }
 
+/// Create a fully signed timelock spend of the contract output at `contract_outpoint`, paying the given
+/// miner fee.
+pub(crate) fn create_timelock_spend_tx(
+    contract_outpoint: OutPoint,
+    contract_value: Amount,
+    contract_redeemscript: &Script,
+    timelock_privkey: &SecretKey,
+    destination_script: &Script,
+    miner_fee: Amount,
+) -> Result<Transaction, WalletError> {
+    let mut tx = Transaction {
+        input: vec![TxIn {
+            previous_output: contract_outpoint,
+            sequence: Sequence(read_contract_locktime(contract_redeemscript)? as u32),
+            witness: Witness::new(),
+            script_sig: ScriptBuf::new(),
+        }],
+        output: vec![TxOut {
+            script_pubkey: destination_script.to_owned(),
+            value: contract_value - miner_fee,
+        }],
+        lock_time: LockTime::ZERO,
+        version: Version::TWO,
+    };
+    sign_timelock_input(
+        0,
+        &tx.clone(),
+        &mut tx.input[0],
+        contract_value,
+        contract_redeemscript,
+        timelock_privkey,
+    )?;
+    Ok(tx)
+}
+
+/// Sign the input spending a contract via its timelock branch.
+fn sign_timelock_input(
+    index: usize,
+    tx: &Transaction,
+    input: &mut TxIn,
+    input_value: Amount,
+    contract_redeemscript: &Script,
+    timelock_privkey: &SecretKey,
+) -> Result<(), WalletError> {
+    let secp = Secp256k1::new();
+    let sighash = secp256k1::Message::from_digest_slice(
+        &SighashCache::new(tx)
+            .p2wsh_signature_hash(
+                index,
+                contract_redeemscript,
+                input_value,
+                EcdsaSighashType::All,
+            )
+            .map_err(ProtocolError::Sighash)?[..],
+    )
+    .map_err(ProtocolError::Secp)?;
+
+    let sig_timelock = secp.sign_ecdsa(&sighash, timelock_privkey);
+
+    let mut sig_timelock_bytes = sig_timelock.serialize_der().to_vec();
+    sig_timelock_bytes.push(EcdsaSighashType::All as u8);
+    input.witness.push(sig_timelock_bytes);
+    input.witness.push(Vec::new());
+    input.witness.push(contract_redeemscript.to_bytes());
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use std::str::FromStr;
@@ -0,0 +1,121 @@
+//! Internal transfers of swap coins.
+//!
+//! Swap coins sit in 2-of-2 multisig outputs until they are spent. An internal transfer "graduates" them into
+//! the regular balance: each selected coin is spent alone to a fresh internal address, so transferring coins
+//! of different swaps doesn't link them together. The transfers can be broadcast after random delays, so they
+//! don't all show up on chain at once.
+
+use std::time::Duration;
+
+use bitcoin::{
+    secp256k1::rand::{thread_rng, Rng},
+    Address, Amount, FeeRate, OutPoint, Transaction,
+};
+use bitcoind::bitcoincore_rpc::json::ListUnspentResultEntry;
+
+use super::{api::UTXOSpendInfo, Destination, SendAmount, Wallet, WalletError};
+
+impl Wallet {
+    /// Creates the transactions moving each of the selected swap coins to its own fresh internal address.
+    ///
+    /// All `outpoints` must be spendable swap coins of the wallet. The returned transactions are signed, but not
+    /// broadcast, in the order of `outpoints`.
+    pub fn transfer_swap_coins(
+        &mut self,
+        outpoints: &[OutPoint],
+        feerate: FeeRate,
+    ) -> Result<Vec<Transaction>, WalletError> {
+        let swap_coins = self.list_swap_coin_utxo_spend_info(None)?;
+        let coins = outpoints
+            .iter()
+            .map(|outpoint| {
+                swap_coins
+                    .iter()
+                    .find(|(utxo, _)| utxo.txid == outpoint.txid && utxo.vout == outpoint.vout)
+                    .cloned()
+                    .ok_or_else(|| {
+                        WalletError::General(format!("{} is not a spendable swap coin", outpoint))
+                    })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // Unused addresses are derived from the chain, so all of them are derived at once, before any is paid.
+        let addresses = self.get_next_internal_addresses(coins.len() as u32)?;
+        self.transfer_coins(coins, addresses, feerate)
+    }
+
+    /// Creates the transactions moving each of the coins alone to the address at the same position, so that
+    /// the coins aren't linked together. The returned transactions are signed, but not broadcast.
+    pub(crate) fn transfer_coins(
+        &mut self,
+        coins: Vec<(ListUnspentResultEntry, UTXOSpendInfo)>,
+        addresses: Vec<Address>,
+        feerate: FeeRate,
+    ) -> Result<Vec<Transaction>, WalletError> {
+        if addresses.len() != coins.len() {
+            return Err(WalletError::General(format!(
+                "{} addresses for {} coins to transfer",
+                addresses.len(),
+                coins.len()
+            )));
+        }
+        coins
+            .into_iter()
+            .zip(addresses)
+            .map(|(coin, address)| {
+                let available = coin.0.amount;
+                let dust_limit = coin.0.script_pub_key.minimal_non_dust();
+                let coins = [coin];
+
+                // Sign once to measure the size, then create the final tx with the fee.
+                let destination = Destination::Address(address);
+                let vsize = self
+                    .spend_from_wallet(Amount::ZERO, SendAmount::Max, destination.clone(), &coins)?
+                    .vsize() as u64;
+                let fee = Amount::from_sat(feerate.to_sat_per_vb_ceil() * vsize);
+                if fee + dust_limit > available {
+                    return Err(WalletError::InsufficientFund {
+                        available: available.to_sat(),
+                        required: (fee + dust_limit).to_sat(),
+                    });
+                }
+
+                log::info!(
+                    "Transferring {} coin {}:{} of {} | fee {}",
+                    coins[0].1,
+                    coins[0].0.txid,
+                    coins[0].0.vout,
+                    available,
+                    fee
+                );
+                self.spend_from_wallet(fee, SendAmount::Max, destination, &coins)
+            })
+            .collect()
+    }
+}
+
+/// Random delays before broadcasting each of `count` transfers, up to `max_delay` each, shortest first.
+pub fn transfer_delays(count: usize, max_delay: Duration) -> Vec<Duration> {
+    let mut rng = thread_rng();
+    let mut delays = (0..count)
+        .map(|_| max_delay.mul_f64(rng.gen::<f64>()))
+        .collect::<Vec<_>>();
+    delays.sort();
+    delays
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transfer_delays() {
+        let max_delay = Duration::from_secs(3600);
+        let delays = transfer_delays(5, max_delay);
+        assert_eq!(delays.len(), 5);
+        assert!(delays.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert!(delays.iter().all(|delay| *delay <= max_delay));
+
+        assert_eq!(transfer_delays(3, Duration::ZERO), vec![Duration::ZERO; 3]);
+    }
+}
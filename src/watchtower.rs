@@ -0,0 +1,748 @@
+//! A background watchtower, shared by the Maker and the Taker.
+//!
+//! The watchtower keeps track of all live contract transactions along with the fully signed claim
+//! transactions spending them. The moment a watched contract transaction confirms, the corresponding
+//! claim is broadcasted without waiting for the main swap loop.
+//!
+//! - Hashlock claims are broadcasted as soon as the contract is confirmed.
+//! - Timelock claims are broadcasted once the contract reaches its relative locktime maturity.
+//!
+//! Outgoing contracts are additionally tracked by a persistent timelock sweep schedule ([TimelockSweep]).
+//! The schedule is saved to disk without any key, survives restarts, and keeps retrying the refund spend
+//! until the contract output is spent in a block. The refund starts at the node's feerate estimate, and its
+//! fee is bumped if it doesn't confirm in time.
+//!
+//! Incoming contracts are registered before the preimage is known. As soon as the preimage is revealed,
+//! either through the protocol ([Watchtower::reveal_preimage]) or observed on chain in the witness of
+//! any hashlock spend, the matching incoming contracts are claimed via hashlock.
+
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    fs::{self, File},
+    io::BufWriter,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering::Relaxed},
+        Mutex, MutexGuard,
+    },
+    thread,
+};
+
+use bitcoin::{
+    bip32::{DerivationPath, Xpriv},
+    hashes::Hash,
+    secp256k1::Secp256k1,
+    Address, Amount, OutPoint, ScriptBuf, Transaction, Txid,
+};
+use bitcoind::bitcoincore_rpc::Client;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    protocol::{contract::read_hashvalue_from_contract, messages::Preimage, Hash160},
+    utill::HEART_BEAT_INTERVAL,
+    wallet::{
+        create_timelock_spend_tx, BlockchainBackend, IncomingSwapCoin, OutgoingSwapCoin, RPCConfig,
+        SwapCoin, WalletError, TIMELOCK_SPEND_VSIZE,
+    },
+};
+
+/// Name of the file storing the timelock sweep schedule, inside the data directory.
+pub const SWEEP_SCHEDULE_FILE: &str = "timelock_sweeps.cbor";
+
+/// Feerate of a timelock sweep when the node has no estimate, in sats/vbyte.
+const SWEEP_FALLBACK_FEERATE: u64 = 2;
+
+/// Maximum feerate a timelock sweep is bumped to, in sats/vbyte.
+const SWEEP_MAX_FEERATE: u64 = 200;
+
+/// Confirmation target, in blocks, of the feerate estimate a timelock sweep starts at.
+const SWEEP_CONF_TARGET: u16 = 3;
+
+/// Blocks to wait for a broadcasted sweep to confirm, before bumping its fee.
+const SWEEP_FEE_BUMP_INTERVAL: u64 = 6;
+
+/// The spending branch used to claim a watched contract.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClaimPath {
+    /// Claim via the hashlock branch. Broadcasted as soon as the contract confirms.
+    Hashlock,
+    /// Claim via the timelock branch, after the given relative locktime (in blocks) has matured.
+    Timelock(u16),
+}
+
+/// A contract transaction watched by the [Watchtower], and its fully signed claim transaction.
+#[derive(Debug, Clone)]
+pub struct WatchedContract {
+    /// The contract transaction.
+    pub contract_tx: Transaction,
+    /// The branch used by the claim transaction.
+    pub claim_path: ClaimPath,
+    /// Fully signed transaction spending the contract output.
+    pub claim_tx: Transaction,
+}
+
+impl WatchedContract {
+    /// Returns true if the claim transaction can be broadcasted at the given contract confirmation depth.
+    pub(crate) fn is_claimable(&self, confirmations: u32) -> bool {
+        match self.claim_path {
+            ClaimPath::Hashlock => confirmations > 0,
+            ClaimPath::Timelock(locktime) => confirmations >= locktime as u32,
+        }
+    }
+}
+
+/// A scheduled refund of an outgoing contract via its timelock branch.
+///
+/// No key is kept: the timelock key is derived again from the wallet's swap account key at each attempt.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimelockSweep {
+    /// The contract output.
+    pub(crate) outpoint: OutPoint,
+    /// Value of the contract output.
+    pub(crate) amount: Amount,
+    /// Redeemscript of the contract.
+    pub(crate) contract_redeemscript: ScriptBuf,
+    /// Path of the timelock key, under [SWAP_ACCOUNT_PATH](crate::wallet::SWAP_ACCOUNT_PATH).
+    pub(crate) key_path: DerivationPath,
+    /// Relative locktime of the contract, in blocks.
+    pub(crate) locktime: u16,
+    /// Script receiving the swept funds.
+    pub(crate) destination: ScriptBuf,
+    /// Feerate of the latest sweep attempt, in sats/vbyte.
+    pub(crate) feerate: u64,
+    /// Block height at which the latest sweep attempt was accepted by the node.
+    pub(crate) broadcast_height: Option<u64>,
+    /// Number of broadcast attempts made so far.
+    pub(crate) attempts: u32,
+}
+
+impl TimelockSweep {
+    /// Sweep of an outgoing contract, whose timelock key is at `key_path` in the wallet.
+    pub(crate) fn new(
+        swapcoin: &OutgoingSwapCoin,
+        key_path: DerivationPath,
+        destination: &Address,
+    ) -> Result<Self, WalletError> {
+        Ok(Self {
+            outpoint: OutPoint::new(swapcoin.contract_tx.compute_txid(), 0),
+            amount: swapcoin.contract_tx.output[0].value,
+            contract_redeemscript: swapcoin.contract_redeemscript.clone(),
+            key_path,
+            locktime: swapcoin.get_timelock()?,
+            destination: destination.script_pubkey(),
+            feerate: SWEEP_FALLBACK_FEERATE,
+            broadcast_height: None,
+            attempts: 0,
+        })
+    }
+
+    /// Returns true if a previously accepted sweep should be replaced with a higher fee one.
+    fn needs_fee_bump(&self, current_height: u64) -> bool {
+        match self.broadcast_height {
+            Some(height) => {
+                current_height >= height + SWEEP_FEE_BUMP_INTERVAL
+                    && self.feerate < SWEEP_MAX_FEERATE
+            }
+            None => false,
+        }
+    }
+
+    fn sweep_tx(&self, account_key: &Xpriv) -> Result<Transaction, WalletError> {
+        let timelock_privkey = account_key
+            .derive_priv(&Secp256k1::new(), &self.key_path)?
+            .private_key;
+        create_timelock_spend_tx(
+            self.outpoint,
+            self.amount,
+            &self.contract_redeemscript,
+            &timelock_privkey,
+            &self.destination,
+            Amount::from_sat(TIMELOCK_SPEND_VSIZE * self.feerate),
+        )
+    }
+}
+
+/// The Watchtower. Watches a set of contract transactions and broadcasts their claims when possible.
+///
+/// Runs against Bitcoin Core by default. Any other [BlockchainBackend] can be used with
+/// [Watchtower::with_backend], like the in-memory `MockBlockchain` in tests.
+#[derive(Debug)]
+pub struct Watchtower<B: BlockchainBackend = Client> {
+    backend: B,
+    contracts: Mutex<HashMap<Txid, WatchedContract>>,
+    sweeps: Mutex<HashMap<Txid, TimelockSweep>>,
+    sweep_file: PathBuf,
+    /// Swap account key of the wallet, to sign the timelock sweeps with.
+    account_key: Xpriv,
+    /// Incoming contracts waiting for their preimage, and the address to claim them to.
+    incomings: Mutex<HashMap<Txid, (IncomingSwapCoin, Address)>>,
+    /// Last block scanned for revealed preimages.
+    last_scanned_height: Mutex<Option<u64>>,
+    shutdown: AtomicBool,
+}
+
+impl Watchtower {
+    /// Creates a new watchtower connected to the given bitcoin node, signing the timelock sweeps with
+    /// the swap account key of the wallet.
+    ///
+    /// Any timelock sweep schedule previously saved in `data_dir` is loaded back.
+    pub fn new(
+        rpc_config: &RPCConfig,
+        data_dir: &Path,
+        account_key: Xpriv,
+    ) -> Result<Self, WalletError> {
+        Self::with_backend(Client::try_from(rpc_config)?, data_dir, account_key)
+    }
+}
+
+impl<B: BlockchainBackend> Watchtower<B> {
+    /// Creates a new watchtower on top of any [BlockchainBackend].
+    ///
+    /// Any timelock sweep schedule previously saved in `data_dir` is loaded back.
+    pub fn with_backend(
+        backend: B,
+        data_dir: &Path,
+        account_key: Xpriv,
+    ) -> Result<Self, WalletError> {
+        let sweep_file = data_dir.join(SWEEP_SCHEDULE_FILE);
+        let sweeps = if sweep_file.exists() {
+            let sweeps: HashMap<Txid, TimelockSweep> =
+                serde_cbor::from_slice(&fs::read(&sweep_file)?)?;
+            log::info!("Loaded {} scheduled timelock sweeps", sweeps.len());
+            sweeps
+        } else {
+            HashMap::new()
+        };
+        Ok(Self {
+            backend,
+            contracts: Mutex::new(HashMap::new()),
+            sweeps: Mutex::new(sweeps),
+            sweep_file,
+            account_key,
+            incomings: Mutex::new(HashMap::new()),
+            last_scanned_height: Mutex::new(None),
+            shutdown: AtomicBool::new(false),
+        })
+    }
+
+    fn lock_sweeps(&self) -> Result<MutexGuard<'_, HashMap<Txid, TimelockSweep>>, WalletError> {
+        self.sweeps
+            .lock()
+            .map_err(|_| WalletError::General("Watchtower mutex poisoned".to_string()))
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn lock_incomings(
+        &self,
+    ) -> Result<MutexGuard<'_, HashMap<Txid, (IncomingSwapCoin, Address)>>, WalletError> {
+        self.incomings
+            .lock()
+            .map_err(|_| WalletError::General("Watchtower mutex poisoned".to_string()))
+    }
+
+    fn save_sweeps(&self, sweeps: &HashMap<Txid, TimelockSweep>) -> Result<(), WalletError> {
+        if let Some(parent) = self.sweep_file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let writer = BufWriter::new(File::create(&self.sweep_file)?);
+        Ok(serde_cbor::to_writer(writer, sweeps)?)
+    }
+
+    /// Schedule the timelock refund of an outgoing contract.
+    ///
+    /// The schedule is persisted immediately, so the refund will be attempted even after a restart.
+    pub(crate) fn schedule_timelock_sweep(&self, sweep: TimelockSweep) -> Result<(), WalletError> {
+        log::info!(
+            "Scheduling timelock sweep for contract {} | timelock {}",
+            sweep.outpoint.txid,
+            sweep.locktime
+        );
+        let mut sweeps = self.lock_sweeps()?;
+        sweeps.insert(sweep.outpoint.txid, sweep);
+        self.save_sweeps(&sweeps)
+    }
+
+    /// Feerate to sweep at, in sats/vbyte: the node's estimate, at least `min_feerate`.
+    fn sweep_feerate(&self, min_feerate: u64) -> u64 {
+        let estimate = match self.backend.estimate_feerate(SWEEP_CONF_TARGET) {
+            Ok(Some(feerate)) => feerate.ceil() as u64,
+            Ok(None) => SWEEP_FALLBACK_FEERATE,
+            Err(e) => {
+                log::warn!("No feerate estimate for the timelock sweeps: {:?}", e);
+                SWEEP_FALLBACK_FEERATE
+            }
+        };
+        estimate.max(min_feerate).min(SWEEP_MAX_FEERATE)
+    }
+
+    /// List of all currently scheduled timelock sweeps.
+    pub fn scheduled_sweeps(&self) -> Result<Vec<TimelockSweep>, WalletError> {
+        Ok(self.lock_sweeps()?.values().cloned().collect())
+    }
+
+    fn lock_contracts(
+        &self,
+    ) -> Result<MutexGuard<'_, HashMap<Txid, WatchedContract>>, WalletError> {
+        self.contracts
+            .lock()
+            .map_err(|_| WalletError::General("Watchtower mutex poisoned".to_string()))
+    }
+
+    /// Start watching a contract. Replaces any existing entry for the same contract.
+    pub fn watch(&self, contract: WatchedContract) -> Result<(), WalletError> {
+        let txid = contract.contract_tx.compute_txid();
+        log::info!(
+            "Watchtower watching contract {} | claim path {:?}",
+            txid,
+            contract.claim_path
+        );
+        self.lock_contracts()?.insert(txid, contract);
+        Ok(())
+    }
+
+    /// Register an incoming contract, to be claimed via hashlock once its preimage is revealed.
+    pub(crate) fn register_incoming(
+        &self,
+        swapcoin: IncomingSwapCoin,
+        destination: &Address,
+    ) -> Result<(), WalletError> {
+        let txid = swapcoin.contract_tx.compute_txid();
+        log::info!("Watchtower waiting for preimage of contract {}", txid);
+        self.lock_incomings()?
+            .insert(txid, (swapcoin, destination.clone()));
+        Ok(())
+    }
+
+    /// Claim all registered incoming contracts locked to the hash of `preimage`.
+    ///
+    /// The hashlock claims are handed over to the watched contracts, and broadcasted right away if
+    /// the contracts are already confirmed. Returns the txids of the matched contracts.
+    pub fn reveal_preimage(&self, preimage: &Preimage) -> Result<Vec<Txid>, WalletError> {
+        let hashvalue = Hash160::hash(preimage);
+        let mut matched = Vec::new();
+        {
+            let mut incomings = self.lock_incomings()?;
+            for (txid, (swapcoin, destination)) in incomings.iter() {
+                if read_hashvalue_from_contract(&swapcoin.contract_redeemscript)? != hashvalue {
+                    continue;
+                }
+                self.watch(WatchedContract {
+                    contract_tx: swapcoin.contract_tx.clone(),
+                    claim_path: ClaimPath::Hashlock,
+                    claim_tx: swapcoin.create_hashlock_spend(destination, preimage)?,
+                })?;
+                matched.push(*txid);
+            }
+            incomings.retain(|txid, _| !matched.contains(txid));
+        }
+
+        if !matched.is_empty() {
+            log::info!(
+                "Preimage revealed for hashvalue {} | claiming contracts {:?}",
+                hashvalue,
+                matched
+            );
+            self.check_contracts()?;
+        }
+        Ok(matched)
+    }
+
+    /// Scan new blocks for hashlock spends revealing the preimage of any registered incoming contract.
+    ///
+    /// Returns the txids of the incoming contracts matched by the revealed preimages.
+    pub fn scan_for_revealed_preimages(&self) -> Result<Vec<Txid>, WalletError> {
+        let hashvalues = self
+            .lock_incomings()?
+            .values()
+            .map(|(swapcoin, _)| read_hashvalue_from_contract(&swapcoin.contract_redeemscript))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let tip = self.backend.block_count()?;
+        let mut last_scanned = self
+            .last_scanned_height
+            .lock()
+            .map_err(|_| WalletError::General("Watchtower mutex poisoned".to_string()))?;
+        let from = last_scanned.map_or(tip, |h| h + 1);
+        *last_scanned = Some(tip);
+        drop(last_scanned);
+
+        if hashvalues.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut preimages = Vec::new();
+        for height in from..=tip {
+            let txs = self.backend.block_txs(height)?;
+            // A hashlock spend witness is [sig, preimage, contract_redeemscript].
+            for input in txs.iter().flat_map(|tx| tx.input.iter()) {
+                for item in input.witness.iter() {
+                    if let Ok(preimage) = Preimage::try_from(item) {
+                        if hashvalues.contains(&Hash160::hash(&preimage)) {
+                            preimages.push(preimage);
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut matched = Vec::new();
+        for preimage in preimages {
+            matched.extend(self.reveal_preimage(&preimage)?);
+        }
+        Ok(matched)
+    }
+
+    /// Stop watching a contract. Also cancels its scheduled timelock sweep, if any.
+    pub fn unwatch(&self, contract_txid: &Txid) -> Result<(), WalletError> {
+        self.lock_contracts()?.remove(contract_txid);
+        self.lock_incomings()?.remove(contract_txid);
+        let mut sweeps = self.lock_sweeps()?;
+        if sweeps.remove(contract_txid).is_some() {
+            self.save_sweeps(&sweeps)?;
+        }
+        Ok(())
+    }
+
+    /// List of all contract txids currently watched.
+    pub fn watched_txids(&self) -> Result<Vec<Txid>, WalletError> {
+        Ok(self.lock_contracts()?.keys().cloned().collect())
+    }
+
+    /// Single pass over all watched contracts. Broadcasts every claim that is ready,
+    /// and returns the txids of the broadcasted claim transactions.
+    pub fn check_contracts(&self) -> Result<Vec<Txid>, WalletError> {
+        let watched = self.lock_contracts()?.values().cloned().collect::<Vec<_>>();
+        let mut claimed = Vec::new();
+
+        for contract in watched {
+            let contract_txid = contract.contract_tx.compute_txid();
+
+            // Contract isn't broadcasted. Nothing to do.
+            let confirmations = match self.backend.tx_confirmations(&contract_txid)? {
+                Some(confirmations) => confirmations,
+                None => continue,
+            };
+
+            if !contract.is_claimable(confirmations) {
+                log::debug!(
+                    "Watched contract {} at {} confirmations | claim path {:?}",
+                    contract_txid,
+                    confirmations,
+                    contract.claim_path
+                );
+                continue;
+            }
+
+            // Contract is always one-input-one-output.
+            if !self
+                .backend
+                .is_unspent(&OutPoint::new(contract_txid, 0), true)?
+            {
+                log::info!(
+                    "Contract output {} already spent. Removing from watchtower",
+                    contract_txid
+                );
+                self.lock_contracts()?.remove(&contract_txid);
+                continue;
+            }
+
+            match self.backend.broadcast(&contract.claim_tx) {
+                Ok(txid) => {
+                    log::info!(
+                        "Watchtower broadcasted {:?} claim {} for contract {}",
+                        contract.claim_path,
+                        txid,
+                        contract_txid
+                    );
+                    self.lock_contracts()?.remove(&contract_txid);
+                    claimed.push(txid);
+                }
+                Err(e) => {
+                    log::error!(
+                        "Watchtower failed to broadcast claim for contract {} | {:?}",
+                        contract_txid,
+                        e
+                    );
+                }
+            }
+        }
+
+        Ok(claimed)
+    }
+
+    /// Single pass over the timelock sweep schedule.
+    ///
+    /// Broadcasts the refund of every matured contract, and replaces it with a higher fee version
+    /// if it is still unconfirmed after [SWEEP_FEE_BUMP_INTERVAL] blocks. A sweep is removed from the
+    /// schedule once the contract output is spent in a block. Returns the txids of the broadcasted sweeps.
+    pub fn process_timelock_sweeps(&self) -> Result<Vec<Txid>, WalletError> {
+        let mut sweeps = self.lock_sweeps()?;
+        if sweeps.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let current_height = self.backend.block_count()?;
+        let mut broadcasted = Vec::new();
+        let mut completed = Vec::new();
+
+        for (contract_txid, sweep) in sweeps.iter_mut() {
+            // Contract isn't broadcasted. Nothing to do.
+            let confirmations = match self.backend.tx_confirmations(contract_txid)? {
+                Some(confirmations) => confirmations,
+                None => continue,
+            };
+
+            if confirmations < sweep.locktime as u32 {
+                continue;
+            }
+
+            // Spent in a block, either by our sweep or by the counterparty. Nothing more to do.
+            if !self
+                .backend
+                .is_unspent(&OutPoint::new(*contract_txid, 0), false)?
+            {
+                log::info!(
+                    "Contract {} output spent. Timelock sweep completed",
+                    contract_txid
+                );
+                completed.push(*contract_txid);
+                continue;
+            }
+
+            if sweep.broadcast_height.is_some() {
+                if !sweep.needs_fee_bump(current_height) {
+                    continue;
+                }
+                sweep.feerate = self.sweep_feerate(sweep.feerate * 2);
+                log::info!(
+                    "Timelock sweep of contract {} unconfirmed. Bumping feerate to {} sats/vb",
+                    contract_txid,
+                    sweep.feerate
+                );
+            } else {
+                sweep.feerate = self.sweep_feerate(0);
+            }
+
+            sweep.attempts += 1;
+            match self.backend.broadcast(&sweep.sweep_tx(&self.account_key)?) {
+                Ok(txid) => {
+                    log::info!(
+                        "Broadcasted timelock sweep {} for contract {} | attempt {}",
+                        txid,
+                        contract_txid,
+                        sweep.attempts
+                    );
+                    sweep.broadcast_height = Some(current_height);
+                    broadcasted.push(txid);
+                }
+                Err(e) => {
+                    // Retried at next beat.
+                    log::error!(
+                        "Failed to broadcast timelock sweep for contract {} | attempt {} | {:?}",
+                        contract_txid,
+                        sweep.attempts,
+                        e
+                    );
+                }
+            }
+        }
+
+        for txid in completed {
+            sweeps.remove(&txid);
+        }
+        self.save_sweeps(&sweeps)?;
+
+        Ok(broadcasted)
+    }
+
+    /// Keep checking the watched contracts at every heart beat, until [Watchtower::stop] is called.
+    pub fn run(&self) {
+        log::info!("Watchtower started");
+        while !self.shutdown.load(Relaxed) {
+            if let Err(e) = self.check_contracts() {
+                log::error!("Watchtower error: {:?}", e);
+            }
+            if let Err(e) = self.process_timelock_sweeps() {
+                log::error!("Timelock sweep error: {:?}", e);
+            }
+            if let Err(e) = self.scan_for_revealed_preimages() {
+                log::error!("Preimage scan error: {:?}", e);
+            }
+            thread::sleep(HEART_BEAT_INTERVAL);
+        }
+        log::info!("Watchtower stopped");
+    }
+
+    /// Signal the watchtower loop to stop.
+    pub fn stop(&self) {
+        self.shutdown.store(true, Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{protocol::contract::create_contract_redeemscript, wallet::MockBlockchain};
+    use bitcoin::{
+        absolute::LockTime, secp256k1::SecretKey, transaction::Version, Network, PublicKey,
+        Sequence, TxIn, TxOut,
+    };
+    use bitcoind::tempfile::tempdir;
+    use std::str::FromStr;
+
+    fn account_key() -> Xpriv {
+        Xpriv::new_master(Network::Regtest, &[7; 32]).unwrap()
+    }
+
+    fn spend(outpoint: OutPoint, sequence: Sequence) -> Transaction {
+        Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: outpoint,
+                sequence,
+                ..Default::default()
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(10_000),
+                script_pubkey: ScriptBuf::new(),
+            }],
+        }
+    }
+
+    #[test]
+    fn test_claimability() {
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![],
+            output: vec![],
+        };
+        let hashlock = WatchedContract {
+            contract_tx: tx.clone(),
+            claim_path: ClaimPath::Hashlock,
+            claim_tx: tx.clone(),
+        };
+        assert!(!hashlock.is_claimable(0));
+        assert!(hashlock.is_claimable(1));
+
+        let timelock = WatchedContract {
+            contract_tx: tx.clone(),
+            claim_path: ClaimPath::Timelock(20),
+            claim_tx: tx,
+        };
+        assert!(!timelock.is_claimable(19));
+        assert!(timelock.is_claimable(20));
+    }
+
+    #[test]
+    fn test_timelock_claim_on_mock_chain() {
+        let temp_dir = tempdir().unwrap();
+        let watchtower =
+            Watchtower::with_backend(MockBlockchain::new(), temp_dir.path(), account_key())
+                .unwrap();
+
+        let contract_tx = spend(OutPoint::null(), Sequence::MAX);
+        let contract_txid = contract_tx.compute_txid();
+        let claim_tx = spend(OutPoint::new(contract_txid, 0), Sequence::from_height(2));
+        watchtower
+            .watch(WatchedContract {
+                contract_tx: contract_tx.clone(),
+                claim_path: ClaimPath::Timelock(2),
+                claim_tx: claim_tx.clone(),
+            })
+            .unwrap();
+
+        // Contract not broadcasted yet.
+        assert!(watchtower.check_contracts().unwrap().is_empty());
+
+        watchtower.backend.broadcast(&contract_tx).unwrap();
+        watchtower.backend.mine(1).unwrap();
+        assert!(watchtower.check_contracts().unwrap().is_empty());
+
+        watchtower.backend.mine(1).unwrap();
+        assert_eq!(
+            watchtower.check_contracts().unwrap(),
+            vec![claim_tx.compute_txid()]
+        );
+        assert!(watchtower.watched_txids().unwrap().is_empty());
+        assert_eq!(
+            watchtower.backend.mempool_txids().unwrap(),
+            vec![claim_tx.compute_txid()]
+        );
+    }
+
+    #[test]
+    fn test_timelock_sweep_schedule() {
+        let temp_dir = tempdir().unwrap();
+        let chain = MockBlockchain::new();
+        chain.set_feerate_estimate(Some(9.2)).unwrap();
+        let watchtower = Watchtower::with_backend(chain, temp_dir.path(), account_key()).unwrap();
+
+        let secp = Secp256k1::new();
+        let key_path = DerivationPath::from_str("m/1/0'").unwrap();
+        let timelock_privkey = account_key()
+            .derive_priv(&secp, &key_path)
+            .unwrap()
+            .private_key;
+        let pubkey = |privkey: &SecretKey| PublicKey {
+            compressed: true,
+            inner: privkey.public_key(&secp),
+        };
+        let contract_redeemscript = create_contract_redeemscript(
+            &pubkey(&SecretKey::from_slice(&[1; 32]).unwrap()),
+            &pubkey(&timelock_privkey),
+            &Hash160::hash(&[0; 32]),
+            &2,
+        );
+        let contract_tx = spend(OutPoint::null(), Sequence::MAX);
+        let swapcoin = OutgoingSwapCoin::new(
+            SecretKey::from_slice(&[2; 32]).unwrap(),
+            pubkey(&SecretKey::from_slice(&[3; 32]).unwrap()),
+            contract_tx.clone(),
+            contract_redeemscript,
+            timelock_privkey,
+            Amount::from_sat(10_000),
+        )
+        .unwrap();
+        let destination = Address::p2wsh(&ScriptBuf::new(), Network::Regtest);
+        watchtower
+            .schedule_timelock_sweep(TimelockSweep::new(&swapcoin, key_path, &destination).unwrap())
+            .unwrap();
+
+        // The schedule is saved without the key, and loaded back.
+        let saved = fs::read(temp_dir.path().join(SWEEP_SCHEDULE_FILE)).unwrap();
+        assert!(!saved
+            .windows(32)
+            .any(|window| window == &timelock_privkey.secret_bytes()[..]));
+        let reloaded =
+            Watchtower::with_backend(MockBlockchain::new(), temp_dir.path(), account_key())
+                .unwrap();
+        assert_eq!(
+            reloaded.scheduled_sweeps().unwrap(),
+            watchtower.scheduled_sweeps().unwrap()
+        );
+
+        watchtower.backend.broadcast(&contract_tx).unwrap();
+        watchtower.backend.mine(1).unwrap();
+        assert!(watchtower.process_timelock_sweeps().unwrap().is_empty());
+
+        // Swept at maturity, at the node's estimate, with the key derived again.
+        watchtower.backend.mine(1).unwrap();
+        let broadcasted = watchtower.process_timelock_sweeps().unwrap();
+        let expected = swapcoin
+            .create_timelock_spend_with_fee(
+                &destination.script_pubkey(),
+                Amount::from_sat(TIMELOCK_SPEND_VSIZE * 10),
+            )
+            .unwrap();
+        assert_eq!(broadcasted, vec![expected.compute_txid()]);
+        assert_eq!(watchtower.scheduled_sweeps().unwrap()[0].feerate, 10);
+
+        // Removed once the sweep confirms.
+        watchtower.backend.mine(1).unwrap();
+        assert!(watchtower.process_timelock_sweeps().unwrap().is_empty());
+        assert!(watchtower.scheduled_sweeps().unwrap().is_empty());
+    }
+}
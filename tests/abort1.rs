@@ -108,7 +108,7 @@ fn test_stop_taker_after_setup() {
         send_amount: Amount::from_sat(500000),
         maker_count: 2,
         tx_count: 3,
-        required_confirms: 1,
+        ..Default::default()
     };
     taker.do_coinswap(swap_params).unwrap();
 
@@ -105,7 +105,7 @@ fn test_abort_case_2_move_on_with_other_makers() {
         send_amount: Amount::from_sat(500000),
         maker_count: 2,
         tx_count: 3,
-        required_confirms: 1,
+        ..Default::default()
     };
     taker.do_coinswap(swap_params).unwrap();
 
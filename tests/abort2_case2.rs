@@ -10,9 +10,9 @@ mod test_framework;
 use test_framework::*;
 
 use log::{info, warn};
-use std::{
-    fs::File, io::Read, path::PathBuf, sync::atomic::Ordering::Relaxed, thread, time::Duration,
-};
+#[cfg(feature = "tor")]
+use std::{fs::File, io::Read, path::PathBuf};
+use std::{sync::atomic::Ordering::Relaxed, thread, time::Duration};
 
 /// ABORT 2: Maker Drops Before Setup
 /// This test demonstrates the situation where a Maker prematurely drops connections after doing
@@ -111,7 +111,7 @@ fn test_abort_case_2_recover_if_no_makers_found() {
         send_amount: Amount::from_sat(500000),
         maker_count: 2,
         tx_count: 3,
-        required_confirms: 1,
+        ..Default::default()
     };
 
     if let Err(e) = taker.do_coinswap(swap_params) {
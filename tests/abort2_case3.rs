@@ -9,9 +9,9 @@ use std::sync::Arc;
 mod test_framework;
 use coinswap::taker::TakerBehavior;
 use log::{info, warn};
-use std::{
-    fs::File, io::Read, path::PathBuf, sync::atomic::Ordering::Relaxed, thread, time::Duration,
-};
+#[cfg(feature = "tor")]
+use std::{fs::File, io::Read, path::PathBuf};
+use std::{sync::atomic::Ordering::Relaxed, thread, time::Duration};
 use test_framework::*;
 
 /// ABORT 2: Maker Drops Before Setup
@@ -108,7 +108,7 @@ fn maker_drops_after_sending_senders_sigs() {
         send_amount: Amount::from_sat(500000),
         maker_count: 2,
         tx_count: 3,
-        required_confirms: 1,
+        ..Default::default()
     };
     taker.do_coinswap(swap_params).unwrap();
 
@@ -8,10 +8,9 @@ use coinswap::{
 
 mod test_framework;
 use log::{info, warn};
+#[cfg(feature = "tor")]
+use std::{fs::File, io::Read, path::PathBuf};
 use std::{
-    fs::File,
-    io::Read,
-    path::PathBuf,
     sync::{atomic::Ordering::Relaxed, Arc},
     thread,
     time::Duration,
@@ -112,7 +111,7 @@ fn abort3_case1_close_at_contract_sigs_for_recvr_and_sender() {
         send_amount: Amount::from_sat(500000),
         maker_count: 2,
         tx_count: 3,
-        required_confirms: 1,
+        ..Default::default()
     };
     taker.do_coinswap(swap_params).unwrap();
 
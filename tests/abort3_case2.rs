@@ -11,9 +11,9 @@ mod test_framework;
 use test_framework::*;
 
 use log::{info, warn};
-use std::{
-    fs::File, io::Read, path::PathBuf, sync::atomic::Ordering::Relaxed, thread, time::Duration,
-};
+#[cfg(feature = "tor")]
+use std::{fs::File, io::Read, path::PathBuf};
+use std::{sync::atomic::Ordering::Relaxed, thread, time::Duration};
 
 /// ABORT 3: Maker Drops After Setup
 /// Case 2: CloseAtContractSigsForRecvr
@@ -106,7 +106,7 @@ fn abort3_case2_close_at_contract_sigs_for_recvr() {
         send_amount: Amount::from_sat(500000),
         maker_count: 2,
         tx_count: 3,
-        required_confirms: 1,
+        ..Default::default()
     };
     taker.do_coinswap(swap_params).unwrap();
 
@@ -0,0 +1,18 @@
+#![cfg(feature = "integration-test")]
+mod test_framework;
+use test_framework::*;
+
+use log::warn;
+
+/// Number of swap rounds attempted in the soak test.
+const SOAK_ROUNDS: u64 = 5;
+
+/// Byzantine Soak: the Taker swaps with Makers randomly misbehaving for several rounds, see [byzantine_soak].
+///
+/// This is long-running, run it explicitly with `--ignored`. `tests/byzantine_soak_short.rs` runs a single round.
+#[test]
+#[ignore]
+fn byzantine_makers_soak() {
+    warn!("Running Test: Taker swaps with byzantine makers");
+    byzantine_soak(SOAK_ROUNDS);
+}
@@ -0,0 +1,12 @@
+#![cfg(feature = "integration-test")]
+mod test_framework;
+use test_framework::*;
+
+use log::warn;
+
+/// A single round of the Byzantine Soak, see [byzantine_soak].
+#[test]
+fn byzantine_makers_soak_short() {
+    warn!("Running Test: Taker swaps once with byzantine makers");
+    byzantine_soak(1);
+}
@@ -0,0 +1,302 @@
+#![cfg(feature = "integration-test")]
+use bitcoin::Amount;
+use coinswap::{
+    maker::{start_maker_server, Maker, MakerBehavior},
+    protocol::messages::MessageType,
+    taker::TakerBehavior,
+    utill::ConnectionType,
+};
+use std::sync::Arc;
+
+mod test_framework;
+use test_framework::{
+    chaos::*,
+    proxy::{splitmix64, Faults},
+    *,
+};
+
+use log::{info, warn};
+use std::{
+    env,
+    sync::atomic::Ordering::Relaxed,
+    thread::{self, JoinHandle},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// Messages a participant can be killed at, from the contract signatures to the private key handover.
+const KILL_POINTS: [MessageType; 6] = [
+    MessageType::ReqContractSigsForSender,
+    MessageType::RespProofOfFunding,
+    MessageType::RespContractSigsForRecvrAndSender,
+    MessageType::ReqContractSigsForRecvr,
+    MessageType::RespHashPreimage,
+    MessageType::RespPrivKeyHandover,
+];
+
+/// The participant killed by a [ChaosPlan].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ChaosTarget {
+    /// The Taker.
+    Taker,
+    /// The first Maker of the route the Taker sends the kill message to.
+    Maker,
+}
+
+/// Who to kill, and when, drawn from a seed. See [test_chaos_restart].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ChaosPlan {
+    /// Seed the plan was drawn from.
+    seed: u64,
+    /// The killed participant.
+    target: ChaosTarget,
+    /// The Taker message the participant is killed at, before it's delivered.
+    kill_at: MessageType,
+}
+
+impl ChaosPlan {
+    /// Draws a plan from `seed`.
+    fn from_seed(seed: u64) -> Self {
+        let mut state = seed;
+        let target = match splitmix64(&mut state) % 2 {
+            0 => ChaosTarget::Taker,
+            _ => ChaosTarget::Maker,
+        };
+        let kill_at = KILL_POINTS[(splitmix64(&mut state) % KILL_POINTS.len() as u64) as usize];
+        Self {
+            seed,
+            target,
+            kill_at,
+        }
+    }
+
+    /// The seed in `COINSWAP_CHAOS_SEED`, or a random one.
+    fn seed_from_env() -> u64 {
+        env::var("COINSWAP_CHAOS_SEED")
+            .ok()
+            .and_then(|seed| seed.parse().ok())
+            .unwrap_or_else(|| {
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap()
+                    .as_nanos() as u64
+            })
+    }
+
+    /// Faults severing a link at the kill point.
+    fn faults(&self) -> Faults {
+        Faults {
+            sever_at: Some(self.kill_at),
+            seed: self.seed,
+            ..Default::default()
+        }
+    }
+}
+
+/// Network ports of the Makers. Their proxies listen 100 ports above.
+const MAKER_PORTS: [u16; 2] = [6102, 16102];
+
+/// Upper bound of what a participant can lose in a killed swap: funding fees, contract fees and timelock
+/// recovery fees of all its funding transactions.
+const MAX_MINER_FEES: Amount = Amount::from_sat(10_000);
+
+/// How long a killed Maker stays down.
+const DOWNTIME: Duration = Duration::from_secs(5);
+
+/// Longest the participants may take to recover, including the timelocks maturing.
+const RECOVERY_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+fn start_maker_thread(maker: &Arc<Maker>) -> JoinHandle<()> {
+    let maker_clone = maker.clone();
+    thread::spawn(move || {
+        start_maker_server(maker_clone).unwrap();
+    })
+}
+
+/// Process-Restart Chaos: a Maker or the Taker is killed at a random protocol message, and restarted from its
+/// data directory. Whatever was in flight, once the swap is recovered and the timelocks matured, no participant
+/// may have lost more than the miner fees it paid.
+///
+/// Each run draws a new [ChaosPlan]. Replay a failing run with the seed it logs, in `COINSWAP_CHAOS_SEED`.
+#[test]
+fn test_chaos_restart() {
+    // ---- Setup ----
+
+    let plan = ChaosPlan::from_seed(ChaosPlan::seed_from_env());
+
+    let makers_config_map = MAKER_PORTS
+        .iter()
+        .map(|port| ((*port, None), MakerBehavior::Normal))
+        .collect();
+
+    let (
+        test_framework,
+        mut taker,
+        mut makers,
+        directory_server_instance,
+        block_generation_handle,
+        proxies,
+    ) = TestFramework::init_with_proxies(
+        makers_config_map,
+        TakerBehavior::Normal,
+        ConnectionType::CLEARNET,
+        MAKER_PORTS.iter().map(|port| (*port, port + 100)).collect(),
+    );
+
+    warn!(
+        "Running Test: Chaos restart, {:?}. Replay with COINSWAP_CHAOS_SEED={}",
+        plan, plan.seed
+    );
+
+    // Fund the Taker with 3 utxos of 0.05 btc each, and the Makers with 4 utxos of 0.05 btc each.
+    test_framework.fund_participants(
+        vec![Participant::Taker(&mut taker)],
+        Amount::from_btc(0.05).unwrap(),
+        3,
+    );
+    test_framework.fund_participants(
+        makers
+            .iter()
+            .map(|maker| Participant::Maker(maker))
+            .collect(),
+        Amount::from_btc(0.05).unwrap(),
+        4,
+    );
+
+    //  Start the Maker Server threads
+    log::info!("Initiating Maker...");
+
+    let mut maker_threads = makers.iter().map(start_maker_thread).collect::<Vec<_>>();
+
+    // Makers take time to fully setup.
+    for maker in &makers {
+        while !maker.is_setup_complete.load(Relaxed) {
+            log::info!("Waiting for maker setup completion");
+            // Introduce a delay of 10 seconds to prevent write lock starvation.
+            thread::sleep(Duration::from_secs(10));
+        }
+    }
+
+    let org_taker_spend_balance = Participant::Taker(&mut taker).balances().1;
+    let org_maker_spend_balances = makers
+        .iter()
+        .map(|maker| Participant::Maker(maker).balances().1)
+        .collect::<Vec<_>>();
+
+    // Sever the links of the killed participant at the kill point.
+    match plan.target {
+        ChaosTarget::Taker => proxies
+            .iter()
+            .for_each(|proxy| proxy.set_faults(plan.faults())),
+        ChaosTarget::Maker => FaultProxy::sever_one_of(&proxies, plan.kill_at),
+    }
+
+    // ---- Swap ----
+
+    // The Taker swaps in a process of its own, to be killed mid-protocol.
+    drop(taker);
+    let mut swap = test_framework.spawn_taker_process(
+        "taker",
+        &["coinswap", "--makers", "2", "--amount", "500000"],
+    );
+
+    // Kill the participant as soon as its link is severed. A killed Maker is restarted after some downtime.
+    let mut killed = false;
+    while !killed && swap.try_wait().unwrap().is_none() {
+        if let Some(index) = proxies.iter().position(FaultProxy::is_severed) {
+            match plan.target {
+                ChaosTarget::Taker => {
+                    warn!("Killing the Taker at {:?}", plan.kill_at);
+                    swap.kill().unwrap();
+                }
+                ChaosTarget::Maker => {
+                    warn!("Killing Maker {} at {:?}", MAKER_PORTS[index], plan.kill_at);
+                    kill_maker(&makers[index], maker_threads.remove(index));
+                    thread::sleep(DOWNTIME);
+
+                    info!("Restarting Maker {}", MAKER_PORTS[index]);
+                    makers[index] =
+                        test_framework.restart_maker(MAKER_PORTS[index], MakerBehavior::Normal);
+                    maker_threads.insert(index, start_maker_thread(&makers[index]));
+                }
+            }
+            proxies
+                .iter()
+                .for_each(|proxy| proxy.set_faults(Faults::default()));
+            killed = true;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    assert!(killed, "The swap ended before reaching {:?}", plan.kill_at);
+
+    // A Taker left alive recovers from the Maker kill on its own.
+    let status = swap.wait().unwrap();
+    info!("Taker process ended: {}", status);
+
+    // Restart the Taker from its data directory.
+    let mut taker = test_framework.start_taker("taker", TakerBehavior::Normal);
+
+    // Claim back anything left in contracts by the killed swap.
+    taker.recover_from_swap().unwrap();
+
+    // ---- Recovery ----
+
+    // Wait for the Makers to recover through the timelocks, while the background miner matures them.
+    let lost_more_than_fees = |before: Amount, after: Amount| after + MAX_MINER_FEES < before;
+    let start = Instant::now();
+    while start.elapsed() < RECOVERY_TIMEOUT {
+        let unrecovered = makers
+            .iter()
+            .zip(&org_maker_spend_balances)
+            .filter(|(maker, before)| {
+                maker.get_wallet().write().unwrap().sync_no_fail();
+                lost_more_than_fees(**before, Participant::Maker(maker).balances().1)
+            })
+            .count();
+        if unrecovered == 0 {
+            break;
+        }
+        info!("Waiting for {} Makers to recover", unrecovered);
+        thread::sleep(Duration::from_secs(10));
+    }
+
+    // After Swap is done,  wait for maker threads to conclude.
+    makers
+        .iter()
+        .for_each(|maker| maker.shutdown.store(true, Relaxed));
+
+    maker_threads
+        .into_iter()
+        .for_each(|thread| thread.join().unwrap());
+
+    proxies.into_iter().for_each(FaultProxy::stop);
+
+    // Shutdown Directory Server
+    directory_server_instance.shutdown.store(true, Relaxed);
+
+    thread::sleep(Duration::from_secs(10));
+
+    // ---- After Recovery Asserts ----
+
+    taker.get_wallet_mut().sync_no_fail();
+    let (taker_balances, taker_spend_balance) = Participant::Taker(&mut taker).balances();
+    assert_eq!(taker_balances.contract, Amount::ZERO);
+    assert_loss_within_fees(
+        "Taker",
+        org_taker_spend_balance,
+        taker_spend_balance,
+        MAX_MINER_FEES,
+    );
+    for ((maker, port), before) in makers.iter().zip(MAKER_PORTS).zip(org_maker_spend_balances) {
+        assert_loss_within_fees(
+            &format!("Maker {}", port),
+            before,
+            Participant::Maker(maker).balances().1,
+            MAX_MINER_FEES,
+        );
+    }
+
+    info!("All checks successful. Terminating integration test case");
+
+    test_framework.stop();
+    block_generation_handle.join().unwrap();
+}
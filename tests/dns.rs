@@ -1,9 +1,9 @@
 #![cfg(feature = "integration-test")]
-use std::{io::Write, net::TcpStream, process::Command, thread, time::Duration};
+use std::{net::TcpStream, process::Command, thread, time::Duration};
 
 mod test_framework;
 
-use coinswap::protocol::DnsRequest;
+use coinswap::{protocol::DnsRequest, utill::send_message};
 use test_framework::{init_bitcoind, start_dns};
 
 fn send_addresses(addresses: &[(&str, u32)]) {
@@ -13,11 +13,7 @@ fn send_addresses(addresses: &[(&str, u32)]) {
             url: address.0.to_string(),
             vout: address.1,
         };
-        let buffer = serde_cbor::ser::to_vec(&request).unwrap();
-        let length = buffer.len() as u32;
-        stream.write_all(&length.to_be_bytes()).unwrap();
-        stream.write_all(&buffer).unwrap();
-        stream.flush().unwrap();
+        send_message(&mut stream, &request).unwrap();
     }
 }
 
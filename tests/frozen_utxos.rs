@@ -0,0 +1,79 @@
+#![cfg(feature = "integration-test")]
+use bitcoin::{Amount, OutPoint};
+use bitcoind::bitcoincore_rpc::json::ListUnspentResultEntry;
+use coinswap::{taker::TakerBehavior, utill::ConnectionType};
+
+mod test_framework;
+use test_framework::*;
+
+use log::{info, warn};
+use std::sync::atomic::Ordering::Relaxed;
+
+/// Outpoints of the coins of a selection.
+fn selected_outpoints<T>(selection: Vec<(ListUnspentResultEntry, T)>) -> Vec<OutPoint> {
+    selection
+        .iter()
+        .map(|(utxo, _)| OutPoint::new(utxo.txid, utxo.vout))
+        .collect()
+}
+
+/// Frozen UTXOs are left out of the coin selection and the spendable balance, and are selected again once
+/// unfrozen.
+#[test]
+fn test_frozen_utxos() {
+    // ---- Setup ----
+
+    let (test_framework, mut taker, _, directory_server_instance, block_generation_handle) =
+        TestFramework::init(Vec::new(), TakerBehavior::Normal, ConnectionType::CLEARNET);
+
+    warn!("Running Test: Freezing and unfreezing a UTXO");
+
+    // Fund the Taker with 3 utxos of 0.05 btc each.
+    test_framework.fund_participants(
+        vec![Participant::Taker(&mut taker)],
+        Amount::from_btc(0.05).unwrap(),
+        3,
+    );
+
+    let wallet = taker.get_wallet_mut();
+    let utxo = wallet.get_all_utxo().unwrap()[0].clone();
+    let outpoint = OutPoint::new(utxo.txid, utxo.vout);
+
+    // ---- Freeze ----
+
+    wallet.freeze(outpoint).unwrap();
+    assert_eq!(wallet.frozen_utxos(), vec![outpoint]);
+
+    let balances = wallet.get_balances(None).unwrap();
+    assert_eq!(balances.regular, Amount::from_btc(0.15).unwrap());
+    assert_eq!(balances.frozen, Amount::from_btc(0.05).unwrap());
+    assert_eq!(balances.spendable, Amount::from_btc(0.10).unwrap());
+
+    // Asking for more than the wallet can spend selects all the coins, but the frozen one.
+    let selection =
+        selected_outpoints(wallet.coin_select(Amount::from_btc(0.12).unwrap()).unwrap());
+    assert_eq!(selection.len(), 2);
+    assert!(!selection.contains(&outpoint));
+
+    // ---- Unfreeze ----
+
+    wallet.unfreeze(outpoint).unwrap();
+    assert!(wallet.frozen_utxos().is_empty());
+    assert!(wallet.unfreeze(outpoint).is_err());
+
+    let balances = wallet.get_balances(None).unwrap();
+    assert_eq!(balances.frozen, Amount::ZERO);
+    assert_eq!(balances.spendable, Amount::from_btc(0.15).unwrap());
+
+    let selection =
+        selected_outpoints(wallet.coin_select(Amount::from_btc(0.12).unwrap()).unwrap());
+    assert_eq!(selection.len(), 3);
+    assert!(selection.contains(&outpoint));
+
+    // Shutdown Directory Server
+    directory_server_instance.shutdown.store(true, Relaxed);
+
+    info!("All checks successful. Terminating integration test case");
+    test_framework.stop();
+    block_generation_handle.join().unwrap();
+}
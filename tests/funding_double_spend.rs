@@ -0,0 +1,215 @@
+#![cfg(feature = "integration-test")]
+use bitcoin::{Amount, OutPoint};
+use bitcoind::bitcoincore_rpc::RpcApi;
+use coinswap::{
+    maker::{start_maker_server, MakerBehavior},
+    taker::{SwapParams, TakerBehavior},
+    utill::ConnectionType,
+    wallet::{Destination, SendAmount},
+};
+
+mod test_framework;
+use test_framework::*;
+
+use log::{info, warn};
+use std::{
+    sync::atomic::Ordering::Relaxed,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// A Maker's funding tx double spent while the Taker waits for its confirmation.
+///
+/// The periodic mining is paused, and blocks are mined here one at a time. Once the first Maker of the route
+/// broadcasts its funding tx, and the Taker saw it in the mempool, one of its inputs is spent in a block by another
+/// tx instead. The Taker must abort the swap right away, ban the Maker, and recover its coins.
+#[test]
+fn test_funding_double_spend() {
+    // Funding, contract and timelock recovery fees of the 3 funding txs of a participant.
+    const MAX_MINER_FEES: Amount = Amount::from_sat(10_000);
+    const RECOVERY_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+    // ---- Setup ----
+
+    let makers_config_map = vec![
+        ((6102, None), MakerBehavior::Normal),
+        ((16102, None), MakerBehavior::Normal),
+    ];
+    let (test_framework, mut taker, makers, directory_server_instance, block_generation_handle) =
+        TestFramework::init(
+            makers_config_map,
+            TakerBehavior::Normal,
+            ConnectionType::CLEARNET,
+        );
+
+    warn!("Running Test: A Maker's funding tx is double spent mid-swap");
+
+    // Fund the Taker with 3 utxos of 0.05 btc each, and the Makers with 4 utxos of 0.05 btc each.
+    let org_taker_spend_balance = test_framework.fund_participants(
+        vec![Participant::Taker(&mut taker)],
+        Amount::from_btc(0.05).unwrap(),
+        3,
+    )[0];
+    test_framework.fund_participants(
+        makers
+            .iter()
+            .map(|maker| Participant::Maker(maker))
+            .collect(),
+        Amount::from_btc(0.05).unwrap(),
+        4,
+    );
+
+    //  Start the Maker Server threads
+    log::info!("Initiating Maker...");
+    let maker_threads = makers
+        .iter()
+        .map(|maker| {
+            let maker_clone = maker.clone();
+            thread::spawn(move || {
+                start_maker_server(maker_clone).unwrap();
+            })
+        })
+        .collect::<Vec<_>>();
+
+    // Makers take time to fully setup. Their coins left after the fidelity bond fund the swap.
+    let maker_coins = makers
+        .iter()
+        .map(|maker| {
+            while !maker.is_setup_complete.load(Relaxed) {
+                log::info!("Waiting for maker setup completion");
+                // Introduce a delay of 10 seconds to prevent write lock starvation.
+                thread::sleep(Duration::from_secs(10));
+            }
+            maker
+                .get_wallet()
+                .read()
+                .unwrap()
+                .list_descriptor_utxo_spend_info(None)
+                .unwrap()
+        })
+        .collect::<Vec<_>>();
+
+    // ---- Double spend ----
+
+    test_framework.pause_mining(true);
+    let double_spender = {
+        let test_framework = test_framework.clone();
+        let makers = makers.clone();
+        thread::spawn(move || loop {
+            thread::sleep(Duration::from_secs(3));
+            let bitcoind = &test_framework.bitcoind;
+            let spent = bitcoind
+                .client
+                .get_raw_mempool()
+                .unwrap()
+                .iter()
+                .filter_map(|txid| bitcoind.client.get_raw_transaction(txid, None).ok())
+                .flat_map(|tx| tx.input)
+                .map(|input| input.previous_output)
+                .collect::<Vec<OutPoint>>();
+            let funding_coin = maker_coins.iter().enumerate().find_map(|(index, coins)| {
+                coins
+                    .iter()
+                    .find(|(utxo, _)| spent.contains(&OutPoint::new(utxo.txid, utxo.vout)))
+                    .map(|coin| (index, coin.clone()))
+            });
+            let (index, coin) = match funding_coin {
+                Some(funding_coin) => funding_coin,
+                None => {
+                    generate_blocks(bitcoind, 1);
+                    continue;
+                }
+            };
+
+            // Let the Taker see the funding tx in the mempool, before it's replaced.
+            info!("Maker {} broadcasted its funding tx", index);
+            thread::sleep(Duration::from_secs(5));
+            let double_spend = makers[index]
+                .get_wallet()
+                .write()
+                .unwrap()
+                .spend_from_wallet(
+                    Amount::from_sat(1000),
+                    SendAmount::Max,
+                    Destination::Wallet,
+                    &[coin],
+                )
+                .unwrap();
+            let double_spend_txid = double_spend.compute_txid();
+            generate_block_with(bitcoind, &[double_spend]);
+            test_framework.pause_mining(false);
+            return (index, double_spend_txid);
+        })
+    };
+
+    // ---- Swap ----
+
+    let swap_params = SwapParams {
+        send_amount: Amount::from_sat(500000),
+        maker_count: 2,
+        tx_count: 3,
+        ..Default::default()
+    };
+    // The Taker recovers from the aborted swap by itself.
+    taker.do_coinswap(swap_params).unwrap();
+    let (double_spender, double_spend_txid) = double_spender.join().unwrap();
+
+    // ---- Checks ----
+
+    let confirmations = test_framework
+        .bitcoind
+        .client
+        .get_raw_transaction_info(&double_spend_txid, None)
+        .unwrap()
+        .confirmations;
+    assert!(confirmations >= Some(1), "Double spend didn't confirm");
+
+    // The Maker double spending its funding is banned.
+    let bad_makers = taker.get_bad_makers();
+    assert_eq!(bad_makers.len(), 1);
+    assert_eq!(
+        bad_makers[0].address.to_string(),
+        format!("127.0.0.1:{}", [6102, 16102][double_spender])
+    );
+
+    // The Taker recovered its coins from the first hop.
+    taker.get_wallet_mut().sync_no_fail();
+    let (taker_balances, taker_spend_balance) = Participant::Taker(&mut taker).balances();
+    assert_eq!(taker_balances.contract, Amount::ZERO);
+    assert_eq!(taker_balances.swap, Amount::ZERO);
+    chaos::assert_loss_within_fees(
+        "Taker",
+        org_taker_spend_balance,
+        taker_spend_balance,
+        MAX_MINER_FEES,
+    );
+
+    // The Makers recover their incoming contracts through the timelocks too.
+    let start = Instant::now();
+    while !makers.iter().all(|maker| {
+        maker.get_wallet().write().unwrap().sync_no_fail();
+        Participant::Maker(maker).balances().0.contract == Amount::ZERO
+    }) {
+        assert!(
+            start.elapsed() < RECOVERY_TIMEOUT,
+            "Makers didn't recover in time"
+        );
+        info!("Waiting for the Makers to recover");
+        thread::sleep(Duration::from_secs(10));
+    }
+
+    makers
+        .iter()
+        .for_each(|maker| maker.shutdown.store(true, Relaxed));
+    maker_threads
+        .into_iter()
+        .for_each(|thread| thread.join().unwrap());
+
+    // Shutdown Directory Server
+    directory_server_instance.shutdown.store(true, Relaxed);
+    thread::sleep(Duration::from_secs(10));
+
+    info!("All checks successful. Terminating integration test case");
+    test_framework.stop();
+    block_generation_handle.join().unwrap();
+}
@@ -0,0 +1,34 @@
+#![cfg(feature = "integration-test")]
+use bitcoin::Amount;
+use coinswap::{maker::MakerBehavior, protocol::messages::MessageType};
+
+mod test_framework;
+use test_framework::*;
+
+use log::{info, warn};
+
+/// A Maker closing at the proof of funding, after the Taker funded the first hop. The Taker and the other Maker
+/// recover their coins through the timelocks.
+#[test]
+fn test_maker_closes_at_specific_message() {
+    warn!("Running Test: Maker 6102 closes at the proof of funding");
+    let (test_framework, taker, _, block_generation_handle) = swap_with_makers(vec![
+        (
+            (6102, None),
+            MakerBehavior::CloseAtSpecificMessage(MessageType::RespProofOfFunding),
+        ),
+        ((16102, None), MakerBehavior::Normal),
+    ]);
+
+    let balances = taker.get_wallet().get_balances(None).unwrap();
+    assert_eq!(balances.swap, Amount::ZERO);
+    assert_eq!(balances.contract, Amount::ZERO);
+    assert_eq!(
+        taker.get_bad_makers()[0].address.to_string(),
+        "127.0.0.1:6102"
+    );
+
+    info!("All checks successful. Terminating integration test case");
+    test_framework.stop();
+    block_generation_handle.join().unwrap();
+}
@@ -0,0 +1,33 @@
+#![cfg(feature = "integration-test")]
+use bitcoin::Amount;
+use coinswap::maker::MakerBehavior;
+use std::time::Duration;
+
+mod test_framework;
+use test_framework::*;
+
+use log::{info, warn};
+
+/// Makers slow to answer, but within the Taker's timeouts, don't disrupt the swap.
+#[test]
+fn test_maker_delays_responses() {
+    warn!("Running Test: Makers delay every response");
+    let (test_framework, taker, _, block_generation_handle) = swap_with_makers(vec![
+        (
+            (6102, None),
+            MakerBehavior::DelayResponses(Duration::from_secs(2)),
+        ),
+        (
+            (16102, None),
+            MakerBehavior::DelayResponses(Duration::from_secs(2)),
+        ),
+    ]);
+
+    // The swap completed.
+    let balances = taker.get_wallet().get_balances(None).unwrap();
+    assert_eq!(balances.swap, Amount::from_btc(0.00438642).unwrap());
+
+    info!("All checks successful. Terminating integration test case");
+    test_framework.stop();
+    block_generation_handle.join().unwrap();
+}
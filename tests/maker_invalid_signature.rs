@@ -0,0 +1,33 @@
+#![cfg(feature = "integration-test")]
+use bitcoin::Amount;
+use coinswap::{maker::MakerBehavior, taker::TakerBehavior};
+
+mod test_framework;
+use test_framework::*;
+
+use log::{info, warn};
+
+/// A Maker signing with a wrong key is caught when the Taker verifies its signatures. It's one of the only two
+/// Makers, so the Taker can't route around it: the swap fails, the Maker is marked bad, and everyone recovers
+/// their coins.
+#[test]
+fn test_maker_sends_invalid_signature() {
+    warn!("Running Test: Maker 16102 sends invalid contract signatures");
+    let (test_framework, taker, makers, block_generation_handle) = swap_and_recover(
+        vec![
+            ((6102, None), MakerBehavior::Normal),
+            ((16102, None), MakerBehavior::SendInvalidSignature),
+        ],
+        TakerBehavior::Normal,
+    );
+
+    let bad_makers = taker.get_bad_makers();
+    assert_eq!(bad_makers.len(), 1, "The Maker wasn't marked bad");
+    assert_eq!(bad_makers[0].address.to_string(), "127.0.0.1:16102");
+    let (balances, _) = Participant::Maker(&makers[1]).balances();
+    assert_eq!(balances.contract, Amount::ZERO);
+
+    info!("All checks successful. Terminating integration test case");
+    test_framework.stop();
+    block_generation_handle.join().unwrap();
+}
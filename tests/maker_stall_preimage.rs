@@ -0,0 +1,27 @@
+#![cfg(feature = "integration-test")]
+use bitcoin::Amount;
+use coinswap::maker::MakerBehavior;
+
+mod test_framework;
+use test_framework::*;
+
+use log::{info, warn};
+
+/// A Maker never answering the hash preimage leaves the Taker waiting until its timeout. The Taker then
+/// recovers its coins, like from a Maker closing at the hash preimage.
+#[test]
+fn test_maker_stalls_at_hash_preimage() {
+    warn!("Running Test: Maker 6102 stalls at the hash preimage");
+    let (test_framework, taker, _, block_generation_handle) = swap_with_makers(vec![
+        ((6102, None), MakerBehavior::StallAtHashPreimage),
+        ((16102, None), MakerBehavior::Normal),
+    ]);
+
+    // Nothing is left stuck in contracts.
+    let balances = taker.get_wallet().get_balances(None).unwrap();
+    assert_eq!(balances.contract, Amount::ZERO);
+
+    info!("All checks successful. Terminating integration test case");
+    test_framework.stop();
+    block_generation_handle.join().unwrap();
+}
@@ -17,7 +17,9 @@ use std::{sync::atomic::Ordering::Relaxed, thread, time::Duration};
 /// a potential DOS on other Makers. But the attacker Maker would loose money too in the process.
 ///
 /// This case is hard to "blame". As the contract transactions is available to both the Makers, its not identifiable
-/// which Maker is the culprit. Taker does not ban in this case.
+/// for sure which Maker is the culprit. The Taker guesses it from when the contracts of each hop were first seen:
+/// the culprit broadcasts the contracts of both its hops first, the other Makers only react later. The guess adds
+/// to the ban score of the Maker, weighted by its confidence.
 #[test]
 fn malice2_maker_broadcast_contract_prematurely() {
     // ---- Setup ----
@@ -100,7 +102,7 @@ fn malice2_maker_broadcast_contract_prematurely() {
         send_amount: Amount::from_sat(500000),
         maker_count: 2,
         tx_count: 3,
-        required_confirms: 1,
+        ..Default::default()
     };
     taker.do_coinswap(swap_params).unwrap();
 
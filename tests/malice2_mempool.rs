@@ -0,0 +1,208 @@
+#![cfg(feature = "integration-test")]
+use bitcoin::{
+    opcodes::all::{OP_CHECKMULTISIG, OP_PUSHNUM_2},
+    Amount,
+};
+use bitcoind::{bitcoincore_rpc::RpcApi, BitcoinD};
+use coinswap::{
+    events::Event,
+    maker::{start_maker_server, MakerBehavior},
+    taker::{SwapParams, TakerBehavior},
+    utill::ConnectionType,
+};
+use std::sync::Arc;
+mod test_framework;
+use test_framework::*;
+
+use std::{
+    sync::atomic::Ordering::Relaxed,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Longest the Taker may take to detect the contracts of the malicious Maker.
+const DETECTION_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// Malice 2, caught in the mempool: the Maker broadcasting its contracts prematurely is noticed before they confirm.
+///
+/// The blocks are mined by the test until the contracts show up in the mempool, then the mining stops. The Taker
+/// must see the contracts there, unconfirmed, and recover from the swap by broadcasting its own contracts. Every
+/// participant gets its coins back once the mining resumes.
+#[test]
+fn malice2_contracts_detected_in_mempool() {
+    // ---- Setup ----
+
+    let makers_config_map = [
+        ((6102, None), MakerBehavior::BroadcastContractAfterSetup),
+        ((16102, None), MakerBehavior::Normal),
+    ];
+
+    // Initiate test framework, Makers.
+    // Taker has normal behavior.
+    let (test_framework, mut taker, makers, directory_server_instance, block_generation_handle) =
+        TestFramework::init(
+            makers_config_map.into(),
+            TakerBehavior::Normal,
+            ConnectionType::CLEARNET,
+        );
+
+    // Fund the Taker  with 3 utxos of 0.05 btc each and do basic checks on the balance
+    let org_taker_spend_balance = fund_and_verify_taker(
+        &mut taker,
+        &test_framework.bitcoind,
+        3,
+        Amount::from_btc(0.05).unwrap(),
+    );
+
+    // Fund the Maker with 4 utxos of 0.05 btc each and do basic checks on the balance.
+    let makers_ref = makers.iter().map(Arc::as_ref).collect::<Vec<_>>();
+    fund_and_verify_maker(
+        makers_ref,
+        &test_framework.bitcoind,
+        4,
+        Amount::from_btc(0.05).unwrap(),
+    );
+
+    //  Start the Maker Server threads
+    log::info!("Initiating Maker...");
+
+    let maker_threads = makers
+        .iter()
+        .map(|maker| {
+            let maker_clone = maker.clone();
+            thread::spawn(move || {
+                start_maker_server(maker_clone).unwrap();
+            })
+        })
+        .collect::<Vec<_>>();
+
+    // Makers take time to fully setup.
+    let org_maker_spend_balances = makers
+        .iter()
+        .map(|maker| {
+            while !maker.is_setup_complete.load(Relaxed) {
+                log::info!("Waiting for maker setup completion");
+                // Introduce a delay of 10 seconds to prevent write lock starvation.
+                thread::sleep(Duration::from_secs(10));
+                continue;
+            }
+
+            // Check balance after setting up maker server.
+            let wallet = maker.wallet.read().unwrap();
+            let all_utxos = wallet.get_all_utxo().unwrap();
+
+            let balances = wallet.get_balances(Some(&all_utxos)).unwrap();
+
+            assert_eq!(balances.regular, Amount::from_btc(0.14999).unwrap());
+            assert_eq!(balances.fidelity, Amount::from_btc(0.05).unwrap());
+            assert_eq!(balances.swap, Amount::ZERO);
+            assert_eq!(balances.contract, Amount::ZERO);
+
+            balances.spendable
+        })
+        .collect::<Vec<_>>();
+
+    // Mine the blocks from here on, and stop as soon as the contracts show up in the mempool. Then the Taker
+    // must detect them while they're still unconfirmed.
+    test_framework.pause_mining(true);
+    let taker_events = taker.subscribe_events();
+    let test_framework_clone = test_framework.clone();
+    let watcher = thread::spawn(move || {
+        let bitcoind = &test_framework_clone.bitcoind;
+        let start = Instant::now();
+        while !contracts_in_mempool(bitcoind) && start.elapsed() < DETECTION_TIMEOUT {
+            generate_blocks(bitcoind, 1);
+            thread::sleep(Duration::from_secs(1));
+        }
+        let detected = loop {
+            match taker_events.recv_timeout(DETECTION_TIMEOUT) {
+                Ok(Event::ContractBroadcastDetected { txids }) => break txids,
+                Ok(_) => continue,
+                Err(_) => break Vec::new(),
+            }
+        };
+        let confirmations = detected
+            .iter()
+            .map(|txid| {
+                bitcoind
+                    .client
+                    .get_raw_transaction_info(txid, None)
+                    .unwrap()
+                    .confirmations
+            })
+            .collect::<Vec<_>>();
+        test_framework_clone.pause_mining(false);
+        (detected, confirmations)
+    });
+
+    // Initiate Coinswap
+    log::info!("Initiating coinswap protocol");
+
+    // Swap params for coinswap.
+    let swap_params = SwapParams {
+        send_amount: Amount::from_sat(500000),
+        maker_count: 2,
+        tx_count: 3,
+        ..Default::default()
+    };
+    taker.do_coinswap(swap_params).unwrap();
+
+    let (txids, confirmations) = watcher.join().unwrap();
+    log::info!("Contracts detected by the Taker: {:?}", txids);
+    assert!(!txids.is_empty(), "The Taker didn't detect the contracts");
+    assert!(
+        confirmations.iter().all(Option::is_none),
+        "Contracts detected only after confirming: {:?}",
+        confirmations
+    );
+
+    // After Swap is done,  wait for maker threads to conclude.
+    makers
+        .iter()
+        .for_each(|maker| maker.shutdown.store(true, Relaxed));
+
+    maker_threads
+        .into_iter()
+        .for_each(|thread| thread.join().unwrap());
+
+    log::info!("All coinswaps processed successfully. Transaction complete.");
+
+    // Shutdown Directory Server
+    directory_server_instance.shutdown.store(true, Relaxed);
+
+    thread::sleep(Duration::from_secs(10));
+
+    // After Swap checks:
+    verify_swap_results(
+        &taker,
+        &makers,
+        org_taker_spend_balance,
+        org_maker_spend_balances,
+    );
+
+    log::info!("All checks successful. Terminating integration test case");
+
+    test_framework.stop();
+    block_generation_handle.join().unwrap();
+}
+
+/// Whether a tx in the mempool spends a 2-of-2 multisig, as the contract txs do.
+fn contracts_in_mempool(bitcoind: &BitcoinD) -> bool {
+    let is_multisig = |script: &[u8]| {
+        script.len() == 71
+            && script[0] == OP_PUSHNUM_2.to_u8()
+            && script[69] == OP_PUSHNUM_2.to_u8()
+            && script[70] == OP_CHECKMULTISIG.to_u8()
+    };
+    bitcoind
+        .client
+        .get_raw_mempool()
+        .unwrap()
+        .iter()
+        .filter_map(|txid| bitcoind.client.get_raw_transaction(txid, None).ok())
+        .any(|tx| {
+            tx.input
+                .iter()
+                .any(|input| input.witness.last().is_some_and(is_multisig))
+        })
+}
@@ -0,0 +1,121 @@
+#![cfg(feature = "integration-test")]
+use bitcoin::Amount;
+use coinswap::{
+    maker::{start_maker_server, MakerBehavior},
+    taker::{SwapParams, TakerBehavior},
+    utill::ConnectionType,
+};
+
+mod test_framework;
+use test_framework::*;
+
+use log::{info, warn};
+use std::{sync::atomic::Ordering::Relaxed, thread, time::Duration};
+
+/// Multiple Takers: two Takers swap at the same time through the same 2 Makers. The Makers keep the state of each
+/// swap apart, and fund both from their liquidity, so both swaps complete.
+#[test]
+fn test_concurrent_takers() {
+    // ---- Setup ----
+
+    let makers_config_map = [
+        ((6102, None), MakerBehavior::Normal),
+        ((16102, None), MakerBehavior::Normal),
+    ];
+
+    let (test_framework, taker, makers, directory_server_instance, block_generation_handle) =
+        TestFramework::init(
+            makers_config_map.into(),
+            TakerBehavior::Normal,
+            ConnectionType::CLEARNET,
+        );
+    let mut takers = [taker, test_framework.add_taker(TakerBehavior::Normal)];
+
+    warn!("Running Test: Two Takers swap concurrently with the same Makers");
+
+    // Fund the Takers with 3 utxos of 0.05 btc each, and the Makers with 4 utxos of 0.05 btc each.
+    test_framework.fund_participants(
+        takers.iter_mut().map(Participant::Taker).collect(),
+        Amount::from_btc(0.05).unwrap(),
+        3,
+    );
+    test_framework.fund_participants(
+        makers
+            .iter()
+            .map(|maker| Participant::Maker(maker))
+            .collect(),
+        Amount::from_btc(0.05).unwrap(),
+        4,
+    );
+
+    //  Start the Maker Server threads
+    log::info!("Initiating Maker...");
+
+    let maker_threads = makers
+        .iter()
+        .map(|maker| {
+            let maker_clone = maker.clone();
+            thread::spawn(move || {
+                start_maker_server(maker_clone).unwrap();
+            })
+        })
+        .collect::<Vec<_>>();
+
+    // Makers take time to fully setup.
+    for maker in &makers {
+        while !maker.is_setup_complete.load(Relaxed) {
+            log::info!("Waiting for maker setup completion");
+            // Introduce a delay of 10 seconds to prevent write lock starvation.
+            thread::sleep(Duration::from_secs(10));
+        }
+    }
+
+    // Initiate the Coinswaps, one thread per Taker.
+    log::info!("Initiating coinswap protocol");
+
+    let swap_params = SwapParams {
+        send_amount: Amount::from_sat(500000),
+        maker_count: 2,
+        tx_count: 3,
+        ..Default::default()
+    };
+    thread::scope(|s| {
+        for taker in takers.iter_mut() {
+            s.spawn(move || taker.do_coinswap(swap_params).unwrap());
+        }
+    });
+
+    // After Swap is done,  wait for maker threads to conclude.
+    makers
+        .iter()
+        .for_each(|maker| maker.shutdown.store(true, Relaxed));
+
+    maker_threads
+        .into_iter()
+        .for_each(|thread| thread.join().unwrap());
+
+    log::info!("All coinswaps processed successfully. Transaction complete.");
+
+    // Shutdown Directory Server
+    directory_server_instance.shutdown.store(true, Relaxed);
+
+    thread::sleep(Duration::from_secs(10));
+
+    // ---- After Swap Asserts ----
+
+    // Both Takers received their swapped coins, and nothing is left in contracts.
+    for taker in takers.iter_mut() {
+        let (balances, _) = Participant::Taker(taker).balances();
+        assert!(balances.swap > Amount::ZERO);
+        assert_eq!(balances.contract, Amount::ZERO);
+    }
+    for maker in &makers {
+        let (balances, _) = Participant::Maker(maker).balances();
+        assert_eq!(balances.contract, Amount::ZERO);
+    }
+
+    info!("Balance check successful.");
+
+    test_framework.stop();
+    block_generation_handle.join().unwrap();
+}
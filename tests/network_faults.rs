@@ -0,0 +1,146 @@
+#![cfg(feature = "integration-test")]
+use bitcoin::Amount;
+use coinswap::{
+    maker::{start_maker_server, MakerBehavior},
+    protocol::messages::MessageType,
+    taker::{SwapParams, TakerBehavior},
+    utill::ConnectionType,
+};
+
+mod test_framework;
+use test_framework::{proxy::Faults, *};
+
+use log::{info, warn};
+use std::{sync::atomic::Ordering::Relaxed, thread, time::Duration};
+
+/// Network Faults: the link between the Taker and a Maker of its route is severed by the network, not by the Maker,
+/// when the Taker first asks for the sender's contract signatures. Another Maker is slowed down by latency on its
+/// link.
+///
+/// Like a Maker dropping before setup, this shouldn't disrupt the round: the Taker moves on with the other Makers,
+/// and the cut off Maker is left without any swap or contract coins.
+#[test]
+fn test_network_faults_move_on_with_other_makers() {
+    // ---- Setup ----
+
+    let makers_config_map = [
+        ((6102, None), MakerBehavior::Normal),
+        ((16102, None), MakerBehavior::Normal),
+        ((26102, None), MakerBehavior::Normal),
+    ];
+
+    // Proxy all the Makers, to sever whichever the Taker picks first.
+    let (
+        test_framework,
+        mut taker,
+        makers,
+        directory_server_instance,
+        block_generation_handle,
+        proxies,
+    ) = TestFramework::init_with_proxies(
+        makers_config_map.into(),
+        TakerBehavior::Normal,
+        ConnectionType::CLEARNET,
+        vec![(6102, 6202), (16102, 16202), (26102, 26202)],
+    );
+
+    warn!("Running Test: Link with a Maker severed before sending sender's sigs. Taker moves on with other Makers.");
+
+    // Fund the Taker with 3 utxos of 0.05 btc each, and the Makers with 4 utxos of 0.05 btc each.
+    test_framework.fund_participants(
+        vec![Participant::Taker(&mut taker)],
+        Amount::from_btc(0.05).unwrap(),
+        3,
+    );
+    test_framework.fund_participants(
+        makers
+            .iter()
+            .map(|maker| Participant::Maker(maker))
+            .collect(),
+        Amount::from_btc(0.05).unwrap(),
+        4,
+    );
+
+    //  Start the Maker Server threads
+    log::info!("Initiating Maker...");
+
+    let maker_threads = makers
+        .iter()
+        .map(|maker| {
+            let maker_clone = maker.clone();
+            thread::spawn(move || {
+                start_maker_server(maker_clone).unwrap();
+            })
+        })
+        .collect::<Vec<_>>();
+
+    // Makers take time to fully setup.
+    for maker in &makers {
+        while !maker.is_setup_complete.load(Relaxed) {
+            log::info!("Waiting for maker setup completion");
+            // Introduce a delay of 10 seconds to prevent write lock starvation.
+            thread::sleep(Duration::from_secs(10));
+        }
+    }
+
+    // Inject the faults once the Makers are set up.
+    proxies[0].set_faults(Faults {
+        latency: Duration::from_millis(200),
+        ..Default::default()
+    });
+    FaultProxy::sever_one_of(&proxies, MessageType::ReqContractSigsForSender);
+
+    // Initiate Coinswap
+    log::info!("Initiating coinswap protocol");
+
+    // Swap params for coinswap.
+    let swap_params = SwapParams {
+        send_amount: Amount::from_sat(500000),
+        maker_count: 2,
+        tx_count: 3,
+        ..Default::default()
+    };
+    taker.do_coinswap(swap_params).unwrap();
+
+    // Exactly one link was severed, the others carried the swap.
+    let severed = proxies
+        .iter()
+        .enumerate()
+        .filter(|(_, proxy)| proxy.is_severed())
+        .map(|(index, _)| index)
+        .collect::<Vec<_>>();
+    assert_eq!(severed.len(), 1);
+    let severed = severed[0];
+    info!("Link with Maker {} severed", proxies[severed].port());
+
+    // After Swap is done,  wait for maker threads to conclude.
+    makers
+        .iter()
+        .for_each(|maker| maker.shutdown.store(true, Relaxed));
+
+    maker_threads
+        .into_iter()
+        .for_each(|thread| thread.join().unwrap());
+
+    proxies.into_iter().for_each(FaultProxy::stop);
+
+    // Shutdown Directory Server
+    directory_server_instance.shutdown.store(true, Relaxed);
+
+    thread::sleep(Duration::from_secs(10));
+
+    // ---- After Swap Asserts ----
+
+    // The Taker completed the swap with the other Makers.
+    let (taker_balances, _) = Participant::Taker(&mut taker).balances();
+    assert!(taker_balances.swap > Amount::ZERO);
+    assert_eq!(taker_balances.contract, Amount::ZERO);
+
+    // The cut off Maker never got to fund or sign anything.
+    let (maker_balances, _) = Participant::Maker(&makers[severed]).balances();
+    assert_eq!(maker_balances.swap, Amount::ZERO);
+    assert_eq!(maker_balances.contract, Amount::ZERO);
+
+    test_framework.stop();
+    block_generation_handle.join().unwrap();
+}
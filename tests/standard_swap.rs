@@ -1,14 +1,12 @@
 #![cfg(feature = "integration-test")]
 use bitcoin::Amount;
+use bitcoind::bitcoincore_rpc::RpcApi;
 use coinswap::{
     maker::{start_maker_server, MakerBehavior},
     taker::{SwapParams, TakerBehavior},
     utill::ConnectionType,
     wallet::{Destination, SendAmount},
 };
-use std::sync::Arc;
-
-use bitcoind::bitcoincore_rpc::RpcApi;
 
 mod test_framework;
 use test_framework::*;
@@ -42,12 +40,44 @@ fn test_standard_coinswap() {
     let bitcoind = &test_framework.bitcoind;
 
     // Fund the Taker  with 3 utxos of 0.05 btc each and do basic checks on the balance
-    let org_taker_spend_balance =
-        fund_and_verify_taker(&mut taker, bitcoind, 3, Amount::from_btc(0.05).unwrap());
+    let org_taker_spend_balance = test_framework.fund_participants(
+        vec![Participant::Taker(&mut taker)],
+        Amount::from_btc(0.05).unwrap(),
+        3,
+    )[0];
+    assert_eq!(taker.get_wallet().get_external_index(), &3);
 
     // Fund the Maker with 4 utxos of 0.05 btc each and do basic checks on the balance.
-    let makers_ref = makers.iter().map(Arc::as_ref).collect::<Vec<_>>();
-    fund_and_verify_maker(makers_ref, bitcoind, 4, Amount::from_btc(0.05).unwrap());
+    test_framework.fund_participants(
+        makers
+            .iter()
+            .map(|maker| Participant::Maker(maker))
+            .collect(),
+        Amount::from_btc(0.05).unwrap(),
+        4,
+    );
+    for maker in &makers {
+        assert_eq!(maker.get_wallet().read().unwrap().get_external_index(), &4);
+    }
+    test_framework.assert_balances(
+        std::iter::once((
+            Participant::Taker(&mut taker),
+            WalletBalances {
+                regular: Amount::from_btc(0.15).unwrap(),
+                ..Default::default()
+            },
+        ))
+        .chain(makers.iter().map(|maker| {
+            (
+                Participant::Maker(maker),
+                WalletBalances {
+                    regular: Amount::from_btc(0.20).unwrap(),
+                    ..Default::default()
+                },
+            )
+        }))
+        .collect(),
+    );
 
     //  Start the Maker Server threads
     log::info!("Initiating Maker...");
@@ -63,29 +93,33 @@ fn test_standard_coinswap() {
         .collect::<Vec<_>>();
 
     // Makers take time to fully setup.
+    for maker in &makers {
+        while !maker.is_setup_complete.load(Relaxed) {
+            log::info!("Waiting for maker setup completion");
+            // Introduce a delay of 10 seconds to prevent write lock starvation.
+            thread::sleep(Duration::from_secs(10));
+        }
+    }
+
+    // Check balances after setting up maker servers.
+    test_framework.assert_balances(
+        makers
+            .iter()
+            .map(|maker| {
+                (
+                    Participant::Maker(maker),
+                    WalletBalances {
+                        regular: Amount::from_btc(0.14999).unwrap(),
+                        fidelity: Amount::from_btc(0.05).unwrap(),
+                        ..Default::default()
+                    },
+                )
+            })
+            .collect(),
+    );
     let org_maker_spend_balances = makers
         .iter()
-        .map(|maker| {
-            while !maker.is_setup_complete.load(Relaxed) {
-                log::info!("Waiting for maker setup completion");
-                // Introduce a delay of 10 seconds to prevent write lock starvation.
-                thread::sleep(Duration::from_secs(10));
-                continue;
-            }
-
-            // Check balance after setting up maker server.
-            let wallet = maker.wallet.read().unwrap();
-            let all_utxos = wallet.get_all_utxo().unwrap();
-
-            let balances = wallet.get_balances(Some(&all_utxos)).unwrap();
-
-            assert_eq!(balances.regular, Amount::from_btc(0.14999).unwrap());
-            assert_eq!(balances.fidelity, Amount::from_btc(0.05).unwrap());
-            assert_eq!(balances.swap, Amount::ZERO);
-            assert_eq!(balances.contract, Amount::ZERO);
-
-            balances.spendable
-        })
+        .map(|maker| Participant::Maker(maker).balances().1)
         .collect::<Vec<_>>();
 
     // Initiate Coinswap
@@ -96,9 +130,13 @@ fn test_standard_coinswap() {
         send_amount: Amount::from_sat(500000),
         maker_count: 2,
         tx_count: 3,
-        required_confirms: 1,
+        ..Default::default()
     };
-    taker.do_coinswap(swap_params).unwrap();
+    // The swap runs on a multi threaded runtime, as an async application embedding the Taker would.
+    tokio::runtime::Runtime::new()
+        .unwrap()
+        .block_on(taker.do_coinswap_async(swap_params))
+        .unwrap();
 
     // After Swap is done,  wait for maker threads to conclude.
     makers
@@ -173,10 +211,13 @@ fn test_standard_coinswap() {
     bitcoind.client.send_raw_transaction(&tx).unwrap();
     generate_blocks(bitcoind, 1);
 
-    let balances = taker_wallet_mut.get_balances(None).unwrap();
-
-    assert_eq!(balances.swap, Amount::ZERO);
-    assert_eq!(balances.regular, Amount::from_btc(0.14934642).unwrap());
+    test_framework.assert_balances(vec![(
+        Participant::Taker(&mut taker),
+        WalletBalances {
+            regular: Amount::from_btc(0.14934642).unwrap(),
+            ..Default::default()
+        },
+    )]);
 
     info!("All checks successful. Terminating integration test case");
 
@@ -0,0 +1,151 @@
+#![cfg(feature = "integration-test")]
+use bitcoin::Amount;
+use coinswap::{
+    maker::{start_maker_server, Maker, MakerBehavior},
+    market::directory::DirectoryServer,
+    taker::{error::TakerError, SwapParams, Taker, TakerBehavior},
+    utill::ConnectionType,
+};
+
+mod test_framework;
+use test_framework::*;
+
+use log::warn;
+use std::{
+    sync::{atomic::Ordering::Relaxed, Arc},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// Dust UTXO of the Taker: standard, but below the wallet's dust threshold.
+const DUST_SATS: u64 = 400;
+
+/// Starts 2 makers, and funds the Taker with 3 utxos of 0.05 btc each, and a dust UTXO.
+#[allow(clippy::type_complexity)]
+fn setup() -> (
+    Arc<TestFramework>,
+    Taker,
+    Vec<Arc<Maker>>,
+    Vec<JoinHandle<()>>,
+    Arc<DirectoryServer>,
+    JoinHandle<()>,
+) {
+    let makers_config_map = [
+        ((6102, Some(19051)), MakerBehavior::Normal),
+        ((16102, Some(19052)), MakerBehavior::Normal),
+    ];
+    let (test_framework, mut taker, makers, directory_server_instance, block_generation_handle) =
+        TestFramework::init(
+            makers_config_map.into(),
+            TakerBehavior::Normal,
+            ConnectionType::CLEARNET,
+        );
+
+    test_framework.fund_participants(
+        vec![Participant::Taker(&mut taker)],
+        Amount::from_btc(0.05).unwrap(),
+        3,
+    );
+    let spendable = test_framework.fund_participants(
+        vec![Participant::Taker(&mut taker)],
+        Amount::from_sat(DUST_SATS),
+        1,
+    )[0];
+    // The dust counts as spendable, but is never selected to fund a swap.
+    assert_eq!(
+        spendable,
+        Amount::from_btc(0.15).unwrap() + Amount::from_sat(DUST_SATS)
+    );
+    test_framework.fund_participants(
+        makers
+            .iter()
+            .map(|maker| Participant::Maker(maker))
+            .collect(),
+        Amount::from_btc(0.05).unwrap(),
+        4,
+    );
+
+    let maker_threads = makers
+        .iter()
+        .map(|maker| {
+            let maker_clone = maker.clone();
+            thread::spawn(move || {
+                start_maker_server(maker_clone).unwrap();
+            })
+        })
+        .collect::<Vec<_>>();
+    for maker in &makers {
+        while !maker.is_setup_complete.load(Relaxed) {
+            log::info!("Waiting for maker setup completion");
+            thread::sleep(Duration::from_secs(10));
+        }
+    }
+
+    (
+        test_framework,
+        taker,
+        makers,
+        maker_threads,
+        directory_server_instance,
+        block_generation_handle,
+    )
+}
+
+fn teardown(
+    test_framework: Arc<TestFramework>,
+    makers: Vec<Arc<Maker>>,
+    maker_threads: Vec<JoinHandle<()>>,
+    directory_server_instance: Arc<DirectoryServer>,
+    block_generation_handle: JoinHandle<()>,
+) {
+    makers
+        .iter()
+        .for_each(|maker| maker.shutdown.store(true, Relaxed));
+    maker_threads
+        .into_iter()
+        .for_each(|thread| thread.join().unwrap());
+    directory_server_instance.shutdown.store(true, Relaxed);
+    thread::sleep(Duration::from_secs(10));
+    test_framework.stop();
+    block_generation_handle.join().unwrap();
+}
+
+/// The funds of a swap are the coins its funding txs can select: a sweep leaves the dust UTXO out of the send
+/// amount, and the dust remainder is the change of the selected coins, not counting the dust UTXO.
+#[test]
+fn test_swap_funds_with_dust() {
+    let (test_framework, mut taker, makers, maker_threads, directory, block_generation_handle) =
+        setup();
+    warn!("Running Test: Swap funds with a dust UTXO");
+
+    let quote = taker.quote(SwapParams::sweep(2, 3)).unwrap();
+    let funding_fees = quote.miner_fees_sats;
+    let selectable = Amount::from_btc(0.15).unwrap().to_sat();
+    assert_eq!(quote.send_amount_sats, selectable - funding_fees);
+
+    let swap_params = |send_sats| SwapParams {
+        send_amount: Amount::from_sat(send_sats),
+        maker_count: 2,
+        tx_count: 3,
+        ..Default::default()
+    };
+
+    // Spending the selected coins whole leaves no change, only the dust UTXO, which stays in the wallet.
+    taker.quote(swap_params(selectable - funding_fees)).unwrap();
+
+    // A change below the dust threshold would go to the miners.
+    match taker.quote(swap_params(selectable - funding_fees - 300)) {
+        Err(TakerError::DustRemainder { remainder, .. }) => {
+            assert_eq!(remainder, Amount::from_sat(300))
+        }
+        other => panic!("Expected a dust remainder, got {:?}", other),
+    }
+
+    teardown(
+        test_framework,
+        makers,
+        maker_threads,
+        directory,
+        block_generation_handle,
+    );
+}
@@ -0,0 +1,26 @@
+#![cfg(feature = "integration-test")]
+use bitcoin::Amount;
+use coinswap::taker::TakerBehavior;
+
+mod test_framework;
+use test_framework::*;
+
+use log::{info, warn};
+
+/// TAKER DROPS: the Taker walks away once the first Maker signed its contracts and funded the next hop, leaving
+/// the Makers' coins in 2-of-2 multisigs. The Makers broadcast their contracts and recover through the timelocks.
+#[test]
+fn test_taker_drops_after_contract_sigs() {
+    warn!("Running Test: Taker drops the swap after the contract signatures");
+    let (test_framework, _, makers, block_generation_handle) =
+        swap_with_taker(TakerBehavior::DropAfterContractSigs);
+
+    // The Makers never handed over their keys.
+    for maker in &makers {
+        assert_eq!(Participant::Maker(maker).balances().0.swap, Amount::ZERO);
+    }
+
+    info!("All checks successful. Terminating integration test case");
+    test_framework.stop();
+    block_generation_handle.join().unwrap();
+}
@@ -0,0 +1,26 @@
+#![cfg(feature = "integration-test")]
+use bitcoin::Amount;
+use coinswap::taker::TakerBehavior;
+
+mod test_framework;
+use test_framework::*;
+
+use log::{info, warn};
+
+/// TAKER BROADCASTS EARLY: the Taker broadcasts its contracts as soon as the first hop's funding confirms. The
+/// first Maker sees the contract on chain, and recovers its coins through the timelock.
+#[test]
+fn test_taker_broadcasts_contract_prematurely() {
+    warn!("Running Test: Taker broadcasts its contracts as soon as the first hop is funded");
+    let (test_framework, _, makers, block_generation_handle) =
+        swap_with_taker(TakerBehavior::BroadcastContractPrematurely);
+
+    // The Makers never handed over their keys.
+    for maker in &makers {
+        assert_eq!(Participant::Maker(maker).balances().0.swap, Amount::ZERO);
+    }
+
+    info!("All checks successful. Terminating integration test case");
+    test_framework.stop();
+    block_generation_handle.join().unwrap();
+}
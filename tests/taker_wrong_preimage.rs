@@ -0,0 +1,26 @@
+#![cfg(feature = "integration-test")]
+use bitcoin::Amount;
+use coinswap::taker::TakerBehavior;
+
+mod test_framework;
+use test_framework::*;
+
+use log::{info, warn};
+
+/// TAKER SENDS A WRONG PREIMAGE: the Maker checks the preimage against the hash of its contracts, and never
+/// hands over its keys. The Makers recover their coins from the contracts.
+#[test]
+fn test_taker_sends_wrong_preimage() {
+    warn!("Running Test: Taker sends a wrong preimage at settlement");
+    let (test_framework, _, makers, block_generation_handle) =
+        swap_with_taker(TakerBehavior::SendWrongPreimage);
+
+    // The Makers never handed over their keys.
+    for maker in &makers {
+        assert_eq!(Participant::Maker(maker).balances().0.swap, Amount::ZERO);
+    }
+
+    info!("All checks successful. Terminating integration test case");
+    test_framework.stop();
+    block_generation_handle.join().unwrap();
+}
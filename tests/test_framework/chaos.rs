@@ -0,0 +1,108 @@
+//! Process-restart chaos testing.
+//!
+//! A participant of a swap is killed at a protocol message: its links are severed at that message by their
+//! [FaultProxy](super::FaultProxy), it's killed, and started again from its data directory with
+//! [TestFramework::restart_maker] or [TestFramework::start_taker]. Only what it saved survives, so the swap is
+//! recovered from disk.
+//!
+//! The Taker swaps in a process of its own, see [TestFramework::spawn_taker_process], killed as soon as its link is
+//! severed, in the middle of the protocol. Makers run in-process: a Maker is stopped as soon as its link is
+//! severed, through its shutdown flag by [kill_maker]. Failing runs are replayed by setting their seed in
+//! `COINSWAP_CHAOS_SEED`.
+use std::{
+    fs,
+    process::{Child, Command, Stdio},
+    sync::{atomic::Ordering::Relaxed, Arc},
+    thread::JoinHandle,
+};
+
+use bitcoin::Amount;
+use coinswap::{
+    maker::{Maker, MakerBehavior},
+    taker::{Taker, TakerBehavior},
+    wallet::RPCConfig,
+};
+
+use super::TestFramework;
+
+/// Stops a Maker, and waits for its server thread to end.
+#[allow(dead_code)]
+pub fn kill_maker(maker: &Maker, server: JoinHandle<()>) {
+    maker.shutdown.store(true, Relaxed);
+    server.join().unwrap();
+}
+
+/// Asserts a participant didn't lose more than `max_miner_fees` of its spendable balance.
+#[allow(dead_code)]
+pub fn assert_loss_within_fees(
+    participant: &str,
+    before: Amount,
+    after: Amount,
+    max_miner_fees: Amount,
+) {
+    let loss = before.checked_sub(after).unwrap_or(Amount::ZERO);
+    log::info!("{} loss: {}", participant, loss);
+    assert!(
+        loss <= max_miner_fees,
+        "{} lost more than miner fees: {}",
+        participant,
+        loss
+    );
+}
+
+#[allow(dead_code)]
+impl TestFramework {
+    /// Starts the Maker listening on `port` again, from its data directory. Start its server to recover its
+    /// unfinished swaps.
+    pub fn restart_maker(&self, port: u16, behavior: MakerBehavior) -> Arc<Maker> {
+        // The ports and the advertised address are kept in its config file.
+        Arc::new(
+            Maker::init(
+                Some(self.temp_dir.join(port.to_string())),
+                Some(format!("maker{}", port)),
+                Some(RPCConfig::from(self)),
+                None,
+                None,
+                None,
+                Some(self.connection_type),
+                behavior,
+            )
+            .unwrap(),
+        )
+    }
+
+    /// Runs the `taker` app with `args`, on the data directory and wallet of the Taker `taker_id`. Drop the Taker
+    /// first, so that only the process uses its wallet. Kill the process to kill the Taker mid-protocol.
+    pub fn spawn_taker_process(&self, taker_id: &str, args: &[&str]) -> Child {
+        // RPC authentication (user:password) from the cookie file
+        let rpc_auth = fs::read_to_string(&self.bitcoind.params.cookie_file).unwrap();
+        Command::new("./target/debug/taker")
+            .arg("--data-directory")
+            .arg(self.temp_dir.join(taker_id))
+            .args(["--USER:PASSWORD", &rpc_auth])
+            .args([
+                "--ADDRESS:PORT",
+                &self.bitcoind.params.rpc_socket.to_string(),
+            ])
+            .args(["--WALLET", &format!("{}-wallet", taker_id)])
+            .args(args)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap()
+    }
+
+    /// Starts the Taker `taker_id` again from its data directory, after [TestFramework::spawn_taker_process].
+    /// `taker_id` is `taker` for the Taker of [TestFramework::init], and the id of an added Taker, see
+    /// [TestFramework::add_taker].
+    pub fn start_taker(&self, taker_id: &str, behavior: TakerBehavior) -> Taker {
+        Taker::init(
+            Some(self.temp_dir.join(taker_id)),
+            Some(format!("{}-wallet", taker_id)),
+            Some(RPCConfig::from(self)),
+            behavior,
+            Some(self.connection_type),
+        )
+        .unwrap()
+    }
+}
@@ -10,8 +10,27 @@
 //!
 //! The test data also includes the backend bitcoind data-directory, which is useful for observing the blockchain states after a swap.
 //!
+//! Wallets are funded from the regtest node with [TestFramework::fund_participants], and their balances checked
+//! with [TestFramework::assert_balances].
+//!
+//! [TestFramework::init] starts a miner generating 10 blocks every 3 seconds. Tests waiting on confirmations or
+//! timelocks at their own pace start another [BlockMiner] with [TestFramework::start_miner], mining periodically or
+//! on demand.
+//! [TestFramework::pause_mining] pauses the periodic mining, for tests deciding when the txs confirm.
+//!
+//! More Takers, swapping concurrently with the same Makers, are created with [TestFramework::add_taker].
+//!
+//! [TestFramework::init_with_proxies] puts a [FaultProxy] in front of chosen clearnet Makers, to inject latency,
+//! drop messages or sever a Maker's link with the Taker at a chosen protocol message.
+//!
+//! Makers and Takers are killed at chosen protocol messages and restarted from disk with [chaos], to check swaps
+//! are recovered without losing more than miner fees.
+//!
+//! [SwapSetup] funds the participants and starts the Maker servers, for the swaps of the misbehavior tests.
+//!
 //! Checkout `tests/standard_swap.rs` for example of simple coinswap simulation test between 1 Taker and 2 Makers.
-use bitcoin::Amount;
+use bitcoin::{consensus::encode::serialize_hex, Amount, Transaction};
+use serde_json::json;
 use std::{
     env,
     fs::{self, create_dir_all, File},
@@ -19,12 +38,12 @@ use std::{
     path::{Path, PathBuf},
     process,
     sync::{
-        atomic::{AtomicBool, Ordering::Relaxed},
-        mpsc::{self, Receiver, Sender},
+        atomic::{AtomicBool, AtomicUsize, Ordering::Relaxed},
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
         Arc,
     },
     thread::{self, JoinHandle},
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use flate2::read::GzDecoder;
@@ -36,13 +55,17 @@ use bitcoind::{
 };
 
 use coinswap::{
-    maker::{Maker, MakerBehavior},
+    maker::{start_maker_server, Maker, MakerBehavior},
     market::directory::{start_directory_server, DirectoryServer},
-    taker::{Taker, TakerBehavior},
+    taker::{SwapParams, Taker, TakerBehavior},
     utill::{setup_logger, ConnectionType},
     wallet::RPCConfig,
 };
 
+pub mod chaos;
+pub mod proxy;
+pub use proxy::FaultProxy;
+
 const BITCOIN_VERSION: &str = "28.1";
 
 fn download_bitcoind_tarball(download_url: &str, retries: usize) -> Vec<u8> {
@@ -200,6 +223,22 @@ pub(crate) fn generate_blocks(bitcoind: &BitcoinD, n: u64) {
         .unwrap();
 }
 
+/// Mines a block with `txs`, replacing the mempool txs they conflict with.
+#[allow(dead_code)]
+pub fn generate_block_with(bitcoind: &BitcoinD, txs: &[Transaction]) {
+    let mining_address = bitcoind
+        .client
+        .get_new_address(None, None)
+        .unwrap()
+        .require_network(bitcoind::bitcoincore_rpc::bitcoin::Network::Regtest)
+        .unwrap();
+    let txs = txs.iter().map(serialize_hex).collect::<Vec<_>>();
+    bitcoind
+        .client
+        .call::<serde_json::Value>("generateblock", &[json!(mining_address), json!(txs)])
+        .unwrap();
+}
+
 /// Send coins to a bitcoin address.
 #[allow(dead_code)]
 pub(crate) fn send_to_address(
@@ -367,6 +406,287 @@ pub fn fund_and_verify_maker(
     });
 }
 
+/// A swap test running: the [TestFramework], with the Taker and Makers funded, and the Maker servers started. The
+/// flows of [swap_with_makers], [swap_and_recover] and [byzantine_soak] are built on it.
+#[allow(dead_code)]
+pub struct SwapSetup {
+    pub test_framework: Arc<TestFramework>,
+    pub taker: Taker,
+    pub makers: Vec<Arc<Maker>>,
+    /// Network ports of the Makers, in order.
+    pub maker_ports: Vec<u16>,
+    /// Spendable balance of the Taker, once funded.
+    pub org_taker_spend_balance: Amount,
+    /// Spendable balances of the Makers, once set up with their fidelity bonds.
+    pub org_maker_spend_balances: Vec<Amount>,
+    block_generation_handle: JoinHandle<()>,
+    directory_server_instance: Arc<DirectoryServer>,
+    maker_threads: Vec<JoinHandle<()>>,
+}
+
+#[allow(dead_code)]
+impl SwapSetup {
+    /// Initializes a [TestFramework] with Makers and a Taker of the given behaviors. Funds the Taker with 3 utxos
+    /// of 0.05 btc each, and the Makers with 4 utxos of 0.05 btc each, then starts the Maker servers and waits for
+    /// their setup.
+    pub fn start(
+        makers_config_map: Vec<((u16, Option<u16>), MakerBehavior)>,
+        taker_behavior: TakerBehavior,
+    ) -> Self {
+        let maker_ports = makers_config_map
+            .iter()
+            .map(|((port, _), _)| *port)
+            .collect();
+        let (test_framework, mut taker, makers, directory_server_instance, block_generation_handle) =
+            TestFramework::init(makers_config_map, taker_behavior, ConnectionType::CLEARNET);
+
+        let org_taker_spend_balance = test_framework.fund_participants(
+            vec![Participant::Taker(&mut taker)],
+            Amount::from_btc(0.05).unwrap(),
+            3,
+        )[0];
+        test_framework.fund_participants(
+            makers
+                .iter()
+                .map(|maker| Participant::Maker(maker))
+                .collect(),
+            Amount::from_btc(0.05).unwrap(),
+            4,
+        );
+
+        //  Start the Maker Server threads
+        log::info!("Initiating Maker...");
+        let maker_threads = makers
+            .iter()
+            .map(|maker| {
+                let maker_clone = maker.clone();
+                thread::spawn(move || {
+                    start_maker_server(maker_clone).unwrap();
+                })
+            })
+            .collect::<Vec<_>>();
+
+        // Makers take time to fully setup.
+        let org_maker_spend_balances = makers
+            .iter()
+            .map(|maker| {
+                while !maker.is_setup_complete.load(Relaxed) {
+                    log::info!("Waiting for maker setup completion");
+                    // Introduce a delay of 10 seconds to prevent write lock starvation.
+                    thread::sleep(Duration::from_secs(10));
+                }
+                Participant::Maker(maker).balances().1
+            })
+            .collect();
+
+        Self {
+            test_framework,
+            taker,
+            makers,
+            maker_ports,
+            org_taker_spend_balance,
+            org_maker_spend_balances,
+            block_generation_handle,
+            directory_server_instance,
+            maker_threads,
+        }
+    }
+
+    /// A swap of 500k sats over 2 Makers, with 3 funding txs.
+    pub fn swap_params() -> SwapParams {
+        SwapParams {
+            send_amount: Amount::from_sat(500000),
+            maker_count: 2,
+            tx_count: 3,
+            ..Default::default()
+        }
+    }
+
+    /// Waits for the Makers to recover their coins through the timelocks, while the background miner matures them:
+    /// until none has coins left in contracts, nor lost more than `max_miner_fees`.
+    pub fn wait_for_makers_recovery(&self, max_miner_fees: Amount, timeout: Duration) {
+        let recovered = |maker: &Maker, before: Amount| {
+            maker.get_wallet().write().unwrap().sync_no_fail();
+            let (balances, spendable) = Participant::Maker(maker).balances();
+            balances.contract == Amount::ZERO && spendable + max_miner_fees >= before
+        };
+        let start = Instant::now();
+        while !self
+            .makers
+            .iter()
+            .zip(&self.org_maker_spend_balances)
+            .all(|(maker, before)| recovered(maker, *before))
+        {
+            assert!(start.elapsed() < timeout, "Makers didn't recover in time");
+            log::info!("Waiting for the Makers to recover");
+            thread::sleep(Duration::from_secs(10));
+        }
+    }
+
+    /// Stops the Maker servers and the Directory server, and returns the framework, still running, with the Taker,
+    /// the Makers and the block generation thread.
+    pub fn stop_servers(self) -> (Arc<TestFramework>, Taker, Vec<Arc<Maker>>, JoinHandle<()>) {
+        self.makers
+            .iter()
+            .for_each(|maker| maker.shutdown.store(true, Relaxed));
+        self.maker_threads
+            .into_iter()
+            .for_each(|thread| thread.join().unwrap());
+
+        // Shutdown Directory Server
+        self.directory_server_instance.shutdown.store(true, Relaxed);
+        thread::sleep(Duration::from_secs(10));
+
+        (
+            self.test_framework,
+            self.taker,
+            self.makers,
+            self.block_generation_handle,
+        )
+    }
+}
+
+/// Runs a swap of 500k sats over 2 Makers, with Makers of the given behaviors, and checks that every participant
+/// either swapped or got its coins back. Returns the framework, still running, for the checks of each behavior.
+#[allow(dead_code)]
+pub fn swap_with_makers(
+    makers_config_map: Vec<((u16, Option<u16>), MakerBehavior)>,
+) -> (Arc<TestFramework>, Taker, Vec<Arc<Maker>>, JoinHandle<()>) {
+    let mut setup = SwapSetup::start(makers_config_map, TakerBehavior::Normal);
+
+    log::info!("Initiating coinswap protocol");
+    setup.taker.do_coinswap(SwapSetup::swap_params()).unwrap();
+
+    let org_taker_spend_balance = setup.org_taker_spend_balance;
+    let org_maker_spend_balances = setup.org_maker_spend_balances.clone();
+    let (test_framework, taker, makers, block_generation_handle) = setup.stop_servers();
+
+    // Swapped, recovered via timelock, or never funded.
+    verify_swap_results(
+        &taker,
+        &makers,
+        org_taker_spend_balance,
+        org_maker_spend_balances,
+    );
+
+    (test_framework, taker, makers, block_generation_handle)
+}
+
+/// Runs a swap of 500k sats over 2 Makers with a misbehaving Taker, see [swap_and_recover].
+#[allow(dead_code)]
+pub fn swap_with_taker(
+    taker_behavior: TakerBehavior,
+) -> (Arc<TestFramework>, Taker, Vec<Arc<Maker>>, JoinHandle<()>) {
+    swap_and_recover(
+        vec![
+            ((6102, None), MakerBehavior::Normal),
+            ((16102, None), MakerBehavior::Normal),
+        ],
+        taker_behavior,
+    )
+}
+
+/// Runs a swap of 500k sats over 2 Makers, expected to fail, and waits for every participant to recover its
+/// coins, while blocks are mined. Asserts that no participant is left with coins in contracts, nor lost more than
+/// the miner fees of its recovery. Returns the framework, still running, for the checks of each behavior.
+#[allow(dead_code)]
+pub fn swap_and_recover(
+    makers_config_map: Vec<((u16, Option<u16>), MakerBehavior)>,
+    taker_behavior: TakerBehavior,
+) -> (Arc<TestFramework>, Taker, Vec<Arc<Maker>>, JoinHandle<()>) {
+    // Funding, contract and timelock recovery fees of the 3 funding txs of a participant.
+    const MAX_MINER_FEES: Amount = Amount::from_sat(10_000);
+    const RECOVERY_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+    let mut setup = SwapSetup::start(makers_config_map, taker_behavior);
+
+    log::info!("Initiating coinswap protocol");
+    if let Err(e) = setup.taker.do_coinswap(SwapSetup::swap_params()) {
+        log::warn!("Swap failed: {:?}", e);
+    }
+    // Claim back anything the Taker left in contracts.
+    setup.taker.recover_from_swap().unwrap();
+    setup.wait_for_makers_recovery(MAX_MINER_FEES, RECOVERY_TIMEOUT);
+
+    let org_taker_spend_balance = setup.org_taker_spend_balance;
+    let org_maker_spend_balances = setup.org_maker_spend_balances.clone();
+    let maker_ports = setup.maker_ports.clone();
+    let (test_framework, mut taker, makers, block_generation_handle) = setup.stop_servers();
+
+    taker.get_wallet_mut().sync_no_fail();
+    let (taker_balances, taker_spend_balance) = Participant::Taker(&mut taker).balances();
+    assert_eq!(taker_balances.contract, Amount::ZERO);
+    chaos::assert_loss_within_fees(
+        "Taker",
+        org_taker_spend_balance,
+        taker_spend_balance,
+        MAX_MINER_FEES,
+    );
+    for ((maker, port), before) in makers.iter().zip(maker_ports).zip(org_maker_spend_balances) {
+        chaos::assert_loss_within_fees(
+            &format!("Maker {}", port),
+            before,
+            Participant::Maker(maker).balances().1,
+            MAX_MINER_FEES,
+        );
+    }
+
+    (test_framework, taker, makers, block_generation_handle)
+}
+
+/// Byzantine Soak: Makers randomly corrupt, reorder, delay or drop their responses.
+///
+/// The Taker attempts `rounds` swaps against them, recovering from every failed round. Whatever happens, the Taker
+/// must never lose more than the miner fees it paid along the way, and the fees of the Makers of the rounds that
+/// succeeded.
+#[allow(dead_code)]
+pub fn byzantine_soak(rounds: u64) {
+    // Funding, contract and timelock recovery fees of the 3 funding txs of the Taker, in a single round.
+    const MAX_ROUND_MINER_FEES: Amount = Amount::from_sat(10_000);
+
+    let mut setup = SwapSetup::start(
+        vec![
+            ((6102, None), MakerBehavior::RandomByzantine(1)),
+            ((16102, None), MakerBehavior::RandomByzantine(2)),
+            ((26102, None), MakerBehavior::RandomByzantine(3)),
+        ],
+        TakerBehavior::Normal,
+    );
+    let taker = &mut setup.taker;
+
+    let swap_params = SwapSetup::swap_params();
+    // The Makers charge the same fees, whichever of them the route goes through. Their miner fees are deducted
+    // from the swapped amount too.
+    let quote = taker.quote(swap_params).unwrap();
+    let maker_fees = Amount::from_sat(quote.total_fees_sats() - quote.miner_fees_sats);
+
+    let mut swaps = 0;
+    for round in 1..=rounds {
+        log::info!("Soak round {}/{}", round, rounds);
+        match taker.do_coinswap(swap_params) {
+            Ok(()) => swaps += 1,
+            Err(e) => log::warn!("Round {} failed: {:?}", round, e),
+        }
+        // Claim back anything left in contracts by an aborted round.
+        taker.recover_from_swap().unwrap();
+
+        let (balances, spendable) = Participant::Taker(taker).balances();
+        assert_eq!(balances.contract, Amount::ZERO);
+        chaos::assert_loss_within_fees(
+            "Taker",
+            setup.org_taker_spend_balance,
+            spendable + maker_fees * swaps,
+            MAX_ROUND_MINER_FEES * round,
+        );
+    }
+
+    let (test_framework, _, _, block_generation_handle) = setup.stop_servers();
+
+    log::info!("All checks successful. Terminating integration test case");
+    test_framework.stop();
+    block_generation_handle.join().unwrap();
+}
+
 /// Verifies the results of a coinswap for the taker and makers after performing a swap.
 #[allow(dead_code)]
 pub fn verify_swap_results(
@@ -461,6 +781,80 @@ pub fn verify_swap_results(
         });
 }
 
+/// A wallet owner of a test, see [TestFramework::fund_participants].
+#[allow(dead_code)]
+pub enum Participant<'a> {
+    /// The Taker.
+    Taker(&'a mut Taker),
+    /// A Maker.
+    Maker(&'a Maker),
+}
+
+#[allow(dead_code)]
+impl Participant<'_> {
+    fn next_address(&mut self) -> bitcoin::Address {
+        match self {
+            Self::Taker(taker) => taker.get_wallet_mut().get_next_external_address(),
+            Self::Maker(maker) => maker.wallet.write().unwrap().get_next_external_address(),
+        }
+        .unwrap()
+    }
+
+    /// Balances of the wallet, and its spendable balance.
+    pub fn balances(&self) -> (WalletBalances, Amount) {
+        let balances = match self {
+            Self::Taker(taker) => taker.get_wallet().get_balances(None),
+            Self::Maker(maker) => maker.get_wallet().read().unwrap().get_balances(None),
+        }
+        .unwrap();
+        (
+            WalletBalances {
+                regular: balances.regular,
+                swap: balances.swap,
+                contract: balances.contract,
+                fidelity: balances.fidelity,
+            },
+            balances.spendable,
+        )
+    }
+}
+
+/// Balances of a wallet, per category. See [TestFramework::assert_balances].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct WalletBalances {
+    pub regular: Amount,
+    pub swap: Amount,
+    pub contract: Amount,
+    pub fidelity: Amount,
+}
+
+/// A background miner of regtest blocks, see [TestFramework::start_miner].
+#[allow(dead_code)]
+pub struct BlockMiner {
+    requests: Sender<(u64, Sender<()>)>,
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<()>,
+}
+
+#[allow(dead_code)]
+impl BlockMiner {
+    /// Generates `blocks` blocks, and waits until they are mined.
+    pub fn mine(&self, blocks: u64) {
+        let (done, mined) = mpsc::channel();
+        self.requests
+            .send((blocks, done))
+            .expect("Block miner stopped");
+        mined.recv().expect("Block miner stopped");
+    }
+
+    /// Stops the miner, and waits for its thread to end.
+    pub fn stop(self) {
+        self.stop.store(true, Relaxed);
+        drop(self.requests);
+        self.handle.join().unwrap();
+    }
+}
+
 /// The Test Framework.
 ///
 /// Handles initializing, operating and cleaning up of all backend processes. Bitcoind, Taker and Makers.
@@ -469,8 +863,12 @@ pub struct TestFramework {
     pub(super) bitcoind: BitcoinD,
     temp_dir: PathBuf,
     shutdown: AtomicBool,
+    connection_type: ConnectionType,
+    taker_count: AtomicUsize,
+    mining_paused: AtomicBool,
 }
 
+#[allow(dead_code)]
 impl TestFramework {
     /// Initialize a test-framework environment from given configuration data.
     /// This object holds the reference to backend bitcoind process and RPC.
@@ -495,6 +893,45 @@ impl TestFramework {
         Arc<DirectoryServer>,
         JoinHandle<()>,
     ) {
+        let (test_framework, taker, makers, directory_server_instance, generate_blocks_handle, _) =
+            Self::init_with_proxies(
+                makers_config_map,
+                taker_behavior,
+                connection_type,
+                Vec::new(),
+            );
+        (
+            test_framework,
+            taker,
+            makers,
+            directory_server_instance,
+            generate_blocks_handle,
+        )
+    }
+
+    /// Like [TestFramework::init], with a [FaultProxy] in front of some Makers. `proxies` maps the network port of
+    /// a Maker to the port of its proxy. The Maker advertises the proxy port, so the Taker reaches it through the
+    /// proxy. The proxies start without faults, and are returned in the order of `proxies`.
+    ///
+    /// Only for clearnet Makers: over Tor, the Taker reaches the Makers through their onion services.
+    #[allow(clippy::type_complexity)]
+    pub fn init_with_proxies(
+        makers_config_map: Vec<((u16, Option<u16>), MakerBehavior)>,
+        taker_behavior: TakerBehavior,
+        connection_type: ConnectionType,
+        proxies: Vec<(u16, u16)>,
+    ) -> (
+        Arc<Self>,
+        Taker,
+        Vec<Arc<Maker>>,
+        Arc<DirectoryServer>,
+        JoinHandle<()>,
+        Vec<FaultProxy>,
+    ) {
+        assert!(
+            proxies.is_empty() || connection_type == ConnectionType::CLEARNET,
+            "Fault proxies need clearnet makers"
+        );
         setup_logger(log::LevelFilter::Info);
         // Setup directory
         let temp_dir = env::temp_dir().join("coinswap");
@@ -511,6 +948,9 @@ impl TestFramework {
             bitcoind,
             temp_dir: temp_dir.clone(),
             shutdown,
+            connection_type,
+            taker_count: AtomicUsize::new(1),
+            mining_paused: AtomicBool::new(false),
         });
 
         log::info!("Initiating Directory Server .....");
@@ -550,9 +990,19 @@ impl TestFramework {
                 let maker_id = format!("maker{}", port.0); // ex: "maker6102"
                 let maker_rpc_config = rpc_config.clone();
                 thread::sleep(Duration::from_secs(5)); // Sleep for some time avoid resource unavailable error.
+                let data_dir = temp_dir.join(port.0.to_string());
+                // Advertise the proxy port. The rest of the config takes its defaults.
+                if let Some((_, proxy_port)) = proxies.iter().find(|(p, _)| *p == port.0) {
+                    create_dir_all(&data_dir).unwrap();
+                    fs::write(
+                        data_dir.join("config.toml"),
+                        format!("advertised_port = {}\n", proxy_port),
+                    )
+                    .unwrap();
+                }
                 Arc::new(
                     Maker::init(
-                        Some(temp_dir.join(port.0.to_string())),
+                        Some(data_dir),
                         Some(maker_id),
                         Some(maker_rpc_config),
                         Some(port.0),
@@ -566,19 +1016,15 @@ impl TestFramework {
             })
             .collect::<Vec<_>>();
 
-        // start the block generation thread
-        log::info!("spawning block generation thread");
-        let tf_clone = test_framework.clone();
-        let generate_blocks_handle = thread::spawn(move || loop {
-            thread::sleep(Duration::from_secs(3));
+        let proxies = proxies
+            .into_iter()
+            .map(|(maker_port, proxy_port)| FaultProxy::start(proxy_port, maker_port))
+            .collect();
 
-            if tf_clone.shutdown.load(Relaxed) {
-                log::info!("ending block generation thread");
-                return;
-            }
-            // tf_clone.generate_blocks(10);
-            generate_blocks(&tf_clone.bitcoind, 10);
-        });
+        // start the block generation thread
+        let generate_blocks_handle = test_framework
+            .start_miner(Some(Duration::from_secs(3)), 10)
+            .handle;
 
         (
             test_framework,
@@ -586,7 +1032,128 @@ impl TestFramework {
             makers,
             directory_server_instance,
             generate_blocks_handle,
+            proxies,
+        )
+    }
+
+    /// Creates another Taker, with its own wallet, swapping with the same Makers as the Taker of
+    /// [TestFramework::init]. Takers are driven from their own threads to swap concurrently, to exercise the
+    /// concurrency, liquidity and admission control of the Makers.
+    ///
+    /// The n-th added Taker stores its data in `taker{n}`, with the wallet `taker{n}-wallet`.
+    pub fn add_taker(&self, behavior: TakerBehavior) -> Taker {
+        let taker_id = format!("taker{}", self.taker_count.fetch_add(1, Relaxed));
+        Taker::init(
+            Some(self.temp_dir.join(&taker_id)),
+            Some(format!("{}-wallet", taker_id)),
+            Some(RPCConfig::from(self)),
+            behavior,
+            Some(self.connection_type),
         )
+        .unwrap()
+    }
+
+    /// Starts a background miner, generating `blocks_per_interval` blocks every `interval`, and blocks on
+    /// demand with [BlockMiner::mine]. Without an interval, blocks are only generated on demand.
+    ///
+    /// The miner runs until [BlockMiner::stop], or [TestFramework::stop]. A periodic miner dropped without being
+    /// stopped keeps mining until then.
+    pub fn start_miner(
+        self: &Arc<Self>,
+        interval: Option<Duration>,
+        blocks_per_interval: u64,
+    ) -> BlockMiner {
+        log::info!("spawning block generation thread");
+        let (requests, request_recv) = mpsc::channel::<(u64, Sender<()>)>();
+        let stop = Arc::new(AtomicBool::new(false));
+        let test_framework = self.clone();
+        let miner_stop = stop.clone();
+        let tick = interval.unwrap_or(Duration::from_secs(1));
+        let handle = thread::spawn(move || loop {
+            let request = match request_recv.recv_timeout(tick) {
+                Ok(request) => Some(request),
+                Err(RecvTimeoutError::Timeout) => None,
+                Err(RecvTimeoutError::Disconnected) => {
+                    // Nobody can ask for blocks anymore, keep mining periodically only.
+                    if interval.is_none() {
+                        return;
+                    }
+                    thread::sleep(tick);
+                    None
+                }
+            };
+
+            if test_framework.shutdown.load(Relaxed) || miner_stop.load(Relaxed) {
+                log::info!("ending block generation thread");
+                return;
+            }
+            match request {
+                Some((blocks, done)) => {
+                    generate_blocks(&test_framework.bitcoind, blocks);
+                    let _ = done.send(());
+                }
+                None if interval.is_some() && !test_framework.mining_paused.load(Relaxed) => {
+                    generate_blocks(&test_framework.bitcoind, blocks_per_interval)
+                }
+                None => {}
+            }
+        });
+
+        BlockMiner {
+            requests,
+            stop,
+            handle,
+        }
+    }
+
+    /// Pauses the periodic block generation of all the miners, or resumes it. Blocks are still mined on demand,
+    /// to control when the txs broadcasted meanwhile confirm.
+    pub fn pause_mining(&self, paused: bool) {
+        self.mining_paused.store(paused, Relaxed);
+    }
+
+    /// Funds each participant with `count` utxos of `amount`, and confirms them.
+    ///
+    /// Returns the spendable balances of the participants, in order.
+    #[allow(dead_code)]
+    pub fn fund_participants(
+        &self,
+        mut participants: Vec<Participant>,
+        amount: Amount,
+        count: u32,
+    ) -> Vec<Amount> {
+        log::info!("Funding {} participants...", participants.len());
+        for participant in &mut participants {
+            for _ in 0..count {
+                send_to_address(&self.bitcoind, &participant.next_address(), amount);
+            }
+        }
+
+        // confirm balances
+        generate_blocks(&self.bitcoind, 1);
+
+        participants
+            .iter()
+            .map(|participant| participant.balances().1)
+            .collect()
+    }
+
+    /// Asserts the balances of the wallet of each participant.
+    #[allow(dead_code)]
+    pub fn assert_balances(&self, expected: Vec<(Participant, WalletBalances)>) {
+        for (index, (participant, expected)) in expected.into_iter().enumerate() {
+            let role = match participant {
+                Participant::Taker(_) => "Taker",
+                Participant::Maker(_) => "Maker",
+            };
+            assert_eq!(
+                participant.balances().0,
+                expected,
+                "Balances of participant {} ({})",
+                index,
+                role
+            );
+        }
     }
 
     /// Stop bitcoind and clean up all test data.
@@ -0,0 +1,208 @@
+//! Network fault injection between the Taker and the Makers.
+//!
+//! A [FaultProxy] sits in front of a clearnet Maker: the Maker advertises the proxy port, so the Taker reaches it
+//! through the proxy, which forwards the length-prefixed frames both ways. Its [Faults] delay every frame, drop a
+//! share of them, or sever the link when the Taker sends a chosen message, reaching the timeout and recovery paths
+//! of the protocol that well-behaved makers never trigger.
+//!
+//! Drops are drawn from a seeded SplitMix64, so a failing run can be replayed from its seed.
+use std::{
+    io::{self, Read, Write},
+    net::{Ipv4Addr, Shutdown, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering::Relaxed},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use coinswap::protocol::messages::MessageType;
+
+/// Faults injected by a [FaultProxy].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Faults {
+    /// Delay of every forwarded frame, both ways.
+    pub latency: Duration,
+    /// Percentage of frames dropped, both ways.
+    pub drop_percent: u8,
+    /// Sever the link when the Taker sends this message, instead of forwarding it. The link stays down, refusing
+    /// new connections, until the faults are changed.
+    pub sever_at: Option<MessageType>,
+    /// Seed of the drops.
+    pub seed: u64,
+}
+
+struct ProxyState {
+    faults: Mutex<Faults>,
+    rng: Mutex<u64>,
+    severed: AtomicBool,
+    /// Shared by the proxies of [FaultProxy::sever_one_of], set once one of them severed its link.
+    sever_token: Mutex<Option<Arc<AtomicBool>>>,
+    stop: AtomicBool,
+}
+
+impl ProxyState {
+    /// Whether to drop the next frame.
+    fn roll_drop(&self) -> bool {
+        let drop_percent = self.faults.lock().unwrap().drop_percent;
+        drop_percent > 0 && splitmix64(&mut self.rng.lock().unwrap()) % 100 < drop_percent as u64
+    }
+}
+
+/// A proxy in front of a Maker, injecting network faults. See the [module docs](self).
+#[allow(dead_code)]
+pub struct FaultProxy {
+    port: u16,
+    state: Arc<ProxyState>,
+    handle: JoinHandle<()>,
+}
+
+#[allow(dead_code)]
+impl FaultProxy {
+    /// Listens on `port`, forwarding to the Maker listening on `maker_port`. Starts without faults.
+    pub fn start(port: u16, maker_port: u16) -> Self {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port)).unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let state = Arc::new(ProxyState {
+            faults: Mutex::new(Faults::default()),
+            rng: Mutex::new(0),
+            severed: AtomicBool::new(false),
+            sever_token: Mutex::new(None),
+            stop: AtomicBool::new(false),
+        });
+
+        let proxy_state = state.clone();
+        let handle = thread::spawn(move || {
+            while !proxy_state.stop.load(Relaxed) {
+                let taker = match listener.accept() {
+                    Ok((taker, _)) => taker,
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                        thread::sleep(Duration::from_millis(50));
+                        continue;
+                    }
+                    Err(e) => {
+                        log::error!("[Proxy {}] Failed to accept a connection: {}", port, e);
+                        break;
+                    }
+                };
+                if proxy_state.severed.load(Relaxed) {
+                    log::info!("[Proxy {}] Link severed, refusing connection", port);
+                    continue;
+                }
+                let maker = match TcpStream::connect((Ipv4Addr::LOCALHOST, maker_port)) {
+                    Ok(maker) => maker,
+                    Err(e) => {
+                        log::warn!("[Proxy {}] Maker unreachable: {}", port, e);
+                        continue;
+                    }
+                };
+                taker.set_nonblocking(false).unwrap();
+                for (from, to, from_taker) in [
+                    (taker.try_clone().unwrap(), maker.try_clone().unwrap(), true),
+                    (maker, taker, false),
+                ] {
+                    let pump_state = proxy_state.clone();
+                    thread::spawn(move || pump(port, from, to, from_taker, &pump_state));
+                }
+            }
+        });
+
+        Self {
+            port,
+            state,
+            handle,
+        }
+    }
+
+    /// The port the Maker is reached at, through the proxy.
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Replaces the injected faults, for the next frames. Restores a severed link, and reseeds the drops.
+    pub fn set_faults(&self, faults: Faults) {
+        *self.state.sever_token.lock().unwrap() = None;
+        *self.state.rng.lock().unwrap() = faults.seed;
+        *self.state.faults.lock().unwrap() = faults;
+        self.state.severed.store(false, Relaxed);
+    }
+
+    /// Severs the link of the first of `proxies` the Taker sends `message` through, whichever Maker the Taker
+    /// picked for its route. The other links stay up. Keeps the other faults of each proxy.
+    pub fn sever_one_of(proxies: &[FaultProxy], message: MessageType) {
+        let token = Arc::new(AtomicBool::new(false));
+        for proxy in proxies {
+            *proxy.state.sever_token.lock().unwrap() = Some(token.clone());
+            proxy.state.faults.lock().unwrap().sever_at = Some(message);
+        }
+    }
+
+    /// Whether the link was severed at [Faults::sever_at].
+    pub fn is_severed(&self) -> bool {
+        self.state.severed.load(Relaxed)
+    }
+
+    /// Stops accepting connections, and waits for the proxy thread to end. Open connections end with their
+    /// next frame.
+    pub fn stop(self) {
+        self.state.stop.store(true, Relaxed);
+        self.handle.join().unwrap();
+    }
+}
+
+/// Forwards the frames of one direction of a connection, injecting the faults, until either side closes it.
+fn pump(port: u16, mut from: TcpStream, mut to: TcpStream, from_taker: bool, state: &ProxyState) {
+    while let Some(frame) = read_frame(&mut from) {
+        if state.stop.load(Relaxed) || state.severed.load(Relaxed) {
+            break;
+        }
+        let faults = *state.faults.lock().unwrap();
+        if from_taker && faults.sever_at.is_some() {
+            let message_type = MessageType::of_taker_message(&frame[4..]);
+            let token_taken = match &*state.sever_token.lock().unwrap() {
+                Some(token) => message_type == faults.sever_at && token.swap(true, Relaxed),
+                None => false,
+            };
+            if message_type == faults.sever_at && !token_taken {
+                log::warn!("[Proxy {}] Severing link at {:?}", port, message_type);
+                state.severed.store(true, Relaxed);
+                break;
+            }
+        }
+        thread::sleep(faults.latency);
+        if state.roll_drop() {
+            log::warn!(
+                "[Proxy {}] Dropping a frame from the {}",
+                port,
+                if from_taker { "Taker" } else { "Maker" }
+            );
+            continue;
+        }
+        if to.write_all(&frame).is_err() {
+            break;
+        }
+    }
+    // Closing both sides also ends the pump of the other direction.
+    let _ = from.shutdown(Shutdown::Both);
+    let _ = to.shutdown(Shutdown::Both);
+}
+
+/// Reads a length-prefixed frame, with its prefix. None once the connection is closed.
+fn read_frame(stream: &mut TcpStream) -> Option<Vec<u8>> {
+    let mut len = [0u8; 4];
+    stream.read_exact(&mut len).ok()?;
+    let mut frame = len.to_vec();
+    frame.resize(4 + u32::from_be_bytes(len) as usize, 0);
+    stream.read_exact(&mut frame[4..]).ok()?;
+    Some(frame)
+}
+
+/// Next output of the splitmix64 generator, the seeded randomness of the faults and the chaos plans.
+pub fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}